@@ -0,0 +1,68 @@
+use std::fmt::Write;
+use std::path::Path;
+use std::process::Command;
+use std::{env, fs};
+
+use chrono::{TimeZone, Utc};
+
+//Build metadata for $SYS/broker/build and the labels on /metrics
+fn main() {
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|git_hash| git_hash.trim().to_string())
+        .filter(|git_hash| !git_hash.is_empty())
+        .unwrap_or_else(|| String::from("unknown"));
+    //SOURCE_DATE_EPOCH keeps reproducible builds reproducible
+    let build_date = std::env::var("SOURCE_DATE_EPOCH").ok()
+        .and_then(|epoch| epoch.parse::<i64>().ok())
+        .and_then(|epoch| Utc.timestamp_opt(epoch, 0).single())
+        .unwrap_or_else(Utc::now);
+    println!("cargo:rustc-env=PATINA_GIT_HASH={}", git_hash);
+    println!("cargo:rustc-env=PATINA_BUILD_DATE={}", build_date.format("%Y-%m-%dT%H:%M:%SZ"));
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs");
+    println!("cargo:rerun-if-env-changed=SOURCE_DATE_EPOCH");
+    write_config_schema();
+}
+
+//The structs and enums of the config with their fields, types and // comments, for the unknown key check and the
+//reference config of --print-default-config
+fn write_config_schema() {
+    let source_path = "src/config/broker_config.rs";
+    println!("cargo:rerun-if-changed={}", source_path);
+    let source = fs::read_to_string(source_path).expect("Can't read the config structs");
+    let (mut structs, mut enums, mut fields) = (String::new(), String::new(), String::new());
+    let mut comment: Vec<&str> = vec![];
+    let (mut current_struct, mut skipped) = (None, false);
+    for line in source.lines() {
+        let line = line.trim();
+        if let Some(text) = line.strip_prefix("//") {
+            comment.push(text.trim());
+            continue;
+        }
+        if line.starts_with("#[") {
+            skipped |= line == "#[serde(skip)]";
+            continue;
+        }
+        if let Some(name) = line.strip_prefix("pub struct ").and_then(|rest| rest.strip_suffix(" {")) {
+            writeln!(structs, "    ({:?}, {:?}),", name, comment.join(" ")).unwrap();
+            current_struct = Some(name.to_string());
+        } else if let Some(name) = line.strip_prefix("pub enum ").and_then(|rest| rest.strip_suffix(" {")) {
+            writeln!(enums, "    ({:?}, {:?}),", name, comment.join(" ")).unwrap();
+        } else if line == "}" {
+            current_struct = None;
+        } else if let (Some(struct_name), Some((name, field_type))) = (&current_struct, line.strip_prefix("pub(crate) ").or(line.strip_prefix("pub ")).and_then(|field| field.split_once(": "))) {
+            if !skipped {
+                writeln!(fields, "    ({:?}, {:?}, {:?}, {:?}),", struct_name, name, field_type.trim_end_matches(','), comment.join(" ")).unwrap();
+            }
+        }
+        comment.clear();
+        skipped = false;
+    }
+    let schema = format!("//Generated by build.rs from {}\n\n//Struct, its comment\npub const CONFIG_STRUCTS: &[(&str, &str)] = &[\n{}];\n\n//Enum, its comment\npub const CONFIG_ENUMS: &[(&str, &str)] = &[\n{}];\n\n//Struct, field, type, comment\npub const CONFIG_FIELDS: &[(&str, &str, &str, &str)] = &[\n{}];\n", source_path, structs, enums, fields);
+    fs::write(Path::new(&env::var("OUT_DIR").unwrap()).join("config_schema.rs"), schema).expect("Can't write the config schema");
+}
@@ -0,0 +1,118 @@
+use std::fs::{File, OpenOptions, rename};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use chrono::Utc;
+use log::{error, info, trace};
+use serde::Serialize;
+
+use crate::config::broker_config::AuditConfig;
+
+//Security relevant events, one JSON object per line
+#[derive(Debug)]
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum AuditEvent {
+    ConfigLoaded { path: String },
+    //A request was refused because its credentials were missing or wrong
+    AuthFailure { interface: String, resource: String, reason: String },
+    //A request to the admin API that changed broker state, exported client data or opened a stream
+    AdminAction { action: String, resource: String },
+}
+
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    timestamp: String,
+    #[serde(flatten)]
+    event: &'a AuditEvent,
+}
+
+#[derive(Debug)]
+struct AuditFile {
+    file: File,
+    size: u64,
+}
+
+//Kept apart from the debug log so that it can be retained and shipped on its own.
+//The file is rotated to <path>.1 .. <path>.<max_files> once it exceeds max_size bytes.
+#[derive(Debug)]
+pub struct AuditLog {
+    config: AuditConfig,
+    audit_file: Mutex<Option<AuditFile>>,
+}
+
+impl AuditLog {
+    pub fn record(&self, event: AuditEvent) {
+        trace!("AuditLog::record");
+        if !self.config.enabled {
+            return;
+        }
+        let record = AuditRecord { timestamp: Utc::now().to_rfc3339(), event: &event };
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(result) => { result }
+            Err(err) => {
+                error!("Can't serialize audit event {:?}: {}", event, err);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        let mut audit_file = self.audit_file.lock().unwrap();
+        if audit_file.as_ref().is_some_and(|audit_file| audit_file.size > 0 && audit_file.size + line.len() as u64 > self.config.max_size) {
+            *audit_file = None;
+            self.rotate();
+        }
+        if audit_file.is_none() {
+            *audit_file = self.open();
+        }
+        if let Some(audit_file) = audit_file.as_mut() {
+            match audit_file.file.write_all(&line) {
+                Ok(_) => { audit_file.size += line.len() as u64; }
+                Err(err) => { error!("Can't write audit event to {}: {}", self.config.path, err); }
+            }
+        }
+    }
+
+    fn open(&self) -> Option<AuditFile> {
+        if let Some(parent) = Path::new(&self.config.path).parent() {
+            if let Err(err) = std::fs::create_dir_all(parent) {
+                error!("Can't create audit log directory {:?}: {}", parent, err);
+                return None;
+            }
+        }
+        return match OpenOptions::new().create(true).append(true).open(&self.config.path) {
+            Ok(file) => {
+                let size = file.metadata().map(|metadata| metadata.len()).unwrap_or(0);
+                Some(AuditFile { file, size })
+            }
+            Err(err) => {
+                error!("Can't open audit log {}: {}", self.config.path, err);
+                None
+            }
+        };
+    }
+
+    fn rotate(&self) {
+        info!("Rotating audit log {}", self.config.path);
+        if self.config.max_files == 0 {
+            if let Err(err) = std::fs::remove_file(&self.config.path) {
+                error!("Can't remove audit log {}: {}", self.config.path, err);
+            }
+            return;
+        }
+        for index in (1..self.config.max_files).rev() {
+            let from = format!("{}.{}", self.config.path, index);
+            if Path::new(&from).exists() {
+                let _ = rename(&from, format!("{}.{}", self.config.path, index + 1));
+            }
+        }
+        if let Err(err) = rename(&self.config.path, format!("{}.1", self.config.path)) {
+            error!("Can't rotate audit log {}: {}", self.config.path, err);
+        }
+    }
+
+    pub fn new(config: AuditConfig) -> Self {
+        Self { config, audit_file: Mutex::new(None) }
+    }
+}
@@ -0,0 +1,182 @@
+use log::{debug, error, info};
+use metered::HitCount;
+use serde::Serialize;
+
+use crate::config::broker_config::{AclConfig, AclPermission, AclRule};
+use crate::topic::topic_matcher::{shared_filter, topic_matches};
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct AclMetrics {
+    pub(crate) allowed: HitCount,
+    //Publishes, subscriptions and Will Messages refused with Not Authorized
+    pub(crate) denied: HitCount,
+}
+
+//Decides which topics a client may publish to and subscribe to. The first rule matching the client and the topic
+//decides, acl.no_match decides the rest. An allow rule must cover every topic of a subscription, a deny rule refuses
+//a subscription that could receive any of its topics.
+#[derive(Debug)]
+pub struct Acl {
+    enabled: bool,
+    rules: Vec<AclRule>,
+    no_match: AclPermission,
+    pub(crate) metrics: AclMetrics,
+}
+
+impl Acl {
+    pub fn can_publish(&self, client_id: &str, username: Option<&String>, topic_name: &str) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let permission = self.rules.iter()
+            .filter(|rule| rule.access.allows_write())
+            .find(|rule| topics_of(rule, client_id, username).iter().any(|topic| topic_matches(topic, topic_name)))
+            .map_or(self.no_match, |rule| rule.permission);
+        self.count(permission, "publish to", client_id, topic_name)
+    }
+
+    //A shared subscription is decided on its topic filter, whatever its group
+    pub fn can_subscribe(&self, client_id: &str, username: Option<&String>, topic_filter: &str) -> bool {
+        if !self.enabled {
+            return true;
+        }
+        let filter = shared_filter(topic_filter).map_or(topic_filter, |(_, filter)| filter);
+        let permission = self.rules.iter()
+            .filter(|rule| rule.access.allows_read())
+            .find(|rule| topics_of(rule, client_id, username).iter().any(|topic| match rule.permission {
+                AclPermission::Allow => { filter_covers(topic, filter) }
+                AclPermission::Deny => { filters_overlap(topic, filter) }
+            }))
+            .map_or(self.no_match, |rule| rule.permission);
+        self.count(permission, "subscribe to", client_id, topic_filter)
+    }
+
+    fn count(&self, permission: AclPermission, action: &str, client_id: &str, topic: &str) -> bool {
+        match permission {
+            AclPermission::Allow => {
+                self.metrics.allowed.incr();
+                true
+            }
+            AclPermission::Deny => {
+                debug!("ACL denies client {:?} to {} {:?}", client_id, action, topic);
+                self.metrics.denied.incr();
+                false
+            }
+        }
+    }
+
+    //Rules that fail to load deny everything, BrokerConfig::check_acl keeps the broker from starting with them
+    pub fn new(config: AclConfig) -> Self {
+        if !config.enabled {
+            return Self { enabled: false, rules: vec![], no_match: AclPermission::Allow, metrics: AclMetrics::default() };
+        }
+        let (rules, no_match) = match config.load_rules() {
+            Ok(rules) => {
+                info!("Authorizing topics with {} ACL rules, {:?} when none matches", rules.len(), config.no_match);
+                (rules, config.no_match)
+            }
+            Err(err) => {
+                error!("{}. Denying every topic", err);
+                (vec![], AclPermission::Deny)
+            }
+        };
+        Self { enabled: true, rules, no_match, metrics: AclMetrics::default() }
+    }
+}
+
+//The topics of a rule that applies to the client, with %c and %u substituted. Empty when it applies to other clients.
+//A client_id or username with wildcards or levels would widen the filters, the rules using it don't apply to it.
+fn topics_of(rule: &AclRule, client_id: &str, username: Option<&String>) -> Vec<String> {
+    if rule.client_id.as_ref().is_some_and(|pattern| !pattern_matches(pattern, client_id)) {
+        return vec![];
+    }
+    if rule.username.as_ref().is_some_and(|pattern| !username.is_some_and(|username| pattern_matches(pattern, username))) {
+        return vec![];
+    }
+    let substitutable = |value: &str| !value.contains(['+', '#', '/']);
+    rule.topics.iter()
+        .filter_map(|topic| {
+            let mut topic = topic.clone();
+            if topic.contains("%c") {
+                if !substitutable(client_id) {
+                    return None;
+                }
+                topic = topic.replace("%c", client_id);
+            }
+            if topic.contains("%u") {
+                let username = username.filter(|username| substitutable(username))?;
+                topic = topic.replace("%u", username);
+            }
+            Some(topic)
+        })
+        .collect()
+}
+
+//* matches any characters, everything else itself
+pub fn pattern_matches(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => { rest = &rest[index + part.len()..]; }
+            None => { return false; }
+        }
+    }
+    rest.ends_with(last)
+}
+
+//Every topic name topic_filter matches is matched by rule_filter as well, a/# covers a
+pub fn filter_covers(rule_filter: &str, topic_filter: &str) -> bool {
+    if topic_filter.starts_with('$') && (rule_filter.starts_with('+') || rule_filter.starts_with('#')) {
+        return false;
+    }
+    let mut rule_levels = rule_filter.split('/');
+    let mut filter_levels = topic_filter.split('/');
+    loop {
+        match (rule_levels.next(), filter_levels.next()) {
+            (Some("#"), _) => { return true; }
+            (_, Some("#")) => { return false; }
+            (Some("+"), Some(_)) => {}
+            (Some(_), Some("+")) => { return false; }
+            (Some(rule_level), Some(filter_level)) => {
+                if rule_level != filter_level {
+                    return false;
+                }
+            }
+            (None, None) => { return true; }
+            _ => { return false; }
+        }
+    }
+}
+
+//Some topic name is matched by both filters
+pub fn filters_overlap(filter: &str, other_filter: &str) -> bool {
+    let dollar = |filter: &str| filter.starts_with('$');
+    let wildcard = |filter: &str| filter.starts_with('+') || filter.starts_with('#');
+    if (dollar(filter) && wildcard(other_filter)) || (dollar(other_filter) && wildcard(filter)) {
+        return false;
+    }
+    let mut levels = filter.split('/');
+    let mut other_levels = other_filter.split('/');
+    loop {
+        match (levels.next(), other_levels.next()) {
+            (Some("#"), _) | (_, Some("#")) => { return true; }
+            (Some("+"), Some(_)) | (Some(_), Some("+")) => {}
+            (Some(level), Some(other_level)) => {
+                if level != other_level {
+                    return false;
+                }
+            }
+            (None, None) => { return true; }
+            _ => { return false; }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use log::{debug, info};
+use metered::HitCount;
+use rand::Rng;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::config::broker_config::AuthConfig;
+use crate::model::reason_code::ReasonCode;
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct AuthMetrics {
+    pub(crate) accepted: HitCount,
+    //Unknown username or wrong password
+    pub(crate) rejected: HitCount,
+    //Refused without checking the password, the username or IP address is locked out
+    pub(crate) locked_out: HitCount,
+    //Lockouts started, each one twice as long as the previous one of the same username or IP address
+    pub(crate) lockouts: HitCount,
+}
+
+//Why and how late a CONNECT is refused
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+pub struct Refusal {
+    pub reason_code: ReasonCode,
+    pub delay: Duration,
+}
+
+//Failed CONNECTs of one username or IP address
+#[derive(Debug)]
+struct Failures {
+    count: u32,
+    last_failure: Instant,
+    locked_until: Option<Instant>,
+}
+
+#[derive(Debug)]
+#[derive(Clone, Hash, Eq, PartialEq)]
+enum FailureKey {
+    Username(String),
+    Ip(IpAddr),
+}
+
+//Checks CONNECT credentials against the configured users. Every failure is answered late and repeated failures
+//lock the username and the IP address out for exponentially longer, to slow down password guessing.
+#[derive(Debug)]
+pub struct Authenticator {
+    config: AuthConfig,
+    failures: DashMap<FailureKey, Failures>,
+    pub(crate) metrics: AuthMetrics,
+}
+
+impl Authenticator {
+    pub fn authenticate(&self, username: Option<&String>, password: Option<&String>, ip: IpAddr) -> Result<(), Refusal> {
+        if !self.config.is_enabled() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut keys = vec![FailureKey::Ip(ip)];
+        if let Some(username) = username {
+            keys.push(FailureKey::Username(username.clone()));
+        }
+        if keys.iter().any(|key| self.is_locked_out(key, now)) {
+            debug!("Refusing CONNECT of username {:?} from {:?}, locked out", username, ip);
+            self.metrics.locked_out.incr();
+            return Err(self.refusal(ReasonCode::Banned));
+        }
+        let valid = match (username.and_then(|username| self.config.users.get(username)), password) {
+            (Some(expected), Some(password)) => { same_secret(expected, password) }
+            _ => { false }
+        };
+        if !valid {
+            info!("Wrong username or password for username {:?} from {:?}", username, ip);
+            self.metrics.rejected.incr();
+            self.record_failure(&keys, now);
+            return Err(self.refusal(ReasonCode::BadUsernameOrPassword));
+        }
+        //A valid login doesn't clear its IP address, an attacker with one account could reset the count otherwise
+        if let Some(username) = username {
+            self.failures.remove(&FailureKey::Username(username.clone()));
+        }
+        self.metrics.accepted.incr();
+        Ok(())
+    }
+
+    fn is_locked_out(&self, key: &FailureKey, now: Instant) -> bool {
+        self.failures.get(key)
+            .and_then(|failures| failures.locked_until)
+            .is_some_and(|locked_until| now < locked_until)
+    }
+
+    fn record_failure(&self, keys: &[FailureKey], now: Instant) {
+        let lockout_max = Duration::from_secs(self.config.lockout_max_secs);
+        for key in keys {
+            let mut failures = self.failures.entry(key.clone()).or_insert(Failures { count: 0, last_failure: now, locked_until: None });
+            //Failures older than the longest lockout are forgotten
+            if now.duration_since(failures.last_failure) > lockout_max {
+                failures.count = 0;
+                failures.locked_until = None;
+            }
+            failures.count += 1;
+            failures.last_failure = now;
+            if self.config.lockout_threshold > 0 && failures.count >= self.config.lockout_threshold {
+                let lockout = self.lockout(failures.count - self.config.lockout_threshold);
+                info!("Locking out {:?} for {}s after {} failures", key, lockout.as_secs(), failures.count);
+                failures.locked_until = Some(now + lockout);
+                self.metrics.lockouts.incr();
+            }
+        }
+    }
+
+    //lockout_base_secs doubled for every failure past the threshold
+    pub fn lockout(&self, failures_past_threshold: u32) -> Duration {
+        let lockout_secs = self.config.lockout_base_secs.saturating_mul(1_u64.checked_shl(failures_past_threshold).unwrap_or(u64::MAX));
+        Duration::from_secs(lockout_secs.min(self.config.lockout_max_secs))
+    }
+
+    fn refusal(&self, reason_code: ReasonCode) -> Refusal {
+        let reason_code = match self.config.generic_reason_codes {
+            true => { ReasonCode::NotAuthorized }
+            false => { reason_code }
+        };
+        Refusal { reason_code, delay: self.failure_delay() }
+    }
+
+    fn failure_delay(&self) -> Duration {
+        let jitter = match self.config.failure_jitter_ms {
+            0 => { 0 }
+            failure_jitter_ms => { rand::thread_rng().gen_range(0..=failure_jitter_ms) }
+        };
+        Duration::from_millis(self.config.failure_delay_ms + jitter)
+    }
+
+    pub fn new(config: AuthConfig) -> Self {
+        if config.is_enabled() {
+            info!("Authenticating CONNECT against {} configured users", config.users.len());
+        }
+        Self { config, failures: DashMap::new(), metrics: AuthMetrics::default() }
+    }
+}
+
+//Compares every byte, so the time taken doesn't tell how much of the password matched
+fn same_secret(expected: &str, actual: &str) -> bool {
+    expected.len() == actual.len()
+        && expected.bytes().zip(actual.bytes()).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
@@ -0,0 +1,17 @@
+//Prints the packet types, properties and reason codes the broker supports as JSON:
+//cargo run --bin protocol_table > protocol.json
+#[allow(dead_code)]
+#[path = "../model/mod.rs"]
+mod model;
+
+use crate::model::protocol_table::ProtocolTable;
+
+fn main() {
+    match serde_json::to_string_pretty(&ProtocolTable::new()) {
+        Ok(table) => { println!("{}", table); }
+        Err(err) => {
+            eprintln!("Can't serialize protocol table: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
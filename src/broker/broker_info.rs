@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use log::{error, trace};
+use serde::Serialize;
+use tokio::sync::mpsc::Sender;
+
+use crate::{ClientHandler, TopicHandler};
+use crate::broker::utils::publish_sys_message;
+use crate::config::broker_config::enabled_features;
+use crate::model::control_packet::ControlPacket;
+
+pub const SYS_VERSION_TOPIC: &str = "$SYS/broker/version";
+pub const SYS_UPTIME_TOPIC: &str = "$SYS/broker/uptime";
+pub const SYS_BUILD_TOPIC: &str = "$SYS/broker/build";
+pub const SYS_SUBSCRIPTION_CHURN_TOPIC: &str = "$SYS/broker/subscriptions/churn";
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+//Set by build.rs
+pub const GIT_HASH: &str = env!("PATINA_GIT_HASH");
+pub const BUILD_DATE: &str = env!("PATINA_BUILD_DATE");
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    pub git_hash: &'static str,
+    pub build_date: &'static str,
+    pub features: Vec<&'static str>,
+}
+
+impl BuildInfo {
+    //Labels put on every metric, so a scrape tells which build answered it
+    pub fn metric_labels() -> HashMap<&'static str, &'static str> {
+        HashMap::from([("version", VERSION), ("git_hash", GIT_HASH)])
+    }
+
+    pub fn new() -> Self {
+        Self { version: VERSION, git_hash: GIT_HASH, build_date: BUILD_DATE, features: enabled_features() }
+    }
+}
+
+//Subscriptions made and removed since the start, sampled over time they give the churn rate
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct SubscriptionChurn {
+    pub subscribed: u64,
+    pub unsubscribed: u64,
+}
+
+#[derive(Debug)]
+#[derive(Serialize)]
+pub struct BrokerInfoMetrics {
+    uptime_seconds: u64,
+}
+
+#[derive(Debug)]
+pub struct BrokerInfo {
+    started_at: Instant,
+}
+
+impl BrokerInfo {
+    pub fn uptime(&self) -> Duration {
+        self.started_at.elapsed()
+    }
+
+    pub fn metrics(&self) -> BrokerInfoMetrics {
+        BrokerInfoMetrics { uptime_seconds: self.uptime().as_secs() }
+    }
+
+    //Version and uptime as plain text, the build and the subscription churn as JSON
+    pub async fn publish(&self, client_handler: &ClientHandler, topic_handler: &TopicHandler, to_listener: &Sender<(Vec<SocketAddr>, ControlPacket)>) {
+        trace!("BrokerInfo::publish");
+        publish_sys_message(SYS_VERSION_TOPIC, VERSION.as_bytes().to_vec(), client_handler, topic_handler, to_listener).await;
+        publish_sys_message(SYS_UPTIME_TOPIC, self.uptime().as_secs().to_string().into_bytes(), client_handler, topic_handler, to_listener).await;
+        match serde_json::to_vec(&BuildInfo::new()) {
+            Ok(payload) => { publish_sys_message(SYS_BUILD_TOPIC, payload, client_handler, topic_handler, to_listener).await; }
+            Err(err) => { error!("Can't serialize build info: {}", err); }
+        }
+        let (subscribed, unsubscribed) = topic_handler.subscription_churn();
+        match serde_json::to_vec(&SubscriptionChurn { subscribed, unsubscribed }) {
+            Ok(payload) => { publish_sys_message(SYS_SUBSCRIPTION_CHURN_TOPIC, payload, client_handler, topic_handler, to_listener).await; }
+            Err(err) => { error!("Can't serialize subscription churn: {}", err); }
+        }
+    }
+
+    pub fn new() -> Self {
+        Self { started_at: Instant::now() }
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::Write;
+
+use flate2::Compression;
+use flate2::write::DeflateEncoder;
+use log::{debug, trace};
+use serde::{Deserialize, Serialize};
+
+use crate::model::control_packet::ControlPacket;
+use crate::model::variable_header::Property;
+
+//User property a subscriber sends at SUBSCRIBE to ask for compressed payloads, e.g. "deflate"
+pub const ACCEPT_ENCODING_PROPERTY: &str = "patina-accept-encoding";
+//User property added to a PUBLISH whose payload the broker compressed
+pub const CONTENT_ENCODING_PROPERTY: &str = "content-encoding";
+
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ContentEncoding {
+    Deflate,
+}
+
+impl ContentEncoding {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            ContentEncoding::Deflate => { "deflate" }
+        };
+    }
+
+    //First supported encoding of a comma separated list, unknown ones (zstd, gzip...) are skipped
+    pub fn negotiate(accept_encoding: &str) -> Option<ContentEncoding> {
+        return accept_encoding.split(',')
+            .map(|encoding| encoding.trim())
+            .find_map(|encoding| match encoding {
+                "deflate" => { Some(ContentEncoding::Deflate) }
+                _ => { None }
+            });
+    }
+
+    pub fn from_properties(properties: &[Property]) -> Option<ContentEncoding> {
+        return properties.iter()
+            .find_map(|property| match property {
+                Property::UserProperty(key, value) if key == ACCEPT_ENCODING_PROPERTY => { Self::negotiate(value) }
+                _ => { None }
+            });
+    }
+
+    pub fn encode(&self, data: &[u8]) -> std::io::Result<Vec<u8>> {
+        return match self {
+            ContentEncoding::Deflate => {
+                let mut encoder = DeflateEncoder::new(Vec::with_capacity(data.len() / 2), Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        };
+    }
+}
+
+//Copy of a PUBLISH with its payload compressed and a content-encoding user property added.
+//None when the payload is too small, already encoded or doesn't get smaller.
+//The compressed bytes aren't UTF-8, so the copy has no Payload Format Indicator. Its Content Type
+//still describes the payload once decoded, as an HTTP Content-Type does next to Content-Encoding.
+pub fn compress_publish(publish_packet: &ControlPacket, content_encoding: ContentEncoding, minimum_size: usize) -> Option<ControlPacket> {
+    trace!("Broker::compress_publish");
+    let data = publish_packet.payload_opt()?.data();
+    let properties = publish_packet.variable_header().properties();
+    let already_encoded = properties.iter()
+        .any(|property| matches!(property, Property::UserProperty(key, _) if key == CONTENT_ENCODING_PROPERTY));
+    if data.len() < minimum_size || already_encoded {
+        return None;
+    }
+    let compressed = match content_encoding.encode(data) {
+        Ok(compressed) if compressed.len() < data.len() => { compressed }
+        Ok(_) => { return None; }
+        Err(err) => {
+            debug!("Can't compress payload with {:?}. {}", content_encoding, err);
+            return None;
+        }
+    };
+    debug!("Compressed payload on topic {:?} from {} to {} bytes", publish_packet.variable_header().topic_name(), data.len(), compressed.len());
+    let mut properties: Vec<Property> = properties.iter()
+        .filter(|property| !matches!(property, Property::PayloadFormatIndicator(_)))
+        .cloned()
+        .collect();
+    properties.push(Property::UserProperty(String::from(CONTENT_ENCODING_PROPERTY), String::from(content_encoding.name())));
+    return Some(ControlPacket::publish_with_payload(
+        publish_packet.variable_header().packet_identifier_opt(),
+        publish_packet.variable_header().topic_name().clone(),
+        *publish_packet.fixed_header().qos_level(),
+        *publish_packet.fixed_header().retain(),
+        properties,
+        compressed,
+    ));
+}
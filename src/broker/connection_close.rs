@@ -0,0 +1,42 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use log::{debug, trace};
+
+use crate::{ClientHandler, TopicHandler};
+use crate::broker::invariants::Invariants;
+use crate::broker::shared_rebalance::SharedRebalance;
+use crate::broker::utils::{schedule_session_expiry, set_disconnected};
+use crate::limits::quota_handler::QuotaHandler;
+
+//What follows the end of a client's connection, however it ended: a DISCONNECT from either side, a failed write,
+//a lost TCP connection. The session and its subscriptions stay until the Session Expiry Interval elapses.
+#[derive(Debug)]
+pub struct ConnectionClose {
+    client_handler: Arc<ClientHandler>,
+    topic_handler: Arc<TopicHandler>,
+    quota_handler: Arc<QuotaHandler>,
+    shared_rebalance: Arc<SharedRebalance>,
+    invariants: Arc<Invariants>,
+}
+
+impl ConnectionClose {
+    //Returns the client whose connection it was. None when the socket was never registered, was taken over
+    //or was already closed, the session then belongs to another connection or is already on its way out.
+    pub async fn closed(&self, socket: &SocketAddr) -> Option<String> {
+        trace!("ConnectionClose::closed");
+        let unacknowledged = self.client_handler.limits.unacknowledged(socket);
+        let client_id = self.client_handler.unregister_by_socket(socket)?;
+        debug!("Connection of client {:?} on socket {:?} closed", client_id, socket);
+        self.quota_handler.release(&client_id);
+        self.invariants.forget(&client_id);
+        set_disconnected(&*self.client_handler.sessions, &client_id);
+        self.shared_rebalance.rebalance(&client_id, unacknowledged).await;
+        schedule_session_expiry(&client_id, self.client_handler.clone(), self.topic_handler.clone());
+        Some(client_id)
+    }
+
+    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, quota_handler: Arc<QuotaHandler>, shared_rebalance: Arc<SharedRebalance>, invariants: Arc<Invariants>) -> Self {
+        Self { client_handler, topic_handler, quota_handler, shared_rebalance, invariants }
+    }
+}
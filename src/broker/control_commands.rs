@@ -0,0 +1,63 @@
+use std::sync::Arc;
+
+use log::{info, warn};
+
+use crate::config::broker_config::ControlConfig;
+use crate::logging::log_levels::{LogLevelRequest, LogLevels};
+use crate::model::control_packet::ControlPacket;
+use crate::model::reason_code::ReasonCode;
+
+pub const CONTROL_TOPIC_PREFIX: &str = "$CONTROL/";
+pub const CONTROL_LOG_LEVEL_TOPIC: &str = "$CONTROL/log-level";
+
+//Broker commands published by clients to $CONTROL/ topics. They are run instead of forwarded,
+//the reason code tells the client whether the command ran.
+#[derive(Debug)]
+pub struct ControlCommands {
+    config: ControlConfig,
+    log_levels: Arc<LogLevels>,
+}
+
+impl ControlCommands {
+    pub fn is_command(topic_name: &str) -> bool {
+        topic_name.starts_with(CONTROL_TOPIC_PREFIX)
+    }
+
+    pub fn run(&self, client_id: &String, username: Option<&String>, control_packet: &ControlPacket) -> ReasonCode {
+        let topic_name = control_packet.variable_header().topic_name();
+        if !username.is_some_and(|username| self.config.usernames.contains(username)) {
+            warn!("Refused {:?} of client {:?} with username {:?}", topic_name, client_id, username);
+            return ReasonCode::NotAuthorized;
+        }
+        let payload = control_packet.payload_opt().map(|payload| payload.data().as_slice()).unwrap_or_default();
+        return match topic_name.as_str() {
+            CONTROL_LOG_LEVEL_TOPIC => {
+                let request: LogLevelRequest = match serde_json::from_slice(payload) {
+                    Ok(request) => { request }
+                    Err(err) => {
+                        warn!("Invalid {} from client {:?}: {}", topic_name, client_id, err);
+                        return ReasonCode::PayloadFormatInvalid;
+                    }
+                };
+                match self.log_levels.set(&request) {
+                    Ok(_) => {
+                        info!("Client {:?} set the log level of {} to {}", client_id, request.module, request.level);
+                        ReasonCode::Success
+                    }
+                    Err(err) => {
+                        warn!("Invalid {} from client {:?}: {}", topic_name, client_id, err);
+                        ReasonCode::PayloadFormatInvalid
+                    }
+                }
+            }
+            _ => {
+                warn!("Unknown command {:?} from client {:?}", topic_name, client_id);
+                ReasonCode::TopicNameInvalid
+            }
+        };
+    }
+
+    pub fn new(config: ControlConfig, log_levels: Arc<LogLevels>) -> Self {
+        Self { config, log_levels }
+    }
+}
@@ -0,0 +1,50 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use serde::Serialize;
+
+use crate::model::control_packet::ControlPacket;
+
+//Topic the broker publishes a JSON delivery report to for every PUBLISH on the reported topics
+pub const SYS_DELIVERY_TOPIC: &str = "$SYS/delivery";
+
+//What became of one PUBLISH, so QoS 0 loss can be audited without acknowledging every message
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct DeliveryReport {
+    //Hash of the publisher, the topic, the payload and the receive time
+    pub message_id: String,
+    pub topic: String,
+    pub qos: u8,
+    pub publisher: String,
+    //Unix epoch milliseconds
+    pub received_at: i64,
+    //Deliveries the subscriptions resolved to
+    pub subscribers: usize,
+    //Deliveries shed while the connection writers fell behind
+    pub dropped: usize,
+}
+
+impl DeliveryReport {
+    pub fn message_id(publisher: &str, control_packet: &ControlPacket, received_at: i64) -> String {
+        let mut hasher = DefaultHasher::new();
+        publisher.hash(&mut hasher);
+        control_packet.variable_header().topic_name().hash(&mut hasher);
+        control_packet.payload_opt().map(|payload| payload.data()).hash(&mut hasher);
+        received_at.hash(&mut hasher);
+        format!("{:016x}", hasher.finish())
+    }
+
+    pub fn new(publisher: &str, control_packet: &ControlPacket, received_at: i64, subscribers: usize, dropped: usize) -> Self {
+        Self {
+            message_id: Self::message_id(publisher, control_packet, received_at),
+            topic: control_packet.variable_header().topic_name().clone(),
+            qos: *control_packet.fixed_header().qos_level() as u8,
+            publisher: publisher.to_string(),
+            received_at,
+            subscribers,
+            dropped,
+        }
+    }
+}
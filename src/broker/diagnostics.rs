@@ -0,0 +1,145 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use chrono::{Local, Utc};
+use log::{error, info, trace};
+use serde::Serialize;
+
+use crate::broker::broker_info::VERSION;
+use crate::broker::packet_dispatcher::PacketDispatcher;
+use crate::broker::utils::queued_packets;
+use crate::config::broker_config::DiagnosticsConfig;
+use crate::limits::congestion_control::CongestionControl;
+use crate::topic::tree_telemetry::TreeShape;
+
+#[derive(Debug)]
+#[derive(Serialize)]
+pub struct ClientDiagnostics {
+    pub client_id: String,
+    pub address: String,
+    pub queued_messages: usize,
+    //QoS 1 and QoS 2 publishes sent and not acknowledged yet
+    pub inflight_publishes: usize,
+    //Unfinished QoS 2 handshakes in both directions
+    pub qos2_handshakes: usize,
+    pub subscriptions: usize,
+}
+
+//Memory and threads of the process from /proc/self/status, absent on other systems
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct ProcessDiagnostics {
+    pub resident_bytes: Option<u64>,
+    pub peak_resident_bytes: Option<u64>,
+    pub threads: Option<u64>,
+}
+
+impl ProcessDiagnostics {
+    pub fn parse(status: &str) -> Self {
+        let field = |name: &str| status.lines()
+            .find_map(|line| line.strip_prefix(name))
+            .and_then(|value| value.split_whitespace().next())
+            .and_then(|value| value.parse::<u64>().ok());
+        Self { resident_bytes: field("VmRSS:").map(|kb| kb * 1024), peak_resident_bytes: field("VmHWM:").map(|kb| kb * 1024), threads: field("Threads:") }
+    }
+
+    pub fn current() -> Option<Self> {
+        std::fs::read_to_string("/proc/self/status").ok().map(|status| Self::parse(&status))
+    }
+}
+
+#[derive(Debug)]
+#[derive(Serialize)]
+pub struct DiagnosticsSnapshot {
+    pub version: &'static str,
+    //Unix epoch milliseconds
+    pub taken_at: i64,
+    pub uptime_secs: u64,
+    pub connected_clients: usize,
+    pub sessions: usize,
+    //Connected clients sorted by client_id, up to diagnostics.max_clients
+    pub clients: Vec<ClientDiagnostics>,
+    //Packets handed to the connection writers that they haven't picked up yet
+    pub outbound_queue: usize,
+    //Packets the handlers are working on
+    pub packets_in_flight: u64,
+    pub subscription_tree: TreeShape,
+    pub retained_messages: usize,
+    pub process: Option<ProcessDiagnostics>,
+}
+
+#[derive(Debug)]
+#[derive(Serialize)]
+pub struct DiagnosticsDump {
+    pub path: String,
+    pub snapshot: DiagnosticsSnapshot,
+}
+
+//Writes what a bug report needs when the broker can't be debugged live, on SIGUSR1 or POST /diagnostics
+#[derive(Debug)]
+pub struct Diagnostics {
+    config: DiagnosticsConfig,
+    packet_dispatcher: Arc<PacketDispatcher>,
+}
+
+impl Diagnostics {
+    pub fn snapshot(&self) -> DiagnosticsSnapshot {
+        trace!("Diagnostics::snapshot");
+        let packet_dispatcher = &self.packet_dispatcher;
+        let connected = packet_dispatcher.client_handler.connected_clients();
+        let qos2_handshakes = packet_dispatcher.qos2_tracker.pending_by_client();
+        let clients = connected.iter()
+            .take(self.config.max_clients)
+            .map(|(client_id, socket)| ClientDiagnostics {
+                client_id: client_id.clone(),
+                address: socket.to_string(),
+                queued_messages: queued_packets(&*packet_dispatcher.client_handler.sessions, client_id),
+                inflight_publishes: packet_dispatcher.client_handler.limits.inflight(socket),
+                qos2_handshakes: qos2_handshakes.get(client_id).copied().unwrap_or(0),
+                subscriptions: packet_dispatcher.topic_handler.subscription_count(client_id),
+            })
+            .collect();
+        DiagnosticsSnapshot {
+            version: VERSION,
+            taken_at: Utc::now().timestamp_millis(),
+            uptime_secs: packet_dispatcher.broker_info.uptime().as_secs(),
+            connected_clients: connected.len(),
+            sessions: packet_dispatcher.client_handler.sessions.session_count(),
+            clients,
+            outbound_queue: CongestionControl::queued(&packet_dispatcher.to_listener),
+            packets_in_flight: packet_dispatcher.metrics.process_message.in_flight.0.get(),
+            subscription_tree: TreeShape::of(&packet_dispatcher.topic_handler.subscription_tree()),
+            retained_messages: packet_dispatcher.topic_handler.retained_count(),
+            process: ProcessDiagnostics::current(),
+        }
+    }
+
+    //Writes a snapshot to diagnostics.directory as patina-diagnostics-<timestamp>.json
+    pub fn dump(&self) -> Result<DiagnosticsDump, String> {
+        let snapshot = self.snapshot();
+        let directory = PathBuf::from(&self.config.directory);
+        std::fs::create_dir_all(&directory).map_err(|err| format!("Can't create {}. {}", directory.display(), err))?;
+        let path = directory.join(format!("patina-diagnostics-{}.json", Local::now().format("%Y%m%d%H%M%S%3f")));
+        let json = serde_json::to_vec_pretty(&snapshot).map_err(|err| format!("Can't serialize diagnostics. {}", err))?;
+        std::fs::write(&path, json).map_err(|err| format!("Can't write {}. {}", path.display(), err))?;
+        info!("Diagnostics written to {}", path.display());
+        Ok(DiagnosticsDump { path: path.display().to_string(), snapshot })
+    }
+
+    #[cfg(unix)]
+    pub async fn handle_signals(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut signals = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1())?;
+        info!("Diagnostics are written on SIGUSR1");
+        while signals.recv().await.is_some() {
+            if let Err(err) = self.dump() {
+                error!("{}", err);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn new(config: DiagnosticsConfig, packet_dispatcher: Arc<PacketDispatcher>) -> Self {
+        Self { config, packet_dispatcher }
+    }
+}
@@ -2,21 +2,44 @@ use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Instant;
 
-use log::{debug, info};
+use log::{debug, error, info};
 use metered::{*};
 use tokio::sync::mpsc::Sender;
 
 use crate::{ClientHandler, TopicHandler};
-use crate::broker::utils::{generate_client_id, register_clean_session, register_session, send_packet};
+use crate::auth::acl::Acl;
+use crate::auth::authenticator::Authenticator;
+use crate::broker::handler::shared_handles::SharedHandles;
+use crate::broker::invariants::Invariants;
+use crate::broker::retained_delivery::RetainedDelivery;
+use crate::broker::utils::{generate_client_id, generate_client_id_suffix, publish_sys_message, register_clean_session, send_packet, set_connection_metadata, set_request_problem_information, set_session_expiry_interval};
+use crate::config::broker_config::{BrokerConfig, PROTOCOL_MAXIMUM_PACKET_SIZE, TakeoverPolicy};
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
+use crate::limits::congestion_control::{CongestionControl, RETRY_AFTER_PROPERTY};
+use crate::limits::connection_pacing::ConnectionPacing;
+use crate::limits::quota_handler::QuotaHandler;
 use crate::model::control_packet::ControlPacket;
 use crate::model::reason_code::ReasonCode;
-use crate::session::session_handler::SessionState;
+use crate::model::variable_header::Property;
+use crate::session::client_limits::ClientLimits;
+use crate::session::session_handler::{ConnectionMetadata, SessionState};
+use crate::session::takeover_tracker::{SYS_TAKEOVER_TOPIC, TakeoverTracker};
 
 #[derive(Debug)]
 pub struct ConnectHandler {
     pub(crate) metrics: ConnectHandlerMetrics,
+    config: Arc<BrokerConfig>,
     pub(crate) client_handler: Arc<ClientHandler>,
     pub(crate) topic_handler: Arc<TopicHandler>,
+    pub(crate) quota_handler: Arc<QuotaHandler>,
+    authenticator: Arc<Authenticator>,
+    acl: Arc<Acl>,
+    pub(crate) takeover_tracker: Arc<TakeoverTracker>,
+    retained_delivery: Arc<RetainedDelivery>,
+    invariants: Arc<Invariants>,
+    congestion_control: Arc<CongestionControl>,
+    pub(crate) connack_pacing: ConnectionPacing,
     to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>
 }
 
@@ -24,43 +47,173 @@ pub struct ConnectHandler {
 impl ConnectHandler {
 
     #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
-    pub async fn process(&self, socket: &SocketAddr, control_packet: &ControlPacket) -> Result<(), String>{
+    pub async fn process(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<()>{
+        let socket = &context.socket;
         let now = Instant::now();
+        //A client that sent no client_id learns the one the broker assigned from CONNACK
+        let assigned = !control_packet.has_client_id();
         let mut client_id = generate_client_id();
-        if control_packet.has_client_id() {
+        if !assigned {
             debug!("Using client's client_id");
             client_id = control_packet.payload().client_id().to_string();
         }
         info!("CONNECT client: {:?}", client_id);
+        let limits = match ClientLimits::from_connect(control_packet.variable_header()) {
+            Ok(result) => { result }
+            Err(reason_code) => {
+                info!("Refusing CONNECT of client {:?} with a Receive Maximum or Maximum Packet Size of 0", client_id);
+                self.refuse(socket, reason_code, vec![]).await;
+                return Ok(());
+            }
+        };
 
-        if let Some(previous_socket) = self.client_handler.register(&socket, &client_id) {
+        //Before authentication, a busy broker spends as little as it can on a client it won't take
+        let queued = CongestionControl::queued(&self.to_listener);
+        if let Some(retry_after_secs) = self.congestion_control.connect_retry_after(queued) {
+            info!("Broker busy with {} packets queued. Refusing CONNECT of client {:?}, retry after {}s", queued, client_id, retry_after_secs);
+            let retry_after = Property::UserProperty(String::from(RETRY_AFTER_PROPERTY), retry_after_secs.to_string());
+            self.refuse(socket, ReasonCode::ServerBusy, vec![retry_after]).await;
+            return Ok(());
+        }
+
+        if let Err(refusal) = self.authenticator.authenticate(control_packet.payload().username(), control_packet.payload().password(), socket.ip()) {
+            info!("Refusing CONNECT of client {:?} on socket {:?} with {:?} in {}ms", client_id, socket, refusal.reason_code, refusal.delay.as_millis());
+            tokio::time::sleep(refusal.delay).await;
+            self.refuse(socket, refusal.reason_code, vec![]).await;
+            return Ok(());
+        }
+
+        if let Some(will_topic) = control_packet.payload().will_topic() {
+            if !self.acl.can_publish(&client_id, control_packet.payload().username(), will_topic) {
+                info!("Refusing CONNECT of client {:?}, its Will Message may not be published to {:?}", client_id, will_topic);
+                self.refuse(socket, ReasonCode::NotAuthorized, vec![]).await;
+                return Ok(());
+            }
+        }
+
+        let mut connack_properties = vec![];
+        if assigned {
+            connack_properties.push(Property::AssignedClientIdentifier(client_id.clone()));
+        }
+        if self.client_handler.find_connection(&client_id).await.is_some() {
+            match self.config.session.takeover_policy {
+                TakeoverPolicy::KickOld => {
+                    debug!("Client {:?} is already connected. Previous connection will be taken over", client_id);
+                }
+                TakeoverPolicy::RejectNew => {
+                    info!("Client {:?} is already connected. Rejecting connection on socket {:?}", client_id, socket);
+                    self.refuse(socket, ReasonCode::ClientIdentifierNotValid, vec![]).await;
+                    return Ok(());
+                }
+                TakeoverPolicy::AllowBothWithSuffix => {
+                    let mut suffixed_client_id = generate_client_id_suffix(&client_id);
+                    while self.client_handler.find_connection(&suffixed_client_id).await.is_some() {
+                        suffixed_client_id = generate_client_id_suffix(&client_id);
+                    }
+                    info!("Client {:?} is already connected. Assigning client_id {:?} to socket {:?}", client_id, suffixed_client_id, socket);
+                    client_id = suffixed_client_id;
+                    connack_properties.push(Property::AssignedClientIdentifier(client_id.clone()));
+                }
+            }
+        }
+
+        if let Some(username) = control_packet.payload().username() {
+            if !self.client_handler.add_user_connection(&client_id, username, self.config.session.max_connections_per_user) {
+                info!("Username {:?} has too many connections. Rejecting client {:?} on socket {:?}", username, client_id, socket);
+                self.refuse(socket, ReasonCode::QuotaExceeded, vec![]).await;
+                return Ok(());
+            }
+        } else {
+            self.client_handler.remove_user_connection(&client_id);
+        }
+
+        let previous_connection = self.client_handler.register(&socket, &client_id);
+        self.client_handler.limits.set(socket, limits);
+        self.client_handler.sessions.cancel_expiry(&client_id);
+        if let Some(previous_socket) = previous_connection {
             info!("Found a previous connection on socket {:?} for client_id {:?}", previous_socket, client_id);
             let disconnect_packet = ControlPacket::disconnect(ReasonCode::SessionTakenOver);
             send_packet(previous_socket, &disconnect_packet, &self.to_listener).await;
+            let takeover_event = self.takeover_tracker.record(&client_id, socket, &previous_socket);
+            match serde_json::to_vec(&takeover_event) {
+                Ok(payload) => {
+                    publish_sys_message(SYS_TAKEOVER_TOPIC, payload, &self.client_handler, &self.topic_handler, &self.to_listener).await;
+                }
+                Err(err) => { error!("Can't serialize takeover event {:?}: {}", takeover_event, err); }
+            }
         }
 
         let mut session_present = false;
         if control_packet.variable_header().connect_flags().clean_start_flag() {
             debug!("Creating clean session for client: {:?}", client_id);
-            register_clean_session(&client_id);
+            register_clean_session(&*self.client_handler.sessions, &client_id);
             self.topic_handler.unsubscribe_all(&client_id);
+            self.retained_delivery.forget(&client_id);
+            self.invariants.forget(&client_id);
         } else {
-            session_present = match register_session(&client_id) {
+            session_present = match self.client_handler.sessions.claim_session(&client_id).await {
                 SessionState::SessionPresent => true,
                 SessionState::CleanSession => false
             };
         }
-        let connack_packet = ControlPacket::connack(session_present);
+        set_session_expiry_interval(&*self.client_handler.sessions, &client_id, control_packet.variable_header().session_expiry_interval().unwrap_or(0));
+        set_request_problem_information(&*self.client_handler.sessions, &client_id, control_packet.variable_header().request_problem_information());
+        set_connection_metadata(&*self.client_handler.sessions, &client_id, ConnectionMetadata::from_connect(*socket, control_packet));
+        if let Some(response_topic_prefix) = self.config.session.response_topic_prefix.as_ref() {
+            if control_packet.variable_header().request_response_information() {
+                connack_properties.push(Property::ResponseInformation(format!("{}/{}", response_topic_prefix, client_id)));
+            }
+        }
+        //Absent means available, so only disabled features are advertised
+        if !self.config.subscription.wildcard_subscriptions {
+            connack_properties.push(Property::WildcardSubscriptionAvailable(0));
+        }
+        if !self.config.subscription.shared_subscriptions {
+            connack_properties.push(Property::SharedSubscriptionAvailable(0));
+        }
+        if self.config.packet.topic_alias_maximum > 0 {
+            connack_properties.push(Property::TopicAliasMaximum(self.config.packet.topic_alias_maximum));
+        }
+        if self.config.packet.maximum_packet_size < PROTOCOL_MAXIMUM_PACKET_SIZE {
+            connack_properties.push(Property::MaximumPacketSize(self.config.packet.maximum_packet_size));
+        }
+        let quota_profile = self.quota_handler.assign(&client_id, control_packet.payload().username());
+        if let Some(max_inflight) = quota_profile.and_then(|quota_profile| quota_profile.max_inflight) {
+            connack_properties.push(Property::ReceiveMaximum(max_inflight));
+        }
+        //A session going on on this connection takes over what the previous one left unacknowledged
+        let resent = match (previous_connection, session_present) {
+            (Some(previous_socket), true) => { self.client_handler.limits.take_over(&previous_socket, socket) }
+            _ => { vec![] }
+        };
+        //The session is set up, the traffic of the client waits for its CONNACK
+        self.connack_pacing.pace().await;
+        let connack_packet = ControlPacket::connack(session_present, ReasonCode::Success, connack_properties);
         send_packet(socket.to_owned(), &connack_packet, &self.to_listener).await;
-        //TODO Check Auth
-        //TODO Check previous session using client_id
-        //TODO Check clean_start
+        if session_present {
+            self.retained_delivery.resume(&client_id);
+        }
+        if !resent.is_empty() {
+            info!("Sending {} unacknowledged packets of the previous connection of client {:?} again", resent.len(), client_id);
+        }
+        for packet in resent {
+            send_packet(socket.to_owned(), &packet, &self.to_listener).await;
+            self.takeover_tracker.metrics.resent.incr();
+        }
         debug!("Connect handling took {}ms", now.elapsed().as_millis());
         Ok(())
     }
 
 
-    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
-        Self { metrics: ConnectHandlerMetrics::default(), client_handler, topic_handler, to_listener }
+    //A refused CONNECT gets its CONNACK and no DISCONNECT, the connection is closed once the CONNACK is written
+    async fn refuse(&self, socket: &SocketAddr, reason_code: ReasonCode, properties: Vec<Property>) {
+        let connack_packet = ControlPacket::connack(false, reason_code, properties);
+        send_packet(socket.to_owned(), &connack_packet, &self.to_listener).await;
+    }
+
+    pub fn new(shared: SharedHandles, authenticator: Arc<Authenticator>, acl: Arc<Acl>, takeover_tracker: Arc<TakeoverTracker>, retained_delivery: Arc<RetainedDelivery>, invariants: Arc<Invariants>, congestion_control: Arc<CongestionControl>) -> Self {
+        let SharedHandles { config, client_handler, topic_handler, quota_handler, to_listener } = shared;
+        let connack_pacing = ConnectionPacing::new("CONNACK", config.pacing.connack_per_sec, config.pacing.connack_burst, config.pacing.jitter_ms);
+        Self { metrics: ConnectHandlerMetrics::default(), config, client_handler, topic_handler, quota_handler, authenticator, acl, takeover_tracker, retained_delivery, invariants, congestion_control, connack_pacing, to_listener }
     }
 }
\ No newline at end of file
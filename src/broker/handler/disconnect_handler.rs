@@ -1,20 +1,23 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
 
-use log::{debug, info};
+use log::{debug, info, warn};
 use metered::{*};
 use tokio::sync::mpsc::Sender;
 
-use crate::{ClientHandler, TopicHandler};
-use crate::broker::utils::send_packet;
+use crate::broker::connection_close::ConnectionClose;
+use crate::broker::utils::{get_session_expiry_interval, send_packet, set_session_expiry_interval};
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
 use crate::model::control_packet::ControlPacket;
 use crate::model::reason_code::ReasonCode;
+use crate::session::session_directory::SessionDirectory;
 
 #[derive(Debug)]
 pub struct DisconnectHandler {
     pub(crate) metrics: DisconnectHandlerMetrics,
-    pub(crate) client_handler: Arc<ClientHandler>,
-    pub(crate) topic_handler: Arc<TopicHandler>,
+    connection_close: Arc<ConnectionClose>,
+    sessions: Arc<dyn SessionDirectory>,
     to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>
 
 }
@@ -23,18 +26,31 @@ pub struct DisconnectHandler {
 impl DisconnectHandler {
 
     #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
-    pub async fn process(&self, socket: &SocketAddr, control_packet: &ControlPacket) -> Result<(), String>{
-        let client_id = self.client_handler.get_client_id(&socket)?;
+    pub async fn process(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<()>{
+        let socket = &context.socket;
+        let client_id = context.client_id()?.clone();
         info!("Got a DISCONNECT packet for client {:?}. Going to clean outgoing connections", client_id);
         debug!("Disconnect reason: {:?}. Properties: {:?}", if let Some(header) = control_packet.variable_header_opt() {header.reason_code()} else {None}, if let Some(header) = control_packet.variable_header_opt() {Some(header.properties())} else {None});
-        self.client_handler.unregister(&socket, &client_id);
-        let disconnect_packet = ControlPacket::disconnect(ReasonCode::NormalDisconnection);
+        let mut reason_code = ReasonCode::NormalDisconnection;
+        if let Some(session_expiry_interval) = control_packet.variable_header_opt().and_then(|header| header.session_expiry_interval()) {
+            //A session created with a zero Session Expiry Interval can't be extended on DISCONNECT
+            if get_session_expiry_interval(&*self.sessions, &client_id) == Some(0) && session_expiry_interval != 0 {
+                warn!("Client {:?} tried to set Session Expiry Interval to {}s on DISCONNECT after connecting with 0", client_id, session_expiry_interval);
+                reason_code = ReasonCode::ProtocolError;
+            } else {
+                debug!("Session Expiry Interval for client {:?} set to {}s", client_id, session_expiry_interval);
+                set_session_expiry_interval(&*self.sessions, &client_id, session_expiry_interval);
+            }
+        }
+        //The session belongs to the connection that took this socket over, if any
+        self.connection_close.closed(socket).await;
+        let disconnect_packet = ControlPacket::disconnect(reason_code);
         send_packet(socket.to_owned(), &disconnect_packet, &self.to_listener).await;
         Ok(())
     }
 
 
-    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
-        Self { metrics: DisconnectHandlerMetrics::default(), client_handler, topic_handler, to_listener }
+    pub fn new(connection_close: Arc<ConnectionClose>, sessions: Arc<dyn SessionDirectory>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        Self { metrics: DisconnectHandlerMetrics::default(), connection_close, sessions, to_listener }
     }
 }
\ No newline at end of file
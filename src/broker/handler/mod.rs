@@ -1,8 +1,11 @@
 pub(crate) mod publish_handler;
+pub(crate) mod puback_handler;
 pub(crate) mod pubrec_handler;
 pub(crate) mod pubrel_handler;
+pub(crate) mod pubcomp_handler;
 pub(crate) mod subscribe_handler;
 pub(crate) mod unsubscribe_handler;
 pub(crate) mod pingreq_handler;
 pub(crate) mod disconnect_handler;
 pub(crate) mod connect_handler;
+pub(crate) mod shared_handles;
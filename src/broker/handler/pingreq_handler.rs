@@ -1,19 +1,27 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Instant;
 
-use log::debug;
+use log::{debug, error, trace};
 use metered::{*};
 use tokio::sync::mpsc::Sender;
 
 use crate::{ClientHandler, TopicHandler};
-use crate::broker::utils::send_packet;
+use crate::broker::utils::{publish_sys_message, queued_packets, send_packet, session_summary};
+use crate::config::broker_config::BrokerConfig;
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
+use crate::limits::congestion_control::CongestionControl;
 use crate::model::control_packet::ControlPacket;
+use crate::session::keep_alive_stats::{KEEP_ALIVE_STATS_TARGET, KeepAliveSample, KeepAliveStats, sys_keep_alive_topic};
 
 #[derive(Debug)]
 pub struct PingreqHandler {
     pub(crate) metrics: PingreqHandlerMetrics,
+    config: Arc<BrokerConfig>,
     pub(crate) client_handler: Arc<ClientHandler>,
     pub(crate) topic_handler: Arc<TopicHandler>,
+    keep_alive_stats: KeepAliveStats,
     to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>
 
 }
@@ -22,16 +30,42 @@ pub struct PingreqHandler {
 impl PingreqHandler {
 
     #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
-    pub async fn process(&self, socket: &SocketAddr, control_packet: &ControlPacket) -> Result<(), String> {
-        let client_id = self.client_handler.get_client_id(&socket)?;
+    pub async fn process(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<()> {
+        let socket = &context.socket;
+        let client_id = context.client_id()?.clone();
         debug!("PINGREQ from client {:?}", client_id);
         let pingresp_packet = ControlPacket::pingresp();
         send_packet(socket.to_owned(), &pingresp_packet, &self.to_listener).await;
+        if self.config.keep_alive.statistics {
+            self.report_statistics(context, &client_id).await;
+        }
         Ok(())
     }
 
 
-    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
-        Self { metrics: PingreqHandlerMetrics::default(), client_handler, topic_handler, to_listener }
+    async fn report_statistics(&self, context: &ClientContext, client_id: &String) {
+        let (interval, rtt_estimate) = self.keep_alive_stats.record(client_id, context.socket, context.received_at, Instant::now());
+        let sample = KeepAliveSample {
+            client_id: client_id.clone(),
+            keep_alive: session_summary(&*self.client_handler.sessions, client_id).and_then(|summary| summary.connection).map(|connection| connection.keep_alive).unwrap_or(0),
+            interval_ms: interval.map(|interval| interval.as_millis() as u64),
+            rtt_estimate_ms: rtt_estimate.as_millis() as u64,
+            queued_messages: queued_packets(&*self.client_handler.sessions, client_id),
+            outbound_queue: CongestionControl::queued(&self.to_listener),
+        };
+        trace!(target: KEEP_ALIVE_STATS_TARGET, "{:?}", sample);
+        if !self.config.keep_alive.statistics_topic {
+            return;
+        }
+        match serde_json::to_vec(&sample) {
+            Ok(payload) => {
+                publish_sys_message(&sys_keep_alive_topic(client_id), payload, &self.client_handler, &self.topic_handler, &self.to_listener).await;
+            }
+            Err(err) => { error!("Can't serialize keep-alive sample {:?}: {}", sample, err); }
+        }
     }
-}
\ No newline at end of file
+
+    pub fn new(config: Arc<BrokerConfig>, client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        Self { metrics: PingreqHandlerMetrics::default(), config, client_handler, topic_handler, keep_alive_stats: KeepAliveStats::default(), to_listener }
+    }
+}
@@ -0,0 +1,41 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use log::trace;
+use metered::{*};
+use tokio::sync::mpsc::Sender;
+
+use crate::ClientHandler;
+use crate::broker::utils::send_packet;
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
+use crate::model::control_packet::ControlPacket;
+
+#[derive(Debug)]
+pub struct PubackHandler {
+    pub(crate) metrics: PubackHandlerMetrics,
+    pub(crate) client_handler: Arc<ClientHandler>,
+    to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>
+}
+
+#[metered(registry = PubackHandlerMetrics)]
+impl PubackHandler {
+
+    #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
+    pub async fn process(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<()> {
+        let socket = &context.socket;
+        trace!("PUBACK for {:?} Packet Identifier from client {:?}", control_packet.variable_header().packet_identifier_opt(), context.client_id);
+        //The QoS 1 flow is over, a publish deferred by the Receive Maximum of the client can go
+        if let Some(packet_identifier) = control_packet.variable_header().packet_identifier_opt() {
+            if let Some(next_packet) = self.client_handler.limits.release(socket, packet_identifier) {
+                send_packet(socket.to_owned(), &next_packet, &self.to_listener).await;
+            }
+        }
+        Ok(())
+    }
+
+
+    pub fn new(client_handler: Arc<ClientHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        Self { metrics: PubackHandlerMetrics::default(), client_handler, to_listener }
+    }
+}
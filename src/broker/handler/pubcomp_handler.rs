@@ -0,0 +1,43 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use log::trace;
+use metered::{*};
+use tokio::sync::mpsc::Sender;
+
+use crate::ClientHandler;
+use crate::broker::utils::send_packet;
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
+use crate::model::control_packet::ControlPacket;
+use crate::session::qos2_tracker::{Direction, Qos2Tracker};
+
+#[derive(Debug)]
+pub struct PubcompHandler {
+    pub(crate) metrics: PubcompHandlerMetrics,
+    pub(crate) client_handler: Arc<ClientHandler>,
+    qos2_tracker: Arc<Qos2Tracker>,
+    to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>
+}
+
+#[metered(registry = PubcompHandlerMetrics)]
+impl PubcompHandler {
+
+    #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
+    pub async fn process(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<()> {
+        let client_id = context.client_id()?.clone();
+        trace!("PUBCOMP for {:?} Packet Identifier from client {:?}", control_packet.variable_header().packet_identifier_opt(), client_id);
+        if let Some(packet_identifier) = control_packet.variable_header().packet_identifier_opt() {
+            self.qos2_tracker.complete(Direction::Outbound, &client_id, packet_identifier);
+            if let Some(next_packet) = self.client_handler.limits.release(&context.socket, packet_identifier) {
+                send_packet(context.socket, &next_packet, &self.to_listener).await;
+            }
+        }
+        Ok(())
+    }
+
+
+    pub fn new(client_handler: Arc<ClientHandler>, qos2_tracker: Arc<Qos2Tracker>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        Self { metrics: PubcompHandlerMetrics::default(), client_handler, qos2_tracker, to_listener }
+    }
+}
@@ -1,21 +1,70 @@
+use std::collections::{BTreeMap, HashSet};
 use std::net::SocketAddr;
 use std::sync::Arc;
-use std::time::Instant;
 
-use log::{debug, info, trace};
+use chrono::Utc;
+use log::{debug, error, info, trace};
 use metered::{*};
+use serde::Serialize;
 use tokio::sync::mpsc::Sender;
 
 use crate::{ClientHandler, TopicHandler};
-use crate::broker::utils::{persist_packets, send_packet, send_packets};
+use crate::broker::compression::{compress_publish, ContentEncoding};
+use crate::broker::control_commands::ControlCommands;
+use crate::broker::delivery_report::{DeliveryReport, SYS_DELIVERY_TOPIC};
+use crate::broker::handler::shared_handles::SharedHandles;
+use crate::broker::invariants::Invariants;
+use crate::broker::utils::{persist_packet, publish_sys_message, send_packet, send_packets, with_problem_information};
+use crate::config::broker_config::BrokerConfig;
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
+use crate::limits::congestion_control::CongestionControl;
+use crate::limits::fair_share::{FairShare, UNPROFILED_GROUP};
+use crate::limits::quota_handler::QuotaHandler;
+use crate::logging::log_levels::log_levels;
+use crate::metrics::hot_topics::HotTopics;
+use crate::metrics::payload_sizes::PayloadSizes;
 use crate::model::control_packet::ControlPacket;
 use crate::model::qos_level::QoSLevel;
+use crate::model::reason_code::ReasonCode;
+use crate::model::variable_header::Property;
+use crate::session::qos2_tracker::{Direction, Qos2Tracker};
+use crate::topic::subscription::{Delivery, DeliveryTarget};
+use crate::topic::virtual_topics::MessageRate;
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct OfflineDeliveryMetrics {
+    //Stored in a session the client can resume
+    pub(crate) queued: HitCount,
+    //For clients whose session ended with the connection
+    pub(crate) dropped: HitCount,
+}
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct RoutingMetrics {
+    //Acknowledged and discarded without looking for deliveries, nobody was subscribed
+    pub(crate) unrouted: HitCount,
+}
 
 #[derive(Debug)]
 pub struct PublishHandler {
     pub(crate) metrics: PublishHandlerMetrics,
+    pub(crate) offline_metrics: OfflineDeliveryMetrics,
+    pub(crate) routing_metrics: RoutingMetrics,
+    config: Arc<BrokerConfig>,
     pub(crate) client_handler: Arc<ClientHandler>,
     pub(crate) topic_handler: Arc<TopicHandler>,
+    pub(crate) quota_handler: Arc<QuotaHandler>,
+    pub(crate) congestion_control: Arc<CongestionControl>,
+    pub(crate) fair_share: FairShare,
+    qos2_tracker: Arc<Qos2Tracker>,
+    hot_topics: Arc<HotTopics>,
+    payload_sizes: Arc<PayloadSizes>,
+    message_rate: Arc<MessageRate>,
+    control_commands: ControlCommands,
+    pub(crate) invariants: Arc<Invariants>,
     to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>
 
 }
@@ -24,10 +73,20 @@ pub struct PublishHandler {
 impl PublishHandler {
 
     #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
-    pub async fn process(&self, socket: &SocketAddr, control_packet: &ControlPacket) -> Result<(), String>{
-        let now = Instant::now();
+    pub async fn process(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<()>{
+        let socket = &context.socket;
+        let now = context.received_at;
+        let received_at = Utc::now().timestamp_millis();
 
-        let client_id = self.client_handler.get_client_id(&socket)?;
+        let client_id = context.client_id()?.clone();
+        if ControlCommands::is_command(control_packet.variable_header().topic_name()) {
+            let reason_code = self.control_commands.run(&client_id, context.username.as_ref(), control_packet);
+            return self.answer_command(socket, control_packet, reason_code).await;
+        }
+        //The ACL and the quota profile were checked by the PublishAcl and PublishQuota middlewares
+        let payload_size = control_packet.payload_opt().map(|payload| payload.data().len()).unwrap_or(0);
+        self.payload_sizes.record(Direction::Inbound, control_packet.variable_header().topic_name(), payload_size);
+        self.message_rate.record(now);
         if control_packet.fixed_header().qos_level() == &QoSLevel::AtLeastOnce {
             trace!("Sending PUBACK for {:?} Packet Identifier to client {:?}", control_packet.variable_header().packet_identifier_opt(), client_id);
             let puback_packet = ControlPacket::puback(control_packet.variable_header().packet_identifier_opt());
@@ -36,29 +95,254 @@ impl PublishHandler {
             trace!("Sending PUBREC for {:?} Packet Identifier to client {:?}", control_packet.variable_header().packet_identifier_opt(), client_id);
             let pubrec_packet = ControlPacket::pubrec(control_packet.variable_header().packet_identifier_opt());
             send_packet(socket.to_owned(), &pubrec_packet, &self.to_listener).await;
+            if let Some(packet_identifier) = control_packet.variable_header().packet_identifier_opt() {
+                self.qos2_tracker.start(Direction::Inbound, &client_id, packet_identifier);
+            }
+        }
+        if self.is_unrouted(control_packet) {
+            trace!("No subscribers for topic {:?}", control_packet.variable_header().topic_name());
+            self.hot_topics.record(control_packet.variable_header().topic_name(), now);
+            self.routing_metrics.unrouted.incr();
+            return Ok(());
+        }
+        let forwarded_packet = self.forwarded_packet(&client_id, context.username.as_ref(), control_packet, received_at);
+        let control_packet = forwarded_packet.as_ref().unwrap_or(control_packet);
+        if *control_packet.fixed_header().retain() {
+            self.topic_handler.retain_message(&client_id, control_packet, now);
         }
-        let topic_filter = control_packet.variable_header().topic_name();
-        let subscribers =self.topic_handler.find_subscribers(topic_filter);
-        info!("PUBLISH client: {:?} to topic:{:?}. Subscribers count: {:?}", client_id, topic_filter, subscribers.len());
-        trace!("Found subscribers {:?} for topic {:?}", subscribers, topic_filter);
+        let topic_name = control_packet.variable_header().topic_name();
+        self.hot_topics.record(topic_name, now);
+        if let (QoSLevel::ExactlyOnce, Some(packet_identifier)) = (control_packet.fixed_header().qos_level(), control_packet.variable_header().packet_identifier_opt()) {
+            self.invariants.forwarding_qos2(&client_id, packet_identifier);
+        }
+        let deliveries = self.topic_handler.find_deliveries_from(Some(&client_id), topic_name, self.config.subscription.overlap_policy, &self.client_handler);
+        info!("PUBLISH client: {:?} to topic:{:?}. Deliveries count: {:?}", client_id, topic_name, deliveries.len());
+        trace!("Found deliveries {:?} for topic {:?}", deliveries, topic_name);
+        self.topic_handler.register_deliveries(&deliveries);
+        let deliveries = self.without_dropped(deliveries);
 
-        persist_packets(&subscribers, &control_packet);
-        let clients = subscribers
-            .iter()
-            .map(|receiver| {
-                self.client_handler.get_socket(receiver)
-            })
-            .filter(Result::is_ok)
-            .map(|c| c.unwrap().clone())
-            .filter(|receiver| { receiver.ne(&socket) })
-            .collect();
-        send_packets(clients, control_packet, &self.to_listener).await;
+        let queued = CongestionControl::queued(&self.to_listener);
+        //The publisher's group is shed by how much of its share of the deliveries it used
+        let group = self.fair_share.is_enabled()
+            .then(|| self.quota_handler.profile_name(&client_id).unwrap_or_else(|| String::from(UNPROFILED_GROUP)));
+        let share_used = match &group {
+            Some(group) => { self.fair_share.record(group, deliveries.len(), now) }
+            None => { 1.0 }
+        };
+        let mut dropped = 0;
+        //Deliveries with the same QoS, Subscription Identifiers and RETAIN flag share a packet
+        let mut packet2deliveries: BTreeMap<(QoSLevel, Vec<u64>, bool), Vec<&Delivery>> = BTreeMap::new();
+        for delivery in &deliveries {
+            let qos_level = (*control_packet.fixed_header().qos_level()).min(delivery.maximum_qos);
+            let retain = *control_packet.fixed_header().retain() && delivery.retain_as_published;
+            packet2deliveries.entry((qos_level, delivery.subscription_identifiers.clone(), retain)).or_default().push(delivery);
+        }
+        for ((qos_level, subscription_identifiers, retain), deliveries) in packet2deliveries {
+            let delivery_count = deliveries.len();
+            let deliveries: Vec<&Delivery> = deliveries.into_iter()
+                .filter(|_| !self.congestion_control.shed_scaled(topic_name, qos_level, queued, share_used))
+                .collect();
+            dropped += delivery_count - deliveries.len();
+            if deliveries.is_empty() {
+                continue;
+            }
+            deliveries.iter().for_each(|delivery| self.invariants.forwarding(&client_id, &delivery.client_id, now));
+            let delivery_packet = Self::delivery_packet(control_packet, qos_level, &subscription_identifiers, retain);
+            deliveries.iter()
+                .filter(|delivery| self.quota_handler.can_queue(&delivery.client_id, &*self.client_handler.sessions))
+                .for_each(|delivery| persist_packet(&*self.client_handler.sessions, &delivery.client_id, &delivery.topic_filters, &delivery_packet, now));
+            if let (QoSLevel::ExactlyOnce, Some(packet_identifier)) = (qos_level, delivery_packet.variable_header().packet_identifier_opt()) {
+                deliveries.iter()
+                    .filter(|delivery| delivery.connection().is_some())
+                    .for_each(|delivery| self.qos2_tracker.start(Direction::Outbound, &delivery.client_id, packet_identifier));
+            }
+            self.send_deliveries(&delivery_packet, deliveries).await;
+        }
+        if let (Some(group), true) = (&group, dropped > 0) {
+            self.fair_share.record_shed(group, dropped);
+        }
+        if self.config.delivery_report.applies_to(topic_name) {
+            self.report_delivery(&client_id, control_packet, received_at, deliveries.len(), dropped).await;
+        }
         debug!("Publish handling took {}ms", now.elapsed().as_millis());
         Ok(())
     }
 
+    //Nothing to retain, report or deliver: the message needs no copy, stamping or session storage
+    fn is_unrouted(&self, control_packet: &ControlPacket) -> bool {
+        let topic_name = control_packet.variable_header().topic_name();
+        !*control_packet.fixed_header().retain()
+            && !self.config.delivery_report.applies_to(topic_name)
+            && !self.topic_handler.subscription_tree().has_subscribers(topic_name)
+    }
+
+    //Offline clients without a session to resume never get the message, persisting it would only leak it
+    fn without_dropped(&self, deliveries: Vec<Delivery>) -> Vec<Delivery> {
+        deliveries.into_iter()
+            .filter(|delivery| match delivery.target {
+                DeliveryTarget::Connected(_) => { true }
+                DeliveryTarget::Queued => {
+                    self.offline_metrics.queued.incr();
+                    true
+                }
+                DeliveryTarget::Dropped => {
+                    debug!("Dropping message for offline client {:?} without a persistent session", delivery.client_id);
+                    self.offline_metrics.dropped.incr();
+                    false
+                }
+            })
+            .collect()
+    }
+
+    //The publish with the properties the broker adds, None when it is forwarded as received.
+    //DUP refers to the publisher's retransmissions, a receiver gets the message for the first time.
+    fn forwarded_packet(&self, client_id: &String, username: Option<&String>, control_packet: &ControlPacket, received_at: i64) -> Option<ControlPacket> {
+        let topic_name = control_packet.variable_header().topic_name();
+        let mut forwarded_packet = None;
+        //A Topic Alias belongs to the connection of the publisher, the broker sets up none with the subscribers
+        if control_packet.variable_header().topic_alias().is_some() {
+            trace!("Removing Topic Alias of PUBLISH on topic {:?}", topic_name);
+            forwarded_packet = Some(control_packet.clone().without_topic_alias());
+        }
+        if *control_packet.fixed_header().dup_flag() {
+            trace!("Clearing DUP flag of PUBLISH on topic {:?}", topic_name);
+            forwarded_packet = Some(forwarded_packet.unwrap_or_else(|| control_packet.clone()).with_dup_flag(false));
+        }
+        if self.config.receive_timestamp.applies_to(topic_name) {
+            forwarded_packet = Some(forwarded_packet.unwrap_or_else(|| control_packet.clone()).with_user_property(self.config.receive_timestamp.property_name.clone(), received_at.to_string()));
+        }
+        let publisher_identity = &self.config.publisher_identity;
+        if publisher_identity.enabled {
+            //Stripped on every topic, a consumer may not know which prefixes are stamped
+            for property_name in [&publisher_identity.client_id_property, &publisher_identity.username_property] {
+                if control_packet.variable_header().has_user_property(property_name) {
+                    debug!("Removing {:?} set by client {:?} on topic {:?}", property_name, client_id, topic_name);
+                    forwarded_packet = Some(forwarded_packet.unwrap_or_else(|| control_packet.clone()).without_user_property(property_name));
+                }
+            }
+        }
+        if publisher_identity.applies_to(topic_name) {
+            let mut stamped_packet = forwarded_packet.unwrap_or_else(|| control_packet.clone()).with_user_property(publisher_identity.client_id_property.clone(), client_id.clone());
+            if let Some(username) = username {
+                stamped_packet = stamped_packet.with_user_property(publisher_identity.username_property.clone(), username.clone());
+            }
+            forwarded_packet = Some(stamped_packet);
+        }
+        if control_packet.variable_header().message_expiry_interval().is_none() {
+            if let Some(message_expiry_interval) = self.config.message_expiry.default_interval(topic_name) {
+                trace!("Assigning Message Expiry Interval {}s to PUBLISH on topic {:?}", message_expiry_interval, topic_name);
+                forwarded_packet = Some(forwarded_packet.unwrap_or_else(|| control_packet.clone()).with_message_expiry_interval(message_expiry_interval));
+            }
+        }
+        forwarded_packet
+    }
+
+    //Without Retain As Published a subscriber can't tell a message published retained from any other
+    fn delivery_packet(control_packet: &ControlPacket, qos_level: QoSLevel, subscription_identifiers: &[u64], retain: bool) -> ControlPacket {
+        let mut delivery_packet = control_packet.clone();
+        if qos_level.ne(control_packet.fixed_header().qos_level()) {
+            delivery_packet = delivery_packet.with_qos_level(qos_level);
+        }
+        if retain.ne(control_packet.fixed_header().retain()) {
+            delivery_packet = delivery_packet.with_retain(retain);
+        }
+        for subscription_identifier in subscription_identifiers {
+            delivery_packet = delivery_packet.with_property(Property::SubscriptionIdentifier(*subscription_identifier));
+        }
+        delivery_packet
+    }
+
+    async fn send_deliveries(&self, delivery_packet: &ControlPacket, deliveries: Vec<&Delivery>) {
+        let (compressing, mut plain): (Vec<&Delivery>, Vec<&Delivery>) = match self.config.compression.enabled {
+            true => { deliveries.into_iter().partition(|delivery| self.accepts_encoding(delivery)) }
+            false => { (vec![], deliveries) }
+        };
+        let compressed_packet = match compressing.is_empty() {
+            true => { None }
+            false => { compress_publish(delivery_packet, ContentEncoding::Deflate, self.config.compression.minimum_size) }
+        };
+        match compressed_packet {
+            Some(compressed_packet) => {
+                let sockets = self.admitted(Self::get_sockets(&compressing), &compressed_packet);
+                if !sockets.is_empty() {
+                    send_packets(sockets, &compressed_packet, &self.to_listener).await;
+                }
+            }
+            None => { plain.extend(compressing); }
+        }
+        //Queued deliveries have no connection to send to
+        let sockets = self.admitted(Self::get_sockets(&plain), delivery_packet);
+        if !sockets.is_empty() {
+            send_packets(sockets, delivery_packet, &self.to_listener).await;
+        }
+    }
+
+    async fn report_delivery(&self, client_id: &String, control_packet: &ControlPacket, received_at: i64, subscribers: usize, dropped: usize) {
+        let delivery_report = DeliveryReport::new(client_id, control_packet, received_at, subscribers, dropped);
+        match serde_json::to_vec(&delivery_report) {
+            Ok(payload) => {
+                publish_sys_message(SYS_DELIVERY_TOPIC, payload, &self.client_handler, &self.topic_handler, &self.to_listener).await;
+            }
+            Err(err) => { error!("Can't serialize delivery report {:?}: {}", delivery_report, err); }
+        }
+    }
+
+    //QoS 0 publishes are dropped silently, exceeding Receive Maximum is a protocol error.
+    //A publish the ACL denies disconnects the client if acl.disconnect_on_denied_publish is set.
+    pub(crate) async fn refuse_publish(&self, socket: &SocketAddr, client_id: &String, control_packet: &ControlPacket, reason_code: ReasonCode, problem: &str) -> PatinaResult<()> {
+        let packet_identifier = control_packet.variable_header().packet_identifier_opt();
+        let response_packet = match (reason_code, control_packet.fixed_header().qos_level()) {
+            (ReasonCode::ReceiveMaximumExceeded, _) => { ControlPacket::disconnect(reason_code) }
+            (ReasonCode::NotAuthorized, _) if self.config.acl.disconnect_on_denied_publish => { ControlPacket::disconnect(reason_code) }
+            (_, QoSLevel::AtMostOnce) => { return Ok(()); }
+            (_, QoSLevel::AtLeastOnce) => { ControlPacket::puback_with_reason_code(packet_identifier, reason_code) }
+            (_, QoSLevel::ExactlyOnce) => { ControlPacket::pubrec_with_reason_code(packet_identifier, reason_code) }
+        };
+        let reason_string = Property::ReasonString(format!("PUBLISH to {:?} {}", control_packet.variable_header().topic_name(), problem));
+        let response_packet = with_problem_information(&*self.client_handler.sessions, client_id, response_packet.with_property(reason_string));
+        send_packet(socket.to_owned(), &response_packet, &self.to_listener).await;
+        Ok(())
+    }
+
+    //A command isn't forwarded, QoS 0 gets no answer
+    async fn answer_command(&self, socket: &SocketAddr, control_packet: &ControlPacket, reason_code: ReasonCode) -> PatinaResult<()> {
+        let packet_identifier = control_packet.variable_header().packet_identifier_opt();
+        let response_packet = match control_packet.fixed_header().qos_level() {
+            QoSLevel::AtMostOnce => { return Ok(()); }
+            QoSLevel::AtLeastOnce => { ControlPacket::puback_with_reason_code(packet_identifier, reason_code) }
+            QoSLevel::ExactlyOnce => { ControlPacket::pubrec_with_reason_code(packet_identifier, reason_code) }
+        };
+        send_packet(socket.to_owned(), &response_packet, &self.to_listener).await;
+        Ok(())
+    }
+
+    //Connections the receivers had when the deliveries were resolved, the publisher's own among them unless
+    //No Local kept it out. Each connection is addressed once, however many deliveries resolved to it.
+    fn get_sockets(deliveries: &[&Delivery]) -> Vec<SocketAddr> {
+        let mut connections = HashSet::new();
+        deliveries.iter()
+            .filter_map(|delivery| delivery.connection())
+            .filter(|connection| connections.insert(*connection))
+            .map(|connection| connection.socket)
+            .collect()
+    }
+
+    //Sockets whose client can take the publish now, the others get it when they acknowledged an earlier one
+    fn admitted(&self, sockets: Vec<SocketAddr>, delivery_packet: &ControlPacket) -> Vec<SocketAddr> {
+        sockets.into_iter()
+            .inspect(|socket| self.invariants.sending(socket, delivery_packet, self.client_handler.limits.is_inflight(socket, delivery_packet)))
+            .filter(|socket| self.client_handler.limits.admit(socket, delivery_packet))
+            .collect()
+    }
+
+    fn accepts_encoding(&self, delivery: &Delivery) -> bool {
+        delivery.accepts_encoding || self.config.compression.client_ids.contains(&delivery.client_id)
+    }
 
-    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
-        Self { metrics: PublishHandlerMetrics::default(), client_handler, topic_handler, to_listener }
+    pub fn new(shared: SharedHandles, qos2_tracker: Arc<Qos2Tracker>, hot_topics: Arc<HotTopics>, payload_sizes: Arc<PayloadSizes>, message_rate: Arc<MessageRate>, congestion_control: Arc<CongestionControl>, invariants: Arc<Invariants>) -> Self {
+        let SharedHandles { config, client_handler, topic_handler, quota_handler, to_listener } = shared;
+        let control_commands = ControlCommands::new(config.control.clone(), log_levels());
+        let fair_share = FairShare::new(config.clone());
+        Self { metrics: PublishHandlerMetrics::default(), offline_metrics: OfflineDeliveryMetrics::default(), routing_metrics: RoutingMetrics::default(), config, client_handler, topic_handler, quota_handler, congestion_control, fair_share, qos2_tracker, hot_topics, payload_sizes, message_rate, control_commands, invariants, to_listener }
     }
 }
\ No newline at end of file
@@ -5,15 +5,18 @@ use log::trace;
 use metered::{*};
 use tokio::sync::mpsc::Sender;
 
-use crate::{ClientHandler, TopicHandler};
+use crate::ClientHandler;
 use crate::broker::utils::send_packet;
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
 use crate::model::control_packet::ControlPacket;
+use crate::session::qos2_tracker::{Direction, Qos2Tracker};
 
 #[derive(Debug)]
 pub struct PubrecHandler {
     pub(crate) metrics: PubrecHandlerMetrics,
     pub(crate) client_handler: Arc<ClientHandler>,
-    pub(crate) topic_handler: Arc<TopicHandler>,
+    qos2_tracker: Arc<Qos2Tracker>,
     to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>
 
 }
@@ -22,14 +25,21 @@ pub struct PubrecHandler {
 impl PubrecHandler {
 
     #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
-    pub async fn process(&self, socket: &SocketAddr, control_packet: &ControlPacket) -> Result<(), String>{
-
-        let client_id = self.client_handler.get_client_id(&socket)?;
-        trace!("Sending PUBREL for {:?} Packet Identifier to client {:?}", control_packet.variable_header().packet_identifier_opt(), client_id);
-        let pubrel_packet = ControlPacket::pubrel(control_packet.variable_header().packet_identifier_opt());
-        send_packet(socket.to_owned(), &pubrel_packet, &self.to_listener).await;
-
-        let client_id = self.client_handler.get_client_id(&socket)?;
+    pub async fn process(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<()>{
+        let socket = &context.socket;
+
+        let client_id = context.client_id()?.clone();
+        //Waiting for PUBCOMP now
+        if let Some(packet_identifier) = control_packet.variable_header().packet_identifier_opt() {
+            self.qos2_tracker.advance(Direction::Outbound, &client_id, packet_identifier);
+            self.client_handler.limits.received(socket, packet_identifier);
+        }
+        //A PUBREC with an error ends the QoS 2 flow, no PUBCOMP follows
+        if let (Some(packet_identifier), true) = (control_packet.variable_header().packet_identifier_opt(), control_packet.variable_header().reason_code().is_some_and(|reason_code| reason_code.as_u8() >= 0x80)) {
+            if let Some(next_packet) = self.client_handler.limits.release(socket, packet_identifier) {
+                send_packet(socket.to_owned(), &next_packet, &self.to_listener).await;
+            }
+        }
         trace!("Sending PUBREL for {:?} Packet Identifier to client {:?}", control_packet.variable_header().packet_identifier_opt(), client_id);
         let pubrel_packet = ControlPacket::pubrel(control_packet.variable_header().packet_identifier_opt());
         send_packet(socket.to_owned(), &pubrel_packet, &self.to_listener).await;
@@ -37,7 +47,7 @@ impl PubrecHandler {
     }
 
 
-    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
-        Self { metrics: PubrecHandlerMetrics::default(), client_handler, topic_handler, to_listener }
+    pub fn new(client_handler: Arc<ClientHandler>, qos2_tracker: Arc<Qos2Tracker>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        Self { metrics: PubrecHandlerMetrics::default(), client_handler, qos2_tracker, to_listener }
     }
 }
\ No newline at end of file
@@ -5,15 +5,20 @@ use log::trace;
 use metered::{*};
 use tokio::sync::mpsc::Sender;
 
-use crate::{ClientHandler, TopicHandler};
+use crate::broker::invariants::Invariants;
 use crate::broker::utils::send_packet;
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
+use crate::limits::quota_handler::QuotaHandler;
 use crate::model::control_packet::ControlPacket;
+use crate::session::qos2_tracker::{Direction, Qos2Tracker};
 
 #[derive(Debug)]
 pub struct PubrelHandler {
     pub(crate) metrics: PubrelHandlerMetrics,
-    pub(crate) client_handler: Arc<ClientHandler>,
-    pub(crate) topic_handler: Arc<TopicHandler>,
+    pub(crate) quota_handler: Arc<QuotaHandler>,
+    qos2_tracker: Arc<Qos2Tracker>,
+    invariants: Arc<Invariants>,
     to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>
 
 }
@@ -22,8 +27,14 @@ pub struct PubrelHandler {
 impl PubrelHandler {
 
     #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
-    pub async fn process(&self, socket: &SocketAddr, control_packet: &ControlPacket) -> Result<(), String> {
-        let client_id = self.client_handler.get_client_id(&socket)?;
+    pub async fn process(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<()> {
+        let socket = &context.socket;
+        let client_id = context.client_id()?.clone();
+        if let Some(packet_identifier) = control_packet.variable_header().packet_identifier_opt() {
+            self.quota_handler.release_inflight(&client_id, packet_identifier);
+            self.qos2_tracker.complete(Direction::Inbound, &client_id, packet_identifier);
+            self.invariants.released_qos2(&client_id, packet_identifier);
+        }
         trace!("Sending PUBCOMP for {:?} Packet Identifier to client {:?}", control_packet.variable_header().packet_identifier_opt(), client_id);
         let pubcomp_packet = ControlPacket::pubcomp(control_packet.variable_header().packet_identifier_opt());
         send_packet(socket.to_owned(), &pubcomp_packet, &self.to_listener).await;
@@ -31,7 +42,7 @@ impl PubrelHandler {
     }
 
 
-    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
-        Self { metrics: PubrelHandlerMetrics::default(), client_handler, topic_handler, to_listener }
+    pub fn new(quota_handler: Arc<QuotaHandler>, qos2_tracker: Arc<Qos2Tracker>, invariants: Arc<Invariants>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        Self { metrics: PubrelHandlerMetrics::default(), quota_handler, qos2_tracker, invariants, to_listener }
     }
 }
\ No newline at end of file
@@ -0,0 +1,20 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use tokio::sync::mpsc::Sender;
+
+use crate::{ClientHandler, TopicHandler};
+use crate::config::broker_config::BrokerConfig;
+use crate::limits::quota_handler::QuotaHandler;
+use crate::model::control_packet::ControlPacket;
+
+//The handles handlers share, passed as one so a handler's constructor only lists what is its own
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct SharedHandles {
+    pub config: Arc<BrokerConfig>,
+    pub client_handler: Arc<ClientHandler>,
+    pub topic_handler: Arc<TopicHandler>,
+    pub quota_handler: Arc<QuotaHandler>,
+    pub to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>,
+}
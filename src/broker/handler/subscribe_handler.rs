@@ -6,16 +6,33 @@ use log::{debug, info};
 use metered::{*};
 use tokio::sync::mpsc::Sender;
 
-use crate::{ClientHandler, TopicHandler};
-use crate::broker::utils::send_packet;
+use crate::TopicHandler;
+use crate::auth::acl::Acl;
+use crate::broker::compression::ContentEncoding;
+use crate::broker::retained_delivery::RetainedDelivery;
+use crate::broker::utils::{send_packet, with_problem_information};
+use crate::config::broker_config::{BrokerConfig, SharedRetained};
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
+use crate::limits::quota_handler::QuotaHandler;
 use crate::model::control_packet::ControlPacket;
+use crate::model::qos_level::QoSLevel;
 use crate::model::reason_code::ReasonCode;
+use crate::model::topic::RetainHandling;
+use crate::model::variable_header::Property;
+use crate::session::session_directory::SessionDirectory;
+use crate::topic::subscription::SubscriptionSettings;
+use crate::topic::topic_matcher::{is_wildcard, SHARED_SUBSCRIPTION_PREFIX};
 
 #[derive(Debug)]
 pub struct SubscribeHandler {
     pub(crate) metrics: SubscribeHandlerMetrics,
-    pub(crate) client_handler: Arc<ClientHandler>,
+    config: Arc<BrokerConfig>,
     pub(crate) topic_handler: Arc<TopicHandler>,
+    pub(crate) quota_handler: Arc<QuotaHandler>,
+    acl: Arc<Acl>,
+    retained_delivery: Arc<RetainedDelivery>,
+    sessions: Arc<dyn SessionDirectory>,
     to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>
 
 }
@@ -24,29 +41,129 @@ pub struct SubscribeHandler {
 impl SubscribeHandler {
 
     #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
-    pub async fn process(&self, socket: &SocketAddr, control_packet: &ControlPacket) -> Result<(), String> {
+    pub async fn process(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<()> {
+        let socket = &context.socket;
         let now = Instant::now();
 
-        let client_id = self.client_handler.get_client_id(&socket)?;
+        let client_id = context.client_id()?.clone();
         let topic_filters = control_packet.payload().topic_filters();
         info!("SUBSCRIBE client: {:?} to topics: {:?}", client_id, topic_filters);
 
+        let accept_encoding = ContentEncoding::from_properties(control_packet.variable_header().properties());
+        let subscription_identifier = control_packet.variable_header().properties().iter().find_map(|property| match property {
+            Property::SubscriptionIdentifier(value) => Some(*value),
+            _ => None
+        });
         let mut reason_codes = Vec::with_capacity(topic_filters.len());
+        //Sent after the SUBACK, in the order of the topic filters
+        let mut retained_subscriptions = vec![];
         for topic_filter in topic_filters {
-            self.topic_handler.subscribe(&client_id, topic_filter.topic_filter());
-            reason_codes.push(ReasonCode::GrantedQoS0);
+            if let Some(reason_code) = self.disabled_feature(topic_filter.topic_filter()) {
+                info!("Refused subscription of client {:?} to topic {:?}: {:?}", client_id, topic_filter.topic_filter(), reason_code);
+                reason_codes.push(reason_code);
+                continue;
+            }
+            if !self.acl.can_subscribe(&client_id, context.username.as_ref(), topic_filter.topic_filter()) {
+                info!("Refused subscription of client {:?} to topic {:?}: not authorized", client_id, topic_filter.topic_filter());
+                reason_codes.push(ReasonCode::NotAuthorized);
+                continue;
+            }
+            if self.topic_handler.subscription_metadata(&client_id, topic_filter.topic_filter()).is_none() {
+                if let Err(reason_code) = self.quota_handler.check_subscription(&client_id, self.topic_handler.subscription_count(&client_id)) {
+                    info!("Refused subscription of client {:?} to topic {:?}: {:?}", client_id, topic_filter.topic_filter(), reason_code);
+                    reason_codes.push(reason_code);
+                    continue;
+                }
+            }
+            let maximum_qos = topic_filter.options().map(|options| options.maximum_qos()).unwrap_or(QoSLevel::AtMostOnce);
+            let no_local = topic_filter.options().is_some_and(|options| options.no_local());
+            let retain_as_published = topic_filter.options().is_some_and(|options| options.retain_as_published());
+            let settings = SubscriptionSettings { maximum_qos, subscription_identifier, accept_encoding, no_local, retain_as_published };
+            //A repeated SUBSCRIBE replaces the options of the subscription
+            let is_new = self.topic_handler.subscribe_with_settings(&client_id, topic_filter.topic_filter(), &settings);
+            reason_codes.push(match maximum_qos {
+                QoSLevel::AtMostOnce => { ReasonCode::GrantedQoS0 }
+                QoSLevel::AtLeastOnce => { ReasonCode::GrantedQoS1 }
+                QoSLevel::ExactlyOnce => { ReasonCode::GrantedQoS2 }
+            });
+            let retain_handling = topic_filter.options().map(|options| options.retain_handling().clone()).unwrap_or(RetainHandling::SendRetainedMessagesOnSubscribe);
+            let send_retained = match retain_handling {
+                RetainHandling::SendRetainedMessagesOnSubscribe => { true }
+                RetainHandling::SendRetainedMessagesOnNewSubscribe => { is_new }
+                RetainHandling::DontSendRetainedMessages => { false }
+            };
+            if send_retained && self.shared_gets_retained(topic_filter.topic_filter(), is_new) {
+                retained_subscriptions.push((topic_filter.topic_filter(), maximum_qos));
+            }
             debug!("Subscribed client {:?} to topic {:?}", client_id, topic_filter.topic_filter());
         }
-        let suback_packet = ControlPacket::suback(control_packet.variable_header().packet_identifier_opt(), reason_codes);
+        let problems: Vec<String> = [
+            (ReasonCode::NotAuthorized, "not authorized by the ACL"),
+            (ReasonCode::QuotaExceeded, "over the subscription quota"),
+            (ReasonCode::WildcardSubscriptionsNotSupported, "with wildcards, which are disabled"),
+            (ReasonCode::SharedSubscriptionsNotSupported, "shared, which are disabled"),
+        ].into_iter()
+            .map(|(refusal, problem)| (reason_codes.iter().filter(|reason_code| **reason_code == refusal).count(), problem))
+            .filter(|(refused, _)| *refused > 0)
+            .map(|(refused, problem)| format!("{} subscriptions {}", refused, problem))
+            .collect();
+        let mut properties = vec![];
+        if !problems.is_empty() {
+            properties.push(Property::ReasonString(problems.join(", ")));
+        }
+        //Which topic filter got which refusal, the Reason String only has the counts
+        properties.extend(topic_filters.iter().zip(&reason_codes)
+            .filter(|(_, reason_code)| reason_code.as_u8() >= 0x80)
+            .map(|(topic_filter, reason_code)| Property::UserProperty(topic_filter.topic_filter().clone(), format!("{:?}", reason_code))));
+        let suback_packet = ControlPacket::suback(control_packet.variable_header().packet_identifier_opt(), reason_codes, properties);
+        let suback_packet = with_problem_information(&*self.sessions, &client_id, suback_packet);
 
         send_packet(socket.to_owned(), &suback_packet, &self.to_listener).await;
+        for (topic_filter, _) in &retained_subscriptions {
+            self.send_virtual_topics(socket, topic_filter).await;
+        }
+        for (topic_filter, maximum_qos) in retained_subscriptions {
+            self.retained_delivery.start(&client_id, topic_filter, maximum_qos);
+        }
         debug!("Subscribe handling took {}ms", now.elapsed().as_millis());
 
         Ok(())
     }
 
 
-    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
-        Self { metrics: SubscribeHandlerMetrics::default(), client_handler, topic_handler, to_listener }
+    //The computed values of the virtual topics the subscription matches, at QoS 0 with the RETAIN flag like a retained message
+    async fn send_virtual_topics(&self, socket: &SocketAddr, topic_filter: &str) {
+        let virtual_topics = &self.topic_handler.virtual_topics;
+        for (topic_name, value) in virtual_topics.matching(topic_filter, Instant::now()) {
+            let publish_packet = ControlPacket::publish_with_payload(None, topic_name, QoSLevel::AtMostOnce, true, vec![], value);
+            send_packet(socket.to_owned(), &publish_packet, &self.to_listener).await;
+            virtual_topics.metrics.delivered.incr();
+        }
+    }
+
+    //A shared subscription gets no retained messages when it is made, unless configured for the member that starts the group
+    fn shared_gets_retained(&self, topic_filter: &String, is_new: bool) -> bool {
+        if !topic_filter.starts_with(SHARED_SUBSCRIPTION_PREFIX) {
+            return true;
+        }
+        match self.config.subscription.shared_retained {
+            SharedRetained::Never => { false }
+            SharedRetained::FirstMember => { is_new && self.topic_handler.shared_member_count(topic_filter) == 1 }
+        }
+    }
+
+    //Shared subscriptions are checked first, $share/group/a/# is refused as shared rather than as a wildcard
+    fn disabled_feature(&self, topic_filter: &str) -> Option<ReasonCode> {
+        if !self.config.subscription.shared_subscriptions && topic_filter.starts_with(SHARED_SUBSCRIPTION_PREFIX) {
+            return Some(ReasonCode::SharedSubscriptionsNotSupported);
+        }
+        if !self.config.subscription.wildcard_subscriptions && is_wildcard(topic_filter) {
+            return Some(ReasonCode::WildcardSubscriptionsNotSupported);
+        }
+        return None;
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, topic_handler: Arc<TopicHandler>, quota_handler: Arc<QuotaHandler>, acl: Arc<Acl>, retained_delivery: Arc<RetainedDelivery>, sessions: Arc<dyn SessionDirectory>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        Self { metrics: SubscribeHandlerMetrics::default(), config, topic_handler, quota_handler, acl, retained_delivery, sessions, to_listener }
     }
 }
\ No newline at end of file
@@ -3,18 +3,35 @@ use std::sync::Arc;
 
 use log::{debug, info};
 use metered::{*};
+use serde::Serialize;
 use tokio::sync::mpsc::Sender;
 
 use crate::{ClientHandler, TopicHandler};
-use crate::broker::utils::send_packet;
+use crate::broker::utils::{drop_unsubscribed, send_packet, with_problem_information};
+use crate::config::broker_config::BrokerConfig;
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
 use crate::model::control_packet::ControlPacket;
+use crate::model::qos_level::QoSLevel;
 use crate::model::reason_code::ReasonCode;
+use crate::model::variable_header::Property;
+use crate::session::qos2_tracker::{Direction, Qos2Tracker};
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct QueuedDropMetrics {
+    //Queued messages dropped with subscription.drop_queued_on_unsubscribe, never sent
+    pub(crate) dropped: HitCount,
+}
 
 #[derive(Debug)]
 pub struct UnsubscribeHandler {
     pub(crate) metrics: UnsubscribeHandlerMetrics,
+    pub(crate) queued_metrics: QueuedDropMetrics,
+    config: Arc<BrokerConfig>,
     pub(crate) client_handler: Arc<ClientHandler>,
     pub(crate) topic_handler: Arc<TopicHandler>,
+    qos2_tracker: Arc<Qos2Tracker>,
     to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>
 
 }
@@ -23,24 +40,68 @@ pub struct UnsubscribeHandler {
 impl UnsubscribeHandler {
 
     #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
-    pub async fn process(&self, socket: &SocketAddr, control_packet: &ControlPacket) -> Result<(), String> {
-        let client_id = self.client_handler.get_client_id(&socket)?;
+    pub async fn process(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<()> {
+        let socket = &context.socket;
+        let client_id = context.client_id()?.clone();
         let topic_filters = control_packet.payload().topic_filters();
         info!("UNSUBSCRIBE client: {:?} from topics: {:?}", client_id, topic_filters);
         let mut reason_codes = Vec::with_capacity(topic_filters.len());
         for topic_filter in topic_filters {
-            self.topic_handler.unsubscribe(&client_id, topic_filter.topic_filter());
-            reason_codes.push(ReasonCode::Success);
-            debug!("Unsubscribed client {:?} from topic {:?}", client_id, topic_filter.topic_filter());
+            if self.topic_handler.unsubscribe(&client_id, topic_filter.topic_filter()) {
+                reason_codes.push(ReasonCode::Success);
+                debug!("Unsubscribed client {:?} from topic {:?}", client_id, topic_filter.topic_filter());
+            } else {
+                reason_codes.push(ReasonCode::NoSubscriptionExisted);
+                debug!("Client {:?} has no subscription to topic {:?}", client_id, topic_filter.topic_filter());
+            }
+        }
+        let mut properties = vec![];
+        let missing: Vec<&String> = topic_filters.iter().zip(&reason_codes)
+            .filter(|(_, reason_code)| **reason_code == ReasonCode::NoSubscriptionExisted)
+            .map(|(topic_filter, _)| topic_filter.topic_filter())
+            .collect();
+        if !missing.is_empty() {
+            properties.push(Property::ReasonString(format!("{} topic filters matched no subscription", missing.len())));
+            properties.extend(missing.into_iter().map(|topic_filter| Property::UserProperty(topic_filter.clone(), format!("{:?}", ReasonCode::NoSubscriptionExisted))));
+        }
+        if self.config.subscription.drop_queued_on_unsubscribe {
+            let unsubscribed: Vec<String> = topic_filters.iter().zip(&reason_codes)
+                .filter(|(_, reason_code)| **reason_code == ReasonCode::Success)
+                .map(|(topic_filter, _)| topic_filter.topic_filter().clone())
+                .collect();
+            self.drop_queued(socket, &client_id, &unsubscribed);
         }
-        let unsuback_packet = ControlPacket::unsuback(control_packet.variable_header().packet_identifier_opt(), reason_codes);
+        let unsuback_packet = ControlPacket::unsuback(control_packet.variable_header().packet_identifier_opt(), reason_codes, properties);
+        let unsuback_packet = with_problem_information(&*self.client_handler.sessions, &client_id, unsuback_packet);
 
         send_packet(socket.to_owned(), &unsuback_packet, &self.to_listener).await;
         Ok(())
     }
 
+    //What the session and the connection hold back for the client only through the unsubscribed topic filters won't be
+    //sent. The publishes in flight complete their flow
+    fn drop_queued(&self, socket: &SocketAddr, client_id: &String, unsubscribed: &[String]) {
+        if unsubscribed.is_empty() {
+            return;
+        }
+        let limits = &self.client_handler.limits;
+        let dropped = drop_unsubscribed(&*self.client_handler.sessions, client_id, unsubscribed, |packet| limits.is_inflight(socket, packet));
+        if dropped.is_empty() {
+            return;
+        }
+        let packet_identifiers: Vec<u16> = dropped.iter()
+            .filter(|packet| *packet.fixed_header().qos_level() != QoSLevel::AtMostOnce)
+            .filter_map(|packet| packet.variable_header().packet_identifier_opt())
+            .collect();
+        limits.drop_deferred(socket, &packet_identifiers);
+        for packet in dropped.iter().filter(|packet| *packet.fixed_header().qos_level() == QoSLevel::ExactlyOnce) {
+            self.qos2_tracker.complete(Direction::Outbound, client_id, packet.variable_header().packet_identifier());
+        }
+        info!("Dropped {} messages queued for client {:?} through {:?}", dropped.len(), client_id, unsubscribed);
+        self.queued_metrics.dropped.0.incr_by(dropped.len() as u64);
+    }
 
-    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
-        Self { metrics: UnsubscribeHandlerMetrics::default(), client_handler, topic_handler, to_listener }
+    pub fn new(config: Arc<BrokerConfig>, client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, qos2_tracker: Arc<Qos2Tracker>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        Self { metrics: UnsubscribeHandlerMetrics::default(), queued_metrics: QueuedDropMetrics::default(), config, client_handler, topic_handler, qos2_tracker, to_listener }
     }
 }
\ No newline at end of file
@@ -0,0 +1,87 @@
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use dashmap::{DashMap, DashSet};
+use log::error;
+use metered::{*};
+use serde::Serialize;
+
+use crate::model::control_packet::ControlPacket;
+
+//Checked in debug builds and with the strict feature, a release build skips the bookkeeping
+pub const INVARIANTS_CHECKED: bool = cfg!(any(debug_assertions, feature = "strict"));
+
+//Violations of the delivery guarantees, each one also logged as an error
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct InvariantMetrics {
+    //A QoS 2 message forwarded again before the PUBREL of its publisher
+    pub(crate) qos2_duplicates: HitCount,
+    //A publish forwarded to a subscriber after a later one of the same publisher
+    pub(crate) out_of_order: HitCount,
+    //A Packet Identifier sent for a new publish while another one with it is in flight to the same connection
+    pub(crate) packet_id_reuses: HitCount,
+}
+
+//Guarantees the broker gives about the messages it forwards, checked where it forwards them. A violation is
+//counted and logged instead of panicking, so that soak tests show correctness regressions in the metrics.
+//The bookkeeping grows with the pairs of publishers and subscribers connected at once, a client's share of it is
+//forgotten when its connection closes or it starts clean.
+#[derive(Debug, Default)]
+pub struct Invariants {
+    //QoS 2 messages forwarded by publisher client_id and Packet Identifier, until their PUBREL
+    forwarded_qos2: DashSet<(String, u16)>,
+    //When the broker received the last publish forwarded, by subscriber and publisher client_id
+    last_forwarded: DashMap<(String, String), Instant>,
+    pub(crate) metrics: InvariantMetrics,
+}
+
+impl Invariants {
+    //A QoS 2 message is forwarded once, its Packet Identifier names it until the publisher sends PUBREL
+    pub fn forwarding_qos2(&self, publisher: &String, packet_identifier: u16) {
+        if INVARIANTS_CHECKED && !self.forwarded_qos2.insert((publisher.clone(), packet_identifier)) {
+            error!("Invariant violated: QoS 2 message {} of client {:?} forwarded twice", packet_identifier, publisher);
+            self.metrics.qos2_duplicates.incr();
+        }
+    }
+
+    //The PUBREL of the publisher, or the expiry of the handshake, ends the QoS 2 message
+    pub fn released_qos2(&self, publisher: &String, packet_identifier: u16) {
+        if INVARIANTS_CHECKED {
+            self.forwarded_qos2.remove(&(publisher.clone(), packet_identifier));
+        }
+    }
+
+    //Publishes of one publisher reach a subscriber in the order the broker received them
+    pub fn forwarding(&self, publisher: &String, subscriber: &String, received_at: Instant) {
+        if !INVARIANTS_CHECKED {
+            return;
+        }
+        let mut last_received_at = self.last_forwarded.entry((subscriber.clone(), publisher.clone())).or_insert(received_at);
+        if received_at < *last_received_at {
+            error!("Invariant violated: publish of client {:?} received {}ms before the previous one forwarded to client {:?}",
+                publisher, last_received_at.duration_since(received_at).as_millis(), subscriber);
+            self.metrics.out_of_order.incr();
+            return;
+        }
+        *last_received_at = received_at;
+    }
+
+    //The client no longer publishes nor subscribes on this connection. A QoS 2 message it had not released yet is no
+    //longer checked, nor the order of what it receives on its next connection against this one.
+    pub fn forget(&self, client_id: &String) {
+        if INVARIANTS_CHECKED {
+            self.forwarded_qos2.retain(|(publisher, _)| publisher != client_id);
+            self.last_forwarded.retain(|(subscriber, publisher), _| subscriber != client_id && publisher != client_id);
+        }
+    }
+
+    //Only a retransmission, with DUP set, reuses the Packet Identifier of a publish in flight
+    pub fn sending(&self, socket: &SocketAddr, control_packet: &ControlPacket, in_flight: bool) {
+        if INVARIANTS_CHECKED && in_flight && !control_packet.fixed_header().dup_flag_opt().unwrap_or(false) {
+            error!("Invariant violated: Packet Identifier {:?} sent to {} for a new publish while one is in flight",
+                control_packet.variable_header().packet_identifier_opt(), socket);
+            self.metrics.packet_id_reuses.incr();
+        }
+    }
+}
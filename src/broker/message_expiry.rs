@@ -0,0 +1,66 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::model::control_packet::ControlPacket;
+
+//A PUBLISH held by the broker, queued for a session or retained on a topic. Its Message Expiry
+//Interval is counted from the time the broker received it, wherever the interval came from.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct StoredMessage {
+    control_packet: ControlPacket,
+    received_at: Instant,
+    //Subscriptions of the session it was queued through, empty when it wasn't queued through one
+    topic_filters: Vec<Arc<String>>,
+}
+
+impl StoredMessage {
+    pub fn is_expired(&self) -> bool {
+        return self.remaining_interval().map(|remaining| remaining == 0).unwrap_or(false);
+    }
+
+    //Seconds left of the Message Expiry Interval, None when the message never expires
+    pub fn remaining_interval(&self) -> Option<u32> {
+        let message_expiry_interval = self.control_packet.variable_header().message_expiry_interval()?;
+        let elapsed = self.received_at.elapsed();
+        return Some(Duration::from_secs(message_expiry_interval as u64).saturating_sub(elapsed).as_secs() as u32);
+    }
+
+    //The packet to forward, carrying the interval left rather than the original one
+    pub fn to_packet(&self) -> Option<ControlPacket> {
+        return match self.remaining_interval() {
+            None => { Some(self.control_packet.clone()) }
+            Some(0) => { None }
+            Some(remaining) => { Some(self.control_packet.clone().with_message_expiry_interval(remaining)) }
+        };
+    }
+
+    pub fn control_packet(&self) -> &ControlPacket {
+        &self.control_packet
+    }
+
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+
+    pub fn topic_filters(&self) -> &[Arc<String>] {
+        &self.topic_filters
+    }
+
+    //Queued only through subscriptions among topic_filters, so nothing the session still subscribes to wants it
+    pub fn only_through(&self, topic_filters: &[String]) -> bool {
+        !self.topic_filters.is_empty() && self.topic_filters.iter().all(|topic_filter| topic_filters.contains(topic_filter))
+    }
+
+    pub fn with_topic_filters(self, topic_filters: Vec<Arc<String>>) -> Self {
+        Self { topic_filters, ..self }
+    }
+
+    pub fn age(&self) -> Duration {
+        self.received_at.elapsed()
+    }
+
+    pub fn new(control_packet: ControlPacket, received_at: Instant) -> Self {
+        Self { control_packet, received_at, topic_filters: vec![] }
+    }
+}
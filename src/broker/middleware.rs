@@ -0,0 +1,246 @@
+use std::fmt::Debug;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use async_trait::async_trait;
+use log::{debug, info};
+use metered::{*};
+use serde::Serialize;
+
+use crate::ClientHandler;
+use crate::auth::acl::Acl;
+use crate::broker::control_commands::ControlCommands;
+use crate::broker::handler::publish_handler::PublishHandler;
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
+use crate::limits::quota_handler::QuotaHandler;
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::reason_code::ReasonCode;
+use crate::session::client_handler::DEBUG_CAPTURE_TARGET;
+
+//Where a middleware runs before the handler of a packet, in this order. Middlewares of the same stage run in the
+//order they were registered.
+#[derive(Debug)]
+#[derive(Clone, Copy)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub enum Stage {
+    //Looks at every packet, before any other middleware can stop it
+    Observe,
+    //Whether the client may send the packet
+    Auth,
+    //Whether the client may send it now
+    RateLimit,
+    //Whether the packet is still worth handling
+    Validation,
+}
+
+//What a middleware makes of a packet before its handler
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+pub enum Verdict {
+    Continue,
+    //The packet is done with, neither the later middlewares nor the handler get it
+    Stop,
+}
+
+//A concern of every packet, like auditing or quotas, that doesn't belong in each handler
+#[async_trait]
+pub trait PacketMiddleware: Debug + Send + Sync {
+    fn name(&self) -> &str;
+    fn stage(&self) -> Stage;
+    //Before the handler. An error fails the packet like an error of its handler would
+    async fn before(&self, _context: &ClientContext, _control_packet: &ControlPacket) -> PatinaResult<Verdict> {
+        Ok(Verdict::Continue)
+    }
+    //After the handler, in the reverse order of before, with what the handler returned
+    async fn after(&self, _context: &ClientContext, _control_packet: &ControlPacket, _result: &PatinaResult<()>) {}
+}
+
+//The middlewares of the dispatcher in the order they run
+#[derive(Debug, Default)]
+pub struct MiddlewareChain {
+    middlewares: RwLock<Vec<Arc<dyn PacketMiddleware>>>,
+}
+
+impl MiddlewareChain {
+    //After the middlewares of its stage and the ones before
+    pub fn register(&self, middleware: Arc<dyn PacketMiddleware>) {
+        let mut middlewares = self.middlewares.write().unwrap();
+        let position = middlewares.iter().take_while(|registered| registered.stage() <= middleware.stage()).count();
+        debug!("Registered packet middleware {} in stage {:?}", middleware.name(), middleware.stage());
+        middlewares.insert(position, middleware);
+    }
+
+    //The middlewares are taken once per packet, one registered meanwhile gets the next packet
+    pub fn middlewares(&self) -> Vec<Arc<dyn PacketMiddleware>> {
+        self.middlewares.read().unwrap().clone()
+    }
+}
+
+//Logs the packets of clients under debug capture, and the failures of their handlers.
+//A CONNECT is captured by the client_id it asks for.
+#[derive(Debug)]
+pub struct DebugCapture {
+    client_handler: Arc<ClientHandler>,
+}
+
+impl DebugCapture {
+    fn captured_client_id<'a>(&self, context: &'a ClientContext, control_packet: &'a ControlPacket) -> Option<&'a String> {
+        let client_id = match control_packet.fixed_header().packet_type() {
+            ControlPacketType::CONNECT => { control_packet.payload_opt().map(|payload| payload.client_id()) }
+            _ => { context.client_id.as_ref() }
+        };
+        client_id.filter(|client_id| self.client_handler.is_debug_captured(client_id))
+    }
+
+    pub fn new(client_handler: Arc<ClientHandler>) -> Self {
+        Self { client_handler }
+    }
+}
+
+#[async_trait]
+impl PacketMiddleware for DebugCapture {
+    fn name(&self) -> &str {
+        "debug-capture"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Observe
+    }
+
+    async fn before(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<Verdict> {
+        if let Some(client_id) = self.captured_client_id(context, control_packet) {
+            info!(target: DEBUG_CAPTURE_TARGET, "Client {:?} on {} sent {:?}", client_id, context.socket, control_packet);
+        }
+        Ok(Verdict::Continue)
+    }
+
+    async fn after(&self, context: &ClientContext, control_packet: &ControlPacket, result: &PatinaResult<()>) {
+        if let (Some(client_id), Err(err)) = (self.captured_client_id(context, control_packet), result) {
+            info!(target: DEBUG_CAPTURE_TARGET, "Handling {:?} of client {:?} on {} failed: {}", control_packet.fixed_header().packet_type(), client_id, context.socket, err);
+        }
+    }
+}
+
+//Packets the broker got to after their deadline
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct LatePacketMetrics {
+    //Acknowledgements of clients that were gone by then, nothing was left to acknowledge
+    pub(crate) skipped: HitCount,
+}
+
+//Skips an acknowledgement that waited past its deadline for a client that disconnected meanwhile,
+//during a deep backlog handling it would only fail and be retried
+#[derive(Debug, Default)]
+pub struct LateAcknowledgements {
+    pub(crate) metrics: Arc<LatePacketMetrics>,
+}
+
+impl LateAcknowledgements {
+    fn is_stale(context: &ClientContext, control_packet: &ControlPacket, now: Instant) -> bool {
+        let is_acknowledgement = matches!(control_packet.fixed_header().packet_type(),
+            ControlPacketType::PUBACK | ControlPacketType::PUBREC | ControlPacketType::PUBREL | ControlPacketType::PUBCOMP);
+        is_acknowledgement && context.client_id.is_none() && context.deadline().is_some_and(|deadline| deadline < now)
+    }
+}
+
+#[async_trait]
+impl PacketMiddleware for LateAcknowledgements {
+    fn name(&self) -> &str {
+        "late-acknowledgements"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Validation
+    }
+
+    async fn before(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<Verdict> {
+        if !Self::is_stale(context, control_packet, Instant::now()) {
+            return Ok(Verdict::Continue);
+        }
+        info!("Skipping {:?} from {} received {}ms ago, its client is gone", control_packet.fixed_header().packet_type(), context.socket, context.received_at.elapsed().as_millis());
+        self.metrics.skipped.incr();
+        Ok(Verdict::Stop)
+    }
+}
+
+//Refuses a PUBLISH the ACL doesn't allow, $CONTROL commands are authorized by control.usernames
+#[derive(Debug)]
+pub struct PublishAcl {
+    acl: Arc<Acl>,
+    publish_handler: Arc<PublishHandler>,
+}
+
+impl PublishAcl {
+    pub fn new(acl: Arc<Acl>, publish_handler: Arc<PublishHandler>) -> Self {
+        Self { acl, publish_handler }
+    }
+}
+
+#[async_trait]
+impl PacketMiddleware for PublishAcl {
+    fn name(&self) -> &str {
+        "publish-acl"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::Auth
+    }
+
+    async fn before(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<Verdict> {
+        let Some(client_id) = context.client_id.as_ref().filter(|_| is_published_message(control_packet)) else {
+            return Ok(Verdict::Continue);
+        };
+        let topic_name = control_packet.variable_header().topic_name();
+        if self.acl.can_publish(client_id, context.username.as_ref(), topic_name) {
+            return Ok(Verdict::Continue);
+        }
+        info!("Refused PUBLISH of client {:?} to topic {:?}: not authorized", client_id, topic_name);
+        self.publish_handler.refuse_publish(&context.socket, client_id, control_packet, ReasonCode::NotAuthorized, "not authorized by the ACL").await?;
+        Ok(Verdict::Stop)
+    }
+}
+
+//Refuses a PUBLISH over the payload size, rate or inflight limit of the client's quota profile
+#[derive(Debug)]
+pub struct PublishQuota {
+    quota_handler: Arc<QuotaHandler>,
+    publish_handler: Arc<PublishHandler>,
+}
+
+impl PublishQuota {
+    pub fn new(quota_handler: Arc<QuotaHandler>, publish_handler: Arc<PublishHandler>) -> Self {
+        Self { quota_handler, publish_handler }
+    }
+}
+
+#[async_trait]
+impl PacketMiddleware for PublishQuota {
+    fn name(&self) -> &str {
+        "publish-quota"
+    }
+
+    fn stage(&self) -> Stage {
+        Stage::RateLimit
+    }
+
+    async fn before(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<Verdict> {
+        let Some(client_id) = context.client_id.as_ref().filter(|_| is_published_message(control_packet)) else {
+            return Ok(Verdict::Continue);
+        };
+        let Err(reason_code) = self.quota_handler.check_publish(client_id, control_packet) else {
+            return Ok(Verdict::Continue);
+        };
+        info!("Refused PUBLISH of client {:?} to topic {:?}: {:?}", client_id, control_packet.variable_header().topic_name(), reason_code);
+        self.publish_handler.refuse_publish(&context.socket, client_id, control_packet, reason_code, "refused by the quota profile").await?;
+        Ok(Verdict::Stop)
+    }
+}
+
+//A PUBLISH other than a $CONTROL command
+fn is_published_message(control_packet: &ControlPacket) -> bool {
+    control_packet.fixed_header().packet_type() == ControlPacketType::PUBLISH
+        && !ControlCommands::is_command(control_packet.variable_header().topic_name())
+}
@@ -2,7 +2,20 @@
 
 pub mod broker;
 pub mod packet_dispatcher;
-mod utils;
+pub(crate) mod utils;
+pub(crate) mod compression;
+pub(crate) mod control_commands;
+pub(crate) mod message_expiry;
+pub(crate) mod quarantine;
+pub(crate) mod broker_info;
+pub(crate) mod delivery_report;
+pub(crate) mod retained_delivery;
+pub(crate) mod diagnostics;
+pub(crate) mod supervisor;
+pub(crate) mod shared_rebalance;
+pub(crate) mod connection_close;
+pub(crate) mod middleware;
+pub(crate) mod invariants;
 
 pub(crate) mod handler;
 
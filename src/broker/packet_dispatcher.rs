@@ -1,34 +1,79 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use log::debug;
+use log::{debug, error};
 use metered::{*};
 use tokio::sync::mpsc::Sender;
 
 use crate::{ClientHandler, TopicHandler};
+use crate::auth::acl::Acl;
+use crate::auth::authenticator::Authenticator;
+use crate::broker::broker_info::BrokerInfo;
+use crate::broker::connection_close::ConnectionClose;
 use crate::broker::handler::connect_handler::ConnectHandler;
 use crate::broker::handler::disconnect_handler::DisconnectHandler;
 use crate::broker::handler::pingreq_handler::PingreqHandler;
 use crate::broker::handler::publish_handler::PublishHandler;
+use crate::broker::handler::puback_handler::PubackHandler;
+use crate::broker::handler::pubcomp_handler::PubcompHandler;
 use crate::broker::handler::pubrec_handler::PubrecHandler;
 use crate::broker::handler::pubrel_handler::PubrelHandler;
+use crate::broker::handler::shared_handles::SharedHandles;
 use crate::broker::handler::subscribe_handler::SubscribeHandler;
 use crate::broker::handler::unsubscribe_handler::UnsubscribeHandler;
+use crate::broker::invariants::Invariants;
+use crate::broker::middleware::{DebugCapture, LateAcknowledgements, LatePacketMetrics, MiddlewareChain, PublishAcl, PublishQuota, Verdict};
+use crate::broker::quarantine::{Quarantine, SYS_DEAD_LETTER_TOPIC};
+use crate::broker::retained_delivery::RetainedDelivery;
+use crate::broker::shared_rebalance::SharedRebalance;
+use crate::broker::utils::{drop_qos2_message, publish_sys_message};
+use crate::connection::client_context::ClientContext;
+use crate::error::{PatinaError, PatinaResult};
 use crate::model::control_packet::ControlPacket;
 use crate::model::fixed_header::ControlPacketType;
+use crate::config::broker_config::BrokerConfig;
+use crate::limits::congestion_control::CongestionControl;
+use crate::limits::quota_handler::QuotaHandler;
+use crate::metrics::hot_topics::HotTopics;
+use crate::metrics::payload_sizes::PayloadSizes;
+use crate::session::qos2_tracker::{Direction, Qos2Tracker};
+use crate::session::takeover_tracker::TakeoverTracker;
+use crate::topic::tree_telemetry::TreeTelemetry;
+use crate::topic::virtual_topics::{MessageRate, SysTime, VirtualTopicProvider};
 
 #[derive(Debug)]
 pub struct PacketDispatcher {
     pub(crate) metrics: PacketDispatcherMetrics,
-    to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>,
+    pub(crate) late_metrics: Arc<LatePacketMetrics>,
+    //Run around the handler of every packet
+    pub(crate) middlewares: MiddlewareChain,
+    config: Arc<BrokerConfig>,
+    pub(crate) to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>,
     pub(crate) client_handler: Arc<ClientHandler>,
     pub(crate) topic_handler: Arc<TopicHandler>,
+    pub(crate) quota_handler: Arc<QuotaHandler>,
+    pub(crate) authenticator: Arc<Authenticator>,
+    pub(crate) acl: Arc<Acl>,
+    pub(crate) takeover_tracker: Arc<TakeoverTracker>,
+    pub(crate) quarantine: Arc<Quarantine>,
+    pub(crate) broker_info: Arc<BrokerInfo>,
+    pub(crate) retained_delivery: Arc<RetainedDelivery>,
+    pub(crate) shared_rebalance: Arc<SharedRebalance>,
+    pub(crate) connection_close: Arc<ConnectionClose>,
+    pub(crate) qos2_tracker: Arc<Qos2Tracker>,
+    pub(crate) hot_topics: Arc<HotTopics>,
+    pub(crate) payload_sizes: Arc<PayloadSizes>,
+    pub(crate) tree_telemetry: Arc<TreeTelemetry>,
+    pub(crate) invariants: Arc<Invariants>,
     pub(crate) connect_handler: Arc<ConnectHandler>,
     pub(crate) disconnect_handler: Arc<DisconnectHandler>,
     pub(crate) pingreq_handler: Arc<PingreqHandler>,
     pub(crate) publish_handler: Arc<PublishHandler>,
+    pub(crate) puback_handler: Arc<PubackHandler>,
     pub(crate) pubrec_handler: Arc<PubrecHandler>,
     pub(crate) pubrel_handler: Arc<PubrelHandler>,
+    pub(crate) pubcomp_handler: Arc<PubcompHandler>,
     pub(crate) subscribe_handler: Arc<SubscribeHandler>,
     pub(crate) unsubscribe_handler: Arc<UnsubscribeHandler>,
 }
@@ -37,66 +82,228 @@ pub struct PacketDispatcher {
 impl PacketDispatcher {
     #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
     pub(crate) async fn process_message(&self,
-                                        socket: SocketAddr,
+                                        context: ClientContext,
                                         control_packet: ControlPacket,
-    ) -> Result<(), String> {
+    ) -> PatinaResult<()> {
+        //The only lookup of the client of the socket, handlers take it from the context
+        let context = match control_packet.fixed_header().packet_type() {
+            ControlPacketType::CONNECT => { context }
+            _ => {
+                let client_id = self.client_handler.get_client_id(&context.socket).ok();
+                context.with_client_id(client_id)
+            }
+        };
         debug!("Going to handle control packet: {:?} from client {:?} on socket {:?}",
-        control_packet.fixed_header().packet_type(),match self.client_handler.get_client_id(&socket)
-            {Err(_) => {String::from("<CLIENT_ID NOT REGISTERED>")}, Ok(client_id) => {client_id.clone()}},
-            socket);
+            control_packet.fixed_header().packet_type(), context.client_id.as_deref().unwrap_or("<CLIENT_ID NOT REGISTERED>"), context.socket);
+        let middlewares = self.middlewares.middlewares();
+        for middleware in &middlewares {
+            if middleware.before(&context, &control_packet).await? == Verdict::Stop {
+                debug!("Middleware {} stopped {:?} from {}", middleware.name(), control_packet.fixed_header().packet_type(), context.socket);
+                return Ok(());
+            }
+        }
+        let result = self.handle(&context, &control_packet).await;
+        for middleware in middlewares.iter().rev() {
+            middleware.after(&context, &control_packet, &result).await;
+        }
+        result
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        let quota_handler = Arc::new(QuotaHandler::new(config.clone()));
+        let takeover_tracker = Arc::new(TakeoverTracker::default());
+        let authenticator = Arc::new(Authenticator::new(config.auth.clone()));
+        let acl = Arc::new(Acl::new(config.acl.clone()));
+        let qos2_tracker = Arc::new(Qos2Tracker::new(config.qos2.clone()));
+        let hot_topics = Arc::new(HotTopics::new(config.hot_topics.clone()));
+        let payload_sizes = Arc::new(PayloadSizes::new(config.payload_sizes.clone()));
+        let congestion_control = Arc::new(CongestionControl::new(config.congestion.clone()));
+        let retained_delivery = Arc::new(RetainedDelivery::new(config.retained_delivery.clone(), client_handler.clone(), topic_handler.clone(), to_listener.clone()));
+        let shared_rebalance = Arc::new(SharedRebalance::new(client_handler.clone(), topic_handler.clone(), to_listener.clone()));
+        let invariants = Arc::new(Invariants::default());
+        let connection_close = Arc::new(ConnectionClose::new(client_handler.clone(), topic_handler.clone(), quota_handler.clone(), shared_rebalance.clone(), invariants.clone()));
+        let message_rate = Arc::new(MessageRate::new(Instant::now()));
+        if config.virtual_topics.enabled {
+            let providers: [Arc<dyn VirtualTopicProvider>; 2] = [Arc::new(SysTime), message_rate.clone()];
+            for provider in providers {
+                let refresh_interval = config.virtual_topics.refresh_interval(provider.topic_name(), provider.default_refresh_interval());
+                topic_handler.virtual_topics.register(provider, refresh_interval);
+            }
+        }
+        let shared = SharedHandles { config: config.clone(), client_handler: client_handler.clone(), topic_handler: topic_handler.clone(), quota_handler: quota_handler.clone(), to_listener: to_listener.clone() };
+        let publish_handler = Arc::new(PublishHandler::new(shared.clone(), qos2_tracker.clone(), hot_topics.clone(), payload_sizes.clone(), message_rate, congestion_control.clone(), invariants.clone()));
+        let late_acknowledgements = LateAcknowledgements::default();
+        let middlewares = MiddlewareChain::default();
+        middlewares.register(Arc::new(DebugCapture::new(client_handler.clone())));
+        middlewares.register(Arc::new(PublishAcl::new(acl.clone(), publish_handler.clone())));
+        middlewares.register(Arc::new(PublishQuota::new(quota_handler.clone(), publish_handler.clone())));
+        let late_metrics = late_acknowledgements.metrics.clone();
+        middlewares.register(Arc::new(late_acknowledgements));
+        Self {
+            metrics: PacketDispatcherMetrics::default(),
+            late_metrics,
+            middlewares,
+            to_listener: to_listener.clone(),
+            client_handler: client_handler.clone(),
+            topic_handler: topic_handler.clone(),
+            quota_handler: quota_handler.clone(),
+            authenticator: authenticator.clone(),
+            acl: acl.clone(),
+            takeover_tracker: takeover_tracker.clone(),
+            quarantine: Arc::new(Quarantine::new(config.dispatch.clone())),
+            broker_info: Arc::new(BrokerInfo::new()),
+            retained_delivery: retained_delivery.clone(),
+            shared_rebalance: shared_rebalance.clone(),
+            connection_close: connection_close.clone(),
+            qos2_tracker: qos2_tracker.clone(),
+            hot_topics: hot_topics.clone(),
+            payload_sizes: payload_sizes.clone(),
+            tree_telemetry: Arc::new(TreeTelemetry::new(config.tree_telemetry.clone())),
+            invariants: invariants.clone(),
+            connect_handler: Arc::new(ConnectHandler::new(shared, authenticator, acl.clone(), takeover_tracker, retained_delivery.clone(), invariants.clone(), congestion_control.clone())),
+            disconnect_handler: Arc::new(DisconnectHandler::new(connection_close, client_handler.sessions.clone(), to_listener.clone())),
+            pingreq_handler: Arc::new(PingreqHandler::new(config.clone(), client_handler.clone(), topic_handler.clone(), to_listener.clone())),
+            publish_handler,
+            puback_handler: Arc::new(PubackHandler::new(client_handler.clone(), to_listener.clone())),
+            pubrec_handler: Arc::new(PubrecHandler::new(client_handler.clone(), qos2_tracker.clone(), to_listener.clone())),
+            pubrel_handler: Arc::new(PubrelHandler::new(quota_handler.clone(), qos2_tracker.clone(), invariants.clone(), to_listener.clone())),
+            pubcomp_handler: Arc::new(PubcompHandler::new(client_handler.clone(), qos2_tracker.clone(), to_listener.clone())),
+            subscribe_handler: Arc::new(SubscribeHandler::new(config.clone(), topic_handler.clone(), quota_handler.clone(), acl, retained_delivery.clone(), client_handler.sessions.clone(), to_listener.clone())),
+            unsubscribe_handler: Arc::new(UnsubscribeHandler::new(config.clone(), client_handler.clone(), topic_handler.clone(), qos2_tracker.clone(), to_listener.clone())),
+            config,
+        }
+    }
+}
 
-        let result = match control_packet.fixed_header().packet_type() {
+impl PacketDispatcher {
+    //The handler of the packet type, between the middlewares
+    async fn handle(&self, context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<()> {
+        match control_packet.fixed_header().packet_type() {
             ControlPacketType::RESERVED => {}
             ControlPacketType::CONNECT => {
-                self.connect_handler.process(&socket, &control_packet).await?;
+                self.connect_handler.process(context, control_packet).await?;
             }
             ControlPacketType::CONNACK => {}
             ControlPacketType::PUBLISH => {
-                self.publish_handler.process(&socket, &control_packet).await?;
+                self.publish_handler.process(context, control_packet).await?;
+            }
+            ControlPacketType::PUBACK => {
+                self.puback_handler.process(context, control_packet).await?;
             }
-            ControlPacketType::PUBACK => {}
             ControlPacketType::PUBREC => {
-                self.pubrec_handler.process(&socket, &control_packet).await?;
+                self.pubrec_handler.process(context, control_packet).await?;
             }
             ControlPacketType::PUBREL => {
-                self.pubrel_handler.process(&socket, &control_packet).await?;
+                self.pubrel_handler.process(context, control_packet).await?;
+            }
+            ControlPacketType::PUBCOMP => {
+                self.pubcomp_handler.process(context, control_packet).await?;
             }
-            ControlPacketType::PUBCOMP => {}
             ControlPacketType::SUBSCRIBE => {
-                self.subscribe_handler.process(&socket, &control_packet).await?;
+                self.subscribe_handler.process(context, control_packet).await?;
             }
             ControlPacketType::SUBACK => {}
             ControlPacketType::UNSUBSCRIBE => {
-                self.unsubscribe_handler.process(&socket, &control_packet).await?;
+                self.unsubscribe_handler.process(context, control_packet).await?;
             }
             ControlPacketType::UNSUBACK => {}
             ControlPacketType::PINGREQ => {
-                self.pingreq_handler.process(&socket, &control_packet).await?;
+                self.pingreq_handler.process(context, control_packet).await?;
             }
             ControlPacketType::PINGRESP => {}
             ControlPacketType::DISCONNECT => {
-                self.disconnect_handler.process(&socket, &control_packet).await?;
+                self.disconnect_handler.process(context, control_packet).await?;
             }
             ControlPacketType::AUTH => {}
         };
         Ok(())
     }
-    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
-        Self {
-            metrics: PacketDispatcherMetrics::default(),
-            to_listener: to_listener.clone(),
-            client_handler: client_handler.clone(),
-            topic_handler: topic_handler.clone(),
-            connect_handler: Arc::new(ConnectHandler::new(client_handler.clone(), topic_handler.clone(), to_listener.clone())),
-            disconnect_handler: Arc::new(DisconnectHandler::new(client_handler.clone(), topic_handler.clone(), to_listener.clone())),
-            pingreq_handler: Arc::new(PingreqHandler::new(client_handler.clone(), topic_handler.clone(), to_listener.clone())),
-            publish_handler: Arc::new(PublishHandler::new(client_handler.clone(), topic_handler.clone(), to_listener.clone())),
-            pubrec_handler: Arc::new(PubrecHandler::new(client_handler.clone(), topic_handler.clone(), to_listener.clone())),
-            pubrel_handler: Arc::new(PubrelHandler::new(client_handler.clone(), topic_handler.clone(), to_listener.clone())),
-            subscribe_handler: Arc::new(SubscribeHandler::new(client_handler.clone(), topic_handler.clone(), to_listener.clone())),
-            unsubscribe_handler: Arc::new(UnsubscribeHandler::new(client_handler.clone(), topic_handler.clone(), to_listener.clone())),
+
+    //Handles the packet in its own task so that a panicking handler counts as a failed attempt.
+    //A packet still failing after the last attempt, or after the first one for packets that can't be handled
+    //twice, is quarantined and the dispatcher moves on.
+    pub(crate) async fn dispatch(self: Arc<Self>, context: ClientContext, control_packet: ControlPacket) {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let packet_dispatcher = self.clone();
+            let attempt_context = context.clone();
+            let attempt_packet = control_packet.clone();
+            let error = match tokio::spawn(async move { packet_dispatcher.process_message(attempt_context, attempt_packet).await }).await {
+                Ok(Ok(_)) => { return; }
+                Ok(Err(err)) => { err }
+                Err(join_error) => { PatinaError::HandlerPanicked(join_error.to_string()) }
+            };
+            match self.quarantine.retry(attempts, &control_packet, &error) {
+                Some(backoff) => { tokio::time::sleep(backoff).await; }
+                None => {
+                    let dead_letter = self.quarantine.quarantine(context.socket, self.client_handler.get_client_id(&context.socket).ok(), &control_packet, attempts, &error);
+                    match serde_json::to_vec(&dead_letter) {
+                        Ok(payload) => {
+                            publish_sys_message(SYS_DEAD_LETTER_TOPIC, payload, &self.client_handler, &self.topic_handler, &self.to_listener).await;
+                        }
+                        Err(err) => { error!("Can't serialize dead letter {:?}: {}", dead_letter, err); }
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    //Publishes the broker information every sys.interval_secs, for as long as the broker runs
+    pub(crate) async fn publish_broker_info(self: Arc<Self>) {
+        if self.config.sys.interval_secs == 0 {
+            return;
+        }
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.sys.interval_secs));
+        loop {
+            interval.tick().await;
+            self.broker_info.publish(&self.client_handler, &self.topic_handler, &self.to_listener).await;
         }
     }
-}
 
+    //Publishes the next value of every virtual topic whose value ran out to its subscribers.
+    //Nothing is computed for a topic nobody is subscribed to.
+    pub(crate) async fn publish_virtual_topics(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+            let virtual_topics = &self.topic_handler.virtual_topics;
+            for topic_name in virtual_topics.stale(now) {
+                if !self.topic_handler.subscription_tree().has_subscribers(&topic_name) {
+                    continue;
+                }
+                if let Some(value) = virtual_topics.value(&topic_name, now) {
+                    publish_sys_message(&topic_name, value, &self.client_handler, &self.topic_handler, &self.to_listener).await;
+                    virtual_topics.metrics.delivered.incr();
+                }
+            }
+        }
+    }
 
+    //Drops the state of QoS 2 handshakes abandoned by their clients: the inflight slot of an inbound one,
+    //the stored message of an outbound one
+    pub(crate) async fn expire_qos2_handshakes(self: Arc<Self>) {
+        if !self.qos2_tracker.is_enabled() {
+            return;
+        }
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.qos2.sweep_interval_secs.max(1)));
+        loop {
+            interval.tick().await;
+            for (direction, client_id, packet_identifier) in self.qos2_tracker.expire(tokio::time::Instant::now()) {
+                match direction {
+                    Direction::Inbound => {
+                        self.quota_handler.release_inflight(&client_id, packet_identifier);
+                        self.invariants.released_qos2(&client_id, packet_identifier);
+                    }
+                    Direction::Outbound => {
+                        if drop_qos2_message(&*self.client_handler.sessions, &client_id, packet_identifier) {
+                            debug!("Dropped stored QoS 2 message {} of client {:?}", packet_identifier, client_id);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use log::{error, warn};
+use metered::HitCount;
+use serde::Serialize;
+
+use crate::config::broker_config::DispatchConfig;
+use crate::error::PatinaError;
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::ControlPacketType;
+
+//Topic the broker publishes a JSON dead letter to for every quarantined packet
+pub const SYS_DEAD_LETTER_TOPIC: &str = "$SYS/broker/dead-letter";
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct QuarantineMetrics {
+    pub(crate) dispatch_retried: HitCount,
+    pub(crate) poison_messages: HitCount,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct DeadLetter {
+    pub address: SocketAddr,
+    pub client_id: Option<String>,
+    pub packet_type: String,
+    pub attempts: u32,
+    //Error of the last attempt
+    pub error: String,
+    //Debug dump of the whole packet
+    pub packet: String,
+}
+
+//Retry accounting for packets whose handler fails or panics. A packet that keeps failing is
+//set aside as a poison message so that the rest of the traffic goes on.
+#[derive(Debug)]
+pub struct Quarantine {
+    config: DispatchConfig,
+    pub(crate) metrics: QuarantineMetrics,
+}
+
+impl Quarantine {
+    //Whether the packet gets another attempt, after waiting for the returned backoff
+    pub fn retry(&self, attempts: u32, control_packet: &ControlPacket, error: &PatinaError) -> Option<Duration> {
+        if attempts >= self.config.max_attempts || !Self::is_idempotent(control_packet.fixed_header().packet_type()) {
+            return None;
+        }
+        warn!("Attempt {}/{} to handle {:?} failed: {}. Retrying", attempts, self.config.max_attempts, control_packet.fixed_header().packet_type(), error);
+        self.metrics.dispatch_retried.incr();
+        Some(Duration::from_millis(self.config.retry_backoff_ms) * attempts)
+    }
+
+    //Handling them again does nothing a retransmission of the client wouldn't. Another attempt at a PUBLISH, CONNECT,
+    //SUBSCRIBE, UNSUBSCRIBE or DISCONNECT could repeat what the failed one did before failing, e.g. deliver a
+    //message to the subscribers it already reached.
+    fn is_idempotent(packet_type: ControlPacketType) -> bool {
+        matches!(packet_type, ControlPacketType::PUBACK | ControlPacketType::PUBREC | ControlPacketType::PUBREL | ControlPacketType::PUBCOMP | ControlPacketType::PINGREQ)
+    }
+
+    pub fn quarantine(&self, socket: SocketAddr, client_id: Option<String>, control_packet: &ControlPacket, attempts: u32, error: &PatinaError) -> DeadLetter {
+        error!("Quarantined packet from {:?} (client {:?}) after {} attempts: {}. Packet: {:?}", socket, client_id, attempts, error, control_packet);
+        self.metrics.poison_messages.incr();
+        DeadLetter {
+            address: socket,
+            client_id,
+            packet_type: format!("{:?}", control_packet.fixed_header().packet_type()),
+            attempts,
+            error: error.to_string(),
+            packet: format!("{:?}", control_packet),
+        }
+    }
+
+    pub fn new(config: DispatchConfig) -> Self {
+        Self { config, metrics: QuarantineMetrics::default() }
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use log::{debug, trace};
+use metered::HitCount;
+use serde::Serialize;
+use tokio::sync::mpsc::Sender;
+
+use crate::{ClientHandler, TopicHandler};
+use crate::broker::utils::send_packet;
+use crate::config::broker_config::RetainedDeliveryConfig;
+use crate::model::control_packet::ControlPacket;
+use crate::model::qos_level::QoSLevel;
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct RetainedDeliveryMetrics {
+    pub(crate) sent: HitCount,
+    pub(crate) interrupted: HitCount,
+    pub(crate) resumed: HitCount,
+    //Interrupted backlogs whose client didn't come back within the resume window
+    pub(crate) abandoned: HitCount,
+}
+
+//Retained messages still to be sent to one subscription
+#[derive(Debug)]
+struct Backlog {
+    //A new SUBSCRIBE to the same filter replaces the backlog, the task of the old one stops
+    generation: u64,
+    topic_names: VecDeque<String>,
+    maximum_qos: QoSLevel,
+    //None while a task sends the backlog
+    interrupted_at: Option<Instant>,
+}
+
+//Sends the retained messages matching a new subscription one by one at the configured pace.
+//A backlog interrupted by a disconnection continues where it stopped when the client resumes its session in time.
+#[derive(Debug)]
+pub struct RetainedDelivery {
+    config: RetainedDeliveryConfig,
+    client_handler: Arc<ClientHandler>,
+    topic_handler: Arc<TopicHandler>,
+    to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>,
+    //Backlogs by client_id and topic filter
+    backlogs: DashMap<(String, String), Backlog>,
+    generations: AtomicU64,
+    pub(crate) metrics: RetainedDeliveryMetrics,
+}
+
+impl RetainedDelivery {
+    pub fn start(self: &Arc<Self>, client_id: &String, topic_filter: &String, maximum_qos: QoSLevel) {
+        let topic_names: VecDeque<String> = self.topic_handler.retained_topics(topic_filter).into();
+        if topic_names.is_empty() {
+            return;
+        }
+        debug!("Sending {} retained messages matching {:?} to client {:?}", topic_names.len(), topic_filter, client_id);
+        let generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        let key = (client_id.clone(), topic_filter.clone());
+        self.backlogs.insert(key.clone(), Backlog { generation, topic_names, maximum_qos, interrupted_at: None });
+        tokio::spawn(self.clone().send_backlog(key, generation));
+    }
+
+    //Continues the backlogs of a client reconnecting with its session, the ones interrupted too long ago are dropped
+    pub fn resume(self: &Arc<Self>, client_id: &String) {
+        let resume_window = Duration::from_secs(self.config.resume_window_secs);
+        let mut resumed = vec![];
+        self.backlogs.retain(|(backlog_client_id, topic_filter), backlog| {
+            if backlog_client_id != client_id {
+                return true;
+            }
+            match backlog.interrupted_at {
+                None => { true }
+                Some(interrupted_at) if interrupted_at.elapsed() <= resume_window => {
+                    backlog.interrupted_at = None;
+                    resumed.push(((backlog_client_id.clone(), topic_filter.clone()), backlog.generation));
+                    true
+                }
+                Some(_) => {
+                    self.metrics.abandoned.incr();
+                    false
+                }
+            }
+        });
+        for (key, generation) in resumed {
+            debug!("Resuming retained messages matching {:?} for client {:?}", key.1, key.0);
+            self.metrics.resumed.incr();
+            tokio::spawn(self.clone().send_backlog(key, generation));
+        }
+    }
+
+    //A clean start discards what the previous session didn't get
+    pub fn forget(&self, client_id: &String) {
+        self.backlogs.retain(|(backlog_client_id, _), _| backlog_client_id != client_id);
+    }
+
+    async fn send_backlog(self: Arc<Self>, key: (String, String), generation: u64) {
+        loop {
+            let socket = match self.client_handler.get_socket(&key.0) {
+                Ok(socket) => { socket }
+                Err(_) => {
+                    self.interrupt(&key, generation);
+                    return;
+                }
+            };
+            let (topic_name, maximum_qos) = match self.backlogs.get_mut(&key) {
+                Some(mut backlog) if backlog.generation == generation => {
+                    match backlog.topic_names.pop_front() {
+                        Some(topic_name) => { (topic_name, backlog.maximum_qos) }
+                        None => {
+                            drop(backlog);
+                            self.backlogs.remove_if(&key, |_, backlog| backlog.generation == generation);
+                            return;
+                        }
+                    }
+                }
+                _ => { return; }
+            };
+            //Cleared or expired since the subscription
+            let retained_packet = match self.topic_handler.retained_message(&topic_name) {
+                Some(retained_packet) => { retained_packet }
+                None => { continue; }
+            };
+            let payload_size = retained_packet.payload_opt().map(|payload| payload.data().len()).unwrap_or(0);
+            let qos_level = (*retained_packet.fixed_header().qos_level()).min(maximum_qos);
+            let retained_packet = match qos_level.ne(retained_packet.fixed_header().qos_level()) {
+                true => { retained_packet.with_qos_level(qos_level) }
+                false => { retained_packet }
+            };
+            trace!("Sending retained message on topic {:?} to client {:?}", topic_name, key.0);
+            send_packet(socket, &retained_packet, &self.to_listener).await;
+            self.metrics.sent.incr();
+            let pause = self.pause(payload_size);
+            if !pause.is_zero() {
+                tokio::time::sleep(pause).await;
+            }
+        }
+    }
+
+    fn interrupt(&self, key: &(String, String), generation: u64) {
+        if let Some(mut backlog) = self.backlogs.get_mut(key) {
+            if backlog.generation == generation {
+                debug!("Client {:?} disconnected with {} retained messages matching {:?} left", key.0, backlog.topic_names.len(), key.1);
+                backlog.interrupted_at = Some(Instant::now());
+                self.metrics.interrupted.incr();
+            }
+        }
+    }
+
+    //Time the message takes at the configured pace, the slower of the two limits wins
+    pub fn pause(&self, payload_size: usize) -> Duration {
+        let by_messages = match self.config.messages_per_sec {
+            0 => { Duration::ZERO }
+            messages_per_sec => { Duration::from_secs(1) / messages_per_sec }
+        };
+        let by_bytes = match self.config.bytes_per_sec {
+            0 => { Duration::ZERO }
+            bytes_per_sec => { Duration::from_secs_f64(payload_size as f64 / bytes_per_sec as f64) }
+        };
+        by_messages.max(by_bytes)
+    }
+
+    pub fn new(config: RetainedDeliveryConfig, client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        Self { config, client_handler, topic_handler, to_listener, backlogs: DashMap::new(), generations: AtomicU64::new(0), metrics: RetainedDeliveryMetrics::default() }
+    }
+}
@@ -0,0 +1,80 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use log::{debug, info, trace};
+use metered::HitCount;
+use serde::Serialize;
+use tokio::sync::mpsc::Sender;
+
+use crate::{ClientHandler, TopicHandler};
+use crate::broker::utils::{drop_message, persist_packet, send_packet};
+use crate::model::control_packet::ControlPacket;
+use crate::topic::topic_matcher::{shared_filter, topic_matches};
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct SharedSubscriptionMetrics {
+    //Unacknowledged messages of a member that left, handed to another member of its group
+    pub(crate) rebalanced: HitCount,
+    //Left with the member because no other member of its group was connected
+    pub(crate) stranded: HitCount,
+}
+
+//A member of a shared subscription that disconnects gives the messages it hadn't acknowledged to the
+//members still connected, instead of keeping them until it comes back or its session ends. Its membership
+//stays with its session, while it is offline the group delivers to the other members.
+#[derive(Debug)]
+pub struct SharedRebalance {
+    client_handler: Arc<ClientHandler>,
+    topic_handler: Arc<TopicHandler>,
+    to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>,
+    pub(crate) metrics: SharedSubscriptionMetrics,
+}
+
+impl SharedRebalance {
+    //Called once the client is no longer connected, before its session and subscriptions are dropped.
+    //A message that also matched a subscription of the client's own stays with it.
+    pub async fn rebalance(&self, client_id: &String, unacknowledged: Vec<ControlPacket>) {
+        trace!("SharedRebalance::rebalance");
+        if unacknowledged.is_empty() {
+            return;
+        }
+        let topic_filters: Vec<String> = self.topic_handler.subscriptions_of(client_id).into_iter()
+            .map(|record| record.topic_filter)
+            .collect();
+        for control_packet in unacknowledged {
+            let topic_name = control_packet.variable_header().topic_name();
+            let matching: Vec<&String> = topic_filters.iter()
+                .filter(|topic_filter| topic_matches(shared_filter(topic_filter).map_or(topic_filter.as_str(), |(_, filter)| filter), topic_name))
+                .collect();
+            if matching.is_empty() || matching.iter().any(|topic_filter| shared_filter(topic_filter).is_none()) {
+                continue;
+            }
+            let shared_topic_filter = matching[0];
+            let packet_identifier = match control_packet.variable_header().packet_identifier_opt() {
+                Some(result) => { result }
+                None => { continue; }
+            };
+            let (member, connection) = match self.topic_handler.next_shared_member(shared_topic_filter, client_id, &self.client_handler) {
+                Some(result) => { result }
+                None => {
+                    debug!("No other member of {:?} connected, Packet Identifier {} stays with client {:?}", shared_topic_filter, packet_identifier, client_id);
+                    self.metrics.stranded.incr();
+                    continue;
+                }
+            };
+            info!("Handing Packet Identifier {} on {:?} of client {:?} to {:?}", packet_identifier, topic_name, client_id, member);
+            drop_message(&*self.client_handler.sessions, client_id, packet_identifier);
+            persist_packet(&*self.client_handler.sessions, &member, &[Arc::new(shared_topic_filter.clone())], &control_packet, Instant::now());
+            if self.client_handler.limits.admit(&connection.socket, &control_packet) {
+                send_packet(connection.socket, &control_packet, &self.to_listener).await;
+            }
+            self.metrics.rebalanced.incr();
+        }
+    }
+
+    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        Self { client_handler, topic_handler, to_listener, metrics: SharedSubscriptionMetrics::default() }
+    }
+}
@@ -0,0 +1,156 @@
+use std::any::Any;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{error, info, warn};
+use tokio::sync::watch;
+use tokio::task::{JoinError, JoinHandle};
+use tokio::time::Instant;
+
+use crate::config::broker_config::SupervisorConfig;
+
+pub type SubsystemResult = Result<(), String>;
+type SubsystemFuture = Pin<Box<dyn Future<Output=SubsystemResult> + Send>>;
+type StartSubsystem = Arc<dyn Fn() -> SubsystemFuture + Send + Sync>;
+
+//How one run of a subsystem ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Exit {
+    Finished,
+    Failed(String),
+    Panicked(String),
+}
+
+impl Exit {
+    fn from_join(result: Result<SubsystemResult, JoinError>) -> Self {
+        match result {
+            Ok(Ok(())) => { Exit::Finished }
+            Ok(Err(err)) => { Exit::Failed(err) }
+            Err(err) if err.is_panic() => { Exit::Panicked(panic_message(err.into_panic())) }
+            Err(err) => { Exit::Failed(err.to_string()) }
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        return message.to_string();
+    }
+    if let Some(message) = payload.downcast_ref::<String>() {
+        return message.clone();
+    }
+    String::from("unknown panic")
+}
+
+struct Subsystem {
+    name: &'static str,
+    depends_on: Vec<&'static str>,
+    start: StartSubsystem,
+}
+
+//Runs every long-lived part of the broker as a tokio task. A subsystem that returns an error or panics is
+//started again after a backoff, one that returns Ok is done. Subsystems start after the ones they depend on
+//and stop before them, so the listeners stop taking packets before the broker loop and the writers go away.
+pub struct Supervisor {
+    config: SupervisorConfig,
+    subsystems: Vec<Subsystem>,
+}
+
+impl Supervisor {
+    pub fn add<F, Fut>(&mut self, name: &'static str, depends_on: &[&'static str], start: F)
+        where F: Fn() -> Fut + Send + Sync + 'static,
+              Fut: Future<Output=SubsystemResult> + Send + 'static {
+        let start: StartSubsystem = Arc::new(move || Box::pin(start()));
+        self.subsystems.push(Subsystem { name, depends_on: depends_on.to_vec(), start });
+    }
+
+    //Names in the order they start, every subsystem after its dependencies, otherwise in the order they were added
+    pub fn start_order(&self) -> Result<Vec<&'static str>, String> {
+        for subsystem in &self.subsystems {
+            if let Some(missing) = subsystem.depends_on.iter().find(|name| !self.subsystems.iter().any(|other| other.name == **name)) {
+                return Err(format!("Subsystem {} depends on unknown subsystem {}", subsystem.name, missing));
+            }
+        }
+        let mut order: Vec<&'static str> = Vec::with_capacity(self.subsystems.len());
+        while order.len() < self.subsystems.len() {
+            let next = self.subsystems.iter()
+                .find(|subsystem| !order.contains(&subsystem.name) && subsystem.depends_on.iter().all(|name| order.contains(name)));
+            match next {
+                Some(subsystem) => { order.push(subsystem.name); }
+                None => {
+                    let remaining: Vec<&str> = self.subsystems.iter().map(|subsystem| subsystem.name).filter(|name| !order.contains(name)).collect();
+                    return Err(format!("Subsystems {:?} depend on each other", remaining));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    //Starts every subsystem and supervises them until shutdown completes, then stops them in reverse start order
+    pub async fn run(self, shutdown: impl Future<Output=()>) -> Result<(), String> {
+        let order = self.start_order()?;
+        let mut running: Vec<(&'static str, watch::Sender<bool>, JoinHandle<()>)> = Vec::with_capacity(order.len());
+        for name in order {
+            let subsystem = self.subsystems.iter().find(|subsystem| subsystem.name == name).unwrap();
+            let (stop_tx, stop_rx) = watch::channel(false);
+            let handle = tokio::spawn(Self::supervise(subsystem.name, subsystem.start.clone(), self.config.clone(), stop_rx));
+            running.push((name, stop_tx, handle));
+        }
+        shutdown.await;
+        info!("Shutting down");
+        for (name, stop_tx, handle) in running.into_iter().rev() {
+            let _ = stop_tx.send(true);
+            if let Err(err) = handle.await {
+                error!("Supervisor of {} failed. {}", name, err);
+            }
+        }
+        info!("Shut down");
+        Ok(())
+    }
+
+    async fn supervise(name: &'static str, start: StartSubsystem, config: SupervisorConfig, mut stop: watch::Receiver<bool>) {
+        let stable_after = Duration::from_secs(config.stable_after_secs);
+        let mut crashes = 0;
+        loop {
+            let started = Instant::now();
+            let mut task = tokio::spawn(start());
+            info!("Started {}", name);
+            let exit = tokio::select! {
+                result = &mut task => { Exit::from_join(result) }
+                _ = stop.changed() => {
+                    task.abort();
+                    let _ = task.await;
+                    info!("Stopped {}", name);
+                    return;
+                }
+            };
+            let reason = match exit {
+                Exit::Finished => {
+                    info!("{} finished", name);
+                    return;
+                }
+                Exit::Failed(err) => { format!("failed. {}", err) }
+                Exit::Panicked(message) => { format!("panicked. {}", message) }
+            };
+            if started.elapsed() >= stable_after {
+                crashes = 0;
+            }
+            crashes += 1;
+            let backoff = config.backoff(crashes);
+            warn!("{} {}. Restarting in {:?}", name, reason, backoff);
+            tokio::select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = stop.changed() => {
+                    info!("Stopped {}", name);
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn new(config: SupervisorConfig) -> Self {
+        Self { config, subsystems: Vec::new() }
+    }
+}
@@ -1,51 +1,186 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 use chrono::Local;
-use dashmap::DashMap;
-use log::{error, trace};
+use log::{debug, error, info, trace};
 use rand::Rng;
 use tokio::sync::mpsc::Sender;
 
+use crate::{ClientHandler, TopicHandler};
+use crate::config::broker_config::OverlapPolicy;
 use crate::model::control_packet::ControlPacket;
-use crate::session::session_handler::{SessionHandler, SessionState};
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::qos_level::QoSLevel;
+use crate::session::session_directory::SessionDirectory;
+use crate::session::session_handler::{ConnectionMetadata, QueuedMessage, QueueSelector, SessionHandler, SessionSnapshot, SessionSummary};
 
-lazy_static! {
+//Unique across sessions, so a timer can't mistake a later session of the same client for its own
+static EXPIRY_GENERATION: AtomicU64 = AtomicU64::new(0);
 
-    static  ref id2session: DashMap<String, SessionHandler> = {
-        let map = DashMap::new();
-        map
-    };
+//Queues the publish in the session of the client, tagged with the subscriptions it matched
+pub fn persist_packet(sessions: &dyn SessionDirectory, client_id: &String, topic_filters: &[Arc<String>], publish_packet: &ControlPacket, received_at: Instant) {
+    trace!("Broker::persist_packet");
+    if let Some(session) = sessions.session_of(client_id) {
+        session.register_publish(client_id.clone(), publish_packet, received_at, topic_filters);
+    }
+}
+
+//Expired messages don't count
+pub fn queued_packets(sessions: &dyn SessionDirectory, client_id: &String) -> usize {
+    trace!("Broker::queued_packets");
+    return sessions.session_of(client_id).map(|session| {
+        session.drop_expired();
+        session.queued_len()
+    }).unwrap_or(0);
+}
+
+pub fn register_clean_session(sessions: &dyn SessionDirectory, client_id: &String) {
+    trace!("Broker::register_clean_session");
+    sessions.replace(client_id, SessionHandler::new());
+}
+
+pub fn get_session_expiry_interval(sessions: &dyn SessionDirectory, client_id: &String) -> Option<u32> {
+    trace!("Broker::get_session_expiry_interval");
+    return sessions.session_of(client_id).map(|session| session.session_expiry_interval());
+}
+
+//A session left behind by a disconnection that keeps it for a while, a clean one is gone with the connection
+pub fn has_persistent_session(sessions: &dyn SessionDirectory, client_id: &String) -> bool {
+    trace!("Broker::has_persistent_session");
+    return sessions.session_of(client_id).is_some_and(|session| session.session_expiry_interval() > 0);
+}
+
+pub fn set_session_expiry_interval(sessions: &dyn SessionDirectory, client_id: &String, session_expiry_interval: u32) {
+    trace!("Broker::set_session_expiry_interval");
+    if let Some(session) = sessions.session_of(client_id) {
+        session.set_session_expiry_interval(session_expiry_interval);
+    }
+}
+
+pub fn set_request_problem_information(sessions: &dyn SessionDirectory, client_id: &String, request_problem_information: bool) {
+    trace!("Broker::set_request_problem_information");
+    if let Some(session) = sessions.session_of(client_id) {
+        session.set_request_problem_information(request_problem_information);
+    }
+}
+
+pub fn set_connection_metadata(sessions: &dyn SessionDirectory, client_id: &String, connection: ConnectionMetadata) {
+    trace!("Broker::set_connection_metadata");
+    if let Some(session) = sessions.session_of(client_id) {
+        session.set_connection(connection);
+    }
+}
+
+//Returns whether the session still held the message
+pub fn drop_qos2_message(sessions: &dyn SessionDirectory, client_id: &String, packet_identifier: u16) -> bool {
+    trace!("Broker::drop_qos2_message");
+    return sessions.session_of(client_id).is_some_and(|session| session.drop_qos2_message(client_id.clone(), packet_identifier));
+}
+
+//Returns whether the session held the QoS 1 or QoS 2 message
+pub fn drop_message(sessions: &dyn SessionDirectory, client_id: &String, packet_identifier: u16) -> bool {
+    trace!("Broker::drop_message");
+    return sessions.session_of(client_id).is_some_and(|session| session.drop_message(client_id.clone(), packet_identifier));
 }
 
-pub fn persist_packets(client_ids: &Vec<String>, publish_packet: &ControlPacket) {
-    trace!("Broker::persist_packets");
-    for client_id in client_ids {
-        id2session.get_mut(client_id).unwrap()
-            .register_publish(client_id.clone(), publish_packet);
+pub fn set_disconnected(sessions: &dyn SessionDirectory, client_id: &String) {
+    trace!("Broker::set_disconnected");
+    if let Some(session) = sessions.session_of(client_id) {
+        session.set_disconnected();
     }
 }
 
-pub fn register_session(client_id: &String) -> SessionState {
-    trace!("Broker::register_session");
-    if id2session.contains_key(client_id) {
-        return SessionState::SessionPresent;
+pub fn session_summary(sessions: &dyn SessionDirectory, client_id: &String) -> Option<SessionSummary> {
+    trace!("Broker::session_summary");
+    return sessions.session_of(client_id).map(|session| session.summary());
+}
+
+//None without a session
+pub fn queued_messages(sessions: &dyn SessionDirectory, client_id: &String) -> Option<Vec<QueuedMessage>> {
+    trace!("Broker::queued_messages");
+    return sessions.session_of(client_id).map(|session| session.queued_messages());
+}
+
+//How many queued messages were dropped, None without a session
+pub fn purge_queued(sessions: &dyn SessionDirectory, client_id: &String, selector: &QueueSelector) -> Option<usize> {
+    trace!("Broker::purge_queued");
+    return sessions.session_of(client_id).map(|session| session.purge_queued(selector));
+}
+
+//The packets dropped from the session, see SessionHandler::drop_unsubscribed
+pub fn drop_unsubscribed(sessions: &dyn SessionDirectory, client_id: &String, topic_filters: &[String], in_flight: impl Fn(&ControlPacket) -> bool) -> Vec<ControlPacket> {
+    trace!("Broker::drop_unsubscribed");
+    return sessions.session_of(client_id).map(|session| session.drop_unsubscribed(topic_filters, in_flight)).unwrap_or_default();
+}
+
+//Sessions kept once their connection closes, the ones the session store saves
+pub fn persistent_session_snapshots(sessions: &dyn SessionDirectory) -> Vec<(String, SessionSnapshot)> {
+    trace!("Broker::persistent_session_snapshots");
+    return sessions.sessions().into_iter()
+        .filter(|(_, session)| session.session_expiry_interval() > 0)
+        .map(|(client_id, session)| (client_id, session.snapshot()))
+        .collect();
+}
+
+//Problem information may only go on PUBLISH, CONNACK and DISCONNECT to a client that opted out of it
+pub fn with_problem_information(sessions: &dyn SessionDirectory, client_id: &String, control_packet: ControlPacket) -> ControlPacket {
+    trace!("Broker::with_problem_information");
+    let requested = sessions.session_of(client_id).map(|session| session.request_problem_information()).unwrap_or(true);
+    return match (requested, control_packet.fixed_header().packet_type()) {
+        (true, _) | (false, ControlPacketType::PUBLISH | ControlPacketType::CONNACK | ControlPacketType::DISCONNECT) => { control_packet }
+        (false, _) => { control_packet.without_problem_information() }
+    };
+}
+
+//Drops the session and the subscriptions of a disconnected client once its Session Expiry Interval elapses.
+//0 drops them right away, u32::MAX keeps them forever. Replaces the expiry scheduled before for the client.
+pub fn schedule_session_expiry(client_id: &String, client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>) {
+    trace!("Broker::schedule_session_expiry");
+    let sessions = client_handler.sessions.clone();
+    let session_expiry_interval = match get_session_expiry_interval(&*sessions, client_id) {
+        Some(result) => { result }
+        None => { return; }
+    };
+    match session_expiry_interval {
+        0 => {
+            debug!("Session for client {:?} expired", client_id);
+            sessions.cancel_expiry(client_id);
+            expire_session(&*sessions, client_id, &topic_handler);
+        }
+        u32::MAX => {
+            debug!("Session for client {:?} never expires", client_id);
+            sessions.cancel_expiry(client_id);
+        }
+        _ => {
+            schedule_session_expiry_after(client_id, Duration::from_secs(session_expiry_interval as u64), client_handler, topic_handler);
+        }
     }
+}
 
-    return match id2session.insert(client_id.clone(), SessionHandler::new()) {
-        None => {
-            trace!("Created new Session for client: {:?}", client_id);
-            SessionState::CleanSession
+//Drops the session once remaining elapses, for sessions whose interval started earlier. The timer does nothing
+//when the client reconnected, or when its expiry was canceled or scheduled again in the meantime.
+pub fn schedule_session_expiry_after(client_id: &String, remaining: Duration, client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>) {
+    trace!("Broker::schedule_session_expiry_after");
+    let generation = EXPIRY_GENERATION.fetch_add(1, Ordering::Relaxed);
+    client_handler.sessions.set_expiry(client_id, generation);
+    let client_id = client_id.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(remaining).await;
+        if !client_handler.sessions.take_expiry(&client_id, generation) {
+            return;
         }
-        Some(session) => {
-            error!("Need to handle 'session taken over' case");
-            SessionState::SessionPresent
+        if client_handler.get_socket(&client_id).is_err() {
+            info!("Session for client {:?} expired after {}s", client_id, remaining.as_secs());
+            expire_session(&*client_handler.sessions, &client_id, &topic_handler);
         }
-    };
+    });
 }
 
-pub fn register_clean_session(client_id: &String) {
-    trace!("Broker::register_clean_session");
-    id2session.insert(client_id.clone(), SessionHandler::new());
+fn expire_session(sessions: &dyn SessionDirectory, client_id: &String, topic_handler: &TopicHandler) {
+    sessions.remove(client_id);
+    topic_handler.unsubscribe_all(client_id);
 }
 
 pub async fn send_packet(socket: SocketAddr, packet: &ControlPacket, to_listener: &Sender<(Vec<SocketAddr>, ControlPacket)>) {
@@ -66,6 +201,25 @@ pub async fn send_packets(sockets: Vec<SocketAddr>, control_packet: &ControlPack
     trace!("Done sending packets");
 }
 
+//QoS 0 PUBLISH from the broker itself to the subscribers of a $SYS topic, it is neither retained nor queued
+pub async fn publish_sys_message(topic_name: &str, payload: Vec<u8>, client_handler: &ClientHandler, topic_handler: &TopicHandler, to_listener: &Sender<(Vec<SocketAddr>, ControlPacket)>) {
+    trace!("Broker::publish_sys_message");
+    let sockets: Vec<SocketAddr> = topic_handler.find_deliveries(&topic_name.to_string(), OverlapPolicy::Once, client_handler).iter()
+        .filter_map(|delivery| delivery.connection())
+        .map(|connection| connection.socket)
+        .collect();
+    if sockets.is_empty() {
+        return;
+    }
+    let publish_packet = ControlPacket::publish_with_payload(None, topic_name.to_string(), QoSLevel::AtMostOnce, false, vec![], payload);
+    send_packets(sockets, &publish_packet, to_listener).await;
+}
+
+pub fn generate_client_id_suffix(client_id: &String) -> String {
+    trace!("Broker::generate_client_id_suffix");
+    let random_suffix: u16 = rand::thread_rng().gen();
+    return format!("{}-{}", client_id, random_suffix);
+}
 
 pub fn generate_client_id() -> String {
     trace!("Broker::generate_client_id");
@@ -0,0 +1,1174 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Mapping;
+
+use crate::config::config_provenance::{apply_override, ConfigProvenance, ConfigSource, ConfigValue};
+use crate::config::config_schema::{invalid_values, unknown_keys};
+use crate::topic::topic_matcher::is_valid_filter;
+
+#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct BrokerConfig {
+    pub(crate) session: SessionConfig,
+    pub(crate) packet: PacketConfig,
+    pub(crate) gateway: GatewayConfig,
+    pub(crate) admin: AdminConfig,
+    pub(crate) compression: CompressionConfig,
+    pub(crate) receive_timestamp: ReceiveTimestampConfig,
+    pub(crate) quota: QuotaConfig,
+    pub(crate) audit: AuditConfig,
+    pub(crate) subscription: SubscriptionConfig,
+    pub(crate) message_expiry: MessageExpiryConfig,
+    pub(crate) keep_alive: KeepAliveConfig,
+    pub(crate) tcp: TcpConfig,
+    pub(crate) handshake: HandshakeConfig,
+    pub(crate) pacing: PacingConfig,
+    pub(crate) dispatch: DispatchConfig,
+    pub(crate) congestion: CongestionConfig,
+    pub(crate) sys: SysConfig,
+    pub(crate) virtual_topics: VirtualTopicsConfig,
+    pub(crate) decode: DecodeConfig,
+    pub(crate) delivery_report: DeliveryReportConfig,
+    pub(crate) retained_delivery: RetainedDeliveryConfig,
+    pub(crate) storage: StorageConfig,
+    pub(crate) auth: AuthConfig,
+    pub(crate) acl: AclConfig,
+    pub(crate) publisher_identity: PublisherIdentityConfig,
+    pub(crate) qos2: Qos2Config,
+    pub(crate) hot_topics: HotTopicsConfig,
+    pub(crate) payload_sizes: PayloadSizesConfig,
+    pub(crate) subnet_stats: SubnetStatsConfig,
+    pub(crate) tree_telemetry: TreeTelemetryConfig,
+    pub(crate) control: ControlConfig,
+    pub(crate) debug_capture: DebugCaptureConfig,
+    pub(crate) diagnostics: DiagnosticsConfig,
+    pub(crate) supervisor: SupervisorConfig,
+    pub(crate) profiling: ProfilingConfig,
+    #[serde(skip)]
+    pub(crate) provenance: ConfigProvenance,
+}
+
+impl BrokerConfig {
+    pub fn load(path: &str) -> Self {
+        Self::load_with_overrides(path, &[])
+    }
+
+    //The file at path, if it can be read, with the --set overrides on top. Every value remembers where it came from.
+    //Problems are logged and skipped, a value serde can't take leaves the default config.
+    pub fn load_with_overrides(path: &str, overrides: &[String]) -> Self {
+        let (config, problems) = Self::parse(path, overrides);
+        for problem in problems {
+            warn!("{}. Ignoring it", problem);
+        }
+        config.unwrap_or_else(|| {
+            warn!("Using default config");
+            Self::default()
+        })
+    }
+
+    //Like load_with_overrides, but every problem found refuses the config
+    pub fn load_checked(path: &str, overrides: &[String]) -> Result<Self, Vec<String>> {
+        match Self::parse(path, overrides) {
+            (Some(config), problems) if problems.is_empty() => { Ok(config) }
+            (_, problems) => { Err(problems) }
+        }
+    }
+
+    //The config with the problems found in it: a file that can't be parsed, invalid overrides, unknown keys
+    //and invalid values, all of them by dotted key. A missing file is the default config.
+    fn parse(path: &str, overrides: &[String]) -> (Option<Self>, Vec<String>) {
+        let mut problems = vec![];
+        let file = match Self::read_file(path) {
+            Ok(Some(file)) => {
+                info!("Loaded config from {}", path);
+                file
+            }
+            Ok(None) => {
+                warn!("No config file {}. Using default config", path);
+                Mapping::new()
+            }
+            Err(err) => {
+                problems.push(err);
+                Mapping::new()
+            }
+        };
+        let mut merged = file.clone();
+        let mut cli_keys = BTreeSet::new();
+        for assignment in overrides {
+            match apply_override(&mut merged, assignment) {
+                Ok(key) => { cli_keys.insert(key); }
+                Err(err) => { problems.push(err); }
+            }
+        }
+        problems.extend(unknown_keys(&merged));
+        //Parsed from text, so that serde errors name the key of the value
+        let parsed = serde_yaml::to_string(&merged).map_err(|err| err.to_string())
+            .and_then(|merged| serde_yaml::from_str::<Self>(&merged).map_err(|err| {
+                //Lines of the merged config aren't the ones of the file
+                let location = err.location().map(|location| format!(" at line {} column {}", location.line(), location.column())).unwrap_or_default();
+                err.to_string().trim_end_matches(&location).to_string()
+            }));
+        let mut config = match parsed {
+            Ok(config) => { config }
+            Err(err) => {
+                problems.push(format!("Can't parse config from {} and command line. {}", path, err));
+                return (None, problems);
+            }
+        };
+        problems.extend(invalid_values(&config));
+        config.provenance = ConfigProvenance::new(&file, cli_keys);
+        (Some(config), problems)
+    }
+
+    //None when there is no file at path
+    fn read_file(path: &str) -> Result<Option<Mapping>, String> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => { content }
+            Err(err) if err.kind() == ErrorKind::NotFound => { return Ok(None); }
+            Err(err) => { return Err(format!("Can't read config file {}. {}", path, err)); }
+        };
+        serde_yaml::from_str::<Option<Mapping>>(&content)
+            .map(|file| Some(file.unwrap_or_default()))
+            .map_err(|err| format!("Can't parse config file {}. {}", path, err))
+    }
+
+    //Every value by dotted key with its source, secrets redacted
+    pub fn effective(&self) -> BTreeMap<String, ConfigValue> {
+        let config = serde_json::to_value(self).unwrap_or_default();
+        self.provenance.resolve(config)
+    }
+
+    //Values that differ from the defaults at info level, the rest at debug
+    pub fn log_summary(&self) {
+        info!("patina {} built with features {:?}", env!("CARGO_PKG_VERSION"), enabled_features());
+        for (key, config_value) in self.effective() {
+            match config_value.source {
+                ConfigSource::Default => { debug!("Config {} = {} ({:?})", key, config_value.value, config_value.source); }
+                _ => { info!("Config {} = {} ({:?})", key, config_value.value, config_value.source); }
+            }
+        }
+    }
+
+    //Memory mode refuses to start with a feature that writes to disk instead of silently skipping the writes
+    pub fn check_storage(&self) -> Result<(), String> {
+        if self.storage.mode == StorageMode::Disk {
+            return Ok(());
+        }
+        let disk_features: Vec<&str> = [
+            ("audit.enabled", self.audit.enabled),
+        ].into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(feature, _)| feature)
+            .collect();
+        if !disk_features.is_empty() {
+            return Err(format!("storage.mode is memory but {} needs the disk", disk_features.join(", ")));
+        }
+        return Ok(());
+    }
+
+    //Rules that can't be read or don't parse stop the broker rather than leaving topics open or everything refused
+    pub fn check_acl(&self) -> Result<(), String> {
+        if !self.acl.enabled {
+            return Ok(());
+        }
+        self.acl.load_rules().map(|_| ())
+    }
+}
+
+//Cargo features the broker was built with
+pub fn enabled_features() -> Vec<&'static str> {
+    [
+        ("admin-api", cfg!(feature = "admin-api")),
+        ("logging", cfg!(feature = "logging")),
+        ("mqtt-sn", cfg!(feature = "mqtt-sn")),
+        ("coap", cfg!(feature = "coap")),
+        ("symmetry-check", cfg!(feature = "symmetry-check")),
+        ("profiling", cfg!(feature = "profiling")),
+    ].into_iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(feature, _)| feature)
+        .collect()
+}
+
+//What happens to the sessions of clients that connect
+#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct SessionConfig {
+    pub(crate) takeover_policy: TakeoverPolicy,
+    //Sent as "<prefix>/<client_id>" Response Information to clients that set Request Response Information
+    pub(crate) response_topic_prefix: Option<String>,
+    //Clients connected at once with the same username, 0 is unlimited. Clients without a username don't count
+    pub(crate) max_connections_per_user: u32,
+}
+
+//Largest packet MQTT can carry: 1 byte header, 4 bytes Remaining Length, 268435455 bytes of content
+pub const PROTOCOL_MAXIMUM_PACKET_SIZE: u32 = 268_435_460;
+
+//Limits of the packets read from clients
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PacketConfig {
+    //Upper bound in bytes for any packet read from a client
+    pub(crate) maximum_packet_size: u32,
+    //Topic Aliases a client may set up on its connection, announced in CONNACK. 0 refuses them
+    pub(crate) topic_alias_maximum: u16,
+}
+
+impl Default for PacketConfig {
+    fn default() -> Self {
+        Self { maximum_packet_size: PROTOCOL_MAXIMUM_PACKET_SIZE, topic_alias_maximum: 16 }
+    }
+}
+
+//What to do when a CONNECT arrives with a client_id that is already connected
+#[derive(Debug, Default)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TakeoverPolicy {
+    //Disconnect the previous connection with SessionTakenOver and accept the new one
+    #[default]
+    KickOld,
+    //Refuse the new connection with ClientIdentifierNotValid, keep the previous one
+    RejectNew,
+    //Keep both connections, the new one gets a suffixed client_id
+    AllowBothWithSuffix,
+}
+
+//UDP gateways translating other protocols to MQTT clients
+#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct GatewayConfig {
+    pub(crate) mqtt_sn: MqttSnConfig,
+    pub(crate) coap: CoapConfig,
+}
+
+//Only used when the broker is built with the mqtt-sn feature
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttSnConfig {
+    pub(crate) enabled: bool,
+    pub(crate) port: u16,
+}
+
+impl Default for MqttSnConfig {
+    fn default() -> Self {
+        Self { enabled: false, port: 1884 }
+    }
+}
+
+//Only used when the broker is built with the coap feature
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoapConfig {
+    pub(crate) enabled: bool,
+    pub(crate) port: u16,
+    //Client id the bridge connects to the broker with
+    pub(crate) client_id: String,
+    //Longest matching path prefix wins, the rest of the path is appended to its topic
+    pub(crate) mappings: Vec<CoapMapping>,
+}
+
+impl Default for CoapConfig {
+    fn default() -> Self {
+        Self { enabled: false, port: 5683, client_id: String::from("coap-bridge"), mappings: vec![] }
+    }
+}
+
+//A CoAP path prefix and the topic it is published to
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct CoapMapping {
+    pub(crate) path: String,
+    pub(crate) topic: String,
+}
+
+//Only used when the broker is built with the admin-api feature
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    //Bearer token with the admin role
+    pub(crate) api_token: Option<String>,
+    //More bearer tokens, each with its own role
+    pub(crate) tokens: Vec<ApiToken>,
+    //Role each endpoint needs, by "<METHOD> /<path>". Endpoints not listed need their default role
+    pub(crate) endpoint_roles: BTreeMap<String, AdminRole>,
+    //Client id HTTP publishes are made with, SSE subscribers get it with a -sse-<n> suffix
+    pub(crate) client_id: String,
+    //Concurrent GET /subscribe streams
+    pub(crate) max_subscribe_streams: usize,
+    //Messages buffered per stream, a client reading slower than that loses messages
+    pub(crate) subscribe_stream_capacity: usize,
+    //Largest state snapshot PUT /state takes, in bytes
+    pub(crate) max_state_bytes: u64,
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        Self { api_token: None, tokens: vec![], endpoint_roles: BTreeMap::new(), client_id: String::from("admin-api"), max_subscribe_streams: 100, subscribe_stream_capacity: 1000, max_state_bytes: 64 * 1024 * 1024 }
+    }
+}
+
+//Roles of the endpoints that admin.endpoint_roles doesn't list
+pub const DEFAULT_ENDPOINT_ROLES: [(&str, AdminRole); 27] = [
+    ("GET /metrics", AdminRole::Public),
+    ("GET /takeovers", AdminRole::Public),
+    ("GET /config", AdminRole::Read),
+    ("GET /hot-topics", AdminRole::Read),
+    ("GET /subnets", AdminRole::Read),
+    ("GET /subscriptions/stale", AdminRole::Read),
+    ("GET /log-levels", AdminRole::Read),
+    ("GET /debug-captures", AdminRole::Read),
+    ("GET /subscribe", AdminRole::Read),
+    ("GET /retained", AdminRole::Read),
+    ("GET /listeners", AdminRole::Read),
+    ("GET /clients", AdminRole::Admin),
+    ("DELETE /clients", AdminRole::Admin),
+    ("GET /users", AdminRole::Admin),
+    ("DELETE /users", AdminRole::Admin),
+    ("DELETE /retained", AdminRole::Admin),
+    ("POST /publish", AdminRole::Admin),
+    ("PUT /log-levels", AdminRole::Admin),
+    ("DELETE /log-levels", AdminRole::Admin),
+    ("PUT /debug-captures", AdminRole::Admin),
+    ("DELETE /debug-captures", AdminRole::Admin),
+    ("POST /diagnostics", AdminRole::Admin),
+    ("GET /debug/pprof/profile", AdminRole::Admin),
+    ("GET /state", AdminRole::Admin),
+    ("PUT /state", AdminRole::Admin),
+    ("PUT /listeners/drain", AdminRole::Admin),
+    ("DELETE /listeners/drain", AdminRole::Admin),
+];
+
+impl AdminConfig {
+    //Unknown endpoints need the admin role
+    pub fn required_role(&self, endpoint: &str) -> AdminRole {
+        if let Some(role) = self.endpoint_roles.get(endpoint) {
+            return *role;
+        }
+        return DEFAULT_ENDPOINT_ROLES.iter()
+            .find(|(default_endpoint, _)| *default_endpoint == endpoint)
+            .map(|(_, role)| *role)
+            .unwrap_or(AdminRole::Admin);
+    }
+
+    pub fn has_tokens(&self) -> bool {
+        self.api_token.is_some() || !self.tokens.is_empty()
+    }
+}
+
+//A role includes everything the roles before it may do
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AdminRole {
+    //No token needed
+    Public,
+    //Reading metrics, config and streams
+    Read,
+    //Actions on clients and messages
+    Admin,
+}
+
+//A bearer token of the admin API with its role
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+pub struct ApiToken {
+    //Shown in the audit log instead of the token
+    pub(crate) name: String,
+    pub(crate) token: String,
+    pub(crate) role: AdminRole,
+}
+
+//Deflated payloads for subscribers that ask for them
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct CompressionConfig {
+    pub(crate) enabled: bool,
+    //Payloads smaller than this many bytes are forwarded as they are
+    pub(crate) minimum_size: usize,
+    //Clients that get deflate payloads without asking for them at SUBSCRIBE
+    pub(crate) client_ids: Vec<String>,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self { enabled: false, minimum_size: 1024, client_ids: vec![] }
+    }
+}
+
+//Stamps forwarded PUBLISH packets with the time the broker received them
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReceiveTimestampConfig {
+    pub(crate) enabled: bool,
+    //User property carrying the Unix epoch milliseconds
+    pub(crate) property_name: String,
+    //Topic name prefixes to stamp, every topic when empty
+    pub(crate) topics: Vec<String>,
+}
+
+impl Default for ReceiveTimestampConfig {
+    fn default() -> Self {
+        Self { enabled: false, property_name: String::from("patina-recv-ts"), topics: vec![] }
+    }
+}
+
+impl ReceiveTimestampConfig {
+    pub fn applies_to(&self, topic_name: &str) -> bool {
+        self.enabled && (self.topics.is_empty() || self.topics.iter().any(|prefix| topic_name.starts_with(prefix.as_str())))
+    }
+}
+
+//Limits of the clients, by the quota profile of their username
+#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaConfig {
+    //Profile of clients without an assignment, they are unlimited when unset
+    pub(crate) default_profile: Option<String>,
+    pub(crate) profiles: HashMap<String, QuotaProfile>,
+    //CONNECT username -> profile name
+    pub(crate) usernames: HashMap<String, String>,
+}
+
+impl QuotaConfig {
+    pub fn resolve_profile(&self, username: Option<&String>) -> Option<(&String, &QuotaProfile)> {
+        let profile_name = username.and_then(|username| self.usernames.get(username))
+            .or(self.default_profile.as_ref())?;
+        self.profiles.get_key_value(profile_name)
+    }
+}
+
+//Every limit is optional, an unset one is not enforced
+#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct QuotaProfile {
+    //QoS 2 publishes awaiting PUBREL, advertised as Receive Maximum in CONNACK
+    pub(crate) max_inflight: Option<u16>,
+    //Messages kept in the session of the client, newer ones are not stored once reached
+    pub(crate) max_queued: Option<usize>,
+    //Bytes of a PUBLISH payload
+    pub(crate) max_payload: Option<usize>,
+    //PUBLISH packets per second
+    pub(crate) publish_rate: Option<u32>,
+    pub(crate) max_subscriptions: Option<usize>,
+    //Share of the outbound queue under congestion.fair_share relative to the other profiles, 1 when unset
+    pub(crate) weight: Option<u32>,
+}
+
+//Admin actions, authentication failures and config loads written to a rotated log file
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    pub(crate) enabled: bool,
+    pub(crate) path: String,
+    //Bytes written before the file is rotated
+    pub(crate) max_size: u64,
+    //Rotated files kept next to the current one
+    pub(crate) max_files: usize,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self { enabled: false, path: String::from("log/audit.log"), max_size: 10 * 1024 * 1024, max_files: 5 }
+    }
+}
+
+//Which topic filters clients may subscribe to and how overlapping subscriptions are served
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubscriptionConfig {
+    pub(crate) overlap_policy: OverlapPolicy,
+    //Topic filters with + or #, refused with WildcardSubscriptionsNotSupported when disabled
+    pub(crate) wildcard_subscriptions: bool,
+    //$share/ topic filters, refused with SharedSubscriptionsNotSupported when disabled
+    pub(crate) shared_subscriptions: bool,
+    pub(crate) shared_retained: SharedRetained,
+    //UNSUBSCRIBE drops the messages queued for the client only through the topic filters it unsubscribed from,
+    //the ones sent already and waiting for their acknowledgement are still delivered
+    pub(crate) drop_queued_on_unsubscribe: bool,
+}
+
+impl Default for SubscriptionConfig {
+    fn default() -> Self {
+        Self { overlap_policy: OverlapPolicy::default(), wildcard_subscriptions: true, shared_subscriptions: true, shared_retained: SharedRetained::default(), drop_queued_on_unsubscribe: false }
+    }
+}
+
+//Which shared subscriptions get the retained messages matching their filter when they are made
+#[derive(Debug, Default)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SharedRetained {
+    //None of them, as the specification requires
+    #[default]
+    Never,
+    //The subscription that starts its group, later members get nothing
+    FirstMember,
+}
+
+//How a message is forwarded to a client with several subscriptions matching its topic
+#[derive(Debug, Default)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverlapPolicy {
+    //A single copy with the highest QoS and the identifiers of all the subscriptions
+    #[default]
+    Once,
+    //A copy per matching subscription
+    PerSubscription,
+}
+
+//Message Expiry Interval given to PUBLISH packets that arrive without one
+#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct MessageExpiryConfig {
+    //Topic name prefix -> seconds, the longest matching prefix applies
+    pub(crate) defaults: BTreeMap<String, u32>,
+}
+
+impl MessageExpiryConfig {
+    pub fn default_interval(&self, topic_name: &str) -> Option<u32> {
+        self.defaults.iter()
+            .filter(|(prefix, _)| topic_name.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, message_expiry_interval)| *message_expiry_interval)
+    }
+}
+
+//How long a client may stay silent past its Keep Alive before it is disconnected with KeepAliveTimeout
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeepAliveConfig {
+    //Multiplies the Keep Alive of the client, MQTT asks for 1.5
+    pub(crate) grace_factor: f64,
+    //Added on top for network jitter and clock skew
+    pub(crate) jitter_tolerance_ms: u64,
+    //Logs the RTT estimate and the queue depths of a client at every PINGREQ, to patina::keep_alive_stats at trace
+    pub(crate) statistics: bool,
+    //Also publishes them as JSON to $SYS/clients/<client_id>/keep-alive
+    pub(crate) statistics_topic: bool,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        Self { grace_factor: 1.5, jitter_tolerance_ms: 500, statistics: false, statistics_topic: false }
+    }
+}
+
+impl KeepAliveConfig {
+    //None for a Keep Alive of 0, which turns the mechanism off
+    pub fn deadline(&self, keep_alive: u16) -> Option<Duration> {
+        if keep_alive == 0 {
+            return None;
+        }
+        let grace = Duration::from_secs(keep_alive as u64).mul_f64(self.grace_factor.max(1.0));
+        Some(grace + Duration::from_millis(self.jitter_tolerance_ms))
+    }
+}
+
+//Options of accepted TCP sockets. Kernel keepalive probes find half-open connections, e.g. behind a NAT
+//that forgot them, long before a large MQTT Keep Alive runs out.
+#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct TcpConfig {
+    pub(crate) nodelay: bool,
+    //Idle time before the first keepalive probe, 0 leaves keepalive off
+    pub(crate) keepalive_time_secs: u64,
+    //Between unanswered probes, 0 keeps the kernel default. Linux only
+    pub(crate) keepalive_interval_secs: u64,
+    //Unanswered probes before the connection is dropped, 0 keeps the kernel default. Linux only
+    pub(crate) keepalive_retries: u32,
+    //How long sent data may stay unacknowledged before the connection is dropped, 0 keeps the kernel default. Linux only
+    pub(crate) user_timeout_ms: u64,
+}
+
+//Connections accepted but without a CONNECT yet
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct HandshakeConfig {
+    //Pending connections per client address, more are closed right after accept. 0 doesn't limit them
+    pub(crate) max_pending_per_ip: usize,
+    //Seconds a connection may take to send its CONNECT, 0 waits forever
+    pub(crate) connect_timeout_secs: u64,
+}
+
+impl Default for HandshakeConfig {
+    fn default() -> Self {
+        Self { max_pending_per_ip: 16, connect_timeout_secs: 10 }
+    }
+}
+
+//Spreads the reconnects of a whole fleet, e.g. after a restart of the broker, over time. Connections past
+//the burst wait in the accept backlog, CONNECTs past it wait for their CONNACK, while the traffic of the
+//clients connected already goes on
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PacingConfig {
+    //New connections accepted per second, 0 doesn't pace them
+    pub(crate) accept_per_sec: u32,
+    //Connections accepted at once before the pacing starts
+    pub(crate) accept_burst: u32,
+    //CONNACKs of accepted sessions sent per second, 0 doesn't pace them
+    pub(crate) connack_per_sec: u32,
+    //CONNACKs sent at once before the pacing starts
+    pub(crate) connack_burst: u32,
+    //Up to this many milliseconds added to a paced wait, so clients paced together don't go on together
+    pub(crate) jitter_ms: u64,
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self { accept_per_sec: 0, accept_burst: 100, connack_per_sec: 0, connack_burst: 100, jitter_ms: 50 }
+    }
+}
+
+//Packets whose handler fails or panics are retried, then quarantined
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct DispatchConfig {
+    //Attempts per packet, the first one included
+    pub(crate) max_attempts: u32,
+    //Wait before a retry, multiplied by the attempts made so far
+    pub(crate) retry_backoff_ms: u64,
+    //Packets read from clients waiting for the broker, readers wait while it is full
+    pub(crate) inbound_capacity: usize,
+    //How long a packet of a client without Keep Alive may wait for the broker, 0 for as long as it takes.
+    //With Keep Alive the packet may wait as long as the client may stay silent.
+    pub(crate) packet_deadline_ms: u64,
+}
+
+impl DispatchConfig {
+    pub fn packet_deadline(&self, keep_alive_deadline: Option<Duration>) -> Option<Duration> {
+        keep_alive_deadline.or((self.packet_deadline_ms > 0).then(|| Duration::from_millis(self.packet_deadline_ms)))
+    }
+}
+
+impl Default for DispatchConfig {
+    fn default() -> Self {
+        Self { max_attempts: 3, retry_backoff_ms: 50, inbound_capacity: 1000000, packet_deadline_ms: 30000 }
+    }
+}
+
+//Under congestion QoS 0 deliveries are shed first so that acknowledged QoS 1 and 2 traffic keeps flowing
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct CongestionConfig {
+    //Packets waiting for the connection writers at which shedding starts, 0 never sheds
+    pub(crate) queue_threshold: usize,
+    //Chance of dropping each QoS 0 delivery while congested
+    pub(crate) drop_probability: f64,
+    //Topic name prefixes counting their own drops, the longest matching one applies
+    pub(crate) drop_counter_prefixes: BTreeSet<String>,
+    //Packets waiting for the connection writers at which CONNECTs are refused with Server Busy, 0 never refuses
+    pub(crate) connect_queue_threshold: usize,
+    //Bounds of the retry-after hint of a refused CONNECT, it grows with the queue past the threshold
+    pub(crate) retry_after_min_secs: u64,
+    pub(crate) retry_after_max_secs: u64,
+    //Sheds the quota profile groups by how far they exceed their weighted share of the deliveries, instead of evenly
+    pub(crate) fair_share: bool,
+}
+
+impl Default for CongestionConfig {
+    fn default() -> Self {
+        Self { queue_threshold: 0, drop_probability: 1.0, drop_counter_prefixes: BTreeSet::new(), connect_queue_threshold: 0, retry_after_min_secs: 5, retry_after_max_secs: 300, fair_share: false }
+    }
+}
+
+//Broker information published to $SYS/broker/version, $SYS/broker/uptime and $SYS/broker/build
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct SysConfig {
+    //Seconds between two publications, 0 disables them
+    pub(crate) interval_secs: u64,
+}
+
+impl Default for SysConfig {
+    fn default() -> Self {
+        Self { interval_secs: 10 }
+    }
+}
+
+//Topics whose value the broker computes when a client subscribes, see topic::virtual_topics
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct VirtualTopicsConfig {
+    //Registers the built-in providers
+    pub(crate) enabled: bool,
+    //Topic name -> seconds a computed value is served and between two publications to the subscribers,
+    //replacing the interval of its provider. 0 computes the value for every subscription and never publishes it
+    pub(crate) refresh_secs: BTreeMap<String, u64>,
+}
+
+impl VirtualTopicsConfig {
+    pub fn refresh_interval(&self, topic_name: &str, default: Duration) -> Duration {
+        self.refresh_secs.get(topic_name).map_or(default, |refresh_secs| Duration::from_secs(*refresh_secs))
+    }
+}
+
+impl Default for VirtualTopicsConfig {
+    fn default() -> Self {
+        Self { enabled: false, refresh_secs: BTreeMap::new() }
+    }
+}
+
+//Inbound packets decoded on a pool of worker threads instead of the task reading the connection,
+//so that a single busy connection isn't limited to one core
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct DecodeConfig {
+    //Worker threads, 0 decodes every packet on the reading task
+    pub(crate) workers: usize,
+    //Packets with at least this Remaining Length are offloaded
+    pub(crate) min_packet_size: u64,
+    //Every packet of a connection reading at least this many packets per second is offloaded
+    pub(crate) min_packet_rate: u32,
+}
+
+impl Default for DecodeConfig {
+    fn default() -> Self {
+        Self { workers: 0, min_packet_size: 16 * 1024, min_packet_rate: 1000 }
+    }
+}
+
+//JSON reports on $SYS/delivery of what became of each PUBLISH on the topics
+#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct DeliveryReportConfig {
+    pub(crate) enabled: bool,
+    //Topic name prefixes to report, every topic when empty
+    pub(crate) topics: Vec<String>,
+}
+
+impl DeliveryReportConfig {
+    pub fn applies_to(&self, topic_name: &str) -> bool {
+        self.enabled && (self.topics.is_empty() || self.topics.iter().any(|prefix| topic_name.starts_with(prefix.as_str())))
+    }
+}
+
+//Pace of the retained messages sent to a new subscription, so a broad wildcard doesn't flood the connection
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetainedDeliveryConfig {
+    //Retained messages per second and subscription, 0 doesn't limit them
+    pub(crate) messages_per_sec: u32,
+    //Payload bytes per second and subscription, 0 doesn't limit them
+    pub(crate) bytes_per_sec: u64,
+    //Seconds after a disconnection during which a reconnect with the session continues the remaining messages
+    pub(crate) resume_window_secs: u64,
+}
+
+impl Default for RetainedDeliveryConfig {
+    fn default() -> Self {
+        Self { messages_per_sec: 0, bytes_per_sec: 0, resume_window_secs: 30 }
+    }
+}
+
+#[derive(Debug, Default)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageMode {
+    #[default]
+    Disk,
+    //The broker never writes to disk, sessions and retained messages only live in memory
+    Memory,
+}
+
+//Whether the broker writes to disk and where it saves the sessions
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub(crate) mode: StorageMode,
+    //Sessions with a Session Expiry Interval are saved here in disk mode, one file per client
+    pub(crate) session_directory: String,
+    //Seconds between two saves of the sessions, which are saved once more on shutdown. 0 never saves them
+    pub(crate) session_checkpoint_secs: u64,
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self { mode: StorageMode::default(), session_directory: String::from("data/sessions"), session_checkpoint_secs: 5 }
+    }
+}
+
+//Username and password authentication of CONNECT, with delays and lockouts after failures
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    //username -> password. Empty accepts every CONNECT
+    pub(crate) users: BTreeMap<String, String>,
+    //Milliseconds before answering a failed CONNECT, plus up to failure_jitter_ms at random
+    pub(crate) failure_delay_ms: u64,
+    pub(crate) failure_jitter_ms: u64,
+    //Failures in a row after which a username or IP address is locked out, 0 never locks out
+    pub(crate) lockout_threshold: u32,
+    //Doubled with every failure past the threshold, up to lockout_max_secs
+    pub(crate) lockout_base_secs: u64,
+    pub(crate) lockout_max_secs: u64,
+    //Refuses every failed CONNECT with Not Authorized, so a client can't tell a wrong password from a lockout
+    pub(crate) generic_reason_codes: bool,
+}
+
+impl AuthConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.users.is_empty()
+    }
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self { users: BTreeMap::new(), failure_delay_ms: 1000, failure_jitter_ms: 500, lockout_threshold: 5, lockout_base_secs: 10, lockout_max_secs: 900, generic_reason_codes: false }
+    }
+}
+
+//Topics clients may publish and subscribe to, see auth::acl
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct AclConfig {
+    pub(crate) enabled: bool,
+    //Checked in order, the first rule matching the client and the topic decides
+    pub(crate) rules: Vec<AclRule>,
+    //YAML file with a list of rules like the ones above, checked after them
+    pub(crate) file: Option<String>,
+    //Decides a publish or subscription no rule matches
+    pub(crate) no_match: AclPermission,
+    //A denied PUBLISH disconnects the client with Not Authorized, instead of only being refused
+    pub(crate) disconnect_on_denied_publish: bool,
+}
+
+impl AclConfig {
+    //The rules above followed by the ones of the file
+    pub fn load_rules(&self) -> Result<Vec<AclRule>, String> {
+        let mut rules = self.rules.clone();
+        if let Some(path) = &self.file {
+            let content = fs::read_to_string(path)
+                .map_err(|err| format!("Can't read ACL file {}. {}", path, err))?;
+            let file_rules: Option<Vec<AclRule>> = serde_yaml::from_str(&content)
+                .map_err(|err| format!("Can't parse ACL file {}. {}", path, err))?;
+            rules.extend(file_rules.unwrap_or_default());
+        }
+        for rule in &rules {
+            rule.check()?;
+        }
+        Ok(rules)
+    }
+}
+
+impl Default for AclConfig {
+    fn default() -> Self {
+        Self { enabled: false, rules: vec![], file: None, no_match: AclPermission::Deny, disconnect_on_denied_publish: false }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AclPermission {
+    Allow,
+    Deny,
+}
+
+//What a rule lets a client do on its topics
+#[derive(Debug, Default)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AclAccess {
+    //Subscribe
+    Read,
+    //Publish, a Will Message included
+    Write,
+    #[default]
+    ReadWrite,
+}
+
+impl AclAccess {
+    pub fn allows_read(&self) -> bool {
+        *self != AclAccess::Write
+    }
+
+    pub fn allows_write(&self) -> bool {
+        *self != AclAccess::Read
+    }
+}
+
+//Allows or denies the topics to the clients it matches
+#[derive(Debug, Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct AclRule {
+    pub(crate) permission: AclPermission,
+    //Patterns where * stands for any characters, a rule without them applies to every client
+    #[serde(default)]
+    pub(crate) client_id: Option<String>,
+    //A client without a username never matches a username pattern
+    #[serde(default)]
+    pub(crate) username: Option<String>,
+    #[serde(default)]
+    pub(crate) access: AclAccess,
+    //Topic filters, %c stands for the client_id and %u for the username
+    pub(crate) topics: Vec<String>,
+}
+
+impl AclRule {
+    fn check(&self) -> Result<(), String> {
+        if self.topics.is_empty() {
+            return Err(format!("ACL rule {:?} has no topics", self));
+        }
+        match self.topics.iter().find(|topic| !is_valid_filter(topic)) {
+            Some(topic) => { Err(format!("ACL rule {:?} has an invalid topic filter {:?}", self, topic)) }
+            None => { Ok(()) }
+        }
+    }
+}
+
+//Stamps forwarded PUBLISH packets with the publisher's identity
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PublisherIdentityConfig {
+    pub(crate) enabled: bool,
+    //User property carrying the publisher's client_id
+    pub(crate) client_id_property: String,
+    //User property carrying the publisher's CONNECT username, absent without one
+    pub(crate) username_property: String,
+    //Topic name prefixes to stamp, every topic when empty
+    pub(crate) topics: Vec<String>,
+}
+
+impl Default for PublisherIdentityConfig {
+    fn default() -> Self {
+        Self { enabled: false, client_id_property: String::from("patina-publisher"), username_property: String::from("patina-publisher-username"), topics: vec![] }
+    }
+}
+
+impl PublisherIdentityConfig {
+    pub fn applies_to(&self, topic_name: &str) -> bool {
+        self.enabled && (self.topics.is_empty() || self.topics.iter().any(|prefix| topic_name.starts_with(prefix.as_str())))
+    }
+}
+
+//QoS 2 handshakes abandoned by their client
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Qos2Config {
+    //Seconds a QoS 2 handshake may wait for the client's next packet before its state is dropped, 0 never drops it
+    pub(crate) handshake_timeout_secs: u64,
+    //Seconds between checks for abandoned handshakes
+    pub(crate) sweep_interval_secs: u64,
+}
+
+impl Default for Qos2Config {
+    fn default() -> Self {
+        Self { handshake_timeout_secs: 60, sweep_interval_secs: 5 }
+    }
+}
+
+//Connections are counted per subnet of the client address rather than per address, so a fleet of devices
+//behind one network segment shows up as one entry
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubnetStatsConfig {
+    pub(crate) ipv4_prefix_len: u8,
+    pub(crate) ipv6_prefix_len: u8,
+    //Subnets tracked at most, connections from others are only counted as untracked
+    pub(crate) capacity: usize,
+    //Subnets with the most open connections exported with the metrics, 0 exports none
+    pub(crate) exported: usize,
+}
+
+impl Default for SubnetStatsConfig {
+    fn default() -> Self {
+        Self { ipv4_prefix_len: 24, ipv6_prefix_len: 64, capacity: 10000, exported: 10 }
+    }
+}
+
+//Topics with the highest publish rate, on GET /hot-topics
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct HotTopicsConfig {
+    pub(crate) enabled: bool,
+    //Topics tracked at most
+    pub(crate) capacity: usize,
+    //Seconds after which a publish counts half in the rate
+    pub(crate) half_life_secs: u64,
+}
+
+impl Default for HotTopicsConfig {
+    fn default() -> Self {
+        Self { enabled: false, capacity: 100, half_life_secs: 60 }
+    }
+}
+
+//Histograms of the payload sizes per topic prefix, exported with the metrics
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PayloadSizesConfig {
+    pub(crate) enabled: bool,
+    //Leading topic levels a prefix is made of
+    pub(crate) prefix_levels: usize,
+    //Prefixes with a histogram of their own, the messages of later ones share one
+    pub(crate) capacity: usize,
+}
+
+impl Default for PayloadSizesConfig {
+    fn default() -> Self {
+        Self { enabled: true, prefix_levels: 1, capacity: 32 }
+    }
+}
+
+//Reports of the shape of the subscription tree
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct TreeTelemetryConfig {
+    //Seconds between reports of the subscription tree shape, 0 turns them off
+    pub(crate) report_interval_secs: u64,
+    //A topic level with this many children is logged as a warning, 0 never warns
+    pub(crate) fanout_warning: usize,
+}
+
+impl Default for TreeTelemetryConfig {
+    fn default() -> Self {
+        Self { report_interval_secs: 60, fanout_warning: 10000 }
+    }
+}
+
+//Commands published to $CONTROL/ topics
+#[derive(Debug, Clone, Default)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControlConfig {
+    //Usernames whose PUBLISH to $CONTROL/ topics is run as a command, nobody's when empty
+    pub(crate) usernames: Vec<String>,
+}
+
+//Packets of a single client logged on demand from the admin API
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct DebugCaptureConfig {
+    //How long the packets of a client are logged when the admin API doesn't say
+    pub(crate) default_duration_secs: u64,
+    //Longest capture the admin API can start, a forgotten one stops on its own
+    pub(crate) max_duration_secs: u64,
+}
+
+impl Default for DebugCaptureConfig {
+    fn default() -> Self {
+        Self { default_duration_secs: 300, max_duration_secs: 3600 }
+    }
+}
+
+//Publishers and subscribers of an application embedding the broker
+//Snapshots of the broker state written on SIGUSR1 or POST /diagnostics, to attach to bug reports
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiagnosticsConfig {
+    //Directory of the patina-diagnostics-<timestamp>.json files, created when missing
+    pub(crate) directory: String,
+    //Connected clients listed one by one, the others are only counted
+    pub(crate) max_clients: usize,
+}
+
+impl Default for DiagnosticsConfig {
+    fn default() -> Self {
+        Self { directory: String::from("log"), max_clients: 10000 }
+    }
+}
+
+//CPU profiles taken on GET /debug/pprof/profile. Only used when the broker is built with the profiling feature
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProfilingConfig {
+    //Length of a profile when the request doesn't give one
+    pub(crate) default_seconds: u64,
+    //Longer requests are cut to this
+    pub(crate) max_seconds: u64,
+    //Samples per second
+    pub(crate) frequency: i32,
+}
+
+impl Default for ProfilingConfig {
+    fn default() -> Self {
+        Self { default_seconds: 10, max_seconds: 60, frequency: 99 }
+    }
+}
+
+//Restarts of the subsystems (listeners, broker loop, gateways, admin API) that crashed or returned an error
+#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct SupervisorConfig {
+    //Wait before the first restart, doubled for every crash in a row
+    pub(crate) initial_backoff_ms: u64,
+    //Longest wait between two restarts
+    pub(crate) max_backoff_ms: u64,
+    //A subsystem that ran this long before crashing starts over from initial_backoff_ms
+    pub(crate) stable_after_secs: u64,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self { initial_backoff_ms: 100, max_backoff_ms: 30000, stable_after_secs: 60 }
+    }
+}
+
+impl SupervisorConfig {
+    //Wait before the restart following the given number of crashes in a row, at least 1
+    pub fn backoff(&self, crashes: u32) -> Duration {
+        let factor = 1u64.checked_shl(crashes.saturating_sub(1)).unwrap_or(u64::MAX);
+        Duration::from_millis(self.initial_backoff_ms.saturating_mul(factor).min(self.max_backoff_ms.max(self.initial_backoff_ms)))
+    }
+}
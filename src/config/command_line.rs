@@ -0,0 +1,90 @@
+pub const DEFAULT_CONFIG_PATH: &str = "config/patina.yaml";
+
+const USAGE: &str = "Usage: patina [--config <path>] [--set <section.key>=<value>]...\n       \
+    patina --print-default-config\n       \
+    patina export-state --out <file> [--admin-api [--token <token>]] [--config <path>] [--set <section.key>=<value>]...\n       \
+    patina import-state --in <file> [--admin-api [--token <token>]] [--config <path>] [--set <section.key>=<value>]...";
+
+//Where export-state and import-state find the sessions
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+pub enum StateTarget {
+    //The session store of a stopped broker, storage.session_directory
+    Storage,
+    //The admin API of a running broker, authorized with the token or else admin.api_token
+    AdminApi { token: Option<String> },
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+pub enum Command {
+    //Starts the broker
+    Run,
+    //Writes the sessions to a state snapshot file
+    ExportState { path: String, target: StateTarget },
+    //Adds the sessions of a state snapshot file
+    ImportState { path: String, target: StateTarget },
+    //Writes the default config with the comments of its keys to stdout
+    PrintDefaultConfig,
+}
+
+//patina [<command>] [--config <path>] [--set <section.key>=<value>]...
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+pub struct CommandLine {
+    pub config_path: String,
+    //Applied in order on top of the config file
+    pub overrides: Vec<String>,
+    pub command: Command,
+}
+
+impl CommandLine {
+    pub fn parse(args: impl IntoIterator<Item=String>) -> Result<Self, String> {
+        let mut command_line = Self { config_path: String::from(DEFAULT_CONFIG_PATH), overrides: vec![], command: Command::Run };
+        let mut args = args.into_iter().peekable();
+        let command_name = args.next_if(|arg| !arg.starts_with("--"));
+        let (mut out, mut input, mut admin_api, mut token, mut print_default_config) = (None, None, false, None, false);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--config" => {
+                    command_line.config_path = args.next().ok_or_else(|| String::from("--config needs a path"))?;
+                }
+                "--set" => {
+                    command_line.overrides.push(args.next().ok_or_else(|| String::from("--set needs a <section.key>=<value>"))?);
+                }
+                "--out" => { out = Some(args.next().ok_or_else(|| String::from("--out needs a path"))?); }
+                "--in" => { input = Some(args.next().ok_or_else(|| String::from("--in needs a path"))?); }
+                "--admin-api" => { admin_api = true; }
+                "--token" => { token = Some(args.next().ok_or_else(|| String::from("--token needs a token"))?); }
+                "--print-default-config" => { print_default_config = true; }
+                _ => { return Err(format!("Unknown argument {:?}. {}", arg, USAGE)); }
+            }
+        }
+        if print_default_config {
+            if command_name.is_some() || out.is_some() || input.is_some() || admin_api || token.is_some() {
+                return Err(format!("--print-default-config doesn't go with a command. {}", USAGE));
+            }
+            command_line.command = Command::PrintDefaultConfig;
+            return Ok(command_line);
+        }
+        if token.is_some() && !admin_api {
+            return Err(String::from("--token goes with --admin-api"));
+        }
+        let target = match admin_api {
+            true => { StateTarget::AdminApi { token } }
+            false => { StateTarget::Storage }
+        };
+        command_line.command = match (command_name.as_deref(), out, input) {
+            (None, None, None) if !admin_api => { Command::Run }
+            (Some("export-state"), Some(path), None) => { Command::ExportState { path, target } }
+            (Some("import-state"), None, Some(path)) => { Command::ImportState { path, target } }
+            (Some("export-state"), _, _) => { return Err(format!("export-state needs --out <file> and no --in. {}", USAGE)); }
+            (Some("import-state"), _, _) => { return Err(format!("import-state needs --in <file> and no --out. {}", USAGE)); }
+            (Some(command_name), _, _) => { return Err(format!("Unknown command {:?}. {}", command_name, USAGE)); }
+            (None, _, _) => { return Err(format!("--out, --in and --admin-api go with export-state or import-state. {}", USAGE)); }
+        };
+        return Ok(command_line);
+    }
+}
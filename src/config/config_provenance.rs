@@ -0,0 +1,154 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+use serde_json::Value;
+use serde_yaml::Mapping;
+
+//Keys whose value is never shown, only whether it is set
+const SECRET_KEYS: [&str; 1] = ["admin.api_token"];
+//Maps whose values are all secrets
+const SECRET_MAPS: [&str; 1] = ["auth.users"];
+//Lists of objects whose field is a secret
+const SECRET_LIST_FIELDS: [(&str, &str); 1] = [("admin.tokens", "token")];
+const REDACTED: &str = "<redacted>";
+
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigSource {
+    Default,
+    File,
+    Cli,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct ConfigValue {
+    pub value: Value,
+    pub source: ConfigSource,
+}
+
+//Dotted keys set by the config file and by --set overrides, everything else is a default
+#[derive(Debug, Clone, Default)]
+pub struct ConfigProvenance {
+    file_keys: BTreeSet<String>,
+    cli_keys: BTreeSet<String>,
+}
+
+impl ConfigProvenance {
+    pub fn source(&self, key: &str) -> ConfigSource {
+        if covers(&self.cli_keys, key) {
+            return ConfigSource::Cli;
+        }
+        if covers(&self.file_keys, key) {
+            return ConfigSource::File;
+        }
+        return ConfigSource::Default;
+    }
+
+    //Every leaf of the serialized config by dotted key, with secrets redacted
+    pub fn resolve(&self, config: Value) -> BTreeMap<String, ConfigValue> {
+        let mut values = BTreeMap::new();
+        flatten_json(String::new(), config, &mut values);
+        return values.into_iter()
+            .map(|(key, value)| {
+                let value = match is_secret(&key) && !value.is_null() {
+                    true => { Value::from(REDACTED) }
+                    false => { redact_list_fields(&key, value) }
+                };
+                let source = self.source(&key);
+                (key, ConfigValue { value, source })
+            })
+            .collect();
+    }
+
+    pub fn new(file: &Mapping, cli_keys: BTreeSet<String>) -> Self {
+        let mut file_keys = BTreeSet::new();
+        flatten_yaml(String::new(), file, &mut file_keys);
+        Self { file_keys, cli_keys }
+    }
+}
+
+fn is_secret(key: &str) -> bool {
+    SECRET_KEYS.contains(&key) || SECRET_MAPS.iter().any(|map_key| key.starts_with(&format!("{}.", map_key)))
+}
+
+fn redact_list_fields(key: &str, mut value: Value) -> Value {
+    for (list_key, field) in SECRET_LIST_FIELDS {
+        if key != list_key {
+            continue;
+        }
+        if let Value::Array(items) = &mut value {
+            for item in items.iter_mut().filter_map(Value::as_object_mut) {
+                if item.contains_key(field) {
+                    item.insert(field.to_string(), Value::from(REDACTED));
+                }
+            }
+        }
+    }
+    value
+}
+
+//"section.key=value" with a YAML value, so numbers, booleans and lists keep their type
+pub fn apply_override(config: &mut Mapping, assignment: &str) -> Result<String, String> {
+    let (key, value) = assignment.split_once('=')
+        .ok_or_else(|| format!("Override {:?} is not key=value", assignment))?;
+    if key.is_empty() || key.split('.').any(|segment| segment.is_empty()) {
+        return Err(format!("Invalid override key {:?}", key));
+    }
+    let value: serde_yaml::Value = serde_yaml::from_str(value)
+        .map_err(|err| format!("Invalid override value for {}. {}", key, err))?;
+    let mut segments: Vec<&str> = key.split('.').collect();
+    let last = segments.pop().unwrap();
+    let mut mapping = config;
+    for segment in segments {
+        let entry = mapping.entry(serde_yaml::Value::from(segment)).or_insert_with(|| serde_yaml::Value::Mapping(Mapping::new()));
+        if !entry.is_mapping() {
+            *entry = serde_yaml::Value::Mapping(Mapping::new());
+        }
+        mapping = entry.as_mapping_mut().unwrap();
+    }
+    mapping.insert(serde_yaml::Value::from(last), value);
+    return Ok(key.to_string());
+}
+
+//A key covers the values under it, and a value covers the keys set below it (a map given entry by entry)
+fn covers(keys: &BTreeSet<String>, key: &str) -> bool {
+    keys.iter().any(|set_key| set_key == key || key.starts_with(&format!("{}.", set_key)) || set_key.starts_with(&format!("{}.", key)))
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    match prefix.is_empty() {
+        true => { key.to_string() }
+        false => { format!("{}.{}", prefix, key) }
+    }
+}
+
+fn flatten_json(prefix: String, value: Value, values: &mut BTreeMap<String, Value>) {
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            for (key, value) in object {
+                flatten_json(join(&prefix, &key), value, values);
+            }
+        }
+        value => { values.insert(prefix, value); }
+    }
+}
+
+fn flatten_yaml(prefix: String, mapping: &Mapping, keys: &mut BTreeSet<String>) {
+    for (key, value) in mapping {
+        let key = match key {
+            serde_yaml::Value::String(key) => { key.clone() }
+            key => { serde_yaml::to_string(key).unwrap_or_default().trim().to_string() }
+        };
+        let key = join(&prefix, &key);
+        match value {
+            serde_yaml::Value::Mapping(mapping) if !mapping.is_empty() => { flatten_yaml(key, mapping, keys); }
+            _ => { keys.insert(key); }
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use serde_yaml::{Mapping, Value};
+
+use crate::config::broker_config::{BrokerConfig, PROTOCOL_MAXIMUM_PACKET_SIZE};
+
+include!(concat!(env!("OUT_DIR"), "/config_schema.rs"));
+
+const ROOT_STRUCT: &str = "BrokerConfig";
+//Collections whose values are checked against the struct of their type
+const CONTAINERS: [&str; 4] = ["Option<", "Vec<", "BTreeMap<String, ", "HashMap<String, "];
+
+fn fields_of(struct_name: &str) -> impl Iterator<Item=&'static (&'static str, &'static str, &'static str, &'static str)> + '_ {
+    CONFIG_FIELDS.iter().filter(move |(field_struct, _, _, _)| *field_struct == struct_name)
+}
+
+//The comment of the struct or enum of a field without one of its own
+fn type_comment(field_type: &str) -> &'static str {
+    let type_name = field_type.strip_prefix("Option<").and_then(|rest| rest.strip_suffix('>')).unwrap_or(field_type);
+    CONFIG_STRUCTS.iter().chain(CONFIG_ENUMS.iter())
+        .find(|(name, _)| *name == type_name)
+        .map_or("", |(_, comment)| comment)
+}
+
+//The config struct a field holds, directly or in a collection. None for scalars, enums and collections of them
+fn struct_of(field_type: &str) -> Option<(&str, bool)> {
+    let (inner, in_container) = CONTAINERS.iter()
+        .find_map(|container| field_type.strip_prefix(container).and_then(|rest| rest.strip_suffix('>')).map(|inner| (inner, *container != "Option<")))
+        .unwrap_or((field_type, false));
+    CONFIG_STRUCTS.iter().any(|(name, _)| *name == inner).then_some((inner, in_container))
+}
+
+//Keys the config structs don't have, by dotted key with the closest known key. They would be silently ignored
+pub fn unknown_keys(config: &Mapping) -> Vec<String> {
+    let mut problems = vec![];
+    check_keys(ROOT_STRUCT, "", config, &mut problems);
+    problems
+}
+
+fn check_keys(struct_name: &str, prefix: &str, mapping: &Mapping, problems: &mut Vec<String>) {
+    for (key, value) in mapping {
+        let key = match key {
+            Value::String(key) => { key.clone() }
+            key => { serde_yaml::to_string(key).unwrap_or_default().trim().to_string() }
+        };
+        let dotted_key = join(prefix, &key);
+        let Some((_, _, field_type, _)) = fields_of(struct_name).find(|(_, name, _, _)| *name == key) else {
+            let suggestion = closest(&key, fields_of(struct_name).map(|(_, name, _, _)| *name))
+                .map(|name| format!(", did you mean {}?", join(prefix, name)))
+                .unwrap_or_default();
+            problems.push(format!("Unknown key {}{}", dotted_key, suggestion));
+            continue;
+        };
+        match (struct_of(field_type), value) {
+            (Some((field_struct, false)), Value::Mapping(mapping)) => { check_keys(field_struct, &dotted_key, mapping, problems); }
+            (Some((field_struct, true)), Value::Sequence(items)) => {
+                for (index, item) in items.iter().enumerate() {
+                    if let Value::Mapping(mapping) = item {
+                        check_keys(field_struct, &format!("{}[{}]", dotted_key, index), mapping, problems);
+                    }
+                }
+            }
+            (Some((field_struct, true)), Value::Mapping(entries)) => {
+                for (entry_key, entry) in entries {
+                    if let (Value::String(entry_key), Value::Mapping(mapping)) = (entry_key, entry) {
+                        check_keys(field_struct, &join(&dotted_key, entry_key), mapping, problems);
+                    }
+                }
+            }
+            //serde reports values of the wrong type with their key
+            _ => {}
+        }
+    }
+}
+
+//A known key a typo away from the unknown one
+fn closest<'a>(key: &str, known_keys: impl Iterator<Item=&'a str>) -> Option<&'a str> {
+    let max_distance = (key.chars().count() / 3).max(2);
+    known_keys
+        .map(|known_key| (edit_distance(key, known_key), known_key))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, known_key)| known_key)
+}
+
+//Levenshtein distance
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + (a_char != *b_char) as usize;
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+//Values of the right type that the broker can't work with
+pub fn invalid_values(config: &BrokerConfig) -> Vec<String> {
+    let checks = [
+        (config.gateway.mqtt_sn.port != 0, format!("gateway.mqtt_sn.port must be 1-65535, not {}", config.gateway.mqtt_sn.port)),
+        (config.gateway.coap.port != 0, format!("gateway.coap.port must be 1-65535, not {}", config.gateway.coap.port)),
+        ((1..=PROTOCOL_MAXIMUM_PACKET_SIZE).contains(&config.packet.maximum_packet_size),
+         format!("packet.maximum_packet_size must be 1-{}, not {}", PROTOCOL_MAXIMUM_PACKET_SIZE, config.packet.maximum_packet_size)),
+        (config.keep_alive.grace_factor >= 1.0, format!("keep_alive.grace_factor must be at least 1, not {}", config.keep_alive.grace_factor)),
+        (config.dispatch.max_attempts >= 1, String::from("dispatch.max_attempts must be at least 1, not 0")),
+        (config.dispatch.inbound_capacity >= 1, String::from("dispatch.inbound_capacity must be at least 1, not 0")),
+        ((0.0..=1.0).contains(&config.congestion.drop_probability), format!("congestion.drop_probability must be 0-1, not {}", config.congestion.drop_probability)),
+        (config.congestion.retry_after_min_secs <= config.congestion.retry_after_max_secs,
+         format!("congestion.retry_after_min_secs must be at most congestion.retry_after_max_secs ({}), not {}", config.congestion.retry_after_max_secs, config.congestion.retry_after_min_secs)),
+        (config.pacing.accept_per_sec == 0 || config.pacing.accept_burst >= 1, String::from("pacing.accept_burst must be at least 1, not 0")),
+        (config.pacing.connack_per_sec == 0 || config.pacing.connack_burst >= 1, String::from("pacing.connack_burst must be at least 1, not 0")),
+        (config.subnet_stats.ipv4_prefix_len <= 32, format!("subnet_stats.ipv4_prefix_len must be 0-32, not {}", config.subnet_stats.ipv4_prefix_len)),
+        (config.subnet_stats.ipv6_prefix_len <= 128, format!("subnet_stats.ipv6_prefix_len must be 0-128, not {}", config.subnet_stats.ipv6_prefix_len)),
+        (config.auth.lockout_base_secs <= config.auth.lockout_max_secs,
+         format!("auth.lockout_base_secs must be at most auth.lockout_max_secs ({}), not {}", config.auth.lockout_max_secs, config.auth.lockout_base_secs)),
+        ((1..=config.debug_capture.max_duration_secs).contains(&config.debug_capture.default_duration_secs),
+         format!("debug_capture.default_duration_secs must be 1-{} (debug_capture.max_duration_secs), not {}", config.debug_capture.max_duration_secs, config.debug_capture.default_duration_secs)),
+        ((1..=config.profiling.max_seconds).contains(&config.profiling.default_seconds),
+         format!("profiling.default_seconds must be 1-{} (profiling.max_seconds), not {}", config.profiling.max_seconds, config.profiling.default_seconds)),
+        (config.profiling.frequency >= 1, format!("profiling.frequency must be at least 1, not {}", config.profiling.frequency)),
+    ];
+    let mut problems: Vec<String> = checks.into_iter()
+        .filter(|(valid, _)| !valid)
+        .map(|(_, problem)| problem)
+        .collect();
+    let quota = &config.quota;
+    if let Some(profile) = quota.default_profile.as_ref().filter(|profile| !quota.profiles.contains_key(*profile)) {
+        problems.push(format!("quota.default_profile {:?} is not in quota.profiles", profile));
+    }
+    let mut usernames: Vec<(&String, &String)> = quota.usernames.iter().filter(|(_, profile)| !quota.profiles.contains_key(*profile)).collect();
+    usernames.sort();
+    for (username, profile) in usernames {
+        problems.push(format!("quota.usernames.{} {:?} is not in quota.profiles", username, profile));
+    }
+    problems
+}
+
+//The default config as YAML, every key under the comment of its field or struct
+pub fn default_config_yaml() -> String {
+    let mut yaml = format!("# Default config of patina {}, generated from its config structs\n", env!("CARGO_PKG_VERSION"));
+    if let Ok(Value::Mapping(config)) = serde_yaml::to_value(BrokerConfig::default()) {
+        write_mapping(ROOT_STRUCT, &config, 0, &mut yaml);
+    }
+    yaml
+}
+
+fn write_mapping(struct_name: &str, mapping: &Mapping, depth: usize, yaml: &mut String) {
+    let indent = "  ".repeat(depth);
+    for (key, value) in mapping {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        let (field_type, field_comment) = fields_of(struct_name)
+            .find(|(_, name, _, _)| *name == key)
+            .map_or(("", ""), |(_, _, field_type, comment)| (*field_type, *comment));
+        let field_struct = struct_of(field_type).filter(|(_, in_container)| !in_container).map(|(field_struct, _)| field_struct);
+        let comment = match field_comment {
+            "" => { type_comment(field_type) }
+            comment => { comment }
+        };
+        if !comment.is_empty() {
+            yaml.push_str(&format!("{}# {}\n", indent, comment));
+        }
+        match (value, field_struct) {
+            (Value::Mapping(mapping), Some(field_struct)) if !mapping.is_empty() => {
+                yaml.push_str(&format!("{}{}:\n", indent, key));
+                write_mapping(field_struct, mapping, depth + 1, yaml);
+            }
+            //JSON is YAML on one line
+            (value, _) => { yaml.push_str(&format!("{}{}: {}\n", indent, key, serde_json::to_string(value).unwrap_or_default())); }
+        }
+    }
+}
+
+fn join(prefix: &str, key: &str) -> String {
+    match prefix.is_empty() {
+        true => { key.to_string() }
+        false => { format!("{}.{}", prefix, key) }
+    }
+}
@@ -0,0 +1,5 @@
+pub mod broker_config;
+pub mod command_line;
+pub mod config_provenance;
+pub mod config_schema;
+pub mod port_check;
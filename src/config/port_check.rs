@@ -0,0 +1,144 @@
+use std::fmt;
+use std::io::ErrorKind;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, TcpListener, UdpSocket};
+
+use log::trace;
+
+use crate::config::broker_config::BrokerConfig;
+
+pub const MQTT_LISTENER_ADDRESS: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 1883));
+pub const ADMIN_API_ADDRESS: SocketAddr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 9000));
+
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+impl fmt::Display for Transport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Transport::Tcp => { write!(f, "tcp") }
+            Transport::Udp => { write!(f, "udp") }
+        }
+    }
+}
+
+//An address a subsystem of the broker binds once the supervisor starts it
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+pub struct Endpoint {
+    pub name: &'static str,
+    pub transport: Transport,
+    pub address: SocketAddr,
+}
+
+impl Endpoint {
+    //The same port of the same transport, on the same address or on every address
+    fn overlaps(&self, other: &Endpoint) -> bool {
+        self.transport == other.transport
+            && self.address.port() == other.address.port()
+            && (self.address.ip() == other.address.ip() || self.address.ip().is_unspecified() || other.address.ip().is_unspecified())
+    }
+
+    //Binds and releases the address right away
+    fn try_bind(&self) -> std::io::Result<()> {
+        match self.transport {
+            Transport::Tcp => { TcpListener::bind(self.address).map(|_| ()) }
+            Transport::Udp => { UdpSocket::bind(self.address).map(|_| ()) }
+        }
+    }
+}
+
+//Every endpoint the build and config enable
+pub fn configured_endpoints(config: &BrokerConfig) -> Vec<Endpoint> {
+    let mut endpoints = vec![Endpoint { name: "mqtt", transport: Transport::Tcp, address: MQTT_LISTENER_ADDRESS }];
+    if cfg!(feature = "admin-api") {
+        endpoints.push(Endpoint { name: "admin-api", transport: Transport::Tcp, address: ADMIN_API_ADDRESS });
+    }
+    if cfg!(feature = "mqtt-sn") && config.gateway.mqtt_sn.enabled {
+        endpoints.push(Endpoint { name: "mqtt-sn", transport: Transport::Udp, address: SocketAddr::from(([0, 0, 0, 0], config.gateway.mqtt_sn.port)) });
+    }
+    if cfg!(feature = "coap") && config.gateway.coap.enabled {
+        endpoints.push(Endpoint { name: "coap", transport: Transport::Udp, address: SocketAddr::from(([0, 0, 0, 0], config.gateway.coap.port)) });
+    }
+    endpoints
+}
+
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+pub enum ConflictKind {
+    //Two endpoints of the config on the same port
+    Duplicate,
+    //Another process holds the port
+    InUse,
+    //Ports below 1024 need privileges
+    PermissionDenied,
+    //The address isn't one of the host, or binding failed otherwise
+    Unavailable,
+}
+
+impl ConflictKind {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ConflictKind::Duplicate => { 3 }
+            ConflictKind::InUse => { 4 }
+            ConflictKind::PermissionDenied => { 5 }
+            ConflictKind::Unavailable => { 6 }
+        }
+    }
+}
+
+impl fmt::Display for ConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConflictKind::Duplicate => { write!(f, "duplicate") }
+            ConflictKind::InUse => { write!(f, "in-use") }
+            ConflictKind::PermissionDenied => { write!(f, "permission-denied") }
+            ConflictKind::Unavailable => { write!(f, "unavailable") }
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+pub struct PortConflict {
+    pub endpoint: Endpoint,
+    pub kind: ConflictKind,
+    pub detail: String,
+}
+
+//One line of key=value pairs, for log scrapers as much as for people
+impl fmt::Display for PortConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "port_conflict endpoint={} transport={} address={} reason={} detail={:?}",
+               self.endpoint.name, self.endpoint.transport, self.endpoint.address, self.kind, self.detail)
+    }
+}
+
+//Every endpoint that can't be bound, in the order of the endpoints. An endpoint overlapping
+//an earlier one isn't bound, it would fail once the earlier one is listening.
+pub fn check_ports(endpoints: &[Endpoint]) -> Vec<PortConflict> {
+    trace!("check_ports");
+    let mut conflicts = vec![];
+    for (index, endpoint) in endpoints.iter().enumerate() {
+        if let Some(earlier) = endpoints[..index].iter().find(|earlier| earlier.overlaps(endpoint)) {
+            conflicts.push(PortConflict { endpoint: endpoint.clone(), kind: ConflictKind::Duplicate, detail: format!("same port as {} on {}", earlier.name, earlier.address) });
+            continue;
+        }
+        if let Err(err) = endpoint.try_bind() {
+            let kind = match err.kind() {
+                ErrorKind::AddrInUse => { ConflictKind::InUse }
+                ErrorKind::PermissionDenied => { ConflictKind::PermissionDenied }
+                _ => { ConflictKind::Unavailable }
+            };
+            conflicts.push(PortConflict { endpoint: endpoint.clone(), kind, detail: err.to_string() });
+        }
+    }
+    conflicts
+}
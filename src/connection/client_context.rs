@@ -0,0 +1,53 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use crate::error::{PatinaError, PatinaResult};
+use crate::model::control_packet::ControlPacket;
+
+//Who sent a packet to the broker, travelling with it from the reader of the connection to the handlers.
+//The reader fills in what the CONNECT told it, the dispatcher adds the client id once per packet:
+//the broker assigns client ids and a connection that was taken over no longer has one.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct ClientContext {
+    pub socket: SocketAddr,
+    pub client_id: Option<String>,
+    pub protocol_version: Option<u8>,
+    //The username of the CONNECT, the client was authenticated with it
+    pub username: Option<String>,
+    //When the reader got the packet, before it waited in the channel to the broker
+    pub received_at: Instant,
+    //How long the packet may wait for the broker, None for as long as it takes
+    pub max_wait: Option<Duration>,
+}
+
+impl ClientContext {
+    //Handlers of a connected client's packets can't go on without it
+    pub fn client_id(&self) -> PatinaResult<&String> {
+        self.client_id.as_ref().ok_or(PatinaError::UnknownClient(self.socket))
+    }
+
+    //What the CONNECT of the connection tells about the client
+    pub fn connected(&mut self, connect_packet: &ControlPacket) {
+        self.protocol_version = connect_packet.variable_header().protocol_version_opt();
+        self.username = connect_packet.payload_opt().and_then(|payload| payload.username()).cloned();
+    }
+
+    //The context of a packet the reader just got
+    pub fn next(&self) -> Self {
+        Self { received_at: Instant::now(), ..self.clone() }
+    }
+
+    //Past it the broker got to the packet too late for work only a connected client needs
+    pub fn deadline(&self) -> Option<Instant> {
+        self.max_wait.map(|max_wait| self.received_at + max_wait)
+    }
+
+    pub fn with_client_id(self, client_id: Option<String>) -> Self {
+        Self { client_id, ..self }
+    }
+
+    pub fn new(socket: SocketAddr) -> Self {
+        Self { socket, client_id: None, protocol_version: None, username: None, received_at: Instant::now(), max_wait: None }
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::Utc;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
+
+use crate::config::broker_config::BrokerConfig;
+use crate::config::port_check::{configured_endpoints, Endpoint};
+
+pub const MQTT_LISTENER: &str = "mqtt";
+pub const MQTT_SN_LISTENER: &str = "mqtt-sn";
+
+//Endpoints that take connections, the admin API and the CoAP bridge don't
+const CONNECTION_ENDPOINTS: [&str; 2] = [MQTT_LISTENER, MQTT_SN_LISTENER];
+
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListenerMode {
+    Accepting,
+    //No new connections, the open ones go on until they end
+    Draining,
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct ListenerStatus {
+    pub name: String,
+    pub transport: String,
+    pub address: String,
+    pub mode: ListenerMode,
+    //Epoch millis of the last mode change, the start of the broker until then
+    pub since: i64,
+    //TCP connections, or MQTT-SN sensors
+    pub open_connections: u64,
+}
+
+//Exported with the metrics, by listener name
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct ListenerMetrics {
+    draining: BTreeMap<String, u8>,
+    open_connections: BTreeMap<String, u64>,
+}
+
+#[derive(Debug)]
+struct Listener {
+    endpoint: Endpoint,
+    mode: watch::Sender<ListenerMode>,
+    since: Mutex<i64>,
+    open_connections: AtomicU64,
+}
+
+//The mode of every listener taking client connections. A draining listener stops taking new connections while
+//the open ones continue, so a listener can be rebound with new certificates or an interface taken out of service.
+#[derive(Debug)]
+pub struct Listeners {
+    listeners: BTreeMap<&'static str, Listener>,
+}
+
+impl Listeners {
+    //Follows the mode of a listener, None for a listener the config doesn't enable
+    pub fn watch(&self, name: &str) -> Option<watch::Receiver<ListenerMode>> {
+        self.listeners.get(name).map(|listener| listener.mode.subscribe())
+    }
+
+    //None for a listener the config doesn't enable. Setting the mode a listener is in changes nothing
+    pub fn set_mode(&self, name: &str, mode: ListenerMode) -> Option<ListenerStatus> {
+        let listener = self.listeners.get(name)?;
+        if listener.mode.send_if_modified(|current| std::mem::replace(current, mode) != mode) {
+            info!("Listener {} on {} is {:?}", name, listener.endpoint.address, mode);
+            *listener.since.lock().unwrap() = Utc::now().timestamp_millis();
+        }
+        Some(Self::status_of(listener))
+    }
+
+    pub fn opened(&self, name: &str) {
+        if let Some(listener) = self.listeners.get(name) {
+            listener.open_connections.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn closed(&self, name: &str) {
+        if let Some(listener) = self.listeners.get(name) {
+            let _ = listener.open_connections.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |open| open.checked_sub(1));
+        }
+    }
+
+    pub fn statuses(&self) -> Vec<ListenerStatus> {
+        self.listeners.values().map(Self::status_of).collect()
+    }
+
+    fn status_of(listener: &Listener) -> ListenerStatus {
+        ListenerStatus {
+            name: listener.endpoint.name.to_string(),
+            transport: listener.endpoint.transport.to_string(),
+            address: listener.endpoint.address.to_string(),
+            mode: *listener.mode.borrow(),
+            since: *listener.since.lock().unwrap(),
+            open_connections: listener.open_connections.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn metrics(&self) -> ListenerMetrics {
+        let mut metrics = ListenerMetrics::default();
+        for status in self.statuses() {
+            metrics.draining.insert(status.name.clone(), (status.mode == ListenerMode::Draining) as u8);
+            metrics.open_connections.insert(status.name, status.open_connections);
+        }
+        metrics
+    }
+
+    pub fn new(config: &BrokerConfig) -> Self {
+        let now = Utc::now().timestamp_millis();
+        let listeners = configured_endpoints(config).into_iter()
+            .filter(|endpoint| CONNECTION_ENDPOINTS.contains(&endpoint.name))
+            .map(|endpoint| (endpoint.name, Listener {
+                endpoint,
+                mode: watch::Sender::new(ListenerMode::Accepting),
+                since: Mutex::new(now),
+                open_connections: AtomicU64::new(0),
+            }))
+            .collect();
+        Self { listeners }
+    }
+}
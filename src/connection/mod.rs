@@ -1,2 +1,9 @@
 pub mod tx_connection_handler;
-pub mod rx_connection_handler;
\ No newline at end of file
+pub mod rx_connection_handler;
+pub mod virtual_endpoint;
+pub mod client_context;
+pub mod socket_options;
+pub mod subnet_stats;
+pub mod topic_aliases;
+pub mod pending_handshakes;
+pub mod listeners;
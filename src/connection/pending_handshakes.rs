@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::{debug, trace};
+use metered::HitCount;
+use serde::Serialize;
+
+use crate::config::broker_config::HandshakeConfig;
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct PendingHandshakeMetrics {
+    //Connections closed right after accept, their address had handshake.max_pending_per_ip pending already
+    pub(crate) dropped: HitCount,
+    //Connections closed without a CONNECT within handshake.connect_timeout_secs
+    pub(crate) timed_out: HitCount,
+}
+
+//Exported with the metrics
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct HandshakeMetrics {
+    //Connections accepted that haven't sent their CONNECT yet
+    pending: u64,
+    //Addresses with pending connections
+    pending_addresses: usize,
+    dropped: u64,
+    timed_out: u64,
+}
+
+//Connections accepted but not CONNECTed yet, by client address. An address can only hold so many of them,
+//so a flood of connections that never speak MQTT can't take up every socket and reader of the broker.
+#[derive(Debug)]
+pub struct PendingHandshakes {
+    config: HandshakeConfig,
+    by_address: Mutex<HashMap<IpAddr, usize>>,
+    pub(crate) metrics: PendingHandshakeMetrics,
+}
+
+impl PendingHandshakes {
+    //Counts a new connection of the address as pending, false when the address is at its limit
+    pub fn start(&self, address: IpAddr) -> bool {
+        trace!("PendingHandshakes::start");
+        let address = address.to_canonical();
+        let mut by_address = self.by_address.lock().unwrap();
+        let pending = by_address.entry(address).or_insert(0);
+        if self.config.max_pending_per_ip > 0 && *pending >= self.config.max_pending_per_ip {
+            debug!("Dropping a connection of {}, {} are pending already", address, pending);
+            self.metrics.dropped.incr();
+            return false;
+        }
+        *pending += 1;
+        true
+    }
+
+    //The connection sent its CONNECT or closed before
+    pub fn finish(&self, address: IpAddr) {
+        trace!("PendingHandshakes::finish");
+        let address = address.to_canonical();
+        let mut by_address = self.by_address.lock().unwrap();
+        if let Some(pending) = by_address.get_mut(&address) {
+            *pending -= 1;
+            if *pending == 0 {
+                by_address.remove(&address);
+            }
+        }
+    }
+
+    pub fn timed_out(&self) {
+        self.metrics.timed_out.incr();
+    }
+
+    pub fn pending(&self) -> usize {
+        self.by_address.lock().unwrap().values().sum()
+    }
+
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        match self.config.connect_timeout_secs {
+            0 => { None }
+            connect_timeout_secs => { Some(Duration::from_secs(connect_timeout_secs)) }
+        }
+    }
+
+    pub fn metrics(&self) -> HandshakeMetrics {
+        HandshakeMetrics {
+            pending: self.pending() as u64,
+            pending_addresses: self.by_address.lock().unwrap().len(),
+            dropped: self.metrics.dropped.0.get(),
+            timed_out: self.metrics.timed_out.0.get(),
+        }
+    }
+
+    pub fn new(config: HandshakeConfig) -> Self {
+        Self { config, by_address: Mutex::new(HashMap::new()), metrics: PendingHandshakeMetrics::default() }
+    }
+}
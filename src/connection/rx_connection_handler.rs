@@ -1,91 +1,288 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use dashmap::DashMap;
 use log::{debug, error, info, trace, warn};
 use metered::{*};
+use serde::Serialize;
 use tokio::io::BufReader;
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpListener;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::Mutex;
 
+use crate::broker::connection_close::ConnectionClose;
+use crate::broker::utils::send_packet;
+use crate::config::broker_config::{BrokerConfig, DispatchConfig, KeepAliveConfig, TcpConfig};
+use crate::config::port_check::MQTT_LISTENER_ADDRESS;
+use crate::connection::client_context::ClientContext;
+use crate::connection::listeners::{ListenerMode, Listeners, MQTT_LISTENER};
+use crate::connection::pending_handshakes::PendingHandshakes;
+use crate::connection::socket_options::apply_socket_options;
+use crate::connection::subnet_stats::{CloseReason, SubnetStats};
+use crate::connection::topic_aliases::TopicAliases;
+use crate::limits::connection_pacing::ConnectionPacing;
 use crate::model::control_packet::ControlPacket;
-use crate::serdes::deserializer::error::ReadError;
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::reason_code::ReasonCode;
+use crate::serdes::decode_pool::{DecodePool, Decoded, PacketRate};
+use crate::serdes::deserializer::error::{DecodeError, ReadError};
 use crate::serdes::mqtt_decoder::MqttDecoder;
+use crate::serdes::read_buffer::ReadBuffer;
+
+//Packets of a connection waiting for their decoding before they go to the broker
+const FORWARDER_CAPACITY: usize = 1024;
 
 #[derive(Debug)]
 pub struct RxConnectionHandler {
     pub(crate) metrics: RxConnectionHandlerMetrics,
     pub(crate) rx_client_handler: Arc<RxClientHandler>,
+    pub(crate) listeners: Arc<Listeners>,
+    connection_close: Arc<ConnectionClose>,
+    tcp: TcpConfig,
 }
 
 #[metered(registry = RxConnectionHandlerMetrics)]
 impl RxConnectionHandler {
-    //#[tokio::main(flavor = "multi_thread")]
-    #[tokio::main(flavor = "multi_thread", worker_threads = 8)]
-    //#[tokio::main(flavor = "current_thread")]
-    pub async fn handle_incoming_connections(&self, listener2broker: Arc<Sender<(SocketAddr, ControlPacket)>>, stream_repository: Arc<DashMap<SocketAddr, OwnedWriteHalf>>) -> Result<(), Box<dyn std::error::Error>> {
+    //A draining listener is closed, so the port can be bound again, while the connections it took go on
+    pub async fn handle_incoming_connections(&self, listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>, stream_repository: Arc<DashMap<SocketAddr, OwnedWriteHalf>>) -> Result<(), Box<dyn std::error::Error>> {
         trace!("MQTTListener::process");
-        info!("Starting TCP Listener on port {}", 1883);
-        let address = SocketAddr::from(([0, 0, 0, 0], 1883));
-        let listener_instance = TcpListener::bind(address).await
-            .unwrap_or_else(|error| {
-                panic!("Cannot bind TCP Listener to {:?}. {:?}", address, error);
-            });
+        let address = MQTT_LISTENER_ADDRESS;
+        let mut mode = self.listeners.watch(MQTT_LISTENER).ok_or("The MQTT listener is not configured")?;
         let rx_client_handler = self.rx_client_handler.clone();
-        listener_instance.set_ttl(240);
-        info!("Spawned TcpListener listener poller");
         loop {
-            match listener_instance
-                .accept().await {
-                Ok((stream, socket)) => {
-                    info!("New connection request from {:?}", socket);
-
-                    let rx_client_handler = rx_client_handler.clone();
-                    let (in_stream, out_stream) = stream.into_split();
-                    let stream_repository = stream_repository.clone();
-                    let listener2broker = listener2broker.clone();
-                    tokio::spawn(async move {
-                        stream_repository.insert(socket, out_stream);
-                        rx_client_handler.handle_client(&socket, in_stream, listener2broker.clone()).await;
-                    });
-                }
-                Err(error) => {
-                    error!("Can't handle TCP Stream {:?}", error);
+            mode.wait_for(|mode| *mode == ListenerMode::Accepting).await?;
+            info!("Starting TCP Listener on {}", address);
+            let listener_instance = TcpListener::bind(address).await
+                .map_err(|err| format!("Cannot bind TCP Listener to {}. {}", address, err))?;
+            listener_instance.set_ttl(240);
+            info!("Spawned TcpListener listener poller");
+            loop {
+                let accepted = tokio::select! {
+                    accepted = listener_instance.accept() => { accepted }
+                    draining = mode.wait_for(|mode| *mode == ListenerMode::Draining) => {
+                        draining?;
+                        break;
+                    }
+                };
+                match accepted {
+                    Ok((stream, socket)) => {
+                        info!("New connection request from {:?}", socket);
+                        //Closed without a word, a flood isn't worth answering
+                        if !rx_client_handler.pending_handshakes.start(socket.ip()) {
+                            continue;
+                        }
+                        //While it waits, the connections behind it stay in the accept backlog
+                        rx_client_handler.accept_pacing.pace().await;
+                        if let Err(err) = apply_socket_options(&stream, &self.tcp) {
+                            warn!("Can't set socket options of {:?}: {}", socket, err);
+                        }
+
+                        let rx_client_handler = rx_client_handler.clone();
+                        let (in_stream, out_stream) = stream.into_split();
+                        let stream_repository = stream_repository.clone();
+                        let listener2broker = listener2broker.clone();
+                        let listeners = self.listeners.clone();
+                        let connection_close = self.connection_close.clone();
+                        tokio::spawn(async move {
+                            stream_repository.insert(socket, out_stream);
+                            listeners.opened(MQTT_LISTENER);
+                            match rx_client_handler.handle_client(&socket, in_stream, listener2broker.clone()).await {
+                                //No client owns the socket to close it
+                                ConnectionEnd::Unconnected => {
+                                    stream_repository.remove(&socket);
+                                }
+                                ConnectionEnd::Disconnected => {}
+                                ConnectionEnd::Dropped => {
+                                    connection_close.closed(&socket).await;
+                                    stream_repository.remove(&socket);
+                                }
+                            }
+                            listeners.closed(MQTT_LISTENER);
+                        });
+                    }
+                    Err(error) => {
+                        error!("Can't handle TCP Stream {:?}", error);
+                    }
                 }
             }
+            info!("Closed TCP Listener on {}, draining its connections", address);
         }
-
-        Ok(())
     }
 
-    pub fn new() -> Self {
-        Self { metrics: RxConnectionHandlerMetrics::default(), rx_client_handler: Arc::new(RxClientHandler::default()) }
+    pub fn new(config: Arc<BrokerConfig>, connection_close: Arc<ConnectionClose>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        let listeners = Arc::new(Listeners::new(&config));
+        Self { metrics: RxConnectionHandlerMetrics::default(), tcp: config.tcp.clone(), listeners, connection_close, rx_client_handler: Arc::new(RxClientHandler::new(config, to_listener)) }
     }
 }
 
-#[derive(Default, Debug)]
+//Why connections of clients ended: a dead device stops answering and runs into its Keep Alive,
+//a flaky network drops the TCP connection.
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct ConnectionCloseMetrics {
+    pub(crate) keep_alive_expired: HitCount,
+    pub(crate) connection_lost: HitCount,
+}
+
+//How a connection read by RxClientHandler::handle_client ended
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+pub enum ConnectionEnd {
+    //Before its CONNECT
+    Unconnected,
+    //On a DISCONNECT from either side, whose handling closes the connection
+    Disconnected,
+    //Without a DISCONNECT from the broker: the client hung up, its connection broke
+    Dropped,
+}
+
+#[derive(Debug)]
 pub struct RxClientHandler {
     pub(crate) decoder: Arc<MqttDecoder>,
+    pub(crate) decode_pool: Arc<DecodePool>,
+    pub(crate) subnet_stats: Arc<SubnetStats>,
+    pub(crate) pending_handshakes: Arc<PendingHandshakes>,
+    pub(crate) accept_pacing: Arc<ConnectionPacing>,
+    keep_alive: KeepAliveConfig,
+    dispatch: DispatchConfig,
+    topic_alias_maximum: u16,
+    to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>,
+    pub(crate) close_metrics: ConnectionCloseMetrics,
     pub(crate) metrics: RxClientHandlerMetrics,
 
 }
 
+impl RxClientHandler {
+    pub fn new(config: Arc<BrokerConfig>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>) -> Self {
+        let decoder = Arc::new(MqttDecoder::new(config.clone()));
+        let decode_pool = Arc::new(DecodePool::new(config.decode.clone(), decoder.clone()));
+        let subnet_stats = Arc::new(SubnetStats::new(config.subnet_stats.clone()));
+        let pending_handshakes = Arc::new(PendingHandshakes::new(config.handshake.clone()));
+        let accept_pacing = Arc::new(ConnectionPacing::new("accept", config.pacing.accept_per_sec, config.pacing.accept_burst, config.pacing.jitter_ms));
+        Self { decoder, decode_pool, subnet_stats, pending_handshakes, accept_pacing, keep_alive: config.keep_alive.clone(), dispatch: config.dispatch.clone(), topic_alias_maximum: config.packet.topic_alias_maximum, to_listener, close_metrics: ConnectionCloseMetrics::default(), metrics: RxClientHandlerMetrics::default() }
+    }
+}
+
 #[metered(registry = RxClientHandlerMetrics)]
 impl RxClientHandler {
 
     #[measure([HitCount, InFlight, ResponseTime])]
-    async fn handle_client(&self, socket: &SocketAddr,mut in_stream: OwnedReadHalf, listener2broker: Arc<Sender<(SocketAddr, ControlPacket)>>) {
+    pub(crate) async fn handle_client(&self, socket: &SocketAddr,mut in_stream: OwnedReadHalf, listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>) -> ConnectionEnd {
         debug!("START - handle_client({})", socket);
         let socket = socket.clone();
         let decoder = self.decoder.clone();
+        let mut buffer = ReadBuffer::default();
+        //Known once the CONNECT arrived
+        let mut keep_alive_deadline: Option<Duration> = None;
+        //With the decode pool every packet goes to the broker through the forwarder, in reading order
+        let forwarder = match self.decode_pool.is_enabled() {
+            true => { Some(Self::spawn_forwarder(socket, listener2broker.clone(), self.to_listener.clone(), TopicAliases::new(self.topic_alias_maximum))) }
+            false => { None }
+        };
+        let mut topic_aliases = TopicAliases::new(self.topic_alias_maximum);
+        let mut packet_rate = PacketRate::default();
+        let mut context = ClientContext::new(socket);
+        context.max_wait = self.dispatch.packet_deadline(None);
+        self.subnet_stats.opened(socket.ip());
+        let mut close_reason = CloseReason::Closed;
+        let mut disconnected = false;
+        //Until the CONNECT is read, counted in pending_handshakes by the listener
+        let mut pending = true;
         loop {
-            match decoder.decode_packet(in_stream).await {
-                Ok((ret_stream, control_packet)) => {
+            let deadline = match pending {
+                true => { self.pending_handshakes.connect_timeout() }
+                false => { keep_alive_deadline }
+            };
+            let read = match deadline {
+                None => { decoder.read_frame(in_stream, &mut buffer).await }
+                Some(deadline) => {
+                    match tokio::time::timeout(deadline, decoder.read_frame(in_stream, &mut buffer)).await {
+                        Ok(read) => { read }
+                        Err(_) if pending => {
+                            info!("No CONNECT from {:?} within {:?}, closing the connection", socket, deadline);
+                            self.pending_handshakes.timed_out();
+                            break;
+                        }
+                        Err(_) => {
+                            info!("Nothing received from client {:?} for {:?}, its Keep Alive expired", socket, deadline);
+                            self.close_metrics.keep_alive_expired.incr();
+                            close_reason = CloseReason::KeepAliveExpired;
+                            send_packet(socket, &ControlPacket::disconnect(ReasonCode::KeepAliveTimeout), &self.to_listener).await;
+                            disconnected = true;
+                            break;
+                        }
+                    }
+                }
+            };
+            let fixed_header = match read {
+                Ok((ret_stream, fixed_header)) => {
                     in_stream = ret_stream;
-                    debug!("Got new Control Packet from client: {:?}", socket);
-                    match listener2broker.send((socket.clone(), control_packet)).await {
+                    fixed_header
+                }
+                Err(err) => {
+                    let disconnect_sent;
+                    (close_reason, disconnect_sent) = self.read_failed(&context, &err).await;
+                    disconnected |= disconnect_sent;
+                    break;
+                }
+            };
+            //The broker closes the connection once it handled the DISCONNECT, with the Session Expiry Interval it sets
+            if !pending && fixed_header.packet_type() == ControlPacketType::DISCONNECT {
+                disconnected = true;
+            }
+            let body = match fixed_header.remaining_length() {
+                0 => { &[][..] }
+                _ => { buffer.filled() }
+            };
+            if let Some(forwarder) = &forwarder {
+                if self.decode_pool.should_offload(&fixed_header, packet_rate.record(Instant::now())) {
+                    if forwarder.send((context.next(), self.decode_pool.submit(fixed_header, body.to_vec()))).await.is_err() {
+                        disconnected = true;
+                        break;
+                    }
+                    continue;
+                }
+            }
+            let control_packet = match decoder.decode_body(fixed_header, body) {
+                Ok(control_packet) => { control_packet }
+                Err(err) => {
+                    let disconnect_sent;
+                    (close_reason, disconnect_sent) = self.read_failed(&context, &err).await;
+                    disconnected |= disconnect_sent;
+                    break;
+                }
+            };
+            debug!("Got new Control Packet from client: {:?}", socket);
+            if control_packet.fixed_header().packet_type() == ControlPacketType::CONNECT {
+                keep_alive_deadline = self.keep_alive.deadline(control_packet.variable_header().keep_alive_opt().unwrap_or(0));
+                debug!("Keep Alive deadline of client {:?}: {:?}", socket, keep_alive_deadline);
+                context.connected(&control_packet);
+                context.max_wait = self.dispatch.packet_deadline(keep_alive_deadline);
+                if pending {
+                    pending = false;
+                    self.pending_handshakes.finish(socket.ip());
+                }
+            }
+            match &forwarder {
+                Some(forwarder) => {
+                    if forwarder.send((context.next(), DecodePool::ready(Ok(control_packet)))).await.is_err() {
+                        disconnected = true;
+                        break;
+                    }
+                }
+                None => {
+                    let control_packet = match topic_aliases.resolve(control_packet) {
+                        Ok(control_packet) => { control_packet }
+                        Err(reason_code) => {
+                            warn!("Disconnecting client {:?}: {:?}", socket, reason_code);
+                            send_packet(socket, &ControlPacket::disconnect(reason_code), &self.to_listener).await;
+                            disconnected = true;
+                            break;
+                        }
+                    };
+                    match listener2broker.send((context.next(), control_packet)).await {
                         Ok(_) => {
                             debug!("Sent message to broker");
                             Ok(())
@@ -95,22 +292,96 @@ impl RxClientHandler {
                         }
                     }.expect("panic send_to_broker");
                 }
-                Err(err) => {
-                    error!("Can't read any valid control packet from stream: {:?}", err);
-                    match err.cause() {
-                        ReadError::ConnectionError => {
-                            warn!("Connection closed for client {:?}. Going to stop incoming messages handler.", socket);
-                            break;
-                        }
-                        _ => {}
-                    }
-                    break;
-                }
-            };
+            }
+        }
+        self.subnet_stats.closed(socket.ip(), close_reason);
+        if pending {
+            self.pending_handshakes.finish(socket.ip());
         }
 
         debug!("END - handle_client({})", socket);
+        return match (pending, disconnected) {
+            (_, true) => { ConnectionEnd::Disconnected }
+            (true, false) => { ConnectionEnd::Unconnected }
+            (false, false) => { ConnectionEnd::Dropped }
+        };
     }
-}
 
+    //Why the connection ends and whether a DISCONNECT, or the CONNACK refusing the CONNECT, was sent for it
+    async fn read_failed(&self, context: &ClientContext, err: &DecodeError) -> (CloseReason, bool) {
+        let socket = context.socket;
+        error!("Can't read any valid control packet from stream: {:?}. Reason code: {:?}", err, err.reason_code());
+        if let ReadError::ConnectionError = err.cause() {
+            warn!("Connection closed for client {:?}. Going to stop incoming messages handler.", socket);
+            self.close_metrics.connection_lost.incr();
+            return (CloseReason::ConnectionLost, false);
+        }
+        if let Some(reason_code) = Self::disconnect_reason(context, err) {
+            send_packet(socket, &ControlPacket::disconnect(reason_code), &self.to_listener).await;
+            return (CloseReason::Malformed, true);
+        }
+        if let Some(reason_code) = Self::refusal_reason(context, err) {
+            info!("Refusing CONNECT of {:?}: {:?}", socket, reason_code);
+            send_packet(socket, &ControlPacket::connack(false, reason_code, vec![]), &self.to_listener).await;
+            return (CloseReason::Malformed, true);
+        }
+        return (CloseReason::Malformed, false);
+    }
+
+    //The reason code of the CONNACK refusing a CONNECT that can't be decoded, only an unsupported protocol version
+    //gets one. The writer closes the connection once it is written.
+    pub(crate) fn refusal_reason(context: &ClientContext, err: &DecodeError) -> Option<ReasonCode> {
+        match err.reason_code() {
+            ReasonCode::UnsupportedProtocolVersion if context.protocol_version.is_none() => { Some(ReasonCode::UnsupportedProtocolVersion) }
+            _ => { None }
+        }
+    }
+
+    //The reason code of the DISCONNECT closing a connection on a packet that can't be decoded. None when the
+    //connection is already gone or the CONNECT isn't read yet, a DISCONNECT can't come before the CONNACK.
+    pub(crate) fn disconnect_reason(context: &ClientContext, err: &DecodeError) -> Option<ReasonCode> {
+        match err.cause() {
+            ReadError::ConnectionError | ReadError::IOError => { None }
+            _ if context.protocol_version.is_none() => { None }
+            _ => { Some(err.reason_code()) }
+        }
+    }
 
+    //Hands the packets of a connection to the broker as their decoding completes, in reading order, with their
+    //Topic Aliases resolved. It stops at the first packet that can't be decoded or uses an invalid alias,
+    //the reader stops on its next packet.
+    fn spawn_forwarder(socket: SocketAddr, listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>, mut topic_aliases: TopicAliases) -> Sender<(ClientContext, Decoded)> {
+        let (forwarder, mut decoded_packets) = tokio::sync::mpsc::channel::<(ClientContext, Decoded)>(FORWARDER_CAPACITY);
+        tokio::spawn(async move {
+            while let Some((context, decoded)) = decoded_packets.recv().await {
+                let control_packet = match decoded.await {
+                    Ok(Ok(control_packet)) => { control_packet }
+                    Ok(Err(err)) => {
+                        error!("Can't decode control packet of client {:?}: {:?}. Reason code: {:?}", socket, err, err.reason_code());
+                        if let Some(reason_code) = Self::disconnect_reason(&context, &err) {
+                            send_packet(socket, &ControlPacket::disconnect(reason_code), &to_listener).await;
+                        }
+                        break;
+                    }
+                    Err(_) => {
+                        error!("Decode worker dropped a packet of client {:?}", socket);
+                        break;
+                    }
+                };
+                let control_packet = match topic_aliases.resolve(control_packet) {
+                    Ok(control_packet) => { control_packet }
+                    Err(reason_code) => {
+                        warn!("Disconnecting client {:?}: {:?}", socket, reason_code);
+                        send_packet(socket, &ControlPacket::disconnect(reason_code), &to_listener).await;
+                        break;
+                    }
+                };
+                if let Err(err) = listener2broker.send((context, control_packet)).await {
+                    error!("Can't send message to broker: {:?}", err);
+                    break;
+                }
+            }
+        });
+        forwarder
+    }
+}
@@ -0,0 +1,36 @@
+use std::io;
+use std::time::Duration;
+
+use socket2::{SockRef, TcpKeepalive};
+use tokio::net::TcpStream;
+
+use crate::config::broker_config::TcpConfig;
+
+//Applies the configured options to an accepted socket
+pub fn apply_socket_options(stream: &TcpStream, config: &TcpConfig) -> io::Result<()> {
+    stream.set_nodelay(config.nodelay)?;
+    let socket = SockRef::from(stream);
+    if config.keepalive_time_secs > 0 {
+        socket.set_tcp_keepalive(&keepalive(config))?;
+    }
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    if config.user_timeout_ms > 0 {
+        socket.set_tcp_user_timeout(Some(Duration::from_millis(config.user_timeout_ms)))?;
+    }
+    Ok(())
+}
+
+#[allow(unused_mut)]
+fn keepalive(config: &TcpConfig) -> TcpKeepalive {
+    let mut keepalive = TcpKeepalive::new().with_time(Duration::from_secs(config.keepalive_time_secs));
+    #[cfg(any(target_os = "android", target_os = "fuchsia", target_os = "linux"))]
+    {
+        if config.keepalive_interval_secs > 0 {
+            keepalive = keepalive.with_interval(Duration::from_secs(config.keepalive_interval_secs));
+        }
+        if config.keepalive_retries > 0 {
+            keepalive = keepalive.with_retries(config.keepalive_retries);
+        }
+    }
+    keepalive
+}
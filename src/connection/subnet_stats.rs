@@ -0,0 +1,194 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Mutex;
+
+use log::{debug, trace};
+use metered::HitCount;
+use serde::Serialize;
+
+use crate::config::broker_config::SubnetStatsConfig;
+
+//A client address masked to the prefix length of its family. IPv4-mapped IPv6 addresses count as IPv4.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct Subnet {
+    pub network: IpAddr,
+    pub prefix_len: u8,
+}
+
+impl Subnet {
+    pub fn of(address: IpAddr, ipv4_prefix_len: u8, ipv6_prefix_len: u8) -> Self {
+        match address.to_canonical() {
+            IpAddr::V4(address) => {
+                let prefix_len = ipv4_prefix_len.min(32);
+                let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+                Self { network: IpAddr::V4(Ipv4Addr::from(u32::from(address) & mask)), prefix_len }
+            }
+            IpAddr::V6(address) => {
+                let prefix_len = ipv6_prefix_len.min(128);
+                let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+                Self { network: IpAddr::V6(Ipv6Addr::from(u128::from(address) & mask)), prefix_len }
+            }
+        }
+    }
+
+    //Metric names can't hold dots, colons or slashes
+    fn metric_key(&self) -> String {
+        self.to_string().chars()
+            .map(|character| if character.is_ascii_alphanumeric() { character } else { '_' })
+            .collect()
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+//Why a connection of the subnet ended, when it didn't just close
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+pub enum CloseReason {
+    Closed,
+    KeepAliveExpired,
+    ConnectionLost,
+    Malformed,
+}
+
+#[derive(Debug, Default)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct SubnetConnections {
+    pub open: u64,
+    //Accepted since the subnet is tracked, open ones included
+    pub opened: u64,
+    pub keep_alive_expired: u64,
+    pub connection_lost: u64,
+    //Connections closed on a packet that couldn't be decoded
+    pub malformed: u64,
+}
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct SubnetStatsMetrics {
+    //Connections from subnets past the capacity
+    pub(crate) untracked: HitCount,
+    //Subnets without open connections replaced by a new one
+    pub(crate) evicted: HitCount,
+}
+
+//Exported with the metrics, the subnets with the most open connections by name
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct SubnetMetrics {
+    subnets: usize,
+    untracked: u64,
+    evicted: u64,
+    open_by_subnet: BTreeMap<String, u64>,
+}
+
+//Connection counts per subnet of the client addresses. Memory is bounded by subnet_stats.capacity
+//however many addresses connect.
+#[derive(Debug)]
+pub struct SubnetStats {
+    config: SubnetStatsConfig,
+    subnets: Mutex<HashMap<Subnet, SubnetConnections>>,
+    pub(crate) metrics: SubnetStatsMetrics,
+}
+
+impl SubnetStats {
+    pub fn subnet(&self, address: IpAddr) -> Subnet {
+        Subnet::of(address, self.config.ipv4_prefix_len, self.config.ipv6_prefix_len)
+    }
+
+    pub fn opened(&self, address: IpAddr) {
+        trace!("SubnetStats::opened");
+        let subnet = self.subnet(address);
+        let mut subnets = self.subnets.lock().unwrap();
+        if !subnets.contains_key(&subnet) && !self.make_room(&mut subnets) {
+            self.metrics.untracked.incr();
+            return;
+        }
+        let connections = subnets.entry(subnet).or_default();
+        connections.open += 1;
+        connections.opened += 1;
+    }
+
+    //A connection of an untracked subnet, or of one replaced since it opened, isn't counted
+    pub fn closed(&self, address: IpAddr, reason: CloseReason) {
+        trace!("SubnetStats::closed");
+        let subnet = self.subnet(address);
+        let mut subnets = self.subnets.lock().unwrap();
+        let connections = match subnets.get_mut(&subnet) {
+            Some(result) => { result }
+            None => { return; }
+        };
+        connections.open = connections.open.saturating_sub(1);
+        match reason {
+            CloseReason::Closed => {}
+            CloseReason::KeepAliveExpired => { connections.keep_alive_expired += 1; }
+            CloseReason::ConnectionLost => { connections.connection_lost += 1; }
+            CloseReason::Malformed => { connections.malformed += 1; }
+        }
+    }
+
+    //Subnets with the most open connections first, then the most opened
+    pub fn top(&self, limit: usize) -> Vec<(Subnet, SubnetConnections)> {
+        let mut top: Vec<(Subnet, SubnetConnections)> = self.subnets.lock().unwrap().iter()
+            .map(|(subnet, connections)| (*subnet, connections.clone()))
+            .collect();
+        top.sort_by(|(subnet_a, connections_a), (subnet_b, connections_b)| connections_b.open.cmp(&connections_a.open)
+            .then(connections_b.opened.cmp(&connections_a.opened))
+            .then(subnet_a.cmp(subnet_b)));
+        top.truncate(limit);
+        top
+    }
+
+    pub fn len(&self) -> usize {
+        self.subnets.lock().unwrap().len()
+    }
+
+    pub fn metrics(&self) -> SubnetMetrics {
+        SubnetMetrics {
+            subnets: self.len(),
+            untracked: self.metrics.untracked.0.get(),
+            evicted: self.metrics.evicted.0.get(),
+            open_by_subnet: self.top(self.config.exported).into_iter()
+                .map(|(subnet, connections)| (subnet.metric_key(), connections.open))
+                .collect(),
+        }
+    }
+
+    pub fn prefix_lens(&self) -> (u8, u8) {
+        (self.config.ipv4_prefix_len, self.config.ipv6_prefix_len)
+    }
+
+    //At capacity the subnet without open connections that opened the fewest goes
+    fn make_room(&self, subnets: &mut HashMap<Subnet, SubnetConnections>) -> bool {
+        if subnets.len() < self.config.capacity {
+            return true;
+        }
+        let idle = subnets.iter()
+            .filter(|(_, connections)| connections.open == 0)
+            .min_by(|(subnet_a, connections_a), (subnet_b, connections_b)| connections_a.opened.cmp(&connections_b.opened).then(subnet_a.cmp(subnet_b)))
+            .map(|(subnet, _)| *subnet);
+        match idle {
+            Some(subnet) => {
+                debug!("Replacing subnet {} in the connection statistics", subnet);
+                subnets.remove(&subnet);
+                self.metrics.evicted.incr();
+                true
+            }
+            None => { false }
+        }
+    }
+
+    pub fn new(config: SubnetStatsConfig) -> Self {
+        Self { config, subnets: Mutex::new(HashMap::new()), metrics: SubnetStatsMetrics::default() }
+    }
+}
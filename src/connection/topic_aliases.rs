@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+use log::{debug, trace};
+
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::reason_code::ReasonCode;
+
+//Topic Aliases a client set up on its connection, resolved by the reader so that the broker, which handles
+//packets concurrently, only ever sees topic names. A PUBLISH with an alias and a topic name sets the alias,
+//one with an alias and an empty topic name goes to the topic the alias was last set to.
+#[derive(Debug)]
+pub struct TopicAliases {
+    //Announced in CONNACK, 0 refuses every alias
+    maximum: u16,
+    alias2topic: HashMap<u16, String>,
+}
+
+impl TopicAliases {
+    //Packets other than PUBLISH pass unchanged. The error is the reason code to disconnect the client with.
+    pub fn resolve(&mut self, control_packet: ControlPacket) -> Result<ControlPacket, ReasonCode> {
+        if control_packet.fixed_header().packet_type() != ControlPacketType::PUBLISH {
+            return Ok(control_packet);
+        }
+        let topic_name = control_packet.variable_header().topic_name();
+        let topic_alias = match control_packet.variable_header().topic_alias() {
+            Some(result) => { result }
+            None if topic_name.is_empty() => {
+                debug!("PUBLISH without Topic Name nor Topic Alias");
+                return Err(ReasonCode::ProtocolError);
+            }
+            None => { return Ok(control_packet); }
+        };
+        if topic_alias == 0 || topic_alias > self.maximum {
+            debug!("Topic Alias {} outside of 1 to {}", topic_alias, self.maximum);
+            return Err(ReasonCode::TopicAliasInvalid);
+        }
+        if !topic_name.is_empty() {
+            trace!("Topic Alias {} set to {:?}", topic_alias, topic_name);
+            self.alias2topic.insert(topic_alias, topic_name.clone());
+            return Ok(control_packet);
+        }
+        match self.alias2topic.get(&topic_alias) {
+            Some(topic_name) => { Ok(control_packet.with_topic_name(topic_name.clone())) }
+            None => {
+                debug!("Topic Alias {} was never set", topic_alias);
+                Err(ReasonCode::TopicAliasInvalid)
+            }
+        }
+    }
+
+    pub fn new(maximum: u16) -> Self {
+        Self { maximum, alias2topic: HashMap::new() }
+    }
+}
@@ -1,4 +1,3 @@
-use core::fmt;
 use std::borrow::BorrowMut;
 use std::cell::RefCell;
 use std::net::SocketAddr;
@@ -6,94 +5,207 @@ use std::sync::Arc;
 
 use bytes::BytesMut;
 use dashmap::DashMap;
-use log::{debug, error, trace};
+use log::{debug, error, info, trace};
 use metered::{*};
 use nameof::name_of;
 use tokio::io::AsyncWriteExt;
 use tokio::net::tcp::OwnedWriteHalf;
+use thiserror::Error;
 use tokio::sync::mpsc::{Receiver};
 
-use crate::{ClientHandler, TopicHandler};
+use crate::ClientHandler;
+use crate::broker::connection_close::ConnectionClose;
+use crate::connection::virtual_endpoint::VirtualEndpoints;
 use crate::model::control_packet::ControlPacket;
+use crate::metrics::payload_sizes::PayloadSizes;
 use crate::model::fixed_header::ControlPacketType;
+use crate::model::qos_level::QoSLevel;
 use crate::serdes::mqtt_encoder::MqttEncoder;
+use crate::serdes::serializer::error::EncodeError;
+use crate::session::client_handler::DEBUG_CAPTURE_TARGET;
+use crate::session::qos2_tracker::Direction;
 
 #[derive(Debug)]
 pub struct TxConnectionHandler {
     pub(crate) metrics: TxConnectionHandlerMetrics,
     pub(crate) tx_client_handler: Arc<TxClientHandler>,
     client_handler: Arc<ClientHandler>,
-    topic_handler: Arc<TopicHandler>,
+    virtual_endpoints: Arc<VirtualEndpoints>,
+    connection_close: Arc<ConnectionClose>,
+    payload_sizes: Arc<PayloadSizes>,
     pub(crate) encoder: MqttEncoder,
 
 }
 
 #[metered(registry = TxConnectionHandlerMetrics)]
 impl TxConnectionHandler {
-    //#[tokio::main(flavor = "multi_thread")]
-    #[tokio::main(flavor = "multi_thread", worker_threads = 4)]
-    pub async fn handle_outgoing_connections(&self, mut broker2listener: Receiver<(Vec<SocketAddr>, ControlPacket)>, stream_repository: Arc<DashMap<SocketAddr, OwnedWriteHalf>>) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn handle_outgoing_connections(&self, broker2listener: &mut Receiver<(Vec<SocketAddr>, ControlPacket)>, stream_repository: Arc<DashMap<SocketAddr, OwnedWriteHalf>>) -> Result<(), Box<dyn std::error::Error>> {
         let encoder = self.encoder.clone();
         let tx_client_handler = self.tx_client_handler.clone();
         let client_handler = self.client_handler.clone();
-        let topic_handler = self.topic_handler.clone();
-        loop {
-            if let Some((sockets, packet)) = broker2listener.recv().await {
-                let encoder = encoder.clone();
-                let tx_client_handler = tx_client_handler.clone();
-                let client_handler = client_handler.clone();
-                let topic_handler = topic_handler.clone();
-                let stream_repository = stream_repository.clone();
-                let packet = Arc::new(packet);
-                tokio::spawn(async move {
-                    match encoder.encode_packet(&packet) {
-                        Ok(encoded_packet) => {
-                            let encoded_packet = Arc::new(encoded_packet);
-                            for socket in sockets {
-                                let encoded_packet = encoded_packet.clone();
-                                let packet = packet.clone();
-                                let tx_client_handler = tx_client_handler.clone();
-                                let client_handler = client_handler.clone();
-                                let topic_handler = topic_handler.clone();
-                                let stream_repository = stream_repository.clone();
-
-                                tokio::spawn(async move {
-                                    trace!("Acquiring {} lock", name_of!(stream_repository));
-                                    if Self::is_disconnection(&packet).await {
-                                        debug!("Handling disconnection for socket {:?}", socket);
-                                        Self::clean_after_disconnection(&socket, &stream_repository, &client_handler, &topic_handler).await;
-                                    } else {
-                                        debug!("Sending packet {:?} to {:?}", packet.fixed_header().packet_type(), socket);
+        let virtual_endpoints = self.virtual_endpoints.clone();
+        let connection_close = self.connection_close.clone();
+        let payload_sizes = self.payload_sizes.clone();
+        while let Some((sockets, packet)) = broker2listener.recv().await {
+            let encoder = encoder.clone();
+            let tx_client_handler = tx_client_handler.clone();
+            let client_handler = client_handler.clone();
+            let stream_repository = stream_repository.clone();
+            let virtual_endpoints = virtual_endpoints.clone();
+            let connection_close = connection_close.clone();
+            let payload_sizes = payload_sizes.clone();
+            let (virtual_sockets, sockets): (Vec<SocketAddr>, Vec<SocketAddr>) = Self::current_sockets(sockets, &packet, &client_handler).into_iter()
+                .partition(|socket| virtual_endpoints.contains(socket));
+            for socket in virtual_sockets {
+                Self::record_payload_size(&packet, &payload_sizes);
+                if Self::send_to_virtual_endpoint(socket, packet.clone(), &virtual_endpoints, &client_handler) {
+                    debug!("Handling disconnection for virtual endpoint {:?}", socket);
+                    connection_close.closed(&socket).await;
+                    virtual_endpoints.unregister(&socket);
+                }
+            }
+            if sockets.is_empty() {
+                continue;
+            }
+            let packet = Arc::new(packet);
+            tokio::spawn(async move {
+                match encoder.encode_packet(&packet) {
+                    Ok(encoded_packet) => {
+                        let encoded_packet = Arc::new(encoded_packet);
+                        for socket in sockets {
+                            let encoder = encoder.clone();
+                            let encoded_packet = encoded_packet.clone();
+                            let packet = packet.clone();
+                            let tx_client_handler = tx_client_handler.clone();
+                            let client_handler = client_handler.clone();
+                                            let stream_repository = stream_repository.clone();
+                            let connection_close = connection_close.clone();
+                            let payload_sizes = payload_sizes.clone();
 
+                            tokio::spawn(async move {
+                                trace!("Acquiring {} lock", name_of!(stream_repository));
+                                if Self::is_disconnection(&packet).await {
+                                    debug!("Handling disconnection for socket {:?}", socket);
+                                    //A DISCONNECT the broker closes the connection with on an error tells the client why. It only
+                                    //follows a successful CONNACK, a socket without a registered client was refused at CONNECT.
+                                    if Self::is_error_disconnection(&packet) && client_handler.get_client_id(&socket).is_ok() {
                                         if let Some(mut out_stream) = stream_repository.get_mut(&socket) {
-                                            let out_stream = out_stream.borrow_mut();
-                                            match tx_client_handler.send_packet(&socket, &encoded_packet, out_stream).await {
-                                                Ok(_) => {}
-                                                Err(err) => {
-                                                    error!("Can't send packet {:?} to socket {}. {}", packet.fixed_header().packet_type(), socket, err);
-                                                    Self::clean_after_disconnection(&socket, &stream_repository, &client_handler, &topic_handler).await;
-                                                }
+                                            if let Err(err) = tx_client_handler.send_packet(&socket, &encoded_packet, out_stream.borrow_mut()).await {
+                                                debug!("Can't send DISCONNECT to socket {}. {}", socket, err);
                                             }
                                         }
                                     }
-                                });
-                            }
-                        }
-                        Err(err) => {
-                            panic!("Can't encode Control Packet: {:?}", err);
+                                    Self::clean_after_disconnection(&socket, &stream_repository, &connection_close).await;
+                                } else {
+                                    let (packet, encoded_packet) = match Self::within_maximum_packet_size(&socket, packet, encoded_packet, &encoder, &client_handler) {
+                                        Some(result) => { result }
+                                        None => { return; }
+                                    };
+                                    debug!("Sending packet {:?} to {:?}", packet.fixed_header().packet_type(), socket);
+                                    if let Some(client_id) = client_handler.debug_captured_client(&socket) {
+                                        info!(target: DEBUG_CAPTURE_TARGET, "Client {:?} on {} receives {:?}", client_id, socket, packet);
+                                    }
+
+                                    //The stream is released before the connection is cleaned up, which takes it again
+                                    let sent = match stream_repository.get_mut(&socket) {
+                                        Some(mut out_stream) => { Some(tx_client_handler.send_packet(&socket, &encoded_packet, out_stream.borrow_mut()).await) }
+                                        None => { None }
+                                    };
+                                    match sent {
+                                        Some(Ok(_)) => {
+                                            Self::record_payload_size(&packet, &payload_sizes);
+                                            //Closed in the task that wrote the CONNACK, so the refusal reaches the client before the connection ends
+                                            if Self::is_refusal(&packet) {
+                                                debug!("Closing socket {:?} refused at CONNECT", socket);
+                                                Self::clean_after_disconnection(&socket, &stream_repository, &connection_close).await;
+                                            }
+                                        }
+                                        Some(Err(err)) => {
+                                            error!("Can't send packet {:?} to socket {}. {}", packet.fixed_header().packet_type(), socket, err);
+                                            Self::clean_after_disconnection(&socket, &stream_repository, &connection_close).await;
+                                        }
+                                        None => {}
+                                    }
+                                }
+                            });
                         }
                     }
-                });
+                    Err(err) => {
+                        panic!("Can't encode Control Packet: {:?}", err);
+                    }
+                }
+            });
+        }
+        Err("The broker is gone".into())
+    }
+
+    //The client never gets a packet over its Maximum Packet Size. A PUBLISH is discarded as if it was sent and
+    //acknowledged, so the next one deferred by the Receive Maximum of the client takes its place.
+    fn within_maximum_packet_size(socket: &SocketAddr, mut packet: Arc<ControlPacket>, mut encoded_packet: Arc<BytesMut>, encoder: &MqttEncoder, client_handler: &Arc<ClientHandler>) -> Option<(Arc<ControlPacket>, Arc<BytesMut>)> {
+        while !client_handler.limits.fits(socket, encoded_packet.len()) {
+            info!("Discarding {:?} of {} bytes over the Maximum Packet Size of socket {:?}", packet.fixed_header().packet_type(), encoded_packet.len(), socket);
+            let packet_identifier = match packet.fixed_header().packet_type() {
+                ControlPacketType::PUBLISH => { packet.variable_header().packet_identifier_opt()? }
+                _ => { return None; }
+            };
+            packet = Arc::new(client_handler.limits.release(socket, packet_identifier)?);
+            encoded_packet = match encoder.encode_packet(&packet) {
+                Ok(result) => { Arc::new(result) }
+                Err(err) => {
+                    error!("Can't encode Control Packet: {:?}", err);
+                    return None;
+                }
+            };
+        }
+        Some((packet, encoded_packet))
+    }
+
+    fn record_payload_size(packet: &ControlPacket, payload_sizes: &PayloadSizes) {
+        if packet.fixed_header().packet_type() == ControlPacketType::PUBLISH {
+            let payload_size = packet.payload_opt().map(|payload| payload.data().len()).unwrap_or(0);
+            payload_sizes.record(Direction::Outbound, packet.variable_header().topic_name(), payload_size);
+        }
+    }
+
+    //A PUBLISH resolved before a takeover must not reach the socket that was taken over
+    fn current_sockets(sockets: Vec<SocketAddr>, packet: &ControlPacket, client_handler: &Arc<ClientHandler>) -> Vec<SocketAddr> {
+        if packet.fixed_header().packet_type() != ControlPacketType::PUBLISH {
+            return sockets;
+        }
+        let (current, stale): (Vec<SocketAddr>, Vec<SocketAddr>) = sockets.into_iter()
+            .partition(|socket| client_handler.is_current(socket));
+        if !stale.is_empty() {
+            debug!("Skipping PUBLISH to stale sockets {:?}", stale);
+        }
+        return current;
+    }
+
+    //Returns whether the packet ends the endpoint's connection
+    fn send_to_virtual_endpoint(socket: SocketAddr, packet: ControlPacket, virtual_endpoints: &Arc<VirtualEndpoints>, client_handler: &Arc<ClientHandler>) -> bool {
+        debug!("Sending packet {:?} to virtual endpoint {:?}", packet.fixed_header().packet_type(), socket);
+        let is_disconnection = packet.fixed_header().packet_type() == ControlPacketType::DISCONNECT || Self::is_refusal(&packet);
+        //An endpoint takes a publish as soon as it is handed over, which frees its Receive Maximum slot right away
+        let packet_identifier = match (packet.fixed_header().packet_type(), packet.fixed_header().qos_level_opt()) {
+            (ControlPacketType::PUBLISH, Some(QoSLevel::AtLeastOnce | QoSLevel::ExactlyOnce)) => { packet.variable_header().packet_identifier_opt() }
+            _ => { None }
+        };
+        if let Err(err) = virtual_endpoints.deliver(&socket, packet) {
+            //Endpoints that close on their own (HTTP streams) are gone before their DISCONNECT arrives
+            if is_disconnection {
+                debug!("{}", err);
+            } else {
+                error!("{}", err);
             }
         }
-        Ok(())
+        if let Some(next_packet) = packet_identifier.and_then(|packet_identifier| client_handler.limits.release(&socket, packet_identifier)) {
+            Self::send_to_virtual_endpoint(socket, next_packet, virtual_endpoints, client_handler);
+        }
+        is_disconnection
     }
 
-    async fn clean_after_disconnection(socket: &SocketAddr, stream_repository: &Arc<DashMap<SocketAddr, OwnedWriteHalf>>, client_handler: &Arc<ClientHandler>, topic_handler: &Arc<TopicHandler>) {
+    async fn clean_after_disconnection(socket: &SocketAddr, stream_repository: &Arc<DashMap<SocketAddr, OwnedWriteHalf>>, connection_close: &Arc<ConnectionClose>) {
         debug!("clean_after_disconnection");
-        if let Some(client_id) = client_handler.unregister_by_socket(socket) {
-            topic_handler.unsubscribe_all(&client_id);
-        }
+        connection_close.closed(socket).await;
         if let Some(mut out_stream) = stream_repository.get_mut(&socket) {
             match out_stream.borrow_mut().shutdown().await {
                 Ok(_) => {
@@ -114,8 +226,17 @@ impl TxConnectionHandler {
         return false;
     }
 
-    pub fn new(client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>) -> Self {
-        Self { metrics: TxConnectionHandlerMetrics::default(), tx_client_handler: Arc::new(TxClientHandler::default()), client_handler, topic_handler, encoder: MqttEncoder::default() }
+    fn is_error_disconnection(packet: &ControlPacket) -> bool {
+        packet.variable_header_opt().and_then(|header| header.reason_code()).is_some_and(|reason_code| reason_code.is_error())
+    }
+
+    //A CONNACK refusing the CONNECT ends the connection, no DISCONNECT follows it
+    fn is_refusal(packet: &ControlPacket) -> bool {
+        packet.fixed_header().packet_type() == ControlPacketType::CONNACK && Self::is_error_disconnection(packet)
+    }
+
+    pub fn new(client_handler: Arc<ClientHandler>, virtual_endpoints: Arc<VirtualEndpoints>, connection_close: Arc<ConnectionClose>, payload_sizes: Arc<PayloadSizes>) -> Self {
+        Self { metrics: TxConnectionHandlerMetrics::default(), tx_client_handler: Arc::new(TxClientHandler::default()), client_handler, virtual_endpoints, connection_close, payload_sizes, encoder: MqttEncoder::default() }
     }
 }
 
@@ -168,24 +289,17 @@ impl TxClientHandler {
     }
 }
 
-pub type WriteResult = Result<(), WriteError>; //TODO Needs better errors
+pub type WriteResult = Result<(), WriteError>;
 
 #[derive(Debug, PartialEq, Clone)]
+#[derive(Error)]
 pub enum WriteError {
+    #[error("connection timed out")]
     ConnectionTimedOut,
-    EncodeError,
+    #[error(transparent)]
+    Encode(#[from] EncodeError),
+    #[error("can't send buffer to the client")]
     SendError,
+    #[error("can't flush buffered writer")]
     FlushError,
 }
-
-impl fmt::Display for WriteError {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        //self.description().fmt(fmt)
-        match *self {
-            WriteError::ConnectionTimedOut => write!(fmt, "WriteError::ConnectionTimedOut"),
-            WriteError::EncodeError => write!(fmt, "WriteError::EncodeError"),
-            WriteError::SendError => write!(fmt, "WriteError::SendError"),
-            WriteError::FlushError => write!(fmt, "WriteError::FlushError"),
-        }
-    }
-}
@@ -0,0 +1,52 @@
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+use log::{debug, trace};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+use crate::model::control_packet::ControlPacket;
+
+//Clients that don't own a TCP stream (gateways, HTTP subscribers) are registered here.
+//The broker addresses them by a synthetic socket in fd00::/8, TxConnectionHandler hands them
+//decoded ControlPackets instead of writing bytes to a stream.
+#[derive(Debug, Default)]
+pub struct VirtualEndpoints {
+    endpoints: DashMap<SocketAddr, Sender<ControlPacket>>,
+    next_id: AtomicU64,
+}
+
+impl VirtualEndpoints {
+    pub fn register(&self, capacity: usize) -> (SocketAddr, Receiver<ControlPacket>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let segments = [(id >> 48) as u16, (id >> 32) as u16, (id >> 16) as u16, id as u16];
+        let socket = SocketAddr::new(IpAddr::V6(Ipv6Addr::new(0xfd00, 0, 0, 0, segments[0], segments[1], segments[2], segments[3])), 0);
+        let (sender, receiver) = mpsc::channel(capacity);
+        self.endpoints.insert(socket, sender);
+        debug!("Registered virtual endpoint {:?}", socket);
+        (socket, receiver)
+    }
+
+    pub fn unregister(&self, socket: &SocketAddr) {
+        if self.endpoints.remove(socket).is_some() {
+            debug!("Unregistered virtual endpoint {:?}", socket);
+        }
+    }
+
+    pub fn contains(&self, socket: &SocketAddr) -> bool {
+        self.endpoints.contains_key(socket)
+    }
+
+    //Never waits: a slow endpoint loses packets instead of stalling delivery to every other client
+    pub fn deliver(&self, socket: &SocketAddr, packet: ControlPacket) -> Result<(), String> {
+        trace!("VirtualEndpoints::deliver");
+        return match self.endpoints.get(socket) {
+            Some(sender) => {
+                sender.try_send(packet)
+                    .map_err(|err| format!("Can't deliver packet to virtual endpoint {}. {}", socket, err))
+            }
+            None => { Err(format!("No virtual endpoint registered for {}", socket)) }
+        };
+    }
+}
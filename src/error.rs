@@ -0,0 +1,51 @@
+use std::net::SocketAddr;
+
+use thiserror::Error;
+
+use crate::config::port_check::PortConflict;
+use crate::connection::tx_connection_handler::WriteError;
+use crate::serdes::deserializer::error::DecodeError;
+use crate::serdes::serializer::error::EncodeError;
+
+pub type PatinaResult<T> = Result<T, PatinaError>;
+
+//Every layer's error, so a caller can match on the kind of failure without parsing messages
+#[derive(Debug, PartialEq, Clone)]
+#[derive(Error)]
+pub enum PatinaError {
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    #[error(transparent)]
+    Encode(#[from] EncodeError),
+    #[error(transparent)]
+    Write(#[from] WriteError),
+    #[error("Can't get any client_id for socket {0}")]
+    UnknownClient(SocketAddr),
+    #[error("Handler panicked. {0}")]
+    HandlerPanicked(String),
+}
+
+//Why the broker didn't start. Each kind exits with its own code so scripts can tell them apart:
+//2 for the command line and the config, 3 to 6 for ports by the kind of the first conflict
+#[derive(Debug, PartialEq, Clone)]
+#[derive(Error)]
+pub enum StartupError {
+    #[error("startup_error kind=command_line detail={0:?}")]
+    CommandLine(String),
+    #[error("startup_error kind=config detail={0:?}")]
+    Config(String),
+    //Every problem of the config file and overrides, one line each
+    #[error("{}", .0.iter().map(|problem| StartupError::Config(problem.clone()).to_string()).collect::<Vec<String>>().join("\n"))]
+    InvalidConfig(Vec<String>),
+    #[error("{}", .0.iter().map(|conflict| conflict.to_string()).collect::<Vec<String>>().join("\n"))]
+    PortConflicts(Vec<PortConflict>),
+}
+
+impl StartupError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            StartupError::CommandLine(_) | StartupError::Config(_) | StartupError::InvalidConfig(_) => { 2 }
+            StartupError::PortConflicts(conflicts) => { conflicts.first().map(|conflict| conflict.kind.exit_code()).unwrap_or(1) }
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU16, Ordering};
+
+use log::{debug, error, info, trace, warn};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+use crate::auth::acl::Acl;
+use crate::config::broker_config::{BrokerConfig, CoapMapping};
+use crate::connection::client_context::ClientContext;
+use crate::connection::virtual_endpoint::VirtualEndpoints;
+use crate::gateway::coap::message::{CoapMessage, Code, MessageType};
+use crate::model::control_packet::ControlPacket;
+use crate::model::qos_level::QoSLevel;
+use crate::model::variable_header::ConnectFlags;
+use crate::topic::topic_handler::TopicHandler;
+
+const ENDPOINT_CAPACITY: usize = 1000;
+
+//Serves CoAP requests on UDP: PUT publishes a retained message, POST a plain one, GET returns
+//the last retained payload of the mapped topic. Publishes go through the broker as a single
+//client, so they are handled like those of any TCP client.
+#[derive(Debug)]
+pub struct CoapBridge {
+    config: Arc<BrokerConfig>,
+    listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>,
+    topic_handler: Arc<TopicHandler>,
+    acl: Arc<Acl>,
+    virtual_endpoints: Arc<VirtualEndpoints>,
+    broker_socket: SocketAddr,
+    next_message_id: AtomicU16,
+}
+
+impl CoapBridge {
+    //Serves until the bridge fails, as a client of its own endpoint. The endpoint of a bridge that failed may be gone
+    //with its connection, so every start connects on a new one.
+    pub async fn run(config: Arc<BrokerConfig>, listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>, virtual_endpoints: Arc<VirtualEndpoints>, topic_handler: Arc<TopicHandler>, acl: Arc<Acl>) -> Result<(), Box<dyn std::error::Error>> {
+        let (coap_bridge, mut from_broker) = Self::new(config, listener2broker, virtual_endpoints, topic_handler, acl);
+        let result = coap_bridge.handle_datagrams(&mut from_broker).await;
+        coap_bridge.virtual_endpoints.unregister(&coap_bridge.broker_socket);
+        result
+    }
+
+    pub async fn handle_datagrams(&self, from_broker: &mut Receiver<ControlPacket>) -> Result<(), Box<dyn std::error::Error>> {
+        let address = SocketAddr::from(([0, 0, 0, 0], self.config.gateway.coap.port));
+        let udp_socket = UdpSocket::bind(address).await?;
+        info!("CoAP bridge listening on UDP {}", address);
+        self.connect().await?;
+        tokio::select! {
+            result = self.serve(&udp_socket) => { result }
+            _ = Self::handle_broker_packets(from_broker) => { Err("CoAP bridge disconnected from broker".into()) }
+        }
+    }
+
+    async fn serve(&self, udp_socket: &UdpSocket) -> Result<(), Box<dyn std::error::Error>> {
+        let mut buffer = vec![0; u16::MAX as usize];
+        loop {
+            let (length, peer) = match udp_socket.recv_from(&mut buffer).await {
+                Ok(result) => { result }
+                Err(err) => {
+                    error!("Can't read CoAP datagram. {}", err);
+                    continue;
+                }
+            };
+            let request = match CoapMessage::decode(&buffer[..length]) {
+                Ok(request) => { request }
+                Err(err) => {
+                    warn!("Dropping malformed CoAP datagram from {}: {:?}", peer, err);
+                    continue;
+                }
+            };
+            if let Some(response) = self.handle_request(&request).await {
+                if let Err(err) = udp_socket.send_to(&response.encode(), peer).await {
+                    error!("Can't send CoAP response to {}. {}", peer, err);
+                }
+            }
+        }
+    }
+
+    pub async fn handle_request(&self, request: &CoapMessage) -> Option<CoapMessage> {
+        trace!("CoapBridge::handle_request");
+        debug!("CoAP request: {:?}", request);
+        let message_id = self.next_message_id.fetch_add(1, Ordering::Relaxed);
+        let code = request.code();
+        if code == Code::Empty {
+            //CoAP ping: a confirmable empty message is answered with a reset
+            return match request.message_type() {
+                MessageType::Confirmable => { Some(CoapMessage::new(MessageType::Reset, Code::Empty, request.message_id(), vec![])) }
+                _ => { None }
+            };
+        }
+        if matches!(request.message_type(), MessageType::Acknowledgement | MessageType::Reset) {
+            return None;
+        }
+
+        let topic_name = match request.uri_path().ok().and_then(|path| Self::resolve_topic(&self.config.gateway.coap.mappings, &path)) {
+            Some(topic_name) => { topic_name }
+            None => { return Some(CoapMessage::response(request, Code::NotFound, message_id)); }
+        };
+        if topic_name.contains(['+', '#']) {
+            return Some(CoapMessage::response(request, Code::BadRequest, message_id));
+        }
+
+        let response = match code {
+            //Reading a retained message takes the permission a subscription to its topic would
+            Code::Get if !self.acl.can_subscribe(&self.config.gateway.coap.client_id, None, &topic_name) => {
+                CoapMessage::response(request, Code::Forbidden, message_id)
+            }
+            Code::Get => {
+                match self.topic_handler.retained_message(&topic_name) {
+                    Some(control_packet) => {
+                        let data = control_packet.payload_opt().map(|payload| payload.data().clone()).unwrap_or_default();
+                        CoapMessage::response(request, Code::Content, message_id).with_payload(data)
+                    }
+                    None => { CoapMessage::response(request, Code::NotFound, message_id) }
+                }
+            }
+            Code::Put | Code::Post => {
+                let publish_packet = ControlPacket::publish_with_payload(None, topic_name, QoSLevel::AtMostOnce, code == Code::Put, vec![], request.payload().clone());
+                match self.send_to_broker(publish_packet).await {
+                    Ok(_) => { CoapMessage::response(request, Code::Changed, message_id) }
+                    Err(err) => {
+                        error!("{}", err);
+                        CoapMessage::response(request, Code::InternalServerError, message_id)
+                    }
+                }
+            }
+            _ => { CoapMessage::response(request, Code::MethodNotAllowed, message_id) }
+        };
+        return Some(response);
+    }
+
+    //Longest mapped path that is the request path or one of its parents, the remaining segments are appended to its topic
+    pub fn resolve_topic(mappings: &[CoapMapping], path: &str) -> Option<String> {
+        let path = path.trim_matches('/');
+        return mappings.iter()
+            .filter_map(|mapping| {
+                let prefix = mapping.path.trim_matches('/');
+                if path == prefix {
+                    Some((prefix.len(), mapping.topic.clone()))
+                } else if prefix.is_empty() {
+                    Some((0, format!("{}/{}", mapping.topic, path)))
+                } else {
+                    path.strip_prefix(prefix)
+                        .and_then(|rest| rest.strip_prefix('/'))
+                        .map(|rest| (prefix.len(), format!("{}/{}", mapping.topic, rest)))
+                }
+            })
+            .max_by_key(|(length, _)| *length)
+            .map(|(_, topic_name)| topic_name);
+    }
+
+    async fn connect(&self) -> Result<(), String> {
+        let client_id = self.config.gateway.coap.client_id.clone();
+        let connect_flags = ConnectFlags::new(false, false, false, QoSLevel::AtMostOnce, false, true, false);
+        let connect_packet = ControlPacket::connect(connect_flags, Some(0), vec![], Some(client_id), None, None, None, None, None);
+        return self.send_to_broker(connect_packet).await;
+    }
+
+    //The bridge only publishes QoS 0, whatever the broker sends back is informational
+    async fn handle_broker_packets(from_broker: &mut Receiver<ControlPacket>) {
+        while let Some(control_packet) = from_broker.recv().await {
+            debug!("CoAP bridge received {:?}", control_packet.fixed_header().packet_type());
+        }
+    }
+
+    async fn send_to_broker(&self, control_packet: ControlPacket) -> Result<(), String> {
+        return self.listener2broker.send((ClientContext::new(self.broker_socket), control_packet)).await
+            .map_err(|err| format!("Can't send message to broker: {:?}", err));
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>, virtual_endpoints: Arc<VirtualEndpoints>, topic_handler: Arc<TopicHandler>, acl: Arc<Acl>) -> (Self, Receiver<ControlPacket>) {
+        let (broker_socket, from_broker) = virtual_endpoints.register(ENDPOINT_CAPACITY);
+        let bridge = Self { config, listener2broker, topic_handler, acl, virtual_endpoints, broker_socket, next_message_id: AtomicU16::new(1) };
+        (bridge, from_broker)
+    }
+}
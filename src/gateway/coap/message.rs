@@ -0,0 +1,257 @@
+use bytes::{Buf, BufMut, BytesMut};
+use log::trace;
+
+use crate::serdes::deserializer::error::{DecodeError, DecodeResult, ReadError};
+
+//RFC 7252 option numbers used by the bridge
+pub const OPTION_URI_PATH: u16 = 11;
+pub const OPTION_CONTENT_FORMAT: u16 = 12;
+
+const VERSION: u8 = 1;
+const PAYLOAD_MARKER: u8 = 0xFF;
+
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+pub enum MessageType {
+    Confirmable,
+    NonConfirmable,
+    Acknowledgement,
+    Reset,
+}
+
+impl MessageType {
+    pub fn as_u8(&self) -> u8 {
+        return match self {
+            MessageType::Confirmable => { 0 }
+            MessageType::NonConfirmable => { 1 }
+            MessageType::Acknowledgement => { 2 }
+            MessageType::Reset => { 3 }
+        };
+    }
+
+    pub fn from_u8(value: u8) -> MessageType {
+        return match value & 0b11 {
+            0 => { MessageType::Confirmable }
+            1 => { MessageType::NonConfirmable }
+            2 => { MessageType::Acknowledgement }
+            _ => { MessageType::Reset }
+        };
+    }
+}
+
+//Request methods and the response codes the bridge answers with, as class.detail
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+pub enum Code {
+    Empty,
+    Get,
+    Post,
+    Put,
+    Delete,
+    Changed,
+    Content,
+    BadRequest,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    InternalServerError,
+    Other(u8),
+}
+
+impl Code {
+    pub fn as_u8(&self) -> u8 {
+        return match self {
+            Code::Empty => { 0x00 }
+            Code::Get => { 0x01 }
+            Code::Post => { 0x02 }
+            Code::Put => { 0x03 }
+            Code::Delete => { 0x04 }
+            Code::Changed => { 0x44 }
+            Code::Content => { 0x45 }
+            Code::BadRequest => { 0x80 }
+            Code::Forbidden => { 0x83 }
+            Code::NotFound => { 0x84 }
+            Code::MethodNotAllowed => { 0x85 }
+            Code::InternalServerError => { 0xA0 }
+            Code::Other(value) => { *value }
+        };
+    }
+
+    pub fn from_u8(value: u8) -> Code {
+        return match value {
+            0x00 => { Code::Empty }
+            0x01 => { Code::Get }
+            0x02 => { Code::Post }
+            0x03 => { Code::Put }
+            0x04 => { Code::Delete }
+            0x44 => { Code::Changed }
+            0x45 => { Code::Content }
+            0x80 => { Code::BadRequest }
+            0x83 => { Code::Forbidden }
+            0x84 => { Code::NotFound }
+            0x85 => { Code::MethodNotAllowed }
+            0xA0 => { Code::InternalServerError }
+            value => { Code::Other(value) }
+        };
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+pub struct CoapMessage {
+    message_type: MessageType,
+    code: Code,
+    message_id: u16,
+    token: Vec<u8>,
+    //Kept sorted by option number, as they go on the wire
+    options: Vec<(u16, Vec<u8>)>,
+    payload: Vec<u8>,
+}
+
+impl CoapMessage {
+    pub fn new(message_type: MessageType, code: Code, message_id: u16, token: Vec<u8>) -> Self {
+        Self { message_type, code, message_id, token, options: vec![], payload: vec![] }
+    }
+
+    //Piggybacked response for a confirmable request, a non-confirmable one otherwise
+    pub fn response(request: &CoapMessage, code: Code, message_id: u16) -> Self {
+        return match request.message_type {
+            MessageType::Confirmable => { Self::new(MessageType::Acknowledgement, code, request.message_id, request.token.clone()) }
+            _ => { Self::new(MessageType::NonConfirmable, code, message_id, request.token.clone()) }
+        };
+    }
+
+    pub fn message_type(&self) -> MessageType {
+        self.message_type
+    }
+    pub fn code(&self) -> Code {
+        self.code
+    }
+    pub fn message_id(&self) -> u16 {
+        self.message_id
+    }
+    pub fn token(&self) -> &Vec<u8> {
+        &self.token
+    }
+    pub fn payload(&self) -> &Vec<u8> {
+        &self.payload
+    }
+
+    pub fn with_option(mut self, number: u16, value: Vec<u8>) -> Self {
+        let position = self.options.iter().position(|(n, _)| *n > number).unwrap_or(self.options.len());
+        self.options.insert(position, (number, value));
+        self
+    }
+
+    pub fn with_payload(mut self, payload: Vec<u8>) -> Self {
+        self.payload = payload;
+        self
+    }
+
+    pub fn options(&self, number: u16) -> Vec<&Vec<u8>> {
+        self.options.iter()
+            .filter(|(n, _)| *n == number)
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    //Uri-Path segments joined with '/'
+    pub fn uri_path(&self) -> DecodeResult<String> {
+        let mut segments = Vec::new();
+        for segment in self.options(OPTION_URI_PATH) {
+            segments.push(String::from_utf8(segment.clone()).map_err(|_| DecodeError::TopicName { cause: ReadError::InvalidData })?);
+        }
+        return Ok(segments.join("/"));
+    }
+
+    pub fn decode(datagram: &[u8]) -> DecodeResult<CoapMessage> {
+        trace!("CoapMessage::decode");
+        let err = DecodeError::VariableHeaderAndPayload { cause: ReadError::InvalidData };
+        let mut buffer = datagram;
+        if buffer.remaining() < 4 {
+            return Err(DecodeError::RemainingLength { cause: ReadError::InvalidData });
+        }
+        let first = buffer.get_u8();
+        if first >> 6 != VERSION {
+            return Err(DecodeError::ProtocolVersion { cause: ReadError::InvalidData });
+        }
+        let message_type = MessageType::from_u8(first >> 4);
+        let token_length = (first & 0x0F) as usize;
+        let code = Code::from_u8(buffer.get_u8());
+        let message_id = buffer.get_u16();
+        //Lengths 9-15 are reserved
+        if token_length > 8 || buffer.remaining() < token_length {
+            return Err(err);
+        }
+        let token = buffer[..token_length].to_vec();
+        buffer.advance(token_length);
+
+        let mut message = CoapMessage::new(message_type, code, message_id, token);
+        let mut number: u16 = 0;
+        while buffer.has_remaining() {
+            let header = buffer.get_u8();
+            if header == PAYLOAD_MARKER {
+                //A marker followed by nothing is a format error
+                if !buffer.has_remaining() {
+                    return Err(err);
+                }
+                message.payload = buffer.to_vec();
+                break;
+            }
+            let delta = Self::read_extended(&mut buffer, header >> 4, err)?;
+            let length = Self::read_extended(&mut buffer, header & 0x0F, err)? as usize;
+            if buffer.remaining() < length {
+                return Err(err);
+            }
+            number = number.checked_add(delta).ok_or(err)?;
+            message.options.push((number, buffer[..length].to_vec()));
+            buffer.advance(length);
+        }
+        return Ok(message);
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        trace!("CoapMessage::encode");
+        let mut buffer = BytesMut::with_capacity(4 + self.token.len() + self.payload.len() + 16);
+        buffer.put_u8(VERSION << 6 | self.message_type.as_u8() << 4 | self.token.len() as u8);
+        buffer.put_u8(self.code.as_u8());
+        buffer.put_u16(self.message_id);
+        buffer.put_slice(&self.token);
+        let mut previous = 0;
+        for (number, value) in &self.options {
+            let (delta, delta_extended) = Self::split_extended(number - previous);
+            let (length, length_extended) = Self::split_extended(value.len() as u16);
+            buffer.put_u8(delta << 4 | length);
+            buffer.put_slice(&delta_extended);
+            buffer.put_slice(&length_extended);
+            buffer.put_slice(value);
+            previous = *number;
+        }
+        if !self.payload.is_empty() {
+            buffer.put_u8(PAYLOAD_MARKER);
+            buffer.put_slice(&self.payload);
+        }
+        return buffer;
+    }
+
+    //Option delta and length nibbles: 13 adds one extra byte, 14 adds two, 15 is reserved
+    fn read_extended(buffer: &mut &[u8], nibble: u8, err: DecodeError) -> DecodeResult<u16> {
+        return match nibble {
+            13 if buffer.remaining() >= 1 => { Ok(buffer.get_u8() as u16 + 13) }
+            14 if buffer.remaining() >= 2 => { buffer.get_u16().checked_add(269).ok_or(err) }
+            0..=12 => { Ok(nibble as u16) }
+            _ => { Err(err) }
+        };
+    }
+
+    fn split_extended(value: u16) -> (u8, Vec<u8>) {
+        return match value {
+            0..=12 => { (value as u8, vec![]) }
+            13..=268 => { (13, vec![(value - 13) as u8]) }
+            _ => { (14, (value - 269).to_be_bytes().to_vec()) }
+        };
+    }
+}
@@ -0,0 +1,4 @@
+#[cfg(feature = "mqtt-sn")]
+pub mod mqtt_sn;
+#[cfg(feature = "coap")]
+pub mod coap;
@@ -0,0 +1,342 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use log::{debug, error, info, trace, warn};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+use crate::config::broker_config::BrokerConfig;
+use crate::connection::client_context::ClientContext;
+use crate::connection::listeners::{ListenerMode, Listeners, MQTT_SN_LISTENER};
+use crate::connection::virtual_endpoint::VirtualEndpoints;
+use crate::gateway::mqtt_sn::message::{MqttSnMessage, ReturnCode, SnTopic};
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::qos_level::QoSLevel;
+use crate::model::reason_code::ReasonCode;
+use crate::model::variable_header::ConnectFlags;
+
+const ENDPOINT_CAPACITY: usize = 1000;
+
+//Per sensor state: the virtual socket the broker knows it by and the topic ids it registered
+#[derive(Debug)]
+pub struct MqttSnClient {
+    broker_socket: SocketAddr,
+    id2topic: DashMap<u16, String>,
+    topic2id: DashMap<String, u16>,
+    next_topic_id: AtomicU16,
+    //Topic id of PUBLISH/SUBSCRIBE waiting for PUBACK/SUBACK from the broker, by message id
+    pending_topic_ids: DashMap<u16, u16>,
+    //When the sensor was last heard from and how long it may stay silent, from the duration of its CONNECT
+    activity: Mutex<(Instant, Option<Duration>)>,
+}
+
+impl MqttSnClient {
+    pub(crate) fn new(broker_socket: SocketAddr) -> Self {
+        Self {
+            broker_socket,
+            id2topic: DashMap::new(),
+            topic2id: DashMap::new(),
+            next_topic_id: AtomicU16::new(1),
+            pending_topic_ids: DashMap::new(),
+            activity: Mutex::new((Instant::now(), None)),
+        }
+    }
+
+    fn connected(&self, keep_alive: Option<Duration>) {
+        *self.activity.lock().unwrap() = (Instant::now(), keep_alive);
+    }
+
+    fn heard_from(&self) {
+        self.activity.lock().unwrap().0 = Instant::now();
+    }
+
+    //None while the sensor may stay silent for good
+    fn expires_at(&self) -> Option<Instant> {
+        let (last_activity, keep_alive) = *self.activity.lock().unwrap();
+        keep_alive.map(|keep_alive| last_activity + keep_alive)
+    }
+
+    //Returns the topic id and whether it was just assigned
+    fn register_topic(&self, topic_name: &String) -> (u16, bool) {
+        if let Some(topic_id) = self.topic2id.get(topic_name) {
+            return (*topic_id, false);
+        }
+        let topic_id = self.next_topic_id.fetch_add(1, Ordering::Relaxed);
+        self.topic2id.insert(topic_name.clone(), topic_id);
+        self.id2topic.insert(topic_id, topic_name.clone());
+        return (topic_id, true);
+    }
+
+    fn topic_name(&self, topic: &SnTopic) -> Option<String> {
+        return match topic {
+            SnTopic::Name(topic_name) | SnTopic::Short(topic_name) => { Some(topic_name.clone()) }
+            SnTopic::Id(topic_id) => { self.id2topic.get(topic_id).map(|topic_name| topic_name.clone()) }
+            SnTopic::Predefined(_) => { None }
+        };
+    }
+}
+
+//Translates MQTT-SN datagrams into ControlPackets for the broker and the broker's answers back.
+//Each sensor is a virtual endpoint, so sessions, subscriptions and routing are the ones of TCP clients.
+#[derive(Debug)]
+#[derive(Clone)]
+pub struct MqttSnGateway {
+    config: Arc<BrokerConfig>,
+    listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>,
+    virtual_endpoints: Arc<VirtualEndpoints>,
+    clients: Arc<DashMap<SocketAddr, Arc<MqttSnClient>>>,
+    listeners: Arc<Listeners>,
+    mode: Option<watch::Receiver<ListenerMode>>,
+}
+
+impl MqttSnGateway {
+    pub async fn handle_datagrams(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let address = SocketAddr::from(([0, 0, 0, 0], self.config.gateway.mqtt_sn.port));
+        let udp_socket = Arc::new(UdpSocket::bind(address).await?);
+        info!("MQTT-SN gateway listening on UDP {}", address);
+        let mut buffer = vec![0; u16::MAX as usize];
+        loop {
+            let (length, sensor) = match udp_socket.recv_from(&mut buffer).await {
+                Ok(result) => { result }
+                Err(err) => {
+                    error!("Can't read MQTT-SN datagram. {}", err);
+                    continue;
+                }
+            };
+            match MqttSnMessage::decode(&buffer[..length]) {
+                Ok(message) => {
+                    if let Err(err) = self.handle_message(sensor, message, &udp_socket).await {
+                        error!("Can't handle MQTT-SN message from {}. {}", sensor, err);
+                    }
+                }
+                Err(err) => {
+                    warn!("Dropping malformed MQTT-SN datagram from {}: {:?}", sensor, err);
+                }
+            }
+        }
+    }
+
+    pub async fn handle_message(&self, sensor: SocketAddr, message: MqttSnMessage, udp_socket: &Arc<UdpSocket>) -> Result<(), String> {
+        trace!("MqttSnGateway::handle_message");
+        debug!("MQTT-SN message from {}: {:?}", sensor, message);
+        if let MqttSnMessage::Connect { clean_session, duration, client_id } = message {
+            //UDP has no connection to refuse, a draining gateway turns away the sensors it doesn't know
+            if self.is_draining() && !self.clients.contains_key(&sensor) {
+                debug!("MQTT-SN gateway is draining, refusing sensor {}", sensor);
+                return Self::send_to_sensor(udp_socket, &sensor, &MqttSnMessage::Connack { return_code: ReturnCode::Congestion }).await;
+            }
+            let client = self.connect_client(sensor, udp_socket);
+            client.connected(self.config.keep_alive.deadline(duration));
+            let connect_flags = ConnectFlags::new(false, false, false, QoSLevel::AtMostOnce, false, clean_session, false);
+            let connect_packet = ControlPacket::connect(connect_flags, Some(duration), vec![], Some(client_id), None, None, None, None, None);
+            return self.send_to_broker(client.broker_socket, connect_packet).await;
+        }
+
+        let client = match self.clients.get(&sensor) {
+            Some(client) => { client.clone() }
+            None => { return Err(String::from("Sensor is not connected")); }
+        };
+        client.heard_from();
+        return match message {
+            MqttSnMessage::Register { msg_id, topic_name, .. } => {
+                let (topic_id, _) = client.register_topic(&topic_name);
+                let regack = MqttSnMessage::Regack { topic_id, msg_id, return_code: ReturnCode::Accepted };
+                Self::send_to_sensor(udp_socket, &sensor, &regack).await
+            }
+            MqttSnMessage::Publish { qos, retain, topic, msg_id, data, .. } => {
+                let topic_id = match &topic {
+                    SnTopic::Id(topic_id) | SnTopic::Predefined(topic_id) => { *topic_id }
+                    _ => { 0 }
+                };
+                let topic_name = match client.topic_name(&topic) {
+                    Some(topic_name) => { topic_name }
+                    None => {
+                        let puback = MqttSnMessage::Puback { topic_id, msg_id, return_code: ReturnCode::InvalidTopicId };
+                        return Self::send_to_sensor(udp_socket, &sensor, &puback).await;
+                    }
+                };
+                match qos {
+                    QoSLevel::AtMostOnce => {
+                        let publish_packet = ControlPacket::publish_with_payload(None, topic_name, qos, retain, vec![], data);
+                        self.send_to_broker(client.broker_socket, publish_packet).await
+                    }
+                    QoSLevel::AtLeastOnce => {
+                        client.pending_topic_ids.insert(msg_id, topic_id);
+                        let publish_packet = ControlPacket::publish_with_payload(Some(msg_id), topic_name, qos, retain, vec![], data);
+                        self.send_to_broker(client.broker_socket, publish_packet).await
+                    }
+                    QoSLevel::ExactlyOnce => {
+                        let puback = MqttSnMessage::Puback { topic_id, msg_id, return_code: ReturnCode::NotSupported };
+                        Self::send_to_sensor(udp_socket, &sensor, &puback).await
+                    }
+                }
+            }
+            MqttSnMessage::Subscribe { qos, msg_id, topic, .. } => {
+                let topic_name = match client.topic_name(&topic) {
+                    Some(topic_name) => { topic_name }
+                    None => {
+                        let suback = MqttSnMessage::Suback { qos, topic_id: 0, msg_id, return_code: ReturnCode::InvalidTopicId };
+                        return Self::send_to_sensor(udp_socket, &sensor, &suback).await;
+                    }
+                };
+                //Topic filters with wildcards get their topic ids through REGISTER when messages arrive
+                if let SnTopic::Name(topic_name) = &topic {
+                    if !topic_name.contains(|c| c == '+' || c == '#') {
+                        let (topic_id, _) = client.register_topic(topic_name);
+                        client.pending_topic_ids.insert(msg_id, topic_id);
+                    }
+                }
+                let subscribe_packet = ControlPacket::subscribe(Some(msg_id), topic_name, qos);
+                self.send_to_broker(client.broker_socket, subscribe_packet).await
+            }
+            MqttSnMessage::Puback { msg_id, .. } => {
+                self.send_to_broker(client.broker_socket, ControlPacket::puback(Some(msg_id))).await
+            }
+            MqttSnMessage::Regack { topic_id, return_code, .. } => {
+                debug!("Sensor {} acknowledged topic id {:?}: {:?}", sensor, topic_id, return_code);
+                Ok(())
+            }
+            MqttSnMessage::Pingreq { .. } => {
+                self.send_to_broker(client.broker_socket, ControlPacket::pingreq()).await
+            }
+            MqttSnMessage::Disconnect { .. } => {
+                self.send_to_broker(client.broker_socket, ControlPacket::disconnect(ReasonCode::NormalDisconnection)).await
+            }
+            unsupported => {
+                Err(format!("Unsupported MQTT-SN message from sensor: {:?}", unsupported))
+            }
+        };
+    }
+
+    fn connect_client(&self, sensor: SocketAddr, udp_socket: &Arc<UdpSocket>) -> Arc<MqttSnClient> {
+        if let Some(client) = self.clients.get(&sensor) {
+            return client.clone();
+        }
+        let (broker_socket, from_broker) = self.virtual_endpoints.register(ENDPOINT_CAPACITY);
+        let client = Arc::new(MqttSnClient::new(broker_socket));
+        self.clients.insert(sensor, client.clone());
+        self.listeners.opened(MQTT_SN_LISTENER);
+        info!("MQTT-SN sensor {} connected as {}", sensor, broker_socket);
+        tokio::spawn(self.clone().handle_broker_packets(sensor, client.clone(), from_broker, udp_socket.clone()));
+        return client;
+    }
+
+    fn is_draining(&self) -> bool {
+        self.mode.as_ref().is_some_and(|mode| *mode.borrow() == ListenerMode::Draining)
+    }
+
+    //Ends with the DISCONNECT of the broker, or once the sensor stays silent past its keep alive
+    async fn handle_broker_packets(self, sensor: SocketAddr, client: Arc<MqttSnClient>, mut from_broker: Receiver<ControlPacket>, udp_socket: Arc<UdpSocket>) {
+        loop {
+            let expires_at = client.expires_at();
+            let control_packet = tokio::select! {
+                control_packet = from_broker.recv() => {
+                    match control_packet {
+                        Some(control_packet) => { control_packet }
+                        None => { break; }
+                    }
+                }
+                _ = tokio::time::sleep_until(expires_at.unwrap_or_else(Instant::now)), if expires_at.is_some() => {
+                    //The sensor may have been heard from while sleeping
+                    if client.expires_at().is_some_and(|expires_at| expires_at <= Instant::now()) {
+                        info!("MQTT-SN sensor {} stayed silent past its keep alive", sensor);
+                        //Closed as a lost connection, not a normal disconnection
+                        let disconnect_packet = ControlPacket::disconnect(ReasonCode::DisconnectWithWillMessage);
+                        if let Err(err) = self.send_to_broker(client.broker_socket, disconnect_packet).await {
+                            error!("{}", err);
+                        }
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let is_disconnection = control_packet.fixed_header().packet_type() == ControlPacketType::DISCONNECT;
+            for message in Self::translate(&client, &control_packet) {
+                if let Err(err) = Self::send_to_sensor(&udp_socket, &sensor, &message).await {
+                    error!("{}", err);
+                }
+            }
+            if is_disconnection {
+                break;
+            }
+        }
+        self.clients.remove(&sensor);
+        self.virtual_endpoints.unregister(&client.broker_socket);
+        self.listeners.closed(MQTT_SN_LISTENER);
+        info!("MQTT-SN sensor {} disconnected", sensor);
+    }
+
+    pub fn translate(client: &MqttSnClient, control_packet: &ControlPacket) -> Vec<MqttSnMessage> {
+        return match control_packet.fixed_header().packet_type() {
+            ControlPacketType::CONNACK => {
+                let return_code = match control_packet.variable_header().reason_code() {
+                    Some(ReasonCode::Success) => { ReturnCode::Accepted }
+                    _ => { ReturnCode::NotSupported }
+                };
+                vec![MqttSnMessage::Connack { return_code }]
+            }
+            ControlPacketType::PUBACK => {
+                let msg_id = control_packet.variable_header().packet_identifier_opt().unwrap_or(0);
+                let topic_id = client.pending_topic_ids.remove(&msg_id).map(|(_, topic_id)| topic_id).unwrap_or(0);
+                vec![MqttSnMessage::Puback { topic_id, msg_id, return_code: ReturnCode::Accepted }]
+            }
+            ControlPacketType::SUBACK => {
+                let msg_id = control_packet.variable_header().packet_identifier_opt().unwrap_or(0);
+                let topic_id = client.pending_topic_ids.remove(&msg_id).map(|(_, topic_id)| topic_id).unwrap_or(0);
+                let (qos, return_code) = match control_packet.payload().reason_codes().first() {
+                    Some(ReasonCode::GrantedQoS0) => { (QoSLevel::AtMostOnce, ReturnCode::Accepted) }
+                    Some(ReasonCode::GrantedQoS1) => { (QoSLevel::AtLeastOnce, ReturnCode::Accepted) }
+                    Some(ReasonCode::GrantedQoS2) => { (QoSLevel::ExactlyOnce, ReturnCode::Accepted) }
+                    _ => { (QoSLevel::AtMostOnce, ReturnCode::NotSupported) }
+                };
+                vec![MqttSnMessage::Suback { qos, topic_id, msg_id, return_code }]
+            }
+            ControlPacketType::PUBLISH => {
+                let topic_name = control_packet.variable_header().topic_name();
+                let data = control_packet.payload_opt().map(|payload| payload.data().clone()).unwrap_or_default();
+                let qos = *control_packet.fixed_header().qos_level();
+                let msg_id = control_packet.variable_header().packet_identifier_opt().unwrap_or(0);
+                let retain = *control_packet.fixed_header().retain();
+                if topic_name.len() == 2 {
+                    return vec![MqttSnMessage::Publish { dup: false, qos, retain, topic: SnTopic::Short(topic_name.clone()), msg_id, data }];
+                }
+                let mut messages = Vec::with_capacity(2);
+                let (topic_id, registered) = client.register_topic(topic_name);
+                if registered {
+                    messages.push(MqttSnMessage::Register { topic_id, msg_id: 0, topic_name: topic_name.clone() });
+                }
+                messages.push(MqttSnMessage::Publish { dup: false, qos, retain, topic: SnTopic::Id(topic_id), msg_id, data });
+                messages
+            }
+            ControlPacketType::PINGRESP => { vec![MqttSnMessage::Pingresp] }
+            ControlPacketType::DISCONNECT => { vec![MqttSnMessage::Disconnect { duration: None }] }
+            packet_type => {
+                debug!("No MQTT-SN counterpart for {:?}", packet_type);
+                vec![]
+            }
+        };
+    }
+
+    async fn send_to_broker(&self, broker_socket: SocketAddr, control_packet: ControlPacket) -> Result<(), String> {
+        return self.listener2broker.send((ClientContext::new(broker_socket), control_packet)).await
+            .map_err(|err| format!("Can't send message to broker: {:?}", err));
+    }
+
+    async fn send_to_sensor(udp_socket: &UdpSocket, sensor: &SocketAddr, message: &MqttSnMessage) -> Result<(), String> {
+        return match udp_socket.send_to(&message.encode(), sensor).await {
+            Ok(_) => { Ok(()) }
+            Err(err) => { Err(format!("Can't send MQTT-SN message to {}. {}", sensor, err)) }
+        };
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>, virtual_endpoints: Arc<VirtualEndpoints>, listeners: Arc<Listeners>) -> Self {
+        let mode = listeners.watch(MQTT_SN_LISTENER);
+        Self { config, listener2broker, virtual_endpoints, clients: Arc::new(DashMap::new()), listeners, mode }
+    }
+}
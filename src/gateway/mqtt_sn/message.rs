@@ -0,0 +1,330 @@
+use bytes::{Buf, BufMut, BytesMut};
+use log::{error, trace};
+
+use crate::model::qos_level::QoSLevel;
+use crate::serdes::deserializer::error::{DecodeError, DecodeResult, ReadError};
+
+//MQTT-SN v1.2 message types handled by the gateway
+const CONNECT: u8 = 0x04;
+const CONNACK: u8 = 0x05;
+const REGISTER: u8 = 0x0A;
+const REGACK: u8 = 0x0B;
+const PUBLISH: u8 = 0x0C;
+const PUBACK: u8 = 0x0D;
+const SUBSCRIBE: u8 = 0x12;
+const SUBACK: u8 = 0x13;
+const PINGREQ: u8 = 0x16;
+const PINGRESP: u8 = 0x17;
+const DISCONNECT: u8 = 0x18;
+
+const FLAG_DUP: u8 = 0b1000_0000;
+const FLAG_RETAIN: u8 = 0b0001_0000;
+const FLAG_CLEAN_SESSION: u8 = 0b0000_0100;
+
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+pub enum ReturnCode {
+    Accepted,
+    Congestion,
+    InvalidTopicId,
+    NotSupported,
+}
+
+impl ReturnCode {
+    pub fn as_u8(&self) -> u8 {
+        return match self {
+            ReturnCode::Accepted => { 0x00 }
+            ReturnCode::Congestion => { 0x01 }
+            ReturnCode::InvalidTopicId => { 0x02 }
+            ReturnCode::NotSupported => { 0x03 }
+        };
+    }
+
+    pub fn from_u8(value: u8) -> Option<ReturnCode> {
+        return match value {
+            0x00 => { Some(ReturnCode::Accepted) }
+            0x01 => { Some(ReturnCode::Congestion) }
+            0x02 => { Some(ReturnCode::InvalidTopicId) }
+            0x03 => { Some(ReturnCode::NotSupported) }
+            _ => { None }
+        };
+    }
+}
+
+//How a PUBLISH/SUBSCRIBE refers to its topic, selected by the TopicIdType flag bits
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+pub enum SnTopic {
+    //Topic name, SUBSCRIBE only
+    Name(String),
+    //Topic id obtained with REGISTER
+    Id(u16),
+    //Topic id agreed out of band
+    Predefined(u16),
+    //Two characters topic name sent in place of the topic id
+    Short(String),
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+pub enum MqttSnMessage {
+    Connect { clean_session: bool, duration: u16, client_id: String },
+    Connack { return_code: ReturnCode },
+    Register { topic_id: u16, msg_id: u16, topic_name: String },
+    Regack { topic_id: u16, msg_id: u16, return_code: ReturnCode },
+    Publish { dup: bool, qos: QoSLevel, retain: bool, topic: SnTopic, msg_id: u16, data: Vec<u8> },
+    Puback { topic_id: u16, msg_id: u16, return_code: ReturnCode },
+    Subscribe { dup: bool, qos: QoSLevel, msg_id: u16, topic: SnTopic },
+    Suback { qos: QoSLevel, topic_id: u16, msg_id: u16, return_code: ReturnCode },
+    Pingreq { client_id: Option<String> },
+    Pingresp,
+    Disconnect { duration: Option<u16> },
+}
+
+impl MqttSnMessage {
+    pub fn decode(datagram: &[u8]) -> DecodeResult<MqttSnMessage> {
+        trace!("MqttSnMessage::decode");
+        let mut buffer = datagram;
+        let length = match Self::read_u8(&mut buffer, DecodeError::RemainingLength { cause: ReadError::InvalidData })? {
+            0x01 => { Self::read_u16(&mut buffer, DecodeError::RemainingLength { cause: ReadError::InvalidData })? as usize }
+            length => { length as usize }
+        };
+        if length != datagram.len() {
+            error!("MQTT-SN Length {:?} doesn't match datagram size {:?}", length, datagram.len());
+            return Err(DecodeError::RemainingLength { cause: ReadError::InvalidData });
+        }
+        let msg_type = Self::read_u8(&mut buffer, DecodeError::PacketType { cause: ReadError::InvalidData })?;
+        let err = DecodeError::VariableHeaderAndPayload { cause: ReadError::InvalidData };
+        return match msg_type {
+            CONNECT => {
+                let flags = Self::read_u8(&mut buffer, err)?;
+                let _protocol_id = Self::read_u8(&mut buffer, err)?;
+                let duration = Self::read_u16(&mut buffer, err)?;
+                let client_id = Self::read_string(&mut buffer, DecodeError::ClientId { cause: ReadError::InvalidData })?;
+                Ok(MqttSnMessage::Connect { clean_session: flags & FLAG_CLEAN_SESSION != 0, duration, client_id })
+            }
+            REGISTER => {
+                let topic_id = Self::read_u16(&mut buffer, err)?;
+                let msg_id = Self::read_u16(&mut buffer, err)?;
+                let topic_name = Self::read_string(&mut buffer, DecodeError::TopicName { cause: ReadError::InvalidData })?;
+                Ok(MqttSnMessage::Register { topic_id, msg_id, topic_name })
+            }
+            PUBLISH => {
+                let flags = Self::read_u8(&mut buffer, err)?;
+                let topic = match flags & 0b11 {
+                    0b00 => { SnTopic::Id(Self::read_u16(&mut buffer, err)?) }
+                    0b01 => { SnTopic::Predefined(Self::read_u16(&mut buffer, err)?) }
+                    0b10 => { SnTopic::Short(Self::read_short_topic(&mut buffer)?) }
+                    _ => { return Err(DecodeError::TopicName { cause: ReadError::InvalidData }); }
+                };
+                let msg_id = Self::read_u16(&mut buffer, err)?;
+                Ok(MqttSnMessage::Publish {
+                    dup: flags & FLAG_DUP != 0,
+                    qos: Self::qos_from_flags(flags)?,
+                    retain: flags & FLAG_RETAIN != 0,
+                    topic,
+                    msg_id,
+                    data: buffer.to_vec(),
+                })
+            }
+            PUBACK => {
+                let topic_id = Self::read_u16(&mut buffer, err)?;
+                let msg_id = Self::read_u16(&mut buffer, err)?;
+                let return_code = Self::read_return_code(&mut buffer)?;
+                Ok(MqttSnMessage::Puback { topic_id, msg_id, return_code })
+            }
+            SUBSCRIBE => {
+                let flags = Self::read_u8(&mut buffer, err)?;
+                let msg_id = Self::read_u16(&mut buffer, err)?;
+                let topic = match flags & 0b11 {
+                    0b00 => { SnTopic::Name(Self::read_string(&mut buffer, DecodeError::TopicFilter { cause: ReadError::InvalidData })?) }
+                    0b01 => { SnTopic::Predefined(Self::read_u16(&mut buffer, err)?) }
+                    0b10 => { SnTopic::Short(Self::read_short_topic(&mut buffer)?) }
+                    _ => { return Err(DecodeError::TopicFilter { cause: ReadError::InvalidData }); }
+                };
+                Ok(MqttSnMessage::Subscribe { dup: flags & FLAG_DUP != 0, qos: Self::qos_from_flags(flags)?, msg_id, topic })
+            }
+            PINGREQ => {
+                let client_id = if buffer.has_remaining() {
+                    Some(Self::read_string(&mut buffer, DecodeError::ClientId { cause: ReadError::InvalidData })?)
+                } else {
+                    None
+                };
+                Ok(MqttSnMessage::Pingreq { client_id })
+            }
+            DISCONNECT => {
+                let duration = if buffer.has_remaining() {
+                    Some(Self::read_u16(&mut buffer, err)?)
+                } else {
+                    None
+                };
+                Ok(MqttSnMessage::Disconnect { duration })
+            }
+            unsupported => {
+                error!("Unsupported MQTT-SN message type: {:#04X?}", unsupported);
+                Err(DecodeError::PacketType { cause: ReadError::InvalidData })
+            }
+        };
+    }
+
+    pub fn encode(&self) -> BytesMut {
+        trace!("MqttSnMessage::encode");
+        let mut body = BytesMut::new();
+        match self {
+            MqttSnMessage::Connect { clean_session, duration, client_id } => {
+                body.put_u8(CONNECT);
+                body.put_u8(if *clean_session { FLAG_CLEAN_SESSION } else { 0 });
+                body.put_u8(0x01);
+                body.put_u16(*duration);
+                body.put_slice(client_id.as_bytes());
+            }
+            MqttSnMessage::Connack { return_code } => {
+                body.put_u8(CONNACK);
+                body.put_u8(return_code.as_u8());
+            }
+            MqttSnMessage::Register { topic_id, msg_id, topic_name } => {
+                body.put_u8(REGISTER);
+                body.put_u16(*topic_id);
+                body.put_u16(*msg_id);
+                body.put_slice(topic_name.as_bytes());
+            }
+            MqttSnMessage::Regack { topic_id, msg_id, return_code } => {
+                body.put_u8(REGACK);
+                body.put_u16(*topic_id);
+                body.put_u16(*msg_id);
+                body.put_u8(return_code.as_u8());
+            }
+            MqttSnMessage::Publish { dup, qos, retain, topic, msg_id, data } => {
+                body.put_u8(PUBLISH);
+                body.put_u8(Self::flags(*dup, qos, *retain, topic));
+                Self::put_topic(&mut body, topic);
+                body.put_u16(*msg_id);
+                body.put_slice(data);
+            }
+            MqttSnMessage::Puback { topic_id, msg_id, return_code } => {
+                body.put_u8(PUBACK);
+                body.put_u16(*topic_id);
+                body.put_u16(*msg_id);
+                body.put_u8(return_code.as_u8());
+            }
+            MqttSnMessage::Subscribe { dup, qos, msg_id, topic } => {
+                body.put_u8(SUBSCRIBE);
+                body.put_u8(Self::flags(*dup, qos, false, topic));
+                body.put_u16(*msg_id);
+                Self::put_topic(&mut body, topic);
+            }
+            MqttSnMessage::Suback { qos, topic_id, msg_id, return_code } => {
+                body.put_u8(SUBACK);
+                body.put_u8(Self::flags(false, qos, false, &SnTopic::Id(*topic_id)));
+                body.put_u16(*topic_id);
+                body.put_u16(*msg_id);
+                body.put_u8(return_code.as_u8());
+            }
+            MqttSnMessage::Pingreq { client_id } => {
+                body.put_u8(PINGREQ);
+                if let Some(client_id) = client_id {
+                    body.put_slice(client_id.as_bytes());
+                }
+            }
+            MqttSnMessage::Pingresp => {
+                body.put_u8(PINGRESP);
+            }
+            MqttSnMessage::Disconnect { duration } => {
+                body.put_u8(DISCONNECT);
+                if let Some(duration) = duration {
+                    body.put_u16(*duration);
+                }
+            }
+        }
+
+        //Length counts itself: 1 byte up to 255, otherwise 0x01 followed by 2 bytes
+        let mut buffer = BytesMut::with_capacity(body.len() + 3);
+        if body.len() < 0xFF {
+            buffer.put_u8((body.len() + 1) as u8);
+        } else {
+            buffer.put_u8(0x01);
+            buffer.put_u16((body.len() + 3) as u16);
+        }
+        buffer.put_slice(&body);
+        return buffer;
+    }
+
+    fn flags(dup: bool, qos: &QoSLevel, retain: bool, topic: &SnTopic) -> u8 {
+        let mut flags = 0;
+        if dup {
+            flags |= FLAG_DUP;
+        }
+        flags |= match qos {
+            QoSLevel::AtMostOnce => { 0b00 }
+            QoSLevel::AtLeastOnce => { 0b01 }
+            QoSLevel::ExactlyOnce => { 0b10 }
+        } << 5;
+        if retain {
+            flags |= FLAG_RETAIN;
+        }
+        flags |= match topic {
+            SnTopic::Name(_) | SnTopic::Id(_) => { 0b00 }
+            SnTopic::Predefined(_) => { 0b01 }
+            SnTopic::Short(_) => { 0b10 }
+        };
+        return flags;
+    }
+
+    fn put_topic(buffer: &mut BytesMut, topic: &SnTopic) {
+        match topic {
+            SnTopic::Name(topic_name) => { buffer.put_slice(topic_name.as_bytes()); }
+            SnTopic::Id(topic_id) | SnTopic::Predefined(topic_id) => { buffer.put_u16(*topic_id); }
+            SnTopic::Short(topic_name) => { buffer.put_slice(topic_name.as_bytes()); }
+        }
+    }
+
+    fn qos_from_flags(flags: u8) -> DecodeResult<QoSLevel> {
+        //0b11 is QoS -1 (publish without connection), not supported by the gateway
+        return match QoSLevel::from_u8((flags >> 5) & 0b11) {
+            Some(qos) => { Ok(qos) }
+            None => { Err(DecodeError::QoSLevel { cause: ReadError::InvalidData }) }
+        };
+    }
+
+    fn read_u8(buffer: &mut &[u8], err: DecodeError) -> DecodeResult<u8> {
+        if buffer.remaining() < 1 {
+            return Err(err);
+        }
+        return Ok(buffer.get_u8());
+    }
+
+    fn read_u16(buffer: &mut &[u8], err: DecodeError) -> DecodeResult<u16> {
+        if buffer.remaining() < 2 {
+            return Err(err);
+        }
+        return Ok(buffer.get_u16());
+    }
+
+    fn read_short_topic(buffer: &mut &[u8]) -> DecodeResult<String> {
+        if buffer.remaining() < 2 {
+            return Err(DecodeError::TopicName { cause: ReadError::InvalidData });
+        }
+        let topic_name = String::from_utf8(buffer[..2].to_vec())
+            .map_err(|_| DecodeError::TopicName { cause: ReadError::InvalidData })?;
+        buffer.advance(2);
+        return Ok(topic_name);
+    }
+
+    //Strings have no length prefix in MQTT-SN, they take the rest of the message
+    fn read_string(buffer: &mut &[u8], err: DecodeError) -> DecodeResult<String> {
+        let value = String::from_utf8(buffer.to_vec()).map_err(|_| err)?;
+        buffer.advance(buffer.remaining());
+        return Ok(value);
+    }
+
+    fn read_return_code(buffer: &mut &[u8]) -> DecodeResult<ReturnCode> {
+        let err = DecodeError::ReasonCode { cause: ReadError::InvalidData };
+        return match ReturnCode::from_u8(Self::read_u8(buffer, err)?) {
+            Some(return_code) => { Ok(return_code) }
+            None => { Err(err) }
+        };
+    }
+}
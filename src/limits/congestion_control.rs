@@ -0,0 +1,106 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+
+use log::debug;
+use metered::HitCount;
+use rand::Rng;
+use serde::Serialize;
+use tokio::sync::mpsc::Sender;
+
+use crate::config::broker_config::CongestionConfig;
+use crate::model::control_packet::ControlPacket;
+use crate::model::qos_level::QoSLevel;
+
+//Drop counter of the topics outside of every configured prefix
+pub const OTHER_TOPICS: &str = "#";
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct PrefixDropMetrics {
+    pub(crate) qos0_dropped: HitCount,
+}
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct BusyConnectMetrics {
+    pub(crate) refused: HitCount,
+}
+
+//User property of a CONNACK Server Busy, seconds the client should wait before connecting again
+pub const RETRY_AFTER_PROPERTY: &str = "retry-after";
+
+//Exposed on /metrics with the topic prefix in the path
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct CongestionMetrics(pub(crate) BTreeMap<String, PrefixDropMetrics>);
+
+//Sheds best-effort deliveries while the connection writers fall behind, so that QoS 0 telemetry
+//doesn't delay the acknowledged traffic queued behind it
+#[derive(Debug)]
+pub struct CongestionControl {
+    config: CongestionConfig,
+    pub(crate) metrics: CongestionMetrics,
+    pub(crate) connect_metrics: BusyConnectMetrics,
+}
+
+impl CongestionControl {
+    pub fn is_congested(&self, queued: usize) -> bool {
+        self.config.queue_threshold > 0 && queued >= self.config.queue_threshold
+    }
+
+    //Whether a delivery of topic_name at qos_level is dropped with queued packets ahead of it
+    pub fn shed(&self, topic_name: &str, qos_level: QoSLevel, queued: usize) -> bool {
+        self.shed_scaled(topic_name, qos_level, queued, 1.0)
+    }
+
+    //The same with drop_probability multiplied by scale, see FairShare
+    pub fn shed_scaled(&self, topic_name: &str, qos_level: QoSLevel, queued: usize, scale: f64) -> bool {
+        if qos_level != QoSLevel::AtMostOnce || !self.is_congested(queued) {
+            return false;
+        }
+        let drop_probability = (self.config.drop_probability * scale).clamp(0.0, 1.0);
+        if !rand::thread_rng().gen_bool(drop_probability) {
+            return false;
+        }
+        debug!("Dropping QoS 0 delivery on topic {:?}, {} packets queued", topic_name, queued);
+        self.prefix_metrics(topic_name).qos0_dropped.incr();
+        return true;
+    }
+
+    //Seconds a CONNECT refused with queued packets ahead should wait, None when it is accepted.
+    //The deeper the queue, the longer the wait. The jitter spreads the reconnects of clients refused together.
+    pub fn connect_retry_after(&self, queued: usize) -> Option<u64> {
+        let threshold = self.config.connect_queue_threshold;
+        if threshold == 0 || queued < threshold {
+            return None;
+        }
+        let max_secs = self.config.retry_after_max_secs.max(1) as f64;
+        let min_secs = (self.config.retry_after_min_secs as f64).clamp(1.0, max_secs);
+        let retry_after = (min_secs * queued as f64 / threshold as f64).min(max_secs);
+        let jitter = rand::thread_rng().gen_range(0.0..=retry_after / 2.0);
+        self.connect_metrics.refused.incr();
+        return Some((retry_after + jitter).min(max_secs).round() as u64);
+    }
+
+    fn prefix_metrics(&self, topic_name: &str) -> &PrefixDropMetrics {
+        self.metrics.0.iter()
+            .filter(|(prefix, _)| prefix.as_str() != OTHER_TOPICS && topic_name.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .or_else(|| self.metrics.0.get_key_value(OTHER_TOPICS))
+            .map(|(_, metrics)| metrics)
+            .unwrap()
+    }
+
+    //Packets handed to the connection writers that they haven't picked up yet
+    pub fn queued(to_listener: &Sender<(Vec<SocketAddr>, ControlPacket)>) -> usize {
+        to_listener.max_capacity() - to_listener.capacity()
+    }
+
+    pub fn new(config: CongestionConfig) -> Self {
+        let metrics = config.drop_counter_prefixes.iter()
+            .chain([String::from(OTHER_TOPICS)].iter())
+            .map(|prefix| (prefix.clone(), PrefixDropMetrics::default()))
+            .collect();
+        Self { config, metrics: CongestionMetrics(metrics), connect_metrics: BusyConnectMetrics::default() }
+    }
+}
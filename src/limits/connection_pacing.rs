@@ -0,0 +1,110 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::debug;
+use metered::HitCount;
+use rand::Rng;
+use serde::Serialize;
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct PacingCounters {
+    //Went on right away, out of the burst
+    pub(crate) immediate: HitCount,
+    //Waited for their turn
+    pub(crate) paced: HitCount,
+}
+
+//Exported with the metrics
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct PacingMetrics {
+    //0 when not paced
+    per_sec: u32,
+    //Left of the burst
+    tokens: f64,
+    //Waiting for their turn now
+    waiting: u64,
+    //Milliseconds the last one to come has to wait
+    delay_ms: u64,
+    immediate: u64,
+    paced: u64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    //Below 0 by the number of reserved turns not come yet
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+//A token bucket refilled with per_sec tokens a second up to burst. Each connection takes a token and waits
+//for it when the bucket is empty, so a reconnect storm is let in at the configured rate instead of all at once.
+#[derive(Debug)]
+pub struct ConnectionPacing {
+    name: &'static str,
+    per_sec: u32,
+    burst: u32,
+    jitter_ms: u64,
+    bucket: Mutex<Bucket>,
+    pub(crate) counters: PacingCounters,
+}
+
+impl ConnectionPacing {
+    //Waits for the turn of one more connection
+    pub async fn pace(&self) {
+        let delay = self.reserve(Instant::now());
+        if delay.is_zero() {
+            return;
+        }
+        let jitter = Duration::from_millis(rand::thread_rng().gen_range(0..=self.jitter_ms));
+        debug!("Pacing {} for {}ms", self.name, (delay + jitter).as_millis());
+        tokio::time::sleep(delay + jitter).await;
+    }
+
+    //Takes a token at now and returns how long to wait for it, without jitter
+    pub fn reserve(&self, now: Instant) -> Duration {
+        if self.per_sec == 0 {
+            return Duration::ZERO;
+        }
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket, now);
+        bucket.tokens -= 1.0;
+        if bucket.tokens >= 0.0 {
+            self.counters.immediate.incr();
+            return Duration::ZERO;
+        }
+        self.counters.paced.incr();
+        Duration::from_secs_f64(-bucket.tokens / self.per_sec as f64)
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let elapsed = now.saturating_duration_since(bucket.refilled_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_sec as f64).min(self.burst as f64);
+        bucket.refilled_at = bucket.refilled_at.max(now);
+    }
+
+    pub fn metrics(&self) -> PacingMetrics {
+        let mut bucket = self.bucket.lock().unwrap();
+        if self.per_sec > 0 {
+            self.refill(&mut bucket, Instant::now());
+        }
+        let waiting = (-bucket.tokens).max(0.0);
+        PacingMetrics {
+            per_sec: self.per_sec,
+            tokens: bucket.tokens.max(0.0),
+            waiting: waiting.ceil() as u64,
+            delay_ms: match self.per_sec {
+                0 => { 0 }
+                per_sec => { (waiting * 1000.0 / per_sec as f64) as u64 }
+            },
+            immediate: self.counters.immediate.0.get(),
+            paced: self.counters.paced.0.get(),
+        }
+    }
+
+    pub fn new(name: &'static str, per_sec: u32, burst: u32, jitter_ms: u64) -> Self {
+        let bucket = Mutex::new(Bucket { tokens: burst as f64, refilled_at: Instant::now() });
+        Self { name, per_sec, burst, jitter_ms, bucket, counters: PacingCounters::default() }
+    }
+}
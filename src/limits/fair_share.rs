@@ -0,0 +1,93 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::trace;
+use metered::HitCount;
+use serde::Serialize;
+
+use crate::config::broker_config::BrokerConfig;
+
+//Group of the clients without a quota profile
+pub const UNPROFILED_GROUP: &str = "-";
+
+const SHARE_WINDOW: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct GroupMetrics {
+    pub(crate) deliveries: HitCount,
+    pub(crate) qos0_shed: HitCount,
+}
+
+//Exposed on /metrics with the group name in the path
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct FairShareMetrics(pub(crate) BTreeMap<String, GroupMetrics>);
+
+#[derive(Debug)]
+struct ShareWindow {
+    start: Instant,
+    group2deliveries: HashMap<String, u64>,
+}
+
+//Splits the outbound queue between the client groups of the quota profiles by their weight while the connection
+//writers fall behind. The deliveries a PUBLISH fans out to count against the group of the publisher, and each group
+//is shed by how much of its share of the current window it used, so the burst of one group is shed before the
+//steady traffic of the others.
+#[derive(Debug)]
+pub struct FairShare {
+    config: Arc<BrokerConfig>,
+    window: Mutex<ShareWindow>,
+    pub(crate) metrics: FairShareMetrics,
+}
+
+impl FairShare {
+    pub fn is_enabled(&self) -> bool {
+        self.config.congestion.fair_share
+    }
+
+    pub fn weight(&self, group: &str) -> u32 {
+        self.config.quota.profiles.get(group)
+            .and_then(|profile| profile.weight)
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    //Counts the deliveries of the group and returns the deliveries it made in the window over its weighted
+    //share of them, 1 for a group using exactly its share
+    pub fn record(&self, group: &str, deliveries: usize, now: Instant) -> f64 {
+        trace!("FairShare::record");
+        if let Some(metrics) = self.metrics.0.get(group) {
+            metrics.deliveries.0.incr_by(deliveries as u64);
+        }
+        let mut window = self.window.lock().unwrap();
+        if now.saturating_duration_since(window.start) >= SHARE_WINDOW {
+            window.start = now;
+            window.group2deliveries.clear();
+        }
+        *window.group2deliveries.entry(group.to_string()).or_default() += deliveries as u64;
+        let total: u64 = window.group2deliveries.values().sum();
+        let total_weight: u64 = window.group2deliveries.keys().map(|group| self.weight(group) as u64).sum();
+        let share = total as f64 * self.weight(group) as f64 / total_weight as f64;
+        match share > 0.0 {
+            true => { window.group2deliveries[group] as f64 / share }
+            false => { 1.0 }
+        }
+    }
+
+    pub fn record_shed(&self, group: &str, shed: usize) {
+        if let Some(metrics) = self.metrics.0.get(group) {
+            metrics.qos0_shed.0.incr_by(shed as u64);
+        }
+    }
+
+    pub fn new(config: Arc<BrokerConfig>) -> Self {
+        let metrics = config.quota.profiles.keys()
+            .map(String::as_str)
+            .chain([UNPROFILED_GROUP])
+            .map(|group| (group.to_string(), GroupMetrics::default()))
+            .collect();
+        Self { config, window: Mutex::new(ShareWindow { start: Instant::now(), group2deliveries: HashMap::new() }), metrics: FairShareMetrics(metrics) }
+    }
+}
@@ -0,0 +1,4 @@
+pub mod quota_handler;
+pub mod congestion_control;
+pub mod fair_share;
+pub mod connection_pacing;
@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use log::{debug, info, trace};
+use metered::HitCount;
+use serde::Serialize;
+
+use crate::broker::utils::queued_packets;
+use crate::config::broker_config::{BrokerConfig, QuotaProfile};
+use crate::model::control_packet::ControlPacket;
+use crate::model::qos_level::QoSLevel;
+use crate::model::reason_code::ReasonCode;
+use crate::session::session_directory::SessionDirectory;
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+//Counters of a single quota profile
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct ProfileMetrics {
+    connections: HitCount,
+    publishes: HitCount,
+    rejected_payload: HitCount,
+    rejected_rate: HitCount,
+    rejected_inflight: HitCount,
+    rejected_subscriptions: HitCount,
+    dropped_queued: HitCount,
+}
+
+//Exposed on /metrics with the profile name in the path
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct QuotaHandlerMetrics(BTreeMap<String, ProfileMetrics>);
+
+#[derive(Debug)]
+struct ClientQuota {
+    profile_name: String,
+    profile: QuotaProfile,
+    //Packet Identifiers of QoS 2 publishes awaiting PUBREL
+    inflight: HashSet<u16>,
+    rate_window_start: Instant,
+    rate_window_count: u32,
+}
+
+//Holds the quota profile each connected client got at CONNECT. Clients without one are unlimited.
+#[derive(Debug)]
+pub struct QuotaHandler {
+    config: Arc<BrokerConfig>,
+    id2quota: DashMap<String, ClientQuota>,
+    pub(crate) metrics: QuotaHandlerMetrics,
+}
+
+impl QuotaHandler {
+    pub fn assign(&self, client_id: &String, username: Option<&String>) -> Option<QuotaProfile> {
+        trace!("QuotaHandler::assign");
+        let (profile_name, profile) = match self.config.quota.resolve_profile(username) {
+            Some(result) => { result }
+            None => {
+                self.id2quota.remove(client_id);
+                return None;
+            }
+        };
+        info!("Client {:?} got quota profile {:?}", client_id, profile_name);
+        if let Some(metrics) = self.metrics.0.get(profile_name) {
+            metrics.connections.incr();
+        }
+        self.id2quota.insert(client_id.clone(), ClientQuota {
+            profile_name: profile_name.clone(),
+            profile: profile.clone(),
+            inflight: HashSet::new(),
+            rate_window_start: Instant::now(),
+            rate_window_count: 0,
+        });
+        return Some(profile.clone());
+    }
+
+    pub fn profile_name(&self, client_id: &String) -> Option<String> {
+        self.id2quota.get(client_id).map(|quota| quota.profile_name.clone())
+    }
+
+    pub fn release(&self, client_id: &String) {
+        trace!("QuotaHandler::release");
+        self.id2quota.remove(client_id);
+    }
+
+    pub fn check_publish(&self, client_id: &String, control_packet: &ControlPacket) -> Result<(), ReasonCode> {
+        trace!("QuotaHandler::check_publish");
+        let mut quota = match self.id2quota.get_mut(client_id) {
+            Some(result) => { result }
+            None => { return Ok(()); }
+        };
+        let metrics = self.metrics.0.get(&quota.profile_name);
+        let reject = |counter: fn(&ProfileMetrics) -> &HitCount, reason_code: ReasonCode| {
+            if let Some(metrics) = metrics {
+                counter(metrics).incr();
+            }
+            return Err(reason_code);
+        };
+
+        let payload_size = control_packet.payload_opt().map(|payload| payload.data().len()).unwrap_or(0);
+        if quota.profile.max_payload.is_some_and(|max_payload| payload_size > max_payload) {
+            debug!("Payload of {} bytes from client {:?} exceeds its quota", payload_size, client_id);
+            return reject(|metrics| &metrics.rejected_payload, ReasonCode::QuotaExceeded);
+        }
+        if let Some(publish_rate) = quota.profile.publish_rate {
+            if quota.rate_window_start.elapsed() >= RATE_WINDOW {
+                quota.rate_window_start = Instant::now();
+                quota.rate_window_count = 0;
+            }
+            if quota.rate_window_count >= publish_rate {
+                debug!("Client {:?} exceeded {} publishes per second", client_id, publish_rate);
+                return reject(|metrics| &metrics.rejected_rate, ReasonCode::QuotaExceeded);
+            }
+            quota.rate_window_count += 1;
+        }
+        if let (QoSLevel::ExactlyOnce, Some(max_inflight)) = (control_packet.fixed_header().qos_level(), quota.profile.max_inflight) {
+            let packet_identifier = control_packet.variable_header().packet_identifier();
+            //A retransmission doesn't take another slot
+            if !quota.inflight.contains(&packet_identifier) && quota.inflight.len() >= max_inflight as usize {
+                debug!("Client {:?} exceeded {} inflight publishes", client_id, max_inflight);
+                return reject(|metrics| &metrics.rejected_inflight, ReasonCode::ReceiveMaximumExceeded);
+            }
+            quota.inflight.insert(packet_identifier);
+        }
+        if let Some(metrics) = metrics {
+            metrics.publishes.incr();
+        }
+        return Ok(());
+    }
+
+    pub fn release_inflight(&self, client_id: &String, packet_identifier: u16) {
+        trace!("QuotaHandler::release_inflight");
+        if let Some(mut quota) = self.id2quota.get_mut(client_id) {
+            quota.inflight.remove(&packet_identifier);
+        }
+    }
+
+    //subscriptions is the count the client has before the new one
+    pub fn check_subscription(&self, client_id: &String, subscriptions: usize) -> Result<(), ReasonCode> {
+        trace!("QuotaHandler::check_subscription");
+        let quota = match self.id2quota.get(client_id) {
+            Some(result) => { result }
+            None => { return Ok(()); }
+        };
+        if quota.profile.max_subscriptions.is_some_and(|max_subscriptions| subscriptions >= max_subscriptions) {
+            debug!("Client {:?} exceeded its {} subscriptions", client_id, subscriptions);
+            if let Some(metrics) = self.metrics.0.get(&quota.profile_name) {
+                metrics.rejected_subscriptions.incr();
+            }
+            return Err(ReasonCode::QuotaExceeded);
+        }
+        return Ok(());
+    }
+
+    //Whether another message can be stored in the session of the client
+    pub fn can_queue(&self, client_id: &String, sessions: &dyn SessionDirectory) -> bool {
+        let quota = match self.id2quota.get(client_id) {
+            Some(result) => { result }
+            None => { return true; }
+        };
+        let max_queued = match quota.profile.max_queued {
+            Some(result) => { result }
+            None => { return true; }
+        };
+        if queued_packets(sessions, client_id) < max_queued {
+            return true;
+        }
+        debug!("Session of client {:?} already holds {} messages", client_id, max_queued);
+        if let Some(metrics) = self.metrics.0.get(&quota.profile_name) {
+            metrics.dropped_queued.incr();
+        }
+        return false;
+    }
+
+    pub fn new(config: Arc<BrokerConfig>) -> Self {
+        let metrics = config.quota.profiles.keys()
+            .map(|profile_name| (profile_name.clone(), ProfileMetrics::default()))
+            .collect();
+        Self { config, id2quota: DashMap::new(), metrics: QuotaHandlerMetrics(metrics) }
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use log::{info, LevelFilter, warn};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+lazy_static! {
+    static ref LOG_LEVELS: Arc<LogLevels> = Arc::new(LogLevels::default());
+}
+
+//The levels of the process wide logger, shared by the admin API and $CONTROL commands
+pub fn log_levels() -> Arc<LogLevels> {
+    LOG_LEVELS.clone()
+}
+
+//Body of PUT /log-levels and payload of a $CONTROL/log-level PUBLISH
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct LogLevelRequest {
+    //Module path the level applies to, with everything below it, e.g. patina::serdes
+    pub module: String,
+    //off, error, warn, info, debug or trace
+    pub level: String,
+    //Reverted to the configured level after this many seconds, kept until reset when absent
+    pub duration_secs: Option<u64>,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct LogLevelOverride {
+    pub module: String,
+    pub level: String,
+    pub reverts_in_secs: Option<u64>,
+}
+
+#[derive(Debug)]
+struct LevelOverride {
+    level: LevelFilter,
+    reverts_at: Option<Instant>,
+    //A newer override of the same module cancels the revert of the previous one
+    generation: u64,
+}
+
+//Per module log levels set at runtime on top of config/log4rs.yaml. Every change rebuilds the
+//logger config from the file with the overrides applied, so the file stays the baseline.
+#[derive(Debug, Default)]
+pub struct LogLevels {
+    overrides: Mutex<BTreeMap<String, LevelOverride>>,
+    generations: AtomicU64,
+    #[cfg(feature = "logging")]
+    backend: Mutex<Option<backend::LogBackend>>,
+}
+
+impl LogLevels {
+    pub fn set(self: &Arc<Self>, request: &LogLevelRequest) -> Result<LogLevelOverride, String> {
+        if !is_module_path(&request.module) {
+            return Err(format!("Invalid module {:?}", request.module));
+        }
+        let level = LevelFilter::from_str(&request.level)
+            .map_err(|_| format!("Invalid level {:?}, expected off, error, warn, info, debug or trace", request.level))?;
+        let duration = request.duration_secs.map(Duration::from_secs);
+        let generation = self.generations.fetch_add(1, Ordering::Relaxed);
+        let reverts_at = duration.map(|duration| Instant::now() + duration);
+        self.overrides.lock().unwrap().insert(request.module.clone(), LevelOverride { level, reverts_at, generation });
+        info!("Log level of {} set to {} for {}", request.module, level, duration.map(|duration| format!("{}s", duration.as_secs())).unwrap_or_else(|| String::from("ever")));
+        self.apply();
+        if let Some(duration) = duration {
+            let log_levels = self.clone();
+            let module = request.module.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(duration).await;
+                log_levels.revert(&module, generation);
+            });
+        }
+        return Ok(LogLevelOverride { module: request.module.clone(), level: level.as_str().to_lowercase(), reverts_in_secs: request.duration_secs });
+    }
+
+    //Back to the configured level, returns whether the module had an override
+    pub fn reset(&self, module: &str) -> bool {
+        let existed = self.overrides.lock().unwrap().remove(module).is_some();
+        if existed {
+            info!("Log level of {} reset", module);
+            self.apply();
+        }
+        existed
+    }
+
+    fn revert(&self, module: &str, generation: u64) {
+        let reverted = {
+            let mut overrides = self.overrides.lock().unwrap();
+            match overrides.get(module) {
+                Some(level_override) if level_override.generation == generation => { overrides.remove(module).is_some() }
+                _ => { false }
+            }
+        };
+        if reverted {
+            info!("Log level of {} reverted", module);
+            self.apply();
+        }
+    }
+
+    pub fn overrides(&self) -> Vec<LogLevelOverride> {
+        let now = Instant::now();
+        self.overrides.lock().unwrap().iter()
+            .map(|(module, level_override)| LogLevelOverride {
+                module: module.clone(),
+                level: level_override.level.as_str().to_lowercase(),
+                reverts_in_secs: level_override.reverts_at.map(|reverts_at| reverts_at.saturating_duration_since(now).as_secs()),
+            })
+            .collect()
+    }
+
+    fn levels(&self) -> BTreeMap<String, LevelFilter> {
+        self.overrides.lock().unwrap().iter()
+            .map(|(module, level_override)| (module.clone(), level_override.level))
+            .collect()
+    }
+
+    #[cfg(feature = "logging")]
+    fn apply(&self) {
+        if let Some(backend) = self.backend.lock().unwrap().as_ref() {
+            if let Err(err) = backend.apply(&self.levels()) {
+                warn!("Can't apply log levels. {}", err);
+            }
+        }
+    }
+
+    #[cfg(not(feature = "logging"))]
+    fn apply(&self) {
+        warn!("Built without the logging feature, log levels {:?} have no effect", self.levels());
+    }
+
+    //Installs the logger from the file. It is re-read at its refresh_rate, the overrides stay on top.
+    #[cfg(feature = "logging")]
+    pub fn init(self: &Arc<Self>, config_path: &str) {
+        let (backend, refresh_rate) = match backend::LogBackend::init(config_path) {
+            Ok(result) => { result }
+            Err(err) => {
+                eprintln!("Can't initialize logging from {}. {}", config_path, err);
+                return;
+            }
+        };
+        *self.backend.lock().unwrap() = Some(backend);
+        if let Some(refresh_rate) = refresh_rate {
+            let log_levels = self.clone();
+            std::thread::Builder::new()
+                .name(String::from("log-config-refresh"))
+                .spawn(move || loop {
+                    std::thread::sleep(refresh_rate);
+                    log_levels.apply();
+                })
+                .expect("can't spawn log config refresh");
+        }
+    }
+}
+
+//Rust paths, a::b::c
+fn is_module_path(module: &str) -> bool {
+    !module.is_empty() && module.split("::").all(|segment| !segment.is_empty() && segment.chars().all(|char| char.is_ascii_alphanumeric() || char == '_' || char == '-'))
+}
+
+#[cfg(feature = "logging")]
+pub(crate) mod backend {
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    use log::LevelFilter;
+    use log4rs::config::{Config, Deserializers, Logger, RawConfig};
+    use log4rs::Handle;
+
+    #[derive(Debug)]
+    pub struct LogBackend {
+        config_path: String,
+        handle: Handle,
+    }
+
+    impl LogBackend {
+        pub fn apply(&self, levels: &BTreeMap<String, LevelFilter>) -> Result<(), String> {
+            let (config, _) = load_config(&self.config_path, levels)?;
+            self.handle.set_config(config);
+            Ok(())
+        }
+
+        pub fn init(config_path: &str) -> Result<(Self, Option<Duration>), String> {
+            let (config, refresh_rate) = load_config(config_path, &BTreeMap::new())?;
+            let handle = log4rs::init_config(config).map_err(|err| err.to_string())?;
+            Ok((Self { config_path: config_path.to_string(), handle }, refresh_rate))
+        }
+    }
+
+    fn load_config(config_path: &str, levels: &BTreeMap<String, LevelFilter>) -> Result<(Config, Option<Duration>), String> {
+        let file = std::fs::read_to_string(config_path).map_err(|err| format!("Can't read {}. {}", config_path, err))?;
+        let raw_config: RawConfig = serde_yaml::from_str(&file).map_err(|err| format!("Can't parse {}. {}", config_path, err))?;
+        let (appenders, mut errors) = raw_config.appenders_lossy(&Deserializers::default());
+        errors.handle();
+        let config = Config::builder()
+            .appenders(appenders)
+            .loggers(with_levels(raw_config.loggers(), levels))
+            .build(raw_config.root())
+            .map_err(|err| err.to_string())?;
+        Ok((config, raw_config.refresh_rate()))
+    }
+
+    //A configured logger keeps its appenders with the new level, other modules log through the root appenders
+    pub fn with_levels(loggers: Vec<Logger>, levels: &BTreeMap<String, LevelFilter>) -> Vec<Logger> {
+        let mut loggers: Vec<Logger> = loggers.into_iter()
+            .map(|logger| match levels.get(logger.name()) {
+                Some(level) => { Logger::builder().appenders(logger.appenders().to_vec()).additive(logger.additive()).build(logger.name(), *level) }
+                None => { logger }
+            })
+            .collect();
+        for (module, level) in levels {
+            if !loggers.iter().any(|logger| logger.name() == module) {
+                loggers.push(Logger::builder().build(module.clone(), *level));
+            }
+        }
+        loggers
+    }
+}
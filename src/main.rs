@@ -3,19 +3,35 @@ extern crate core;
 extern crate lazy_static;
 
 use std::sync::Arc;
-use std::thread;
+use std::time::Duration;
 
 use dashmap::DashMap;
 use log::info;
-use log4rs;
+use tokio::sync::Mutex;
 
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::auth::acl::Acl;
 use crate::broker::broker::Broker;
+use crate::broker::diagnostics::Diagnostics;
 use crate::broker::packet_dispatcher::PacketDispatcher;
+use crate::broker::supervisor::Supervisor;
+use crate::config::broker_config::{BrokerConfig, StorageMode};
+use crate::config::command_line::{Command, CommandLine};
+use crate::config::config_schema::default_config_yaml;
+use crate::config::port_check::{check_ports, configured_endpoints};
+use crate::connection::listeners::Listeners;
 use crate::connection::rx_connection_handler::RxConnectionHandler;
 use crate::connection::tx_connection_handler::TxConnectionHandler;
+use crate::connection::virtual_endpoint::VirtualEndpoints;
+use crate::error::StartupError;
+#[cfg(feature = "admin-api")]
 use crate::metrics::metrics_registry::ServiceMetricRegistry;
 use crate::session::client_handler::ClientHandler;
+use crate::session::session_persistence::SessionPersistence;
+use crate::session::session_store::FileSessionStore;
+use crate::session::state_transfer::run_state_command;
 use crate::topic::topic_handler::TopicHandler;
+use crate::topic::tree_telemetry::TreeTelemetry;
 
 mod tests;
 mod topic;
@@ -25,68 +41,268 @@ mod broker;
 mod session;
 mod metrics;
 mod model;
+mod config;
+mod gateway;
+mod limits;
+mod audit;
+mod auth;
+mod logging;
+mod error;
 
+#[cfg(feature = "logging")]
 pub fn init_logging() {
-    log4rs::init_file("config/log4rs.yaml", Default::default());
+    logging::log_levels::log_levels().init("config/log4rs.yaml");
 }
 
+#[cfg(not(feature = "logging"))]
+pub fn init_logging() {}
 
-fn main() {
+//Every problem of the config at once, rather than starting with part of it ignored
+pub fn init_config(command_line: &CommandLine) -> BrokerConfig {
+    match BrokerConfig::load_checked(&command_line.config_path, &command_line.overrides) {
+        Ok(config) => { config }
+        Err(problems) => { exit_on(StartupError::InvalidConfig(problems)) }
+    }
+}
+
+
+#[tokio::main]
+async fn main() {
+    let command_line = match CommandLine::parse(std::env::args().skip(1)) {
+        Ok(command_line) => { command_line }
+        Err(err) => { exit_on(StartupError::CommandLine(err)) }
+    };
+    //Before logging starts, so that stdout is the config alone
+    if command_line.command == Command::PrintDefaultConfig {
+        print!("{}", default_config_yaml());
+        return;
+    }
     init_logging();
 
     info!("MQTT SERVER");
-    let (listener2broker_tx, listener2broker_rx) = tokio::sync::mpsc::channel(1000000);
+    let config = Arc::new(init_config(&command_line));
+    if command_line.command != Command::Run {
+        return run_state(config, &command_line.command).await;
+    }
+    config.log_summary();
+    if let Err(err) = config.check_storage() {
+        exit_on(StartupError::Config(err));
+    }
+    if let Err(err) = config.check_acl() {
+        exit_on(StartupError::Config(err));
+    }
+    //Every conflict at once, rather than the first subsystem that fails to bind restarting forever
+    let port_conflicts = check_ports(&configured_endpoints(&config));
+    if !port_conflicts.is_empty() {
+        exit_on(StartupError::PortConflicts(port_conflicts));
+    }
+    info!("Storage mode: {:?}", config.storage.mode);
+    let audit_log = Arc::new(AuditLog::new(config.audit.clone()));
+    audit_log.record(AuditEvent::ConfigLoaded { path: command_line.config_path.clone() });
+    let (listener2broker_tx, listener2broker_rx) = tokio::sync::mpsc::channel(config.dispatch.inbound_capacity.max(1));
     let (broker2listener_tx, broker2listener_rx) = tokio::sync::mpsc::channel(1000000);
     let listener2broker_tx = Arc::new(listener2broker_tx);
-    let broker2listener_tx =Arc::new(broker2listener_tx);
-    let listener2broker_rx =listener2broker_rx;
-    let broker2listener_rx =broker2listener_rx;
-
+    let broker2listener_tx = Arc::new(broker2listener_tx);
+    //A restarted broker loop or writer picks up the queue where the crashed one left it
+    let listener2broker_rx = Arc::new(Mutex::new(listener2broker_rx));
+    let broker2listener_rx = Arc::new(Mutex::new(broker2listener_rx));
 
     let stream_repository = Arc::new(DashMap::new());
+    let virtual_endpoints = Arc::new(VirtualEndpoints::default());
     let topic_handler = Arc::new(TopicHandler::default());
     let client_handler = Arc::new(ClientHandler::default());
-    let packet_handler = Arc::new(PacketDispatcher::new(client_handler.clone(), topic_handler.clone(), broker2listener_tx));
+    let packet_handler = Arc::new(PacketDispatcher::new(config.clone(), client_handler.clone(), topic_handler.clone(), broker2listener_tx.clone()));
+    let diagnostics = Arc::new(Diagnostics::new(config.diagnostics.clone(), packet_handler.clone()));
     let broker = Arc::new(Broker::new(packet_handler.clone()));
-    let packet_handler_ = broker.clone();
+    let tx_connection_handler = Arc::new(TxConnectionHandler::new(client_handler.clone(), virtual_endpoints.clone(), packet_handler.connection_close.clone(), packet_handler.payload_sizes.clone()));
+    let rx_connection_handler = Arc::new(RxConnectionHandler::new(config.clone(), packet_handler.connection_close.clone(), broker2listener_tx));
+    let session_persistence = restore_sessions(config.clone(), client_handler.clone(), topic_handler.clone());
 
-    let broker_handle = thread::spawn(move || {
-        info!("Spawned Broker thread");
-        packet_handler_.handle_packets(
-            listener2broker_rx,
-        );
+    let mut supervisor = Supervisor::new(config.supervisor.clone());
+    let (tx_connection_handler_, stream_repository_) = (tx_connection_handler.clone(), stream_repository.clone());
+    supervisor.add("tx_connections", &[], move || {
+        let (tx_connection_handler, broker2listener_rx, stream_repository) = (tx_connection_handler_.clone(), broker2listener_rx.clone(), stream_repository_.clone());
+        async move {
+            let mut broker2listener_rx = broker2listener_rx.lock().await;
+            tx_connection_handler.handle_outgoing_connections(&mut broker2listener_rx, stream_repository).await.map_err(|err| err.to_string())
+        }
+    });
+    let broker_ = broker.clone();
+    supervisor.add("broker", &["tx_connections"], move || {
+        let (broker, listener2broker_rx) = (broker_.clone(), listener2broker_rx.clone());
+        async move {
+            let mut listener2broker_rx = listener2broker_rx.lock().await;
+            broker.handle_packets(&mut listener2broker_rx).await.map_err(|err| err.to_string())
+        }
+    });
+    let packet_handler_ = packet_handler.clone();
+    supervisor.add("broker_info", &["broker"], move || {
+        let packet_handler = packet_handler_.clone();
+        async move { packet_handler.publish_broker_info().await; Ok(()) }
+    });
+    let packet_handler_ = packet_handler.clone();
+    supervisor.add("virtual_topics", &["broker"], move || {
+        let packet_handler = packet_handler_.clone();
+        async move { packet_handler.publish_virtual_topics().await; Ok(()) }
     });
+    let packet_handler_ = packet_handler.clone();
+    supervisor.add("qos2_expiry", &["broker"], move || {
+        let packet_handler = packet_handler_.clone();
+        async move { packet_handler.expire_qos2_handshakes().await; Ok(()) }
+    });
+    let (rx_connection_handler_, listener2broker_tx_) = (rx_connection_handler.clone(), listener2broker_tx.clone());
+    supervisor.add("rx_connections", &["broker"], move || {
+        let (rx_connection_handler, listener2broker_tx, stream_repository) = (rx_connection_handler_.clone(), listener2broker_tx_.clone(), stream_repository.clone());
+        async move { rx_connection_handler.handle_incoming_connections(listener2broker_tx, stream_repository).await.map_err(|err| err.to_string()) }
+    });
+    add_tree_telemetry(&mut supervisor, packet_handler.tree_telemetry.clone(), topic_handler.clone());
+    add_session_checkpoints(&mut supervisor, session_persistence.clone());
+    add_diagnostics_signal(&mut supervisor, diagnostics.clone());
+    add_mqtt_sn_gateway(&mut supervisor, config.clone(), listener2broker_tx.clone(), virtual_endpoints.clone(), rx_connection_handler.listeners.clone());
+    add_coap_bridge(&mut supervisor, config.clone(), listener2broker_tx.clone(), virtual_endpoints.clone(), topic_handler.clone(), packet_handler.acl.clone());
+    add_metrics_server(&mut supervisor, rx_connection_handler, tx_connection_handler, broker, config.clone(), listener2broker_tx, virtual_endpoints, topic_handler, diagnostics, audit_log);
+
+    let result = supervisor.run(shutdown_signal()).await;
+    if let Some(session_persistence) = session_persistence {
+        session_persistence.checkpoint_off_runtime().await;
+    }
+    if let Err(err) = result {
+        log::error!("{}", err);
+        std::process::exit(1);
+    }
+}
 
-    let stream_repository_ = stream_repository.clone();
-    let tx_connection_handler = Arc::new(TxConnectionHandler::new(client_handler.clone(), topic_handler.clone()));
-    let tx_connection_handler_ = tx_connection_handler.clone();
+//export-state and import-state exit 0 when done, 1 otherwise
+async fn run_state(config: Arc<BrokerConfig>, command: &Command) {
+    match run_state_command(config, command).await {
+        Ok(summary) => { println!("{}", summary); }
+        Err(err) => {
+            log::error!("{}", err);
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn exit_on(err: StartupError) -> ! {
+    log::error!("{}", err);
+    eprintln!("{}", err);
+    std::process::exit(err.exit_code());
+}
 
-    let tx_connections_handle = thread::spawn(move || {
-        info!("Spawned TxConnectionHandler thread");
-        tx_connection_handler_.handle_outgoing_connections(broker2listener_rx, stream_repository_);
+//Sessions survive restarts in disk mode, unless storage.session_checkpoint_secs is 0
+fn restore_sessions(config: Arc<BrokerConfig>, client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>) -> Option<Arc<SessionPersistence>> {
+    if config.storage.mode != StorageMode::Disk || config.storage.session_checkpoint_secs == 0 {
+        return None;
+    }
+    let session_store = Arc::new(FileSessionStore::new(&config.storage.session_directory));
+    let session_persistence = Arc::new(SessionPersistence::new(config, session_store, client_handler, topic_handler));
+    session_persistence.restore();
+    Some(session_persistence)
+}
+
+fn add_session_checkpoints(supervisor: &mut Supervisor, session_persistence: Option<Arc<SessionPersistence>>) {
+    let session_persistence = match session_persistence {
+        Some(result) => { result }
+        None => { return; }
+    };
+    supervisor.add("session_checkpoints", &[], move || {
+        let session_persistence = session_persistence.clone();
+        async move {
+            session_persistence.run().await;
+            Ok(())
+        }
     });
+}
 
-    let stream_repository_ = stream_repository.clone();
-    let rx_connection_handler = Arc::new(RxConnectionHandler::new());
-    let rx_connection_handler_ = rx_connection_handler.clone();
+//Ctrl-C, or SIGTERM from a service manager
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+                return;
+            }
+            Err(err) => { log::warn!("Can't listen for SIGTERM. {}", err); }
+        }
+    }
+    if let Err(err) = tokio::signal::ctrl_c().await {
+        log::error!("Can't listen for Ctrl-C. {}", err);
+        std::future::pending::<()>().await;
+    }
+}
 
-    let rx_connection_handle = thread::spawn(move || {
-        info!("Spawned RxConnectionHandler thread");
-        rx_connection_handler_.handle_incoming_connections(listener2broker_tx, stream_repository_);
+fn add_tree_telemetry(supervisor: &mut Supervisor, tree_telemetry: Arc<TreeTelemetry>, topic_handler: Arc<TopicHandler>) {
+    let interval_secs = tree_telemetry.interval_secs();
+    if interval_secs == 0 {
+        return;
+    }
+    supervisor.add("tree_telemetry", &[], move || {
+        let (tree_telemetry, topic_handler) = (tree_telemetry.clone(), topic_handler.clone());
+        async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                tree_telemetry.report(&topic_handler.subscription_tree());
+            }
+        }
     });
+}
 
-    let metrics_handle = thread::spawn(move || {
-        info!("Spawned MetricsServer thread");
-        metrics::metrics_server::start_metrics_server(rx_connection_handler, tx_connection_handler, broker);
+#[cfg(unix)]
+fn add_diagnostics_signal(supervisor: &mut Supervisor, diagnostics: Arc<Diagnostics>) {
+    supervisor.add("diagnostics", &[], move || {
+        let diagnostics = diagnostics.clone();
+        async move { diagnostics.handle_signals().await.map_err(|err| format!("Stopped listening for SIGUSR1. {}", err)) }
     });
+}
 
+#[cfg(not(unix))]
+fn add_diagnostics_signal(_supervisor: &mut Supervisor, _diagnostics: Arc<Diagnostics>) {}
 
-    broker_handle.join().expect("");
-    tx_connections_handle.join().expect("");
-    rx_connection_handle.join().expect("");
-    metrics_handle.join().expect("");
+#[cfg(feature = "mqtt-sn")]
+fn add_mqtt_sn_gateway(supervisor: &mut Supervisor, config: Arc<BrokerConfig>, listener2broker_tx: Arc<tokio::sync::mpsc::Sender<(connection::client_context::ClientContext, model::control_packet::ControlPacket)>>, virtual_endpoints: Arc<VirtualEndpoints>, listeners: Arc<Listeners>) {
+    if !config.gateway.mqtt_sn.enabled {
+        return;
+    }
+    let mqtt_sn_gateway = Arc::new(gateway::mqtt_sn::gateway::MqttSnGateway::new(config, listener2broker_tx, virtual_endpoints, listeners));
+    supervisor.add("mqtt_sn_gateway", &["broker"], move || {
+        let mqtt_sn_gateway = mqtt_sn_gateway.clone();
+        async move { mqtt_sn_gateway.handle_datagrams().await.map_err(|err| err.to_string()) }
+    });
 }
 
+#[cfg(not(feature = "mqtt-sn"))]
+fn add_mqtt_sn_gateway(_supervisor: &mut Supervisor, _config: Arc<BrokerConfig>, _listener2broker_tx: Arc<tokio::sync::mpsc::Sender<(connection::client_context::ClientContext, model::control_packet::ControlPacket)>>, _virtual_endpoints: Arc<VirtualEndpoints>, _listeners: Arc<Listeners>) {}
 
+#[cfg(feature = "coap")]
+fn add_coap_bridge(supervisor: &mut Supervisor, config: Arc<BrokerConfig>, listener2broker_tx: Arc<tokio::sync::mpsc::Sender<(connection::client_context::ClientContext, model::control_packet::ControlPacket)>>, virtual_endpoints: Arc<VirtualEndpoints>, topic_handler: Arc<TopicHandler>, acl: Arc<Acl>) {
+    if !config.gateway.coap.enabled {
+        return;
+    }
+    supervisor.add("coap_bridge", &["broker"], move || {
+        let future = gateway::coap::bridge::CoapBridge::run(config.clone(), listener2broker_tx.clone(), virtual_endpoints.clone(), topic_handler.clone(), acl.clone());
+        async move { future.await.map_err(|err| err.to_string()) }
+    });
+}
+
+#[cfg(not(feature = "coap"))]
+fn add_coap_bridge(_supervisor: &mut Supervisor, _config: Arc<BrokerConfig>, _listener2broker_tx: Arc<tokio::sync::mpsc::Sender<(connection::client_context::ClientContext, model::control_packet::ControlPacket)>>, _virtual_endpoints: Arc<VirtualEndpoints>, _topic_handler: Arc<TopicHandler>, _acl: Arc<Acl>) {}
 
+#[cfg(feature = "admin-api")]
+fn add_metrics_server(supervisor: &mut Supervisor, rx_connection_handler: Arc<RxConnectionHandler>, tx_connection_handler: Arc<TxConnectionHandler>, broker: Arc<Broker>, config: Arc<BrokerConfig>, listener2broker_tx: Arc<tokio::sync::mpsc::Sender<(connection::client_context::ClientContext, model::control_packet::ControlPacket)>>, virtual_endpoints: Arc<VirtualEndpoints>, topic_handler: Arc<TopicHandler>, diagnostics: Arc<Diagnostics>, audit_log: Arc<AuditLog>) {
+    supervisor.add("metrics_server", &["broker"], move || {
+        let future = metrics::metrics_server::start_metrics_server(rx_connection_handler.clone(), tx_connection_handler.clone(), broker.clone(), config.clone(), listener2broker_tx.clone(), virtual_endpoints.clone(), topic_handler.clone(), diagnostics.clone(), audit_log.clone());
+        async move { future.await.map_err(|err| err.to_string()) }
+    });
+}
 
+#[cfg(not(feature = "admin-api"))]
+fn add_metrics_server(_supervisor: &mut Supervisor, _rx_connection_handler: Arc<RxConnectionHandler>, _tx_connection_handler: Arc<TxConnectionHandler>, _broker: Arc<Broker>, _config: Arc<BrokerConfig>, _listener2broker_tx: Arc<tokio::sync::mpsc::Sender<(connection::client_context::ClientContext, model::control_packet::ControlPacket)>>, _virtual_endpoints: Arc<VirtualEndpoints>, _topic_handler: Arc<TopicHandler>, _diagnostics: Arc<Diagnostics>, _audit_log: Arc<AuditLog>) {
+    info!("Admin API is disabled, metrics are not exposed");
+}
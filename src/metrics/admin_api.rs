@@ -0,0 +1,52 @@
+use serde::Serialize;
+
+use crate::config::broker_config::{AdminConfig, AdminRole};
+
+//Body of every admin API answer that isn't a stream
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct ApiResponse {
+    pub status: u16,
+    pub message: String,
+}
+
+impl ApiResponse {
+    pub fn new(status: u16, message: String) -> Self {
+        Self { status, message }
+    }
+}
+
+//Endpoints that need a role take `Authorization: Bearer <token>` with admin.api_token or one of admin.tokens
+pub fn authorize(config: &AdminConfig, endpoint: &str, authorization: Option<&String>) -> Result<(), ApiResponse> {
+    let required_role = config.required_role(endpoint);
+    if required_role == AdminRole::Public {
+        return Ok(());
+    }
+    if !config.has_tokens() {
+        return Err(ApiResponse::new(403, String::from("No api_token configured")));
+    }
+    let role = authorization
+        .and_then(|authorization| authorization.strip_prefix("Bearer "))
+        .and_then(|token| token_role(config, token));
+    return match role {
+        None => { Err(ApiResponse::new(401, String::from("Unauthorized"))) }
+        Some(role) if role < required_role => { Err(ApiResponse::new(403, format!("{} needs the {:?} role", endpoint, required_role))) }
+        Some(_) => { Ok(()) }
+    };
+}
+
+//Every token is compared, the highest matching role wins
+fn token_role(config: &AdminConfig, token: &str) -> Option<AdminRole> {
+    let api_token = config.api_token.iter().map(|api_token| (api_token, AdminRole::Admin));
+    let tokens = config.tokens.iter().map(|api_token| (&api_token.token, api_token.role));
+    api_token.chain(tokens)
+        .filter(|(api_token, _)| tokens_match(token, api_token))
+        .map(|(_, role)| role)
+        .max()
+}
+
+//Compares every byte so the time taken doesn't tell how much of the token was right
+fn tokens_match(token: &str, api_token: &str) -> bool {
+    token.len() == api_token.len() && token.bytes().zip(api_token.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
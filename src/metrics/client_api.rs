@@ -0,0 +1,134 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use log::{info, trace, warn};
+use serde::Serialize;
+use tokio::sync::mpsc::Sender;
+
+use crate::{ClientHandler, TopicHandler};
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::broker::utils::{purge_queued, queued_messages, send_packet, session_summary};
+use crate::config::broker_config::BrokerConfig;
+use crate::limits::quota_handler::QuotaHandler;
+use crate::metrics::admin_api::{ApiResponse, authorize};
+use crate::metrics::subscribe_api::MessageEnvelope;
+use crate::model::control_packet::ControlPacket;
+use crate::model::reason_code::ReasonCode;
+use crate::session::session_handler::{QueuedMessage, QueueSelector, SessionSummary};
+use crate::topic::subscription::SubscriptionRecord;
+
+//Everything the broker keeps about one client
+#[derive(Debug)]
+#[derive(Serialize)]
+pub struct ClientExport {
+    pub client_id: String,
+    pub connected: bool,
+    pub session: Option<SessionSummary>,
+    pub subscriptions: Vec<SubscriptionRecord>,
+    //Retained messages the client published that are still the retained message of their topic
+    pub retained_messages: Vec<MessageEnvelope>,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct ClientPurge {
+    pub client_id: String,
+    pub disconnected: bool,
+    pub session_removed: bool,
+    pub subscriptions_removed: usize,
+    pub retained_messages_removed: usize,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct QueuePurge {
+    pub client_id: String,
+    pub purged: usize,
+    pub remaining: usize,
+}
+
+//GET and DELETE /clients/{client_id}, to look at or forget the state of a single client.
+//GET and DELETE /clients/{client_id}/queue, for the messages its session holds
+#[derive(Debug)]
+pub struct ClientApi {
+    config: Arc<BrokerConfig>,
+    client_handler: Arc<ClientHandler>,
+    topic_handler: Arc<TopicHandler>,
+    quota_handler: Arc<QuotaHandler>,
+    to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>,
+    audit_log: Arc<AuditLog>,
+}
+
+impl ClientApi {
+    pub fn export(&self, authorization: Option<String>, client_id: String) -> Result<ClientExport, ApiResponse> {
+        trace!("ClientApi::export");
+        self.authorize(authorization, "GET /clients", format!("GET /clients/{}", client_id))?;
+        let export = ClientExport {
+            connected: self.client_handler.get_socket(&client_id).is_ok(),
+            session: session_summary(&*self.client_handler.sessions, &client_id),
+            subscriptions: self.topic_handler.subscriptions_of(&client_id),
+            retained_messages: self.topic_handler.retained_messages_of(&client_id).iter().map(MessageEnvelope::from_publish).collect(),
+            client_id,
+        };
+        if !export.connected && export.session.is_none() && export.subscriptions.is_empty() && export.retained_messages.is_empty() {
+            return Err(ApiResponse::new(404, format!("Unknown client {:?}", export.client_id)));
+        }
+        self.audit_log.record(AuditEvent::AdminAction { action: String::from("export-client"), resource: export.client_id.clone() });
+        return Ok(export);
+    }
+
+    //A connected client is disconnected first so it can't recreate what is being removed
+    pub async fn purge(&self, authorization: Option<String>, client_id: String) -> Result<ClientPurge, ApiResponse> {
+        trace!("ClientApi::purge");
+        self.authorize(authorization, "DELETE /clients", format!("DELETE /clients/{}", client_id))?;
+        let socket = self.client_handler.get_socket(&client_id).ok();
+        if let Some(socket) = socket {
+            send_packet(socket, &ControlPacket::disconnect(ReasonCode::AdministrativeAction), &self.to_listener).await;
+        }
+        let subscriptions_removed = self.topic_handler.subscriptions_of(&client_id).len();
+        self.topic_handler.unsubscribe_all(&client_id);
+        self.quota_handler.release(&client_id);
+        let purge = ClientPurge {
+            disconnected: socket.is_some(),
+            session_removed: self.client_handler.sessions.remove(&client_id),
+            subscriptions_removed,
+            retained_messages_removed: self.topic_handler.clear_retained_of(&client_id),
+            client_id,
+        };
+        info!("Purged client {:?}: {:?}", purge.client_id, purge);
+        self.audit_log.record(AuditEvent::AdminAction { action: String::from("purge-client"), resource: purge.client_id.clone() });
+        return Ok(purge);
+    }
+
+    pub fn queue(&self, authorization: Option<String>, client_id: String) -> Result<Vec<QueuedMessage>, ApiResponse> {
+        trace!("ClientApi::queue");
+        self.authorize(authorization, "GET /clients", format!("GET /clients/{}/queue", client_id))?;
+        return queued_messages(&*self.client_handler.sessions, &client_id).ok_or_else(|| ApiResponse::new(404, format!("No session for client {:?}", client_id)));
+    }
+
+    //A stuck client's backlog can be dropped in part, e.g. the messages of one topic or those older than a minute
+    pub fn purge_queue(&self, authorization: Option<String>, client_id: String, selector: QueueSelector) -> Result<QueuePurge, ApiResponse> {
+        trace!("ClientApi::purge_queue");
+        self.authorize(authorization, "DELETE /clients", format!("DELETE /clients/{}/queue", client_id))?;
+        let purged = purge_queued(&*self.client_handler.sessions, &client_id, &selector).ok_or_else(|| ApiResponse::new(404, format!("No session for client {:?}", client_id)))?;
+        let remaining = queued_messages(&*self.client_handler.sessions, &client_id).map(|messages| messages.len()).unwrap_or(0);
+        info!("Purged {} queued messages of client {:?} matching {:?}, {} left", purged, client_id, selector, remaining);
+        self.audit_log.record(AuditEvent::AdminAction { action: format!("purge-queue {}", purged), resource: client_id.clone() });
+        return Ok(QueuePurge { client_id, purged, remaining });
+    }
+
+    fn authorize(&self, authorization: Option<String>, endpoint: &str, resource: String) -> Result<(), ApiResponse> {
+        if let Err(response) = authorize(&self.config.admin, endpoint, authorization.as_ref()) {
+            warn!("Refused {}: {}", resource, response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource, reason: response.message.clone() });
+            return Err(response);
+        }
+        return Ok(());
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, quota_handler: Arc<QuotaHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>, audit_log: Arc<AuditLog>) -> Self {
+        Self { config, client_handler, topic_handler, quota_handler, to_listener, audit_log }
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use log::{trace, warn};
+use serde::Serialize;
+
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::config::broker_config::{BrokerConfig, enabled_features, StorageMode};
+use crate::config::config_provenance::ConfigValue;
+use crate::metrics::admin_api::{ApiResponse, authorize};
+
+#[derive(Debug)]
+#[derive(Serialize)]
+pub struct ConfigReport {
+    pub version: &'static str,
+    pub features: Vec<&'static str>,
+    pub storage_mode: StorageMode,
+    pub values: BTreeMap<String, ConfigValue>,
+}
+
+//GET /config, the configuration the broker is running with and where each value came from
+#[derive(Debug)]
+pub struct ConfigApi {
+    config: Arc<BrokerConfig>,
+    audit_log: Arc<AuditLog>,
+}
+
+impl ConfigApi {
+    pub fn report(&self, authorization: Option<String>) -> Result<ConfigReport, ApiResponse> {
+        trace!("ConfigApi::report");
+        if let Err(response) = authorize(&self.config.admin, "GET /config", authorization.as_ref()) {
+            warn!("Refused GET /config: {}", response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource: String::from("GET /config"), reason: response.message.clone() });
+            return Err(response);
+        }
+        return Ok(ConfigReport { version: env!("CARGO_PKG_VERSION"), features: enabled_features(), storage_mode: self.config.storage.mode, values: self.config.effective() });
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, audit_log: Arc<AuditLog>) -> Self {
+        Self { config, audit_log }
+    }
+}
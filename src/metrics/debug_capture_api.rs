@@ -0,0 +1,81 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::ClientHandler;
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::config::broker_config::BrokerConfig;
+use crate::metrics::admin_api::{ApiResponse, authorize};
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct DebugCaptureQuery {
+    //debug_capture.default_duration_secs when absent
+    pub duration_secs: Option<u64>,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct DebugCapture {
+    pub client_id: String,
+    pub connected: bool,
+    pub stops_in_secs: u64,
+}
+
+//GET, PUT and DELETE /debug-captures, to log every packet of a single client of a running broker
+#[derive(Debug)]
+pub struct DebugCaptureApi {
+    config: Arc<BrokerConfig>,
+    client_handler: Arc<ClientHandler>,
+    audit_log: Arc<AuditLog>,
+}
+
+impl DebugCaptureApi {
+    pub fn list(&self, authorization: Option<String>) -> Result<Vec<DebugCapture>, ApiResponse> {
+        trace!("DebugCaptureApi::list");
+        self.authorize(authorization, "GET /debug-captures", String::from("GET /debug-captures"))?;
+        return Ok(self.client_handler.debug_captures().into_iter()
+            .map(|(client_id, stops_in)| DebugCapture { connected: self.client_handler.get_socket(&client_id).is_ok(), client_id, stops_in_secs: stops_in.as_secs() })
+            .collect());
+    }
+
+    //The client doesn't have to be connected, its packets are logged from its next CONNECT
+    pub fn start(&self, authorization: Option<String>, client_id: String, query: DebugCaptureQuery) -> Result<DebugCapture, ApiResponse> {
+        trace!("DebugCaptureApi::start");
+        self.authorize(authorization, "PUT /debug-captures", format!("PUT /debug-captures/{}", client_id))?;
+        let config = &self.config.debug_capture;
+        let duration_secs = query.duration_secs.unwrap_or(config.default_duration_secs);
+        if duration_secs == 0 || duration_secs > config.max_duration_secs {
+            return Err(ApiResponse::new(400, format!("duration_secs must be between 1 and {}", config.max_duration_secs)));
+        }
+        self.client_handler.start_debug_capture(&client_id, Instant::now() + Duration::from_secs(duration_secs));
+        self.audit_log.record(AuditEvent::AdminAction { action: format!("start-debug-capture {}s", duration_secs), resource: client_id.clone() });
+        return Ok(DebugCapture { connected: self.client_handler.get_socket(&client_id).is_ok(), client_id, stops_in_secs: duration_secs });
+    }
+
+    pub fn stop(&self, authorization: Option<String>, client_id: String) -> Result<ApiResponse, ApiResponse> {
+        trace!("DebugCaptureApi::stop");
+        self.authorize(authorization, "DELETE /debug-captures", format!("DELETE /debug-captures/{}", client_id))?;
+        if !self.client_handler.stop_debug_capture(&client_id) {
+            return Err(ApiResponse::new(404, format!("No debug capture of client {:?}", client_id)));
+        }
+        self.audit_log.record(AuditEvent::AdminAction { action: String::from("stop-debug-capture"), resource: client_id.clone() });
+        return Ok(ApiResponse::new(200, format!("Debug capture of client {} stopped", client_id)));
+    }
+
+    fn authorize(&self, authorization: Option<String>, endpoint: &str, resource: String) -> Result<(), ApiResponse> {
+        if let Err(response) = authorize(&self.config.admin, endpoint, authorization.as_ref()) {
+            warn!("Refused {}: {}", resource, response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource, reason: response.message.clone() });
+            return Err(response);
+        }
+        return Ok(());
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, client_handler: Arc<ClientHandler>, audit_log: Arc<AuditLog>) -> Self {
+        Self { config, client_handler, audit_log }
+    }
+}
@@ -0,0 +1,42 @@
+use std::sync::Arc;
+
+use log::{error, trace, warn};
+
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::broker::diagnostics::{Diagnostics, DiagnosticsDump};
+use crate::config::broker_config::BrokerConfig;
+use crate::metrics::admin_api::{ApiResponse, authorize};
+
+//POST /diagnostics, the same dump as SIGUSR1 for brokers whose process can't be signalled
+#[derive(Debug)]
+pub struct DiagnosticsApi {
+    config: Arc<BrokerConfig>,
+    diagnostics: Arc<Diagnostics>,
+    audit_log: Arc<AuditLog>,
+}
+
+impl DiagnosticsApi {
+    pub fn dump(&self, authorization: Option<String>) -> Result<DiagnosticsDump, ApiResponse> {
+        trace!("DiagnosticsApi::dump");
+        self.authorize(authorization, "POST /diagnostics", String::from("POST /diagnostics"))?;
+        let dump = self.diagnostics.dump().map_err(|err| {
+            error!("{}", err);
+            ApiResponse::new(500, err)
+        })?;
+        self.audit_log.record(AuditEvent::AdminAction { action: String::from("dump-diagnostics"), resource: dump.path.clone() });
+        return Ok(dump);
+    }
+
+    fn authorize(&self, authorization: Option<String>, endpoint: &str, resource: String) -> Result<(), ApiResponse> {
+        if let Err(response) = authorize(&self.config.admin, endpoint, authorization.as_ref()) {
+            warn!("Refused {}: {}", resource, response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource, reason: response.message.clone() });
+            return Err(response);
+        }
+        return Ok(());
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, diagnostics: Arc<Diagnostics>, audit_log: Arc<AuditLog>) -> Self {
+        Self { config, diagnostics, audit_log }
+    }
+}
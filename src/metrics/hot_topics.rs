@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use metered::HitCount;
+use serde::{Deserialize, Serialize};
+
+use crate::config::broker_config::HotTopicsConfig;
+
+const DEFAULT_LIMIT: usize = 10;
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct HotTopicsMetrics {
+    pub(crate) recorded: HitCount,
+    //Topics pushed out of the table by a hotter one
+    pub(crate) evicted: HitCount,
+}
+
+//Exponentially decayed publish count of one topic
+#[derive(Debug)]
+struct TopicRate {
+    score: f64,
+    updated_at: Instant,
+    //Publishes seen since the topic entered the table
+    messages: u64,
+    //Score inherited from the evicted topic, the rate may be overestimated by that much
+    error: f64,
+}
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct HotTopicsQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+pub struct HotTopic {
+    pub topic: String,
+    //Publishes per second, decayed with the configured half-life
+    pub rate: f64,
+    pub messages: u64,
+    //Upper bound of the overestimation of rate
+    pub error: f64,
+}
+
+//GET /hot-topics, the hottest of the tracked topics
+#[derive(Debug)]
+#[derive(PartialEq)]
+#[derive(Serialize)]
+pub struct HotTopicsReport {
+    pub capacity: usize,
+    pub tracked: usize,
+    pub topics: Vec<HotTopic>,
+}
+
+//The publish rate of at most capacity topics. A topic missing from the full table replaces the coldest one
+//and inherits its score, so a newly hot topic gets in while memory and metric cardinality stay bounded.
+#[derive(Debug)]
+pub struct HotTopics {
+    config: HotTopicsConfig,
+    topics: Mutex<HashMap<String, TopicRate>>,
+    pub(crate) metrics: HotTopicsMetrics,
+}
+
+impl HotTopics {
+    pub fn record(&self, topic_name: &str, now: Instant) {
+        if !self.config.enabled || self.config.capacity == 0 {
+            return;
+        }
+        self.metrics.recorded.incr();
+        let mut topics = self.topics.lock().unwrap();
+        if let Some(topic_rate) = topics.get_mut(topic_name) {
+            topic_rate.score = self.decayed(topic_rate, now) + 1.0;
+            topic_rate.updated_at = now;
+            topic_rate.messages += 1;
+            return;
+        }
+        let mut error = 0.0;
+        if topics.len() >= self.config.capacity {
+            let coldest = topics.iter()
+                .map(|(topic_name, topic_rate)| (topic_name, self.decayed(topic_rate, now)))
+                .min_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(topic_name, score)| (topic_name.clone(), score));
+            if let Some((coldest_topic, score)) = coldest {
+                topics.remove(&coldest_topic);
+                self.metrics.evicted.incr();
+                error = score;
+            }
+        }
+        topics.insert(topic_name.to_string(), TopicRate { score: error + 1.0, updated_at: now, messages: 1, error });
+    }
+
+    pub fn report(&self, limit: Option<usize>, now: Instant) -> HotTopicsReport {
+        let topics = self.topics.lock().unwrap();
+        let mut hot_topics: Vec<HotTopic> = topics.iter()
+            .map(|(topic_name, topic_rate)| HotTopic {
+                topic: topic_name.clone(),
+                rate: self.rate(self.decayed(topic_rate, now)),
+                messages: topic_rate.messages,
+                error: self.rate(topic_rate.error),
+            })
+            .collect();
+        hot_topics.sort_by(|a, b| b.rate.total_cmp(&a.rate).then_with(|| a.topic.cmp(&b.topic)));
+        hot_topics.truncate(limit.unwrap_or(DEFAULT_LIMIT).min(self.config.capacity));
+        HotTopicsReport { capacity: self.config.capacity, tracked: topics.len(), topics: hot_topics }
+    }
+
+    fn decayed(&self, topic_rate: &TopicRate, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(topic_rate.updated_at);
+        topic_rate.score * 0.5_f64.powf(elapsed.as_secs_f64() / self.half_life().as_secs_f64())
+    }
+
+    //A steady rate r keeps the score at r * half-life / ln 2
+    fn rate(&self, score: f64) -> f64 {
+        score * std::f64::consts::LN_2 / self.half_life().as_secs_f64()
+    }
+
+    fn half_life(&self) -> Duration {
+        Duration::from_secs(self.config.half_life_secs.max(1))
+    }
+
+    pub fn new(config: HotTopicsConfig) -> Self {
+        Self { config, topics: Mutex::new(HashMap::new()), metrics: HotTopicsMetrics::default() }
+    }
+}
@@ -0,0 +1,57 @@
+use std::sync::Arc;
+
+use log::{trace, warn};
+
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::config::broker_config::BrokerConfig;
+use crate::connection::listeners::{ListenerMode, ListenerStatus, Listeners};
+use crate::metrics::admin_api::{ApiResponse, authorize};
+
+//GET /listeners and PUT, DELETE /listeners/{name}/drain, to stop a listener taking new connections without
+//closing the ones it took
+#[derive(Debug)]
+pub struct ListenerApi {
+    config: Arc<BrokerConfig>,
+    listeners: Arc<Listeners>,
+    audit_log: Arc<AuditLog>,
+}
+
+impl ListenerApi {
+    pub fn list(&self, authorization: Option<String>) -> Result<Vec<ListenerStatus>, ApiResponse> {
+        trace!("ListenerApi::list");
+        self.authorize(authorization, "GET /listeners", String::from("GET /listeners"))?;
+        return Ok(self.listeners.statuses());
+    }
+
+    pub fn drain(&self, authorization: Option<String>, name: String) -> Result<ListenerStatus, ApiResponse> {
+        trace!("ListenerApi::drain");
+        self.authorize(authorization, "PUT /listeners/drain", format!("PUT /listeners/{}/drain", name))?;
+        return self.set_mode(name, ListenerMode::Draining, "drain-listener");
+    }
+
+    pub fn resume(&self, authorization: Option<String>, name: String) -> Result<ListenerStatus, ApiResponse> {
+        trace!("ListenerApi::resume");
+        self.authorize(authorization, "DELETE /listeners/drain", format!("DELETE /listeners/{}/drain", name))?;
+        return self.set_mode(name, ListenerMode::Accepting, "resume-listener");
+    }
+
+    fn set_mode(&self, name: String, mode: ListenerMode, action: &str) -> Result<ListenerStatus, ApiResponse> {
+        let status = self.listeners.set_mode(&name, mode)
+            .ok_or_else(|| ApiResponse::new(404, format!("No listener {:?}", name)))?;
+        self.audit_log.record(AuditEvent::AdminAction { action: String::from(action), resource: name });
+        return Ok(status);
+    }
+
+    fn authorize(&self, authorization: Option<String>, endpoint: &str, resource: String) -> Result<(), ApiResponse> {
+        if let Err(response) = authorize(&self.config.admin, endpoint, authorization.as_ref()) {
+            warn!("Refused {}: {}", resource, response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource, reason: response.message.clone() });
+            return Err(response);
+        }
+        return Ok(());
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, listeners: Arc<Listeners>, audit_log: Arc<AuditLog>) -> Self {
+        Self { config, listeners, audit_log }
+    }
+}
@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use log::{trace, warn};
+
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::config::broker_config::BrokerConfig;
+use crate::logging::log_levels::{LogLevelOverride, LogLevelRequest, LogLevels};
+use crate::metrics::admin_api::{ApiResponse, authorize};
+
+//GET, PUT and DELETE /log-levels, to debug a running broker without restarting it
+#[derive(Debug)]
+pub struct LogLevelApi {
+    config: Arc<BrokerConfig>,
+    log_levels: Arc<LogLevels>,
+    audit_log: Arc<AuditLog>,
+}
+
+impl LogLevelApi {
+    pub fn list(&self, authorization: Option<String>) -> Result<Vec<LogLevelOverride>, ApiResponse> {
+        trace!("LogLevelApi::list");
+        self.authorize(authorization, "GET /log-levels", String::from("GET /log-levels"))?;
+        return Ok(self.log_levels.overrides());
+    }
+
+    pub fn set(&self, authorization: Option<String>, request: LogLevelRequest) -> Result<LogLevelOverride, ApiResponse> {
+        trace!("LogLevelApi::set");
+        self.authorize(authorization, "PUT /log-levels", format!("PUT /log-levels {}", request.module))?;
+        let level_override = self.log_levels.set(&request).map_err(|err| ApiResponse::new(400, err))?;
+        self.audit_log.record(AuditEvent::AdminAction { action: format!("set-log-level {}", level_override.level), resource: level_override.module.clone() });
+        return Ok(level_override);
+    }
+
+    pub fn reset(&self, authorization: Option<String>, module: String) -> Result<ApiResponse, ApiResponse> {
+        trace!("LogLevelApi::reset");
+        self.authorize(authorization, "DELETE /log-levels", format!("DELETE /log-levels/{}", module))?;
+        if !self.log_levels.reset(&module) {
+            return Err(ApiResponse::new(404, format!("No log level set for {:?}", module)));
+        }
+        self.audit_log.record(AuditEvent::AdminAction { action: String::from("reset-log-level"), resource: module.clone() });
+        return Ok(ApiResponse::new(200, format!("Log level of {} reset", module)));
+    }
+
+    fn authorize(&self, authorization: Option<String>, endpoint: &str, resource: String) -> Result<(), ApiResponse> {
+        if let Err(response) = authorize(&self.config.admin, endpoint, authorization.as_ref()) {
+            warn!("Refused {}: {}", resource, response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource, reason: response.message.clone() });
+            return Err(response);
+        }
+        return Ok(());
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, log_levels: Arc<LogLevels>, audit_log: Arc<AuditLog>) -> Self {
+        Self { config, log_levels, audit_log }
+    }
+}
@@ -1,42 +1,104 @@
+use crate::auth::acl::AclMetrics;
+use crate::auth::authenticator::AuthMetrics;
+use crate::broker::broker_info::BrokerInfoMetrics;
 use crate::broker::handler::connect_handler::ConnectHandlerMetrics;
 use crate::broker::handler::disconnect_handler::DisconnectHandlerMetrics;
 use crate::broker::handler::pingreq_handler::PingreqHandlerMetrics;
-use crate::broker::handler::publish_handler::PublishHandlerMetrics;
+use crate::broker::handler::publish_handler::{OfflineDeliveryMetrics, PublishHandlerMetrics, RoutingMetrics};
+use crate::broker::handler::puback_handler::PubackHandlerMetrics;
+use crate::broker::handler::pubcomp_handler::PubcompHandlerMetrics;
 use crate::broker::handler::pubrec_handler::PubrecHandlerMetrics;
 use crate::broker::handler::pubrel_handler::PubrelHandlerMetrics;
 use crate::broker::handler::subscribe_handler::SubscribeHandlerMetrics;
-use crate::broker::handler::unsubscribe_handler::UnsubscribeHandlerMetrics;
+use crate::broker::handler::unsubscribe_handler::{QueuedDropMetrics, UnsubscribeHandlerMetrics};
+use crate::broker::invariants::InvariantMetrics;
+use crate::broker::middleware::LatePacketMetrics;
 use crate::broker::packet_dispatcher::{*};
-use crate::connection::rx_connection_handler::RxClientHandlerMetrics;
+use crate::broker::quarantine::QuarantineMetrics;
+use crate::broker::retained_delivery::RetainedDeliveryMetrics;
+use crate::broker::shared_rebalance::SharedSubscriptionMetrics;
+use crate::connection::listeners::ListenerMetrics;
+use crate::connection::pending_handshakes::HandshakeMetrics;
+use crate::connection::rx_connection_handler::{ConnectionCloseMetrics, RxClientHandlerMetrics};
+use crate::connection::subnet_stats::SubnetMetrics;
 use crate::connection::tx_connection_handler::TxClientHandlerMetrics;
+use crate::limits::congestion_control::{BusyConnectMetrics, CongestionMetrics};
+use crate::limits::connection_pacing::PacingMetrics;
+use crate::limits::fair_share::FairShareMetrics;
+use crate::limits::quota_handler::QuotaHandlerMetrics;
+use crate::metrics::hot_topics::HotTopicsMetrics;
+use crate::metrics::payload_sizes::PayloadSizeMetrics;
+use crate::serdes::decode_pool::DecodePoolMetrics;
 use crate::serdes::deserializer::fixed_header_decoder::FixedHeaderDecoderMetrics;
+use crate::serdes::deserializer::packet_validator::PacketValidatorMetrics;
 use crate::serdes::deserializer::payload_decoder::PayloadDecoderMetrics;
 use crate::serdes::deserializer::variable_header_decoder::VariableHeaderDecoderMetrics;
 use crate::serdes::mqtt_decoder::MqttDecoderMetrics;
 use crate::serdes::mqtt_encoder::MqttEncoderMetrics;
+#[cfg(feature = "symmetry-check")]
+use crate::serdes::symmetry_check::SymmetryCheckMetrics;
 use crate::session::client_handler::ClientHandlerMetrics;
+use crate::session::client_limits::ClientLimitsMetrics;
+use crate::session::qos2_tracker::Qos2Metrics;
+use crate::session::takeover_tracker::TakeoverMetrics;
 //use crate::session::session_handler::SessionHandlerMetrics;
 use crate::topic::topic_handler::TopicHandlerMetrics;
+use crate::topic::virtual_topics::VirtualTopicMetrics;
+use crate::topic::tree_telemetry::TreeShapeMetrics;
 
 #[derive(Clone)]
 #[derive(serde::Serialize)]
 pub struct ServiceMetricRegistry<'a> {
+    pub(crate) broker_info: &'a BrokerInfoMetrics,
     pub(crate) rx_client_handler: &'a RxClientHandlerMetrics,
+    pub(crate) connection_close: &'a ConnectionCloseMetrics,
+    pub(crate) pending_handshakes: &'a HandshakeMetrics,
+    pub(crate) accept_pacing: &'a PacingMetrics,
+    pub(crate) connack_pacing: &'a PacingMetrics,
+    pub(crate) listeners: &'a ListenerMetrics,
+    pub(crate) subnets: &'a SubnetMetrics,
     pub(crate) tx_client_handler: &'a TxClientHandlerMetrics,
     pub(crate) packet_dispatcher: &'a PacketDispatcherMetrics,
+    pub(crate) late_packets: &'a LatePacketMetrics,
+    pub(crate) invariants: &'a InvariantMetrics,
     pub(crate) mqtt_decoder: &'a MqttDecoderMetrics,
+    pub(crate) decode_pool: &'a DecodePoolMetrics,
     pub(crate) fixed_header_decoder: &'a FixedHeaderDecoderMetrics,
     pub(crate) variable_header_decoder: &'a VariableHeaderDecoderMetrics,
     pub(crate) payload_decoder: &'a PayloadDecoderMetrics,
+    pub(crate) packet_validator: &'a PacketValidatorMetrics,
     pub(crate) mqtt_encoder: &'a MqttEncoderMetrics,
+    #[cfg(feature = "symmetry-check")]
+    pub(crate) symmetry_check: &'a SymmetryCheckMetrics,
     pub(crate) client_handler: &'a ClientHandlerMetrics,
+    pub(crate) client_limits: &'a ClientLimitsMetrics,
     pub(crate) topic_handler: &'a TopicHandlerMetrics,
+    pub(crate) topic_tree: &'a TreeShapeMetrics,
+    pub(crate) virtual_topics: &'a VirtualTopicMetrics,
+    pub(crate) quota_handler: &'a QuotaHandlerMetrics,
+    pub(crate) authenticator: &'a AuthMetrics,
+    pub(crate) acl: &'a AclMetrics,
+    pub(crate) takeover_tracker: &'a TakeoverMetrics,
+    pub(crate) quarantine: &'a QuarantineMetrics,
+    pub(crate) congestion_control: &'a CongestionMetrics,
+    pub(crate) busy_connects: &'a BusyConnectMetrics,
+    pub(crate) fair_share: &'a FairShareMetrics,
+    pub(crate) retained_delivery: &'a RetainedDeliveryMetrics,
+    pub(crate) shared_subscriptions: &'a SharedSubscriptionMetrics,
+    pub(crate) qos2_tracker: &'a Qos2Metrics,
+    pub(crate) hot_topics: &'a HotTopicsMetrics,
+    pub(crate) payload_sizes: &'a PayloadSizeMetrics,
     pub(crate) connect_handler: &'a ConnectHandlerMetrics,
     pub(crate) disconnect_handler: &'a DisconnectHandlerMetrics,
     pub(crate) pingreq_handler: &'a PingreqHandlerMetrics,
     pub(crate) publish_handler: &'a PublishHandlerMetrics,
+    pub(crate) offline_delivery: &'a OfflineDeliveryMetrics,
+    pub(crate) unsubscribe_dropped: &'a QueuedDropMetrics,
+    pub(crate) publish_routing: &'a RoutingMetrics,
+    pub(crate) puback_handler: &'a PubackHandlerMetrics,
     pub(crate) pubrec_handler: &'a PubrecHandlerMetrics,
     pub(crate) pubrel_handler: &'a PubrelHandlerMetrics,
+    pub(crate) pubcomp_handler: &'a PubcompHandlerMetrics,
     pub(crate) subscribe_handler: &'a SubscribeHandlerMetrics,
     pub(crate) unsubscribe_handler: &'a UnsubscribeHandlerMetrics
 }
@@ -1,50 +1,617 @@
-use std::collections::HashMap;
+use std::convert::Infallible;
 use std::sync::Arc;
+use std::time::Instant;
 
-use log::info;
+use log::{info, warn};
+use tokio::sync::mpsc::Sender;
 use warp::Filter;
+use warp::http::StatusCode;
+use warp::sse::Event;
 
-use crate::{Broker, RxConnectionHandler, ServiceMetricRegistry, TxConnectionHandler};
+use crate::{Broker, RxConnectionHandler, ServiceMetricRegistry, TopicHandler, TxConnectionHandler};
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::broker::broker_info::BuildInfo;
+use crate::broker::diagnostics::Diagnostics;
+use crate::config::broker_config::{AdminConfig, BrokerConfig};
+use crate::config::port_check::ADMIN_API_ADDRESS;
+use crate::connection::client_context::ClientContext;
+use crate::connection::virtual_endpoint::VirtualEndpoints;
+use crate::metrics::admin_api::authorize;
+use crate::metrics::client_api::ClientApi;
+use crate::metrics::config_api::ConfigApi;
+use crate::metrics::debug_capture_api::{DebugCaptureApi, DebugCaptureQuery};
+use crate::logging::log_levels::{log_levels, LogLevelRequest};
+use crate::metrics::hot_topics::HotTopicsQuery;
+use crate::metrics::listener_api::ListenerApi;
+use crate::metrics::log_level_api::LogLevelApi;
+use crate::metrics::publish_api::{PublishApi, PublishRequest};
+use crate::metrics::diagnostics_api::DiagnosticsApi;
+#[cfg(feature = "profiling")]
+use crate::metrics::profile_api::{ProfileApi, ProfileQuery};
+use crate::metrics::retained_api::{RetainedApi, RetainedQuery, RetainedSelector};
+use crate::metrics::state_api::StateApi;
+use crate::metrics::subnet_api::{SubnetQuery, SubnetReport};
+use crate::metrics::subscription_api::{StaleSubscriptionQuery, StaleSubscriptionReport};
+use crate::metrics::subscribe_api::{SubscribeApi, SubscribeQuery};
+use crate::metrics::takeover_api::{TakeoverQuery, TakeoverReport};
+use crate::metrics::user_api::UserApi;
+use crate::model::control_packet::ControlPacket;
+use crate::session::session_handler::QueueSelector;
 
-#[tokio::main(flavor = "multi_thread", worker_threads = 1)]
 pub async fn start_metrics_server(
     rx_connection_handler: Arc<RxConnectionHandler>,
     tx_connection_handler: Arc<TxConnectionHandler>,
     broker: Arc<Broker>,
+    config: Arc<BrokerConfig>,
+    listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>,
+    virtual_endpoints: Arc<VirtualEndpoints>,
+    topic_handler: Arc<TopicHandler>,
+    diagnostics: Arc<Diagnostics>,
+    audit_log: Arc<AuditLog>,
 ) -> Result<(), Box<dyn std::error::Error>> {
-    info!("Prometheus metrics exposed on 127.0.0.1:9000");
+    info!("Prometheus metrics exposed on {}", ADMIN_API_ADDRESS);
 
-    let routes = warp::get()
+    let maximum_packet_size = config.packet.maximum_packet_size as u64;
+    let admin_config = config.admin.clone();
+    let config_api = Arc::new(ConfigApi::new(config.clone(), audit_log.clone()));
+    let log_level_api = Arc::new(LogLevelApi::new(config.clone(), log_levels(), audit_log.clone()));
+    let packet_dispatcher = &broker.packet_dispatcher;
+    let debug_capture_api = Arc::new(DebugCaptureApi::new(config.clone(), packet_dispatcher.client_handler.clone(), audit_log.clone()));
+    let listener_api = Arc::new(ListenerApi::new(config.clone(), rx_connection_handler.listeners.clone(), audit_log.clone()));
+    let diagnostics_api = Arc::new(DiagnosticsApi::new(config.clone(), diagnostics, audit_log.clone()));
+    #[cfg(feature = "profiling")]
+    let profile_api = Arc::new(ProfileApi::new(config.clone(), audit_log.clone()));
+    let state_api = Arc::new(StateApi::new(config.clone(), packet_dispatcher.client_handler.clone(), topic_handler.clone(), audit_log.clone()));
+    let client_api = Arc::new(ClientApi::new(config.clone(), packet_dispatcher.client_handler.clone(), topic_handler.clone(), packet_dispatcher.quota_handler.clone(), packet_dispatcher.to_listener.clone(), audit_log.clone()));
+    let user_api = Arc::new(UserApi::new(config.clone(), packet_dispatcher.client_handler.clone(), packet_dispatcher.to_listener.clone(), audit_log.clone()));
+    let retained_api = Arc::new(RetainedApi::new(config.clone(), topic_handler.clone(), audit_log.clone()));
+    let subscribe_api = Arc::new(SubscribeApi::new(config.clone(), listener2broker.clone(), virtual_endpoints.clone(), topic_handler, audit_log.clone()));
+    let (publish_api, from_broker) = PublishApi::new(config, listener2broker, virtual_endpoints, audit_log.clone());
+    let publish_api = Arc::new(publish_api);
+    publish_api.start(from_broker).await?;
+
+    let publish = warp::post()
+        .and(warp::path("publish"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(maximum_packet_size))
+        .and(warp::body::json())
+        .and_then(move |authorization: Option<String>, request: PublishRequest| {
+            let publish_api = publish_api.clone();
+            async move {
+                let response = publish_api.handle(authorization, request).await;
+                let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                Ok::<_, Infallible>(warp::reply::with_status(warp::reply::json(&response), status))
+            }
+        });
+
+    let subscribe = warp::get()
+        .and(warp::path("subscribe"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::query::<SubscribeQuery>())
+        .and_then(move |authorization: Option<String>, query: SubscribeQuery| {
+            let subscribe_api = subscribe_api.clone();
+            async move {
+                let reply: Box<dyn warp::Reply> = match subscribe_api.open(authorization, query.topic).await {
+                    Ok(subscription_stream) => {
+                        let events = futures_util::stream::unfold(subscription_stream, |mut subscription_stream| async move {
+                            let envelope = subscription_stream.next_envelope().await?;
+                            Some((Event::default().event("message").json_data(envelope), subscription_stream))
+                        });
+                        Box::new(warp::sse::reply(warp::sse::keep_alive().stream(events)))
+                    }
+                    Err(response) => {
+                        let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                        Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                    }
+                };
+                Ok::<_, Infallible>(reply)
+            }
+        });
+
+    let export_client_api = client_api.clone();
+    let export_client = warp::get()
+        .and(warp::path!("clients" / String))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |client_id: String, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match export_client_api.export(authorization, client_id) {
+                Ok(export) => { Box::new(warp::reply::json(&export)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let queue_client_api = client_api.clone();
+    let client_queue = warp::get()
+        .and(warp::path!("clients" / String / "queue"))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |client_id: String, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match queue_client_api.queue(authorization, client_id) {
+                Ok(messages) => { Box::new(warp::reply::json(&messages)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let purge_queue_client_api = client_api.clone();
+    let purge_client_queue = warp::delete()
+        .and(warp::path!("clients" / String / "queue"))
+        .and(warp::query::<QueueSelector>())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |client_id: String, selector: QueueSelector, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match purge_queue_client_api.purge_queue(authorization, client_id, selector) {
+                Ok(purge) => { Box::new(warp::reply::json(&purge)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let purge_client = warp::delete()
+        .and(warp::path!("clients" / String))
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(move |client_id: String, authorization: Option<String>| {
+            let client_api = client_api.clone();
+            async move {
+                let reply: Box<dyn warp::Reply> = match client_api.purge(authorization, client_id).await {
+                    Ok(purge) => { Box::new(warp::reply::json(&purge)) }
+                    Err(response) => {
+                        let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                        Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                    }
+                };
+                Ok::<_, Infallible>(reply)
+            }
+        });
+
+    let connections_user_api = user_api.clone();
+    let user_connections = warp::get()
+        .and(warp::path!("users" / String / "connections"))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |username: String, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match connections_user_api.connections(authorization, username) {
+                Ok(connections) => { Box::new(warp::reply::json(&connections)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let kick_user = warp::delete()
+        .and(warp::path!("users" / String / "connections"))
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(move |username: String, authorization: Option<String>| {
+            let user_api = user_api.clone();
+            async move {
+                let reply: Box<dyn warp::Reply> = match user_api.kick(authorization, username).await {
+                    Ok(kick) => { Box::new(warp::reply::json(&kick)) }
+                    Err(response) => {
+                        let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                        Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                    }
+                };
+                Ok::<_, Infallible>(reply)
+            }
+        });
+
+    let list_retained_api = retained_api.clone();
+    let list_retained = warp::get()
+        .and(warp::path("retained"))
+        .and(warp::path::end())
+        .and(warp::query::<RetainedQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |query: RetainedQuery, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match list_retained_api.list(authorization, query) {
+                Ok(page) => { Box::new(warp::reply::json(&page)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let delete_retained = warp::delete()
+        .and(warp::path("retained"))
+        .and(warp::path::end())
+        .and(warp::query::<RetainedSelector>())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |selector: RetainedSelector, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match retained_api.delete(authorization, selector) {
+                Ok(purge) => { Box::new(warp::reply::json(&purge)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let export_state_api = state_api.clone();
+    let export_state = warp::get()
+        .and(warp::path("state"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match export_state_api.export(authorization) {
+                Ok(snapshot) => { Box::new(warp::reply::json(&snapshot)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let import_state = warp::put()
+        .and(warp::path("state"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(admin_config.max_state_bytes))
+        .and(warp::body::bytes())
+        .map(move |authorization: Option<String>, body: bytes::Bytes| {
+            let reply: Box<dyn warp::Reply> = match state_api.import(authorization, &body) {
+                Ok(state_import) => { Box::new(warp::reply::json(&state_import)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let effective_config = warp::get()
+        .and(warp::path("config"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match config_api.report(authorization) {
+                Ok(report) => { Box::new(warp::reply::json(&report)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let list_log_level_api = log_level_api.clone();
+    let list_log_levels = warp::get()
+        .and(warp::path("log-levels"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match list_log_level_api.list(authorization) {
+                Ok(overrides) => { Box::new(warp::reply::json(&overrides)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let set_log_level_api = log_level_api.clone();
+    let set_log_level = warp::put()
+        .and(warp::path("log-levels"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("authorization"))
+        .and(warp::body::content_length_limit(maximum_packet_size))
+        .and(warp::body::json())
+        .map(move |authorization: Option<String>, request: LogLevelRequest| {
+            let reply: Box<dyn warp::Reply> = match set_log_level_api.set(authorization, request) {
+                Ok(level_override) => { Box::new(warp::reply::json(&level_override)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let reset_log_level = warp::delete()
+        .and(warp::path!("log-levels" / String))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |module: String, authorization: Option<String>| {
+            let response = match log_level_api.reset(authorization, module) {
+                Ok(response) => { response }
+                Err(response) => { response }
+            };
+            let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            warp::reply::with_status(warp::reply::json(&response), status)
+        });
+
+    let list_debug_capture_api = debug_capture_api.clone();
+    let list_debug_captures = warp::get()
+        .and(warp::path("debug-captures"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match list_debug_capture_api.list(authorization) {
+                Ok(captures) => { Box::new(warp::reply::json(&captures)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let start_debug_capture_api = debug_capture_api.clone();
+    let start_debug_capture = warp::put()
+        .and(warp::path!("debug-captures" / String))
+        .and(warp::query::<DebugCaptureQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |client_id: String, query: DebugCaptureQuery, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match start_debug_capture_api.start(authorization, client_id, query) {
+                Ok(capture) => { Box::new(warp::reply::json(&capture)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let stop_debug_capture = warp::delete()
+        .and(warp::path!("debug-captures" / String))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |client_id: String, authorization: Option<String>| {
+            let response = match debug_capture_api.stop(authorization, client_id) {
+                Ok(response) => { response }
+                Err(response) => { response }
+            };
+            let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            warp::reply::with_status(warp::reply::json(&response), status)
+        });
+
+    let list_listener_api = listener_api.clone();
+    let list_listeners = warp::get()
+        .and(warp::path("listeners"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match list_listener_api.list(authorization) {
+                Ok(listeners) => { Box::new(warp::reply::json(&listeners)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let drain_listener_api = listener_api.clone();
+    let drain_listener = warp::put()
+        .and(warp::path!("listeners" / String / "drain"))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |name: String, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match drain_listener_api.drain(authorization, name) {
+                Ok(listener) => { Box::new(warp::reply::json(&listener)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let resume_listener = warp::delete()
+        .and(warp::path!("listeners" / String / "drain"))
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |name: String, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match listener_api.resume(authorization, name) {
+                Ok(listener) => { Box::new(warp::reply::json(&listener)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    let dump_diagnostics = warp::post()
+        .and(warp::path("diagnostics"))
+        .and(warp::path::end())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match diagnostics_api.dump(authorization) {
+                Ok(dump) => { Box::new(warp::reply::json(&dump)) }
+                Err(response) => {
+                    let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                    Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                }
+            };
+            reply
+        });
+
+    #[cfg(feature = "profiling")]
+    let profile = warp::get()
+        .and(warp::path!("debug" / "pprof" / "profile"))
+        .and(warp::query::<ProfileQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .and_then(move |query: ProfileQuery, authorization: Option<String>| {
+            let profile_api = profile_api.clone();
+            async move {
+                let content_type = query.format.content_type();
+                let reply: Box<dyn warp::Reply> = match profile_api.profile(authorization, query).await {
+                    Ok(profile) => { Box::new(warp::reply::with_header(profile, "content-type", content_type)) }
+                    Err(response) => {
+                        let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+                        Box::new(warp::reply::with_status(warp::reply::json(&response), status))
+                    }
+                };
+                Ok::<_, Infallible>(reply)
+            }
+        });
+
+    let takeover_tracker = broker.packet_dispatcher.takeover_tracker.clone();
+    let takeovers_config = admin_config.clone();
+    let takeovers_audit_log = audit_log.clone();
+    let takeovers = warp::get()
+        .and(warp::path("takeovers"))
+        .and(warp::path::end())
+        .and(warp::query::<TakeoverQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |query: TakeoverQuery, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match authorize_read(&takeovers_config, "GET /takeovers", authorization, &takeovers_audit_log) {
+                Ok(_) => { Box::new(warp::reply::json(&TakeoverReport::new(&takeover_tracker, query.limit))) }
+                Err(reply) => { reply }
+            };
+            reply
+        });
+
+    let subnet_stats = rx_connection_handler.rx_client_handler.subnet_stats.clone();
+    let subnets_config = admin_config.clone();
+    let subnets_audit_log = audit_log.clone();
+    let subnets = warp::get()
+        .and(warp::path("subnets"))
+        .and(warp::path::end())
+        .and(warp::query::<SubnetQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |query: SubnetQuery, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match authorize_read(&subnets_config, "GET /subnets", authorization, &subnets_audit_log) {
+                Ok(_) => { Box::new(warp::reply::json(&SubnetReport::new(&subnet_stats, query.limit))) }
+                Err(reply) => { reply }
+            };
+            reply
+        });
+
+    let stale_topic_handler = broker.packet_dispatcher.topic_handler.clone();
+    let stale_subscriptions_config = admin_config.clone();
+    let stale_subscriptions_audit_log = audit_log.clone();
+    let stale_subscriptions = warp::get()
+        .and(warp::path!("subscriptions" / "stale"))
+        .and(warp::query::<StaleSubscriptionQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |query: StaleSubscriptionQuery, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match authorize_read(&stale_subscriptions_config, "GET /subscriptions/stale", authorization, &stale_subscriptions_audit_log) {
+                Ok(_) => { Box::new(warp::reply::json(&StaleSubscriptionReport::new(&stale_topic_handler, &query))) }
+                Err(reply) => { reply }
+            };
+            reply
+        });
+
+    let hot_topics = broker.packet_dispatcher.hot_topics.clone();
+    let hot_topics_config = admin_config.clone();
+    let hot_topics_audit_log = audit_log.clone();
+    let hot_topics = warp::get()
+        .and(warp::path("hot-topics"))
+        .and(warp::path::end())
+        .and(warp::query::<HotTopicsQuery>())
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |query: HotTopicsQuery, authorization: Option<String>| {
+            let reply: Box<dyn warp::Reply> = match authorize_read(&hot_topics_config, "GET /hot-topics", authorization, &hot_topics_audit_log) {
+                Ok(_) => { Box::new(warp::reply::json(&hot_topics.report(query.limit, Instant::now()))) }
+                Err(reply) => { reply }
+            };
+            reply
+        });
+
+    let metrics = warp::get()
         .and(warp::path("metrics"))
-        .map(move || {
+        .and(warp::header::optional::<String>("authorization"))
+        .map(move |authorization: Option<String>| {
+            if let Err(reply) = authorize_read(&admin_config, "GET /metrics", authorization, &audit_log) {
+                return reply;
+            }
+            let broker_info = broker.packet_dispatcher.broker_info.metrics();
+            let topic_tree = broker.packet_dispatcher.tree_telemetry.metrics();
+            let subnets = rx_connection_handler.rx_client_handler.subnet_stats.metrics();
+            let pending_handshakes = rx_connection_handler.rx_client_handler.pending_handshakes.metrics();
+            let accept_pacing = rx_connection_handler.rx_client_handler.accept_pacing.metrics();
+            let connack_pacing = broker.packet_dispatcher.connect_handler.connack_pacing.metrics();
+            let listeners = rx_connection_handler.listeners.metrics();
+            let payload_sizes = broker.packet_dispatcher.payload_sizes.metrics();
             let registry = &ServiceMetricRegistry {
+                broker_info: &broker_info,
                 rx_client_handler: &rx_connection_handler.rx_client_handler.metrics,
+                connection_close: &rx_connection_handler.rx_client_handler.close_metrics,
+                pending_handshakes: &pending_handshakes,
+                accept_pacing: &accept_pacing,
+                connack_pacing: &connack_pacing,
+                listeners: &listeners,
+                subnets: &subnets,
                 tx_client_handler: &tx_connection_handler.tx_client_handler.metrics,
                 packet_dispatcher: &broker.packet_dispatcher.metrics,
+                late_packets: &broker.packet_dispatcher.late_metrics,
+                invariants: &broker.packet_dispatcher.invariants.metrics,
                 mqtt_decoder: &rx_connection_handler.rx_client_handler.decoder.metrics,
+                decode_pool: &rx_connection_handler.rx_client_handler.decode_pool.metrics,
                 fixed_header_decoder: &rx_connection_handler.rx_client_handler.decoder.fixed_header_decoder.metrics,
                 variable_header_decoder: &rx_connection_handler.rx_client_handler.decoder.variable_header_decoder.metrics,
                 payload_decoder: &rx_connection_handler.rx_client_handler.decoder.payload_decoder.metrics,
+                packet_validator: &rx_connection_handler.rx_client_handler.decoder.packet_validator.metrics,
                 mqtt_encoder: &tx_connection_handler.encoder.metrics,
+                #[cfg(feature = "symmetry-check")]
+                symmetry_check: &tx_connection_handler.encoder.symmetry_check.metrics,
                 client_handler: &broker.packet_dispatcher.client_handler.metrics,
+                client_limits: &broker.packet_dispatcher.client_handler.limits.metrics,
                 topic_handler: &broker.packet_dispatcher.topic_handler.metrics,
+                topic_tree: &topic_tree,
+                virtual_topics: &broker.packet_dispatcher.topic_handler.virtual_topics.metrics,
+                quota_handler: &broker.packet_dispatcher.quota_handler.metrics,
+                authenticator: &broker.packet_dispatcher.authenticator.metrics,
+                acl: &broker.packet_dispatcher.acl.metrics,
+                takeover_tracker: &broker.packet_dispatcher.takeover_tracker.metrics,
+                quarantine: &broker.packet_dispatcher.quarantine.metrics,
+                congestion_control: &broker.packet_dispatcher.publish_handler.congestion_control.metrics,
+                busy_connects: &broker.packet_dispatcher.publish_handler.congestion_control.connect_metrics,
+                fair_share: &broker.packet_dispatcher.publish_handler.fair_share.metrics,
+                retained_delivery: &broker.packet_dispatcher.retained_delivery.metrics,
+                shared_subscriptions: &broker.packet_dispatcher.shared_rebalance.metrics,
+                qos2_tracker: &broker.packet_dispatcher.qos2_tracker.metrics,
+                hot_topics: &broker.packet_dispatcher.hot_topics.metrics,
+                payload_sizes: &payload_sizes,
                 connect_handler: &broker.packet_dispatcher.connect_handler.metrics,
                 disconnect_handler: &broker.packet_dispatcher.disconnect_handler.metrics,
                 pingreq_handler: &broker.packet_dispatcher.pingreq_handler.metrics,
                 publish_handler:&broker.packet_dispatcher.publish_handler.metrics,
+                offline_delivery: &broker.packet_dispatcher.publish_handler.offline_metrics,
+                unsubscribe_dropped: &broker.packet_dispatcher.unsubscribe_handler.queued_metrics,
+                publish_routing: &broker.packet_dispatcher.publish_handler.routing_metrics,
+                puback_handler: &broker.packet_dispatcher.puback_handler.metrics,
                 pubrec_handler: &broker.packet_dispatcher.pubrec_handler.metrics,
                 pubrel_handler: &broker.packet_dispatcher.pubrel_handler.metrics,
+                pubcomp_handler: &broker.packet_dispatcher.pubcomp_handler.metrics,
                 subscribe_handler: &broker.packet_dispatcher.subscribe_handler.metrics,
                 unsubscribe_handler: &broker.packet_dispatcher.unsubscribe_handler.metrics
             };
-            let globals = HashMap::new();
-            serde_prometheus::to_string(
+            let reply: Box<dyn warp::Reply> = Box::new(serde_prometheus::to_string(
                 &registry,
                 Some("patina"),
-                globals,
-            ).unwrap()
+                BuildInfo::metric_labels(),
+            ).unwrap());
+            reply
         });
 
-    warp::serve(routes).run(([127, 0, 0, 1], 9000)).await;
+    let routes = metrics.or(publish).or(subscribe).or(takeovers).or(subnets).or(stale_subscriptions).or(hot_topics).or(export_client).or(purge_client).or(client_queue).or(purge_client_queue).or(user_connections).or(kick_user).or(list_retained).or(delete_retained).or(export_state).or(import_state).or(effective_config).or(list_log_levels).or(set_log_level).or(reset_log_level).or(list_debug_captures).or(start_debug_capture).or(stop_debug_capture).or(list_listeners).or(drain_listener).or(resume_listener).or(dump_diagnostics);
+    #[cfg(feature = "profiling")]
+    let routes = routes.or(profile);
+    let (_, server) = warp::serve(routes).try_bind_ephemeral(ADMIN_API_ADDRESS)
+        .map_err(|err| format!("Cannot bind the admin API to {}. {}", ADMIN_API_ADDRESS, err))?;
+    server.await;
     Ok(())
-}
\ No newline at end of file
+}
+
+//Endpoints answered by the server itself, refused with the JSON body the APIs answer with
+fn authorize_read(config: &AdminConfig, endpoint: &str, authorization: Option<String>, audit_log: &AuditLog) -> Result<(), Box<dyn warp::Reply>> {
+    if let Err(response) = authorize(config, endpoint, authorization.as_ref()) {
+        warn!("Refused {}: {}", endpoint, response.message);
+        audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource: endpoint.to_string(), reason: response.message.clone() });
+        let status = StatusCode::from_u16(response.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        return Err(Box::new(warp::reply::with_status(warp::reply::json(&response), status)));
+    }
+    return Ok(());
+}
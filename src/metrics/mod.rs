@@ -1,3 +1,38 @@
 pub mod metrics_registry;
+pub mod hot_topics;
+pub mod payload_sizes;
+#[cfg(feature = "admin-api")]
 pub(crate) mod metrics_server;
 
+#[cfg(feature = "admin-api")]
+pub(crate) mod admin_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod client_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod config_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod debug_capture_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod diagnostics_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod listener_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod log_level_api;
+#[cfg(feature = "profiling")]
+pub(crate) mod profile_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod publish_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod retained_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod state_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod subnet_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod subscribe_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod subscription_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod takeover_api;
+#[cfg(feature = "admin-api")]
+pub(crate) mod user_api;
@@ -0,0 +1,125 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::RwLock;
+
+use metered::hdr_histogram::{AtomicHdrHistogram, HdrHistogram};
+use metered::HitCount;
+use metered::metric::Histogram;
+use serde::Serialize;
+
+use crate::config::broker_config::PayloadSizesConfig;
+use crate::session::qos2_tracker::Direction;
+
+//Largest payload a PUBLISH can carry, anything above is recorded as this
+const MAX_PAYLOAD_SIZE: u64 = 268_435_455;
+
+//Payload sizes of one direction, overall and by topic prefix
+#[derive(Debug)]
+struct DirectionSizes {
+    all: AtomicHdrHistogram,
+    by_prefix: RwLock<HashMap<String, AtomicHdrHistogram>>,
+    //Messages of prefixes past the capacity
+    other: AtomicHdrHistogram,
+}
+
+impl DirectionSizes {
+    fn metrics(&self) -> DirectionSizeMetrics {
+        DirectionSizeMetrics {
+            all: self.all.histogram(),
+            by_prefix: self.by_prefix.read().unwrap().iter()
+                .map(|(prefix, histogram)| (metric_key(prefix), histogram.histogram()))
+                .collect(),
+            other: self.other.histogram(),
+        }
+    }
+
+    fn new() -> Self {
+        Self { all: AtomicHdrHistogram::with_bound(MAX_PAYLOAD_SIZE), by_prefix: RwLock::new(HashMap::new()), other: AtomicHdrHistogram::with_bound(MAX_PAYLOAD_SIZE) }
+    }
+}
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct PayloadSizesMetrics {
+    //Messages counted under other since the prefixes are at capacity
+    pub(crate) untracked: HitCount,
+}
+
+//Exported with the metrics, samples, min, max, mean and percentiles of the payload sizes in bytes
+#[derive(Debug)]
+#[derive(Serialize)]
+pub struct DirectionSizeMetrics {
+    all: HdrHistogram,
+    by_prefix: BTreeMap<String, HdrHistogram>,
+    other: HdrHistogram,
+}
+
+#[derive(Debug)]
+#[derive(Serialize)]
+pub struct PayloadSizeMetrics {
+    inbound: DirectionSizeMetrics,
+    outbound: DirectionSizeMetrics,
+    untracked: u64,
+}
+
+//Payload size distributions of the PUBLISH packets clients send and receive, to tell many small messages from few
+//huge ones. Topics are grouped by their first payload_sizes.prefix_levels levels, the first capacity prefixes seen
+//get a histogram of their own, later ones share one, so the metric cardinality stays bounded.
+#[derive(Debug)]
+pub struct PayloadSizes {
+    config: PayloadSizesConfig,
+    inbound: DirectionSizes,
+    outbound: DirectionSizes,
+    pub(crate) metrics: PayloadSizesMetrics,
+}
+
+impl PayloadSizes {
+    pub fn record(&self, direction: Direction, topic_name: &str, payload_size: usize) {
+        if !self.config.enabled {
+            return;
+        }
+        let sizes = match direction {
+            Direction::Inbound => { &self.inbound }
+            Direction::Outbound => { &self.outbound }
+        };
+        let payload_size = payload_size as u64;
+        sizes.all.record(payload_size);
+        let prefix = self.prefix(topic_name);
+        if let Some(histogram) = sizes.by_prefix.read().unwrap().get(prefix) {
+            histogram.record(payload_size);
+            return;
+        }
+        let mut by_prefix = sizes.by_prefix.write().unwrap();
+        if !by_prefix.contains_key(prefix) && by_prefix.len() >= self.config.capacity {
+            self.metrics.untracked.incr();
+            sizes.other.record(payload_size);
+            return;
+        }
+        by_prefix.entry(prefix.to_string())
+            .or_insert_with(|| AtomicHdrHistogram::with_bound(MAX_PAYLOAD_SIZE))
+            .record(payload_size);
+    }
+
+    //The first prefix_levels levels of the topic, the whole topic when it has fewer
+    pub fn prefix<'a>(&self, topic_name: &'a str) -> &'a str {
+        match topic_name.match_indices('/').nth(self.config.prefix_levels.max(1) - 1) {
+            Some((index, _)) => { &topic_name[..index] }
+            None => { topic_name }
+        }
+    }
+
+    pub fn metrics(&self) -> PayloadSizeMetrics {
+        PayloadSizeMetrics { inbound: self.inbound.metrics(), outbound: self.outbound.metrics(), untracked: self.metrics.untracked.0.get() }
+    }
+
+    pub fn new(config: PayloadSizesConfig) -> Self {
+        Self { config, inbound: DirectionSizes::new(), outbound: DirectionSizes::new(), metrics: PayloadSizesMetrics::default() }
+    }
+}
+
+//Metric names can't hold slashes, $ or +. The empty first level of /topic names is exported as _
+fn metric_key(prefix: &str) -> String {
+    match prefix.is_empty() {
+        true => { String::from("_") }
+        false => { prefix.chars().map(|character| if character.is_ascii_alphanumeric() { character } else { '_' }).collect() }
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use log::{error, info, trace, warn};
+use pprof::protos::Message;
+use serde::Deserialize;
+
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::config::broker_config::BrokerConfig;
+use crate::metrics::admin_api::{ApiResponse, authorize};
+
+#[derive(Debug, Default)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProfileFormat {
+    //SVG, opens in a browser
+    #[default]
+    Flamegraph,
+    //Protobuf for go tool pprof
+    Pprof,
+}
+
+impl ProfileFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ProfileFormat::Flamegraph => { "image/svg+xml" }
+            ProfileFormat::Pprof => { "application/octet-stream" }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct ProfileQuery {
+    pub seconds: Option<u64>,
+    pub format: ProfileFormat,
+}
+
+struct Running<'a>(&'a AtomicBool);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        self.0.store(false, Ordering::Release);
+    }
+}
+
+//GET /debug/pprof/profile, samples the broker for a while and answers with the profile.
+//The sampler is process wide, a request arriving while another one profiles is refused.
+#[derive(Debug)]
+pub struct ProfileApi {
+    config: Arc<BrokerConfig>,
+    audit_log: Arc<AuditLog>,
+    running: AtomicBool,
+}
+
+impl ProfileApi {
+    pub async fn profile(&self, authorization: Option<String>, query: ProfileQuery) -> Result<Vec<u8>, ApiResponse> {
+        trace!("ProfileApi::profile");
+        if let Err(response) = authorize(&self.config.admin, "GET /debug/pprof/profile", authorization.as_ref()) {
+            warn!("Refused GET /debug/pprof/profile: {}", response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource: String::from("GET /debug/pprof/profile"), reason: response.message.clone() });
+            return Err(response);
+        }
+        let seconds = self.seconds(&query);
+        if self.running.swap(true, Ordering::AcqRel) {
+            return Err(ApiResponse::new(409, String::from("A profile is already running")));
+        }
+        //Released as well when the request is dropped before the profile completes
+        let _running = Running(&self.running);
+        info!("Profiling the broker for {}s", seconds);
+        self.audit_log.record(AuditEvent::AdminAction { action: String::from("cpu-profile"), resource: format!("{}s", seconds) });
+        let frequency = self.config.profiling.frequency;
+        let format = query.format;
+        //The sampler isn't Send, it stays on a blocking thread for the whole profile
+        let profile = tokio::task::spawn_blocking(move || Self::sample(Duration::from_secs(seconds), frequency, format)).await;
+        return match profile {
+            Ok(Ok(profile)) => { Ok(profile) }
+            Ok(Err(err)) => {
+                error!("Profiling failed: {}", err);
+                Err(ApiResponse::new(500, format!("Profiling failed: {}", err)))
+            }
+            Err(err) => {
+                error!("Profiling task failed: {:?}", err);
+                Err(ApiResponse::new(500, String::from("Profiling task failed")))
+            }
+        };
+    }
+
+    //Between 1 and profiling.max_seconds, profiling.default_seconds when not given
+    pub fn seconds(&self, query: &ProfileQuery) -> u64 {
+        query.seconds.unwrap_or(self.config.profiling.default_seconds).clamp(1, self.config.profiling.max_seconds.max(1))
+    }
+
+    fn sample(duration: Duration, frequency: i32, format: ProfileFormat) -> Result<Vec<u8>, String> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(frequency)
+            //Unwinding from these can deadlock in the signal handler
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .map_err(|err| err.to_string())?;
+        std::thread::sleep(duration);
+        let report = guard.report().build().map_err(|err| err.to_string())?;
+        let mut profile = vec![];
+        match format {
+            ProfileFormat::Flamegraph => {
+                report.flamegraph(&mut profile).map_err(|err| err.to_string())?;
+                if profile.is_empty() {
+                    return Err(String::from("no samples, the broker was idle"));
+                }
+            }
+            ProfileFormat::Pprof => {
+                profile = report.pprof().map_err(|err| err.to_string())?
+                    .write_to_bytes().map_err(|err| err.to_string())?;
+            }
+        }
+        Ok(profile)
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, audit_log: Arc<AuditLog>) -> Self {
+        Self { config, audit_log, running: AtomicBool::new(false) }
+    }
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+
+use dashmap::DashMap;
+use log::{debug, info, trace, warn};
+use serde::Deserialize;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::oneshot;
+
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::config::broker_config::BrokerConfig;
+use crate::connection::client_context::ClientContext;
+use crate::connection::virtual_endpoint::VirtualEndpoints;
+use crate::metrics::admin_api::{ApiResponse, authorize};
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::qos_level::QoSLevel;
+use crate::model::reason_code::ReasonCode;
+use crate::model::variable_header::{ConnectFlags, Property};
+
+const ENDPOINT_CAPACITY: usize = 1000;
+const ACKNOWLEDGEMENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct PublishRequest {
+    pub topic: String,
+    pub payload: String,
+    #[serde(default)]
+    pub qos: u8,
+    #[serde(default)]
+    pub retain: bool,
+    #[serde(default)]
+    pub user_properties: BTreeMap<String, String>,
+}
+
+//Publishes made over HTTP. They are injected into the broker as PUBLISH packets of a single
+//virtual client, so they take the same path through PublishHandler as those of TCP clients.
+//QoS 1 and 2 requests are answered once the broker has acknowledged the message.
+#[derive(Debug)]
+pub struct PublishApi {
+    config: Arc<BrokerConfig>,
+    listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>,
+    broker_socket: SocketAddr,
+    next_packet_identifier: AtomicU16,
+    pending: Arc<DashMap<u16, oneshot::Sender<ReasonCode>>>,
+    audit_log: Arc<AuditLog>,
+}
+
+impl PublishApi {
+    pub async fn start(&self, from_broker: Receiver<ControlPacket>) -> Result<(), String> {
+        tokio::spawn(Self::handle_broker_packets(self.broker_socket, from_broker, self.listener2broker.clone(), self.pending.clone()));
+        let connect_flags = ConnectFlags::new(false, false, false, QoSLevel::AtMostOnce, false, true, false);
+        let connect_packet = ControlPacket::connect(connect_flags, Some(0), vec![], Some(self.config.admin.client_id.clone()), None, None, None, None, None);
+        return self.send_to_broker(connect_packet).await;
+    }
+
+    pub async fn handle(&self, authorization: Option<String>, request: PublishRequest) -> ApiResponse {
+        trace!("PublishApi::handle");
+        if let Err(response) = authorize(&self.config.admin, "POST /publish", authorization.as_ref()) {
+            warn!("Refused HTTP publish to {:?}: {}", request.topic, response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource: format!("POST /publish {}", request.topic), reason: response.message.clone() });
+            return response;
+        }
+
+        let qos = match QoSLevel::from_u8(request.qos) {
+            Some(qos) => { qos }
+            None => { return ApiResponse::new(400, format!("Invalid QoS {}", request.qos)); }
+        };
+        if request.topic.is_empty() || request.topic.contains(['+', '#']) {
+            return ApiResponse::new(400, format!("Invalid topic name {:?}", request.topic));
+        }
+        info!("HTTP PUBLISH to topic:{:?} QoS:{:?} retain:{:?}", request.topic, qos, request.retain);
+        self.audit_log.record(AuditEvent::AdminAction { action: String::from("publish"), resource: request.topic.clone() });
+        let properties = request.user_properties.into_iter()
+            .map(|(key, value)| Property::UserProperty(key, value))
+            .collect();
+
+        if qos == QoSLevel::AtMostOnce {
+            let publish_packet = ControlPacket::publish_with_payload(None, request.topic, qos, request.retain, properties, request.payload.into_bytes());
+            return match self.send_to_broker(publish_packet).await {
+                Ok(_) => { ApiResponse::new(202, String::from("Accepted")) }
+                Err(err) => { ApiResponse::new(500, err) }
+            };
+        }
+
+        let packet_identifier = self.next_packet_identifier();
+        let (acknowledged, acknowledgement) = oneshot::channel();
+        self.pending.insert(packet_identifier, acknowledged);
+        let publish_packet = ControlPacket::publish_with_payload(Some(packet_identifier), request.topic, qos, request.retain, properties, request.payload.into_bytes());
+        if let Err(err) = self.send_to_broker(publish_packet).await {
+            self.pending.remove(&packet_identifier);
+            return ApiResponse::new(500, err);
+        }
+        return match tokio::time::timeout(ACKNOWLEDGEMENT_TIMEOUT, acknowledgement).await {
+            Ok(Ok(ReasonCode::Success)) => { ApiResponse::new(200, String::from("Success")) }
+            Ok(Ok(reason_code)) => { ApiResponse::new(502, format!("{:?}", reason_code)) }
+            Ok(Err(_)) | Err(_) => {
+                self.pending.remove(&packet_identifier);
+                ApiResponse::new(504, String::from("Broker didn't acknowledge the message"))
+            }
+        };
+    }
+
+    async fn handle_broker_packets(broker_socket: SocketAddr, mut from_broker: Receiver<ControlPacket>, listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>, pending: Arc<DashMap<u16, oneshot::Sender<ReasonCode>>>) {
+        while let Some(control_packet) = from_broker.recv().await {
+            let packet_identifier = control_packet.variable_header_opt().and_then(|variable_header| variable_header.packet_identifier_opt());
+            match control_packet.fixed_header().packet_type() {
+                ControlPacketType::PUBACK | ControlPacketType::PUBCOMP => {
+                    let reason_code = control_packet.variable_header().reason_code().copied().unwrap_or(ReasonCode::Success);
+                    if let Some((_, acknowledged)) = packet_identifier.and_then(|packet_identifier| pending.remove(&packet_identifier)) {
+                        let _ = acknowledged.send(reason_code);
+                    }
+                }
+                ControlPacketType::PUBREC => {
+                    if let Err(err) = listener2broker.send((ClientContext::new(broker_socket), ControlPacket::pubrel(packet_identifier))).await {
+                        warn!("Can't send PUBREL to broker: {:?}", err);
+                    }
+                }
+                packet_type => {
+                    debug!("Publish API received {:?}", packet_type);
+                }
+            }
+        }
+        info!("Publish API disconnected from broker");
+    }
+
+    //0 is not a valid Packet Identifier
+    fn next_packet_identifier(&self) -> u16 {
+        loop {
+            let packet_identifier = self.next_packet_identifier.fetch_add(1, Ordering::Relaxed);
+            if packet_identifier != 0 {
+                return packet_identifier;
+            }
+        }
+    }
+
+    async fn send_to_broker(&self, control_packet: ControlPacket) -> Result<(), String> {
+        return self.listener2broker.send((ClientContext::new(self.broker_socket), control_packet)).await
+            .map_err(|err| format!("Can't send message to broker: {:?}", err));
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>, virtual_endpoints: Arc<VirtualEndpoints>, audit_log: Arc<AuditLog>) -> (Self, Receiver<ControlPacket>) {
+        let (broker_socket, from_broker) = virtual_endpoints.register(ENDPOINT_CAPACITY);
+        let publish_api = Self { config, listener2broker, broker_socket, next_packet_identifier: AtomicU16::new(1), pending: Arc::new(DashMap::new()), audit_log };
+        (publish_api, from_broker)
+    }
+}
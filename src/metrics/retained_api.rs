@@ -0,0 +1,131 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use log::{info, trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::TopicHandler;
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::config::broker_config::BrokerConfig;
+use crate::metrics::admin_api::{ApiResponse, authorize};
+use crate::topic::topic_matcher::is_valid_filter;
+
+const DEFAULT_LIMIT: usize = 100;
+const MAXIMUM_LIMIT: usize = 1000;
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct RetainedQuery {
+    pub filter: String,
+    pub limit: Option<usize>,
+    //Topic name the previous page ended with, the page starts after it
+    pub after: Option<String>,
+}
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct RetainedSelector {
+    pub filter: String,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct RetainedMessage {
+    pub topic: String,
+    pub qos: u8,
+    //Payload bytes
+    pub size: usize,
+    //Epoch millis the broker received it at
+    pub received_at: i64,
+    pub expires_in_secs: Option<u32>,
+    pub publisher: String,
+}
+
+//A page of the retained messages matching a filter, in topic name order
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct RetainedPage {
+    pub filter: String,
+    //Matching, on every page
+    pub total: usize,
+    pub messages: Vec<RetainedMessage>,
+    //The after of the next page, None on the last one
+    pub next: Option<String>,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct RetainedPurge {
+    pub filter: String,
+    pub deleted: usize,
+}
+
+//GET /retained?filter=... pages through the retained messages matching a topic filter, DELETE /retained?filter=... clears them
+#[derive(Debug)]
+pub struct RetainedApi {
+    config: Arc<BrokerConfig>,
+    topic_handler: Arc<TopicHandler>,
+    audit_log: Arc<AuditLog>,
+}
+
+impl RetainedApi {
+    pub fn list(&self, authorization: Option<String>, query: RetainedQuery) -> Result<RetainedPage, ApiResponse> {
+        trace!("RetainedApi::list");
+        self.authorize(authorization, "GET /retained", &query.filter)?;
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAXIMUM_LIMIT);
+        let now = Utc::now().timestamp_millis();
+        let retained = self.topic_handler.retained_matching(&query.filter);
+        let total = retained.len();
+        let mut messages: Vec<RetainedMessage> = retained.into_iter()
+            .filter(|(_, message)| query.after.as_ref().map_or(true, |after| message.control_packet().variable_header().topic_name() > after))
+            .take(limit + 1)
+            .map(|(publisher, message)| {
+                let control_packet = message.control_packet();
+                RetainedMessage {
+                    topic: control_packet.variable_header().topic_name().clone(),
+                    qos: control_packet.fixed_header().qos_level().as_u8(),
+                    size: control_packet.payload_opt().map(|payload| payload.data().len()).unwrap_or(0),
+                    received_at: now - message.age().as_millis() as i64,
+                    expires_in_secs: message.remaining_interval(),
+                    publisher,
+                }
+            })
+            .collect();
+        let next = match messages.len() > limit {
+            true => {
+                messages.truncate(limit);
+                messages.last().map(|message| message.topic.clone())
+            }
+            false => { None }
+        };
+        return Ok(RetainedPage { filter: query.filter, total, messages, next });
+    }
+
+    pub fn delete(&self, authorization: Option<String>, selector: RetainedSelector) -> Result<RetainedPurge, ApiResponse> {
+        trace!("RetainedApi::delete");
+        self.authorize(authorization, "DELETE /retained", &selector.filter)?;
+        let deleted = self.topic_handler.clear_retained(&selector.filter);
+        info!("Cleared {} retained messages matching {:?}", deleted, selector.filter);
+        self.audit_log.record(AuditEvent::AdminAction { action: format!("clear-retained {}", deleted), resource: selector.filter.clone() });
+        return Ok(RetainedPurge { filter: selector.filter, deleted });
+    }
+
+    fn authorize(&self, authorization: Option<String>, endpoint: &str, topic_filter: &String) -> Result<(), ApiResponse> {
+        if let Err(response) = authorize(&self.config.admin, endpoint, authorization.as_ref()) {
+            warn!("Refused {} {}: {}", endpoint, topic_filter, response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource: format!("{} {}", endpoint, topic_filter), reason: response.message.clone() });
+            return Err(response);
+        }
+        if !is_valid_filter(topic_filter) {
+            return Err(ApiResponse::new(400, format!("Invalid topic filter {:?}", topic_filter)));
+        }
+        return Ok(());
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, topic_handler: Arc<TopicHandler>, audit_log: Arc<AuditLog>) -> Self {
+        Self { config, topic_handler, audit_log }
+    }
+}
@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::Utc;
+use log::{info, trace, warn};
+
+use crate::{ClientHandler, TopicHandler};
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::config::broker_config::BrokerConfig;
+use crate::metrics::admin_api::{ApiResponse, authorize};
+use crate::session::session_state::{SessionState, StateImport};
+use crate::session::state_snapshot::StateSnapshot;
+
+//GET and PUT /state, the sessions of the running broker as a state snapshot for export-state and import-state
+#[derive(Debug)]
+pub struct StateApi {
+    config: Arc<BrokerConfig>,
+    state: SessionState,
+    audit_log: Arc<AuditLog>,
+}
+
+impl StateApi {
+    pub fn export(&self, authorization: Option<String>) -> Result<StateSnapshot, ApiResponse> {
+        trace!("StateApi::export");
+        self.authorize(authorization, "GET /state")?;
+        let now = Utc::now().timestamp_millis();
+        let snapshot = StateSnapshot::new(self.state.export(&HashMap::new(), now), now);
+        info!("Exported {} sessions", snapshot.sessions.len());
+        self.audit_log.record(AuditEvent::AdminAction { action: String::from("export-state"), resource: format!("{} sessions", snapshot.sessions.len()) });
+        return Ok(snapshot);
+    }
+
+    //Clients that already have a session keep it
+    pub fn import(&self, authorization: Option<String>, json: &[u8]) -> Result<StateImport, ApiResponse> {
+        trace!("StateApi::import");
+        self.authorize(authorization, "PUT /state")?;
+        let snapshot = StateSnapshot::from_json(json).map_err(|err| ApiResponse::new(400, err))?;
+        let state_import = self.state.import(snapshot.sessions, snapshot.exported_at, Utc::now().timestamp_millis());
+        info!("Imported {} sessions, {} clients already had one, {} expired", state_import.imported.len(), state_import.existing.len(), state_import.expired.len());
+        self.audit_log.record(AuditEvent::AdminAction { action: String::from("import-state"), resource: format!("{} sessions", state_import.imported.len()) });
+        return Ok(state_import);
+    }
+
+    fn authorize(&self, authorization: Option<String>, endpoint: &str) -> Result<(), ApiResponse> {
+        if let Err(response) = authorize(&self.config.admin, endpoint, authorization.as_ref()) {
+            warn!("Refused {}: {}", endpoint, response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource: endpoint.to_string(), reason: response.message.clone() });
+            return Err(response);
+        }
+        return Ok(());
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>, audit_log: Arc<AuditLog>) -> Self {
+        Self { state: SessionState::new(config.clone(), client_handler, topic_handler), config, audit_log }
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+use crate::connection::subnet_stats::{SubnetConnections, SubnetStats};
+
+const DEFAULT_LIMIT: usize = 10;
+const MAXIMUM_LIMIT: usize = 1000;
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct SubnetQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct SubnetEntry {
+    pub subnet: String,
+    #[serde(flatten)]
+    pub connections: SubnetConnections,
+}
+
+//GET /subnets, the subnets with the most open connections
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct SubnetReport {
+    pub ipv4_prefix_len: u8,
+    pub ipv6_prefix_len: u8,
+    //Subnets tracked, listed or not
+    pub tracked: usize,
+    pub untracked_connections: u64,
+    pub subnets: Vec<SubnetEntry>,
+}
+
+impl SubnetReport {
+    pub fn new(subnet_stats: &SubnetStats, limit: Option<usize>) -> Self {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAXIMUM_LIMIT);
+        let (ipv4_prefix_len, ipv6_prefix_len) = subnet_stats.prefix_lens();
+        Self {
+            ipv4_prefix_len,
+            ipv6_prefix_len,
+            tracked: subnet_stats.len(),
+            untracked_connections: subnet_stats.metrics.untracked.0.get(),
+            subnets: subnet_stats.top(limit).into_iter()
+                .map(|(subnet, connections)| SubnetEntry { subnet: subnet.to_string(), connections })
+                .collect(),
+        }
+    }
+}
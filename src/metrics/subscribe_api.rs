@@ -0,0 +1,159 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use log::{debug, info, trace, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::{Receiver, Sender};
+
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::config::broker_config::BrokerConfig;
+use crate::connection::client_context::ClientContext;
+use crate::connection::virtual_endpoint::VirtualEndpoints;
+use crate::metrics::admin_api::{ApiResponse, authorize};
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::qos_level::QoSLevel;
+use crate::model::reason_code::ReasonCode;
+use crate::model::variable_header::{ConnectFlags, Property};
+use crate::topic::topic_handler::TopicHandler;
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct SubscribeQuery {
+    pub topic: String,
+}
+
+//One SSE event per PUBLISH delivered to the stream
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct MessageEnvelope {
+    pub topic: String,
+    //Base64 of the raw payload
+    pub payload: String,
+    pub qos: u8,
+    pub retain: bool,
+    pub properties: Vec<Property>,
+}
+
+impl MessageEnvelope {
+    pub fn from_publish(control_packet: &ControlPacket) -> Self {
+        let data = control_packet.payload_opt().map(|payload| payload.data().clone()).unwrap_or_default();
+        Self {
+            topic: control_packet.variable_header().topic_name().clone(),
+            payload: STANDARD.encode(data),
+            qos: control_packet.fixed_header().qos_level().as_u8(),
+            retain: *control_packet.fixed_header().retain(),
+            properties: control_packet.variable_header().properties().clone(),
+        }
+    }
+}
+
+//GET /subscribe streams. Every stream is a virtual client of the broker subscribed to the
+//requested filter, so messages reach it through TopicHandler like any other subscriber.
+#[derive(Debug)]
+pub struct SubscribeApi {
+    config: Arc<BrokerConfig>,
+    listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>,
+    virtual_endpoints: Arc<VirtualEndpoints>,
+    topic_handler: Arc<TopicHandler>,
+    active_streams: Arc<AtomicUsize>,
+    next_stream_id: AtomicU64,
+    audit_log: Arc<AuditLog>,
+}
+
+impl SubscribeApi {
+    pub async fn open(&self, authorization: Option<String>, topic_filter: String) -> Result<SubscriptionStream, ApiResponse> {
+        trace!("SubscribeApi::open");
+        if let Err(response) = authorize(&self.config.admin, "GET /subscribe", authorization.as_ref()) {
+            warn!("Refused HTTP subscribe to {:?}: {}", topic_filter, response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource: format!("GET /subscribe {}", topic_filter), reason: response.message.clone() });
+            return Err(response);
+        }
+        if topic_filter.is_empty() {
+            return Err(ApiResponse::new(400, String::from("Empty topic filter")));
+        }
+        let max_streams = self.config.admin.max_subscribe_streams;
+        if self.active_streams.fetch_add(1, Ordering::SeqCst) >= max_streams {
+            self.active_streams.fetch_sub(1, Ordering::SeqCst);
+            return Err(ApiResponse::new(429, format!("Already {} subscribe streams open", max_streams)));
+        }
+
+        let client_id = format!("{}-sse-{}", self.config.admin.client_id, self.next_stream_id.fetch_add(1, Ordering::Relaxed));
+        let (broker_socket, from_broker) = self.virtual_endpoints.register(self.config.admin.subscribe_stream_capacity);
+        //Built before anything is sent so that an early return still cleans up
+        let stream = SubscriptionStream {
+            client_id: client_id.clone(),
+            broker_socket,
+            from_broker,
+            listener2broker: self.listener2broker.clone(),
+            topic_handler: self.topic_handler.clone(),
+            active_streams: self.active_streams.clone(),
+        };
+        info!("HTTP SUBSCRIBE client: {:?} to topic filter:{:?}. Open streams: {:?}", client_id, topic_filter, self.active_streams());
+        self.audit_log.record(AuditEvent::AdminAction { action: String::from("subscribe"), resource: topic_filter.clone() });
+        let connect_flags = ConnectFlags::new(false, false, false, QoSLevel::AtMostOnce, false, true, false);
+        let connect_packet = ControlPacket::connect(connect_flags, Some(0), vec![], Some(client_id), None, None, None, None, None);
+        let subscribe_packet = ControlPacket::subscribe(Some(1), topic_filter, QoSLevel::AtMostOnce);
+        for control_packet in [connect_packet, subscribe_packet] {
+            if let Err(err) = self.listener2broker.send((ClientContext::new(broker_socket), control_packet)).await {
+                return Err(ApiResponse::new(500, format!("Can't send message to broker: {:?}", err)));
+            }
+        }
+        return Ok(stream);
+    }
+
+    pub fn active_streams(&self) -> usize {
+        self.active_streams.load(Ordering::SeqCst)
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>, virtual_endpoints: Arc<VirtualEndpoints>, topic_handler: Arc<TopicHandler>, audit_log: Arc<AuditLog>) -> Self {
+        Self { config, listener2broker, virtual_endpoints, topic_handler, active_streams: Arc::new(AtomicUsize::new(0)), next_stream_id: AtomicU64::new(1), audit_log }
+    }
+}
+
+//Dropping the stream (the HTTP client went away) disconnects its virtual client
+#[derive(Debug)]
+pub struct SubscriptionStream {
+    client_id: String,
+    broker_socket: SocketAddr,
+    from_broker: Receiver<ControlPacket>,
+    listener2broker: Arc<Sender<(ClientContext, ControlPacket)>>,
+    topic_handler: Arc<TopicHandler>,
+    active_streams: Arc<AtomicUsize>,
+}
+
+impl SubscriptionStream {
+    //Next message for the HTTP client, None once the broker disconnected the stream
+    pub async fn next_envelope(&mut self) -> Option<MessageEnvelope> {
+        while let Some(control_packet) = self.from_broker.recv().await {
+            match control_packet.fixed_header().packet_type() {
+                ControlPacketType::PUBLISH => { return Some(MessageEnvelope::from_publish(&control_packet)); }
+                ControlPacketType::DISCONNECT => { return None; }
+                ControlPacketType::SUBACK => {
+                    if let Some(ReasonCode::GrantedQoS0) = control_packet.payload().reason_codes().first() {
+                        continue;
+                    }
+                    warn!("Broker refused subscription of {:?}", self.client_id);
+                    return None;
+                }
+                packet_type => { debug!("Subscribe stream {:?} received {:?}", self.client_id, packet_type); }
+            }
+        }
+        return None;
+    }
+}
+
+impl Drop for SubscriptionStream {
+    fn drop(&mut self) {
+        debug!("Closing subscribe stream {:?}", self.client_id);
+        if let Err(err) = self.listener2broker.try_send((ClientContext::new(self.broker_socket), ControlPacket::disconnect(ReasonCode::NormalDisconnection))) {
+            warn!("Can't send DISCONNECT for {:?} to broker: {:?}", self.client_id, err);
+        }
+        self.topic_handler.unsubscribe_all(&self.client_id);
+        self.active_streams.fetch_sub(1, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::topic::subscription::SubscriptionRecord;
+use crate::topic::topic_handler::TopicHandler;
+
+const DEFAULT_IDLE_SECS: u64 = 3600;
+const DEFAULT_LIMIT: usize = 100;
+const MAXIMUM_LIMIT: usize = 1000;
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct StaleSubscriptionQuery {
+    pub idle_secs: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+//GET /subscriptions/stale, the subscriptions without a delivery for longer than idle_secs, the longest idle first
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct StaleSubscriptionReport {
+    pub idle_secs: u64,
+    //Stale subscriptions, listed or not
+    pub total: usize,
+    pub subscriptions: Vec<SubscriptionRecord>,
+}
+
+impl StaleSubscriptionReport {
+    pub fn new(topic_handler: &TopicHandler, query: &StaleSubscriptionQuery) -> Self {
+        let idle_secs = query.idle_secs.unwrap_or(DEFAULT_IDLE_SECS);
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAXIMUM_LIMIT);
+        let mut subscriptions = topic_handler.stale_subscriptions(Duration::from_secs(idle_secs));
+        let total = subscriptions.len();
+        subscriptions.sort_by_key(|record| record.metadata.last_activity_at());
+        subscriptions.truncate(limit);
+        Self { idle_secs, total, subscriptions }
+    }
+}
@@ -0,0 +1,55 @@
+use std::net::IpAddr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::session::takeover_tracker::TakeoverTracker;
+
+const DEFAULT_LIMIT: usize = 10;
+const MAXIMUM_LIMIT: usize = 1000;
+
+#[derive(Debug)]
+#[derive(Deserialize)]
+pub struct TakeoverQuery {
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct ClientTakeovers {
+    pub client_id: String,
+    pub takeovers: u64,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct AddressTakeovers {
+    pub address: IpAddr,
+    pub takeovers: u64,
+}
+
+//GET /takeovers, the client ids and addresses with the most session takeovers
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct TakeoverReport {
+    pub total: u64,
+    pub clients: Vec<ClientTakeovers>,
+    pub addresses: Vec<AddressTakeovers>,
+}
+
+impl TakeoverReport {
+    pub fn new(takeover_tracker: &TakeoverTracker, limit: Option<usize>) -> Self {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAXIMUM_LIMIT);
+        Self {
+            total: takeover_tracker.total(),
+            clients: takeover_tracker.top_clients(limit).into_iter()
+                .map(|(client_id, takeovers)| ClientTakeovers { client_id, takeovers })
+                .collect(),
+            addresses: takeover_tracker.top_addresses(limit).into_iter()
+                .map(|(address, takeovers)| AddressTakeovers { address, takeovers })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,87 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use log::{info, trace, warn};
+use serde::Serialize;
+use tokio::sync::mpsc::Sender;
+
+use crate::ClientHandler;
+use crate::audit::audit_log::{AuditEvent, AuditLog};
+use crate::broker::utils::send_packets;
+use crate::config::broker_config::BrokerConfig;
+use crate::metrics::admin_api::{ApiResponse, authorize};
+use crate::model::control_packet::ControlPacket;
+use crate::model::reason_code::ReasonCode;
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct UserClient {
+    pub client_id: String,
+    //None while the client is counted but not yet registered by its CONNECT
+    pub address: Option<SocketAddr>,
+}
+
+//What counts against session.max_connections_per_user
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct UserConnections {
+    pub username: String,
+    pub connection_count: usize,
+    pub clients: Vec<UserClient>,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct UserKick {
+    pub username: String,
+    pub disconnected: Vec<SocketAddr>,
+}
+
+//GET and DELETE /users/{username}/connections, to list or disconnect the connected clients of a username
+#[derive(Debug)]
+pub struct UserApi {
+    config: Arc<BrokerConfig>,
+    client_handler: Arc<ClientHandler>,
+    to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>,
+    audit_log: Arc<AuditLog>,
+}
+
+impl UserApi {
+    pub fn connections(&self, authorization: Option<String>, username: String) -> Result<UserConnections, ApiResponse> {
+        trace!("UserApi::connections");
+        self.authorize(authorization, "GET /users", format!("GET /users/{}/connections", username))?;
+        let clients = self.client_handler.client_ids_of(&username).into_iter()
+            .map(|client_id| UserClient { address: self.client_handler.get_socket(&client_id).ok(), client_id })
+            .collect();
+        return Ok(UserConnections { connection_count: self.client_handler.connection_count(&username), clients, username });
+    }
+
+    //The clients may connect again, a password change or an ACL has to keep them out
+    pub async fn kick(&self, authorization: Option<String>, username: String) -> Result<UserKick, ApiResponse> {
+        trace!("UserApi::kick");
+        self.authorize(authorization, "DELETE /users", format!("DELETE /users/{}/connections", username))?;
+        let sockets = self.client_handler.sockets_of(&username);
+        if !sockets.is_empty() {
+            send_packets(sockets.clone(), &ControlPacket::disconnect(ReasonCode::AdministrativeAction), &self.to_listener).await;
+        }
+        info!("Disconnected {} connections of username {:?}", sockets.len(), username);
+        self.audit_log.record(AuditEvent::AdminAction { action: format!("kick-user {}", sockets.len()), resource: username.clone() });
+        return Ok(UserKick { username, disconnected: sockets });
+    }
+
+    fn authorize(&self, authorization: Option<String>, endpoint: &str, resource: String) -> Result<(), ApiResponse> {
+        if let Err(response) = authorize(&self.config.admin, endpoint, authorization.as_ref()) {
+            warn!("Refused {}: {}", resource, response.message);
+            self.audit_log.record(AuditEvent::AuthFailure { interface: String::from("admin-api"), resource, reason: response.message.clone() });
+            return Err(response);
+        }
+        return Ok(());
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, client_handler: Arc<ClientHandler>, to_listener: Arc<Sender<(Vec<SocketAddr>, ControlPacket)>>, audit_log: Arc<AuditLog>) -> Self {
+        Self { config, client_handler, to_listener, audit_log }
+    }
+}
@@ -34,6 +34,79 @@ impl ControlPacket {
         self.payload.as_ref().expect("Payload")
     }
 
+    pub fn with_user_property(mut self, key: String, value: String) -> Self {
+        if let Some(variable_header) = self.variable_header.as_mut() {
+            variable_header.set_user_property(key, value);
+        }
+        self
+    }
+
+    pub fn with_topic_name(mut self, topic_name: String) -> Self {
+        if let Some(variable_header) = self.variable_header.as_mut() {
+            variable_header.set_topic_name(topic_name);
+        }
+        self
+    }
+
+    pub fn without_topic_alias(mut self) -> Self {
+        if let Some(variable_header) = self.variable_header.as_mut() {
+            variable_header.remove_topic_alias();
+        }
+        self
+    }
+
+    pub fn without_user_property(mut self, key: &str) -> Self {
+        if let Some(variable_header) = self.variable_header.as_mut() {
+            variable_header.remove_user_property(key);
+        }
+        self
+    }
+
+    pub fn with_property(mut self, property: Property) -> Self {
+        if let Some(variable_header) = self.variable_header.as_mut() {
+            variable_header.add_property(property);
+        }
+        self
+    }
+
+    pub fn with_message_expiry_interval(mut self, message_expiry_interval: u32) -> Self {
+        if let Some(variable_header) = self.variable_header.as_mut() {
+            variable_header.set_message_expiry_interval(message_expiry_interval);
+        }
+        self
+    }
+
+    //Reason String and User Property are problem information
+    pub fn without_problem_information(mut self) -> Self {
+        if let Some(variable_header) = self.variable_header.as_mut() {
+            variable_header.remove_problem_information();
+        }
+        self
+    }
+
+    //Only a retransmission by the sender may carry DUP
+    pub fn with_dup_flag(mut self, dup_flag: bool) -> Self {
+        self.fixed_header.set_dup_flag(dup_flag);
+        self
+    }
+
+    //RETAIN of a forwarded message tells whether it was published retained, not that it is a retained message
+    pub fn with_retain(mut self, retain: bool) -> Self {
+        self.fixed_header.set_retain(retain);
+        self
+    }
+
+    //A QoS 0 PUBLISH has no Packet Identifier
+    pub fn with_qos_level(mut self, qos_level: QoSLevel) -> Self {
+        self.fixed_header.set_qos_level(qos_level);
+        if qos_level == QoSLevel::AtMostOnce {
+            if let Some(variable_header) = self.variable_header.as_mut() {
+                variable_header.set_packet_identifier(None);
+            }
+        }
+        self
+    }
+
     pub fn has_client_id(&self) -> bool {
         self.payload_opt().is_some() &&
             self.payload_opt().unwrap().client_id_opt().is_some() &&
@@ -64,9 +137,9 @@ impl ControlPacket {
         let connect_packet = ControlPacket::new(fixed_header, Some(variable_header), Some(payload));
         return connect_packet;
     }
-    pub fn connack(session_present: bool) -> Self {
+    pub fn connack(session_present: bool, reason_code: ReasonCode, properties: Vec<Property>) -> Self {
         let fixed_header = FixedHeader::new(ControlPacketType::CONNACK, vec![false, false, false, false], 0);
-        let variable_header = VariableHeader::from_connack(ConnectAcknowledgeFlags::new(session_present), ReasonCode::Success, vec![]);
+        let variable_header = VariableHeader::from_connack(ConnectAcknowledgeFlags::new(session_present), reason_code, properties);
         let connack_packet = ControlPacket::new(fixed_header, Some(variable_header), None);
         return connack_packet;
     }
@@ -79,21 +152,21 @@ impl ControlPacket {
         let subscribe_packet = ControlPacket::new(fixed_header, Some(variable_header), Some(payload));
         return subscribe_packet;
     }
-    pub fn suback(packet_identifier: Option<u16>, reason_codes: Vec<ReasonCode>) -> Self {
+    pub fn suback(packet_identifier: Option<u16>, reason_codes: Vec<ReasonCode>, properties: Vec<Property>) -> Self {
         let payload = Payload::from_sub_unsub_ack(Option::from(reason_codes));
-        let variable_header = VariableHeader::from_suback(packet_identifier, vec![]);
+        let variable_header = VariableHeader::from_suback(packet_identifier, properties);
         let fixed_header = FixedHeader::new(ControlPacketType::SUBACK, vec![false, false, false, false], 0);
 
         let suback_packet = ControlPacket::new(fixed_header, Some(variable_header), Some(payload));
         return suback_packet;
     }
-    pub fn unsuback(packet_identifier: Option<u16>, reason_codes: Vec<ReasonCode>) -> Self {
+    pub fn unsuback(packet_identifier: Option<u16>, reason_codes: Vec<ReasonCode>, properties: Vec<Property>) -> Self {
         let payload = Payload::from_sub_unsub_ack(Option::from(reason_codes));
-        let variable_header = VariableHeader::from_suback(packet_identifier, vec![]);
+        let variable_header = VariableHeader::from_suback(packet_identifier, properties);
         let fixed_header = FixedHeader::new(ControlPacketType::UNSUBACK, vec![false, false, false, false], 0);
 
-        let suback_packet = ControlPacket::new(fixed_header, Some(variable_header), Some(payload));
-        return suback_packet;
+        let unsuback_packet = ControlPacket::new(fixed_header, Some(variable_header), Some(payload));
+        return unsuback_packet;
     }
     pub fn publish(packet_identifier: Option<u16>, topic_name: Option<String>, dup_flag: bool, qos_level: QoSLevel, retain: bool) -> Self {
         let fixed_header = FixedHeader::from_publish(dup_flag, qos_level, retain, u64::MAX);
@@ -101,15 +174,28 @@ impl ControlPacket {
         let publish_packet = ControlPacket::new(fixed_header, Some(variable_header), None);
         return publish_packet;
     }
+    pub fn publish_with_payload(packet_identifier: Option<u16>, topic_name: String, qos_level: QoSLevel, retain: bool, properties: Vec<Property>, data: Vec<u8>) -> Self {
+        let fixed_header = FixedHeader::from_publish(false, qos_level, retain, 0);
+        let variable_header = VariableHeader::from_publish(packet_identifier, Some(topic_name), properties);
+        let payload = Payload::from_publish(Some(data));
+        let publish_packet = ControlPacket::new(fixed_header, Some(variable_header), Some(payload));
+        return publish_packet;
+    }
     pub fn puback(packet_identifier: Option<u16>) -> Self {
+        return ControlPacket::puback_with_reason_code(packet_identifier, ReasonCode::Success);
+    }
+    pub fn puback_with_reason_code(packet_identifier: Option<u16>, reason_code: ReasonCode) -> Self {
         let fixed_header = FixedHeader::new(ControlPacketType::PUBACK, vec![false, false, false, false], 0);
-        let variable_header = VariableHeader::from_pub_ack_rel_comp(packet_identifier, Some(ReasonCode::Success), vec![]);
+        let variable_header = VariableHeader::from_pub_ack_rel_comp(packet_identifier, Some(reason_code), vec![]);
         let puback_packet = ControlPacket::new(fixed_header, Some(variable_header), None);
         return puback_packet;
     }
     pub fn pubrec(packet_identifier: Option<u16>) -> Self {
+        return ControlPacket::pubrec_with_reason_code(packet_identifier, ReasonCode::Success);
+    }
+    pub fn pubrec_with_reason_code(packet_identifier: Option<u16>, reason_code: ReasonCode) -> Self {
         let fixed_header = FixedHeader::new(ControlPacketType::PUBREC, vec![false, false, false, false], 0);
-        let variable_header = VariableHeader::from_pub_ack_rel_comp(packet_identifier, Some(ReasonCode::Success), vec![]);
+        let variable_header = VariableHeader::from_pub_ack_rel_comp(packet_identifier, Some(reason_code), vec![]);
         let pubrec_packet = ControlPacket::new(fixed_header, Some(variable_header), None);
         return pubrec_packet;
     }
@@ -125,6 +211,11 @@ impl ControlPacket {
         let pubcomp_packet = ControlPacket::new(fixed_header, Some(variable_header), None);
         return pubcomp_packet;
     }
+    pub fn pingreq() -> Self {
+        let fixed_header = FixedHeader::new(ControlPacketType::PINGREQ, vec![false, false, false, false], 0);
+        let pingreq_packet = ControlPacket::new(fixed_header, None, None);
+        return pingreq_packet;
+    }
     pub fn pingresp() -> Self {
         let fixed_header = FixedHeader::new(ControlPacketType::PINGRESP, vec![false, false, false, false], 0);
         let pingresp_packet = ControlPacket::new(fixed_header, None, None);
@@ -1,3 +1,5 @@
+use strum::{EnumIter, IntoStaticStr};
+
 use crate::model::qos_level::QoSLevel;
 
 #[derive(Debug)]
@@ -31,6 +33,24 @@ impl FixedHeader {
     pub fn retain(&self) -> &bool {
         self.retain.as_ref().unwrap()
     }
+    pub fn dup_flag_opt(&self) -> Option<bool> {
+        self.dup_flag
+    }
+    pub fn qos_level_opt(&self) -> Option<QoSLevel> {
+        self.qos_level
+    }
+    pub fn retain_opt(&self) -> Option<bool> {
+        self.retain
+    }
+    pub fn set_qos_level(&mut self, qos_level: QoSLevel) {
+        self.qos_level = Some(qos_level);
+    }
+    pub fn set_dup_flag(&mut self, dup_flag: bool) {
+        self.dup_flag = Some(dup_flag);
+    }
+    pub fn set_retain(&mut self, retain: bool) {
+        self.retain = Some(retain);
+    }
 }
 
 impl FixedHeader {
@@ -64,6 +84,7 @@ impl FixedHeader {
 #[derive(Debug)]
 #[derive(Copy, Clone)]
 #[derive(Eq, PartialEq)]
+#[derive(EnumIter, IntoStaticStr)]
 pub enum ControlPacketType {
     //Reserved
     RESERVED,
@@ -5,4 +5,6 @@ pub mod reason_code;
 pub mod payload;
 pub mod control_packet;
 pub mod topic;
-
+//Used by the protocol_table binary
+#[cfg_attr(not(test), allow(dead_code))]
+pub mod protocol_table;
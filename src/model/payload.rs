@@ -4,6 +4,7 @@ use crate::model::variable_header::Property;
 
 #[derive(Debug)]
 #[derive(Clone)]
+#[derive(PartialEq)]
 pub struct Payload {
     client_id: Option<String>,
     will_properties: Option<Vec<Property>>,
@@ -23,6 +24,18 @@ impl Payload {
     pub fn client_id(&self) -> &String {
         self.client_id.as_ref().expect("client_id")
     }
+    pub fn username(&self) -> Option<&String> {
+        self.username.as_ref()
+    }
+    pub fn password(&self) -> Option<&String> {
+        self.password.as_ref()
+    }
+    pub fn will_topic(&self) -> Option<&String> {
+        self.will_topic.as_ref()
+    }
+    pub fn will_payload(&self) -> Option<&Vec<u8>> {
+        self.will_payload.as_ref()
+    }
     pub fn topic_filters(&self) -> &Vec<TopicFilter> {
         self.topic_filters.as_ref().unwrap()
     }
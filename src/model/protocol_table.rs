@@ -0,0 +1,44 @@
+use serde::Serialize;
+use strum::IntoEnumIterator;
+
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::reason_code::ReasonCode;
+use crate::model::variable_header::Property;
+
+pub const PROTOCOL_VERSION: u8 = 5;
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct ProtocolEntry {
+    pub name: &'static str,
+    pub value: u8,
+}
+
+//Every packet type, property and reason code the model knows, generated from the enums themselves
+//so that a variant added to the model shows up without touching this table
+#[derive(Debug)]
+#[derive(Serialize)]
+pub struct ProtocolTable {
+    pub protocol_version: u8,
+    pub packet_types: Vec<ProtocolEntry>,
+    pub properties: Vec<ProtocolEntry>,
+    pub reason_codes: Vec<ProtocolEntry>,
+}
+
+impl ProtocolTable {
+    pub fn new() -> Self {
+        Self {
+            protocol_version: PROTOCOL_VERSION,
+            packet_types: ControlPacketType::iter()
+                .map(|packet_type| ProtocolEntry { name: packet_type.into(), value: packet_type.as_u8() >> 4 })
+                .collect(),
+            properties: Property::iter()
+                .map(|property| ProtocolEntry { value: property.identifier(), name: property.into() })
+                .collect(),
+            reason_codes: ReasonCode::iter()
+                .map(|reason_code| ProtocolEntry { name: reason_code.into(), value: reason_code.as_u8() })
+                .collect(),
+        }
+    }
+}
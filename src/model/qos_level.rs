@@ -1,6 +1,9 @@
+use serde::{Deserialize, Serialize};
+
 #[derive(Debug)]
 #[derive(Copy, Clone)]
-#[derive(Eq, PartialEq)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Serialize, Deserialize)]
 pub enum QoSLevel {
     AtMostOnce,
     AtLeastOnce,
@@ -25,6 +28,14 @@ impl QoSLevel {
         };
     }
 
+    pub fn as_u8(&self) -> u8 {
+        return match self {
+            QoSLevel::AtMostOnce => { 0 }
+            QoSLevel::AtLeastOnce => { 1 }
+            QoSLevel::ExactlyOnce => { 2 }
+        };
+    }
+
     pub fn to_bool(&self) -> (bool, bool) {
         return match self {
             QoSLevel::AtMostOnce => { (false, false) }
@@ -1,7 +1,10 @@
+use strum::{EnumIter, IntoStaticStr};
+
 
 #[derive(Debug)]
 #[derive(Copy, Clone)]
 #[derive(Eq, PartialEq)]
+#[derive(EnumIter, IntoStaticStr)]
 pub enum ReasonCode {
     Success,
     NormalDisconnection,
@@ -52,6 +55,11 @@ pub enum ReasonCode {
 }
 
 impl ReasonCode {
+    //0x80 and above report a failure
+    pub fn is_error(&self) -> bool {
+        self.as_u8() >= 0x80
+    }
+
     pub fn as_u8(&self) -> u8 {
         return match self {
             ReasonCode::Success => { 0x00_u8 }
@@ -23,8 +23,8 @@ impl RetainHandling {
 
 #[derive(Debug)]
 #[derive(Clone)]
-pub struct TopicFilter {
-    topic_filter: String,
+#[derive(Eq, PartialEq)]
+pub struct SubscriptionOptions {
     maximum_qos: QoSLevel,
     no_local: bool,
     retain_as_published: bool,
@@ -32,14 +32,42 @@ pub struct TopicFilter {
     reserved_bits: Vec<bool>,
 }
 
+impl SubscriptionOptions {
+    pub fn maximum_qos(&self) -> QoSLevel {
+        self.maximum_qos
+    }
+    pub fn no_local(&self) -> bool {
+        self.no_local
+    }
+    pub fn retain_as_published(&self) -> bool {
+        self.retain_as_published
+    }
+    pub fn retain_handling(&self) -> &RetainHandling {
+        &self.retain_handling
+    }
+}
+
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+pub struct TopicFilter {
+    topic_filter: String,
+    //UNSUBSCRIBE carries the bare filter
+    options: Option<SubscriptionOptions>,
+}
+
 impl TopicFilter {
     pub fn from_subscribe(topic_filter: String, maximum_qos: QoSLevel, no_local: bool, retain_as_published: bool, retain_handling: RetainHandling, reserved_bits: Vec<bool>) -> Self {
-        TopicFilter { topic_filter, maximum_qos, no_local, retain_as_published, retain_handling, reserved_bits }
+        let options = SubscriptionOptions { maximum_qos, no_local, retain_as_published, retain_handling, reserved_bits };
+        TopicFilter { topic_filter, options: Some(options) }
     }
     pub fn from_unsubscribe(topic_filter: String) -> Self {
-        TopicFilter { topic_filter, maximum_qos: QoSLevel::ExactlyOnce, no_local: true, retain_as_published: false, retain_handling: RetainHandling::DontSendRetainedMessages, reserved_bits: vec![] }
+        TopicFilter { topic_filter, options: None }
     }
     pub fn topic_filter(&self) -> &String {
         return &self.topic_filter;
     }
+    pub fn options(&self) -> Option<&SubscriptionOptions> {
+        self.options.as_ref()
+    }
 }
\ No newline at end of file
@@ -1,8 +1,12 @@
+use serde::Serialize;
+use strum::{EnumIter, IntoStaticStr};
+
 use crate::model::qos_level::QoSLevel;
 use crate::model::reason_code::ReasonCode;
 
 #[derive(Debug)]
 #[derive(Clone)]
+#[derive(PartialEq)]
 pub struct VariableHeader {
     // START CONNECT
     protocol_name: Option<String>,
@@ -132,12 +136,18 @@ impl VariableHeader {
     pub fn protocol_version(&self) -> u8 {
         self.protocol_version.unwrap()
     }
+    pub fn protocol_version_opt(&self) -> Option<u8> {
+        self.protocol_version
+    }
     pub fn connect_flags(&self) -> &ConnectFlags {
         return self.connect_flags.as_ref().unwrap();
     }
     pub fn keep_alive(&self) -> u16 {
         self.keep_alive.unwrap()
     }
+    pub fn keep_alive_opt(&self) -> Option<u16> {
+        self.keep_alive
+    }
 
     pub fn connect_acknowledge_flags(&self) -> &ConnectAcknowledgeFlags {
         self.connect_acknowledge_flags.as_ref().unwrap()
@@ -150,6 +160,86 @@ impl VariableHeader {
     pub fn properties(&self) -> &Vec<Property> {
         self.properties.as_ref()
     }
+    pub fn session_expiry_interval(&self) -> Option<u32> {
+        self.properties.iter().find_map(|property| match property {
+            Property::SessionExpiryInterval(value) => Some(*value),
+            _ => None
+        })
+    }
+    pub fn receive_maximum(&self) -> Option<u16> {
+        self.properties.iter().find_map(|property| match property {
+            Property::ReceiveMaximum(value) => Some(*value),
+            _ => None
+        })
+    }
+    pub fn maximum_packet_size(&self) -> Option<u32> {
+        self.properties.iter().find_map(|property| match property {
+            Property::MaximumPacketSize(value) => Some(*value),
+            _ => None
+        })
+    }
+    pub fn topic_alias_maximum(&self) -> Option<u16> {
+        self.properties.iter().find_map(|property| match property {
+            Property::TopicAliasMaximum(value) => Some(*value),
+            _ => None
+        })
+    }
+    pub fn topic_alias(&self) -> Option<u16> {
+        self.properties.iter().find_map(|property| match property {
+            Property::TopicAlias(value) => Some(*value),
+            _ => None
+        })
+    }
+    //Absent means 1, the client accepts Reason String and User Property on any packet
+    pub fn request_problem_information(&self) -> bool {
+        self.properties.iter().find_map(|property| match property {
+            Property::RequestProblemInformation(value) => Some(*value != 0),
+            _ => None
+        }).unwrap_or(true)
+    }
+    //Absent means 0, no Response Information in CONNACK
+    pub fn request_response_information(&self) -> bool {
+        self.properties.iter().find_map(|property| match property {
+            Property::RequestResponseInformation(value) => Some(*value != 0),
+            _ => None
+        }).unwrap_or(false)
+    }
+    pub fn remove_topic_alias(&mut self) {
+        self.properties.retain(|property| !matches!(property, Property::TopicAlias(_)));
+    }
+    pub fn remove_problem_information(&mut self) {
+        self.properties.retain(|property| !matches!(property, Property::ReasonString(_) | Property::UserProperty(_, _)));
+    }
+    pub fn message_expiry_interval(&self) -> Option<u32> {
+        self.properties.iter().find_map(|property| match property {
+            Property::MessageExpiryInterval(value) => Some(*value),
+            _ => None
+        })
+    }
+    pub fn set_message_expiry_interval(&mut self, message_expiry_interval: u32) {
+        self.properties.retain(|property| !matches!(property, Property::MessageExpiryInterval(_)));
+        self.properties.push(Property::MessageExpiryInterval(message_expiry_interval));
+    }
+    //Replaces the user properties named key, if any
+    pub fn set_user_property(&mut self, key: String, value: String) {
+        self.properties.retain(|property| !matches!(property, Property::UserProperty(name, _) if name.eq(&key)));
+        self.properties.push(Property::UserProperty(key, value));
+    }
+    pub fn has_user_property(&self, key: &str) -> bool {
+        self.properties.iter().any(|property| matches!(property, Property::UserProperty(name, _) if name == key))
+    }
+    pub fn remove_user_property(&mut self, key: &str) {
+        self.properties.retain(|property| !matches!(property, Property::UserProperty(name, _) if name == key));
+    }
+    pub fn add_property(&mut self, property: Property) {
+        self.properties.push(property);
+    }
+    pub fn set_topic_name(&mut self, topic_name: String) {
+        self.topic_name = Some(topic_name);
+    }
+    pub fn set_packet_identifier(&mut self, packet_identifier: Option<u16>) {
+        self.packet_identifier = packet_identifier;
+    }
     pub fn packet_identifier_opt(&self) -> Option<u16> { self.packet_identifier.clone() }
     pub fn packet_identifier(&self) -> u16 { self.packet_identifier.unwrap() }
     pub fn topic_name(&self) -> &String { self.topic_name.as_ref().unwrap() }
@@ -159,6 +249,7 @@ impl VariableHeader {
 
 #[derive(Debug)]
 #[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
 pub struct ConnectFlags {
     username_flag: bool,
     password_flag: bool,
@@ -201,6 +292,7 @@ impl ConnectFlags {
 
 #[derive(Debug)]
 #[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
 pub struct ConnectAcknowledgeFlags {
     session_present: bool,
 }
@@ -223,6 +315,8 @@ impl ConnectAcknowledgeFlags {
 #[derive(Debug)]
 #[derive(Clone)]
 #[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+#[derive(EnumIter, IntoStaticStr)]
 pub enum Property {
     PayloadFormatIndicator(u8),
     MessageExpiryInterval(u32),
@@ -232,7 +326,7 @@ pub enum Property {
     SubscriptionIdentifier(u64),
     SessionExpiryInterval(u32),
     AssignedClientIdentifier(String),
-    ServerKeepAlive(u8),
+    ServerKeepAlive(u16),
     AuthenticationMethod(String),
     AuthenticationData(Vec<u8>),
     RequestProblemInformation(u8),
@@ -251,4 +345,39 @@ pub enum Property {
     WildcardSubscriptionAvailable(u8),
     SubscriptionIdentifierAvailable(u8),
     SharedSubscriptionAvailable(u8),
-}
\ No newline at end of file
+}
+
+impl Property {
+    //Identifier the property is encoded with
+    pub fn identifier(&self) -> u8 {
+        return match self {
+            Property::PayloadFormatIndicator(_) => { 1 }
+            Property::MessageExpiryInterval(_) => { 2 }
+            Property::ContentType(_) => { 3 }
+            Property::ResponseTopic(_) => { 8 }
+            Property::CorrelationData(_) => { 9 }
+            Property::SubscriptionIdentifier(_) => { 11 }
+            Property::SessionExpiryInterval(_) => { 17 }
+            Property::AssignedClientIdentifier(_) => { 18 }
+            Property::ServerKeepAlive(_) => { 19 }
+            Property::AuthenticationMethod(_) => { 21 }
+            Property::AuthenticationData(_) => { 22 }
+            Property::RequestProblemInformation(_) => { 23 }
+            Property::WillDelayInterval(_) => { 24 }
+            Property::RequestResponseInformation(_) => { 25 }
+            Property::ResponseInformation(_) => { 26 }
+            Property::ServerReference(_) => { 28 }
+            Property::ReasonString(_) => { 31 }
+            Property::ReceiveMaximum(_) => { 33 }
+            Property::TopicAliasMaximum(_) => { 34 }
+            Property::TopicAlias(_) => { 35 }
+            Property::MaximumQoS(_) => { 36 }
+            Property::RetainAvailable(_) => { 37 }
+            Property::UserProperty(_, _) => { 38 }
+            Property::MaximumPacketSize(_) => { 39 }
+            Property::WildcardSubscriptionAvailable(_) => { 40 }
+            Property::SubscriptionIdentifierAvailable(_) => { 41 }
+            Property::SharedSubscriptionAvailable(_) => { 42 }
+        };
+    }
+}
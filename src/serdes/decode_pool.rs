@@ -0,0 +1,133 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use log::{error, info, trace};
+use metered::HitCount;
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::config::broker_config::DecodeConfig;
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::{ControlPacketType, FixedHeader};
+use crate::serdes::deserializer::error::DecodeResult;
+use crate::serdes::mqtt_decoder::MqttDecoder;
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+pub type Decoded = oneshot::Receiver<DecodeResult<ControlPacket>>;
+
+#[derive(Debug)]
+struct DecodeJob {
+    fixed_header: FixedHeader,
+    body: Vec<u8>,
+    reply: oneshot::Sender<DecodeResult<ControlPacket>>,
+}
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct DecodePoolMetrics {
+    pub(crate) offloaded: HitCount,
+    pub(crate) decoded_inline: HitCount,
+}
+
+//Worker threads decoding the packets that connection readers hand over. Each packet gets its own
+//reply channel, a reader keeps the channels in reading order to keep the order of its packets.
+#[derive(Debug)]
+pub struct DecodePool {
+    config: DecodeConfig,
+    workers: Vec<mpsc::Sender<DecodeJob>>,
+    next_worker: AtomicUsize,
+    pub(crate) metrics: DecodePoolMetrics,
+}
+
+impl DecodePool {
+    pub fn is_enabled(&self) -> bool {
+        !self.workers.is_empty()
+    }
+
+    //CONNECT stays on the reader, which needs its Keep Alive before reading on
+    pub fn should_offload(&self, fixed_header: &FixedHeader, packet_rate: u32) -> bool {
+        let offload = self.is_enabled()
+            && fixed_header.packet_type() != ControlPacketType::CONNECT
+            && (fixed_header.remaining_length() >= self.config.min_packet_size || packet_rate >= self.config.min_packet_rate);
+        match offload {
+            true => { self.metrics.offloaded.incr(); }
+            false => { self.metrics.decoded_inline.incr(); }
+        }
+        offload
+    }
+
+    pub fn submit(&self, fixed_header: FixedHeader, body: Vec<u8>) -> Decoded {
+        trace!("DecodePool::submit");
+        let (reply, decoded) = oneshot::channel();
+        let worker = self.next_worker.fetch_add(1, Ordering::Relaxed) % self.workers.len();
+        if let Err(err) = self.workers[worker].send(DecodeJob { fixed_header, body, reply }) {
+            //The reply sender is dropped with the job, the reader sees the packet as lost
+            error!("Decode worker {} stopped, dropping packet: {:?}", worker, err.0.fixed_header);
+        }
+        decoded
+    }
+
+    //A packet the reader decoded itself, queued behind the offloaded ones before it
+    pub fn ready(decoded: DecodeResult<ControlPacket>) -> Decoded {
+        let (reply, receiver) = oneshot::channel();
+        let _ = reply.send(decoded);
+        receiver
+    }
+
+    pub fn new(config: DecodeConfig, decoder: Arc<MqttDecoder>) -> Self {
+        let workers = (0..config.workers)
+            .map(|worker| {
+                let (jobs_tx, jobs_rx) = mpsc::channel::<DecodeJob>();
+                let decoder = decoder.clone();
+                thread::Builder::new()
+                    .name(format!("decode-worker-{}", worker))
+                    .spawn(move || {
+                        while let Ok(job) = jobs_rx.recv() {
+                            let _ = job.reply.send(decoder.decode_body(job.fixed_header, &job.body));
+                        }
+                    })
+                    .expect("can't spawn decode worker");
+                jobs_tx
+            })
+            .collect();
+        if config.workers > 0 {
+            info!("Decoding packets of at least {} bytes or of connections reading {} packets/s on {} workers", config.min_packet_size, config.min_packet_rate, config.workers);
+        }
+        Self { config, workers, next_worker: AtomicUsize::new(0), metrics: DecodePoolMetrics::default() }
+    }
+}
+
+//Packets a connection read in the last full second
+#[derive(Debug)]
+pub struct PacketRate {
+    window_start: Instant,
+    window_count: u32,
+    rate: u32,
+}
+
+impl Default for PacketRate {
+    fn default() -> Self {
+        Self { window_start: Instant::now(), window_count: 0, rate: 0 }
+    }
+}
+
+impl PacketRate {
+    pub fn record(&mut self, now: Instant) -> u32 {
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed >= RATE_WINDOW {
+            //A connection that was silent for longer than a window starts over
+            self.rate = match elapsed < RATE_WINDOW * 2 {
+                true => { self.window_count }
+                false => { 0 }
+            };
+            self.window_start = now;
+            self.window_count = 0;
+        }
+        self.window_count += 1;
+        self.rate.max(self.window_count)
+    }
+}
@@ -1,71 +1,126 @@
+use thiserror::Error;
+
+use crate::model::reason_code::ReasonCode;
+
 pub type ReadResult<T> = Result<T, ReadError>;
 pub type DecodeResult<T> = Result<T, DecodeError>;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Error)]
 pub enum ReadError {
+    #[error("connection closed")]
     ConnectionError,
+    #[error("not enough data, {requested} bits requested at {position} of {length}")]
     NotEnoughData {
         position: u64,
         length: u64,
         requested: u64,
     },
+    #[error("{requested} bits requested at {position}, the type holds {allowed}")]
     TooManyBitsForType {
         position: u64,
         requested: u8,
         allowed: u8,
     },
+    #[error("exceeded maximum length")]
     ExceededMaxLength,
+    #[error("value {current} exceeds maximum {max}")]
     ExceededMaxValue {
         current: u64,
         max: u64,
     },
+    #[error("invalid data")]
     InvalidData,
+    //Well-formed data that breaks a protocol rule
+    #[error("protocol violation")]
+    ProtocolViolation,
+    #[error("I/O error")]
     IOError,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
+#[derive(Error)]
 pub enum DecodeError {
+    #[error("can't decode VariableHeaderAndPayload: {cause}")]
     VariableHeaderAndPayload { cause: ReadError },
+    #[error("connection timed out: {cause}")]
     ConnectionTimedOut { cause: ReadError },
+    #[error("can't decode VariableByteInteger: {cause}")]
     VariableByteInteger { cause: ReadError },
+    #[error("can't decode UTF8String: {cause}")]
     UTF8String { cause: ReadError },
+    #[error("can't decode BinaryData: {cause}")]
     BinaryData { cause: ReadError },
+    #[error("can't decode PacketType: {cause}")]
     PacketType { cause: ReadError },
+    #[error("can't decode RemainingLength: {cause}")]
     RemainingLength { cause: ReadError },
+    #[error("can't decode ProtocolName: {cause}")]
     ProtocolName { cause: ReadError },
+    #[error("can't decode ProtocolVersion: {cause}")]
     ProtocolVersion { cause: ReadError },
+    #[error("can't decode ConnectFlags: {cause}")]
     ConnectFlags { cause: ReadError },
+    #[error("can't decode PropertyLength: {cause}")]
     PropertyLength { cause: ReadError },
+    #[error("can't decode UnknownProperty: {cause}")]
     UnknownProperty { cause: ReadError },
+    #[error("can't decode KeepAlive: {cause}")]
     KeepAlive { cause: ReadError },
+    #[error("can't decode ClientId: {cause}")]
     ClientId { cause: ReadError },
+    #[error("can't decode Username: {cause}")]
     Username { cause: ReadError },
+    #[error("can't decode Password: {cause}")]
     Password { cause: ReadError },
+    #[error("can't decode WillProperties: {cause}")]
     WillProperties { cause: ReadError },
+    #[error("can't decode WillTopic: {cause}")]
     WillTopic { cause: ReadError },
+    #[error("can't decode WillPayload: {cause}")]
     WillPayload { cause: ReadError },
+    #[error("can't decode ControlFlags: {cause}")]
     ControlFlags { cause: ReadError },
+    #[error("can't decode UsernameFlag: {cause}")]
     UsernameFlag { cause: ReadError },
+    #[error("can't decode PasswordFlag: {cause}")]
     PasswordFlag { cause: ReadError },
+    #[error("can't decode WillRetainFlag: {cause}")]
     WillRetainFlag { cause: ReadError },
+    #[error("can't decode WillQoSFlag: {cause}")]
     WillQoSFlag { cause: ReadError },
+    #[error("can't decode CleanStartFlag: {cause}")]
     CleanStartFlag { cause: ReadError },
+    #[error("can't decode WillFlag: {cause}")]
     WillFlag { cause: ReadError },
+    #[error("can't decode ReservedFlag: {cause}")]
     ReservedFlag { cause: ReadError },
+    #[error("can't decode Property: {cause}")]
     Property { cause: ReadError },
+    #[error("can't decode RetainHandling: {cause}")]
     RetainHandling { cause: ReadError },
+    #[error("can't decode MaximumQoS: {cause}")]
     MaximumQoS { cause: ReadError },
+    #[error("can't decode TopicFilter: {cause}")]
     TopicFilter { cause: ReadError },
+    #[error("can't decode RetainAsPublished: {cause}")]
     RetainAsPublished { cause: ReadError },
+    #[error("can't decode NoLocal: {cause}")]
     NoLocal { cause: ReadError },
+    #[error("can't decode PacketIdentifier: {cause}")]
     PacketIdentifier { cause: ReadError },
+    #[error("can't decode QoSLevel: {cause}")]
     QoSLevel { cause: ReadError },
+    #[error("can't decode DupFlag: {cause}")]
     DupFlag { cause: ReadError },
+    #[error("can't decode RetainFlag: {cause}")]
     RetainFlag { cause: ReadError },
+    #[error("can't decode TopicName: {cause}")]
     TopicName { cause: ReadError },
+    #[error("can't decode Payload: {cause}")]
     Payload { cause: ReadError },
+    #[error("can't decode ReasonCode: {cause}")]
     ReasonCode { cause: ReadError },
-
 }
 
 impl DecodeError {
@@ -113,4 +168,14 @@ impl DecodeError {
             DecodeError::ReasonCode { cause } => { cause.clone() }
         };
     }
-}
\ No newline at end of file
+
+    pub(crate) fn reason_code(&self) -> ReasonCode {
+        return match (self, self.cause()) {
+            (DecodeError::ProtocolVersion { .. }, ReadError::ProtocolViolation) => { ReasonCode::UnsupportedProtocolVersion }
+            (DecodeError::TopicName { .. }, ReadError::ProtocolViolation) => { ReasonCode::TopicNameInvalid }
+            (DecodeError::RemainingLength { .. }, ReadError::ExceededMaxValue { .. }) => { ReasonCode::PacketTooLarge }
+            (_, ReadError::ProtocolViolation) => { ReasonCode::ProtocolError }
+            (_, _) => { ReasonCode::MalformedPacket }
+        };
+    }
+}
@@ -2,4 +2,5 @@ pub mod fixed_header_decoder;
 pub mod variable_header_decoder;
 pub mod property_decoder;
 pub mod payload_decoder;
-pub mod error;
\ No newline at end of file
+pub mod error;
+pub mod packet_validator;
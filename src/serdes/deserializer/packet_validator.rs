@@ -0,0 +1,135 @@
+use log::{debug, error};
+use metered::{*};
+
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::qos_level::QoSLevel;
+use crate::serdes::deserializer::error::{DecodeError, DecodeResult, ReadError};
+
+//Control flags as read by FixedHeaderDecoder, most significant bit first
+const RESERVED_CONTROL_FLAGS: [bool; 4] = [false, false, false, false];
+const SUB_UNSUB_PUBREL_CONTROL_FLAGS: [bool; 4] = [false, false, true, false];
+
+//Cross-field checks that can't be done while decoding a single field.
+//Runs after MqttDecoder built the ControlPacket and before it is sent to the broker,
+//so handlers can rely on the headers and payload they unwrap being present.
+#[derive(Default, Debug)]
+pub struct PacketValidator {
+    pub(crate) metrics: PacketValidatorMetrics,
+}
+
+#[metered(registry = PacketValidatorMetrics)]
+impl PacketValidator {
+    #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
+    pub fn validate(&self, control_packet: &ControlPacket) -> DecodeResult<()> {
+        debug!("PacketValidator::validate");
+        self.validate_control_flags(control_packet)?;
+        return match control_packet.fixed_header().packet_type() {
+            ControlPacketType::CONNECT => { self.validate_connect(control_packet) }
+            ControlPacketType::PUBLISH => { self.validate_publish(control_packet) }
+            ControlPacketType::PUBACK
+            | ControlPacketType::PUBREC
+            | ControlPacketType::PUBREL
+            | ControlPacketType::PUBCOMP => { self.validate_packet_identifier(control_packet) }
+            ControlPacketType::SUBSCRIBE
+            | ControlPacketType::UNSUBSCRIBE => { self.validate_sub_unsub(control_packet) }
+            _ => { Ok(()) }
+        };
+    }
+}
+
+impl PacketValidator {
+    fn validate_control_flags(&self, control_packet: &ControlPacket) -> DecodeResult<()> {
+        let fixed_header = control_packet.fixed_header();
+        let expected_flags = match fixed_header.packet_type() {
+            //PUBLISH flags carry DUP, QoS and RETAIN and are validated by validate_publish
+            ControlPacketType::PUBLISH => { return Ok(()); }
+            ControlPacketType::SUBSCRIBE
+            | ControlPacketType::UNSUBSCRIBE
+            | ControlPacketType::PUBREL => { SUB_UNSUB_PUBREL_CONTROL_FLAGS }
+            _ => { RESERVED_CONTROL_FLAGS }
+        };
+        if fixed_header.control_flags().as_slice() != expected_flags {
+            error!("Invalid control flags {:?} for {:?}", fixed_header.control_flags(), fixed_header.packet_type());
+            return Err(DecodeError::ControlFlags { cause: ReadError::InvalidData });
+        }
+        return Ok(());
+    }
+
+    fn validate_connect(&self, control_packet: &ControlPacket) -> DecodeResult<()> {
+        let variable_header = match control_packet.variable_header_opt() {
+            Some(result) => { result }
+            None => { return Err(DecodeError::VariableHeaderAndPayload { cause: ReadError::InvalidData }); }
+        };
+        if control_packet.payload_opt().is_none() {
+            return Err(DecodeError::ClientId { cause: ReadError::InvalidData });
+        }
+        if variable_header.protocol_name() != "MQTT" {
+            error!("Unsupported protocol name: {:?}", variable_header.protocol_name());
+            return Err(DecodeError::ProtocolName { cause: ReadError::ProtocolViolation });
+        }
+        if variable_header.protocol_version() != 5 {
+            error!("Unsupported protocol version: {:?}", variable_header.protocol_version());
+            return Err(DecodeError::ProtocolVersion { cause: ReadError::ProtocolViolation });
+        }
+
+        let connect_flags = variable_header.connect_flags();
+        if connect_flags.reserved_flag() {
+            error!("CONNECT reserved flag must be 0");
+            return Err(DecodeError::ReservedFlag { cause: ReadError::InvalidData });
+        }
+        if !connect_flags.will_flag() && connect_flags.will_qos() != QoSLevel::AtMostOnce {
+            error!("Will QoS must be 0 when Will Flag is not set");
+            return Err(DecodeError::WillQoSFlag { cause: ReadError::InvalidData });
+        }
+        if !connect_flags.will_flag() && connect_flags.will_retain_flag() {
+            error!("Will Retain must be 0 when Will Flag is not set");
+            return Err(DecodeError::WillRetainFlag { cause: ReadError::InvalidData });
+        }
+        return Ok(());
+    }
+
+    fn validate_publish(&self, control_packet: &ControlPacket) -> DecodeResult<()> {
+        let fixed_header = control_packet.fixed_header();
+        let variable_header = match control_packet.variable_header_opt() {
+            Some(result) => { result }
+            None => { return Err(DecodeError::VariableHeaderAndPayload { cause: ReadError::InvalidData }); }
+        };
+        if fixed_header.qos_level() == &QoSLevel::AtMostOnce {
+            if *fixed_header.dup_flag() {
+                error!("DUP flag must be 0 for QoS 0 PUBLISH");
+                return Err(DecodeError::DupFlag { cause: ReadError::InvalidData });
+            }
+        } else {
+            self.validate_packet_identifier(control_packet)?;
+        }
+        if variable_header.topic_name().contains(|c| c == '+' || c == '#') {
+            error!("Topic Name must not contain wildcards: {:?}", variable_header.topic_name());
+            return Err(DecodeError::TopicName { cause: ReadError::ProtocolViolation });
+        }
+        return Ok(());
+    }
+
+    fn validate_sub_unsub(&self, control_packet: &ControlPacket) -> DecodeResult<()> {
+        self.validate_packet_identifier(control_packet)?;
+        let has_topic_filters = match control_packet.payload_opt() {
+            Some(payload) => { !payload.topic_filters().is_empty() }
+            None => { false }
+        };
+        if !has_topic_filters {
+            error!("{:?} must contain at least one Topic Filter", control_packet.fixed_header().packet_type());
+            return Err(DecodeError::TopicFilter { cause: ReadError::ProtocolViolation });
+        }
+        return Ok(());
+    }
+
+    fn validate_packet_identifier(&self, control_packet: &ControlPacket) -> DecodeResult<()> {
+        return match control_packet.variable_header_opt().and_then(|variable_header| variable_header.packet_identifier_opt()) {
+            Some(packet_identifier) if packet_identifier != 0 => { Ok(()) }
+            packet_identifier => {
+                error!("{:?} requires a non-zero Packet Identifier. Found: {:?}", control_packet.fixed_header().packet_type(), packet_identifier);
+                Err(DecodeError::PacketIdentifier { cause: ReadError::ProtocolViolation })
+            }
+        };
+    }
+}
@@ -17,6 +17,12 @@ pub struct PayloadDecoder {
 
 }
 
+impl PayloadDecoder {
+    pub fn new(property_decoder: PropertyDecoder) -> Self {
+        Self { metrics: PayloadDecoderMetrics::default(), property_decoder }
+    }
+}
+
 #[metered(registry = PayloadDecoderMetrics)]
 impl PayloadDecoder {
     #[measure([HitCount, Throughput, InFlight, ResponseTime])]
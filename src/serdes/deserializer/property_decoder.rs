@@ -1,18 +1,29 @@
 use bitreader::BitReader;
 use log::{debug, error, trace};
 use metered::{*};
+use crate::config::broker_config::PROTOCOL_MAXIMUM_PACKET_SIZE;
 use crate::model::variable_header::Property;
 use crate::serdes::deserializer::error::{DecodeError, DecodeResult, ReadError};
 use crate::serdes::r#trait::decoder::Decoder;
 
-#[derive(Default, Debug)]
+#[derive(Debug)]
 pub struct PropertyDecoder {
     pub(crate) metrics: PropertyDecoderMetrics,
+    maximum_packet_size: u64,
+}
 
+impl Default for PropertyDecoder {
+    fn default() -> Self {
+        Self::new(PROTOCOL_MAXIMUM_PACKET_SIZE)
+    }
 }
 
 
 impl PropertyDecoder {
+    pub fn new(maximum_packet_size: u32) -> Self {
+        Self { metrics: PropertyDecoderMetrics::default(), maximum_packet_size: maximum_packet_size as u64 }
+    }
+
     fn read_property_length(&self, reader: &mut BitReader) -> DecodeResult<u64> {
         trace!("PropertyDecoder::read_property_length");
 
@@ -24,6 +35,12 @@ impl PropertyDecoder {
             }
         };
         trace!("Extracted Property Length: {:?}", property_length);
+        //The reader holds what is left of the packet, properties can't claim more than that
+        let remaining_bytes = reader.remaining() / 8;
+        if property_length > remaining_bytes || property_length > self.maximum_packet_size {
+            error!("Property Length {:?} exceeds remaining {:?} bytes of the packet", property_length, remaining_bytes);
+            return Err(DecodeError::PropertyLength { cause: ReadError::ProtocolViolation });
+        }
         return Ok(property_length);
     }
 
@@ -79,7 +96,7 @@ impl PropertyDecoder {
                 Ok(Some(Property::AssignedClientIdentifier(value)))
             }
             19 => {
-                let value = match self.read_u8(8 * 2, reader) {
+                let value = match self.read_u16(8 * 2, reader) {
                     Ok(result) => { result }
                     Err(err) => { return map_error(err); }
                 };
@@ -224,7 +241,13 @@ impl Decoder<Vec<Property>> for PropertyDecoder {
             }
             //I need to check how many bytes Property Identifier and their values consumed from stream
             let consumed_bytes = (reader.position() - properties_start) / 8;
-            properties_byte_size = properties_byte_size - consumed_bytes;
+            properties_byte_size = match properties_byte_size.checked_sub(consumed_bytes) {
+                Some(result) => { result }
+                None => {
+                    error!("Property consumed {:?} bytes but only {:?} were left in Property Length", consumed_bytes, properties_byte_size);
+                    return Err(DecodeError::PropertyLength { cause: ReadError::ProtocolViolation });
+                }
+            };
             trace!("Consumed bytes: {:?}. Remaining properties bytes: {:?}", consumed_bytes, properties_byte_size);
         }
         trace!("Properties consumed {:?} bytes from stream", (reader.position() - start_position) / 8);
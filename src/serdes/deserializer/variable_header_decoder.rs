@@ -16,6 +16,12 @@ pub struct VariableHeaderDecoder {
 
 }
 
+impl VariableHeaderDecoder {
+    pub fn new(property_decoder: PropertyDecoder) -> Self {
+        Self { metrics: VariableHeaderDecoderMetrics::default(), property_decoder }
+    }
+}
+
 #[metered(registry = VariableHeaderDecoderMetrics)]
 impl VariableHeaderDecoder {
     #[measure([HitCount, Throughput, InFlight, ResponseTime])]
@@ -147,6 +153,11 @@ impl VariableHeaderDecoder {
             }
         };
         trace!("Extracted Protocol Version: {:?}", protocol_version);
+        //Everything after it is laid out differently in MQTT 3.1 and 3.1.1, so it isn't decoded
+        if protocol_version != 5 {
+            error!("Unsupported protocol version: {:?}", protocol_version);
+            return Err(DecodeError::ProtocolVersion { cause: ReadError::ProtocolViolation });
+        }
         return Ok(protocol_version);
     }
 
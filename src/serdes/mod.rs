@@ -3,3 +3,7 @@ pub mod deserializer;
 pub mod serializer;
 pub mod r#trait;
 pub mod mqtt_encoder;
+pub mod read_buffer;
+pub mod decode_pool;
+#[cfg(any(test, feature = "symmetry-check"))]
+pub mod symmetry_check;
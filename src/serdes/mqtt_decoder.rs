@@ -1,18 +1,24 @@
 use std::io::ErrorKind;
+use std::sync::Arc;
 
 use bitreader::BitReader;
-use bytes::BytesMut;
 use log::{debug, error, trace};
 use metered::{*};
 use serde::Serializer;
 use tokio::io::AsyncReadExt;
 use tokio::net::tcp::OwnedReadHalf;
 
+use crate::config::broker_config::BrokerConfig;
 use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::FixedHeader;
 use crate::serdes::deserializer::error::{DecodeError, DecodeResult, ReadError};
 use crate::serdes::deserializer::fixed_header_decoder::FixedHeaderDecoder;
+use crate::serdes::deserializer::packet_validator::PacketValidator;
 use crate::serdes::deserializer::payload_decoder::PayloadDecoder;
+use crate::serdes::deserializer::property_decoder::PropertyDecoder;
 use crate::serdes::deserializer::variable_header_decoder::VariableHeaderDecoder;
+use crate::serdes::r#trait::decoder::Decoder;
+use crate::serdes::read_buffer::ReadBuffer;
 
 #[derive(Default, Debug)]
 pub struct MqttDecoder {
@@ -20,21 +26,76 @@ pub struct MqttDecoder {
     pub(crate) fixed_header_decoder: FixedHeaderDecoder,
     pub(crate) variable_header_decoder: VariableHeaderDecoder,
     pub(crate) payload_decoder: PayloadDecoder,
+    maximum_packet_size: u32,
+    pub(crate) packet_validator: PacketValidator,
+}
+
+impl MqttDecoder {
+    //A whole packet already in memory, e.g. from a capture
+    pub(crate) fn decode_bytes(&self, bytes: &[u8]) -> DecodeResult<ControlPacket> {
+        let mut reader = BitReader::new(bytes);
+        let fixed_header = self.fixed_header_decoder.decode(&mut reader)?;
+        let body = &bytes[(reader.position() / 8) as usize..];
+        if body.len() as u64 != fixed_header.remaining_length() {
+            error!("Remaining Length {:?} doesn't match the {:?} bytes after the Fixed Header", fixed_header.remaining_length(), body.len());
+            return Err(DecodeError::RemainingLength { cause: ReadError::InvalidData });
+        }
+        return self.decode_body(fixed_header, body);
+    }
+
+    //Variable Header and Payload, body holds the Remaining Length bytes following the Fixed Header
+    pub(crate) fn decode_body(&self, fixed_header: FixedHeader, body: &[u8]) -> DecodeResult<ControlPacket> {
+        let mut variable_header = None;
+        let mut payload = None;
+        if fixed_header.remaining_length() > 0 {
+            let mut reader = BitReader::new(body);
+            variable_header = self.variable_header_decoder.decode_with_header(&fixed_header, &mut reader)?;
+            if let Some(_variable_header) = variable_header {
+                payload = self.payload_decoder.decode_with_headers(&fixed_header, &_variable_header, &mut reader)?;
+                variable_header = Some(_variable_header);
+            }
+        }
+        let control_packet = ControlPacket::new(fixed_header, variable_header, payload);
+        debug!("ControlPacket: {:?}", control_packet);
+        self.packet_validator.validate(&control_packet)?;
+        return Ok(control_packet);
+    }
+
+    pub fn new(config: Arc<BrokerConfig>) -> Self {
+        let maximum_packet_size = config.packet.maximum_packet_size;
+        Self {
+            metrics: MqttDecoderMetrics::default(),
+            fixed_header_decoder: FixedHeaderDecoder::default(),
+            variable_header_decoder: VariableHeaderDecoder::new(PropertyDecoder::new(maximum_packet_size)),
+            payload_decoder: PayloadDecoder::new(PropertyDecoder::new(maximum_packet_size)),
+            packet_validator: PacketValidator::default(),
+            maximum_packet_size,
+        }
+    }
 }
 
 #[metered(registry = MqttDecoderMetrics)]
 impl MqttDecoder {
     #[measure([HitCount, Throughput, InFlight, ResponseTime])]
-    pub(crate) async fn decode_packet(&self, mut stream: OwnedReadHalf) -> DecodeResult<(OwnedReadHalf, ControlPacket)> {
+    pub(crate) async fn decode_packet(&self, stream: OwnedReadHalf, buffer: &mut ReadBuffer) -> DecodeResult<(OwnedReadHalf, ControlPacket)> {
         debug!("START decode_packet");
+        let (stream, fixed_header) = self.read_frame(stream, buffer).await?;
+        let control_packet = self.decode_body(fixed_header, buffer.filled())?;
+        return Ok((stream, control_packet));
+    }
+
+    //Reads the Fixed Header and leaves the Remaining Length bytes in buffer, undecoded
+    pub(crate) async fn read_frame(&self, mut stream: OwnedReadHalf, buffer: &mut ReadBuffer) -> DecodeResult<(OwnedReadHalf, FixedHeader)> {
         let fixed_header = self.fixed_header_decoder.decode_from_stream(&mut stream).await?;
 
-        let mut buffer = BytesMut::with_capacity(fixed_header.remaining_length() as usize);
         debug!("Remaining packet length: {:?}", fixed_header.remaining_length());
-        let mut variable_header = None;
-        let mut payload = None;
+        //Refused before its content is read, the connection closes with PacketTooLarge
+        let packet_size = packet_size(fixed_header.remaining_length());
+        if packet_size > self.maximum_packet_size as u64 {
+            return Err(DecodeError::RemainingLength { cause: ReadError::ExceededMaxValue { current: packet_size, max: self.maximum_packet_size as u64 } });
+        }
         if fixed_header.remaining_length() > 0 {
-            match stream.read_buf(&mut buffer).await {
+            match stream.read_exact(buffer.prepare(fixed_header.remaining_length() as usize)).await {
                 Ok(bytes_read) => {
                     trace!("Read {:?} bytes from stream", bytes_read);
                 }
@@ -59,19 +120,18 @@ impl MqttDecoder {
                     };
                 }
             };
-
-            let mut reader = BitReader::new(&buffer);
-
-            variable_header = self.variable_header_decoder.decode_with_header(&fixed_header, &mut reader)?;
-            if let Some(_variable_header) = variable_header{
-                payload = self.payload_decoder.decode_with_headers(&fixed_header, &_variable_header,&mut reader)?;
-                variable_header = Some(_variable_header);
-
-            }
         }
-
-        let control_packet = ControlPacket::new(fixed_header, variable_header, payload);
-        debug!("ControlPacket: {:?}", control_packet);
-        return Ok((stream, control_packet));
+        return Ok((stream, fixed_header));
     }
-}
\ No newline at end of file
+}
+
+//Bytes of a packet on the wire: the first byte, the Remaining Length and what it counts
+fn packet_size(remaining_length: u64) -> u64 {
+    let remaining_length_size = match remaining_length {
+        0..=127 => { 1 }
+        128..=16_383 => { 2 }
+        16_384..=2_097_151 => { 3 }
+        _ => { 4 }
+    };
+    1 + remaining_length_size + remaining_length
+}
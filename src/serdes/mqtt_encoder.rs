@@ -37,6 +37,9 @@ impl serde::Serialize for MqttEncoder{
 #[derive(serde::Serialize)]
 pub struct MqttEncoderImpl {
     pub(crate) metrics: MqttEncoderMetrics,
+    #[cfg(feature = "symmetry-check")]
+    #[serde(skip)]
+    pub(crate) symmetry_check: crate::serdes::symmetry_check::SymmetryCheck,
 
 }
 
@@ -78,6 +81,8 @@ impl MqttEncoderImpl {
         fixed_header_encoder.encode(&(packet.fixed_header(), calculated_remaining_length), &mut buffer)?;
         variable_header_encoder.encode_opt(packet.variable_header_opt(), &mut buffer)?;
         payload_encoder.encode_opt(packet.payload_opt(), &mut buffer).expect("panic encode_opt");
+        #[cfg(feature = "symmetry-check")]
+        self.symmetry_check.check(packet, &buffer);
         Ok(buffer)
     }
 
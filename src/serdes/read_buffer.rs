@@ -0,0 +1,56 @@
+use bytes::BytesMut;
+use log::trace;
+
+//Capacity a connection starts with, enough for most control packets
+const INITIAL_CAPACITY: usize = 1024;
+//Reads between two checks whether the buffer outgrew the typical packet
+pub const SHRINK_INTERVAL: u32 = 1024;
+//How much larger than the typical packet the buffer may stay
+const SHRINK_FACTOR: usize = 4;
+
+//Buffer a connection reads the Variable Header and Payload of every packet into. It keeps the
+//allocation between packets, grows to the largest one and shrinks back to the connection's
+//typical packet size once a spike is over.
+#[derive(Debug)]
+pub struct ReadBuffer {
+    buffer: BytesMut,
+    //Moving average of the packet sizes read
+    typical_size: usize,
+    reads: u32,
+}
+
+impl Default for ReadBuffer {
+    fn default() -> Self {
+        Self { buffer: BytesMut::with_capacity(INITIAL_CAPACITY), typical_size: 0, reads: 0 }
+    }
+}
+
+impl ReadBuffer {
+    //Space for the next packet of len bytes
+    pub fn prepare(&mut self, len: usize) -> &mut [u8] {
+        self.typical_size = (self.typical_size * 7 + len) / 8;
+        self.reads = self.reads.wrapping_add(1);
+        if self.reads.is_multiple_of(SHRINK_INTERVAL) {
+            self.shrink(len);
+        }
+        self.buffer.clear();
+        self.buffer.resize(len, 0);
+        &mut self.buffer[..]
+    }
+
+    pub fn filled(&self) -> &[u8] {
+        &self.buffer[..]
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    fn shrink(&mut self, len: usize) {
+        let target = self.typical_size.max(len).max(INITIAL_CAPACITY);
+        if self.capacity() > target * SHRINK_FACTOR {
+            trace!("Shrinking read buffer from {} to {} bytes", self.capacity(), target);
+            self.buffer = BytesMut::with_capacity(target);
+        }
+    }
+}
@@ -1,19 +1,12 @@
-use core::fmt;
+use thiserror::Error;
 
 pub type EncodeResult<T> = Result<T, EncodeError>;
 
 #[derive(Debug, PartialEq, Clone)]
+#[derive(Error)]
 pub enum EncodeError {
+    #[error("not enough data to encode")]
     NotEnoughData,
+    #[error("value exceeds its maximum encoded length")]
     ExceededMaxLength,
 }
-
-impl fmt::Display for EncodeError {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
-        //self.description().fmt(fmt)
-        match *self {
-            EncodeError::NotEnoughData => write!(fmt, "EncodeError::NotEnoughData"),
-            EncodeError::ExceededMaxLength => write!(fmt, "EncodeError::ExceededMaxLength"),
-        }
-    }
-}
\ No newline at end of file
@@ -46,11 +46,12 @@ impl FixedHeaderEncoder {
 
     fn encode_publish_flags(&mut self, mut first_byte: u8, dup_flag: bool, qos_level: QoSLevel, retain: bool) -> EncodeResult<u8> {
         trace!("FixedHeaderEncoder::encode_publish_flags");
-        first_byte = (if dup_flag { 1 } else { 0 } << 0) | first_byte;
+        //DUP is bit 3, QoS bits 2-1, RETAIN bit 0
+        first_byte = (if dup_flag { 1 } else { 0 } << 3) | first_byte;
         let qos_flags = qos_level.to_bool();
         first_byte = (if qos_flags.1 { 1 } else { 0 } << 1) | first_byte;
         first_byte = (if qos_flags.0 { 1 } else { 0 } << 2) | first_byte;
-        first_byte = (if retain { 1 } else { 0 } << 3) | first_byte;
+        first_byte = (if retain { 1 } else { 0 } << 0) | first_byte;
         return Ok(first_byte);
     }
 }
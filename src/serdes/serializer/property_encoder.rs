@@ -19,6 +19,122 @@ impl PropertyEncoder {
         let internal_buffer = BytesMut::new();
         PropertyEncoder { internal_buffer }
     }
+
+    fn encode_property(&mut self, property: &Property, buffer: &mut BytesMut) -> EncodeResult<()> {
+        trace!("PropertyEncoder::encode_property");
+        match property {
+            Property::PayloadFormatIndicator(value) => {
+                buffer.put_u8(1);
+                buffer.put_u8(*value);
+            }
+            Property::MessageExpiryInterval(value) => {
+                buffer.put_u8(2);
+                buffer.put_u32(*value);
+            }
+            Property::ContentType(value) => {
+                buffer.put_u8(3);
+                self.write_utf8_encoded_string(value, buffer)?;
+            }
+            Property::ResponseTopic(value) => {
+                buffer.put_u8(8);
+                self.write_utf8_encoded_string(value, buffer)?;
+            }
+            Property::CorrelationData(value) => {
+                buffer.put_u8(9);
+                self.write_binary_data(value.clone(), buffer)?;
+            }
+            Property::SubscriptionIdentifier(value) => {
+                buffer.put_u8(11);
+                self.write_variable_byte_integer(*value, buffer)?;
+            }
+            Property::SessionExpiryInterval(value) => {
+                buffer.put_u8(17);
+                buffer.put_u32(*value);
+            }
+            Property::AssignedClientIdentifier(value) => {
+                buffer.put_u8(18);
+                self.write_utf8_encoded_string(value, buffer)?;
+            }
+            Property::ServerKeepAlive(value) => {
+                buffer.put_u8(19);
+                buffer.put_u16(*value);
+            }
+            Property::AuthenticationMethod(value) => {
+                buffer.put_u8(21);
+                self.write_utf8_encoded_string(value, buffer)?;
+            }
+            Property::AuthenticationData(value) => {
+                buffer.put_u8(22);
+                self.write_binary_data(value.clone(), buffer)?;
+            }
+            Property::RequestProblemInformation(value) => {
+                buffer.put_u8(23);
+                buffer.put_u8(*value);
+            }
+            Property::WillDelayInterval(value) => {
+                buffer.put_u8(24);
+                buffer.put_u32(*value);
+            }
+            Property::RequestResponseInformation(value) => {
+                buffer.put_u8(25);
+                buffer.put_u8(*value);
+            }
+            Property::ResponseInformation(value) => {
+                buffer.put_u8(26);
+                self.write_utf8_encoded_string(value, buffer)?;
+            }
+            Property::ServerReference(value) => {
+                buffer.put_u8(28);
+                self.write_utf8_encoded_string(value, buffer)?;
+            }
+            Property::ReasonString(value) => {
+                buffer.put_u8(31);
+                self.write_utf8_encoded_string(value, buffer)?;
+            }
+            Property::ReceiveMaximum(value) => {
+                buffer.put_u8(33);
+                buffer.put_u16(*value);
+            }
+            Property::TopicAliasMaximum(value) => {
+                buffer.put_u8(34);
+                buffer.put_u16(*value);
+            }
+            Property::TopicAlias(value) => {
+                buffer.put_u8(35);
+                buffer.put_u16(*value);
+            }
+            Property::MaximumQoS(value) => {
+                buffer.put_u8(36);
+                buffer.put_u8(*value);
+            }
+            Property::RetainAvailable(value) => {
+                buffer.put_u8(37);
+                buffer.put_u8(*value);
+            }
+            Property::UserProperty(key, value) => {
+                buffer.put_u8(38);
+                self.write_utf8_encoded_string(key, buffer)?;
+                self.write_utf8_encoded_string(value, buffer)?;
+            }
+            Property::MaximumPacketSize(value) => {
+                buffer.put_u8(39);
+                buffer.put_u32(*value);
+            }
+            Property::WildcardSubscriptionAvailable(value) => {
+                buffer.put_u8(40);
+                buffer.put_u8(*value);
+            }
+            Property::SubscriptionIdentifierAvailable(value) => {
+                buffer.put_u8(41);
+                buffer.put_u8(*value);
+            }
+            Property::SharedSubscriptionAvailable(value) => {
+                buffer.put_u8(42);
+                buffer.put_u8(*value);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl LengthCalculator<Vec<Property>> for PropertyEncoder {}
@@ -31,9 +147,14 @@ impl Encoder<Vec<Property>> for PropertyEncoder {
             buffer.put_slice(&self.internal_buffer);
             return Ok(());
         }
-        let length = 0;
-        //TODO Implement properties encoding
-        self.write_variable_byte_integer(length, buffer)?;
+        //Property Length comes first, so properties are encoded aside to measure them
+        let mut properties = BytesMut::new();
+        for property in item {
+            self.encode_property(property, &mut properties)?;
+        }
+        trace!("Encoded {:?} properties in {:?} bytes", item.len(), properties.len());
+        self.write_variable_byte_integer(properties.len() as u64, buffer)?;
+        buffer.put_slice(&properties);
         Ok(())
     }
 
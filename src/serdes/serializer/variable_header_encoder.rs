@@ -106,7 +106,10 @@ impl Encoder<VariableHeader> for VariableHeaderEncoder {
             }
             ControlPacketType::PINGREQ => {}
             ControlPacketType::PINGRESP => {}
-            ControlPacketType::DISCONNECT => {}
+            ControlPacketType::DISCONNECT => {
+                self.encode_reason_code(item.reason_code(), buffer);
+                property_encoder.encode(&item.properties(), buffer).expect("encode");
+            }
             ControlPacketType::AUTH => {}
         }
         Ok(())
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use log::{error, trace};
+use metered::HitCount;
+use serde::Serialize;
+
+use crate::config::broker_config::BrokerConfig;
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::FixedHeader;
+use crate::model::payload::Payload;
+use crate::serdes::mqtt_decoder::MqttDecoder;
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct SymmetryCheckMetrics {
+    pub(crate) checked: HitCount,
+    pub(crate) mismatches: HitCount,
+    //Packets whose Variable Header the decoder doesn't read, only their Fixed Header is compared
+    pub(crate) unchecked_bodies: HitCount,
+}
+
+//Decodes every encoded packet again and compares it to the packet it was encoded from, so a field the
+//encoder drops or writes wrong shows up in the logs of development builds and integration tests.
+//Built with the symmetry-check feature, it decodes everything twice.
+#[derive(Debug)]
+pub struct SymmetryCheck {
+    decoder: MqttDecoder,
+    pub(crate) metrics: SymmetryCheckMetrics,
+}
+
+impl SymmetryCheck {
+    //The differences found, every one is logged
+    pub fn check(&self, control_packet: &ControlPacket, encoded: &[u8]) -> Vec<String> {
+        trace!("SymmetryCheck::check");
+        self.metrics.checked.incr();
+        let mismatches = match self.decoder.decode_bytes(encoded) {
+            Ok(decoded) => { self.compare(control_packet, &decoded) }
+            Err(err) => { vec![format!("can't decode {:?}", err)] }
+        };
+        if !mismatches.is_empty() {
+            self.metrics.mismatches.incr();
+            for mismatch in &mismatches {
+                error!("Encoded {:?} doesn't decode to its source, {}. Source: {:?}", control_packet.fixed_header().packet_type(), mismatch, control_packet);
+            }
+        }
+        mismatches
+    }
+
+    fn compare(&self, source: &ControlPacket, decoded: &ControlPacket) -> Vec<String> {
+        let mut mismatches = compare_fixed_headers(source.fixed_header(), decoded.fixed_header());
+        //CONNACK, SUBACK, UNSUBACK and AUTH are only sent by the broker, it never decodes their body
+        if source.variable_header_opt().is_some() && decoded.variable_header_opt().is_none() {
+            self.metrics.unchecked_bodies.incr();
+            return mismatches;
+        }
+        if source.variable_header_opt() != decoded.variable_header_opt() {
+            mismatches.push(format!("variable header {:?} decoded as {:?}", source.variable_header_opt(), decoded.variable_header_opt()));
+        }
+        //A PUBLISH without a payload decodes with empty application data
+        let empty_publish = Payload::from_publish(Some(vec![]));
+        let decoded_payload = decoded.payload_opt().filter(|payload| source.payload_opt().is_some() || **payload != empty_publish);
+        if source.payload_opt() != decoded_payload {
+            mismatches.push(format!("payload {:?} decoded as {:?}", source.payload_opt(), decoded.payload_opt()));
+        }
+        mismatches
+    }
+
+    pub fn new(config: Arc<BrokerConfig>) -> Self {
+        Self { decoder: MqttDecoder::new(config), metrics: SymmetryCheckMetrics::default() }
+    }
+}
+
+impl Default for SymmetryCheck {
+    fn default() -> Self {
+        Self::new(Arc::new(BrokerConfig::default()))
+    }
+}
+
+//The Remaining Length of a source packet is a placeholder, the encoder calculates it.
+//Control flags are checked by the validator of the decoder.
+fn compare_fixed_headers(source: &FixedHeader, decoded: &FixedHeader) -> Vec<String> {
+    let mut mismatches = vec![];
+    if source.packet_type() != decoded.packet_type() {
+        mismatches.push(format!("packet type {:?} decoded as {:?}", source.packet_type(), decoded.packet_type()));
+    }
+    if source.dup_flag_opt().unwrap_or(false) != decoded.dup_flag_opt().unwrap_or(false) {
+        mismatches.push(format!("DUP {:?} decoded as {:?}", source.dup_flag_opt(), decoded.dup_flag_opt()));
+    }
+    if source.qos_level_opt() != decoded.qos_level_opt() && source.qos_level_opt().is_some() {
+        mismatches.push(format!("QoS {:?} decoded as {:?}", source.qos_level_opt(), decoded.qos_level_opt()));
+    }
+    if source.retain_opt().unwrap_or(false) != decoded.retain_opt().unwrap_or(false) {
+        mismatches.push(format!("RETAIN {:?} decoded as {:?}", source.retain_opt(), decoded.retain_opt()));
+    }
+    mismatches
+}
@@ -0,0 +1,75 @@
+use std::fmt::Debug;
+use std::net::SocketAddr;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+
+use crate::session::client_handler::Connection;
+
+//Where connected clients are: the client_id and generation of each socket, the connection of each client_id.
+//A single broker keeps them in memory. A cluster can back them with a directory shared by its nodes, e.g. Redis,
+//and answer the lookups by client_id for clients connected to another node, which is why those have async
+//variants. A socket always belongs to this node, its lookups stay synchronous on the path of every packet.
+//The sessions of the clients are in the SessionDirectory next to it.
+#[async_trait]
+pub trait ClientDirectory: Debug + Send + Sync {
+    fn client_of(&self, socket: &SocketAddr) -> Option<(String, u64)>;
+
+    //Returns what the socket was registered with before
+    fn set_client(&self, socket: &SocketAddr, client_id: &String, generation: u64) -> Option<(String, u64)>;
+
+    fn remove_client(&self, socket: &SocketAddr) -> Option<(String, u64)>;
+
+    fn connection_of(&self, client_id: &String) -> Option<Connection>;
+
+    //Returns the previous connection of the client
+    fn set_connection(&self, client_id: &String, connection: Connection) -> Option<Connection>;
+
+    //Only removes the connection of the given generation, a newer one took the client over
+    fn remove_connection(&self, client_id: &String, generation: u64) -> bool;
+
+    fn connections(&self) -> Vec<(String, Connection)>;
+
+    //The connection of the client wherever the directory knows it from
+    async fn find_connection(&self, client_id: &String) -> Option<Connection> {
+        self.connection_of(client_id)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LocalDirectory {
+    socket2id: DashMap<SocketAddr, (String, u64)>,
+    id2socket: DashMap<String, Connection>,
+}
+
+impl ClientDirectory for LocalDirectory {
+    fn client_of(&self, socket: &SocketAddr) -> Option<(String, u64)> {
+        self.socket2id.get(socket).map(|entry| entry.value().clone())
+    }
+
+    fn set_client(&self, socket: &SocketAddr, client_id: &String, generation: u64) -> Option<(String, u64)> {
+        self.socket2id.insert(*socket, (client_id.clone(), generation))
+    }
+
+    fn remove_client(&self, socket: &SocketAddr) -> Option<(String, u64)> {
+        self.socket2id.remove(socket).map(|(_, client)| client)
+    }
+
+    fn connection_of(&self, client_id: &String) -> Option<Connection> {
+        self.id2socket.get(client_id).map(|connection| *connection.value())
+    }
+
+    fn set_connection(&self, client_id: &String, connection: Connection) -> Option<Connection> {
+        self.id2socket.insert(client_id.clone(), connection)
+    }
+
+    fn remove_connection(&self, client_id: &String, generation: u64) -> bool {
+        self.id2socket.remove_if(client_id, |_, connection| connection.generation == generation).is_some()
+    }
+
+    fn connections(&self) -> Vec<(String, Connection)> {
+        self.id2socket.iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect()
+    }
+}
@@ -1,121 +1,285 @@
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
 use dashmap::DashMap;
-use log::{error, info, trace, warn};
+use log::{debug, info, trace, warn};
 use metered::{*};
 
+use crate::error::{PatinaError, PatinaResult};
+use crate::session::client_directory::{ClientDirectory, LocalDirectory};
+use crate::session::client_limits::ConnectionLimits;
+use crate::session::session_directory::{LocalSessions, SessionDirectory};
+
+//Log target of the packets of clients under debug capture. It is not a module, so the levels of patina modules don't hide it
+pub const DEBUG_CAPTURE_TARGET: &str = "patina::debug_capture";
+
+//A socket of a client together with the generation it was registered with.
+//Every CONNECT gets a new generation, so a socket left over from a takeover can be told apart from the current one.
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq, Hash)]
+pub struct Connection {
+    pub socket: SocketAddr,
+    pub generation: u64,
+}
+
 #[derive(Debug)]
 pub struct ClientHandler {
-    socket2id: Arc<DashMap<SocketAddr, String>>,
-    id2socket: Arc<DashMap<String, SocketAddr>>,
+    //The sockets and client_ids of the connected clients, see ClientDirectory
+    directory: Arc<dyn ClientDirectory>,
+    //The sessions of the clients, connected or not, see SessionDirectory
+    pub(crate) sessions: Arc<dyn SessionDirectory>,
+    //Connected clients by the username they authenticated with, and back
+    username2ids: DashMap<String, HashSet<String>>,
+    id2username: DashMap<String, String>,
+    //Clients whose packets are logged, until the instant their capture expires
+    debug_captures: DashMap<String, Instant>,
+    //Size of debug_captures, the only thing read on the path of a packet while nobody is captured
+    debug_capture_count: AtomicUsize,
+    next_generation: AtomicU64,
+    //Receive Maximum, Maximum Packet Size and Topic Alias Maximum of each connection
+    pub(crate) limits: ConnectionLimits,
     pub(crate) metrics: ClientHandlerMetrics,
 }
 
 impl Default for ClientHandler {
     fn default() -> Self {
-        Self { socket2id: Arc::new(DashMap::new()), id2socket: Arc::new(DashMap::new()), metrics: ClientHandlerMetrics::default() }
+        Self::with_directories(Arc::new(LocalDirectory::default()), Arc::new(LocalSessions::default()))
+    }
+}
+
+impl ClientHandler {
+    pub fn with_directories(directory: Arc<dyn ClientDirectory>, sessions: Arc<dyn SessionDirectory>) -> Self {
+        Self { directory, sessions, username2ids: DashMap::new(), id2username: DashMap::new(), debug_captures: DashMap::new(), debug_capture_count: AtomicUsize::new(0), next_generation: AtomicU64::new(1), limits: ConnectionLimits::default(), metrics: ClientHandlerMetrics::default() }
     }
 }
 
 #[metered(registry = ClientHandlerMetrics)]
 impl ClientHandler {
     #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
-    pub fn get_client_id(&self, socket: &SocketAddr) -> Result<String, String> {
-        match self.socket2id.get(&socket) {
+    pub fn get_client_id(&self, socket: &SocketAddr) -> PatinaResult<String> {
+        match self.directory.client_of(socket) {
             None => {
-                Err(format!("Can't get any client_id for socket {}", socket))
+                Err(PatinaError::UnknownClient(*socket))
             }
-            Some(client_id) => {
-                Ok(client_id.value().clone())
+            Some((client_id, _)) => {
+                Ok(client_id)
             }
         }
     }
 
     #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
     pub fn get_socket(&self, client_id: &String) -> Result<SocketAddr, String> {
-        match self.id2socket.get(client_id) {
+        return self.get_connection(client_id).map(|connection| connection.socket);
+    }
+
+    #[measure([HitCount, Throughput, InFlight, ResponseTime, ErrorCount])]
+    pub fn get_connection(&self, client_id: &String) -> Result<Connection, String> {
+        match self.directory.connection_of(client_id) {
             None => {
                 Err(format!("Can't get any socket for client_id {}", client_id))
             }
-            Some(socket) => {
-                Ok(socket.value().clone())
+            Some(connection) => {
+                Ok(connection)
             }
         }
     }
 
+    //False for unknown sockets and for sockets whose client has connected again since
+    pub fn is_current(&self, socket: &SocketAddr) -> bool {
+        let (client_id, generation) = match self.directory.client_of(socket) {
+            Some(result) => { result }
+            None => { return false; }
+        };
+        return self.directory.connection_of(&client_id)
+            .map(|connection| connection.generation == generation)
+            .unwrap_or(false);
+    }
+
     #[measure([HitCount, Throughput, InFlight, ResponseTime])]
     pub fn register(&self, socket: &SocketAddr, client_id: &String) -> Option<SocketAddr> {
-        if self.socket2id.contains_key(&socket) {
-            warn!("The socket {} is already registered with client_id {}. New client_id: {}",client_id, self.socket2id.get(&socket).unwrap().to_string(), socket);
+        let generation = self.next_generation.fetch_add(1, Ordering::Relaxed);
+        if let Some((previous_client_id, _)) = self.directory.set_client(socket, client_id, generation) {
+            warn!("The socket {} was already registered with client_id {}. New client_id: {}", socket, previous_client_id, client_id);
         }
-        match self.socket2id.insert(socket.clone(), client_id.clone()) {
-            None => {
-                trace!("Registered socket2id: {:?} -> {:?}", socket, client_id);
-            }
-            Some(_) => {
-                error!("Need to handle 'session taken over' case");
-            }
-        };
-        //let mut id2socket = id2socket.write().await;
-        let previous_socket = match self.id2socket.insert(client_id.clone(), socket.clone()) {
+        trace!("Registered socket2id: {:?} -> {:?} generation {}", socket, client_id, generation);
+        let previous_socket = match self.directory.set_connection(client_id, Connection { socket: *socket, generation }) {
             None => {
                 trace!("Registered id2socket: {:?} -> {:?}", client_id, socket);
                 None
             }
-            Some(previous_socket) => {
-                info!("Found a previous socket {:?} associated to client {:?}", previous_socket, client_id);
-                Some(previous_socket)
+            Some(previous_connection) => {
+                info!("Found a previous socket {:?} (generation {}) associated to client {:?}", previous_connection.socket, previous_connection.generation, client_id);
+                Some(previous_connection.socket).filter(|previous_socket| previous_socket.ne(socket))
             }
         };
         previous_socket
     }
 
+    //Returns whether the socket was the client's current connection. A stale socket never
+    //removes the mapping of the connection that took it over.
     #[measure([HitCount, Throughput, InFlight, ResponseTime])]
-    pub fn unregister(&self, socket: &SocketAddr, client_id: &String) {
-        match self.socket2id.remove(&socket) {
+    pub fn unregister(&self, socket: &SocketAddr, client_id: &String) -> bool {
+        self.limits.remove(socket);
+        let generation = match self.directory.remove_client(socket) {
+            Some((_, generation)) => { generation }
             None => {
-                trace!("Unregister socket2id: {:?} -> {:?}", socket, client_id);
-            }
-            Some(_) => {
-                error!("Need to handle 'session taken over' case");
+                trace!("Socket {:?} of client {:?} is not registered", socket, client_id);
+                return false;
             }
         };
-
-        match self.id2socket.remove(client_id) {
-            None => {
-                trace!("Unregister id2socket: {:?} -> {:?}", client_id, socket);
+        return match self.directory.remove_connection(client_id, generation) {
+            true => {
+                trace!("Unregistered {:?} -> {:?} generation {}", client_id, socket, generation);
+                self.remove_user_connection(client_id);
+                true
             }
-            Some(previous_socket) => {
-                error!("Need to handle 'session taken over' case");
+            false => {
+                debug!("Socket {:?} of client {:?} was already taken over", socket, client_id);
+                false
             }
         };
     }
 
+    //Counts the client as a connection of the username before it registers. Refused when max_connections
+    //other clients of the username are connected, 0 is unlimited. A client connecting again keeps its place.
     #[measure([HitCount, Throughput, InFlight, ResponseTime])]
-    pub fn unregister_by_socket(&self, socket: &SocketAddr) -> Option<String> {
-        match self.get_client_id(socket) {
-            Ok(client_id) => {
-                match self.id2socket.remove(&client_id) {
-                    None => {
-                        trace!("Unregister id2socket: {:?} -> {:?}", client_id, socket);
-                    }
-                    Some(previous_socket) => {
-                        error!("Need to handle 'session taken over' case");
-                    }
-                };
+    pub fn add_user_connection(&self, client_id: &String, username: &String, max_connections: u32) -> bool {
+        {
+            let mut client_ids = self.username2ids.entry(username.clone()).or_default();
+            if !client_ids.contains(client_id) {
+                if max_connections > 0 && client_ids.len() >= max_connections as usize {
+                    debug!("Username {:?} already has {} connections", username, client_ids.len());
+                    return false;
+                }
+                client_ids.insert(client_id.clone());
             }
-            Err(_) => {}
         }
-        match self.socket2id.remove(&socket) {
-            None => {
-                trace!("Unregister socket2id: {:?}", socket);
-                None
-            }
-            Some((scoket, client_id)) => {
-                error!("Need to handle 'session taken over' case");
-                Some(client_id)
-            }
+        //A client_id connecting with another username leaves the previous one
+        if let Some(previous_username) = self.id2username.insert(client_id.clone(), username.clone()).filter(|previous_username| previous_username.ne(username)) {
+            self.remove_from_username(&previous_username, client_id);
+        }
+        true
+    }
+
+    //The client_id is only returned if the socket was the client's current connection
+    #[measure([HitCount, Throughput, InFlight, ResponseTime])]
+    pub fn unregister_by_socket(&self, socket: &SocketAddr) -> Option<String> {
+        let client_id = self.get_client_id(socket).ok()?;
+        return match self.unregister(socket, &client_id) {
+            true => { Some(client_id) }
+            false => { None }
+        };
+    }
+}
+
+impl ClientHandler {
+    //Also finds a client connected to another node when the directory is shared by a cluster
+    pub async fn find_connection(&self, client_id: &String) -> Option<Connection> {
+        self.directory.find_connection(client_id).await
+    }
+
+    //Connected clients of the username, without scanning every connection
+    pub fn client_ids_of(&self, username: &String) -> Vec<String> {
+        let mut client_ids: Vec<String> = self.username2ids.get(username)
+            .map(|client_ids| client_ids.iter().cloned().collect())
+            .unwrap_or_default();
+        client_ids.sort();
+        client_ids
+    }
+
+    pub fn sockets_of(&self, username: &String) -> Vec<SocketAddr> {
+        self.client_ids_of(username).iter()
+            .filter_map(|client_id| self.get_socket(client_id).ok())
+            .collect()
+    }
+
+    pub fn connection_count(&self, username: &String) -> usize {
+        self.username2ids.get(username).map(|client_ids| client_ids.len()).unwrap_or(0)
+    }
+
+    //Sorted by client_id
+    pub fn connected_clients(&self) -> Vec<(String, SocketAddr)> {
+        let mut clients: Vec<(String, SocketAddr)> = self.directory.connections().into_iter()
+            .map(|(client_id, connection)| (client_id, connection.socket))
+            .collect();
+        clients.sort();
+        clients
+    }
+
+    pub fn remove_user_connection(&self, client_id: &String) {
+        if let Some((_, username)) = self.id2username.remove(client_id) {
+            self.remove_from_username(&username, client_id);
+        }
+    }
+
+    fn remove_from_username(&self, username: &String, client_id: &String) {
+        if let Some(mut client_ids) = self.username2ids.get_mut(username) {
+            client_ids.remove(client_id);
+        }
+        self.username2ids.remove_if(username, |_, client_ids| client_ids.is_empty());
+    }
+
+    //Replaces the expiry of a running capture
+    pub fn start_debug_capture(&self, client_id: &String, until: Instant) {
+        if self.debug_captures.insert(client_id.clone(), until).is_none() {
+            self.debug_capture_count.fetch_add(1, Ordering::Relaxed);
+        }
+        info!("Debug capture of client {:?} started", client_id);
+    }
+
+    pub fn stop_debug_capture(&self, client_id: &String) -> bool {
+        if self.debug_captures.remove(client_id).is_none() {
+            return false;
         }
+        self.debug_capture_count.fetch_sub(1, Ordering::Relaxed);
+        info!("Debug capture of client {:?} stopped", client_id);
+        true
     }
-}
\ No newline at end of file
+
+    //Checked for every packet: one atomic load unless some client is captured.
+    //An expired capture is removed by the first packet that sees it.
+    pub fn is_debug_captured(&self, client_id: &String) -> bool {
+        if self.debug_capture_count.load(Ordering::Relaxed) == 0 {
+            return false;
+        }
+        let until = match self.debug_captures.get(client_id) {
+            Some(until) => { *until }
+            None => { return false; }
+        };
+        if Instant::now() < until {
+            return true;
+        }
+        if self.debug_captures.remove_if(client_id, |_, current| *current == until).is_some() {
+            self.debug_capture_count.fetch_sub(1, Ordering::Relaxed);
+            info!("Debug capture of client {:?} expired", client_id);
+        }
+        false
+    }
+
+    //The client to log a packet sent to the socket for
+    pub fn debug_captured_client(&self, socket: &SocketAddr) -> Option<String> {
+        if self.debug_capture_count.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        self.get_client_id(socket).ok().filter(|client_id| self.is_debug_captured(client_id))
+    }
+
+    //Running captures with the time they have left, sorted by client_id. Expired ones are removed.
+    pub fn debug_captures(&self) -> Vec<(String, Duration)> {
+        let now = Instant::now();
+        let client_ids: Vec<String> = self.debug_captures.iter().map(|entry| entry.key().clone()).collect();
+        let mut captures: Vec<(String, Duration)> = client_ids.into_iter()
+            .filter(|client_id| self.is_debug_captured(client_id))
+            .filter_map(|client_id| {
+                let until = *self.debug_captures.get(&client_id)?;
+                Some((client_id, until.saturating_duration_since(now)))
+            })
+            .collect();
+        captures.sort();
+        captures
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::net::SocketAddr;
+
+use dashmap::DashMap;
+use log::{debug, trace};
+use metered::HitCount;
+use serde::Serialize;
+
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::qos_level::QoSLevel;
+use crate::model::reason_code::ReasonCode;
+use crate::model::variable_header::VariableHeader;
+
+//What a client advertised in its CONNECT about the packets it takes from the broker
+#[derive(Debug)]
+#[derive(Copy, Clone, Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct ClientLimits {
+    //QoS 1 and QoS 2 publishes the client handles at once, 65535 when absent
+    pub receive_maximum: u16,
+    //Bytes of a whole packet, unlimited when absent
+    pub maximum_packet_size: Option<u32>,
+    //Topic aliases the broker may set up, 0 when absent
+    pub topic_alias_maximum: u16,
+}
+
+impl Default for ClientLimits {
+    fn default() -> Self {
+        Self { receive_maximum: u16::MAX, maximum_packet_size: None, topic_alias_maximum: 0 }
+    }
+}
+
+impl ClientLimits {
+    //A Receive Maximum or Maximum Packet Size of 0 is a Protocol Error
+    pub fn from_connect(variable_header: &VariableHeader) -> Result<Self, ReasonCode> {
+        let receive_maximum = variable_header.receive_maximum();
+        let maximum_packet_size = variable_header.maximum_packet_size();
+        if receive_maximum == Some(0) || maximum_packet_size == Some(0) {
+            return Err(ReasonCode::ProtocolError);
+        }
+        Ok(Self {
+            receive_maximum: receive_maximum.unwrap_or(u16::MAX),
+            maximum_packet_size,
+            topic_alias_maximum: variable_header.topic_alias_maximum().unwrap_or(0),
+        })
+    }
+
+    pub fn fits(&self, packet_size: usize) -> bool {
+        self.maximum_packet_size.is_none_or(|maximum_packet_size| packet_size <= maximum_packet_size as usize)
+    }
+}
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct ClientLimitsMetrics {
+    //Publishes held back until the client acknowledged enough of the ones it has
+    pub(crate) deferred: HitCount,
+    //Packets larger than the Maximum Packet Size of their client, never sent
+    pub(crate) oversized: HitCount,
+}
+
+#[derive(Debug)]
+struct ConnectionState {
+    limits: ClientLimits,
+    //QoS 1 and QoS 2 publishes sent and not acknowledged yet, by Packet Identifier
+    inflight: HashMap<u16, ControlPacket>,
+    //QoS 2 publishes of inflight the client sent PUBREC for, only PUBCOMP is missing
+    received: HashSet<u16>,
+    //Publishes waiting for a free slot, in the order they were delivered
+    pending: VecDeque<ControlPacket>,
+}
+
+//The limits of each connection with the publishes it has in flight. Kept by socket, a new connection of the
+//client starts with the limits of its own CONNECT.
+#[derive(Debug, Default)]
+pub struct ConnectionLimits {
+    socket2state: DashMap<SocketAddr, ConnectionState>,
+    pub(crate) metrics: ClientLimitsMetrics,
+}
+
+impl ConnectionLimits {
+    pub fn set(&self, socket: &SocketAddr, limits: ClientLimits) {
+        trace!("ConnectionLimits::set {:?} {:?}", socket, limits);
+        self.socket2state.insert(*socket, ConnectionState { limits, inflight: HashMap::new(), received: HashSet::new(), pending: VecDeque::new() });
+    }
+
+    pub fn remove(&self, socket: &SocketAddr) {
+        self.socket2state.remove(socket);
+    }
+
+    pub fn inflight(&self, socket: &SocketAddr) -> usize {
+        self.socket2state.get(socket).map(|state| state.inflight.len()).unwrap_or(0)
+    }
+
+    //Whether the Packet Identifier of the publish names one in flight to the socket
+    pub fn is_inflight(&self, socket: &SocketAddr, packet: &ControlPacket) -> bool {
+        Self::flow_controlled(packet)
+            .is_some_and(|packet_identifier| self.socket2state.get(socket).is_some_and(|state| state.inflight.contains_key(&packet_identifier)))
+    }
+
+    //Whether the packet can be sent to the socket now. A QoS 1 or QoS 2 publish over the Receive Maximum
+    //of the client is kept and handed back by release once an earlier one is acknowledged.
+    pub fn admit(&self, socket: &SocketAddr, packet: &ControlPacket) -> bool {
+        let packet_identifier = match Self::flow_controlled(packet) {
+            Some(result) => { result }
+            None => { return true; }
+        };
+        let mut state = match self.socket2state.get_mut(socket) {
+            Some(result) => { result }
+            None => { return true; }
+        };
+        //A retransmission doesn't take another slot
+        if state.inflight.contains_key(&packet_identifier) || state.inflight.len() < state.limits.receive_maximum as usize {
+            state.inflight.insert(packet_identifier, packet.clone());
+            return true;
+        }
+        debug!("Socket {:?} has {} publishes in flight. Deferring Packet Identifier {}", socket, state.inflight.len(), packet_identifier);
+        state.pending.push_back(packet.clone());
+        self.metrics.deferred.incr();
+        false
+    }
+
+    //Frees the slot of an acknowledged publish and returns the next deferred one, which takes it
+    pub fn release(&self, socket: &SocketAddr, packet_identifier: u16) -> Option<ControlPacket> {
+        let mut state = self.socket2state.get_mut(socket)?;
+        state.inflight.remove(&packet_identifier)?;
+        state.received.remove(&packet_identifier);
+        let next_packet = state.pending.pop_front()?;
+        if let Some(next_packet_identifier) = Self::flow_controlled(&next_packet) {
+            state.inflight.insert(next_packet_identifier, next_packet.clone());
+        }
+        Some(next_packet)
+    }
+
+    //The client got the QoS 2 publish, its slot is taken until PUBCOMP
+    pub fn received(&self, socket: &SocketAddr, packet_identifier: u16) {
+        if let Some(mut state) = self.socket2state.get_mut(socket).filter(|state| state.inflight.contains_key(&packet_identifier)) {
+            state.received.insert(packet_identifier);
+        }
+    }
+
+    //Moves what the previous connection of a client left unacknowledged to the connection taking its session
+    //over and returns what that one has to send: the publishes in flight again with DUP set, PUBREL for the ones
+    //the client received. The deferred publishes follow once the Receive Maximum of the new connection allows.
+    pub fn take_over(&self, previous_socket: &SocketAddr, socket: &SocketAddr) -> Vec<ControlPacket> {
+        let previous_state = match self.socket2state.remove(previous_socket) {
+            Some((_, result)) => { result }
+            None => { return vec![]; }
+        };
+        let mut inflight: Vec<(u16, ControlPacket)> = previous_state.inflight.into_iter().collect();
+        inflight.sort_by_key(|(packet_identifier, _)| *packet_identifier);
+        let mut resent = vec![];
+        for (packet_identifier, packet) in inflight {
+            if !previous_state.received.contains(&packet_identifier) {
+                resent.push(packet.with_dup_flag(true));
+                continue;
+            }
+            resent.push(ControlPacket::pubrel(Some(packet_identifier)));
+            if let Some(mut state) = self.socket2state.get_mut(socket) {
+                state.inflight.insert(packet_identifier, packet);
+                state.received.insert(packet_identifier);
+            }
+        }
+        resent.into_iter()
+            .chain(previous_state.pending)
+            .filter(|packet| self.admit(socket, packet))
+            .collect()
+    }
+
+    //Drops the deferred publishes with these Packet Identifiers, they won't be sent. Returns how many were dropped
+    pub fn drop_deferred(&self, socket: &SocketAddr, packet_identifiers: &[u16]) -> usize {
+        let mut state = match self.socket2state.get_mut(socket) {
+            Some(result) => { result }
+            None => { return 0; }
+        };
+        let before = state.pending.len();
+        state.pending.retain(|packet| !Self::flow_controlled(packet).is_some_and(|packet_identifier| packet_identifiers.contains(&packet_identifier)));
+        before - state.pending.len()
+    }
+
+    //Publishes the client hasn't acknowledged: the ones in flight by Packet Identifier, then the deferred ones
+    pub fn unacknowledged(&self, socket: &SocketAddr) -> Vec<ControlPacket> {
+        let state = match self.socket2state.get(socket) {
+            Some(result) => { result }
+            None => { return vec![]; }
+        };
+        let mut inflight: Vec<(&u16, &ControlPacket)> = state.inflight.iter().collect();
+        inflight.sort_by_key(|(packet_identifier, _)| **packet_identifier);
+        inflight.into_iter().map(|(_, packet)| packet.clone())
+            .chain(state.pending.iter().cloned())
+            .collect()
+    }
+
+    //Whether the encoded packet is within the Maximum Packet Size of the client of the socket
+    pub fn fits(&self, socket: &SocketAddr, packet_size: usize) -> bool {
+        if self.socket2state.get(socket).is_none_or(|state| state.limits.fits(packet_size)) {
+            return true;
+        }
+        self.metrics.oversized.incr();
+        false
+    }
+
+    fn flow_controlled(packet: &ControlPacket) -> Option<u16> {
+        if packet.fixed_header().packet_type() != ControlPacketType::PUBLISH || packet.fixed_header().qos_level_opt().unwrap_or(QoSLevel::AtMostOnce) == QoSLevel::AtMostOnce {
+            return None;
+        }
+        packet.variable_header().packet_identifier_opt()
+    }
+}
@@ -0,0 +1,71 @@
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use dashmap::DashMap;
+use log::trace;
+use serde::Serialize;
+
+//Log target of the samples, its level is set in config/log4rs.yaml or through /log-levels
+pub const KEEP_ALIVE_STATS_TARGET: &str = "patina::keep_alive_stats";
+
+//Topic the samples of a client are published to when keep_alive.statistics_topic is on
+pub fn sys_keep_alive_topic(client_id: &str) -> String {
+    format!("$SYS/clients/{}/keep-alive", client_id)
+}
+
+//Health of one client at a PINGREQ
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct KeepAliveSample {
+    pub client_id: String,
+    //Keep Alive of the CONNECT in seconds
+    pub keep_alive: u16,
+    //Milliseconds since the previous PINGREQ of the connection, absent for the first one
+    pub interval_ms: Option<u64>,
+    //Milliseconds a PINGREQ waits between being read and its PINGRESP being queued, smoothed like TCP's SRTT.
+    //It is the share of the broker in the round trip the client measures.
+    pub rtt_estimate_ms: u64,
+    //Messages waiting in the session of the client
+    pub queued_messages: usize,
+    //Packets of every client waiting for the connection writers
+    pub outbound_queue: usize,
+}
+
+#[derive(Debug)]
+struct PingState {
+    socket: SocketAddr,
+    last_pingreq: Instant,
+    rtt_estimate: Duration,
+}
+
+//Last PINGREQ and smoothed turnaround by client_id, kept like the takeover counts. A new connection
+//of the client starts over.
+#[derive(Debug, Default)]
+pub struct KeepAliveStats {
+    states: DashMap<String, PingState>,
+}
+
+impl KeepAliveStats {
+    //The interval since the previous PINGREQ of the same connection and the new RTT estimate
+    pub fn record(&self, client_id: &String, socket: SocketAddr, received_at: Instant, now: Instant) -> (Option<Duration>, Duration) {
+        trace!("KeepAliveStats::record");
+        let turnaround = now.saturating_duration_since(received_at);
+        let mut state = self.states.entry(client_id.clone())
+            .or_insert_with(|| PingState { socket, last_pingreq: received_at, rtt_estimate: turnaround });
+        if state.socket != socket {
+            *state = PingState { socket, last_pingreq: received_at, rtt_estimate: turnaround };
+            return (None, turnaround);
+        }
+        let interval = match state.last_pingreq == received_at {
+            true => { None }
+            false => { Some(received_at.saturating_duration_since(state.last_pingreq)) }
+        };
+        //SRTT = 7/8 SRTT + 1/8 sample
+        if interval.is_some() {
+            state.rtt_estimate = (state.rtt_estimate * 7 + turnaround) / 8;
+        }
+        state.last_pingreq = received_at;
+        (interval, state.rtt_estimate)
+    }
+}
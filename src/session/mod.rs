@@ -1,2 +1,13 @@
 pub mod session_handler;
-pub mod client_handler;
\ No newline at end of file
+pub mod client_handler;
+pub mod client_directory;
+pub mod session_directory;
+pub mod takeover_tracker;
+pub mod qos2_tracker;
+pub mod keep_alive_stats;
+pub mod client_limits;
+pub mod session_store;
+pub mod session_persistence;
+pub mod session_state;
+pub mod state_snapshot;
+pub mod state_transfer;
@@ -0,0 +1,102 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use dashmap::DashMap;
+use log::{trace, warn};
+use metered::HitCount;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::config::broker_config::Qos2Config;
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct Qos2Metrics {
+    pub(crate) inbound_completed: HitCount,
+    //PUBREC sent, the client never sent PUBREL
+    pub(crate) inbound_abandoned: HitCount,
+    pub(crate) outbound_completed: HitCount,
+    //PUBLISH or PUBREL sent, the client never answered with PUBREC or PUBCOMP
+    pub(crate) outbound_abandoned: HitCount,
+}
+
+#[derive(Debug)]
+#[derive(Copy, Clone, Hash, Eq, PartialEq)]
+pub enum Direction {
+    //QoS 2 PUBLISH from a client
+    Inbound,
+    //QoS 2 PUBLISH to a subscriber
+    Outbound,
+}
+
+//Handshake of a QoS 2 PUBLISH by direction, client_id and Packet Identifier
+pub type Handshake = (Direction, String, u16);
+
+//When each unfinished QoS 2 handshake last made progress, so that the state of the ones a client
+//leaves hanging can be dropped instead of piling up
+#[derive(Debug)]
+pub struct Qos2Tracker {
+    config: Qos2Config,
+    handshakes: DashMap<Handshake, Instant>,
+    pub(crate) metrics: Qos2Metrics,
+}
+
+impl Qos2Tracker {
+    pub fn is_enabled(&self) -> bool {
+        self.config.handshake_timeout_secs > 0
+    }
+
+    pub fn start(&self, direction: Direction, client_id: &String, packet_identifier: u16) {
+        if self.is_enabled() {
+            trace!("Qos2Tracker::start {:?} {:?} {}", direction, client_id, packet_identifier);
+            self.handshakes.insert((direction, client_id.clone(), packet_identifier), Instant::now());
+        }
+    }
+
+    //The handshake moved on to its next step, which gets the whole timeout again
+    pub fn advance(&self, direction: Direction, client_id: &String, packet_identifier: u16) {
+        if let Some(mut last_progress) = self.handshakes.get_mut(&(direction, client_id.clone(), packet_identifier)) {
+            *last_progress = Instant::now();
+        }
+    }
+
+    pub fn complete(&self, direction: Direction, client_id: &String, packet_identifier: u16) {
+        if self.handshakes.remove(&(direction, client_id.clone(), packet_identifier)).is_some() {
+            match direction {
+                Direction::Inbound => { self.metrics.inbound_completed.incr(); }
+                Direction::Outbound => { self.metrics.outbound_completed.incr(); }
+            }
+        }
+    }
+
+    //Unfinished handshakes in both directions by client_id
+    pub fn pending_by_client(&self) -> HashMap<String, usize> {
+        let mut pending = HashMap::new();
+        self.handshakes.iter().for_each(|entry| *pending.entry(entry.key().1.clone()).or_default() += 1);
+        pending
+    }
+
+    //Removes and returns the handshakes without progress for longer than the timeout
+    pub fn expire(&self, now: Instant) -> Vec<Handshake> {
+        let timeout = Duration::from_secs(self.config.handshake_timeout_secs);
+        let mut expired = vec![];
+        self.handshakes.retain(|handshake, last_progress| {
+            if now.duration_since(*last_progress) <= timeout {
+                return true;
+            }
+            let (direction, client_id, packet_identifier) = handshake;
+            warn!("Abandoning {:?} QoS 2 handshake of client {:?} for Packet Identifier {}, no progress in {}s", direction, client_id, packet_identifier, timeout.as_secs());
+            match direction {
+                Direction::Inbound => { self.metrics.inbound_abandoned.incr(); }
+                Direction::Outbound => { self.metrics.outbound_abandoned.incr(); }
+            }
+            expired.push(handshake.clone());
+            false
+        });
+        expired
+    }
+
+    pub fn new(config: Qos2Config) -> Self {
+        Self { config, handshakes: DashMap::new(), metrics: Qos2Metrics::default() }
+    }
+}
@@ -0,0 +1,113 @@
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use dashmap::DashMap;
+use dashmap::mapref::entry::Entry;
+
+use crate::session::session_handler::{SessionHandler, SessionState};
+
+//Where the sessions of clients are, by client_id: the queued messages, QoS handshakes and Session Expiry Interval a
+//client keeps across its connections, and the pending expiry of the sessions whose connection closed.
+//A single broker keeps them in memory. A cluster can back them with a store shared by its nodes, so that a client
+//connecting to another node finds its session there, which is why finding and claiming a session have async variants.
+#[async_trait]
+pub trait SessionDirectory: Debug + Send + Sync {
+    fn session_of(&self, client_id: &String) -> Option<Arc<SessionHandler>>;
+
+    //Atomic, of two clients connecting with the same client_id at once one creates the session and the other finds it
+    fn register(&self, client_id: &String) -> SessionState;
+
+    //Replaces the session the client had, if any
+    fn replace(&self, client_id: &String, session: SessionHandler);
+
+    //Returns false when the client already has a session, which is kept
+    fn restore(&self, client_id: &String, session: SessionHandler) -> bool;
+
+    //Returns whether there was a session to remove
+    fn remove(&self, client_id: &String) -> bool;
+
+    fn sessions(&self) -> Vec<(String, Arc<SessionHandler>)>;
+
+    fn session_count(&self) -> usize;
+
+    //Replaces the pending expiry of the session, by the generation of its timer
+    fn set_expiry(&self, client_id: &String, generation: u64);
+
+    //Whether the expiry of that generation was still pending. It no longer is
+    fn take_expiry(&self, client_id: &String, generation: u64) -> bool;
+
+    fn cancel_expiry(&self, client_id: &String);
+
+    //The session of the client wherever the directory keeps it
+    async fn find_session(&self, client_id: &String) -> Option<Arc<SessionHandler>> {
+        self.session_of(client_id)
+    }
+
+    //Registers the session of a connecting client, across every node sharing the directory
+    async fn claim_session(&self, client_id: &String) -> SessionState {
+        self.register(client_id)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LocalSessions {
+    id2session: DashMap<String, Arc<SessionHandler>>,
+    expiries: DashMap<String, u64>,
+}
+
+impl SessionDirectory for LocalSessions {
+    fn session_of(&self, client_id: &String) -> Option<Arc<SessionHandler>> {
+        self.id2session.get(client_id).map(|session| session.value().clone())
+    }
+
+    fn register(&self, client_id: &String) -> SessionState {
+        match self.id2session.entry(client_id.clone()) {
+            Entry::Occupied(_) => { SessionState::SessionPresent }
+            Entry::Vacant(entry) => {
+                entry.insert(Arc::new(SessionHandler::new()));
+                SessionState::CleanSession
+            }
+        }
+    }
+
+    fn replace(&self, client_id: &String, session: SessionHandler) {
+        self.id2session.insert(client_id.clone(), Arc::new(session));
+    }
+
+    fn restore(&self, client_id: &String, session: SessionHandler) -> bool {
+        match self.id2session.entry(client_id.clone()) {
+            Entry::Occupied(_) => { false }
+            Entry::Vacant(entry) => {
+                entry.insert(Arc::new(session));
+                true
+            }
+        }
+    }
+
+    fn remove(&self, client_id: &String) -> bool {
+        self.id2session.remove(client_id).is_some()
+    }
+
+    fn sessions(&self) -> Vec<(String, Arc<SessionHandler>)> {
+        self.id2session.iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect()
+    }
+
+    fn session_count(&self) -> usize {
+        self.id2session.len()
+    }
+
+    fn set_expiry(&self, client_id: &String, generation: u64) {
+        self.expiries.insert(client_id.clone(), generation);
+    }
+
+    fn take_expiry(&self, client_id: &String, generation: u64) -> bool {
+        self.expiries.remove_if(client_id, |_, current| *current == generation).is_some()
+    }
+
+    fn cancel_expiry(&self, client_id: &String) {
+        self.expiries.remove(client_id);
+    }
+}
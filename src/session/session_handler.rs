@@ -1,23 +1,128 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
 use dashmap::DashMap;
 use log::trace;
 use metered::{*};
+use serde::{Deserialize, Serialize};
 
+use crate::broker::message_expiry::StoredMessage;
 use crate::model::control_packet::ControlPacket;
 use crate::model::qos_level::QoSLevel;
+use crate::session::client_limits::ClientLimits;
+use crate::topic::topic_matcher::topic_matches;
 
 pub enum SessionState {
     SessionPresent,
     CleanSession,
 }
 
+//The last network connection of a session, timestamps are epoch millis
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Serialize)]
+pub struct ConnectionMetadata {
+    pub address: SocketAddr,
+    pub protocol_version: u8,
+    pub username: Option<String>,
+    pub keep_alive: u16,
+    pub clean_start: bool,
+    pub limits: ClientLimits,
+    pub connected_at: i64,
+    pub disconnected_at: Option<i64>,
+}
+
+impl ConnectionMetadata {
+    pub fn from_connect(address: SocketAddr, connect_packet: &ControlPacket) -> Self {
+        let variable_header = connect_packet.variable_header();
+        Self {
+            address,
+            protocol_version: variable_header.protocol_version(),
+            username: connect_packet.payload().username().cloned(),
+            keep_alive: variable_header.keep_alive_opt().unwrap_or(0),
+            clean_start: variable_header.connect_flags().clean_start_flag(),
+            limits: ClientLimits::from_connect(variable_header).unwrap_or_default(),
+            connected_at: Utc::now().timestamp_millis(),
+            disconnected_at: None,
+        }
+    }
+}
+
+#[derive(Debug)]
+#[derive(Serialize)]
+pub struct SessionSummary {
+    pub queued_qos0: usize,
+    pub queued_qos1: usize,
+    pub queued_qos2: usize,
+    pub session_expiry_interval: u32,
+    pub request_problem_information: bool,
+    pub connection: Option<ConnectionMetadata>,
+}
+
+//A message waiting in a session, without its payload
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct QueuedMessage {
+    pub topic: String,
+    pub qos: u8,
+    //None at QoS 0
+    pub packet_identifier: Option<u16>,
+    //Payload bytes
+    pub size: usize,
+    pub age_secs: u64,
+    pub expires_in_secs: Option<u32>,
+}
+
+//Which queued messages to drop, every given condition must match. Nothing given selects every message.
+#[derive(Debug, Default)]
+#[derive(Deserialize)]
+pub struct QueueSelector {
+    pub topic_filter: Option<String>,
+    pub qos: Option<u8>,
+    pub packet_identifier: Option<u16>,
+    pub older_than_secs: Option<u64>,
+}
+
+impl QueueSelector {
+    fn selects(&self, message: &StoredMessage) -> bool {
+        let control_packet = message.control_packet();
+        self.topic_filter.as_ref().map_or(true, |topic_filter| topic_matches(topic_filter, control_packet.variable_header().topic_name()))
+            && self.qos.map_or(true, |qos| control_packet.fixed_header().qos_level().as_u8() == qos)
+            && self.packet_identifier.map_or(true, |packet_identifier| control_packet.variable_header().packet_identifier_opt() == Some(packet_identifier))
+            && self.older_than_secs.map_or(true, |older_than_secs| message.age().as_secs() >= older_than_secs)
+    }
+}
+
+//What of a session outlives the broker process, the last connection doesn't
+#[derive(Debug)]
+pub struct SessionSnapshot {
+    pub session_expiry_interval: u32,
+    pub request_problem_information: bool,
+    //Epoch millis, None while connected
+    pub disconnected_at: Option<i64>,
+    //Expired messages left out
+    pub messages: Vec<StoredMessage>,
+}
+
 #[derive(Debug)]
 pub struct SessionHandler {
-    client2pub_qos0_packets: DashMap<String, Vec<ControlPacket>>,
-    client2pub_qos1_packets: DashMap<(String, u16), ControlPacket>,
-    client2pub_qos2_packets: DashMap<(String, u16), ControlPacket>,
+    client2pub_qos0_packets: DashMap<String, Vec<StoredMessage>>,
+    client2pub_qos1_packets: DashMap<(String, u16), StoredMessage>,
+    client2pub_qos2_packets: DashMap<(String, u16), StoredMessage>,
     client2puback: DashMap<(String, u16), bool>,
     client2pubrel: DashMap<(String, u16), bool>,
     client2pubrec: DashMap<(String, u16), bool>,
+    //Seconds the session is kept after the network connection is closed
+    session_expiry_interval: AtomicU32,
+    //Request Problem Information of the last CONNECT
+    request_problem_information: AtomicBool,
+    connection: Mutex<Option<ConnectionMetadata>>,
+    //Epoch millis a restored session was disconnected at, until its client connects again
+    restored_disconnected_at: Option<i64>,
     pub(crate) metrics: SessionHandlerMetrics,
 
 }
@@ -25,28 +130,29 @@ pub struct SessionHandler {
 #[metered(registry = SessionHandlerMetrics)]
 impl SessionHandler {
     #[measure([HitCount, Throughput, InFlight, ResponseTime])]
-    pub fn register_publish(&self, client_id: String, packet: &ControlPacket) {
+    pub fn register_publish(&self, client_id: String, packet: &ControlPacket, received_at: Instant, topic_filters: &[Arc<String>]) {
         trace!("register_publish");
         let qos = packet.fixed_header().qos_level();
+        let message = StoredMessage::new(packet.clone(), received_at).with_topic_filters(topic_filters.to_vec());
         match qos {
             QoSLevel::AtMostOnce => {
                 if self.client2pub_qos0_packets.contains_key(&client_id) {
                     self.client2pub_qos0_packets
                         .entry(client_id)
                         .and_modify(|f| {
-                            f.push(packet.clone());
+                            f.push(message);
                         });
                 } else {
-                    self.client2pub_qos0_packets.insert(client_id, vec![packet.clone()]);
+                    self.client2pub_qos0_packets.insert(client_id, vec![message]);
                 }
             }
             QoSLevel::AtLeastOnce => {
                 let packet_id = packet.variable_header().packet_identifier();
-                self.client2pub_qos1_packets.insert((client_id, packet_id), packet.clone());
+                self.client2pub_qos1_packets.insert((client_id, packet_id), message);
             }
             QoSLevel::ExactlyOnce => {
                 let packet_id = packet.variable_header().packet_identifier();
-                self.client2pub_qos2_packets.insert((client_id, packet_id), packet.clone());
+                self.client2pub_qos2_packets.insert((client_id, packet_id), message);
             }
         }
     }
@@ -113,14 +219,152 @@ impl SessionHandler {
 }
 
 impl SessionHandler {
+    pub fn session_expiry_interval(&self) -> u32 {
+        self.session_expiry_interval.load(Ordering::SeqCst)
+    }
+
+    //Expired messages are dropped the same way whether the client or the broker set their interval
+    pub fn drop_qos2_message(&self, client_id: String, packet_id: u16) -> bool {
+        self.client2pub_qos2_packets.remove(&(client_id, packet_id)).is_some()
+    }
+
+    //A QoS 1 or QoS 2 message handed to another client, which now owns its delivery
+    pub fn drop_message(&self, client_id: String, packet_id: u16) -> bool {
+        let key = (client_id, packet_id);
+        self.client2pub_qos1_packets.remove(&key).is_some() | self.client2pub_qos2_packets.remove(&key).is_some()
+    }
+
+    pub fn drop_expired(&self) {
+        self.client2pub_qos0_packets.iter_mut().for_each(|mut messages| messages.retain(|message| !message.is_expired()));
+        self.client2pub_qos1_packets.retain(|_, message| !message.is_expired());
+        self.client2pub_qos2_packets.retain(|_, message| !message.is_expired());
+    }
+
+    pub fn request_problem_information(&self) -> bool {
+        self.request_problem_information.load(Ordering::SeqCst)
+    }
+
+    pub fn set_request_problem_information(&self, request_problem_information: bool) {
+        self.request_problem_information.store(request_problem_information, Ordering::SeqCst);
+    }
+
+    pub fn set_connection(&self, connection: ConnectionMetadata) {
+        *self.connection.lock().unwrap() = Some(connection);
+    }
+
+    pub fn set_disconnected(&self) {
+        if let Some(connection) = self.connection.lock().unwrap().as_mut() {
+            connection.disconnected_at = Some(Utc::now().timestamp_millis());
+        }
+    }
+
+    pub fn summary(&self) -> SessionSummary {
+        self.drop_expired();
+        SessionSummary {
+            queued_qos0: self.client2pub_qos0_packets.iter().map(|packets| packets.len()).sum(),
+            queued_qos1: self.client2pub_qos1_packets.len(),
+            queued_qos2: self.client2pub_qos2_packets.len(),
+            session_expiry_interval: self.session_expiry_interval(),
+            request_problem_information: self.request_problem_information(),
+            connection: self.connection.lock().unwrap().clone(),
+        }
+    }
+
+    //Expired messages are dropped first, the oldest message comes first
+    pub fn queued_messages(&self) -> Vec<QueuedMessage> {
+        self.drop_expired();
+        let mut messages: Vec<(Duration, QueuedMessage)> = Vec::new();
+        self.client2pub_qos0_packets.iter().for_each(|queue| messages.extend(queue.iter().map(|message| (message.age(), Self::queued_message(message)))));
+        for queue in [&self.client2pub_qos1_packets, &self.client2pub_qos2_packets] {
+            messages.extend(queue.iter().map(|message| (message.age(), Self::queued_message(message.value()))));
+        }
+        messages.sort_by(|(age, _), (other_age, _)| other_age.cmp(age));
+        messages.into_iter().map(|(_, message)| message).collect()
+    }
+
+    fn queued_message(message: &StoredMessage) -> QueuedMessage {
+        let control_packet = message.control_packet();
+        QueuedMessage {
+            topic: control_packet.variable_header().topic_name().clone(),
+            qos: control_packet.fixed_header().qos_level().as_u8(),
+            packet_identifier: control_packet.variable_header().packet_identifier_opt().filter(|_| *control_packet.fixed_header().qos_level() != QoSLevel::AtMostOnce),
+            size: control_packet.payload_opt().map(|payload| payload.data().len()).unwrap_or(0),
+            age_secs: message.age().as_secs(),
+            expires_in_secs: message.remaining_interval(),
+        }
+    }
+
+    //Returns how many messages were dropped
+    pub fn purge_queued(&self, selector: &QueueSelector) -> usize {
+        let before = self.queued_len();
+        self.client2pub_qos0_packets.iter_mut().for_each(|mut messages| messages.retain(|message| !selector.selects(message)));
+        self.client2pub_qos1_packets.retain(|_, message| !selector.selects(message));
+        self.client2pub_qos2_packets.retain(|_, message| !selector.selects(message));
+        before - self.queued_len()
+    }
+
+    //Drops the messages queued only through the topic filters the client unsubscribed from, except the ones
+    //in_flight says were sent already. Returns the packets dropped
+    pub fn drop_unsubscribed(&self, topic_filters: &[String], in_flight: impl Fn(&ControlPacket) -> bool) -> Vec<ControlPacket> {
+        let mut dropped = vec![];
+        let mut drops = |message: &StoredMessage| {
+            if !message.only_through(topic_filters) || in_flight(message.control_packet()) {
+                return false;
+            }
+            dropped.push(message.control_packet().clone());
+            true
+        };
+        self.client2pub_qos0_packets.iter_mut().for_each(|mut messages| messages.retain(|message| !drops(message)));
+        self.client2pub_qos1_packets.retain(|_, message| !drops(message));
+        self.client2pub_qos2_packets.retain(|_, message| !drops(message));
+        dropped
+    }
+
+    pub fn queued_len(&self) -> usize {
+        let qos0_len: usize = self.client2pub_qos0_packets.iter().map(|packets| packets.len()).sum();
+        qos0_len + self.client2pub_qos1_packets.len() + self.client2pub_qos2_packets.len()
+    }
+
+    pub fn set_session_expiry_interval(&self, session_expiry_interval: u32) {
+        self.session_expiry_interval.store(session_expiry_interval, Ordering::SeqCst);
+    }
+
+    pub fn snapshot(&self) -> SessionSnapshot {
+        self.drop_expired();
+        let mut messages: Vec<StoredMessage> = Vec::with_capacity(self.queued_len());
+        self.client2pub_qos0_packets.iter().for_each(|queue| messages.extend(queue.iter().cloned()));
+        for queue in [&self.client2pub_qos1_packets, &self.client2pub_qos2_packets] {
+            messages.extend(queue.iter().map(|message| message.value().clone()));
+        }
+        SessionSnapshot {
+            session_expiry_interval: self.session_expiry_interval(),
+            request_problem_information: self.request_problem_information(),
+            disconnected_at: match self.connection.lock().unwrap().as_ref() {
+                Some(connection) => { connection.disconnected_at }
+                None => { self.restored_disconnected_at }
+            },
+            messages,
+        }
+    }
+
+    pub fn restore(client_id: &String, snapshot: SessionSnapshot) -> Self {
+        let session = Self { restored_disconnected_at: snapshot.disconnected_at, ..Self::new() };
+        session.set_session_expiry_interval(snapshot.session_expiry_interval);
+        session.set_request_problem_information(snapshot.request_problem_information);
+        for message in snapshot.messages {
+            session.register_publish(client_id.clone(), message.control_packet(), message.received_at(), message.topic_filters());
+        }
+        session
+    }
+
     pub fn new() -> Self {
-        let client2pub_qos0_packets: DashMap<String, Vec<ControlPacket>> = DashMap::new();
-        let client2pub_qos1_packets: DashMap<(String, u16), ControlPacket> = DashMap::new();
-        let client2pub_qos2_packets: DashMap<(String, u16), ControlPacket> = DashMap::new();
+        let client2pub_qos0_packets: DashMap<String, Vec<StoredMessage>> = DashMap::new();
+        let client2pub_qos1_packets: DashMap<(String, u16), StoredMessage> = DashMap::new();
+        let client2pub_qos2_packets: DashMap<(String, u16), StoredMessage> = DashMap::new();
         let client2puback: DashMap<(String, u16), bool> = DashMap::new();
         let client2pubrel: DashMap<(String, u16), bool> = DashMap::new();
         let client2pubrec: DashMap<(String, u16), bool> = DashMap::new();
 
-        SessionHandler { client2pub_qos0_packets, client2pub_qos1_packets, client2pub_qos2_packets, client2puback, client2pubrel, client2pubrec, metrics: SessionHandlerMetrics::default() }
+        SessionHandler { client2pub_qos0_packets, client2pub_qos1_packets, client2pub_qos2_packets, client2puback, client2pubrel, client2pubrec, session_expiry_interval: AtomicU32::new(0), request_problem_information: AtomicBool::new(true), connection: Mutex::new(None), restored_disconnected_at: None, metrics: SessionHandlerMetrics::default() }
     }
 }
@@ -0,0 +1,110 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::Utc;
+use log::{debug, error, info, trace, warn};
+
+use crate::{ClientHandler, TopicHandler};
+use crate::config::broker_config::BrokerConfig;
+use crate::session::session_state::SessionState;
+use crate::session::session_store::{SessionStore, StoredSession};
+
+//Saves the sessions with a Session Expiry Interval to a SessionStore every storage.session_checkpoint_secs and
+//reads them back when the broker starts. Their Session Expiry Interval keeps counting while the broker is down.
+#[derive(Debug)]
+pub struct SessionPersistence {
+    store: Arc<dyn SessionStore>,
+    state: SessionState,
+    checkpoint_secs: u64,
+    //What the store holds, unchanged sessions aren't written again
+    saved: Mutex<HashMap<String, StoredSession>>,
+}
+
+impl SessionPersistence {
+    //Before the listeners start. Returns how many sessions were restored, the expired ones are dropped from the store.
+    pub fn restore(&self) -> usize {
+        trace!("SessionPersistence::restore");
+        let stored_sessions = match self.store.load_all() {
+            Ok(result) => { result }
+            Err(err) => {
+                error!("Can't restore sessions. {}", err);
+                return 0;
+            }
+        };
+        let now = Utc::now().timestamp_millis();
+        //Without a checkpoint time the sessions of clients connected then count from now
+        let checkpoint_time = self.store.checkpoint_time().unwrap_or_else(|err| {
+            warn!("{}", err);
+            None
+        }).unwrap_or(now);
+        let state_import = self.state.import(stored_sessions.clone(), checkpoint_time, now);
+        for client_id in &state_import.expired {
+            debug!("Session of client {:?} expired while the broker was down", client_id);
+            self.remove(client_id);
+        }
+        let imported: HashSet<&String> = state_import.imported.iter().collect();
+        let mut saved = self.saved.lock().unwrap();
+        for stored_session in stored_sessions.into_iter().filter(|stored_session| imported.contains(&stored_session.client_id)) {
+            let disconnected_at = stored_session.disconnected_at.unwrap_or(checkpoint_time);
+            saved.insert(stored_session.client_id.clone(), StoredSession { disconnected_at: Some(disconnected_at), ..stored_session });
+        }
+        info!("Restored {} sessions", imported.len());
+        imported.len()
+    }
+
+    //Saves the sessions that changed since the last checkpoint and removes the ones that are gone
+    pub fn checkpoint(&self) {
+        trace!("SessionPersistence::checkpoint");
+        let now = Utc::now().timestamp_millis();
+        let mut saved = self.saved.lock().unwrap();
+        let current: HashMap<String, StoredSession> = self.state.export(&saved, now).into_iter()
+            .map(|stored_session| (stored_session.client_id.clone(), stored_session))
+            .collect();
+        for (client_id, stored_session) in &current {
+            if saved.get(client_id) == Some(stored_session) {
+                continue;
+            }
+            if let Err(err) = self.store.save(stored_session) {
+                error!("Can't save session of client {:?}. {}", client_id, err);
+            }
+        }
+        let removed: Vec<String> = saved.keys().filter(|client_id| !current.contains_key(*client_id)).cloned().collect();
+        for client_id in removed {
+            self.remove(&client_id);
+        }
+        if let Err(err) = self.store.save_checkpoint_time(now) {
+            error!("Can't save the checkpoint time. {}", err);
+        }
+        debug!("Checkpointed {} sessions", current.len());
+        *saved = current;
+    }
+
+    //A checkpoint writes and syncs files, so it runs on the blocking threads rather than holding up the ones
+    //handling packets. Checkpoints don't overlap, each one waits for the previous to finish.
+    pub async fn checkpoint_off_runtime(self: Arc<Self>) {
+        if let Err(err) = tokio::task::spawn_blocking(move || self.checkpoint()).await {
+            error!("Session checkpoint failed. {}", err);
+        }
+    }
+
+    pub async fn run(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.checkpoint_secs.max(1)));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            self.clone().checkpoint_off_runtime().await;
+        }
+    }
+
+    fn remove(&self, client_id: &String) {
+        if let Err(err) = self.store.remove(client_id) {
+            warn!("Can't remove session of client {:?}. {}", client_id, err);
+        }
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, store: Arc<dyn SessionStore>, client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>) -> Self {
+        let checkpoint_secs = config.storage.session_checkpoint_secs;
+        Self { store, state: SessionState::new(config, client_handler, topic_handler), checkpoint_secs, saved: Mutex::new(HashMap::new()) }
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use log::{debug, trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{ClientHandler, TopicHandler};
+use crate::broker::message_expiry::StoredMessage;
+use crate::broker::utils::{persistent_session_snapshots, schedule_session_expiry_after};
+use crate::config::broker_config::BrokerConfig;
+use crate::serdes::mqtt_decoder::MqttDecoder;
+use crate::serdes::mqtt_encoder::MqttEncoder;
+use crate::session::session_handler::{SessionHandler, SessionSnapshot};
+use crate::session::session_store::{StoredPublish, StoredSession};
+
+//What became of the sessions given to SessionState::import, by client id
+#[derive(Debug, Default)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct StateImport {
+    pub imported: Vec<String>,
+    //The broker already had a session for the client, it is kept
+    pub existing: Vec<String>,
+    pub expired: Vec<String>,
+}
+
+//Converts the sessions of the broker to StoredSessions and back, for the session store and the state snapshots
+#[derive(Debug)]
+pub struct SessionState {
+    client_handler: Arc<ClientHandler>,
+    topic_handler: Arc<TopicHandler>,
+    encoder: MqttEncoder,
+    decoder: MqttDecoder,
+    //The same moment on both clocks, converts the receive times of messages to epoch millis and back
+    anchor: (Instant, i64),
+}
+
+impl SessionState {
+    //The sessions with a Session Expiry Interval, by client id. A session without a connection nor a disconnect time
+    //counts from the time `previous` holds for it, or from now
+    pub fn export(&self, previous: &HashMap<String, StoredSession>, now: i64) -> Vec<StoredSession> {
+        trace!("SessionState::export");
+        let mut sessions: Vec<StoredSession> = persistent_session_snapshots(&*self.client_handler.sessions).into_iter()
+            .map(|(client_id, snapshot)| self.stored_session(client_id, snapshot, previous, now))
+            .collect();
+        sessions.sort_by(|session, other_session| session.client_id.cmp(&other_session.client_id));
+        sessions
+    }
+
+    //Installs the sessions the broker doesn't have yet. The ones without a disconnect time count from `disconnected_at`,
+    //their Session Expiry Interval keeps running from there.
+    pub fn import(&self, stored_sessions: Vec<StoredSession>, disconnected_at: i64, now: i64) -> StateImport {
+        trace!("SessionState::import");
+        let mut state_import = StateImport::default();
+        for stored_session in stored_sessions {
+            let client_id = stored_session.client_id.clone();
+            let disconnected_at = stored_session.disconnected_at.unwrap_or(disconnected_at);
+            if stored_session.expired(disconnected_at, now) {
+                debug!("Session of client {:?} expired before it could be imported", client_id);
+                state_import.expired.push(client_id);
+                continue;
+            }
+            let snapshot = SessionSnapshot {
+                session_expiry_interval: stored_session.session_expiry_interval,
+                request_problem_information: stored_session.request_problem_information,
+                disconnected_at: Some(disconnected_at),
+                messages: self.decode_messages(&client_id, &stored_session.messages),
+            };
+            if !self.client_handler.sessions.restore(&client_id, SessionHandler::restore(&client_id, snapshot)) {
+                state_import.existing.push(client_id);
+                continue;
+            }
+            self.topic_handler.import_subscriptions(stored_session.subscriptions);
+            if stored_session.session_expiry_interval != u32::MAX {
+                let disconnected_for = Duration::from_millis(now.saturating_sub(disconnected_at).max(0) as u64);
+                let session_expiry_interval = Duration::from_secs(stored_session.session_expiry_interval as u64);
+                schedule_session_expiry_after(&client_id, session_expiry_interval - disconnected_for, self.client_handler.clone(), self.topic_handler.clone());
+            }
+            state_import.imported.push(client_id);
+        }
+        state_import
+    }
+
+    fn stored_session(&self, client_id: String, snapshot: SessionSnapshot, previous: &HashMap<String, StoredSession>, now: i64) -> StoredSession {
+        //A restored session has no connection until its client comes back
+        let disconnected_at = match self.client_handler.get_socket(&client_id) {
+            Ok(_) => { None }
+            Err(_) => {
+                snapshot.disconnected_at
+                    .or_else(|| previous.get(&client_id).and_then(|stored_session| stored_session.disconnected_at))
+                    .or(Some(now))
+            }
+        };
+        let mut subscriptions = self.topic_handler.subscriptions_of(&client_id);
+        subscriptions.sort_by(|record, other_record| record.topic_filter.cmp(&other_record.topic_filter));
+        let mut messages = self.encode_messages(&client_id, &snapshot.messages);
+        messages.sort_by(|message, other_message| message.received_at.cmp(&other_message.received_at).then(message.packet.cmp(&other_message.packet)));
+        StoredSession {
+            client_id,
+            session_expiry_interval: snapshot.session_expiry_interval,
+            request_problem_information: snapshot.request_problem_information,
+            disconnected_at,
+            subscriptions,
+            messages,
+        }
+    }
+
+    fn encode_messages(&self, client_id: &String, messages: &[StoredMessage]) -> Vec<StoredPublish> {
+        messages.iter()
+            .filter_map(|message| match self.encoder.encode_packet(&Arc::new(message.control_packet().clone())) {
+                Ok(packet) => { Some(StoredPublish { received_at: self.epoch_millis(message.received_at()), packet: packet.to_vec() }) }
+                Err(err) => {
+                    warn!("Can't save a message queued for client {:?}: {:?}", client_id, err);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn decode_messages(&self, client_id: &String, messages: &[StoredPublish]) -> Vec<StoredMessage> {
+        messages.iter()
+            .filter_map(|message| match self.decoder.decode_bytes(&message.packet) {
+                Ok(control_packet) => { Some(StoredMessage::new(control_packet, self.instant(message.received_at))) }
+                Err(err) => {
+                    warn!("Can't restore a message queued for client {:?}: {:?}", client_id, err);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn epoch_millis(&self, instant: Instant) -> i64 {
+        let (anchor_instant, anchor_millis) = self.anchor;
+        match instant.checked_duration_since(anchor_instant) {
+            Some(after) => { anchor_millis + after.as_millis() as i64 }
+            None => { anchor_millis - anchor_instant.duration_since(instant).as_millis() as i64 }
+        }
+    }
+
+    //Instants can't go back further than the start of the host, older messages count from there
+    fn instant(&self, epoch_millis: i64) -> Instant {
+        let (anchor_instant, anchor_millis) = self.anchor;
+        let offset = Duration::from_millis(epoch_millis.abs_diff(anchor_millis));
+        match epoch_millis >= anchor_millis {
+            true => { anchor_instant + offset }
+            false => { anchor_instant.checked_sub(offset).unwrap_or(anchor_instant) }
+        }
+    }
+
+    pub fn new(config: Arc<BrokerConfig>, client_handler: Arc<ClientHandler>, topic_handler: Arc<TopicHandler>) -> Self {
+        Self { client_handler, topic_handler, encoder: MqttEncoder::default(), decoder: MqttDecoder::new(config), anchor: (Instant::now(), Utc::now().timestamp_millis()) }
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt::Debug;
+use std::fs;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use log::{trace, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::topic::subscription::SubscriptionRecord;
+
+//A PUBLISH queued for a session, MQTT encoded, with the epoch millis the broker received it at
+//so that its Message Expiry Interval keeps counting while the broker is down
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct StoredPublish {
+    pub received_at: i64,
+    pub packet: Vec<u8>,
+}
+
+//A session as a SessionStore keeps it
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct StoredSession {
+    pub client_id: String,
+    pub session_expiry_interval: u32,
+    pub request_problem_information: bool,
+    //Epoch millis the Session Expiry Interval counts from, None for a client connected at the checkpoint
+    pub disconnected_at: Option<i64>,
+    #[serde(default)]
+    pub subscriptions: Vec<SubscriptionRecord>,
+    #[serde(default)]
+    pub messages: Vec<StoredPublish>,
+}
+
+impl StoredSession {
+    //Whether its Session Expiry Interval ran out by now when counted from disconnected_at, u32::MAX never does
+    pub fn expired(&self, disconnected_at: i64, now: i64) -> bool {
+        self.session_expiry_interval != u32::MAX && now.saturating_sub(disconnected_at) >= self.session_expiry_interval as i64 * 1000
+    }
+}
+
+//Where sessions go to survive a restart of the broker
+pub trait SessionStore: Debug + Send + Sync {
+    //Replaces what the store held for the client
+    fn save(&self, session: &StoredSession) -> Result<(), String>;
+
+    fn remove(&self, client_id: &String) -> Result<(), String>;
+
+    //Epoch millis of the last checkpoint, the connections open then are considered closed at that time
+    fn save_checkpoint_time(&self, epoch_millis: i64) -> Result<(), String>;
+
+    fn checkpoint_time(&self) -> Result<Option<i64>, String>;
+
+    //A session that can't be read is left out
+    fn load_all(&self) -> Result<Vec<StoredSession>, String>;
+}
+
+const CHECKPOINT_FILE: &str = "checkpoint";
+//Bytes of a client_id whose hex names its file, with ".json.tmp" and the hash of longer ones the name stays
+//within the 255 bytes file systems allow
+const MAX_FILE_NAME_BYTES: usize = 100;
+
+//One JSON file per client in a directory, named after the hex encoded client_id since
+//client identifiers may hold any character. A file is written aside, synced and renamed over the old one,
+//then the directory is synced so that the rename survives a power loss too.
+#[derive(Debug)]
+pub struct FileSessionStore {
+    directory: PathBuf,
+}
+
+impl FileSessionStore {
+    //A longer client_id is named after the hex of its start and a hash of all of it, the file holds the client_id
+    fn path(&self, client_id: &String) -> PathBuf {
+        let bytes = client_id.as_bytes();
+        let file_name = match bytes.len() > MAX_FILE_NAME_BYTES {
+            true => { format!("{}~{:016x}", Self::hex(&bytes[..MAX_FILE_NAME_BYTES]), Self::fnv1a(bytes)) }
+            false => { Self::hex(bytes) }
+        };
+        self.directory.join(format!("{}.json", file_name))
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    //FNV-1a, unlike DefaultHasher the same in every build, the file names must outlive the binary
+    fn fnv1a(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+    }
+
+    fn write_durably(&self, path: &Path, content: &[u8]) -> Result<(), String> {
+        fs::create_dir_all(&self.directory).map_err(|err| format!("Can't create session directory {}: {}", self.directory.display(), err))?;
+        let temporary_path = path.with_file_name(format!("{}.tmp", path.file_name().unwrap_or_default().to_string_lossy()));
+        let mut file = File::create(&temporary_path).map_err(|err| format!("Can't create {}: {}", temporary_path.display(), err))?;
+        file.write_all(content)
+            .and_then(|_| file.sync_all())
+            .map_err(|err| format!("Can't write {}: {}", temporary_path.display(), err))?;
+        fs::rename(&temporary_path, path).map_err(|err| format!("Can't rename {} to {}: {}", temporary_path.display(), path.display(), err))?;
+        self.sync_directory()
+    }
+
+    fn sync_directory(&self) -> Result<(), String> {
+        File::open(&self.directory)
+            .and_then(|directory| directory.sync_all())
+            .map_err(|err| format!("Can't sync session directory {}: {}", self.directory.display(), err))
+    }
+
+    fn session_files(&self) -> Result<Vec<PathBuf>, String> {
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => { entries }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => { return Ok(vec![]); }
+            Err(err) => { return Err(format!("Can't read session directory {}: {}", self.directory.display(), err)); }
+        };
+        Ok(entries.filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .collect())
+    }
+
+    pub fn new(directory: &str) -> Self {
+        Self { directory: PathBuf::from(directory) }
+    }
+}
+
+impl SessionStore for FileSessionStore {
+    fn save(&self, session: &StoredSession) -> Result<(), String> {
+        trace!("FileSessionStore::save");
+        let json = serde_json::to_vec(session).map_err(|err| format!("Can't serialize session of client {:?}: {}", session.client_id, err))?;
+        self.write_durably(&self.path(&session.client_id), &json)
+    }
+
+    fn remove(&self, client_id: &String) -> Result<(), String> {
+        trace!("FileSessionStore::remove");
+        let path = self.path(client_id);
+        match fs::remove_file(&path) {
+            Ok(_) => { self.sync_directory() }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => { Ok(()) }
+            Err(err) => { Err(format!("Can't remove {}: {}", path.display(), err)) }
+        }
+    }
+
+    fn save_checkpoint_time(&self, epoch_millis: i64) -> Result<(), String> {
+        self.write_durably(&self.directory.join(CHECKPOINT_FILE), epoch_millis.to_string().as_bytes())
+    }
+
+    fn checkpoint_time(&self) -> Result<Option<i64>, String> {
+        let path = self.directory.join(CHECKPOINT_FILE);
+        match fs::read_to_string(&path) {
+            Ok(content) => { content.trim().parse().map(Some).map_err(|err| format!("Can't read {}: {}", path.display(), err)) }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => { Ok(None) }
+            Err(err) => { Err(format!("Can't read {}: {}", path.display(), err)) }
+        }
+    }
+
+    fn load_all(&self) -> Result<Vec<StoredSession>, String> {
+        trace!("FileSessionStore::load_all");
+        let mut sessions = vec![];
+        for path in self.session_files()? {
+            let session = fs::read(&path).map_err(|err| err.to_string())
+                .and_then(|json| serde_json::from_slice::<StoredSession>(&json).map_err(|err| err.to_string()));
+            match session {
+                Ok(session) => { sessions.push(session); }
+                Err(err) => { warn!("Skipping session file {}: {}", path.display(), err); }
+            }
+        }
+        Ok(sessions)
+    }
+}
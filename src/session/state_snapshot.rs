@@ -0,0 +1,103 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use log::trace;
+use serde::{Deserialize, Serialize};
+
+use crate::session::session_state::StateImport;
+use crate::session::session_store::{SessionStore, StoredSession};
+
+//A snapshot of another version is refused
+pub const STATE_SNAPSHOT_VERSION: u32 = 1;
+
+//The sessions of a broker as export-state writes them and import-state reads them, the schema is in the README.
+//A session without a disconnect time was connected at exported_at and counts from then.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub version: u32,
+    //Epoch millis
+    pub exported_at: i64,
+    pub sessions: Vec<StoredSession>,
+}
+
+impl StateSnapshot {
+    pub fn check(&self) -> Result<(), String> {
+        if self.version != STATE_SNAPSHOT_VERSION {
+            return Err(format!("Unsupported state snapshot version {}, expected {}", self.version, STATE_SNAPSHOT_VERSION));
+        }
+        let mut client_ids = HashSet::new();
+        if let Some(session) = self.sessions.iter().find(|session| !client_ids.insert(&session.client_id)) {
+            return Err(format!("Client {:?} has more than one session in the state snapshot", session.client_id));
+        }
+        for session in &self.sessions {
+            if let Some(subscription) = session.subscriptions.iter().find(|subscription| subscription.client_id != session.client_id) {
+                return Err(format!("The session of client {:?} holds a subscription of client {:?}", session.client_id, subscription.client_id));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn from_json(json: &[u8]) -> Result<Self, String> {
+        let snapshot: Self = serde_json::from_slice(json).map_err(|err| format!("Invalid state snapshot: {}", err))?;
+        snapshot.check()?;
+        Ok(snapshot)
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, String> {
+        serde_json::to_vec_pretty(self).map_err(|err| format!("Can't serialize the state snapshot: {}", err))
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        trace!("StateSnapshot::read");
+        let json = fs::read(path).map_err(|err| format!("Can't read {}: {}", path.display(), err))?;
+        Self::from_json(&json)
+    }
+
+    //Written aside and renamed, an interrupted export leaves no half snapshot behind
+    pub fn write(&self, path: &Path) -> Result<(), String> {
+        trace!("StateSnapshot::write");
+        let temporary_path = path.with_extension("tmp");
+        fs::write(&temporary_path, self.to_json()?).map_err(|err| format!("Can't write {}: {}", temporary_path.display(), err))?;
+        fs::rename(&temporary_path, path).map_err(|err| format!("Can't rename {} to {}: {}", temporary_path.display(), path.display(), err))
+    }
+
+    //The sessions a stopped broker left in its store. The ones connected at its last checkpoint count from then.
+    pub fn load(store: &dyn SessionStore, now: i64) -> Result<Self, String> {
+        trace!("StateSnapshot::load");
+        let checkpoint_time = store.checkpoint_time()?.unwrap_or(now);
+        let mut sessions: Vec<StoredSession> = store.load_all()?.into_iter()
+            .map(|session| StoredSession { disconnected_at: session.disconnected_at.or(Some(checkpoint_time)), ..session })
+            .collect();
+        sessions.sort_by(|session, other_session| session.client_id.cmp(&other_session.client_id));
+        Ok(Self { version: STATE_SNAPSHOT_VERSION, exported_at: now, sessions })
+    }
+
+    //Adds the sessions to the store of a stopped broker, the broker restores them when it starts.
+    //A client the store already holds a session for keeps it.
+    pub fn save(&self, store: &dyn SessionStore, now: i64) -> Result<StateImport, String> {
+        trace!("StateSnapshot::save");
+        let existing: HashSet<String> = store.load_all()?.into_iter().map(|session| session.client_id).collect();
+        let mut state_import = StateImport::default();
+        for session in &self.sessions {
+            let disconnected_at = session.disconnected_at.unwrap_or(self.exported_at);
+            if existing.contains(&session.client_id) {
+                state_import.existing.push(session.client_id.clone());
+            } else if session.expired(disconnected_at, now) {
+                state_import.expired.push(session.client_id.clone());
+            } else {
+                store.save(&StoredSession { disconnected_at: Some(disconnected_at), ..session.clone() })?;
+                state_import.imported.push(session.client_id.clone());
+            }
+        }
+        Ok(state_import)
+    }
+
+    pub fn new(mut sessions: Vec<StoredSession>, exported_at: i64) -> Self {
+        sessions.sort_by(|session, other_session| session.client_id.cmp(&other_session.client_id));
+        Self { version: STATE_SNAPSHOT_VERSION, exported_at, sessions }
+    }
+}
@@ -0,0 +1,110 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use chrono::Utc;
+use log::{info, trace};
+
+use crate::config::broker_config::{BrokerConfig, StorageMode};
+use crate::config::command_line::{Command, StateTarget};
+use crate::config::port_check::{check_ports, configured_endpoints, ConflictKind};
+use crate::session::session_state::StateImport;
+use crate::session::session_store::FileSessionStore;
+use crate::session::state_snapshot::StateSnapshot;
+
+//export-state and import-state, run instead of the broker. Returns what was done, for the terminal.
+pub async fn run_state_command(config: Arc<BrokerConfig>, command: &Command) -> Result<String, String> {
+    trace!("state_transfer::run_state_command");
+    let result = match command {
+        Command::Run | Command::PrintDefaultConfig => { return Err(String::from("Not a state command")); }
+        Command::ExportState { path, target: StateTarget::Storage } => {
+            let snapshot = StateSnapshot::load(&session_store(&config)?, Utc::now().timestamp_millis())?;
+            snapshot.write(Path::new(path))?;
+            format!("Exported {} sessions to {}", snapshot.sessions.len(), path)
+        }
+        Command::ImportState { path, target: StateTarget::Storage } => {
+            let session_store = session_store(&config)?;
+            check_stopped(&config)?;
+            let snapshot = StateSnapshot::read(Path::new(path))?;
+            import_summary(path, &snapshot.save(&session_store, Utc::now().timestamp_millis())?)
+        }
+        Command::ExportState { path, target: StateTarget::AdminApi { token } } => {
+            let token = token.clone().or_else(|| config.admin.api_token.clone());
+            let snapshot = StateSnapshot::from_json(&admin_api::request("GET", token, None).await?)?;
+            snapshot.write(Path::new(path))?;
+            format!("Exported {} sessions of the running broker to {}", snapshot.sessions.len(), path)
+        }
+        Command::ImportState { path, target: StateTarget::AdminApi { token } } => {
+            let token = token.clone().or_else(|| config.admin.api_token.clone());
+            let snapshot = StateSnapshot::read(Path::new(path))?;
+            let response = admin_api::request("PUT", token, Some(snapshot.to_json()?)).await?;
+            let state_import: StateImport = serde_json::from_slice(&response).map_err(|err| format!("Unexpected answer of the admin API: {}", err))?;
+            import_summary(path, &state_import)
+        }
+    };
+    info!("{}", result);
+    Ok(result)
+}
+
+//Sessions are only on disk in disk mode, a broker in memory mode has them in memory alone
+fn session_store(config: &BrokerConfig) -> Result<FileSessionStore, String> {
+    if config.storage.mode != StorageMode::Disk {
+        return Err(String::from("storage.mode is memory, the sessions are only in the running broker. Use --admin-api"));
+    }
+    Ok(FileSessionStore::new(&config.storage.session_directory))
+}
+
+//A running broker would overwrite the store at its next checkpoint
+fn check_stopped(config: &BrokerConfig) -> Result<(), String> {
+    match check_ports(&configured_endpoints(config)).into_iter().find(|conflict| conflict.kind == ConflictKind::InUse) {
+        Some(conflict) => { Err(format!("{} is in use, the broker seems to be running. Stop it or use --admin-api", conflict.endpoint.address)) }
+        None => { Ok(()) }
+    }
+}
+
+fn import_summary(path: &str, state_import: &StateImport) -> String {
+    let mut summary = format!("Imported {} sessions from {}", state_import.imported.len(), path);
+    if !state_import.existing.is_empty() {
+        summary.push_str(&format!(". Kept the sessions the broker had for {:?}", state_import.existing));
+    }
+    if !state_import.expired.is_empty() {
+        summary.push_str(&format!(". Left out the expired sessions of {:?}", state_import.expired));
+    }
+    summary
+}
+
+#[cfg(feature = "admin-api")]
+mod admin_api {
+    use hyper::{Body, Client, Request};
+
+    use crate::config::port_check::ADMIN_API_ADDRESS;
+
+    //The body of a successful answer of /state, the message of the admin API otherwise
+    pub async fn request(method: &str, token: Option<String>, body: Option<Vec<u8>>) -> Result<Vec<u8>, String> {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(format!("http://{}/state", ADMIN_API_ADDRESS))
+            .header("content-type", "application/json");
+        if let Some(token) = token {
+            request = request.header("authorization", format!("Bearer {}", token));
+        }
+        let request = request.body(body.map(Body::from).unwrap_or_else(Body::empty)).map_err(|err| err.to_string())?;
+        let response = Client::new().request(request).await
+            .map_err(|err| format!("Can't reach the admin API on {}, is the broker running? {}", ADMIN_API_ADDRESS, err))?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.map_err(|err| format!("Can't read the answer of the admin API: {}", err))?;
+        if !status.is_success() {
+            let message = serde_json::from_slice::<serde_json::Value>(&body).ok()
+                .and_then(|response| response.get("message").and_then(|message| message.as_str()).map(String::from))
+                .unwrap_or_else(|| String::from_utf8_lossy(&body).to_string());
+            return Err(format!("{} /state refused with {}: {}", method, status, message));
+        }
+        Ok(body.to_vec())
+    }
+}
+
+#[cfg(not(feature = "admin-api"))]
+mod admin_api {
+    pub async fn request(_method: &str, _token: Option<String>, _body: Option<Vec<u8>>) -> Result<Vec<u8>, String> {
+        Err(String::from("patina was built without the admin-api feature, --admin-api isn't available"))
+    }
+}
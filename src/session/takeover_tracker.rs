@@ -0,0 +1,77 @@
+use std::hash::Hash;
+use std::net::{IpAddr, SocketAddr};
+
+use dashmap::DashMap;
+use log::{trace, warn};
+use metered::HitCount;
+use serde::Serialize;
+
+//Topic the broker publishes a JSON event to on every session takeover
+pub const SYS_TAKEOVER_TOPIC: &str = "$SYS/broker/takeovers";
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct TakeoverMetrics {
+    session_taken_over: HitCount,
+    //Unacknowledged packets of the previous connection sent again on the one taking over
+    pub(crate) resent: HitCount,
+}
+
+#[derive(Debug)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize)]
+pub struct TakeoverEvent {
+    pub client_id: String,
+    pub address: SocketAddr,
+    pub previous_address: SocketAddr,
+    //Takeovers of the client id so far, this one included
+    pub takeovers: u64,
+}
+
+//Counts SessionTakenOver per client id and per address of the connection taking over.
+//Frequent takeovers usually mean several devices share the same credentials.
+#[derive(Debug, Default)]
+pub struct TakeoverTracker {
+    id2takeovers: DashMap<String, u64>,
+    ip2takeovers: DashMap<IpAddr, u64>,
+    pub(crate) metrics: TakeoverMetrics,
+}
+
+impl TakeoverTracker {
+    pub fn record(&self, client_id: &String, socket: &SocketAddr, previous_socket: &SocketAddr) -> TakeoverEvent {
+        trace!("TakeoverTracker::record");
+        self.metrics.session_taken_over.incr();
+        *self.ip2takeovers.entry(socket.ip()).or_default() += 1;
+        let takeovers = {
+            let mut takeovers = self.id2takeovers.entry(client_id.clone()).or_default();
+            *takeovers += 1;
+            *takeovers
+        };
+        if takeovers > 1 {
+            warn!("Session of client {:?} taken over {} times, last from {:?}", client_id, takeovers, socket);
+        }
+        TakeoverEvent { client_id: client_id.clone(), address: *socket, previous_address: *previous_socket, takeovers }
+    }
+
+    pub fn top_clients(&self, limit: usize) -> Vec<(String, u64)> {
+        Self::top(&self.id2takeovers, limit)
+    }
+
+    pub fn top_addresses(&self, limit: usize) -> Vec<(IpAddr, u64)> {
+        Self::top(&self.ip2takeovers, limit)
+    }
+
+    pub fn total(&self) -> u64 {
+        self.id2takeovers.iter().map(|takeovers| *takeovers.value()).sum()
+    }
+
+    //Highest counts first
+    fn top<K: Clone + Eq + Hash + Ord>(counts: &DashMap<K, u64>, limit: usize) -> Vec<(K, u64)> {
+        let mut top: Vec<(K, u64)> = counts.iter()
+            .map(|entry| (entry.key().clone(), *entry.value()))
+            .collect();
+        top.sort_by(|(key_a, count_a), (key_b, count_b)| count_b.cmp(count_a).then(key_a.cmp(key_b)));
+        top.truncate(limit);
+        top
+    }
+}
@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod audit_log_tests {
+    use std::fs;
+
+    use crate::audit::audit_log::{AuditEvent, AuditLog};
+    use crate::config::broker_config::AuditConfig;
+
+    fn create_audit_config(name: &str, max_size: u64) -> AuditConfig {
+        let directory = std::env::temp_dir().join(format!("patina-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        AuditConfig { enabled: true, path: directory.join("audit.log").to_string_lossy().to_string(), max_size, max_files: 2 }
+    }
+
+    fn auth_failure() -> AuditEvent {
+        AuditEvent::AuthFailure { interface: String::from("admin-api"), resource: String::from("POST /publish test"), reason: String::from("Invalid token") }
+    }
+
+    #[test]
+    fn record_json_lines() {
+        let config = create_audit_config("audit-json", 1024 * 1024);
+        let audit_log = AuditLog::new(config.clone());
+        audit_log.record(AuditEvent::ConfigLoaded { path: String::from("config/patina.yaml") });
+        audit_log.record(auth_failure());
+
+        let content = fs::read_to_string(&config.path).unwrap();
+        let records: Vec<serde_json::Value> = content.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["event"], "config-loaded");
+        assert_eq!(records[1]["event"], "auth-failure");
+        assert_eq!(records[1]["reason"], "Invalid token");
+        assert!(records[1]["timestamp"].is_string());
+    }
+
+    #[test]
+    fn rotate_when_full() {
+        let config = create_audit_config("audit-rotation", 1);
+        let audit_log = AuditLog::new(config.clone());
+        for _ in 0..4 {
+            audit_log.record(auth_failure());
+        }
+        assert_eq!(fs::read_to_string(&config.path).unwrap().lines().count(), 1);
+        assert!(fs::metadata(format!("{}.1", config.path)).is_ok());
+        assert!(fs::metadata(format!("{}.2", config.path)).is_ok());
+        assert!(fs::metadata(format!("{}.3", config.path)).is_err());
+    }
+
+    #[test]
+    fn disabled_writes_nothing() {
+        let mut config = create_audit_config("audit-disabled", 1024);
+        config.enabled = false;
+        AuditLog::new(config.clone()).record(auth_failure());
+        assert!(fs::metadata(&config.path).is_err());
+    }
+}
@@ -0,0 +1,165 @@
+#[cfg(test)]
+mod acl_tests {
+    use std::fs;
+
+    use crate::auth::acl::{Acl, filter_covers, filters_overlap, pattern_matches};
+    use crate::config::broker_config::{AclAccess, AclConfig, AclPermission, AclRule, BrokerConfig};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::ConnectFlags;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet_with_username, create_publish_packet_qos1, create_subscribe_packet};
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    fn rule(permission: AclPermission, client_id: Option<&str>, access: AclAccess, topics: &[&str]) -> AclRule {
+        AclRule { permission, client_id: client_id.map(String::from), username: None, access, topics: topics.iter().map(|topic| topic.to_string()).collect() }
+    }
+
+    fn acl_config(rules: Vec<AclRule>) -> AclConfig {
+        AclConfig { enabled: true, rules, ..AclConfig::default() }
+    }
+
+    fn some(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn match_patterns() {
+        assert!(pattern_matches("sensor-*", "sensor-1"));
+        assert!(pattern_matches("sensor-*", "sensor-"));
+        assert!(pattern_matches("*-1", "sensor-1"));
+        assert!(pattern_matches("s*n*1", "sensor-1"));
+        assert!(pattern_matches("*", ""));
+        assert!(pattern_matches("sensor", "sensor"));
+        assert!(!pattern_matches("sensor", "sensor-1"));
+        assert!(!pattern_matches("sensor-*", "actuator-1"));
+        assert!(!pattern_matches("*c*c", "c"));
+    }
+
+    #[test]
+    fn cover_and_overlap_filters() {
+        assert!(filter_covers("a/#", "a"));
+        assert!(filter_covers("a/#", "a/+/c"));
+        assert!(filter_covers("a/+", "a/b"));
+        assert!(filter_covers("#", "a/#"));
+        assert!(!filter_covers("a/+", "a/#"));
+        assert!(!filter_covers("a/b", "a/+"));
+        assert!(!filter_covers("#", "$SYS/broker"));
+        assert!(!filter_covers("a/b", "a/b/c"));
+
+        assert!(filters_overlap("a/secret", "a/#"));
+        assert!(filters_overlap("a/+/c", "a/b/+"));
+        assert!(filters_overlap("a/#", "a"));
+        assert!(!filters_overlap("a/b", "a/c"));
+        assert!(!filters_overlap("a/b", "a/b/c"));
+        assert!(!filters_overlap("$SYS/#", "#"));
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let acl = Acl::new(acl_config(vec![
+            rule(AclPermission::Deny, None, AclAccess::ReadWrite, &["devices/secret"]),
+            rule(AclPermission::Allow, Some("sensor-*"), AclAccess::Write, &["devices/%c/#"]),
+            rule(AclPermission::Allow, None, AclAccess::Read, &["devices/#"]),
+        ]));
+        assert!(acl.can_publish("sensor-1", None, "devices/sensor-1/temperature"));
+        assert!(!acl.can_publish("sensor-1", None, "devices/sensor-2/temperature"));
+        assert!(!acl.can_publish("actuator-1", None, "devices/actuator-1/state"));
+        assert!(!acl.can_publish("sensor-1", None, "devices/secret"));
+        assert!(acl.can_subscribe("dashboard", None, "devices/+/temperature"));
+        assert!(acl.can_subscribe("dashboard", None, "$share/group/devices/sensor-1/#"));
+        //Could receive devices/secret
+        assert!(!acl.can_subscribe("dashboard", None, "devices/#"));
+        assert!(!acl.can_subscribe("dashboard", None, "#"));
+        assert_eq!(acl.metrics.allowed.0.get(), 3);
+        assert_eq!(acl.metrics.denied.0.get(), 5);
+    }
+
+    #[test]
+    fn substitute_client_id_and_username() {
+        let mut username_rule = rule(AclPermission::Allow, None, AclAccess::ReadWrite, &["users/%u/#"]);
+        username_rule.username = some("fleet-*");
+        let acl = Acl::new(acl_config(vec![username_rule, rule(AclPermission::Allow, None, AclAccess::ReadWrite, &["clients/%c"])]));
+        assert!(acl.can_publish("client", some("fleet-a").as_ref(), "users/fleet-a/state"));
+        assert!(!acl.can_publish("client", some("fleet-a").as_ref(), "users/fleet-b/state"));
+        assert!(!acl.can_publish("client", some("other").as_ref(), "users/other/state"));
+        assert!(!acl.can_publish("client", None, "users/%u/state"));
+        assert!(acl.can_subscribe("client", None, "clients/client"));
+        //Would have widened clients/%c to every client
+        assert!(!acl.can_subscribe("#", None, "clients/#"));
+    }
+
+    #[test]
+    fn allow_everything_unless_enabled() {
+        let acl = Acl::new(AclConfig { enabled: false, ..acl_config(vec![]) });
+        assert!(acl.can_publish("client", None, "any/topic"));
+        assert!(acl.can_subscribe("client", None, "#"));
+        let acl = Acl::new(acl_config(vec![]));
+        assert!(!acl.can_publish("client", None, "any/topic"));
+        let acl = Acl::new(AclConfig { no_match: AclPermission::Allow, ..acl_config(vec![]) });
+        assert!(acl.can_subscribe("client", None, "#"));
+    }
+
+    #[test]
+    fn load_rules_from_file() {
+        let path = std::env::temp_dir().join(format!("patina-acl-{}.yaml", std::process::id()));
+        fs::write(&path, "- permission: allow\n  client_id: \"sensor-*\"\n  access: write\n  topics: [\"devices/%c/#\"]\n").unwrap();
+        let mut config = BrokerConfig::default();
+        config.acl = AclConfig { file: Some(path.to_str().unwrap().to_string()), ..acl_config(vec![rule(AclPermission::Deny, None, AclAccess::ReadWrite, &["devices/sensor-1/secret"])]) };
+        assert!(config.check_acl().is_ok());
+        let acl = Acl::new(config.acl.clone());
+        assert!(acl.can_publish("sensor-1", None, "devices/sensor-1/state"));
+        assert!(!acl.can_publish("sensor-1", None, "devices/sensor-1/secret"));
+
+        fs::write(&path, "- permission: allow\n  topics: [\"devices/#/state\"]\n").unwrap();
+        assert!(config.check_acl().unwrap_err().contains("devices/#/state"));
+        fs::write(&path, "- permission: maybe\n  topics: [\"devices\"]\n").unwrap();
+        assert!(config.check_acl().unwrap_err().contains("Can't parse"));
+        //Fails closed
+        assert!(!Acl::new(config.acl.clone()).can_publish("sensor-1", None, "devices/sensor-1/state"));
+        fs::remove_file(&path).unwrap();
+        assert!(config.check_acl().unwrap_err().contains("Can't read"));
+    }
+
+    #[tokio::test]
+    async fn refuse_denied_topics_with_not_authorized() {
+        let mut config = BrokerConfig::default();
+        config.acl = acl_config(vec![rule(AclPermission::Allow, Some("acl-*"), AclAccess::ReadWrite, &["acl/%c/#"])]);
+        let mut harness = HandlerHarness::new(config);
+        let socket = harness.connect("acl-sensor").await;
+
+        harness.send(socket, create_subscribe_packet(1, String::from("acl/acl-other/#"), QoSLevel::AtMostOnce)).await.unwrap();
+        let (_, suback_packet) = harness.expect(ControlPacketType::SUBACK).await;
+        assert_eq!(suback_packet.payload().reason_codes(), &vec![ReasonCode::NotAuthorized]);
+        harness.send(socket, create_subscribe_packet(2, String::from("acl/acl-sensor/#"), QoSLevel::AtMostOnce)).await.unwrap();
+        let (_, suback_packet) = harness.expect(ControlPacketType::SUBACK).await;
+        assert_eq!(suback_packet.payload().reason_codes(), &vec![ReasonCode::GrantedQoS0]);
+
+        harness.send(socket, create_publish_packet_qos1(3, String::from("acl/acl-other/state"))).await.unwrap();
+        let (_, puback_packet) = harness.expect(ControlPacketType::PUBACK).await;
+        assert_eq!(puback_packet.variable_header().reason_code(), Some(&ReasonCode::NotAuthorized));
+
+        //A Will Message to a denied topic refuses the CONNECT
+        let will_connect_packet = ControlPacket::connect(ConnectFlags::new(false, false, false, QoSLevel::AtMostOnce, true, true, false), None, vec![],
+                                                         some("acl-will"), Some(vec![]), some("acl/acl-other/will"), Some(vec![1]), None, None);
+        let socket = HandlerHarness::socket();
+        harness.send(socket, will_connect_packet).await.unwrap();
+        let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::NotAuthorized));
+        harness.expect_nothing();
+    }
+
+    #[tokio::test]
+    async fn disconnect_on_denied_publish() {
+        let mut config = BrokerConfig::default();
+        config.acl = AclConfig { disconnect_on_denied_publish: true, ..acl_config(vec![]) };
+        let mut harness = HandlerHarness::new(config);
+        let socket = HandlerHarness::socket();
+        harness.send(socket, create_connect_packet_with_username(String::from("acl-disconnected"), String::from("fleet"))).await.unwrap();
+        harness.expect(ControlPacketType::CONNACK).await;
+        harness.send(socket, create_publish_packet_qos1(1, String::from("acl/denied"))).await.unwrap();
+        let (_, disconnect_packet) = harness.expect(ControlPacketType::DISCONNECT).await;
+        assert_eq!(disconnect_packet.variable_header().reason_code(), Some(&ReasonCode::NotAuthorized));
+    }
+}
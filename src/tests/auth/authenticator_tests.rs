@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod authenticator_tests {
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    use crate::auth::authenticator::Authenticator;
+    use crate::config::broker_config::{AuthConfig, BrokerConfig};
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::reason_code::ReasonCode;
+    use crate::tests::broker::broker_tests_data::create_connect_packet_with_credentials;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    fn auth_config() -> AuthConfig {
+        let mut config = AuthConfig::default();
+        config.users.insert(String::from("sensor"), String::from("s3cret"));
+        config.failure_delay_ms = 200;
+        config.failure_jitter_ms = 100;
+        config.lockout_threshold = 3;
+        config.lockout_base_secs = 10;
+        config.lockout_max_secs = 60;
+        config
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, last])
+    }
+
+    fn some(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn accept_every_client_without_users() {
+        let authenticator = Authenticator::new(AuthConfig::default());
+        assert_eq!(authenticator.authenticate(None, None, ip(1)), Ok(()));
+        assert_eq!(authenticator.metrics.accepted.0.get(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn check_username_and_password() {
+        let authenticator = Authenticator::new(auth_config());
+        assert_eq!(authenticator.authenticate(some("sensor").as_ref(), some("s3cret").as_ref(), ip(1)), Ok(()));
+        for (last, username, password) in [(2, some("sensor"), some("s3cre")), (3, some("sensor"), None), (4, some("other"), some("s3cret")), (5, None, None)] {
+            let refusal = authenticator.authenticate(username.as_ref(), password.as_ref(), ip(last)).unwrap_err();
+            assert_eq!(refusal.reason_code, ReasonCode::BadUsernameOrPassword);
+            let delay = refusal.delay;
+            assert!(delay >= Duration::from_millis(200) && delay <= Duration::from_millis(300), "{:?}", delay);
+        }
+        assert_eq!(authenticator.metrics.accepted.0.get(), 1);
+        assert_eq!(authenticator.metrics.rejected.0.get(), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lock_out_username_after_repeated_failures() {
+        let authenticator = Authenticator::new(auth_config());
+        let username = some("sensor");
+        for last in 1..=3 {
+            assert!(authenticator.authenticate(username.as_ref(), some("guess").as_ref(), ip(last)).is_err());
+        }
+        //Right password, from an address that never failed
+        assert_eq!(authenticator.authenticate(username.as_ref(), some("s3cret").as_ref(), ip(9)).unwrap_err().reason_code, ReasonCode::Banned);
+        assert_eq!(authenticator.metrics.locked_out.0.get(), 1);
+
+        //The next failure after the lockout doubles it
+        tokio::time::advance(Duration::from_secs(11)).await;
+        assert!(authenticator.authenticate(username.as_ref(), some("guess").as_ref(), ip(4)).is_err());
+        tokio::time::advance(Duration::from_secs(11)).await;
+        assert!(authenticator.authenticate(username.as_ref(), some("s3cret").as_ref(), ip(9)).is_err());
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert_eq!(authenticator.authenticate(username.as_ref(), some("s3cret").as_ref(), ip(9)), Ok(()));
+        assert_eq!(authenticator.metrics.lockouts.0.get(), 2);
+        assert_eq!(authenticator.metrics.locked_out.0.get(), 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lock_out_ip_guessing_usernames() {
+        let authenticator = Authenticator::new(auth_config());
+        for username in ["a", "b", "c"] {
+            assert!(authenticator.authenticate(some(username).as_ref(), some("guess").as_ref(), ip(1)).is_err());
+        }
+        assert!(authenticator.authenticate(some("sensor").as_ref(), some("s3cret").as_ref(), ip(1)).is_err());
+        assert_eq!(authenticator.authenticate(some("sensor").as_ref(), some("s3cret").as_ref(), ip(2)), Ok(()));
+    }
+
+    #[test]
+    fn hide_refusal_cause_with_generic_reason_codes() {
+        let mut config = auth_config();
+        config.generic_reason_codes = true;
+        let authenticator = Authenticator::new(config);
+        let username = some("sensor");
+        for last in 1..=3 {
+            let refusal = authenticator.authenticate(username.as_ref(), some("guess").as_ref(), ip(last)).unwrap_err();
+            assert_eq!(refusal.reason_code, ReasonCode::NotAuthorized);
+        }
+        let refusal = authenticator.authenticate(username.as_ref(), some("s3cret").as_ref(), ip(9)).unwrap_err();
+        assert_eq!(refusal.reason_code, ReasonCode::NotAuthorized);
+        assert_eq!(authenticator.metrics.locked_out.0.get(), 1);
+    }
+
+    #[test]
+    fn double_lockout_up_to_maximum() {
+        let authenticator = Authenticator::new(auth_config());
+        assert_eq!(authenticator.lockout(0), Duration::from_secs(10));
+        assert_eq!(authenticator.lockout(2), Duration::from_secs(40));
+        assert_eq!(authenticator.lockout(3), Duration::from_secs(60));
+        assert_eq!(authenticator.lockout(80), Duration::from_secs(60));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn delay_connack_of_failed_connect() {
+        let mut config = BrokerConfig::default();
+        config.auth = auth_config();
+        let mut harness = HandlerHarness::new(config);
+        let socket = HandlerHarness::socket();
+        let started = tokio::time::Instant::now();
+        harness.send(socket, create_connect_packet_with_credentials(String::from("auth-guesser"), String::from("sensor"), String::from("guess"))).await.unwrap();
+        let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::BadUsernameOrPassword));
+        harness.expect_nothing();
+        assert!(harness.client_handler.get_client_id(&socket).is_err());
+
+        let socket = HandlerHarness::socket();
+        harness.send(socket, create_connect_packet_with_credentials(String::from("auth-sensor"), String::from("sensor"), String::from("s3cret"))).await.unwrap();
+        let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::Success));
+        assert_eq!(harness.packet_dispatcher.authenticator.metrics.rejected.0.get(), 1);
+    }
+}
@@ -0,0 +1,2 @@
+pub mod acl_tests;
+pub mod authenticator_tests;
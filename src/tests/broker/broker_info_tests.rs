@@ -0,0 +1,70 @@
+#[cfg(test)]
+mod broker_info_tests {
+    use std::net::SocketAddr;
+
+    use crate::{ClientHandler, TopicHandler};
+    use crate::broker::broker_info::{BrokerInfo, BuildInfo, SYS_BUILD_TOPIC, SYS_SUBSCRIPTION_CHURN_TOPIC, SYS_UPTIME_TOPIC, SYS_VERSION_TOPIC};
+
+    #[test]
+    fn build_info() {
+        let build_info = BuildInfo::new();
+        assert_eq!(build_info.version, env!("CARGO_PKG_VERSION"));
+        assert!(!build_info.git_hash.is_empty());
+        assert!(chrono::DateTime::parse_from_rfc3339(build_info.build_date).is_ok());
+        assert_eq!(BuildInfo::metric_labels().get("version"), Some(&build_info.version));
+    }
+
+    #[cfg(feature = "admin-api")]
+    #[test]
+    fn metrics_carry_build_labels() {
+        let metrics = serde_prometheus::to_string(&BrokerInfo::new().metrics(), Some("patina"), BuildInfo::metric_labels()).unwrap();
+        assert!(metrics.starts_with("patina_uptime_seconds{"));
+        assert!(metrics.contains(&format!("version = \"{}\"", env!("CARGO_PKG_VERSION"))));
+        assert!(metrics.contains("git_hash = "));
+    }
+
+    #[tokio::test]
+    async fn publish_broker_info() {
+        let (to_listener, mut from_broker) = tokio::sync::mpsc::channel(10);
+        let client_handler = ClientHandler::default();
+        let topic_handler = TopicHandler::default();
+        let socket: SocketAddr = "127.0.0.1:42001".parse().unwrap();
+        let client_id = String::from("broker-info-monitor");
+        client_handler.register(&socket, &client_id);
+        topic_handler.subscribe(&client_id, &String::from("$SYS/broker/+"));
+
+        BrokerInfo::new().publish(&client_handler, &topic_handler, &to_listener).await;
+        let mut topics = vec![];
+        for _ in 0..3 {
+            let (sockets, publish_packet) = from_broker.recv().await.unwrap();
+            assert_eq!(sockets, vec![socket]);
+            topics.push(publish_packet.variable_header().topic_name().clone());
+            if publish_packet.variable_header().topic_name() == SYS_BUILD_TOPIC {
+                let build_info: serde_json::Value = serde_json::from_slice(publish_packet.payload().data()).unwrap();
+                assert_eq!(build_info["version"], env!("CARGO_PKG_VERSION"));
+            }
+        }
+        assert_eq!(topics, vec![SYS_VERSION_TOPIC, SYS_UPTIME_TOPIC, SYS_BUILD_TOPIC]);
+    }
+
+    #[tokio::test]
+    async fn publish_subscription_churn() {
+        let (to_listener, mut from_broker) = tokio::sync::mpsc::channel(10);
+        let client_handler = ClientHandler::default();
+        let topic_handler = TopicHandler::default();
+        let socket: SocketAddr = "127.0.0.1:42002".parse().unwrap();
+        let client_id = String::from("churn-monitor");
+        client_handler.register(&socket, &client_id);
+        topic_handler.subscribe(&client_id, &String::from("test/churn"));
+        topic_handler.unsubscribe(&client_id, &String::from("test/churn"));
+        topic_handler.subscribe(&client_id, &String::from(SYS_SUBSCRIPTION_CHURN_TOPIC));
+
+        BrokerInfo::new().publish(&client_handler, &topic_handler, &to_listener).await;
+        let (sockets, publish_packet) = from_broker.recv().await.unwrap();
+        assert_eq!(sockets, vec![socket]);
+        assert_eq!(publish_packet.variable_header().topic_name(), SYS_SUBSCRIPTION_CHURN_TOPIC);
+        let churn: serde_json::Value = serde_json::from_slice(publish_packet.payload().data()).unwrap();
+        assert_eq!(churn, serde_json::json!({"subscribed": 2, "unsubscribed": 1}));
+        assert!(from_broker.try_recv().is_err());
+    }
+}
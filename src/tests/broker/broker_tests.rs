@@ -1,48 +1,53 @@
 #[cfg(test)]
 mod broker_tests {
     use std::net::{IpAddr, SocketAddr};
+    use std::sync::Arc;
 
-    use log::{info, log, warn};
+    use log::error;
     use tokio::sync::mpsc;
     use tokio::sync::mpsc::{Receiver, Sender};
 
-    use crate::{init_logging, TopicHandler};
-    use crate::broker::Broker;
-    use crate::serdes::mqtt::{ControlPacket, ControlPacketType, QoSLevel};
-    use crate::tests::broker::broker_tests_data::{create_connect_packet, create_publish_packet_qos1, create_subscribe_packet};
+    use crate::{ClientHandler, init_logging, TopicHandler};
+    use crate::broker::packet_dispatcher::PacketDispatcher;
+    use crate::broker::utils::get_session_expiry_interval;
+    use crate::broker::compression::{ACCEPT_ENCODING_PROPERTY, CONTENT_ENCODING_PROPERTY};
+    use crate::config::broker_config::{BrokerConfig, OverlapPolicy, QuotaProfile, TakeoverPolicy};
+    use crate::connection::client_context::ClientContext;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::Property;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet, create_connect_packet_with_properties, create_connect_packet_with_username, create_disconnect_packet, create_publish_packet_qos1, create_subscribe_packet, create_subscribe_packet_with_properties, create_unsubscribe_packet};
 
     #[derive(Debug)]
     pub struct Channels {
-        listener2broker_tx: Sender<(SocketAddr, ControlPacket)>,
-        broker2listener_rx: Receiver<(SocketAddr, ControlPacket)>,
+        listener2broker_tx: Sender<(ClientContext, ControlPacket)>,
+        broker2listener_rx: Receiver<(Vec<SocketAddr>, ControlPacket)>,
+        client_handler: Arc<ClientHandler>,
     }
 
-    lazy_static! {
-
-        static ref BROKER_INSTANCE: Broker = {
-            Broker::new()
-
-        };
-
-}
-
     async fn spinup_broker() -> Channels {
-        let (listener2broker_tx, listener2broker_rx) = mpsc::channel(32);
+        spinup_broker_with_config(BrokerConfig::default()).await
+    }
+
+    async fn spinup_broker_with_config(config: BrokerConfig) -> Channels {
+        let (listener2broker_tx, mut listener2broker_rx) = mpsc::channel::<(ClientContext, ControlPacket)>(32);
         let (broker2listener_tx, broker2listener_rx) = mpsc::channel(32);
-        let (broker2topic_handler_tx, broker2topic_handler_rx) = mpsc::channel(32);
+        let client_handler = Arc::new(ClientHandler::default());
+        let packet_dispatcher = PacketDispatcher::new(Arc::new(config), client_handler.clone(), Arc::new(TopicHandler::default()), Arc::new(broker2listener_tx));
         tokio::spawn(async move {
-            info!("Spawned TopicHandler thread");
-            TopicHandler::handle_topics(broker2topic_handler_rx).await;
-            warn!("TopicHandler thread going to die");
-        });
-        tokio::spawn(async move {
-            info!("Spawned broker thread");
-            BROKER_INSTANCE.handle_packets(listener2broker_rx, broker2listener_tx, broker2topic_handler_tx).await;
-            warn!("Broker thread going to die");
+            while let Some((context, control_packet)) = listener2broker_rx.recv().await {
+                let socket = context.socket;
+                if let Err(err) = packet_dispatcher.process_message(context, control_packet).await {
+                    error!("Can't process packet from socket {}. {}", socket, err);
+                }
+            }
         });
         Channels {
             listener2broker_tx,
             broker2listener_rx,
+            client_handler,
         }
     }
 
@@ -50,13 +55,18 @@ mod broker_tests {
         return SocketAddr::new(IpAddr::from([127, 0, 0, 1]), port);
     }
 
+    fn takeover_config(takeover_policy: TakeoverPolicy) -> BrokerConfig {
+        let mut config = BrokerConfig::default();
+        config.session.takeover_policy = takeover_policy;
+        config
+    }
 
-    async fn send_packet_to_broker(tx_socket: &SocketAddr, channels: &mut Channels, packet: &ControlPacket) -> (SocketAddr, ControlPacket) {
-        channels.listener2broker_tx.send((tx_socket.clone(), packet.clone())).await.expect("can't send packet to broker");
+    async fn send_packet_to_broker(tx_socket: &SocketAddr, channels: &mut Channels, packet: &ControlPacket) -> (Vec<SocketAddr>, ControlPacket) {
+        channels.listener2broker_tx.send((ClientContext::new(*tx_socket), packet.clone())).await.expect("can't send packet to broker");
         return channels.broker2listener_rx.recv().await.expect("can't read packet from broker");
     }
 
-    async fn read_packet_from_broker(channels: &mut Channels) -> (SocketAddr, ControlPacket) {
+    async fn read_packet_from_broker(channels: &mut Channels) -> (Vec<SocketAddr>, ControlPacket) {
         return channels.broker2listener_rx.recv().await.expect("can't read packet from broker");
     }
 
@@ -66,8 +76,8 @@ mod broker_tests {
         let tx_socket = create_socket(0001);
         let mut channels = spinup_broker().await;
         let connect_packet = create_connect_packet(String::from("simulate_connect"));
-        let (rx_socket, connack_packet) = send_packet_to_broker(&tx_socket, &mut channels, &connect_packet).await;
-        assert_eq!(rx_socket, tx_socket);
+        let (rx_sockets, connack_packet) = send_packet_to_broker(&tx_socket, &mut channels, &connect_packet).await;
+        assert_eq!(rx_sockets, vec![tx_socket]);
         assert_eq!(connack_packet.fixed_header().packet_type(), ControlPacketType::CONNACK);
     }
 
@@ -82,9 +92,70 @@ mod broker_tests {
         send_packet_to_broker(&tx_socket, &mut channels, &connect_packet).await;
 
         let subscribe_packet = create_subscribe_packet(0, topic.clone(), QoSLevel::AtLeastOnce);
-        let (res_tx_socket, suback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &subscribe_packet).await;
-        assert_eq!(res_tx_socket, tx_socket);
+        let (res_tx_sockets, suback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &subscribe_packet).await;
+        assert_eq!(res_tx_sockets, vec![tx_socket]);
         assert_eq!(suback_packet.fixed_header().packet_type(), ControlPacketType::SUBACK);
+
+        let unsubscribe_packet = create_unsubscribe_packet(1, vec![topic.clone(), String::from("test/#")]);
+        let (_, unsuback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &unsubscribe_packet).await;
+        assert_eq!(unsuback_packet.fixed_header().packet_type(), ControlPacketType::UNSUBACK);
+        assert_eq!(unsuback_packet.payload().reason_codes(), &vec![ReasonCode::Success, ReasonCode::NoSubscriptionExisted]);
+    }
+
+    #[tokio::test]
+    async fn simulate_request_problem_and_response_information() {
+        init_logging();
+        let opted_out_socket = create_socket(0001);
+        let default_socket = create_socket(0002);
+        let mut config = BrokerConfig::default();
+        config.session.response_topic_prefix = Some(String::from("responses"));
+        let mut channels = spinup_broker_with_config(config).await;
+        let unsubscribe_packet = create_unsubscribe_packet(1, vec![String::from("test/missing")]);
+
+        let client_id = String::from("simulate_request_information_opted_out");
+        let properties = vec![Property::RequestProblemInformation(0), Property::RequestResponseInformation(1)];
+        let (_, connack_packet) = send_packet_to_broker(&opted_out_socket, &mut channels, &create_connect_packet_with_properties(client_id.clone(), properties)).await;
+        assert_eq!(connack_packet.variable_header().properties(), &vec![Property::ResponseInformation(format!("responses/{}", client_id)), Property::TopicAliasMaximum(16)]);
+        let (_, unsuback_packet) = send_packet_to_broker(&opted_out_socket, &mut channels, &unsubscribe_packet).await;
+        assert_eq!(unsuback_packet.payload().reason_codes(), &vec![ReasonCode::NoSubscriptionExisted]);
+        assert!(unsuback_packet.variable_header().properties().is_empty());
+
+        let (_, connack_packet) = send_packet_to_broker(&default_socket, &mut channels, &create_connect_packet(String::from("simulate_request_information_default"))).await;
+        assert_eq!(connack_packet.variable_header().properties(), &vec![Property::TopicAliasMaximum(16)]);
+        let (_, unsuback_packet) = send_packet_to_broker(&default_socket, &mut channels, &unsubscribe_packet).await;
+        assert_eq!(unsuback_packet.variable_header().properties(), &vec![
+            Property::ReasonString(String::from("1 topic filters matched no subscription")),
+            Property::UserProperty(String::from("test/missing"), String::from("NoSubscriptionExisted")),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn simulate_disabled_subscription_features() {
+        init_logging();
+        let socket = create_socket(0001);
+        let mut config = BrokerConfig::default();
+        config.subscription.wildcard_subscriptions = false;
+        config.subscription.shared_subscriptions = false;
+        let mut channels = spinup_broker_with_config(config).await;
+
+        let (_, connack_packet) = send_packet_to_broker(&socket, &mut channels, &create_connect_packet(String::from("simulate_disabled_subscription_features"))).await;
+        assert_eq!(connack_packet.variable_header().properties(), &vec![Property::WildcardSubscriptionAvailable(0), Property::SharedSubscriptionAvailable(0), Property::TopicAliasMaximum(16)]);
+        for (topic_filter, reason_code) in [
+            ("test/+/status", ReasonCode::WildcardSubscriptionsNotSupported),
+            ("$share/group/test/#", ReasonCode::SharedSubscriptionsNotSupported),
+            ("test/status", ReasonCode::GrantedQoS0),
+        ] {
+            let (_, suback_packet) = send_packet_to_broker(&socket, &mut channels, &create_subscribe_packet(1, String::from(topic_filter), QoSLevel::AtMostOnce)).await;
+            assert_eq!(suback_packet.payload().reason_codes(), &vec![reason_code]);
+            let properties = suback_packet.variable_header().properties();
+            match reason_code {
+                ReasonCode::GrantedQoS0 => { assert!(properties.is_empty()); }
+                _ => {
+                    assert!(matches!(properties[0], Property::ReasonString(_)));
+                    assert_eq!(properties[1], Property::UserProperty(String::from(topic_filter), format!("{:?}", reason_code)));
+                }
+            }
+        }
     }
 
     #[tokio::test]
@@ -105,15 +176,376 @@ mod broker_tests {
         send_packet_to_broker(&rx_socket, &mut channels, &subscribe_packet).await;
 
         let publish_packet = create_publish_packet_qos1(0, topic);
-        let (res_tx_socket, puback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &publish_packet).await;
+        let (res_tx_sockets, puback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &publish_packet).await;
 
-        assert_eq!(res_tx_socket, tx_socket);
+        assert_eq!(res_tx_sockets, vec![tx_socket]);
         assert_eq!(puback_packet.fixed_header().packet_type(), ControlPacketType::PUBACK);
         assert_eq!(puback_packet.variable_header().packet_identifier(), publish_packet.variable_header().packet_identifier());
 
-        let (res_rx_socket, publish_packet) = read_packet_from_broker(&mut channels).await;
-        assert_eq!(res_rx_socket, rx_socket);
+        let (res_rx_sockets, publish_packet) = read_packet_from_broker(&mut channels).await;
+        assert_eq!(res_rx_sockets, vec![rx_socket]);
         assert_eq!(publish_packet.fixed_header().qos_level(), &QoSLevel::AtLeastOnce);
         assert_eq!(publish_packet.fixed_header().packet_type(), ControlPacketType::PUBLISH);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn simulate_publish_clears_dup_flag() {
+        init_logging();
+        let tx_socket = create_socket(0001);
+        let rx_socket = create_socket(0002);
+        let topic = String::from("test/dup");
+        let mut channels = spinup_broker().await;
+
+        send_packet_to_broker(&tx_socket, &mut channels, &create_connect_packet(String::from("simulate_publish_clears_dup_flag_tx"))).await;
+        send_packet_to_broker(&rx_socket, &mut channels, &create_connect_packet(String::from("simulate_publish_clears_dup_flag_rx"))).await;
+        send_packet_to_broker(&rx_socket, &mut channels, &create_subscribe_packet(1, topic.clone(), QoSLevel::AtLeastOnce)).await;
+
+        //A retransmission by the publisher is a first delivery for the subscriber
+        let publish_packet = ControlPacket::publish(Some(1), Some(topic), true, QoSLevel::AtLeastOnce, false);
+        let (_, puback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &publish_packet).await;
+        assert_eq!(puback_packet.fixed_header().packet_type(), ControlPacketType::PUBACK);
+        let (res_rx_sockets, forwarded_packet) = read_packet_from_broker(&mut channels).await;
+        assert_eq!(res_rx_sockets, vec![rx_socket]);
+        assert!(!*forwarded_packet.fixed_header().dup_flag());
+    }
+
+    #[tokio::test]
+    async fn simulate_takeover_kick_old() {
+        init_logging();
+        let old_socket = create_socket(0001);
+        let new_socket = create_socket(0002);
+        let mut channels = spinup_broker_with_config(takeover_config(TakeoverPolicy::KickOld)).await;
+
+        let connect_packet = create_connect_packet(String::from("simulate_takeover_kick_old"));
+        send_packet_to_broker(&old_socket, &mut channels, &connect_packet).await;
+
+        let (res_old_sockets, disconnect_packet) = send_packet_to_broker(&new_socket, &mut channels, &connect_packet).await;
+        assert_eq!(res_old_sockets, vec![old_socket]);
+        assert_eq!(disconnect_packet.fixed_header().packet_type(), ControlPacketType::DISCONNECT);
+        assert_eq!(disconnect_packet.variable_header().reason_code(), Some(&ReasonCode::SessionTakenOver));
+
+        let (res_new_sockets, connack_packet) = read_packet_from_broker(&mut channels).await;
+        assert_eq!(res_new_sockets, vec![new_socket]);
+        assert_eq!(connack_packet.fixed_header().packet_type(), ControlPacketType::CONNACK);
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::Success));
+    }
+
+    #[tokio::test]
+    async fn simulate_takeover_sys_event() {
+        init_logging();
+        let old_socket = create_socket(0001);
+        let new_socket = create_socket(0002);
+        let observer_socket = create_socket(0003);
+        let mut channels = spinup_broker_with_config(takeover_config(TakeoverPolicy::KickOld)).await;
+
+        send_packet_to_broker(&observer_socket, &mut channels, &create_connect_packet(String::from("simulate_takeover_sys_observer"))).await;
+        send_packet_to_broker(&observer_socket, &mut channels, &create_subscribe_packet(1, String::from("$SYS/broker/#"), QoSLevel::AtMostOnce)).await;
+        let connect_packet = create_connect_packet(String::from("simulate_takeover_sys_event"));
+        send_packet_to_broker(&old_socket, &mut channels, &connect_packet).await;
+
+        let (_, disconnect_packet) = send_packet_to_broker(&new_socket, &mut channels, &connect_packet).await;
+        assert_eq!(disconnect_packet.variable_header().reason_code(), Some(&ReasonCode::SessionTakenOver));
+        let (res_observer_sockets, event_packet) = read_packet_from_broker(&mut channels).await;
+        assert_eq!(res_observer_sockets, vec![observer_socket]);
+        assert_eq!(event_packet.variable_header().topic_name(), &String::from("$SYS/broker/takeovers"));
+        let event: serde_json::Value = serde_json::from_slice(event_packet.payload().data()).unwrap();
+        assert_eq!(event["client_id"], "simulate_takeover_sys_event");
+        assert_eq!(event["previous_address"], old_socket.to_string());
+        assert_eq!(event["takeovers"], 1);
+        let (_, connack_packet) = read_packet_from_broker(&mut channels).await;
+        assert_eq!(connack_packet.fixed_header().packet_type(), ControlPacketType::CONNACK);
+    }
+
+    #[tokio::test]
+    async fn simulate_takeover_race_single_delivery() {
+        init_logging();
+        let old_socket = create_socket(0001);
+        let new_socket = create_socket(0002);
+        let publisher_socket = create_socket(0003);
+        let topic = String::from("test/takeover_race");
+        let mut channels = spinup_broker_with_config(takeover_config(TakeoverPolicy::KickOld)).await;
+
+        send_packet_to_broker(&publisher_socket, &mut channels, &create_connect_packet(String::from("simulate_takeover_race_tx"))).await;
+        let connect_packet = create_connect_packet(String::from("simulate_takeover_race_rx"));
+        send_packet_to_broker(&old_socket, &mut channels, &connect_packet).await;
+        send_packet_to_broker(&old_socket, &mut channels, &create_subscribe_packet(1, topic.clone(), QoSLevel::AtLeastOnce)).await;
+        send_packet_to_broker(&new_socket, &mut channels, &connect_packet).await;
+        read_packet_from_broker(&mut channels).await;
+        send_packet_to_broker(&new_socket, &mut channels, &create_subscribe_packet(1, topic.clone(), QoSLevel::AtLeastOnce)).await;
+        //Both sockets still map to the client until the old one is cleaned up
+        assert!(channels.client_handler.is_current(&new_socket));
+        assert!(!channels.client_handler.is_current(&old_socket));
+
+        //A late DISCONNECT from the old connection, then its socket cleanup races with a publish
+        let (res_old_sockets, disconnect_packet) = send_packet_to_broker(&old_socket, &mut channels, &create_disconnect_packet(ReasonCode::NormalDisconnection, vec![])).await;
+        assert_eq!(res_old_sockets, vec![old_socket]);
+        assert_eq!(disconnect_packet.fixed_header().packet_type(), ControlPacketType::DISCONNECT);
+        let client_handler = channels.client_handler.clone();
+        let cleanup = tokio::spawn(async move { client_handler.unregister_by_socket(&old_socket) });
+        let (_, puback_packet) = send_packet_to_broker(&publisher_socket, &mut channels, &create_publish_packet_qos1(1, topic)).await;
+        assert_eq!(puback_packet.fixed_header().packet_type(), ControlPacketType::PUBACK);
+        assert_eq!(cleanup.await.unwrap(), None);
+
+        let (res_rx_sockets, publish_packet) = read_packet_from_broker(&mut channels).await;
+        assert_eq!(res_rx_sockets, vec![new_socket]);
+        assert_eq!(publish_packet.fixed_header().packet_type(), ControlPacketType::PUBLISH);
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(100), channels.broker2listener_rx.recv()).await.is_err());
+        assert!(channels.client_handler.is_current(&new_socket));
+    }
+
+    #[tokio::test]
+    async fn simulate_takeover_reject_new() {
+        init_logging();
+        let old_socket = create_socket(0001);
+        let new_socket = create_socket(0002);
+        let mut channels = spinup_broker_with_config(takeover_config(TakeoverPolicy::RejectNew)).await;
+
+        let connect_packet = create_connect_packet(String::from("simulate_takeover_reject_new"));
+        send_packet_to_broker(&old_socket, &mut channels, &connect_packet).await;
+
+        let (res_new_sockets, connack_packet) = send_packet_to_broker(&new_socket, &mut channels, &connect_packet).await;
+        assert_eq!(res_new_sockets, vec![new_socket]);
+        assert_eq!(connack_packet.fixed_header().packet_type(), ControlPacketType::CONNACK);
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::ClientIdentifierNotValid));
+        //The refusing CONNACK closes the connection, no DISCONNECT follows it
+        assert!(tokio::time::timeout(std::time::Duration::from_millis(100), channels.broker2listener_rx.recv()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn simulate_takeover_allow_both_with_suffix() {
+        init_logging();
+        let old_socket = create_socket(0001);
+        let new_socket = create_socket(0002);
+        let client_id = String::from("simulate_takeover_allow_both_with_suffix");
+        let mut channels = spinup_broker_with_config(takeover_config(TakeoverPolicy::AllowBothWithSuffix)).await;
+
+        let connect_packet = create_connect_packet(client_id.clone());
+        send_packet_to_broker(&old_socket, &mut channels, &connect_packet).await;
+
+        let (res_new_sockets, connack_packet) = send_packet_to_broker(&new_socket, &mut channels, &connect_packet).await;
+        assert_eq!(res_new_sockets, vec![new_socket]);
+        assert_eq!(connack_packet.fixed_header().packet_type(), ControlPacketType::CONNACK);
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::Success));
+        match connack_packet.variable_header().properties().as_slice() {
+            [Property::AssignedClientIdentifier(assigned_client_id), Property::TopicAliasMaximum(16)] => {
+                assert_ne!(assigned_client_id, &client_id);
+                assert!(assigned_client_id.starts_with(&client_id));
+            }
+            properties => panic!("Expected AssignedClientIdentifier property. Found: {:?}", properties),
+        }
+    }
+
+    #[tokio::test]
+    async fn simulate_disconnect_session_expiry_override() {
+        init_logging();
+        let tx_socket = create_socket(0001);
+        let client_id = String::from("simulate_disconnect_session_expiry_override");
+        let mut channels = spinup_broker().await;
+
+        let connect_packet = create_connect_packet_with_properties(client_id.clone(), vec![Property::SessionExpiryInterval(30)]);
+        send_packet_to_broker(&tx_socket, &mut channels, &connect_packet).await;
+        assert_eq!(get_session_expiry_interval(&*channels.client_handler.sessions, &client_id), Some(30));
+
+        let disconnect_packet = create_disconnect_packet(ReasonCode::NormalDisconnection, vec![Property::SessionExpiryInterval(u32::MAX)]);
+        let (res_tx_sockets, disconnect_packet) = send_packet_to_broker(&tx_socket, &mut channels, &disconnect_packet).await;
+        assert_eq!(res_tx_sockets, vec![tx_socket]);
+        assert_eq!(disconnect_packet.variable_header().reason_code(), Some(&ReasonCode::NormalDisconnection));
+        assert_eq!(get_session_expiry_interval(&*channels.client_handler.sessions, &client_id), Some(u32::MAX));
+    }
+
+    #[tokio::test]
+    async fn simulate_disconnect_session_expiry_increase_from_zero() {
+        init_logging();
+        let tx_socket = create_socket(0001);
+        let client_id = String::from("simulate_disconnect_session_expiry_increase_from_zero");
+        let mut channels = spinup_broker().await;
+
+        let connect_packet = create_connect_packet(client_id.clone());
+        send_packet_to_broker(&tx_socket, &mut channels, &connect_packet).await;
+
+        let disconnect_packet = create_disconnect_packet(ReasonCode::NormalDisconnection, vec![Property::SessionExpiryInterval(30)]);
+        let (res_tx_sockets, disconnect_packet) = send_packet_to_broker(&tx_socket, &mut channels, &disconnect_packet).await;
+        assert_eq!(res_tx_sockets, vec![tx_socket]);
+        assert_eq!(disconnect_packet.variable_header().reason_code(), Some(&ReasonCode::ProtocolError));
+        assert_eq!(get_session_expiry_interval(&*channels.client_handler.sessions, &client_id), None);
+    }
+
+    #[tokio::test]
+    async fn simulate_publish_compressed_for_accepting_subscriber() {
+        init_logging();
+        let tx_socket = create_socket(0001);
+        let compressing_socket = create_socket(0002);
+        let plain_socket = create_socket(0003);
+        let topic = String::from("test/compression");
+        let mut config = BrokerConfig::default();
+        config.compression.enabled = true;
+        config.compression.minimum_size = 64;
+        let mut channels = spinup_broker_with_config(config).await;
+
+        send_packet_to_broker(&tx_socket, &mut channels, &create_connect_packet(String::from("compression_tx"))).await;
+        send_packet_to_broker(&compressing_socket, &mut channels, &create_connect_packet(String::from("compression_rx_deflate"))).await;
+        send_packet_to_broker(&plain_socket, &mut channels, &create_connect_packet(String::from("compression_rx_plain"))).await;
+        let accept_encoding = vec![Property::UserProperty(String::from(ACCEPT_ENCODING_PROPERTY), String::from("zstd, deflate"))];
+        send_packet_to_broker(&compressing_socket, &mut channels, &create_subscribe_packet_with_properties(1, topic.clone(), QoSLevel::AtMostOnce, accept_encoding)).await;
+        send_packet_to_broker(&plain_socket, &mut channels, &create_subscribe_packet(1, topic.clone(), QoSLevel::AtMostOnce)).await;
+
+        let data = vec![b'a'; 1024];
+        let publish_packet = ControlPacket::publish_with_payload(None, topic.clone(), QoSLevel::AtMostOnce, false, vec![], data.clone());
+        let (res_sockets, compressed_packet) = send_packet_to_broker(&tx_socket, &mut channels, &publish_packet).await;
+        assert_eq!(res_sockets, vec![compressing_socket]);
+        assert!(compressed_packet.payload().data().len() < data.len());
+        assert!(compressed_packet.variable_header().properties().contains(&Property::UserProperty(String::from(CONTENT_ENCODING_PROPERTY), String::from("deflate"))));
+
+        let (res_sockets, plain_packet) = read_packet_from_broker(&mut channels).await;
+        assert_eq!(res_sockets, vec![plain_socket]);
+        assert_eq!(plain_packet.payload().data(), &data);
+
+        let small_packet = ControlPacket::publish_with_payload(None, topic, QoSLevel::AtMostOnce, false, vec![], vec![b'a'; 16]);
+        let (mut res_sockets, _) = send_packet_to_broker(&tx_socket, &mut channels, &small_packet).await;
+        res_sockets.sort();
+        assert_eq!(res_sockets, vec![compressing_socket, plain_socket]);
+    }
+
+    #[tokio::test]
+    async fn simulate_publish_receive_timestamp() {
+        init_logging();
+        let tx_socket = create_socket(0001);
+        let rx_socket = create_socket(0002);
+        let mut config = BrokerConfig::default();
+        config.receive_timestamp.enabled = true;
+        config.receive_timestamp.topics = vec![String::from("sensors/")];
+        let property_name = config.receive_timestamp.property_name.clone();
+        let mut channels = spinup_broker_with_config(config).await;
+
+        send_packet_to_broker(&tx_socket, &mut channels, &create_connect_packet(String::from("timestamp_tx"))).await;
+        send_packet_to_broker(&rx_socket, &mut channels, &create_connect_packet(String::from("timestamp_rx"))).await;
+        send_packet_to_broker(&rx_socket, &mut channels, &create_subscribe_packet(1, String::from("sensors/temperature"), QoSLevel::AtMostOnce)).await;
+        send_packet_to_broker(&rx_socket, &mut channels, &create_subscribe_packet(2, String::from("actuators/valve"), QoSLevel::AtMostOnce)).await;
+
+        //A timestamp set by the publisher is replaced
+        let spoofed = vec![Property::UserProperty(property_name.clone(), String::from("0"))];
+        let publish_packet = ControlPacket::publish_with_payload(None, String::from("sensors/temperature"), QoSLevel::AtMostOnce, false, spoofed, vec![1]);
+        let (_, stamped_packet) = send_packet_to_broker(&tx_socket, &mut channels, &publish_packet).await;
+        let timestamps: Vec<&String> = stamped_packet.variable_header().properties().iter().filter_map(|property| match property {
+            Property::UserProperty(key, value) if key.eq(&property_name) => Some(value),
+            _ => None
+        }).collect();
+        assert_eq!(timestamps.len(), 1);
+        assert!(timestamps[0].parse::<i64>().unwrap() > 0);
+
+        let publish_packet = ControlPacket::publish_with_payload(None, String::from("actuators/valve"), QoSLevel::AtMostOnce, false, vec![], vec![1]);
+        let (_, plain_packet) = send_packet_to_broker(&tx_socket, &mut channels, &publish_packet).await;
+        assert!(plain_packet.variable_header().properties().is_empty());
+    }
+
+    #[tokio::test]
+    async fn simulate_publish_default_message_expiry() {
+        init_logging();
+        let tx_socket = create_socket(0001);
+        let rx_socket = create_socket(0002);
+        let mut config = BrokerConfig::default();
+        config.message_expiry.defaults.insert(String::from("telemetry/"), 60);
+        let mut channels = spinup_broker_with_config(config).await;
+
+        send_packet_to_broker(&tx_socket, &mut channels, &create_connect_packet(String::from("expiry_tx"))).await;
+        send_packet_to_broker(&rx_socket, &mut channels, &create_connect_packet(String::from("expiry_rx"))).await;
+        send_packet_to_broker(&rx_socket, &mut channels, &create_subscribe_packet(1, String::from("telemetry/temperature"), QoSLevel::AtMostOnce)).await;
+        send_packet_to_broker(&rx_socket, &mut channels, &create_subscribe_packet(2, String::from("commands/valve"), QoSLevel::AtMostOnce)).await;
+
+        let publish_packet = ControlPacket::publish_with_payload(None, String::from("telemetry/temperature"), QoSLevel::AtMostOnce, false, vec![], vec![1]);
+        let (_, forwarded_packet) = send_packet_to_broker(&tx_socket, &mut channels, &publish_packet).await;
+        assert_eq!(forwarded_packet.variable_header().properties(), &vec![Property::MessageExpiryInterval(60)]);
+
+        //The publisher's own interval is kept
+        let publish_packet = ControlPacket::publish_with_payload(None, String::from("telemetry/temperature"), QoSLevel::AtMostOnce, false, vec![Property::MessageExpiryInterval(5)], vec![1]);
+        let (_, forwarded_packet) = send_packet_to_broker(&tx_socket, &mut channels, &publish_packet).await;
+        assert_eq!(forwarded_packet.variable_header().properties(), &vec![Property::MessageExpiryInterval(5)]);
+
+        let publish_packet = ControlPacket::publish_with_payload(None, String::from("commands/valve"), QoSLevel::AtMostOnce, false, vec![], vec![1]);
+        let (_, forwarded_packet) = send_packet_to_broker(&tx_socket, &mut channels, &publish_packet).await;
+        assert!(forwarded_packet.variable_header().properties().is_empty());
+    }
+
+    #[tokio::test]
+    async fn simulate_quota_profile() {
+        init_logging();
+        let tx_socket = create_socket(0001);
+        let mut config = BrokerConfig::default();
+        let profile = QuotaProfile { max_inflight: Some(5), max_payload: Some(8), publish_rate: Some(2), max_subscriptions: Some(1), ..QuotaProfile::default() };
+        config.quota.profiles.insert(String::from("constrained"), profile);
+        config.quota.usernames.insert(String::from("fleet"), String::from("constrained"));
+        let mut channels = spinup_broker_with_config(config).await;
+
+        let (_, connack_packet) = send_packet_to_broker(&tx_socket, &mut channels, &create_connect_packet_with_username(String::from("quota_tx"), String::from("fleet"))).await;
+        assert!(connack_packet.variable_header().properties().contains(&Property::ReceiveMaximum(5)));
+
+        let (_, suback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &create_subscribe_packet(1, String::from("test/quota/a"), QoSLevel::AtMostOnce)).await;
+        assert_eq!(suback_packet.payload().reason_codes(), &vec![ReasonCode::GrantedQoS0]);
+        let (_, suback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &create_subscribe_packet(2, String::from("test/quota/b"), QoSLevel::AtMostOnce)).await;
+        assert_eq!(suback_packet.payload().reason_codes(), &vec![ReasonCode::QuotaExceeded]);
+
+        let topic = String::from("test/quota/unsubscribed");
+        let oversized_packet = ControlPacket::publish_with_payload(Some(1), topic.clone(), QoSLevel::AtLeastOnce, false, vec![], vec![0; 16]);
+        let (_, puback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &oversized_packet).await;
+        assert_eq!(puback_packet.variable_header().reason_code(), Some(&ReasonCode::QuotaExceeded));
+
+        //Two publishes per second
+        for packet_identifier in 2..4 {
+            let publish_packet = ControlPacket::publish_with_payload(Some(packet_identifier), topic.clone(), QoSLevel::AtLeastOnce, false, vec![], vec![0; 8]);
+            let (_, puback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &publish_packet).await;
+            assert_eq!(puback_packet.variable_header().reason_code(), Some(&ReasonCode::Success));
+        }
+        let publish_packet = ControlPacket::publish_with_payload(Some(4), topic, QoSLevel::AtLeastOnce, false, vec![], vec![0; 8]);
+        let (_, puback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &publish_packet).await;
+        assert_eq!(puback_packet.variable_header().reason_code(), Some(&ReasonCode::QuotaExceeded));
+    }
+
+    async fn publish_to_overlapping_subscriptions(overlap_policy: OverlapPolicy) -> Vec<ControlPacket> {
+        let tx_socket = create_socket(0001);
+        let rx_socket = create_socket(0002);
+        let mut config = BrokerConfig::default();
+        config.subscription.overlap_policy = overlap_policy;
+        let mut channels = spinup_broker_with_config(config).await;
+
+        send_packet_to_broker(&tx_socket, &mut channels, &create_connect_packet(String::from("overlap_tx"))).await;
+        send_packet_to_broker(&rx_socket, &mut channels, &create_connect_packet(String::from("overlap_rx"))).await;
+        let wildcard_subscription = create_subscribe_packet_with_properties(1, String::from("test/overlap/#"), QoSLevel::AtLeastOnce, vec![Property::SubscriptionIdentifier(1)]);
+        let (_, suback_packet) = send_packet_to_broker(&rx_socket, &mut channels, &wildcard_subscription).await;
+        assert_eq!(suback_packet.payload().reason_codes(), &vec![ReasonCode::GrantedQoS1]);
+        let literal_subscription = create_subscribe_packet_with_properties(2, String::from("test/overlap/a"), QoSLevel::AtMostOnce, vec![Property::SubscriptionIdentifier(2)]);
+        send_packet_to_broker(&rx_socket, &mut channels, &literal_subscription).await;
+
+        let (_, puback_packet) = send_packet_to_broker(&tx_socket, &mut channels, &create_publish_packet_qos1(7, String::from("test/overlap/a"))).await;
+        assert_eq!(puback_packet.fixed_header().packet_type(), ControlPacketType::PUBACK);
+        let mut publish_packets = vec![];
+        while let Ok(Some((sockets, publish_packet))) = tokio::time::timeout(std::time::Duration::from_millis(100), channels.broker2listener_rx.recv()).await {
+            assert_eq!(sockets, vec![rx_socket]);
+            publish_packets.push(publish_packet);
+        }
+        publish_packets
+    }
+
+    #[tokio::test]
+    async fn simulate_overlapping_subscriptions_once() {
+        init_logging();
+        let publish_packets = publish_to_overlapping_subscriptions(OverlapPolicy::Once).await;
+        assert_eq!(publish_packets.len(), 1);
+        assert_eq!(publish_packets[0].fixed_header().qos_level(), &QoSLevel::AtLeastOnce);
+        let properties = publish_packets[0].variable_header().properties();
+        assert!(properties.contains(&Property::SubscriptionIdentifier(1)));
+        assert!(properties.contains(&Property::SubscriptionIdentifier(2)));
+    }
+
+    #[tokio::test]
+    async fn simulate_overlapping_subscriptions_per_subscription() {
+        init_logging();
+        let mut publish_packets = publish_to_overlapping_subscriptions(OverlapPolicy::PerSubscription).await;
+        assert_eq!(publish_packets.len(), 2);
+        publish_packets.sort_by_key(|publish_packet| publish_packet.fixed_header().qos_level().as_u8());
+        assert_eq!(publish_packets[0].fixed_header().qos_level(), &QoSLevel::AtMostOnce);
+        assert_eq!(publish_packets[0].variable_header().packet_identifier_opt(), None);
+        assert_eq!(publish_packets[0].variable_header().properties(), &vec![Property::SubscriptionIdentifier(2)]);
+        assert_eq!(publish_packets[1].fixed_header().qos_level(), &QoSLevel::AtLeastOnce);
+        assert_eq!(publish_packets[1].variable_header().properties(), &vec![Property::SubscriptionIdentifier(1)]);
+    }
+}
@@ -1,12 +1,20 @@
 use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::{ControlPacketType, FixedHeader};
+use crate::model::payload::Payload;
 use crate::model::qos_level::QoSLevel;
-use crate::model::variable_header::ConnectFlags;
+use crate::model::reason_code::ReasonCode;
+use crate::model::topic::{RetainHandling, TopicFilter};
+use crate::model::variable_header::{ConnectFlags, Property, VariableHeader};
 
 pub fn create_connect_packet(client_id: String) -> ControlPacket {
+    create_connect_packet_with_properties(client_id, vec![])
+}
+
+pub fn create_connect_packet_with_properties(client_id: String, properties: Vec<Property>) -> ControlPacket {
     ControlPacket::connect(
         ConnectFlags::new(false, false, false, QoSLevel::AtMostOnce, false, true, false),
         None,
-        vec![],
+        properties,
         Some(client_id),
         None,
         None,
@@ -15,6 +23,45 @@ pub fn create_connect_packet(client_id: String) -> ControlPacket {
         None)
 }
 
+pub fn create_connect_packet_resuming_session(client_id: String) -> ControlPacket {
+    ControlPacket::connect(
+        ConnectFlags::new(false, false, false, QoSLevel::AtMostOnce, false, false, false),
+        None,
+        vec![Property::SessionExpiryInterval(300)],
+        Some(client_id),
+        None,
+        None,
+        None,
+        None,
+        None)
+}
+
+pub fn create_connect_packet_with_username(client_id: String, username: String) -> ControlPacket {
+    ControlPacket::connect(
+        ConnectFlags::new(true, false, false, QoSLevel::AtMostOnce, false, true, false),
+        None,
+        vec![],
+        Some(client_id),
+        None,
+        None,
+        None,
+        Some(username),
+        None)
+}
+
+pub fn create_connect_packet_with_credentials(client_id: String, username: String, password: String) -> ControlPacket {
+    ControlPacket::connect(
+        ConnectFlags::new(true, true, false, QoSLevel::AtMostOnce, false, true, false),
+        None,
+        vec![],
+        Some(client_id),
+        None,
+        None,
+        None,
+        Some(username),
+        Some(password))
+}
+
 pub fn create_subscribe_packet(packet_identifier: u16, topic_filter: String, maximum_qos: QoSLevel) -> ControlPacket {
     ControlPacket::subscribe(
         Some(packet_identifier),
@@ -22,6 +69,41 @@ pub fn create_subscribe_packet(packet_identifier: u16, topic_filter: String, max
         maximum_qos)
 }
 
+pub fn create_subscribe_packet_with_properties(packet_identifier: u16, topic_filter: String, maximum_qos: QoSLevel, properties: Vec<Property>) -> ControlPacket {
+    let topic_filter = TopicFilter::from_subscribe(topic_filter, maximum_qos, false, false, RetainHandling::DontSendRetainedMessages, vec![]);
+    let fixed_header = FixedHeader::new(ControlPacketType::SUBSCRIBE, vec![false, false, true, false], 0);
+    let variable_header = VariableHeader::from_sub_unsub(Some(packet_identifier), properties);
+    ControlPacket::new(fixed_header, Some(variable_header), Some(Payload::from_sub_unsub(vec![topic_filter])))
+}
+
+pub fn create_subscribe_packet_with_retain_handling(packet_identifier: u16, topic_filter: String, maximum_qos: QoSLevel, retain_handling: RetainHandling) -> ControlPacket {
+    let topic_filter = TopicFilter::from_subscribe(topic_filter, maximum_qos, false, false, retain_handling, vec![]);
+    let fixed_header = FixedHeader::new(ControlPacketType::SUBSCRIBE, vec![false, false, true, false], 0);
+    let variable_header = VariableHeader::from_sub_unsub(Some(packet_identifier), vec![]);
+    ControlPacket::new(fixed_header, Some(variable_header), Some(Payload::from_sub_unsub(vec![topic_filter])))
+}
+
+pub fn create_subscribe_packet_with_no_local(packet_identifier: u16, topic_filter: String, maximum_qos: QoSLevel, no_local: bool) -> ControlPacket {
+    let topic_filter = TopicFilter::from_subscribe(topic_filter, maximum_qos, no_local, false, RetainHandling::DontSendRetainedMessages, vec![]);
+    let fixed_header = FixedHeader::new(ControlPacketType::SUBSCRIBE, vec![false, false, true, false], 0);
+    let variable_header = VariableHeader::from_sub_unsub(Some(packet_identifier), vec![]);
+    ControlPacket::new(fixed_header, Some(variable_header), Some(Payload::from_sub_unsub(vec![topic_filter])))
+}
+
+pub fn create_subscribe_packet_with_retain_as_published(packet_identifier: u16, topic_filter: String, maximum_qos: QoSLevel, retain_as_published: bool) -> ControlPacket {
+    let topic_filter = TopicFilter::from_subscribe(topic_filter, maximum_qos, false, retain_as_published, RetainHandling::DontSendRetainedMessages, vec![]);
+    let fixed_header = FixedHeader::new(ControlPacketType::SUBSCRIBE, vec![false, false, true, false], 0);
+    let variable_header = VariableHeader::from_sub_unsub(Some(packet_identifier), vec![]);
+    ControlPacket::new(fixed_header, Some(variable_header), Some(Payload::from_sub_unsub(vec![topic_filter])))
+}
+
+pub fn create_unsubscribe_packet(packet_identifier: u16, topic_filters: Vec<String>) -> ControlPacket {
+    let topic_filters = topic_filters.into_iter().map(TopicFilter::from_unsubscribe).collect();
+    let fixed_header = FixedHeader::new(ControlPacketType::UNSUBSCRIBE, vec![false, false, true, false], 0);
+    let variable_header = VariableHeader::from_sub_unsub(Some(packet_identifier), vec![]);
+    ControlPacket::new(fixed_header, Some(variable_header), Some(Payload::from_sub_unsub(topic_filters)))
+}
+
 pub fn create_publish_packet_qos0(packet_identifier: u16, topic_name: String) -> ControlPacket {
     ControlPacket::publish(
         Some(packet_identifier),
@@ -40,4 +122,10 @@ pub fn create_publish_packet_qos1(packet_identifier: u16, topic_name: String) ->
         QoSLevel::AtLeastOnce,
         false,
     )
-}
\ No newline at end of file
+}
+
+pub fn create_disconnect_packet(reason_code: ReasonCode, properties: Vec<Property>) -> ControlPacket {
+    let fixed_header = FixedHeader::new(ControlPacketType::DISCONNECT, vec![false, false, false, false], 0);
+    let variable_header = VariableHeader::from_disconnect(reason_code, properties);
+    ControlPacket::new(fixed_header, Some(variable_header), None)
+}
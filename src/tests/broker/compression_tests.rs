@@ -0,0 +1,22 @@
+#[cfg(test)]
+mod compression_tests {
+    use crate::broker::compression::{compress_publish, ContentEncoding, CONTENT_ENCODING_PROPERTY};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::variable_header::Property;
+
+    #[test]
+    fn compressed_copy_has_no_payload_format_indicator() {
+        let properties = vec![Property::PayloadFormatIndicator(1), Property::ContentType(String::from("application/json"))];
+        let data = "{\"temperature\": 21}".repeat(20).into_bytes();
+        let publish_packet = ControlPacket::publish_with_payload(None, String::from("sensors/1"), QoSLevel::AtMostOnce, false, properties.clone(), data.clone());
+
+        let compressed_packet = compress_publish(&publish_packet, ContentEncoding::Deflate, 64).unwrap();
+        assert!(compressed_packet.payload().data().len() < data.len());
+        assert_eq!(compressed_packet.variable_header().properties(), &vec![
+            Property::ContentType(String::from("application/json")),
+            Property::UserProperty(String::from(CONTENT_ENCODING_PROPERTY), String::from("deflate")),
+        ]);
+        assert_eq!(publish_packet.variable_header().properties(), &properties);
+    }
+}
@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod delivery_report_tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use tokio::sync::mpsc::Receiver;
+
+    use crate::{ClientHandler, TopicHandler};
+    use crate::broker::delivery_report::SYS_DELIVERY_TOPIC;
+    use crate::broker::packet_dispatcher::PacketDispatcher;
+    use crate::config::broker_config::BrokerConfig;
+    use crate::connection::client_context::ClientContext;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet, create_publish_packet_qos0, create_subscribe_packet};
+
+    const MONITOR: &str = "127.0.0.1:42001";
+    const SUBSCRIBER: &str = "127.0.0.1:42002";
+    const PUBLISHER: &str = "127.0.0.1:42003";
+
+    //A monitor of the reports, a subscriber of sensors/# and a publisher, with their CONNACK and SUBACK still queued
+    async fn create_packet_dispatcher(config: BrokerConfig) -> (PacketDispatcher, Receiver<(Vec<SocketAddr>, ControlPacket)>) {
+        let (to_listener, from_broker) = tokio::sync::mpsc::channel(32);
+        let packet_dispatcher = PacketDispatcher::new(Arc::new(config), Arc::new(ClientHandler::default()), Arc::new(TopicHandler::default()), Arc::new(to_listener));
+        for (socket, client_id, topic_filter) in [(MONITOR, "delivery-monitor", Some(SYS_DELIVERY_TOPIC)), (SUBSCRIBER, "delivery-subscriber", Some("sensors/#")), (PUBLISHER, "delivery-publisher", None)] {
+            let socket: SocketAddr = socket.parse().unwrap();
+            packet_dispatcher.process_message(ClientContext::new(socket), create_connect_packet(String::from(client_id))).await.unwrap();
+            if let Some(topic_filter) = topic_filter {
+                packet_dispatcher.process_message(ClientContext::new(socket), create_subscribe_packet(1, String::from(topic_filter), QoSLevel::AtMostOnce)).await.unwrap();
+            }
+        }
+        (packet_dispatcher, from_broker)
+    }
+
+    fn reporting_config() -> BrokerConfig {
+        let mut config = BrokerConfig::default();
+        config.delivery_report.enabled = true;
+        config.delivery_report.topics = vec![String::from("sensors/")];
+        config
+    }
+
+    fn drain(from_broker: &mut Receiver<(Vec<SocketAddr>, ControlPacket)>) -> Vec<(Vec<SocketAddr>, ControlPacket)> {
+        let mut packets = vec![];
+        while let Ok(packet) = from_broker.try_recv() {
+            packets.push(packet);
+        }
+        packets
+    }
+
+    fn published_to(packet: &ControlPacket, topic_name: &str) -> bool {
+        packet.fixed_header().packet_type() == ControlPacketType::PUBLISH && packet.variable_header().topic_name() == topic_name
+    }
+
+    fn delivery_reports(packets: &[(Vec<SocketAddr>, ControlPacket)]) -> Vec<serde_json::Value> {
+        packets.iter()
+            .filter(|(_, packet)| published_to(packet, SYS_DELIVERY_TOPIC))
+            .map(|(sockets, packet)| {
+                assert_eq!(sockets, &vec![MONITOR.parse::<SocketAddr>().unwrap()]);
+                serde_json::from_slice(packet.payload().data()).unwrap()
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn report_deliveries_of_selected_topics() {
+        let (packet_dispatcher, mut from_broker) = create_packet_dispatcher(reporting_config()).await;
+        drain(&mut from_broker);
+        let publisher: SocketAddr = PUBLISHER.parse().unwrap();
+
+        packet_dispatcher.process_message(ClientContext::new(publisher), create_publish_packet_qos0(1, String::from("sensors/1/temp"))).await.unwrap();
+        let reports = delivery_reports(&drain(&mut from_broker));
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0]["topic"], "sensors/1/temp");
+        assert_eq!(reports[0]["qos"], 0);
+        assert_eq!(reports[0]["publisher"], "delivery-publisher");
+        assert_eq!(reports[0]["subscribers"], 1);
+        assert_eq!(reports[0]["dropped"], 0);
+        assert_eq!(reports[0]["message_id"].as_str().unwrap().len(), 16);
+
+        packet_dispatcher.process_message(ClientContext::new(publisher), create_publish_packet_qos0(2, String::from("actuators/1"))).await.unwrap();
+        assert!(delivery_reports(&drain(&mut from_broker)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn report_shed_deliveries() {
+        let mut config = reporting_config();
+        config.congestion.queue_threshold = 1;
+        //The queued CONNACK and SUBACK packets congest the writers
+        let (packet_dispatcher, mut from_broker) = create_packet_dispatcher(config).await;
+
+        packet_dispatcher.process_message(ClientContext::new(PUBLISHER.parse().unwrap()), create_publish_packet_qos0(1, String::from("sensors/1/temp"))).await.unwrap();
+        let packets = drain(&mut from_broker);
+        let reports = delivery_reports(&packets);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0]["subscribers"], 1);
+        assert_eq!(reports[0]["dropped"], 1);
+        assert!(!packets.iter().any(|(_, packet)| published_to(packet, "sensors/1/temp")));
+    }
+}
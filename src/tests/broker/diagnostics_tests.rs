@@ -0,0 +1,38 @@
+#[cfg(test)]
+mod diagnostics_tests {
+    use crate::broker::diagnostics::{Diagnostics, ProcessDiagnostics};
+    use crate::config::broker_config::DiagnosticsConfig;
+    use crate::model::qos_level::QoSLevel;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    #[test]
+    fn parse_process_status() {
+        let status = "Name:\tpatina\nVmHWM:\t   20480 kB\nVmRSS:\t   10240 kB\nThreads:\t12\n";
+        assert_eq!(ProcessDiagnostics::parse(status), ProcessDiagnostics { resident_bytes: Some(10485760), peak_resident_bytes: Some(20971520), threads: Some(12) });
+        assert_eq!(ProcessDiagnostics::parse(""), ProcessDiagnostics { resident_bytes: None, peak_resident_bytes: None, threads: None });
+    }
+
+    #[tokio::test]
+    async fn dump_connected_clients() {
+        let mut harness = HandlerHarness::default();
+        let sensor = harness.connect("diagnostics-sensor").await;
+        harness.subscribe(sensor, "diagnostics/#", QoSLevel::AtLeastOnce).await;
+        harness.connect("diagnostics-gateway").await;
+        let directory = std::env::temp_dir().join(format!("patina-diagnostics-{}", std::process::id()));
+        let config = DiagnosticsConfig { directory: directory.display().to_string(), max_clients: 1 };
+        let diagnostics = Diagnostics::new(config, harness.packet_dispatcher.clone());
+
+        let dump = diagnostics.dump().unwrap();
+        assert!(dump.path.starts_with(&directory.display().to_string()));
+        let written: serde_json::Value = serde_json::from_slice(&std::fs::read(&dump.path).unwrap()).unwrap();
+        assert_eq!(written["connected_clients"], 2);
+        assert_eq!(written["subscription_tree"]["filters"], 1);
+        //Only max_clients are listed, by client_id
+        let clients = written["clients"].as_array().unwrap();
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0]["client_id"], "diagnostics-gateway");
+        assert_eq!(clients[0]["subscriptions"], 0);
+        assert_eq!(dump.snapshot.clients[0].client_id, "diagnostics-gateway");
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::Duration;
+
+use tokio::sync::mpsc::Receiver;
+
+use crate::{ClientHandler, TopicHandler};
+use crate::broker::packet_dispatcher::PacketDispatcher;
+use crate::config::broker_config::BrokerConfig;
+use crate::connection::client_context::ClientContext;
+use crate::error::PatinaResult;
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::qos_level::QoSLevel;
+use crate::tests::broker::broker_tests_data::{create_connect_packet, create_subscribe_packet};
+
+const CHANNEL_CAPACITY: usize = 256;
+const RECV_TIMEOUT: Duration = Duration::from_secs(2);
+
+//Sessions live in process wide maps, every harness hands out sockets no other test uses
+static NEXT_PORT: AtomicU16 = AtomicU16::new(50000);
+
+//A PacketDispatcher whose listener side is an in-memory channel, to drive one handler at a time
+//without sockets or a running broker. Timers follow the tokio clock, a #[tokio::test(start_paused = true)]
+//test makes them deterministic: the clock only moves when every task waits on it.
+pub struct HandlerHarness {
+    pub packet_dispatcher: Arc<PacketDispatcher>,
+    pub client_handler: Arc<ClientHandler>,
+    pub topic_handler: Arc<TopicHandler>,
+    from_broker: Receiver<(Vec<SocketAddr>, ControlPacket)>,
+    //What the reader of each connection knows about its client
+    contexts: Mutex<HashMap<SocketAddr, ClientContext>>,
+}
+
+impl HandlerHarness {
+    pub fn socket() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], NEXT_PORT.fetch_add(1, Ordering::Relaxed)))
+    }
+
+    //The whole dispatch path, quarantine aside
+    pub async fn send(&self, socket: SocketAddr, control_packet: ControlPacket) -> PatinaResult<()> {
+        let context = {
+            let mut contexts = self.contexts.lock().unwrap();
+            let context = contexts.entry(socket).or_insert_with(|| ClientContext::new(socket));
+            if control_packet.fixed_header().packet_type() == ControlPacketType::CONNECT {
+                context.connected(&control_packet);
+            }
+            context.next()
+        };
+        self.packet_dispatcher.process_message(context, control_packet).await
+    }
+
+    //The context the dispatcher hands to a handler, to call one directly
+    pub fn context(&self, socket: SocketAddr) -> ClientContext {
+        ClientContext::new(socket).with_client_id(self.client_handler.get_client_id(&socket).ok())
+    }
+
+    //Connects a client on a fresh socket and consumes its CONNACK
+    pub async fn connect(&mut self, client_id: &str) -> SocketAddr {
+        let socket = Self::socket();
+        self.send(socket, create_connect_packet(client_id.to_string())).await.unwrap();
+        self.expect(ControlPacketType::CONNACK).await;
+        socket
+    }
+
+    //Subscribes and consumes the SUBACK
+    pub async fn subscribe(&mut self, socket: SocketAddr, topic_filter: &str, maximum_qos: QoSLevel) {
+        self.send(socket, create_subscribe_packet(1, topic_filter.to_string(), maximum_qos)).await.unwrap();
+        self.expect(ControlPacketType::SUBACK).await;
+    }
+
+    //Next packet sent to the listener, which must be of the given type
+    pub async fn expect(&mut self, packet_type: ControlPacketType) -> (Vec<SocketAddr>, ControlPacket) {
+        let (sockets, control_packet) = tokio::time::timeout(RECV_TIMEOUT, self.from_broker.recv()).await
+            .unwrap_or_else(|_| panic!("no {:?} sent within {:?}", packet_type, RECV_TIMEOUT))
+            .expect("to_listener closed");
+        assert_eq!(control_packet.fixed_header().packet_type(), packet_type, "unexpected packet {:?}", control_packet);
+        (sockets, control_packet)
+    }
+
+    pub fn expect_nothing(&mut self) {
+        if let Ok((_, control_packet)) = self.from_broker.try_recv() {
+            panic!("unexpected packet {:?}", control_packet);
+        }
+    }
+
+    pub fn drain(&mut self) -> Vec<(Vec<SocketAddr>, ControlPacket)> {
+        let mut packets = vec![];
+        while let Ok(packet) = self.from_broker.try_recv() {
+            packets.push(packet);
+        }
+        packets
+    }
+
+    pub fn new(config: BrokerConfig) -> Self {
+        Self::with_client_handler(config, Arc::new(ClientHandler::default()))
+    }
+
+    pub fn with_client_handler(config: BrokerConfig, client_handler: Arc<ClientHandler>) -> Self {
+        let (to_listener, from_broker) = tokio::sync::mpsc::channel(CHANNEL_CAPACITY);
+        let topic_handler = Arc::new(TopicHandler::default());
+        let packet_dispatcher = Arc::new(PacketDispatcher::new(Arc::new(config), client_handler.clone(), topic_handler.clone(), Arc::new(to_listener)));
+        Self { packet_dispatcher, client_handler, topic_handler, from_broker, contexts: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl Default for HandlerHarness {
+    fn default() -> Self {
+        Self::new(BrokerConfig::default())
+    }
+}
@@ -0,0 +1,126 @@
+#[cfg(test)]
+mod handler_tests {
+    use std::time::Duration;
+
+    use crate::error::PatinaError;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::topic::RetainHandling;
+    use crate::model::variable_header::Property;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet, create_publish_packet_qos1, create_subscribe_packet_with_retain_handling, create_unsubscribe_packet};
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    #[tokio::test]
+    async fn pingreq_handler_answers_pingresp() {
+        let mut harness = HandlerHarness::default();
+        let socket = harness.connect("harness-pinger").await;
+        assert_eq!(harness.client_handler.get_client_id(&socket).unwrap(), "harness-pinger");
+        harness.packet_dispatcher.pingreq_handler.process(&harness.context(socket), &ControlPacket::pingreq()).await.unwrap();
+        let (sockets, _) = harness.expect(ControlPacketType::PINGRESP).await;
+        assert_eq!(sockets, vec![socket]);
+        harness.expect_nothing();
+    }
+
+    #[tokio::test]
+    async fn connect_handler_tells_assigned_client_id() {
+        let mut harness = HandlerHarness::default();
+        let socket = HandlerHarness::socket();
+        harness.send(socket, create_connect_packet(String::new())).await.unwrap();
+        let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        let assigned_client_id = match connack_packet.variable_header().properties().first() {
+            Some(Property::AssignedClientIdentifier(result)) => { result.clone() }
+            properties => panic!("Expected AssignedClientIdentifier property. Found: {:?}", properties),
+        };
+        assert_eq!(harness.client_handler.get_socket(&assigned_client_id), Ok(socket));
+
+        //A client that chose its client_id isn't told
+        let socket = HandlerHarness::socket();
+        harness.send(socket, create_connect_packet(String::from("harness-named"))).await.unwrap();
+        let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert!(!connack_packet.variable_header().properties().iter().any(|property| matches!(property, Property::AssignedClientIdentifier(_))));
+    }
+
+    #[tokio::test]
+    async fn handlers_reject_unknown_socket() {
+        let mut harness = HandlerHarness::default();
+        let socket = HandlerHarness::socket();
+        let result = harness.packet_dispatcher.pingreq_handler.process(&harness.context(socket), &ControlPacket::pingreq()).await;
+        assert_eq!(result, Err(PatinaError::UnknownClient(socket)));
+        harness.expect_nothing();
+    }
+
+    #[tokio::test]
+    async fn pubrel_handler_answers_pubcomp() {
+        let mut harness = HandlerHarness::default();
+        let socket = harness.connect("harness-pubrel").await;
+        harness.packet_dispatcher.pubrel_handler.process(&harness.context(socket), &ControlPacket::pubrel(Some(7))).await.unwrap();
+        let (_, pubcomp_packet) = harness.expect(ControlPacketType::PUBCOMP).await;
+        assert_eq!(pubcomp_packet.variable_header().packet_identifier_opt(), Some(7));
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_handler_reports_missing_subscription() {
+        let mut harness = HandlerHarness::default();
+        let socket = harness.connect("harness-unsubscriber").await;
+        harness.subscribe(socket, "harness/a", QoSLevel::AtMostOnce).await;
+        let unsubscribe_packet = create_unsubscribe_packet(2, vec![String::from("harness/a"), String::from("harness/b")]);
+        harness.packet_dispatcher.unsubscribe_handler.process(&harness.context(socket), &unsubscribe_packet).await.unwrap();
+        let (_, unsuback_packet) = harness.expect(ControlPacketType::UNSUBACK).await;
+        assert_eq!(unsuback_packet.payload().reason_codes(), &vec![ReasonCode::Success, ReasonCode::NoSubscriptionExisted]);
+    }
+
+    #[tokio::test]
+    async fn publish_handler_forwards_and_acknowledges() {
+        let mut harness = HandlerHarness::default();
+        let subscriber = harness.connect("harness-subscriber").await;
+        harness.subscribe(subscriber, "harness/forwarded", QoSLevel::AtLeastOnce).await;
+        let publisher = harness.connect("harness-publisher").await;
+        harness.send(publisher, create_publish_packet_qos1(3, String::from("harness/forwarded"))).await.unwrap();
+
+        let packets = harness.drain();
+        assert!(packets.iter().any(|(sockets, packet)| sockets == &vec![subscriber] && packet.fixed_header().packet_type() == ControlPacketType::PUBLISH));
+        assert!(packets.iter().any(|(sockets, packet)| sockets == &vec![publisher] && packet.fixed_header().packet_type() == ControlPacketType::PUBACK));
+    }
+
+    //A second per message on a paused clock, done without waiting
+    #[tokio::test(start_paused = true)]
+    async fn retained_pace_follows_paused_clock() {
+        let mut config = crate::config::broker_config::BrokerConfig::default();
+        config.retained_delivery.messages_per_sec = 1;
+        let mut harness = HandlerHarness::new(config);
+        for topic_name in ["harness/retained/1", "harness/retained/2", "harness/retained/3"] {
+            let publish_packet = ControlPacket::publish_with_payload(Some(1), topic_name.to_string(), QoSLevel::AtMostOnce, true, vec![], vec![1]);
+            harness.topic_handler.retain_message(&String::from("publisher"), &publish_packet, std::time::Instant::now());
+        }
+        let socket = harness.connect("harness-paced").await;
+        harness.send(socket, create_subscribe_packet_with_retain_handling(1, String::from("harness/retained/#"), QoSLevel::AtMostOnce, RetainHandling::SendRetainedMessagesOnSubscribe)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+
+        let started = tokio::time::Instant::now();
+        for _ in 0..3 {
+            harness.expect(ControlPacketType::PUBLISH).await;
+        }
+        assert_eq!(started.elapsed().as_secs(), 2);
+        tokio::time::sleep(Duration::from_secs(5)).await;
+        harness.expect_nothing();
+    }
+
+    #[tokio::test]
+    async fn publish_handler_acks_unrouted_publish_only() {
+        let mut harness = HandlerHarness::default();
+        let publisher = harness.connect("harness-unrouted").await;
+        harness.send(publisher, create_publish_packet_qos1(1, String::from("unrouted/burst"))).await.unwrap();
+        harness.expect(ControlPacketType::PUBACK).await;
+        harness.expect_nothing();
+        assert_eq!(harness.packet_dispatcher.publish_handler.routing_metrics.unrouted.0.get(), 1);
+
+        //A retained message is kept for later subscribers
+        let retained_packet = ControlPacket::publish_with_payload(Some(2), String::from("unrouted/retained"), QoSLevel::AtLeastOnce, true, vec![], b"on".to_vec());
+        harness.send(publisher, retained_packet).await.unwrap();
+        harness.expect(ControlPacketType::PUBACK).await;
+        assert_eq!(harness.packet_dispatcher.publish_handler.routing_metrics.unrouted.0.get(), 1);
+        assert!(harness.topic_handler.retained_message(&String::from("unrouted/retained")).is_some());
+    }
+}
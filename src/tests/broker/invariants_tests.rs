@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod invariants_tests {
+    use std::time::{Duration, Instant};
+
+    use crate::broker::invariants::Invariants;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    fn publish(packet_identifier: u16, qos_level: QoSLevel) -> ControlPacket {
+        ControlPacket::publish_with_payload(Some(packet_identifier), String::from("invariants/readings"), qos_level, false, vec![], vec![1])
+    }
+
+    #[tokio::test]
+    async fn count_qos2_message_forwarded_before_pubrel() {
+        let mut harness = HandlerHarness::default();
+        let subscriber = harness.connect("invariants-qos2-subscriber").await;
+        harness.subscribe(subscriber, "invariants/readings", QoSLevel::ExactlyOnce).await;
+        let publisher = harness.connect("invariants-qos2-publisher").await;
+        let invariants = harness.packet_dispatcher.invariants.clone();
+
+        harness.send(publisher, publish(1, QoSLevel::ExactlyOnce)).await.unwrap();
+        harness.send(publisher, ControlPacket::pubrel(Some(1))).await.unwrap();
+        harness.send(publisher, publish(1, QoSLevel::ExactlyOnce)).await.unwrap();
+        assert_eq!(invariants.metrics.qos2_duplicates.0.get(), 0);
+
+        harness.send(publisher, publish(1, QoSLevel::ExactlyOnce).with_dup_flag(true)).await.unwrap();
+        assert_eq!(invariants.metrics.qos2_duplicates.0.get(), 1);
+        harness.drain();
+    }
+
+    #[test]
+    fn count_publishes_forwarded_out_of_order() {
+        let invariants = Invariants::default();
+        let (publisher, subscriber, other_subscriber) = (String::from("publisher"), String::from("subscriber"), String::from("other-subscriber"));
+        let received_at = Instant::now();
+        invariants.forwarding(&publisher, &subscriber, received_at);
+        invariants.forwarding(&publisher, &subscriber, received_at + Duration::from_millis(5));
+        invariants.forwarding(&publisher, &other_subscriber, received_at);
+        assert_eq!(invariants.metrics.out_of_order.0.get(), 0);
+
+        invariants.forwarding(&publisher, &subscriber, received_at);
+        assert_eq!(invariants.metrics.out_of_order.0.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn forget_clients_whose_connection_closed() {
+        let mut harness = HandlerHarness::default();
+        let subscriber = harness.connect("invariants-closed-subscriber").await;
+        harness.subscribe(subscriber, "invariants/readings", QoSLevel::ExactlyOnce).await;
+        let publisher = harness.connect("invariants-closed-publisher").await;
+        let invariants = harness.packet_dispatcher.invariants.clone();
+        let received_at = Instant::now();
+        harness.send(publisher, publish(1, QoSLevel::ExactlyOnce)).await.unwrap();
+        harness.drain();
+
+        harness.packet_dispatcher.connection_close.closed(&subscriber).await;
+        harness.packet_dispatcher.connection_close.closed(&publisher).await;
+        let (publisher, subscriber) = (String::from("invariants-closed-publisher"), String::from("invariants-closed-subscriber"));
+        invariants.forwarding_qos2(&publisher, 1);
+        invariants.forwarding(&publisher, &subscriber, received_at);
+        assert_eq!(invariants.metrics.qos2_duplicates.0.get(), 0);
+        assert_eq!(invariants.metrics.out_of_order.0.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn count_packet_identifier_reused_in_flight() {
+        let mut harness = HandlerHarness::default();
+        let subscriber = harness.connect("invariants-packet-id-subscriber").await;
+        harness.subscribe(subscriber, "invariants/readings", QoSLevel::AtLeastOnce).await;
+        let invariants = harness.packet_dispatcher.invariants.clone();
+
+        //Both publishers pick Packet Identifier 1, the subscriber acknowledged neither
+        let publishers = [harness.connect("invariants-packet-id-first").await, harness.connect("invariants-packet-id-second").await];
+        for publisher in publishers {
+            harness.send(publisher, publish(1, QoSLevel::AtLeastOnce)).await.unwrap();
+        }
+        assert_eq!(invariants.metrics.packet_id_reuses.0.get(), 1);
+        let publishes = harness.drain().into_iter().filter(|(_, packet)| packet.fixed_header().packet_type() == ControlPacketType::PUBLISH).count();
+        assert_eq!(publishes, 2);
+
+        invariants.sending(&subscriber, &publish(1, QoSLevel::AtLeastOnce).with_dup_flag(true), true);
+        assert_eq!(invariants.metrics.packet_id_reuses.0.get(), 1);
+    }
+}
@@ -0,0 +1,52 @@
+#[cfg(test)]
+mod late_packet_tests {
+    use std::time::{Duration, Instant};
+
+    use crate::config::broker_config::DispatchConfig;
+    use crate::connection::client_context::ClientContext;
+    use crate::model::control_packet::ControlPacket;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    //A packet the reader got a minute ago
+    fn late_context(harness: &HandlerHarness, socket: std::net::SocketAddr, max_wait: Option<Duration>) -> ClientContext {
+        let mut context = harness.context(socket);
+        context.received_at = Instant::now() - Duration::from_secs(60);
+        context.max_wait = max_wait;
+        context
+    }
+
+    #[test]
+    fn packet_deadline_from_keep_alive_or_config() {
+        let config = DispatchConfig::default();
+        assert_eq!(config.packet_deadline(None), Some(Duration::from_secs(30)));
+        assert_eq!(config.packet_deadline(Some(Duration::from_secs(90))), Some(Duration::from_secs(90)));
+
+        let config = DispatchConfig { packet_deadline_ms: 0, ..DispatchConfig::default() };
+        assert_eq!(config.packet_deadline(None), None);
+    }
+
+    #[tokio::test]
+    async fn skip_late_acknowledgement_of_gone_client() {
+        let harness = HandlerHarness::default();
+        let context = late_context(&harness, HandlerHarness::socket(), Some(Duration::from_secs(1)));
+        assert!(harness.packet_dispatcher.process_message(context, ControlPacket::puback(Some(1))).await.is_ok());
+        assert_eq!(harness.packet_dispatcher.late_metrics.skipped.0.get(), 1);
+        assert_eq!(harness.packet_dispatcher.puback_handler.metrics.process.hit_count.0.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn handle_acknowledgement_within_deadline_or_of_connected_client() {
+        let mut harness = HandlerHarness::default();
+        let gone = HandlerHarness::socket();
+        let context = late_context(&harness, gone, None);
+        let _ = harness.packet_dispatcher.process_message(context, ControlPacket::puback(Some(1))).await;
+        let context = late_context(&harness, gone, Some(Duration::from_secs(120)));
+        let _ = harness.packet_dispatcher.process_message(context, ControlPacket::puback(Some(1))).await;
+
+        let connected = harness.connect("late-acknowledgement").await;
+        let context = late_context(&harness, connected, Some(Duration::from_secs(1)));
+        let _ = harness.packet_dispatcher.process_message(context, ControlPacket::puback(Some(1))).await;
+        assert_eq!(harness.packet_dispatcher.late_metrics.skipped.0.get(), 0);
+        assert_eq!(harness.packet_dispatcher.puback_handler.metrics.process.hit_count.0.get(), 3);
+    }
+}
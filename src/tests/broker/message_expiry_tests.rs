@@ -0,0 +1,54 @@
+#[cfg(test)]
+mod message_expiry_tests {
+    use std::time::{Duration, Instant};
+
+    use crate::broker::message_expiry::StoredMessage;
+    use crate::config::broker_config::MessageExpiryConfig;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::variable_header::Property;
+    use crate::topic::topic_handler::TopicHandler;
+
+    fn create_publish_packet(properties: Vec<Property>) -> ControlPacket {
+        ControlPacket::publish_with_payload(None, String::from("telemetry/device"), QoSLevel::AtMostOnce, true, properties, vec![1])
+    }
+
+    #[test]
+    fn default_interval_longest_prefix() {
+        let mut config = MessageExpiryConfig::default();
+        assert_eq!(config.default_interval("telemetry/device"), None);
+        config.defaults.insert(String::new(), 3600);
+        config.defaults.insert(String::from("telemetry/"), 60);
+        assert_eq!(config.default_interval("telemetry/device"), Some(60));
+        assert_eq!(config.default_interval("commands/device"), Some(3600));
+    }
+
+    #[test]
+    fn stored_message_remaining_interval() {
+        let received_at = Instant::now() - Duration::from_secs(10);
+        let message = StoredMessage::new(create_publish_packet(vec![Property::MessageExpiryInterval(60)]), received_at);
+        assert!(!message.is_expired());
+        let forwarded_packet = message.to_packet().unwrap();
+        let remaining = forwarded_packet.variable_header().message_expiry_interval().unwrap();
+        assert!((49..=50).contains(&remaining));
+        assert_eq!(forwarded_packet.variable_header().properties().len(), 1);
+
+        let message = StoredMessage::new(create_publish_packet(vec![Property::MessageExpiryInterval(5)]), received_at);
+        assert!(message.is_expired());
+        assert!(message.to_packet().is_none());
+
+        let message = StoredMessage::new(create_publish_packet(vec![]), received_at);
+        assert!(!message.is_expired());
+        assert_eq!(message.to_packet().unwrap().variable_header().message_expiry_interval(), None);
+    }
+
+    #[test]
+    fn retained_message_expires() {
+        let topic_handler = TopicHandler::default();
+        let topic_name = String::from("telemetry/device");
+        topic_handler.retain_message(&String::from("publisher"), &create_publish_packet(vec![Property::MessageExpiryInterval(5)]), Instant::now() - Duration::from_secs(10));
+        assert!(topic_handler.retained_message(&topic_name).is_none());
+        topic_handler.retain_message(&String::from("publisher"), &create_publish_packet(vec![Property::MessageExpiryInterval(5)]), Instant::now());
+        assert!(topic_handler.retained_message(&topic_name).is_some());
+    }
+}
@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod middleware_tests {
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+
+    use crate::broker::middleware::{PacketMiddleware, Stage, Verdict};
+    use crate::connection::client_context::ClientContext;
+    use crate::error::PatinaResult;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::tests::broker::broker_tests_data::create_publish_packet_qos0;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    //Writes what it sees to a shared log, stops the packets of one type
+    #[derive(Debug)]
+    struct Recorder {
+        name: &'static str,
+        stage: Stage,
+        stops: Option<ControlPacketType>,
+        log: Arc<Mutex<Vec<String>>>,
+    }
+
+    #[async_trait]
+    impl PacketMiddleware for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn stage(&self) -> Stage {
+            self.stage
+        }
+
+        async fn before(&self, _context: &ClientContext, control_packet: &ControlPacket) -> PatinaResult<Verdict> {
+            self.log.lock().unwrap().push(format!("before {}", self.name));
+            match self.stops == Some(control_packet.fixed_header().packet_type()) {
+                true => { Ok(Verdict::Stop) }
+                false => { Ok(Verdict::Continue) }
+            }
+        }
+
+        async fn after(&self, _context: &ClientContext, _control_packet: &ControlPacket, result: &PatinaResult<()>) {
+            self.log.lock().unwrap().push(format!("after {} ok={}", self.name, result.is_ok()));
+        }
+    }
+
+    fn register(harness: &HandlerHarness, log: &Arc<Mutex<Vec<String>>>, name: &'static str, stage: Stage, stops: Option<ControlPacketType>) {
+        harness.packet_dispatcher.middlewares.register(Arc::new(Recorder { name, stage, stops, log: log.clone() }));
+    }
+
+    #[tokio::test]
+    async fn run_by_stage_around_the_handler() {
+        let mut harness = HandlerHarness::default();
+        let log = Arc::new(Mutex::new(vec![]));
+        register(&harness, &log, "validation", Stage::Validation, None);
+        register(&harness, &log, "auth", Stage::Auth, None);
+        register(&harness, &log, "rate-limit", Stage::RateLimit, None);
+        let names: Vec<String> = harness.packet_dispatcher.middlewares.middlewares().iter().map(|middleware| middleware.name().to_string()).collect();
+        assert_eq!(names, ["debug-capture", "publish-acl", "auth", "publish-quota", "rate-limit", "late-acknowledgements", "validation"]);
+
+        let socket = harness.connect("middleware-order").await;
+        log.lock().unwrap().clear();
+        harness.send(socket, ControlPacket::pingreq()).await.unwrap();
+        harness.expect(ControlPacketType::PINGRESP).await;
+        assert_eq!(*log.lock().unwrap(), ["before auth", "before rate-limit", "before validation", "after validation ok=true", "after rate-limit ok=true", "after auth ok=true"]);
+    }
+
+    #[tokio::test]
+    async fn stopped_packet_skips_later_middlewares_and_handler() {
+        let mut harness = HandlerHarness::default();
+        let log = Arc::new(Mutex::new(vec![]));
+        register(&harness, &log, "auth", Stage::Auth, Some(ControlPacketType::PUBLISH));
+        register(&harness, &log, "validation", Stage::Validation, None);
+        let socket = harness.connect("middleware-stop").await;
+        log.lock().unwrap().clear();
+
+        harness.send(socket, create_publish_packet_qos0(1, String::from("sensors/1"))).await.unwrap();
+        assert_eq!(*log.lock().unwrap(), ["before auth"]);
+        assert_eq!(harness.packet_dispatcher.publish_handler.metrics.process.hit_count.0.get(), 0);
+        harness.expect_nothing();
+    }
+
+    #[tokio::test]
+    async fn after_hooks_see_handler_failure() {
+        let harness = HandlerHarness::default();
+        let log = Arc::new(Mutex::new(vec![]));
+        register(&harness, &log, "audit", Stage::Observe, None);
+
+        //No CONNECT on this socket, the handler has no client to work for
+        assert!(harness.send(HandlerHarness::socket(), ControlPacket::pingreq()).await.is_err());
+        assert_eq!(*log.lock().unwrap(), ["before audit", "after audit ok=false"]);
+    }
+}
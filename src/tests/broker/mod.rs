@@ -1,2 +1,23 @@
+pub mod broker_info_tests;
 pub mod broker_tests;
-pub mod broker_tests_data;
\ No newline at end of file
+pub mod broker_tests_data;
+pub mod compression_tests;
+pub mod delivery_report_tests;
+pub mod diagnostics_tests;
+#[cfg(test)]
+pub mod handler_harness;
+pub mod handler_tests;
+pub mod invariants_tests;
+pub mod late_packet_tests;
+pub mod message_expiry_tests;
+pub mod middleware_tests;
+pub mod offline_delivery_tests;
+pub mod publisher_identity_tests;
+pub mod quarantine_tests;
+pub mod resubscribe_tests;
+pub mod session_expiry_tests;
+pub mod retained_delivery_tests;
+pub mod shared_rebalance_tests;
+pub mod supervisor_tests;
+pub mod takeover_tests;
+pub mod unsubscribe_drop_tests;
@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod offline_delivery_tests {
+    use crate::broker::utils::queued_packets;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::Property;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet_with_properties, create_disconnect_packet, create_publish_packet_qos1};
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    #[tokio::test]
+    async fn publish_to_offline_clean_session_finds_no_subscription() {
+        let mut harness = HandlerHarness::default();
+        let subscriber = harness.connect("offline-clean-subscriber").await;
+        harness.subscribe(subscriber, "offline/clean", QoSLevel::AtLeastOnce).await;
+        harness.send(subscriber, create_disconnect_packet(ReasonCode::NormalDisconnection, vec![])).await.unwrap();
+        harness.expect(ControlPacketType::DISCONNECT).await;
+        //Gone with the session, which ends with the connection
+        assert!(harness.topic_handler.subscriptions_of(&String::from("offline-clean-subscriber")).is_empty());
+
+        let publisher = harness.connect("offline-clean-publisher").await;
+        harness.send(publisher, create_publish_packet_qos1(1, String::from("offline/clean"))).await.unwrap();
+        harness.expect(ControlPacketType::PUBACK).await;
+        harness.expect_nothing();
+
+        let offline_metrics = &harness.packet_dispatcher.publish_handler.offline_metrics;
+        assert_eq!(offline_metrics.dropped.0.get(), 0);
+        assert_eq!(offline_metrics.queued.0.get(), 0);
+        assert_eq!(queued_packets(&*harness.client_handler.sessions, &String::from("offline-clean-subscriber")), 0);
+    }
+
+    #[tokio::test]
+    async fn publish_to_offline_persistent_session_is_queued() {
+        let mut harness = HandlerHarness::default();
+        let subscriber = HandlerHarness::socket();
+        let connect_packet = create_connect_packet_with_properties(String::from("offline-persistent-subscriber"), vec![Property::SessionExpiryInterval(300)]);
+        harness.send(subscriber, connect_packet).await.unwrap();
+        harness.expect(ControlPacketType::CONNACK).await;
+        harness.subscribe(subscriber, "offline/persistent", QoSLevel::AtLeastOnce).await;
+        harness.send(subscriber, create_disconnect_packet(ReasonCode::NormalDisconnection, vec![])).await.unwrap();
+        harness.expect(ControlPacketType::DISCONNECT).await;
+
+        let publisher = harness.connect("offline-persistent-publisher").await;
+        harness.send(publisher, create_publish_packet_qos1(1, String::from("offline/persistent"))).await.unwrap();
+        harness.expect(ControlPacketType::PUBACK).await;
+        harness.expect_nothing();
+
+        let offline_metrics = &harness.packet_dispatcher.publish_handler.offline_metrics;
+        assert_eq!(offline_metrics.queued.0.get(), 1);
+        assert_eq!(offline_metrics.dropped.0.get(), 0);
+        assert_eq!(queued_packets(&*harness.client_handler.sessions, &String::from("offline-persistent-subscriber")), 1);
+    }
+}
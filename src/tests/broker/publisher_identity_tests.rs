@@ -0,0 +1,89 @@
+#[cfg(test)]
+mod publisher_identity_tests {
+    use crate::config::broker_config::BrokerConfig;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::variable_header::Property;
+    use crate::tests::broker::broker_tests_data::create_connect_packet_with_username;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    fn identity_config() -> BrokerConfig {
+        let mut config = BrokerConfig::default();
+        config.publisher_identity.enabled = true;
+        config.publisher_identity.topics = vec![String::from("trusted/")];
+        config
+    }
+
+    fn spoofed_publish(topic_name: &str) -> ControlPacket {
+        let properties = vec![
+            Property::UserProperty(String::from("patina-publisher"), String::from("admin")),
+            Property::UserProperty(String::from("patina-publisher-username"), String::from("root")),
+            Property::UserProperty(String::from("unit"), String::from("celsius")),
+        ];
+        ControlPacket::publish_with_payload(None, topic_name.to_string(), QoSLevel::AtMostOnce, false, properties, vec![21])
+    }
+
+    fn user_properties(control_packet: &ControlPacket) -> Vec<(String, String)> {
+        control_packet.variable_header().properties().iter()
+            .filter_map(|property| match property {
+                Property::UserProperty(key, value) => Some((key.clone(), value.clone())),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn stamp_publisher_identity_on_selected_topics() {
+        let mut harness = HandlerHarness::new(identity_config());
+        let subscriber = harness.connect("identity-consumer").await;
+        harness.subscribe(subscriber, "#", QoSLevel::AtMostOnce).await;
+        let publisher = HandlerHarness::socket();
+        harness.send(publisher, create_connect_packet_with_username(String::from("identity-sensor"), String::from("sensors"))).await.unwrap();
+        harness.expect(ControlPacketType::CONNACK).await;
+
+        harness.send(publisher, spoofed_publish("trusted/temperature")).await.unwrap();
+        let (_, forwarded_packet) = harness.expect(ControlPacketType::PUBLISH).await;
+        let mut properties = user_properties(&forwarded_packet);
+        properties.sort();
+        assert_eq!(properties, vec![
+            (String::from("patina-publisher"), String::from("identity-sensor")),
+            (String::from("patina-publisher-username"), String::from("sensors")),
+            (String::from("unit"), String::from("celsius")),
+        ]);
+
+        //Not stamped, still stripped
+        harness.send(publisher, spoofed_publish("other/temperature")).await.unwrap();
+        let (_, forwarded_packet) = harness.expect(ControlPacketType::PUBLISH).await;
+        assert_eq!(user_properties(&forwarded_packet), vec![(String::from("unit"), String::from("celsius"))]);
+    }
+
+    #[tokio::test]
+    async fn strip_spoofed_username_of_anonymous_publisher() {
+        let mut harness = HandlerHarness::new(identity_config());
+        let subscriber = harness.connect("identity-anonymous-consumer").await;
+        harness.subscribe(subscriber, "trusted/#", QoSLevel::AtMostOnce).await;
+        let publisher = harness.connect("identity-anonymous").await;
+
+        harness.send(publisher, spoofed_publish("trusted/temperature")).await.unwrap();
+        let (_, forwarded_packet) = harness.expect(ControlPacketType::PUBLISH).await;
+        let mut properties = user_properties(&forwarded_packet);
+        properties.sort();
+        assert_eq!(properties, vec![
+            (String::from("patina-publisher"), String::from("identity-anonymous")),
+            (String::from("unit"), String::from("celsius")),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn forward_client_properties_when_disabled() {
+        let mut harness = HandlerHarness::default();
+        let subscriber = harness.connect("identity-disabled-consumer").await;
+        harness.subscribe(subscriber, "trusted/#", QoSLevel::AtMostOnce).await;
+        let publisher = harness.connect("identity-disabled").await;
+
+        harness.send(publisher, spoofed_publish("trusted/temperature")).await.unwrap();
+        let (_, forwarded_packet) = harness.expect(ControlPacketType::PUBLISH).await;
+        assert_eq!(user_properties(&forwarded_packet).len(), 3);
+    }
+}
@@ -0,0 +1,111 @@
+#[cfg(test)]
+mod quarantine_tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+
+    use crate::{ClientHandler, TopicHandler};
+    use crate::broker::middleware::{PacketMiddleware, Stage};
+    use crate::broker::packet_dispatcher::PacketDispatcher;
+    use crate::broker::quarantine::{Quarantine, SYS_DEAD_LETTER_TOPIC};
+    use crate::config::broker_config::{BrokerConfig, DispatchConfig};
+    use crate::connection::client_context::ClientContext;
+    use crate::error::{PatinaError, PatinaResult};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet, create_publish_packet_qos0, create_subscribe_packet};
+
+    #[test]
+    fn retry_backs_off_until_max_attempts() {
+        let quarantine = Quarantine::new(DispatchConfig { max_attempts: 3, retry_backoff_ms: 10, ..DispatchConfig::default() });
+        let pubrel_packet = ControlPacket::pubrel(Some(1));
+        let error = PatinaError::HandlerPanicked(String::from("failed"));
+        assert_eq!(quarantine.retry(1, &pubrel_packet, &error), Some(Duration::from_millis(10)));
+        assert_eq!(quarantine.retry(2, &pubrel_packet, &error), Some(Duration::from_millis(20)));
+        assert_eq!(quarantine.retry(3, &pubrel_packet, &error), None);
+        assert_eq!(quarantine.metrics.dispatch_retried.0.get(), 2);
+
+        let socket: SocketAddr = "127.0.0.1:41001".parse().unwrap();
+        let publish_packet = create_publish_packet_qos0(1, String::from("poison"));
+        assert_eq!(quarantine.retry(1, &publish_packet, &error), None);
+        let dead_letter = quarantine.quarantine(socket, None, &publish_packet, 1, &error);
+        assert_eq!(dead_letter.packet_type, "PUBLISH");
+        assert!(dead_letter.packet.contains("poison"));
+        assert_eq!(quarantine.metrics.poison_messages.0.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn failing_packet_is_quarantined() {
+        let mut config = BrokerConfig::default();
+        config.dispatch.retry_backoff_ms = 1;
+        let (to_listener, mut from_broker) = tokio::sync::mpsc::channel(32);
+        let packet_dispatcher = Arc::new(PacketDispatcher::new(Arc::new(config), Arc::new(ClientHandler::default()), Arc::new(TopicHandler::default()), Arc::new(to_listener)));
+
+        let monitor_socket: SocketAddr = "127.0.0.1:41002".parse().unwrap();
+        packet_dispatcher.clone().dispatch(ClientContext::new(monitor_socket), create_connect_packet(String::from("dead-letter-monitor"))).await;
+        assert_eq!(from_broker.recv().await.unwrap().1.fixed_header().packet_type(), ControlPacketType::CONNACK);
+        packet_dispatcher.clone().dispatch(ClientContext::new(monitor_socket), create_subscribe_packet(1, String::from(SYS_DEAD_LETTER_TOPIC), QoSLevel::AtMostOnce)).await;
+        assert_eq!(from_broker.recv().await.unwrap().1.fixed_header().packet_type(), ControlPacketType::SUBACK);
+
+        //Never connected, so every attempt to handle its PUBREL fails
+        let poison_socket: SocketAddr = "127.0.0.1:41003".parse().unwrap();
+        packet_dispatcher.clone().dispatch(ClientContext::new(poison_socket), ControlPacket::pubrel(Some(1))).await;
+        let (sockets, dead_letter_packet) = from_broker.recv().await.unwrap();
+        assert_eq!(sockets, vec![monitor_socket]);
+        assert_eq!(dead_letter_packet.variable_header().topic_name(), SYS_DEAD_LETTER_TOPIC);
+        let dead_letter: serde_json::Value = serde_json::from_slice(dead_letter_packet.payload().data()).unwrap();
+        assert_eq!(dead_letter["address"], "127.0.0.1:41003");
+        assert_eq!(dead_letter["packet_type"], "PUBREL");
+        assert_eq!(dead_letter["attempts"], 3);
+        assert_eq!(packet_dispatcher.quarantine.metrics.dispatch_retried.0.get(), 2);
+        assert_eq!(packet_dispatcher.quarantine.metrics.poison_messages.0.get(), 1);
+    }
+
+    //Fails every PUBLISH once its handler delivered it
+    #[derive(Debug)]
+    struct FailAfterFanOut;
+
+    #[async_trait]
+    impl PacketMiddleware for FailAfterFanOut {
+        fn name(&self) -> &str {
+            "fail-after-fan-out"
+        }
+
+        fn stage(&self) -> Stage {
+            Stage::Observe
+        }
+
+        async fn after(&self, _context: &ClientContext, control_packet: &ControlPacket, _result: &PatinaResult<()>) {
+            if control_packet.fixed_header().packet_type() == ControlPacketType::PUBLISH {
+                panic!("PUBLISH failed after fan-out");
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn publish_failing_after_fan_out_is_not_delivered_twice() {
+        let mut config = BrokerConfig::default();
+        config.dispatch.retry_backoff_ms = 1;
+        let (to_listener, mut from_broker) = tokio::sync::mpsc::channel(32);
+        let packet_dispatcher = Arc::new(PacketDispatcher::new(Arc::new(config), Arc::new(ClientHandler::default()), Arc::new(TopicHandler::default()), Arc::new(to_listener)));
+        let (subscriber, publisher): (SocketAddr, SocketAddr) = ("127.0.0.1:41004".parse().unwrap(), "127.0.0.1:41005".parse().unwrap());
+        packet_dispatcher.clone().dispatch(ClientContext::new(subscriber), create_connect_packet(String::from("fan-out-subscriber"))).await;
+        packet_dispatcher.clone().dispatch(ClientContext::new(subscriber), create_subscribe_packet(1, String::from("fan-out"), QoSLevel::AtMostOnce)).await;
+        packet_dispatcher.clone().dispatch(ClientContext::new(publisher), create_connect_packet(String::from("fan-out-publisher"))).await;
+        for _ in 0..3 {
+            from_broker.recv().await.unwrap();
+        }
+
+        packet_dispatcher.middlewares.register(Arc::new(FailAfterFanOut));
+        packet_dispatcher.clone().dispatch(ClientContext::new(publisher), create_publish_packet_qos0(1, String::from("fan-out"))).await;
+        let (sockets, publish_packet) = from_broker.recv().await.unwrap();
+        assert_eq!(sockets, vec![subscriber]);
+        assert_eq!(publish_packet.fixed_header().packet_type(), ControlPacketType::PUBLISH);
+        assert!(from_broker.try_recv().is_err());
+        assert_eq!(packet_dispatcher.quarantine.metrics.dispatch_retried.0.get(), 0);
+        assert_eq!(packet_dispatcher.quarantine.metrics.poison_messages.0.get(), 1);
+    }
+}
@@ -0,0 +1,154 @@
+#[cfg(test)]
+mod resubscribe_tests {
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::topic::RetainHandling;
+    use crate::model::variable_header::Property;
+    use crate::tests::broker::broker_tests_data::{create_publish_packet_qos1, create_subscribe_packet_with_no_local, create_subscribe_packet_with_properties, create_subscribe_packet_with_retain_as_published, create_subscribe_packet_with_retain_handling};
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    #[tokio::test]
+    async fn repeated_subscribe_replaces_options() {
+        let mut harness = HandlerHarness::default();
+        let client_id = String::from("resubscribe-options");
+        let topic_filter = String::from("resubscribe/options");
+        let subscriber = harness.connect(&client_id).await;
+        harness.send(subscriber, create_subscribe_packet_with_properties(1, topic_filter.clone(), QoSLevel::AtLeastOnce, vec![Property::SubscriptionIdentifier(7)])).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+
+        let publisher = harness.connect("resubscribe-options-publisher").await;
+        harness.send(publisher, create_publish_packet_qos1(1, topic_filter.clone())).await.unwrap();
+        let delivered: Vec<ControlPacket> = harness.drain().into_iter()
+            .filter(|(sockets, _)| sockets.contains(&subscriber))
+            .map(|(_, control_packet)| control_packet)
+            .collect();
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].fixed_header().qos_level(), &QoSLevel::AtLeastOnce);
+        assert!(delivered[0].variable_header().properties().contains(&Property::SubscriptionIdentifier(7)));
+
+        harness.send(subscriber, create_subscribe_packet_with_properties(2, topic_filter.clone(), QoSLevel::AtMostOnce, vec![])).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+        assert_eq!(harness.topic_handler.subscription_count(&client_id), 1);
+        let metadata = harness.topic_handler.subscription_metadata(&client_id, &topic_filter).unwrap();
+        assert_eq!(metadata.maximum_qos(), Some(QoSLevel::AtMostOnce));
+        assert_eq!(metadata.subscription_identifier(), None);
+        assert_eq!(metadata.delivery_count(), 1);
+
+        harness.send(publisher, create_publish_packet_qos1(2, topic_filter)).await.unwrap();
+        let delivered: Vec<ControlPacket> = harness.drain().into_iter()
+            .filter(|(sockets, _)| sockets.contains(&subscriber))
+            .map(|(_, control_packet)| control_packet)
+            .collect();
+        assert_eq!(delivered.len(), 1);
+        assert_eq!(delivered[0].fixed_header().qos_level(), &QoSLevel::AtMostOnce);
+        assert!(!delivered[0].variable_header().properties().iter().any(|property| matches!(property, Property::SubscriptionIdentifier(_))));
+    }
+
+    #[tokio::test]
+    async fn repeated_subscribe_switches_no_local() {
+        let mut harness = HandlerHarness::default();
+        let client_id = String::from("resubscribe-no-local");
+        let topic_filter = String::from("resubscribe/echo");
+        let client = harness.connect(&client_id).await;
+        harness.send(client, create_subscribe_packet_with_no_local(1, topic_filter.clone(), QoSLevel::AtMostOnce, true)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+        assert!(harness.topic_handler.subscription_metadata(&client_id, &topic_filter).unwrap().no_local());
+        harness.send(client, create_publish_packet_qos1(1, topic_filter.clone())).await.unwrap();
+        harness.expect(ControlPacketType::PUBACK).await;
+        harness.expect_nothing();
+
+        harness.send(client, create_subscribe_packet_with_no_local(2, topic_filter.clone(), QoSLevel::AtMostOnce, false)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+        assert!(!harness.topic_handler.subscription_metadata(&client_id, &topic_filter).unwrap().no_local());
+        assert_eq!(harness.topic_handler.subscription_count(&client_id), 1);
+        assert_eq!(harness.topic_handler.find_subscribers(&topic_filter), vec![client_id]);
+        harness.send(client, create_publish_packet_qos1(2, topic_filter.clone())).await.unwrap();
+        harness.expect(ControlPacketType::PUBACK).await;
+        let (sockets, publish_packet) = harness.expect(ControlPacketType::PUBLISH).await;
+        assert_eq!(sockets, vec![client]);
+        assert_eq!(publish_packet.variable_header().topic_name(), &topic_filter);
+    }
+
+    #[tokio::test]
+    async fn no_local_skips_only_its_own_subscription() {
+        let mut harness = HandlerHarness::default();
+        let client = harness.connect("resubscribe-no-local-overlap").await;
+        harness.send(client, create_subscribe_packet_with_no_local(1, String::from("resubscribe/overlap/#"), QoSLevel::AtMostOnce, true)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+        let other = harness.connect("resubscribe-no-local-other").await;
+        harness.send(other, create_subscribe_packet_with_no_local(1, String::from("resubscribe/overlap/#"), QoSLevel::AtMostOnce, true)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+
+        //Another client's messages still reach a No Local subscription
+        harness.send(client, create_publish_packet_qos1(1, String::from("resubscribe/overlap/a"))).await.unwrap();
+        let delivered: Vec<Vec<_>> = harness.drain().into_iter()
+            .filter(|(_, control_packet)| control_packet.fixed_header().packet_type() == ControlPacketType::PUBLISH)
+            .map(|(sockets, _)| sockets)
+            .collect();
+        assert_eq!(delivered, vec![vec![other]]);
+
+        //A matching subscription without No Local gets the client its own message
+        harness.send(client, create_subscribe_packet_with_no_local(2, String::from("resubscribe/overlap/a"), QoSLevel::AtMostOnce, false)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+        harness.send(client, create_publish_packet_qos1(2, String::from("resubscribe/overlap/a"))).await.unwrap();
+        let mut delivered: Vec<_> = harness.drain().into_iter()
+            .filter(|(_, control_packet)| control_packet.fixed_header().packet_type() == ControlPacketType::PUBLISH)
+            .flat_map(|(sockets, _)| sockets)
+            .collect();
+        delivered.sort();
+        let mut expected = vec![client, other];
+        expected.sort();
+        assert_eq!(delivered, expected);
+    }
+
+    #[tokio::test]
+    async fn retain_as_published_keeps_retain_flag() {
+        let mut harness = HandlerHarness::default();
+        let topic_filter = String::from("resubscribe/retain-as-published");
+        let keeping = harness.connect("resubscribe-keeping").await;
+        harness.send(keeping, create_subscribe_packet_with_retain_as_published(1, topic_filter.clone(), QoSLevel::AtMostOnce, true)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+        let clearing = harness.connect("resubscribe-clearing").await;
+        harness.send(clearing, create_subscribe_packet_with_retain_as_published(1, topic_filter.clone(), QoSLevel::AtMostOnce, false)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+        assert!(harness.topic_handler.subscription_metadata(&String::from("resubscribe-keeping"), &topic_filter).unwrap().retain_as_published());
+
+        let publisher = harness.connect("resubscribe-retain-publisher").await;
+        let retained_packet = ControlPacket::publish_with_payload(None, topic_filter.clone(), QoSLevel::AtMostOnce, true, vec![], b"retained".to_vec());
+        harness.send(publisher, retained_packet).await.unwrap();
+        let delivered = harness.drain();
+        let retain_of = |subscriber| delivered.iter()
+            .filter(|(sockets, _)| sockets.contains(&subscriber))
+            .map(|(_, control_packet)| *control_packet.fixed_header().retain())
+            .collect::<Vec<bool>>();
+        assert_eq!(retain_of(keeping), vec![true]);
+        assert_eq!(retain_of(clearing), vec![false]);
+        //The stored message stays retained for later subscribers
+        assert!(*harness.topic_handler.retained_message(&topic_filter).unwrap().fixed_header().retain());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retained_messages_on_new_subscription_only() {
+        let mut harness = HandlerHarness::default();
+        let topic_filter = String::from("resubscribe/retained");
+        let publisher = harness.connect("resubscribe-retained-publisher").await;
+        let retained_packet = ControlPacket::publish_with_payload(None, topic_filter.clone(), QoSLevel::AtMostOnce, true, vec![], b"retained".to_vec());
+        harness.send(publisher, retained_packet).await.unwrap();
+
+        let subscriber = harness.connect("resubscribe-retained").await;
+        harness.send(subscriber, create_subscribe_packet_with_retain_handling(1, topic_filter.clone(), QoSLevel::AtMostOnce, RetainHandling::SendRetainedMessagesOnNewSubscribe)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+        harness.expect(ControlPacketType::PUBLISH).await;
+
+        //Changing the options doesn't make the subscription new
+        harness.send(subscriber, create_subscribe_packet_with_retain_handling(2, topic_filter.clone(), QoSLevel::AtLeastOnce, RetainHandling::SendRetainedMessagesOnNewSubscribe)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        harness.expect_nothing();
+
+        harness.send(subscriber, create_subscribe_packet_with_retain_handling(3, topic_filter, QoSLevel::AtLeastOnce, RetainHandling::SendRetainedMessagesOnSubscribe)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+        harness.expect(ControlPacketType::PUBLISH).await;
+    }
+}
@@ -0,0 +1,146 @@
+#[cfg(test)]
+mod retained_delivery_tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use tokio::sync::mpsc::Receiver;
+
+    use crate::{ClientHandler, TopicHandler};
+    use crate::broker::packet_dispatcher::PacketDispatcher;
+    use crate::config::broker_config::{BrokerConfig, SharedRetained};
+    use crate::connection::client_context::ClientContext;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::topic::RetainHandling;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet, create_connect_packet_resuming_session, create_subscribe_packet_with_retain_handling};
+
+    fn create_packet_dispatcher(config: BrokerConfig, retained_topics: &[&str]) -> (PacketDispatcher, Arc<ClientHandler>, Receiver<(Vec<SocketAddr>, ControlPacket)>) {
+        let (to_listener, from_broker) = tokio::sync::mpsc::channel(64);
+        let client_handler = Arc::new(ClientHandler::default());
+        let topic_handler = Arc::new(TopicHandler::default());
+        for topic_name in retained_topics {
+            let publish_packet = ControlPacket::publish_with_payload(Some(1), topic_name.to_string(), QoSLevel::AtLeastOnce, true, vec![], topic_name.as_bytes().to_vec());
+            topic_handler.retain_message(&String::from("publisher"), &publish_packet, Instant::now());
+        }
+        (PacketDispatcher::new(Arc::new(config), client_handler.clone(), topic_handler, Arc::new(to_listener)), client_handler, from_broker)
+    }
+
+    async fn recv_publish(from_broker: &mut Receiver<(Vec<SocketAddr>, ControlPacket)>) -> ControlPacket {
+        let (_, packet) = tokio::time::timeout(Duration::from_secs(2), from_broker.recv()).await.unwrap().unwrap();
+        assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::PUBLISH);
+        packet
+    }
+
+    #[tokio::test]
+    async fn send_retained_messages_after_suback() {
+        let (packet_dispatcher, _, mut from_broker) = create_packet_dispatcher(BrokerConfig::default(), &["sensors/2", "sensors/1", "other/1"]);
+        let socket: SocketAddr = "127.0.0.1:43001".parse().unwrap();
+        let client_id = String::from("retained-subscriber");
+        packet_dispatcher.process_message(ClientContext::new(socket), create_connect_packet(client_id.clone())).await.unwrap();
+        packet_dispatcher.process_message(ClientContext::new(socket), create_subscribe_packet_with_retain_handling(1, String::from("sensors/+"), QoSLevel::AtMostOnce, RetainHandling::SendRetainedMessagesOnSubscribe)).await.unwrap();
+        assert_eq!(from_broker.recv().await.unwrap().1.fixed_header().packet_type(), ControlPacketType::CONNACK);
+        assert_eq!(from_broker.recv().await.unwrap().1.fixed_header().packet_type(), ControlPacketType::SUBACK);
+
+        for topic_name in ["sensors/1", "sensors/2"] {
+            let retained_packet = recv_publish(&mut from_broker).await;
+            assert_eq!(retained_packet.variable_header().topic_name(), topic_name);
+            assert!(*retained_packet.fixed_header().retain());
+            assert_eq!(retained_packet.fixed_header().qos_level(), &QoSLevel::AtMostOnce);
+        }
+
+        //Not a new subscription anymore
+        packet_dispatcher.process_message(ClientContext::new(socket), create_subscribe_packet_with_retain_handling(2, String::from("sensors/+"), QoSLevel::AtMostOnce, RetainHandling::SendRetainedMessagesOnNewSubscribe)).await.unwrap();
+        assert_eq!(from_broker.recv().await.unwrap().1.fixed_header().packet_type(), ControlPacketType::SUBACK);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(from_broker.try_recv().is_err());
+        assert_eq!(packet_dispatcher.retained_delivery.metrics.sent.0.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn pace_retained_messages() {
+        let mut config = BrokerConfig::default();
+        config.retained_delivery.messages_per_sec = 50;
+        let (packet_dispatcher, _, mut from_broker) = create_packet_dispatcher(config, &["sensors/1", "sensors/2", "sensors/3", "sensors/4"]);
+        assert_eq!(packet_dispatcher.retained_delivery.pause(1024), Duration::from_millis(20));
+        let socket: SocketAddr = "127.0.0.1:43002".parse().unwrap();
+        packet_dispatcher.process_message(ClientContext::new(socket), create_connect_packet(String::from("paced-subscriber"))).await.unwrap();
+        packet_dispatcher.process_message(ClientContext::new(socket), create_subscribe_packet_with_retain_handling(1, String::from("sensors/#"), QoSLevel::AtLeastOnce, RetainHandling::SendRetainedMessagesOnSubscribe)).await.unwrap();
+        from_broker.recv().await.unwrap();
+        from_broker.recv().await.unwrap();
+
+        let started = Instant::now();
+        for _ in 0..4 {
+            assert_eq!(recv_publish(&mut from_broker).await.fixed_header().qos_level(), &QoSLevel::AtLeastOnce);
+        }
+        assert!(started.elapsed() >= Duration::from_millis(60));
+    }
+
+    #[tokio::test]
+    async fn resume_interrupted_retained_messages() {
+        let mut config = BrokerConfig::default();
+        config.retained_delivery.messages_per_sec = 20;
+        let (packet_dispatcher, client_handler, mut from_broker) = create_packet_dispatcher(config, &["sensors/1", "sensors/2", "sensors/3"]);
+        let socket: SocketAddr = "127.0.0.1:43003".parse().unwrap();
+        let client_id = String::from("resumed-subscriber");
+        packet_dispatcher.process_message(ClientContext::new(socket), create_connect_packet_resuming_session(client_id.clone())).await.unwrap();
+        packet_dispatcher.process_message(ClientContext::new(socket), create_subscribe_packet_with_retain_handling(1, String::from("sensors/#"), QoSLevel::AtMostOnce, RetainHandling::SendRetainedMessagesOnSubscribe)).await.unwrap();
+        from_broker.recv().await.unwrap();
+        from_broker.recv().await.unwrap();
+        assert_eq!(recv_publish(&mut from_broker).await.variable_header().topic_name(), "sensors/1");
+
+        client_handler.unregister(&socket, &client_id);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(from_broker.try_recv().is_err());
+        assert_eq!(packet_dispatcher.retained_delivery.metrics.interrupted.0.get(), 1);
+        assert_eq!(packet_dispatcher.retained_delivery.metrics.sent.0.get(), 1);
+
+        let socket: SocketAddr = "127.0.0.1:43004".parse().unwrap();
+        packet_dispatcher.process_message(ClientContext::new(socket), create_connect_packet_resuming_session(client_id.clone())).await.unwrap();
+        let (_, connack_packet) = from_broker.recv().await.unwrap();
+        assert_eq!(connack_packet.fixed_header().packet_type(), ControlPacketType::CONNACK);
+        for topic_name in ["sensors/2", "sensors/3"] {
+            let (sockets, retained_packet) = tokio::time::timeout(Duration::from_secs(2), from_broker.recv()).await.unwrap().unwrap();
+            assert_eq!(sockets, vec![socket]);
+            assert_eq!(retained_packet.variable_header().topic_name(), topic_name);
+        }
+        assert_eq!(packet_dispatcher.retained_delivery.metrics.resumed.0.get(), 1);
+        assert_eq!(packet_dispatcher.retained_delivery.metrics.sent.0.get(), 3);
+    }
+
+    //The PUBLISH packets sent once both members subscribed, the retained ones may come before the second CONNACK
+    async fn subscribe_shared_members(packet_dispatcher: &PacketDispatcher, from_broker: &mut Receiver<(Vec<SocketAddr>, ControlPacket)>) -> Vec<(Vec<SocketAddr>, ControlPacket)> {
+        for (port, client_id) in [(43005, "first-member"), (43006, "second-member")] {
+            let socket: SocketAddr = format!("127.0.0.1:{}", port).parse().unwrap();
+            packet_dispatcher.process_message(ClientContext::new(socket), create_connect_packet(String::from(client_id))).await.unwrap();
+            packet_dispatcher.process_message(ClientContext::new(socket), create_subscribe_packet_with_retain_handling(1, String::from("$share/group/sensors/+"), QoSLevel::AtMostOnce, RetainHandling::SendRetainedMessagesOnSubscribe)).await.unwrap();
+        }
+        let mut published = vec![];
+        while let Ok(Some((sockets, packet))) = tokio::time::timeout(Duration::from_millis(100), from_broker.recv()).await {
+            if packet.fixed_header().packet_type() == ControlPacketType::PUBLISH {
+                published.push((sockets, packet));
+            }
+        }
+        published
+    }
+
+    #[tokio::test]
+    async fn no_retained_messages_for_shared_subscriptions() {
+        let (packet_dispatcher, _, mut from_broker) = create_packet_dispatcher(BrokerConfig::default(), &["sensors/1"]);
+        assert!(subscribe_shared_members(&packet_dispatcher, &mut from_broker).await.is_empty());
+        assert_eq!(packet_dispatcher.retained_delivery.metrics.sent.0.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn retained_messages_for_first_shared_member() {
+        let mut config = BrokerConfig::default();
+        config.subscription.shared_retained = SharedRetained::FirstMember;
+        let (packet_dispatcher, _, mut from_broker) = create_packet_dispatcher(config, &["sensors/1", "sensors/2"]);
+        let published = subscribe_shared_members(&packet_dispatcher, &mut from_broker).await;
+        let topic_names: Vec<&String> = published.iter().map(|(_, retained_packet)| retained_packet.variable_header().topic_name()).collect();
+        assert_eq!(topic_names, vec!["sensors/1", "sensors/2"]);
+        assert!(published.iter().all(|(sockets, _)| sockets == &vec!["127.0.0.1:43005".parse::<SocketAddr>().unwrap()]));
+        assert_eq!(packet_dispatcher.retained_delivery.metrics.sent.0.get(), 2);
+    }
+}
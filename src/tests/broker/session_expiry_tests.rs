@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod session_expiry_tests {
+    use std::time::Duration;
+
+    use crate::broker::utils::{get_session_expiry_interval, queued_packets};
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet_resuming_session, create_publish_packet_qos1};
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    //Session Expiry Interval of create_connect_packet_resuming_session
+    const SESSION_EXPIRY_INTERVAL: Duration = Duration::from_secs(300);
+
+    async fn connect_resuming(harness: &mut HandlerHarness, client_id: &str) -> std::net::SocketAddr {
+        let socket = HandlerHarness::socket();
+        harness.send(socket, create_connect_packet_resuming_session(client_id.to_string())).await.unwrap();
+        harness.expect(ControlPacketType::CONNACK).await;
+        socket
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lost_connection_keeps_session_and_subscriptions_until_expiry() {
+        let mut harness = HandlerHarness::default();
+        let client_id = String::from("expiry-lost-connection");
+        let subscriber = connect_resuming(&mut harness, &client_id).await;
+        harness.subscribe(subscriber, "expiry/lost", QoSLevel::AtLeastOnce).await;
+        //What the reader does when the TCP connection breaks
+        assert_eq!(harness.packet_dispatcher.connection_close.closed(&subscriber).await, Some(client_id.clone()));
+        assert!(harness.client_handler.get_socket(&client_id).is_err());
+
+        let publisher = harness.connect("expiry-lost-publisher").await;
+        harness.send(publisher, create_publish_packet_qos1(1, String::from("expiry/lost"))).await.unwrap();
+        harness.expect(ControlPacketType::PUBACK).await;
+        assert_eq!(queued_packets(&*harness.client_handler.sessions, &client_id), 1);
+        assert_eq!(harness.topic_handler.subscriptions_of(&client_id).len(), 1);
+
+        tokio::time::sleep(SESSION_EXPIRY_INTERVAL + Duration::from_secs(1)).await;
+        assert_eq!(get_session_expiry_interval(&*harness.client_handler.sessions, &client_id), None);
+        assert!(harness.topic_handler.subscriptions_of(&client_id).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn second_disconnection_restarts_expiry() {
+        let mut harness = HandlerHarness::default();
+        let client_id = String::from("expiry-reconnected");
+        let socket = connect_resuming(&mut harness, &client_id).await;
+        harness.subscribe(socket, "expiry/reconnected", QoSLevel::AtLeastOnce).await;
+        harness.packet_dispatcher.connection_close.closed(&socket).await;
+        tokio::time::sleep(SESSION_EXPIRY_INTERVAL / 2).await;
+
+        let socket = connect_resuming(&mut harness, &client_id).await;
+        harness.packet_dispatcher.connection_close.closed(&socket).await;
+        //Past the expiry of the first disconnection
+        tokio::time::sleep(SESSION_EXPIRY_INTERVAL / 2 + Duration::from_secs(1)).await;
+        assert_eq!(get_session_expiry_interval(&*harness.client_handler.sessions, &client_id), Some(SESSION_EXPIRY_INTERVAL.as_secs() as u32));
+        assert_eq!(harness.topic_handler.subscriptions_of(&client_id).len(), 1);
+
+        tokio::time::sleep(SESSION_EXPIRY_INTERVAL / 2).await;
+        assert_eq!(get_session_expiry_interval(&*harness.client_handler.sessions, &client_id), None);
+        assert!(harness.topic_handler.subscriptions_of(&client_id).is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reconnected_client_keeps_its_session() {
+        let mut harness = HandlerHarness::default();
+        let client_id = String::from("expiry-still-connected");
+        let socket = connect_resuming(&mut harness, &client_id).await;
+        harness.packet_dispatcher.connection_close.closed(&socket).await;
+        connect_resuming(&mut harness, &client_id).await;
+
+        tokio::time::sleep(SESSION_EXPIRY_INTERVAL + Duration::from_secs(1)).await;
+        assert_eq!(get_session_expiry_interval(&*harness.client_handler.sessions, &client_id), Some(SESSION_EXPIRY_INTERVAL.as_secs() as u32));
+    }
+}
@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod shared_rebalance_tests {
+    use std::net::SocketAddr;
+
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::tests::broker::broker_tests_data::{create_disconnect_packet, create_publish_packet_qos1};
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    //Sockets the PUBLISH packets among the given ones went to
+    fn published_to(packets: Vec<(Vec<SocketAddr>, ControlPacket)>) -> Vec<SocketAddr> {
+        packets.into_iter()
+            .filter(|(_, control_packet)| control_packet.fixed_header().packet_type() == ControlPacketType::PUBLISH)
+            .flat_map(|(sockets, _)| sockets)
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn shared_subscription_delivers_to_one_member() {
+        let mut harness = HandlerHarness::default();
+        let first = harness.connect("shared-one-first").await;
+        let second = harness.connect("shared-one-second").await;
+        harness.subscribe(first, "$share/one/shared/one/#", QoSLevel::AtLeastOnce).await;
+        harness.subscribe(second, "$share/one/shared/one/#", QoSLevel::AtLeastOnce).await;
+
+        let publisher = harness.connect("shared-one-publisher").await;
+        harness.send(publisher, create_publish_packet_qos1(1, String::from("shared/one/a"))).await.unwrap();
+        let first_delivery = published_to(harness.drain());
+        harness.send(publisher, create_publish_packet_qos1(2, String::from("shared/one/b"))).await.unwrap();
+        let second_delivery = published_to(harness.drain());
+
+        assert_eq!(first_delivery.len(), 1);
+        assert_eq!(second_delivery.len(), 1);
+        assert_ne!(first_delivery[0], second_delivery[0]);
+        assert!([first, second].contains(&first_delivery[0]));
+        assert!([first, second].contains(&second_delivery[0]));
+    }
+
+    #[tokio::test]
+    async fn leaving_member_hands_unacknowledged_to_group() {
+        let mut harness = HandlerHarness::default();
+        let first = harness.connect("shared-leave-first").await;
+        let second = harness.connect("shared-leave-second").await;
+        harness.subscribe(first, "$share/leave/shared/leave/#", QoSLevel::AtLeastOnce).await;
+        harness.subscribe(second, "$share/leave/shared/leave/#", QoSLevel::AtLeastOnce).await;
+
+        let publisher = harness.connect("shared-leave-publisher").await;
+        harness.send(publisher, create_publish_packet_qos1(1, String::from("shared/leave/a"))).await.unwrap();
+        let delivered = published_to(harness.drain());
+        assert_eq!(delivered.len(), 1);
+        let (leaving, staying) = if delivered[0] == first { (first, second) } else { (second, first) };
+
+        harness.send(leaving, create_disconnect_packet(ReasonCode::NormalDisconnection, vec![])).await.unwrap();
+        let packets = harness.drain();
+        assert!(packets.iter().any(|(sockets, control_packet)| sockets == &vec![leaving] && control_packet.fixed_header().packet_type() == ControlPacketType::DISCONNECT));
+        assert_eq!(published_to(packets), vec![staying]);
+
+        let metrics = &harness.packet_dispatcher.shared_rebalance.metrics;
+        assert_eq!(metrics.rebalanced.0.get(), 1);
+        assert_eq!(metrics.stranded.0.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn last_member_keeps_unacknowledged() {
+        let mut harness = HandlerHarness::default();
+        let member = harness.connect("shared-last-member").await;
+        harness.subscribe(member, "$share/last/shared/last", QoSLevel::AtLeastOnce).await;
+
+        let publisher = harness.connect("shared-last-publisher").await;
+        harness.send(publisher, create_publish_packet_qos1(1, String::from("shared/last"))).await.unwrap();
+        assert_eq!(published_to(harness.drain()), vec![member]);
+
+        harness.send(member, create_disconnect_packet(ReasonCode::NormalDisconnection, vec![])).await.unwrap();
+        harness.expect(ControlPacketType::DISCONNECT).await;
+        harness.expect_nothing();
+
+        let metrics = &harness.packet_dispatcher.shared_rebalance.metrics;
+        assert_eq!(metrics.rebalanced.0.get(), 0);
+        assert_eq!(metrics.stranded.0.get(), 1);
+    }
+}
@@ -0,0 +1,124 @@
+#[cfg(test)]
+mod supervisor_tests {
+    use std::sync::{Arc, Mutex};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use tokio::sync::oneshot;
+
+    use crate::broker::supervisor::Supervisor;
+    use crate::config::broker_config::SupervisorConfig;
+
+    //Records the name of its subsystem when the task running it is dropped
+    struct StopGuard {
+        name: &'static str,
+        stopped: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl Drop for StopGuard {
+        fn drop(&mut self) {
+            self.stopped.lock().unwrap().push(self.name);
+        }
+    }
+
+    fn add_pending(supervisor: &mut Supervisor, name: &'static str, depends_on: &[&'static str], stopped: &Arc<Mutex<Vec<&'static str>>>) {
+        let stopped = stopped.clone();
+        supervisor.add(name, depends_on, move || {
+            let guard = StopGuard { name, stopped: stopped.clone() };
+            async move {
+                let _guard = guard;
+                std::future::pending::<()>().await;
+                Ok(())
+            }
+        });
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_maximum() {
+        let config = SupervisorConfig { initial_backoff_ms: 100, max_backoff_ms: 1000, stable_after_secs: 60 };
+        assert_eq!(config.backoff(1), Duration::from_millis(100));
+        assert_eq!(config.backoff(2), Duration::from_millis(200));
+        assert_eq!(config.backoff(4), Duration::from_millis(800));
+        assert_eq!(config.backoff(5), Duration::from_millis(1000));
+        assert_eq!(config.backoff(100), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn dependencies_start_first() {
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        let mut supervisor = Supervisor::new(SupervisorConfig::default());
+        add_pending(&mut supervisor, "listener", &["broker"], &stopped);
+        add_pending(&mut supervisor, "broker", &["writer"], &stopped);
+        add_pending(&mut supervisor, "writer", &[], &stopped);
+        add_pending(&mut supervisor, "telemetry", &[], &stopped);
+        assert_eq!(supervisor.start_order().unwrap(), vec!["writer", "broker", "listener", "telemetry"]);
+
+        add_pending(&mut supervisor, "gateway", &["bridge"], &stopped);
+        assert!(supervisor.start_order().unwrap_err().contains("unknown subsystem bridge"));
+        add_pending(&mut supervisor, "bridge", &["gateway"], &stopped);
+        assert!(supervisor.start_order().unwrap_err().contains("depend on each other"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restart_crashed_subsystem_after_backoff() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let mut supervisor = Supervisor::new(SupervisorConfig { initial_backoff_ms: 100, max_backoff_ms: 1000, stable_after_secs: 60 });
+        let starts_ = starts.clone();
+        supervisor.add("flaky", &[], move || {
+            let start = starts_.fetch_add(1, Ordering::SeqCst);
+            async move {
+                match start {
+                    0 => { panic!("first run crashes") }
+                    1 => { Err(String::from("second run fails")) }
+                    _ => { std::future::pending::<()>().await; Ok(()) }
+                }
+            }
+        });
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let supervisor = tokio::spawn(supervisor.run(async { let _ = shutdown_rx.await; }));
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+        //100ms after the panic, then 200ms after the error
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(starts.load(Ordering::SeqCst), 2);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(starts.load(Ordering::SeqCst), 3);
+
+        shutdown_tx.send(()).unwrap();
+        assert_eq!(supervisor.await.unwrap(), Ok(()));
+        assert_eq!(starts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn finished_subsystem_is_not_restarted() {
+        let starts = Arc::new(AtomicUsize::new(0));
+        let mut supervisor = Supervisor::new(SupervisorConfig::default());
+        let starts_ = starts.clone();
+        supervisor.add("once", &[], move || {
+            starts_.fetch_add(1, Ordering::SeqCst);
+            async { Ok(()) }
+        });
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let supervisor = tokio::spawn(supervisor.run(async { let _ = shutdown_rx.await; }));
+
+        tokio::time::sleep(Duration::from_secs(120)).await;
+        assert_eq!(starts.load(Ordering::SeqCst), 1);
+        shutdown_tx.send(()).unwrap();
+        assert_eq!(supervisor.await.unwrap(), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn shutdown_in_reverse_dependency_order() {
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        let mut supervisor = Supervisor::new(SupervisorConfig::default());
+        add_pending(&mut supervisor, "listener", &["broker"], &stopped);
+        add_pending(&mut supervisor, "broker", &["writer"], &stopped);
+        add_pending(&mut supervisor, "writer", &[], &stopped);
+
+        supervisor.run(tokio::time::sleep(Duration::from_millis(10))).await.unwrap();
+        assert_eq!(*stopped.lock().unwrap(), vec!["listener", "broker", "writer"]);
+    }
+}
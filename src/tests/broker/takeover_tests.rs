@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod takeover_tests {
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet, create_connect_packet_resuming_session, create_publish_packet_qos1};
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    const TOPIC: &str = "takeover/commands";
+
+    //A subscriber with a QoS 1 publish it didn't acknowledge, and its socket
+    async fn subscriber_with_unacknowledged(harness: &mut HandlerHarness, client_id: &str) -> std::net::SocketAddr {
+        let previous_socket = HandlerHarness::socket();
+        harness.send(previous_socket, create_connect_packet_resuming_session(client_id.to_string())).await.unwrap();
+        harness.expect(ControlPacketType::CONNACK).await;
+        harness.subscribe(previous_socket, TOPIC, QoSLevel::AtLeastOnce).await;
+        let publisher = harness.connect(&format!("{}-publisher", client_id)).await;
+        harness.send(publisher, create_publish_packet_qos1(7, TOPIC.to_string())).await.unwrap();
+        harness.expect(ControlPacketType::PUBACK).await;
+        let (sockets, _) = harness.expect(ControlPacketType::PUBLISH).await;
+        assert_eq!(sockets, vec![previous_socket]);
+        previous_socket
+    }
+
+    #[tokio::test]
+    async fn resumed_session_gets_unacknowledged_publishes_again() {
+        let mut harness = HandlerHarness::default();
+        let client_id = "takeover-resume";
+        let previous_socket = subscriber_with_unacknowledged(&mut harness, client_id).await;
+
+        let socket = HandlerHarness::socket();
+        harness.send(socket, create_connect_packet_resuming_session(client_id.to_string())).await.unwrap();
+        let (sockets, disconnect_packet) = harness.expect(ControlPacketType::DISCONNECT).await;
+        assert_eq!((sockets, disconnect_packet.variable_header().reason_code()), (vec![previous_socket], Some(&ReasonCode::SessionTakenOver)));
+        let (sockets, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert_eq!(sockets, vec![socket]);
+        assert!(connack_packet.variable_header().connect_acknowledge_flags().session_present());
+        let (sockets, publish_packet) = harness.expect(ControlPacketType::PUBLISH).await;
+        assert_eq!(sockets, vec![socket]);
+        assert!(*publish_packet.fixed_header().dup_flag());
+        assert_eq!(harness.packet_dispatcher.takeover_tracker.metrics.resent.0.get(), 1);
+
+        //The subscription came along
+        harness.send(socket, ControlPacket::puback(Some(publish_packet.variable_header().packet_identifier()))).await.unwrap();
+        let publisher = harness.connect("takeover-resume-second-publisher").await;
+        harness.send(publisher, create_publish_packet_qos1(8, TOPIC.to_string())).await.unwrap();
+        harness.expect(ControlPacketType::PUBACK).await;
+        let (sockets, _) = harness.expect(ControlPacketType::PUBLISH).await;
+        assert_eq!(sockets, vec![socket]);
+    }
+
+    #[tokio::test]
+    async fn clean_start_drops_unacknowledged_publishes() {
+        let mut harness = HandlerHarness::default();
+        let client_id = "takeover-clean";
+        subscriber_with_unacknowledged(&mut harness, client_id).await;
+
+        harness.send(HandlerHarness::socket(), create_connect_packet(client_id.to_string())).await.unwrap();
+        harness.expect(ControlPacketType::DISCONNECT).await;
+        let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert!(!connack_packet.variable_header().connect_acknowledge_flags().session_present());
+        harness.expect_nothing();
+        assert_eq!(harness.packet_dispatcher.takeover_tracker.metrics.resent.0.get(), 0);
+    }
+}
@@ -0,0 +1,84 @@
+#[cfg(test)]
+mod unsubscribe_drop_tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use crate::broker::message_expiry::StoredMessage;
+    use crate::config::broker_config::BrokerConfig;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::variable_header::Property;
+    use crate::session::session_handler::SessionHandler;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet_with_properties, create_publish_packet_qos1, create_unsubscribe_packet};
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    //A subscriber with a Receive Maximum of 1, one publish on drop/a/1 in flight, two more and one on drop/b deferred
+    async fn subscriber_with_deferred(harness: &mut HandlerHarness, client_id: &str) -> SocketAddr {
+        let subscriber = HandlerHarness::socket();
+        harness.send(subscriber, create_connect_packet_with_properties(client_id.to_string(), vec![Property::ReceiveMaximum(1)])).await.unwrap();
+        harness.expect(ControlPacketType::CONNACK).await;
+        harness.subscribe(subscriber, "drop/a/+", QoSLevel::AtLeastOnce).await;
+        harness.subscribe(subscriber, "drop/b", QoSLevel::AtLeastOnce).await;
+        let publisher = harness.connect(&format!("{}-publisher", client_id)).await;
+        for (packet_identifier, topic_name) in [(1, "drop/a/1"), (2, "drop/a/1"), (3, "drop/a/2"), (4, "drop/b")] {
+            harness.send(publisher, create_publish_packet_qos1(packet_identifier, topic_name.to_string())).await.unwrap();
+        }
+        let sent: Vec<u16> = harness.drain().into_iter()
+            .filter(|(sockets, packet)| *sockets == vec![subscriber] && packet.fixed_header().packet_type() == ControlPacketType::PUBLISH)
+            .map(|(_, packet)| packet.variable_header().packet_identifier())
+            .collect();
+        assert_eq!(sent, [1]);
+        subscriber
+    }
+
+    async fn unsubscribe_and_acknowledge(harness: &mut HandlerHarness, subscriber: SocketAddr) -> ControlPacket {
+        harness.send(subscriber, create_unsubscribe_packet(2, vec![String::from("drop/a/+")])).await.unwrap();
+        harness.expect(ControlPacketType::UNSUBACK).await;
+        harness.send(subscriber, ControlPacket::puback(Some(1))).await.unwrap();
+        let (sockets, publish_packet) = harness.expect(ControlPacketType::PUBLISH).await;
+        assert_eq!(sockets, vec![subscriber]);
+        publish_packet
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_drops_deferred_publishes() {
+        let mut config = BrokerConfig::default();
+        config.subscription.drop_queued_on_unsubscribe = true;
+        let mut harness = HandlerHarness::new(config);
+        let subscriber = subscriber_with_deferred(&mut harness, "drop-deferred").await;
+
+        //The one in flight completes, the deferred one on drop/b is next
+        let publish_packet = unsubscribe_and_acknowledge(&mut harness, subscriber).await;
+        assert_eq!(publish_packet.variable_header().topic_name(), "drop/b");
+        assert_eq!(harness.packet_dispatcher.unsubscribe_handler.queued_metrics.dropped.0.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn unsubscribe_keeps_deferred_publishes_by_default() {
+        let mut harness = HandlerHarness::default();
+        let subscriber = subscriber_with_deferred(&mut harness, "drop-default").await;
+
+        let publish_packet = unsubscribe_and_acknowledge(&mut harness, subscriber).await;
+        assert_eq!(publish_packet.variable_header().packet_identifier(), 2);
+        assert_eq!(harness.packet_dispatcher.unsubscribe_handler.queued_metrics.dropped.0.get(), 0);
+    }
+
+    #[test]
+    fn drop_only_messages_of_unsubscribed_filters() {
+        let session = SessionHandler::new();
+        let client_id = String::from("drop-session");
+        let (alarms, everything) = (Arc::new(String::from("alarms/+")), Arc::new(String::from("#")));
+        session.register_publish(client_id.clone(), &create_publish_packet_qos1(1, String::from("alarms/fire")), Instant::now(), &[alarms.clone()]);
+        session.register_publish(client_id.clone(), &create_publish_packet_qos1(2, String::from("alarms/fire")), Instant::now(), &[alarms.clone(), everything]);
+        session.register_publish(client_id.clone(), &create_publish_packet_qos1(3, String::from("alarms/flood")), Instant::now(), &[alarms.clone()]);
+        session.register_publish(client_id.clone(), &create_publish_packet_qos1(4, String::from("alarms/fire")), Instant::now(), &[]);
+
+        //3 was sent already, 2 also came through #, 4 came through no subscription
+        let dropped = session.drop_unsubscribed(&[alarms.to_string()], |packet| packet.variable_header().packet_identifier() == 3);
+        assert_eq!(dropped.iter().map(|packet| packet.variable_header().packet_identifier()).collect::<Vec<u16>>(), [1]);
+        assert_eq!(session.queued_len(), 3);
+        assert!(!StoredMessage::new(create_publish_packet_qos1(5, String::from("alarms/fire")), Instant::now()).only_through(&[alarms.to_string()]));
+    }
+}
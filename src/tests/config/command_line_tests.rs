@@ -0,0 +1,55 @@
+#[cfg(test)]
+mod command_line_tests {
+    use crate::config::command_line::{Command, CommandLine, DEFAULT_CONFIG_PATH, StateTarget};
+
+    fn parse(args: &[&str]) -> Result<CommandLine, String> {
+        CommandLine::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_defaults() {
+        assert_eq!(parse(&[]).unwrap(), CommandLine { config_path: String::from(DEFAULT_CONFIG_PATH), overrides: vec![], command: Command::Run });
+    }
+
+    #[test]
+    fn parse_config_and_overrides() {
+        let command_line = parse(&["--set", "packet.maximum_packet_size=1024", "--config", "/etc/patina.yaml", "--set", "audit.enabled=true"]).unwrap();
+        assert_eq!(command_line.config_path, "/etc/patina.yaml");
+        assert_eq!(command_line.overrides, vec![String::from("packet.maximum_packet_size=1024"), String::from("audit.enabled=true")]);
+    }
+
+    #[test]
+    fn parse_invalid_arguments() {
+        assert!(parse(&["--config"]).is_err());
+        assert!(parse(&["--set"]).is_err());
+        assert!(parse(&["--verbose"]).is_err());
+    }
+
+    #[test]
+    fn parse_state_commands() {
+        let command_line = parse(&["export-state", "--out", "state.json", "--config", "/etc/patina.yaml"]).unwrap();
+        assert_eq!(command_line.command, Command::ExportState { path: String::from("state.json"), target: StateTarget::Storage });
+        assert_eq!(command_line.config_path, "/etc/patina.yaml");
+        let command_line = parse(&["import-state", "--admin-api", "--in", "state.json", "--token", "secret"]).unwrap();
+        assert_eq!(command_line.command, Command::ImportState { path: String::from("state.json"), target: StateTarget::AdminApi { token: Some(String::from("secret")) } });
+        assert_eq!(parse(&["export-state", "--admin-api", "--out", "state.json"]).unwrap().command, Command::ExportState { path: String::from("state.json"), target: StateTarget::AdminApi { token: None } });
+    }
+
+    #[test]
+    fn parse_print_default_config() {
+        assert_eq!(parse(&["--print-default-config"]).unwrap().command, Command::PrintDefaultConfig);
+        assert!(parse(&["export-state", "--out", "state.json", "--print-default-config"]).is_err());
+        assert!(parse(&["--print-default-config", "--admin-api"]).is_err());
+    }
+
+    #[test]
+    fn parse_invalid_state_commands() {
+        assert!(parse(&["export-state"]).is_err());
+        assert!(parse(&["export-state", "--in", "state.json"]).is_err());
+        assert!(parse(&["import-state", "--in", "state.json", "--out", "other.json"]).is_err());
+        assert!(parse(&["import-state", "--in", "state.json", "--token", "secret"]).is_err());
+        assert!(parse(&["--out", "state.json"]).is_err());
+        assert!(parse(&["--admin-api"]).is_err());
+        assert!(parse(&["backup"]).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+#[cfg(test)]
+mod config_provenance_tests {
+    use std::fs;
+
+    use serde_json::Value;
+
+    use crate::config::broker_config::{BrokerConfig, OverlapPolicy};
+    use crate::config::config_provenance::ConfigSource;
+
+    const CONFIG: &str = "
+packet:
+  maximum_packet_size: 65536
+admin:
+  api_token: secret
+  tokens:
+    - name: grafana
+      token: secret-read
+      role: read
+quota:
+  profiles:
+    free:
+      max_queued: 100
+auth:
+  users:
+    sensor: secret-password
+";
+
+    fn write_config(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("patina-{}-{}.yaml", name, std::process::id()));
+        fs::write(&path, CONFIG).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn effective_config_sources() {
+        let path = write_config("config-sources");
+        let overrides = vec![String::from("packet.maximum_packet_size=1024"), String::from("subscription.overlap_policy=per-subscription")];
+        let config = BrokerConfig::load_with_overrides(&path, &overrides);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(config.packet.maximum_packet_size, 1024);
+        assert_eq!(config.subscription.overlap_policy, OverlapPolicy::PerSubscription);
+        assert_eq!(config.admin.api_token, Some(String::from("secret")));
+
+        let values = config.effective();
+        assert_eq!(values["packet.maximum_packet_size"].value, Value::from(1024));
+        assert_eq!(values["packet.maximum_packet_size"].source, ConfigSource::Cli);
+        assert_eq!(values["subscription.overlap_policy"].source, ConfigSource::Cli);
+        assert_eq!(values["quota.profiles.free.max_queued"].value, Value::from(100));
+        assert_eq!(values["quota.profiles.free.max_queued"].source, ConfigSource::File);
+        assert_eq!(values["audit.enabled"].value, Value::from(false));
+        assert_eq!(values["audit.enabled"].source, ConfigSource::Default);
+    }
+
+    #[test]
+    fn effective_config_redacts_secrets() {
+        let path = write_config("config-secrets");
+        let config = BrokerConfig::load(&path);
+        fs::remove_file(&path).unwrap();
+        let values = config.effective();
+        assert_eq!(values["admin.api_token"].value, Value::from("<redacted>"));
+        assert_eq!(values["admin.api_token"].source, ConfigSource::File);
+        assert_eq!(values["admin.tokens"].value[0]["name"], Value::from("grafana"));
+        assert_eq!(values["admin.tokens"].value[0]["token"], Value::from("<redacted>"));
+        assert_eq!(values["auth.users.sensor"].value, Value::from("<redacted>"));
+        assert!(!serde_json::to_string(&values).unwrap().contains("secret"));
+
+        let values = BrokerConfig::default().effective();
+        assert_eq!(values["admin.api_token"].value, Value::Null);
+    }
+
+    #[test]
+    fn invalid_overrides_are_ignored() {
+        let overrides = vec![String::from("audit.enabled"), String::from("audit..path=x"), String::from("audit.max_files=3")];
+        let config = BrokerConfig::load_with_overrides("missing/patina.yaml", &overrides);
+        assert_eq!(config.audit.max_files, 3);
+        assert_eq!(config.effective()["audit.max_files"].source, ConfigSource::Cli);
+        assert_eq!(config.effective()["audit.path"].source, ConfigSource::Default);
+    }
+}
@@ -0,0 +1,103 @@
+#[cfg(test)]
+mod config_schema_tests {
+    use std::fs;
+
+    use crate::config::broker_config::BrokerConfig;
+    use crate::config::command_line::DEFAULT_CONFIG_PATH;
+    use crate::config::config_schema::{default_config_yaml, invalid_values};
+    use crate::error::StartupError;
+
+    fn load(name: &str, content: &str, overrides: &[&str]) -> Result<BrokerConfig, Vec<String>> {
+        let path = std::env::temp_dir().join(format!("patina-{}-{}.yaml", name, std::process::id()));
+        fs::write(&path, content).unwrap();
+        let overrides: Vec<String> = overrides.iter().map(|assignment| assignment.to_string()).collect();
+        let result = BrokerConfig::load_checked(path.to_str().unwrap(), &overrides);
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    #[test]
+    fn flag_unknown_keys_with_suggestions() {
+        let content = "
+packet:
+  maximum_packet_sise: 1024
+gateway:
+  coap:
+    prot: 5683
+    mappings:
+      - path: sensors
+        topic: devices/sensors
+        topc: devices/sensors
+quota:
+  profiles:
+    free:
+      max_inflite: 10
+  usernames:
+    fleet: free
+retained: {}
+";
+        let problems = load("unknown-keys", content, &["audit.enabld=true", "auth.users.sensor=secret"]).unwrap_err();
+        assert_eq!(problems, vec![
+            String::from("Unknown key packet.maximum_packet_sise, did you mean packet.maximum_packet_size?"),
+            String::from("Unknown key gateway.coap.prot, did you mean gateway.coap.port?"),
+            String::from("Unknown key gateway.coap.mappings[0].topc, did you mean gateway.coap.mappings[0].topic?"),
+            String::from("Unknown key quota.profiles.free.max_inflite, did you mean quota.profiles.free.max_inflight?"),
+            String::from("Unknown key retained"),
+            String::from("Unknown key audit.enabld, did you mean audit.enabled?"),
+        ]);
+        //Logged and ignored
+        let config = BrokerConfig::load_with_overrides("missing/patina.yaml", &[String::from("packet.maximum_packet_sise=1024"), String::from("audit.max_files=3")]);
+        assert_eq!(config.audit.max_files, 3);
+    }
+
+    #[test]
+    fn name_the_key_of_values_serde_refuses() {
+        let problems = load("wrong-types", "gateway:\n  coap:\n    port: 70000\n", &[]).unwrap_err();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].ends_with("gateway.coap.port: invalid value: integer `70000`, expected u16"), "{}", problems[0]);
+        let problems = load("unknown-variant", "", &["acl.no_match=maybe"]).unwrap_err();
+        assert!(problems[0].ends_with("acl.no_match: unknown variant `maybe`, expected `allow` or `deny`"), "{}", problems[0]);
+        let problems = load("invalid-yaml", "packet: [", &["audit.max_files"]).unwrap_err();
+        assert!(problems[0].starts_with("Can't parse config file"), "{}", problems[0]);
+        assert!(problems[1].contains("is not key=value"), "{}", problems[1]);
+    }
+
+    #[test]
+    fn check_value_ranges() {
+        let mut config = BrokerConfig::default();
+        assert!(invalid_values(&config).is_empty());
+        config.gateway.mqtt_sn.port = 0;
+        config.subnet_stats.ipv4_prefix_len = 33;
+        config.congestion.retry_after_min_secs = 600;
+        config.quota.default_profile = Some(String::from("gold"));
+        config.quota.usernames.insert(String::from("fleet"), String::from("silver"));
+        assert_eq!(invalid_values(&config), vec![
+            String::from("gateway.mqtt_sn.port must be 1-65535, not 0"),
+            String::from("congestion.retry_after_min_secs must be at most congestion.retry_after_max_secs (300), not 600"),
+            String::from("subnet_stats.ipv4_prefix_len must be 0-32, not 33"),
+            String::from("quota.default_profile \"gold\" is not in quota.profiles"),
+            String::from("quota.usernames.fleet \"silver\" is not in quota.profiles"),
+        ]);
+        let problems = load("invalid-values", "keep_alive:\n  grace_factor: 0.5\n", &["dispatch.max_attempts=0"]).unwrap_err();
+        assert_eq!(problems, vec![String::from("keep_alive.grace_factor must be at least 1, not 0.5"), String::from("dispatch.max_attempts must be at least 1, not 0")]);
+
+        let startup_error = StartupError::InvalidConfig(problems);
+        assert_eq!(startup_error.exit_code(), 2);
+        assert_eq!(startup_error.to_string().lines().next(), Some("startup_error kind=config detail=\"keep_alive.grace_factor must be at least 1, not 0.5\""));
+    }
+
+    #[test]
+    fn shipped_and_default_configs_are_valid() {
+        assert!(BrokerConfig::load_checked(DEFAULT_CONFIG_PATH, &[]).is_ok());
+        assert!(BrokerConfig::load_checked("missing/patina.yaml", &[]).is_ok());
+
+        let reference = default_config_yaml();
+        assert!(reference.contains("# Upper bound in bytes for any packet read from a client\n  maximum_packet_size: 268435460\n"));
+        assert!(reference.contains("# Only used when the broker is built with the coap feature\n  coap:\n    enabled: false\n"));
+        assert!(reference.contains("# What to do when a CONNECT arrives with a client_id that is already connected\n  takeover_policy: \"kick-old\"\n"));
+        assert!(!reference.contains("provenance"));
+        let config = load("reference", &reference, &[]).unwrap();
+        let values = |config: &BrokerConfig| config.effective().into_iter().map(|(key, config_value)| (key, config_value.value)).collect::<Vec<_>>();
+        assert_eq!(values(&config), values(&BrokerConfig::default()));
+    }
+}
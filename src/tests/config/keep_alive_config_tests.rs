@@ -0,0 +1,16 @@
+#[cfg(test)]
+mod keep_alive_config_tests {
+    use std::time::Duration;
+
+    use crate::config::broker_config::KeepAliveConfig;
+
+    #[test]
+    fn keep_alive_deadline() {
+        let config = KeepAliveConfig::default();
+        assert_eq!(config.deadline(0), None);
+        assert_eq!(config.deadline(10), Some(Duration::from_millis(15_500)));
+
+        let config = KeepAliveConfig { grace_factor: 0.5, jitter_tolerance_ms: 0, ..KeepAliveConfig::default() };
+        assert_eq!(config.deadline(10), Some(Duration::from_secs(10)));
+    }
+}
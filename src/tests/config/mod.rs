@@ -0,0 +1,6 @@
+pub mod command_line_tests;
+pub mod config_provenance_tests;
+pub mod config_schema_tests;
+pub mod keep_alive_config_tests;
+pub mod storage_config_tests;
+pub mod port_check_tests;
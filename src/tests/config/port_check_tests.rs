@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod port_check_tests {
+    use std::net::{SocketAddr, TcpListener, UdpSocket};
+
+    use crate::config::broker_config::BrokerConfig;
+    use crate::config::port_check::{ADMIN_API_ADDRESS, check_ports, ConflictKind, configured_endpoints, Endpoint, MQTT_LISTENER_ADDRESS, Transport};
+    use crate::error::StartupError;
+
+    fn free_port(transport: Transport) -> SocketAddr {
+        match transport {
+            Transport::Tcp => { TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap() }
+            Transport::Udp => { UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap() }
+        }
+    }
+
+    #[test]
+    fn endpoints_of_the_config() {
+        let mut config = BrokerConfig::default();
+        config.gateway.mqtt_sn.enabled = true;
+        config.gateway.mqtt_sn.port = 11884;
+        let endpoints = configured_endpoints(&config);
+        assert_eq!(endpoints[0], Endpoint { name: "mqtt", transport: Transport::Tcp, address: MQTT_LISTENER_ADDRESS });
+        assert_eq!(endpoints.iter().any(|endpoint| endpoint.address == ADMIN_API_ADDRESS), cfg!(feature = "admin-api"));
+        assert_eq!(endpoints.iter().any(|endpoint| endpoint.name == "mqtt-sn"), cfg!(feature = "mqtt-sn"));
+        assert!(endpoints.iter().all(|endpoint| endpoint.name != "coap"));
+    }
+
+    #[test]
+    fn free_ports_pass() {
+        let endpoints = vec![
+            Endpoint { name: "mqtt", transport: Transport::Tcp, address: free_port(Transport::Tcp) },
+            Endpoint { name: "coap", transport: Transport::Udp, address: free_port(Transport::Udp) },
+        ];
+        assert_eq!(check_ports(&endpoints), vec![]);
+    }
+
+    #[test]
+    fn report_every_conflict() {
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let free = free_port(Transport::Tcp);
+        let endpoints = vec![
+            Endpoint { name: "mqtt", transport: Transport::Tcp, address: taken.local_addr().unwrap() },
+            Endpoint { name: "admin-api", transport: Transport::Tcp, address: free },
+            //Every address overlaps the loopback one
+            Endpoint { name: "metrics", transport: Transport::Tcp, address: SocketAddr::from(([0, 0, 0, 0], free.port())) },
+            //UDP doesn't share ports with TCP
+            Endpoint { name: "mqtt-sn", transport: Transport::Udp, address: SocketAddr::from(([127, 0, 0, 1], free_port(Transport::Udp).port())) },
+        ];
+        let conflicts = check_ports(&endpoints);
+        assert_eq!(conflicts.iter().map(|conflict| (conflict.endpoint.name, conflict.kind)).collect::<Vec<_>>(), vec![("mqtt", ConflictKind::InUse), ("metrics", ConflictKind::Duplicate)]);
+        assert_eq!(conflicts[1].detail, format!("same port as admin-api on {}", free));
+        assert!(conflicts[0].to_string().starts_with(&format!("port_conflict endpoint=mqtt transport=tcp address={} reason=in-use detail=", taken.local_addr().unwrap())));
+
+        let startup_error = StartupError::PortConflicts(conflicts);
+        assert_eq!(startup_error.exit_code(), 4);
+        assert_eq!(startup_error.to_string().lines().count(), 2);
+    }
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(StartupError::CommandLine(String::from("Unknown argument --verbose")).exit_code(), 2);
+        assert_eq!(StartupError::Config(String::from("storage.mode is memory but audit.enabled needs the disk")).exit_code(), 2);
+        let codes: Vec<i32> = [ConflictKind::Duplicate, ConflictKind::InUse, ConflictKind::PermissionDenied, ConflictKind::Unavailable].iter().map(|kind| kind.exit_code()).collect();
+        assert_eq!(codes, vec![3, 4, 5, 6]);
+    }
+}
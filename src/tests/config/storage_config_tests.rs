@@ -0,0 +1,23 @@
+#[cfg(test)]
+mod storage_config_tests {
+    use crate::config::broker_config::{BrokerConfig, StorageMode};
+
+    #[test]
+    fn memory_mode_refuses_disk_features() {
+        let mut config = BrokerConfig::default();
+        config.audit.enabled = true;
+        assert_eq!(config.check_storage(), Ok(()));
+
+        config.storage.mode = StorageMode::Memory;
+        assert_eq!(config.check_storage(), Err(String::from("storage.mode is memory but audit.enabled needs the disk")));
+
+        config.audit.enabled = false;
+        assert_eq!(config.check_storage(), Ok(()));
+    }
+
+    #[test]
+    fn memory_mode_from_yaml() {
+        let config: BrokerConfig = serde_yaml::from_str("storage:\n  mode: memory\n").unwrap();
+        assert_eq!(config.storage.mode, StorageMode::Memory);
+    }
+}
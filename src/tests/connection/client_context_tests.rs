@@ -0,0 +1,34 @@
+#[cfg(test)]
+mod client_context_tests {
+    use std::net::SocketAddr;
+
+    use crate::connection::client_context::ClientContext;
+    use crate::error::PatinaError;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet, create_connect_packet_with_username};
+
+    fn socket() -> SocketAddr {
+        "127.0.0.1:1883".parse().unwrap()
+    }
+
+    #[test]
+    fn connect_fills_in_username_and_protocol_version() {
+        let mut context = ClientContext::new(socket());
+        context.connected(&create_connect_packet_with_username(String::from("client-1"), String::from("alice")));
+        assert_eq!(context.username, Some(String::from("alice")));
+        assert_eq!(context.protocol_version, Some(5));
+
+        context.connected(&create_connect_packet(String::from("client-1")));
+        assert_eq!(context.username, None);
+    }
+
+    #[test]
+    fn next_keeps_the_connection_and_stamps_the_packet() {
+        let mut context = ClientContext::new(socket());
+        context.connected(&create_connect_packet_with_username(String::from("client-1"), String::from("alice")));
+        let next = context.next().with_client_id(Some(String::from("client-1")));
+        assert_eq!(next.username, context.username);
+        assert!(next.received_at >= context.received_at);
+        assert_eq!(next.client_id().unwrap(), "client-1");
+        assert_eq!(context.client_id(), Err(PatinaError::UnknownClient(socket())));
+    }
+}
@@ -0,0 +1,53 @@
+#[cfg(test)]
+mod listeners_tests {
+    use crate::config::broker_config::BrokerConfig;
+    use crate::connection::listeners::{ListenerMode, Listeners, MQTT_LISTENER};
+
+    #[test]
+    fn list_listeners_taking_connections() {
+        let listeners = Listeners::new(&BrokerConfig::default());
+        let statuses = listeners.statuses();
+        //The admin API takes no client connections
+        assert_eq!(statuses.len(), 1);
+        assert_eq!(statuses[0].name, MQTT_LISTENER);
+        assert_eq!(statuses[0].transport, "tcp");
+        assert_eq!(statuses[0].address, "0.0.0.0:1883");
+        assert_eq!(statuses[0].mode, ListenerMode::Accepting);
+        assert!(listeners.watch("admin-api").is_none());
+        assert!(listeners.set_mode("coap", ListenerMode::Draining).is_none());
+    }
+
+    #[test]
+    fn drain_and_resume_listener() {
+        let listeners = Listeners::new(&BrokerConfig::default());
+        let mut mode = listeners.watch(MQTT_LISTENER).unwrap();
+        let since = listeners.statuses()[0].since;
+        assert!(!mode.has_changed().unwrap());
+
+        assert_eq!(listeners.set_mode(MQTT_LISTENER, ListenerMode::Draining).unwrap().mode, ListenerMode::Draining);
+        assert!(mode.has_changed().unwrap());
+        assert_eq!(*mode.borrow_and_update(), ListenerMode::Draining);
+        //Already draining
+        let status = listeners.set_mode(MQTT_LISTENER, ListenerMode::Draining).unwrap();
+        assert!(!mode.has_changed().unwrap());
+        assert!(status.since >= since);
+
+        listeners.set_mode(MQTT_LISTENER, ListenerMode::Accepting);
+        assert_eq!(*mode.borrow_and_update(), ListenerMode::Accepting);
+    }
+
+    #[test]
+    fn count_open_connections() {
+        let listeners = Listeners::new(&BrokerConfig::default());
+        listeners.opened(MQTT_LISTENER);
+        listeners.opened(MQTT_LISTENER);
+        listeners.closed(MQTT_LISTENER);
+        assert_eq!(listeners.statuses()[0].open_connections, 1);
+        listeners.closed(MQTT_LISTENER);
+        listeners.closed(MQTT_LISTENER);
+        assert_eq!(listeners.statuses()[0].open_connections, 0);
+        //Connections of a listener the config doesn't enable aren't counted
+        listeners.opened("mqtt-sn");
+        assert_eq!(listeners.statuses().len(), 1);
+    }
+}
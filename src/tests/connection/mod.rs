@@ -0,0 +1,8 @@
+pub mod client_context_tests;
+pub mod rx_connection_handler_tests;
+pub mod socket_options_tests;
+pub mod subnet_stats_tests;
+pub mod topic_aliases_tests;
+pub mod pending_handshakes_tests;
+pub mod listeners_tests;
+pub mod tx_connection_handler_tests;
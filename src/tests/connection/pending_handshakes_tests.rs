@@ -0,0 +1,42 @@
+#[cfg(test)]
+mod pending_handshakes_tests {
+    use std::net::{IpAddr, Ipv6Addr};
+
+    use crate::config::broker_config::HandshakeConfig;
+    use crate::connection::pending_handshakes::PendingHandshakes;
+
+    fn pending_handshakes(max_pending_per_ip: usize) -> PendingHandshakes {
+        PendingHandshakes::new(HandshakeConfig { max_pending_per_ip, connect_timeout_secs: 10 })
+    }
+
+    #[test]
+    fn limit_pending_connections_per_address() {
+        let pending_handshakes = pending_handshakes(2);
+        let (flooder, client) = (IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 2]));
+        assert!(pending_handshakes.start(flooder));
+        assert!(pending_handshakes.start(flooder));
+        assert!(!pending_handshakes.start(flooder));
+        //The same address mapped to IPv6
+        assert!(!pending_handshakes.start(IpAddr::V6(Ipv6Addr::from([0, 0, 0, 0, 0, 0xffff, 0x0a00, 0x0001]))));
+        assert!(pending_handshakes.start(client));
+        assert_eq!(pending_handshakes.pending(), 3);
+
+        pending_handshakes.finish(flooder);
+        assert!(pending_handshakes.start(flooder));
+        assert_eq!(pending_handshakes.metrics.dropped.0.get(), 2);
+
+        for address in [flooder, flooder, client, client] {
+            pending_handshakes.finish(address);
+        }
+        assert_eq!(pending_handshakes.pending(), 0);
+    }
+
+    #[test]
+    fn zero_does_not_limit() {
+        let pending_handshakes = PendingHandshakes::new(HandshakeConfig { max_pending_per_ip: 0, connect_timeout_secs: 0 });
+        for _ in 0..100 {
+            assert!(pending_handshakes.start(IpAddr::from([10, 0, 0, 1])));
+        }
+        assert_eq!(pending_handshakes.connect_timeout(), None);
+    }
+}
@@ -0,0 +1,223 @@
+#[cfg(test)]
+mod rx_connection_handler_tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::sync::mpsc::Receiver;
+    use tokio::task::JoinHandle;
+
+    use crate::config::broker_config::BrokerConfig;
+    use crate::connection::client_context::ClientContext;
+    use crate::connection::rx_connection_handler::{ConnectionEnd, RxClientHandler};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::Property;
+    use crate::serdes::mqtt_encoder::MqttEncoder;
+
+    //CONNECT of MQTT 5 with clean start, Keep Alive 1s and client id "k"
+    const CONNECT: [u8; 16] = [0x10, 0x0E, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x05, 0x02, 0x00, 0x01, 0x00, 0x00, 0x01, b'k'];
+
+    struct Connection {
+        client: TcpStream,
+        rx_client_handler: Arc<RxClientHandler>,
+        listener2broker_rx: Receiver<(ClientContext, ControlPacket)>,
+        broker2listener_rx: Receiver<(Vec<SocketAddr>, ControlPacket)>,
+        handle: JoinHandle<ConnectionEnd>,
+    }
+
+    async fn open_connection() -> Connection {
+        let mut config = BrokerConfig::default();
+        config.keep_alive.grace_factor = 1.0;
+        config.keep_alive.jitter_tolerance_ms = 100;
+        open_connection_with(config).await
+    }
+
+    async fn open_connection_with(config: BrokerConfig) -> Connection {
+        let (listener2broker_tx, listener2broker_rx) = tokio::sync::mpsc::channel(10);
+        let (broker2listener_tx, broker2listener_rx) = tokio::sync::mpsc::channel(10);
+        let rx_client_handler = Arc::new(RxClientHandler::new(Arc::new(config), Arc::new(broker2listener_tx)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, socket) = listener.accept().await.unwrap();
+        let (in_stream, _out_stream) = stream.into_split();
+        let rx_client_handler_ = rx_client_handler.clone();
+        let handle = tokio::spawn(async move {
+            rx_client_handler_.handle_client(&socket, in_stream, Arc::new(listener2broker_tx)).await
+        });
+        Connection { client, rx_client_handler, listener2broker_rx, broker2listener_rx, handle }
+    }
+
+    #[tokio::test]
+    async fn keep_alive_expires() {
+        let mut connection = open_connection().await;
+        connection.client.write_all(&CONNECT).await.unwrap();
+        let (_, connect_packet) = connection.listener2broker_rx.recv().await.unwrap();
+        assert_eq!(connect_packet.fixed_header().packet_type(), ControlPacketType::CONNECT);
+
+        let (_, disconnect_packet) = tokio::time::timeout(Duration::from_secs(3), connection.broker2listener_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(disconnect_packet.variable_header().reason_code(), Some(&ReasonCode::KeepAliveTimeout));
+        connection.handle.await.unwrap();
+        assert_eq!(connection.rx_client_handler.close_metrics.keep_alive_expired.0.get(), 1);
+        assert_eq!(connection.rx_client_handler.close_metrics.connection_lost.0.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn connection_lost_is_not_a_keep_alive_expiry() {
+        let mut connection = open_connection().await;
+        connection.client.write_all(&CONNECT).await.unwrap();
+        connection.listener2broker_rx.recv().await.unwrap();
+        drop(connection.client);
+
+        assert_eq!(connection.handle.await.unwrap(), ConnectionEnd::Dropped);
+        assert!(connection.broker2listener_rx.try_recv().is_err());
+        assert_eq!(connection.rx_client_handler.close_metrics.keep_alive_expired.0.get(), 0);
+        assert_eq!(connection.rx_client_handler.close_metrics.connection_lost.0.get(), 1);
+    }
+
+    #[tokio::test]
+    async fn hang_up_after_disconnect_is_left_to_the_broker() {
+        let mut connection = open_connection().await;
+        connection.client.write_all(&CONNECT).await.unwrap();
+        connection.listener2broker_rx.recv().await.unwrap();
+        connection.client.write_all(&[0xE0, 0x00]).await.unwrap();
+        drop(connection.client);
+
+        let (_, disconnect_packet) = connection.listener2broker_rx.recv().await.unwrap();
+        assert_eq!(disconnect_packet.fixed_header().packet_type(), ControlPacketType::DISCONNECT);
+        assert_eq!(connection.handle.await.unwrap(), ConnectionEnd::Disconnected);
+    }
+
+    #[tokio::test]
+    async fn decode_pool_keeps_packet_order() {
+        let mut config = BrokerConfig::default();
+        config.decode.workers = 2;
+        config.decode.min_packet_size = 8;
+        let mut connection = open_connection_with(config).await;
+        connection.client.write_all(&CONNECT).await.unwrap();
+        //Alternating large (offloaded) and small (decoded inline) PUBLISH packets
+        for i in 0..20u8 {
+            let topic = match i % 2 {
+                0 => { format!("large/{}/{}", i, "x".repeat(32)) }
+                _ => { format!("s{}", i) }
+            };
+            let packet = ControlPacket::publish_with_payload(None, topic, QoSLevel::AtMostOnce, false, vec![], vec![i]);
+            connection.client.write_all(&MqttEncoder::default().encode_packet(&Arc::new(packet)).unwrap()).await.unwrap();
+        }
+        let (_, connect_packet) = connection.listener2broker_rx.recv().await.unwrap();
+        assert_eq!(connect_packet.fixed_header().packet_type(), ControlPacketType::CONNECT);
+        for i in 0..20u8 {
+            let (_, publish_packet) = connection.listener2broker_rx.recv().await.unwrap();
+            assert_eq!(publish_packet.payload().data(), &[i]);
+        }
+        let metrics = &connection.rx_client_handler.decode_pool.metrics;
+        assert_eq!(metrics.offloaded.0.get(), 10);
+        assert_eq!(metrics.decoded_inline.0.get(), 11);
+    }
+
+    #[tokio::test]
+    async fn publish_with_topic_alias_only_goes_to_its_topic() {
+        let mut connection = open_connection_with(BrokerConfig::default()).await;
+        connection.client.write_all(&CONNECT).await.unwrap();
+        for (topic, data) in [("sensors/1", 1u8), ("", 2u8)] {
+            let packet = ControlPacket::publish_with_payload(None, topic.to_string(), QoSLevel::AtMostOnce, false, vec![Property::TopicAlias(3)], vec![data]);
+            connection.client.write_all(&MqttEncoder::default().encode_packet(&Arc::new(packet)).unwrap()).await.unwrap();
+        }
+        connection.listener2broker_rx.recv().await.unwrap();
+        for data in [1u8, 2u8] {
+            let (_, publish_packet) = connection.listener2broker_rx.recv().await.unwrap();
+            assert_eq!(publish_packet.variable_header().topic_name(), "sensors/1");
+            assert_eq!(publish_packet.payload().data(), &[data]);
+        }
+    }
+
+    #[tokio::test]
+    async fn unknown_topic_alias_disconnects() {
+        let mut config = BrokerConfig::default();
+        config.decode.workers = 2;
+        let mut connection = open_connection_with(config).await;
+        connection.client.write_all(&CONNECT).await.unwrap();
+        let packet = ControlPacket::publish_with_payload(None, String::new(), QoSLevel::AtMostOnce, false, vec![Property::TopicAlias(3)], vec![1]);
+        connection.client.write_all(&MqttEncoder::default().encode_packet(&Arc::new(packet)).unwrap()).await.unwrap();
+        connection.listener2broker_rx.recv().await.unwrap();
+
+        let (_, disconnect_packet) = tokio::time::timeout(Duration::from_secs(3), connection.broker2listener_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(disconnect_packet.variable_header().reason_code(), Some(&ReasonCode::TopicAliasInvalid));
+        assert!(connection.listener2broker_rx.try_recv().is_err());
+    }
+
+    async fn expect_disconnect(connection: &mut Connection) -> ReasonCode {
+        let (_, disconnect_packet) = tokio::time::timeout(Duration::from_secs(3), connection.broker2listener_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(disconnect_packet.fixed_header().packet_type(), ControlPacketType::DISCONNECT);
+        disconnect_packet.variable_header().reason_code().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn packet_too_large_disconnects() {
+        let mut config = BrokerConfig::default();
+        config.packet.maximum_packet_size = 64;
+        let mut connection = open_connection_with(config).await;
+        connection.client.write_all(&CONNECT).await.unwrap();
+        let packet = ControlPacket::publish_with_payload(None, String::from("sensors/1"), QoSLevel::AtMostOnce, false, vec![], vec![0; 100]);
+        connection.client.write_all(&MqttEncoder::default().encode_packet(&Arc::new(packet)).unwrap()).await.unwrap();
+        connection.listener2broker_rx.recv().await.unwrap();
+
+        assert_eq!(expect_disconnect(&mut connection).await, ReasonCode::PacketTooLarge);
+        assert!(connection.listener2broker_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn invalid_topic_name_disconnects() {
+        for workers in [0, 2] {
+            let mut config = BrokerConfig::default();
+            config.decode.workers = workers;
+            let mut connection = open_connection_with(config).await;
+            connection.client.write_all(&CONNECT).await.unwrap();
+            let packet = ControlPacket::publish_with_payload(None, String::from("sensors/+"), QoSLevel::AtMostOnce, false, vec![], vec![1]);
+            connection.client.write_all(&MqttEncoder::default().encode_packet(&Arc::new(packet)).unwrap()).await.unwrap();
+            connection.listener2broker_rx.recv().await.unwrap();
+
+            assert_eq!(expect_disconnect(&mut connection).await, ReasonCode::TopicNameInvalid);
+        }
+    }
+
+    #[tokio::test]
+    async fn no_disconnect_before_connect() {
+        let mut connection = open_connection().await;
+        //PUBLISH with QoS 3
+        connection.client.write_all(&[0x36, 0x00]).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(3), &mut connection.handle).await.unwrap().unwrap();
+        assert!(connection.broker2listener_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn unsupported_protocol_version_gets_connack() {
+        let mut connection = open_connection().await;
+        //A CONNECT of MQTT 3.1.1
+        connection.client.write_all(&[0x10, 0x15, 0x00, 0x04, b'M', b'Q', b'T', b'T', 0x04, 0x02, 0x00, 0x3c, 0x00, 0x09, b's', b'e', b'n', b's', b'o', b'r', b'-', b'0', b'2']).await.unwrap();
+
+        let (sockets, connack_packet) = tokio::time::timeout(Duration::from_secs(3), connection.broker2listener_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(sockets.len(), 1);
+        assert_eq!(connack_packet.fixed_header().packet_type(), ControlPacketType::CONNACK);
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::UnsupportedProtocolVersion));
+        //Left to the writer, which closes the connection once the CONNACK is written
+        assert_eq!(tokio::time::timeout(Duration::from_secs(3), connection.handle).await.unwrap().unwrap(), ConnectionEnd::Disconnected);
+        assert!(connection.broker2listener_rx.try_recv().is_err());
+        assert!(connection.listener2broker_rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn connection_without_connect_times_out() {
+        let mut config = BrokerConfig::default();
+        config.handshake.connect_timeout_secs = 1;
+        let connection = open_connection_with(config).await;
+        let connection_end = tokio::time::timeout(Duration::from_secs(3), connection.handle).await.unwrap().unwrap();
+        assert_eq!(connection_end, ConnectionEnd::Unconnected);
+        assert_eq!(connection.rx_client_handler.pending_handshakes.metrics.timed_out.0.get(), 1);
+    }
+}
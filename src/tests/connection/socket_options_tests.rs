@@ -0,0 +1,41 @@
+#[cfg(test)]
+mod socket_options_tests {
+    use std::time::Duration;
+
+    use socket2::SockRef;
+    use tokio::net::{TcpListener, TcpStream};
+
+    use crate::config::broker_config::TcpConfig;
+    use crate::connection::socket_options::apply_socket_options;
+
+    #[tokio::test]
+    async fn apply_keepalive_and_nodelay_to_accepted_socket() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let config = TcpConfig { nodelay: true, keepalive_time_secs: 30, keepalive_interval_secs: 5, keepalive_retries: 3, user_timeout_ms: 20_000 };
+        apply_socket_options(&stream, &config).unwrap();
+
+        let socket = SockRef::from(&stream);
+        assert!(stream.nodelay().unwrap());
+        assert!(socket.keepalive().unwrap());
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(socket.keepalive_time().unwrap(), Duration::from_secs(30));
+            assert_eq!(socket.keepalive_interval().unwrap(), Duration::from_secs(5));
+            assert_eq!(socket.keepalive_retries().unwrap(), 3);
+            assert_eq!(socket.tcp_user_timeout().unwrap(), Some(Duration::from_millis(20_000)));
+        }
+    }
+
+    #[tokio::test]
+    async fn default_config_leaves_keepalive_off() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        apply_socket_options(&stream, &TcpConfig::default()).unwrap();
+
+        assert!(!SockRef::from(&stream).keepalive().unwrap());
+        assert!(!stream.nodelay().unwrap());
+    }
+}
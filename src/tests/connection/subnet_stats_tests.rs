@@ -0,0 +1,61 @@
+#[cfg(test)]
+mod subnet_stats_tests {
+    use std::net::IpAddr;
+
+    use crate::config::broker_config::SubnetStatsConfig;
+    use crate::connection::subnet_stats::{CloseReason, Subnet, SubnetConnections, SubnetStats};
+
+    fn address(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn mask_addresses_to_prefix() {
+        assert_eq!(Subnet::of(address("192.168.17.42"), 24, 64).to_string(), "192.168.17.0/24");
+        assert_eq!(Subnet::of(address("192.168.17.42"), 0, 64).to_string(), "0.0.0.0/0");
+        assert_eq!(Subnet::of(address("192.168.17.42"), 40, 64).to_string(), "192.168.17.42/32");
+        assert_eq!(Subnet::of(address("2001:db8:1:2:3:4:5:6"), 24, 48).to_string(), "2001:db8:1::/48");
+        assert_eq!(Subnet::of(address("::ffff:10.1.2.3"), 16, 64).to_string(), "10.1.0.0/16");
+    }
+
+    #[test]
+    fn count_connections_per_subnet() {
+        let subnet_stats = SubnetStats::new(SubnetStatsConfig::default());
+        subnet_stats.opened(address("10.0.0.1"));
+        subnet_stats.opened(address("10.0.0.2"));
+        subnet_stats.opened(address("10.0.0.3"));
+        subnet_stats.opened(address("10.0.1.1"));
+        subnet_stats.closed(address("10.0.0.1"), CloseReason::KeepAliveExpired);
+        subnet_stats.closed(address("10.0.0.2"), CloseReason::Malformed);
+        subnet_stats.closed(address("10.0.1.1"), CloseReason::ConnectionLost);
+
+        let top = subnet_stats.top(10);
+        assert_eq!(top.len(), 2);
+        assert_eq!(top[0].0.to_string(), "10.0.0.0/24");
+        assert_eq!(top[0].1, SubnetConnections { open: 1, opened: 3, keep_alive_expired: 1, connection_lost: 0, malformed: 1 });
+        assert_eq!(top[1].1, SubnetConnections { open: 0, opened: 1, keep_alive_expired: 0, connection_lost: 1, malformed: 0 });
+
+        let metrics = subnet_stats.metrics();
+        let exported = serde_json::to_value(&metrics).unwrap();
+        assert_eq!(exported["open_by_subnet"]["10_0_0_0_24"], 1);
+        assert_eq!(exported["subnets"], 2);
+    }
+
+    #[test]
+    fn bounded_by_capacity() {
+        let subnet_stats = SubnetStats::new(SubnetStatsConfig { capacity: 2, ..SubnetStatsConfig::default() });
+        subnet_stats.opened(address("10.0.1.1"));
+        subnet_stats.opened(address("10.0.2.1"));
+        subnet_stats.closed(address("10.0.2.1"), CloseReason::Closed);
+        //Replaces the one without open connections
+        subnet_stats.opened(address("10.0.3.1"));
+        //Every tracked subnet has an open connection
+        subnet_stats.opened(address("10.0.4.1"));
+        subnet_stats.closed(address("10.0.4.1"), CloseReason::Closed);
+
+        let subnets: Vec<String> = subnet_stats.top(10).into_iter().map(|(subnet, _)| subnet.to_string()).collect();
+        assert_eq!(subnets, vec!["10.0.1.0/24", "10.0.3.0/24"]);
+        assert_eq!(subnet_stats.metrics.evicted.0.get(), 1);
+        assert_eq!(subnet_stats.metrics.untracked.0.get(), 1);
+    }
+}
@@ -0,0 +1,39 @@
+#[cfg(test)]
+mod topic_aliases_tests {
+    use crate::connection::topic_aliases::TopicAliases;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::Property;
+
+    fn publish(topic_name: &str, topic_alias: Option<u16>) -> ControlPacket {
+        let properties = topic_alias.map(|topic_alias| vec![Property::TopicAlias(topic_alias)]).unwrap_or_default();
+        ControlPacket::publish_with_payload(None, topic_name.to_string(), QoSLevel::AtMostOnce, false, properties, vec![])
+    }
+
+    #[test]
+    fn alias_set_then_used() {
+        let mut topic_aliases = TopicAliases::new(2);
+        assert_eq!(topic_aliases.resolve(publish("a/b", Some(1))).unwrap().variable_header().topic_name(), "a/b");
+        assert_eq!(topic_aliases.resolve(publish("", Some(1))).unwrap().variable_header().topic_name(), "a/b");
+        //Setting it again replaces the topic
+        topic_aliases.resolve(publish("c", Some(1))).unwrap();
+        assert_eq!(topic_aliases.resolve(publish("", Some(1))).unwrap().variable_header().topic_name(), "c");
+    }
+
+    #[test]
+    fn invalid_aliases() {
+        let mut topic_aliases = TopicAliases::new(2);
+        assert_eq!(topic_aliases.resolve(publish("", Some(2))).err(), Some(ReasonCode::TopicAliasInvalid));
+        assert_eq!(topic_aliases.resolve(publish("a", Some(0))).err(), Some(ReasonCode::TopicAliasInvalid));
+        assert_eq!(topic_aliases.resolve(publish("a", Some(3))).err(), Some(ReasonCode::TopicAliasInvalid));
+        assert_eq!(topic_aliases.resolve(publish("", None)).err(), Some(ReasonCode::ProtocolError));
+    }
+
+    #[test]
+    fn maximum_zero_refuses_aliases() {
+        let mut topic_aliases = TopicAliases::new(0);
+        assert_eq!(topic_aliases.resolve(publish("a", Some(1))).err(), Some(ReasonCode::TopicAliasInvalid));
+        assert!(topic_aliases.resolve(publish("a", None)).is_ok());
+    }
+}
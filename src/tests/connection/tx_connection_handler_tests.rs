@@ -0,0 +1,59 @@
+#[cfg(test)]
+mod tx_connection_handler_tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use dashmap::DashMap;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio::net::tcp::OwnedWriteHalf;
+
+    use crate::{ClientHandler, TopicHandler};
+    use crate::broker::packet_dispatcher::PacketDispatcher;
+    use crate::config::broker_config::BrokerConfig;
+    use crate::connection::client_context::ClientContext;
+    use crate::connection::tx_connection_handler::TxConnectionHandler;
+    use crate::connection::virtual_endpoint::VirtualEndpoints;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::Property;
+    use crate::tests::broker::broker_tests_data::create_connect_packet_with_properties;
+
+    const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+    #[tokio::test]
+    async fn refused_connect_gets_one_connack_and_no_disconnect() {
+        let config = Arc::new(BrokerConfig::default());
+        let client_handler = Arc::new(ClientHandler::default());
+        let topic_handler = Arc::new(TopicHandler::default());
+        let (broker2listener_tx, mut broker2listener_rx) = tokio::sync::mpsc::channel(10);
+        let packet_dispatcher = PacketDispatcher::new(config, client_handler.clone(), topic_handler.clone(), Arc::new(broker2listener_tx));
+        let tx_connection_handler = TxConnectionHandler::new(client_handler, Arc::new(VirtualEndpoints::default()), packet_dispatcher.connection_close.clone(), packet_dispatcher.payload_sizes.clone());
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (stream, socket) = listener.accept().await.unwrap();
+        //The broker writes with try_write, the readiness of a fresh socket isn't known yet
+        stream.writable().await.unwrap();
+        let (_in_stream, out_stream) = stream.into_split();
+        let stream_repository: Arc<DashMap<SocketAddr, OwnedWriteHalf>> = Arc::new(DashMap::new());
+        stream_repository.insert(socket, out_stream);
+        tokio::spawn(async move {
+            let _ = tx_connection_handler.handle_outgoing_connections(&mut broker2listener_rx, stream_repository).await;
+        });
+
+        let connect_packet = create_connect_packet_with_properties(String::from("tx-refused"), vec![Property::ReceiveMaximum(0)]);
+        let mut context = ClientContext::new(socket);
+        context.connected(&connect_packet);
+        packet_dispatcher.process_message(context, connect_packet).await.unwrap();
+
+        //Everything the client reads until the broker closes the connection
+        let mut received = vec![];
+        tokio::time::timeout(READ_TIMEOUT, client.read_to_end(&mut received)).await
+            .expect("connection not closed")
+            .unwrap();
+        assert_eq!(received[0], 0x20, "not a CONNACK: {:?}", received);
+        assert_eq!(received.len(), 2 + received[1] as usize, "more than the CONNACK: {:?}", received);
+        assert_eq!(received[3], ReasonCode::ProtocolError.as_u8());
+    }
+}
@@ -0,0 +1,148 @@
+#[cfg(all(test, feature = "coap"))]
+mod coap_tests {
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use crate::auth::acl::Acl;
+    use crate::config::broker_config::{AclAccess, AclPermission, AclRule, BrokerConfig, CoapMapping};
+    use crate::connection::virtual_endpoint::VirtualEndpoints;
+    use crate::gateway::coap::bridge::CoapBridge;
+    use crate::gateway::coap::message::{CoapMessage, Code, MessageType, OPTION_CONTENT_FORMAT, OPTION_URI_PATH};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::topic::topic_handler::TopicHandler;
+
+    fn create_mappings() -> Vec<CoapMapping> {
+        vec![
+            CoapMapping { path: String::from("sensors"), topic: String::from("devices/sensors") },
+            CoapMapping { path: String::from("sensors/kitchen"), topic: String::from("home/kitchen") },
+        ]
+    }
+
+    fn create_request(message_type: MessageType, code: Code, path: &[&str]) -> CoapMessage {
+        let mut request = CoapMessage::new(message_type, code, 0x1234, vec![0xCA, 0xFE]);
+        for segment in path {
+            request = request.with_option(OPTION_URI_PATH, segment.as_bytes().to_vec());
+        }
+        request
+    }
+
+    #[test]
+    fn decode_get() {
+        //CON GET, token length 1, message id 0x0102, token 0x07, Uri-Path "temp"
+        let datagram = [0x41, 0x01, 0x01, 0x02, 0x07, 0xB4, b't', b'e', b'm', b'p'];
+        let message = CoapMessage::decode(&datagram).unwrap();
+        assert_eq!(message.message_type(), MessageType::Confirmable);
+        assert_eq!(message.code(), Code::Get);
+        assert_eq!(message.message_id(), 0x0102);
+        assert_eq!(message.token(), &vec![0x07]);
+        assert_eq!(message.uri_path(), Ok(String::from("temp")));
+        assert!(CoapMessage::decode(&datagram[..7]).is_err());
+    }
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let message = create_request(MessageType::NonConfirmable, Code::Put, &["sensors", "a-rather-long-segment-name"])
+            .with_option(OPTION_CONTENT_FORMAT, vec![50])
+            .with_payload(vec![1; 300]);
+        assert_eq!(CoapMessage::decode(&message.encode()), Ok(message));
+    }
+
+    #[test]
+    fn resolve_topic_uses_longest_mapping() {
+        let mappings = create_mappings();
+        assert_eq!(CoapBridge::resolve_topic(&mappings, "sensors"), Some(String::from("devices/sensors")));
+        assert_eq!(CoapBridge::resolve_topic(&mappings, "sensors/garage/temp"), Some(String::from("devices/sensors/garage/temp")));
+        assert_eq!(CoapBridge::resolve_topic(&mappings, "sensors/kitchen/temp"), Some(String::from("home/kitchen/temp")));
+        assert_eq!(CoapBridge::resolve_topic(&mappings, "sensorsx"), None);
+        assert_eq!(CoapBridge::resolve_topic(&mappings, "actuators"), None);
+    }
+
+    #[tokio::test]
+    async fn put_publishes_and_get_reads_retained() {
+        let mut config = BrokerConfig::default();
+        config.gateway.coap.mappings = create_mappings();
+        let (listener2broker_tx, mut listener2broker_rx) = tokio::sync::mpsc::channel(10);
+        let topic_handler = Arc::new(TopicHandler::default());
+        let acl = Arc::new(Acl::new(config.acl.clone()));
+        let (bridge, _from_broker) = CoapBridge::new(Arc::new(config), Arc::new(listener2broker_tx), Arc::new(VirtualEndpoints::default()), topic_handler.clone(), acl);
+
+        let put = create_request(MessageType::Confirmable, Code::Put, &["sensors", "temp"]).with_payload(vec![21]);
+        let response = bridge.handle_request(&put).await.unwrap();
+        assert_eq!(response.message_type(), MessageType::Acknowledgement);
+        assert_eq!(response.code(), Code::Changed);
+        assert_eq!(response.message_id(), 0x1234);
+        assert_eq!(response.token(), &vec![0xCA, 0xFE]);
+        let (_, publish_packet) = listener2broker_rx.recv().await.unwrap();
+        assert_eq!(publish_packet.fixed_header().packet_type(), ControlPacketType::PUBLISH);
+        assert!(*publish_packet.fixed_header().retain());
+        assert_eq!(publish_packet.variable_header().topic_name(), &String::from("devices/sensors/temp"));
+
+        let get = create_request(MessageType::Confirmable, Code::Get, &["sensors", "temp"]);
+        assert_eq!(bridge.handle_request(&get).await.unwrap().code(), Code::NotFound);
+        topic_handler.retain_message(&String::from("sensor"), &ControlPacket::publish_with_payload(None, String::from("devices/sensors/temp"), QoSLevel::AtMostOnce, true, vec![], vec![21]), Instant::now());
+        let response = bridge.handle_request(&get).await.unwrap();
+        assert_eq!(response.code(), Code::Content);
+        assert_eq!(response.payload(), &vec![21]);
+
+        let unmapped = create_request(MessageType::NonConfirmable, Code::Get, &["actuators"]);
+        let response = bridge.handle_request(&unmapped).await.unwrap();
+        assert_eq!(response.message_type(), MessageType::NonConfirmable);
+        assert_eq!(response.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    async fn get_of_a_topic_the_acl_denies_is_forbidden() {
+        let mut config = BrokerConfig::default();
+        config.gateway.coap.mappings = create_mappings();
+        config.acl.enabled = true;
+        config.acl.rules = vec![AclRule { permission: AclPermission::Allow, client_id: Some(config.gateway.coap.client_id.clone()), username: None, access: AclAccess::Read, topics: vec![String::from("devices/sensors/#")] }];
+        let (listener2broker_tx, _listener2broker_rx) = tokio::sync::mpsc::channel(10);
+        let topic_handler = Arc::new(TopicHandler::default());
+        let acl = Arc::new(Acl::new(config.acl.clone()));
+        let (bridge, _from_broker) = CoapBridge::new(Arc::new(config), Arc::new(listener2broker_tx), Arc::new(VirtualEndpoints::default()), topic_handler.clone(), acl);
+        for topic_name in ["devices/sensors/temp", "home/kitchen/temp"] {
+            topic_handler.retain_message(&String::from("sensor"), &ControlPacket::publish_with_payload(None, String::from(topic_name), QoSLevel::AtMostOnce, true, vec![], vec![21]), Instant::now());
+        }
+
+        let allowed = create_request(MessageType::Confirmable, Code::Get, &["sensors", "temp"]);
+        assert_eq!(bridge.handle_request(&allowed).await.unwrap().code(), Code::Content);
+        let denied = create_request(MessageType::Confirmable, Code::Get, &["sensors", "kitchen", "temp"]);
+        let response = bridge.handle_request(&denied).await.unwrap();
+        assert_eq!(response.code(), Code::Forbidden);
+        assert!(response.payload().is_empty());
+    }
+
+    #[tokio::test]
+    async fn restarted_bridge_connects_on_a_new_endpoint() {
+        let mut config = BrokerConfig::default();
+        config.gateway.coap.port = 0;
+        let config = Arc::new(config);
+        let (listener2broker_tx, mut listener2broker_rx) = tokio::sync::mpsc::channel(10);
+        let listener2broker_tx = Arc::new(listener2broker_tx);
+        let virtual_endpoints = Arc::new(VirtualEndpoints::default());
+        let topic_handler = Arc::new(TopicHandler::default());
+        let acl = Arc::new(Acl::new(config.acl.clone()));
+        //Like the supervisor does on every start
+        let start = || {
+            let future = CoapBridge::run(config.clone(), listener2broker_tx.clone(), virtual_endpoints.clone(), topic_handler.clone(), acl.clone());
+            async move { future.await.map_err(|err| err.to_string()) }
+        };
+
+        let first_run = tokio::spawn(start());
+        let (context, connect_packet) = listener2broker_rx.recv().await.unwrap();
+        assert_eq!(connect_packet.fixed_header().packet_type(), ControlPacketType::CONNECT);
+        let first_socket = context.socket;
+        //What the broker does with the endpoint when it disconnects the bridge
+        virtual_endpoints.unregister(&first_socket);
+        assert!(first_run.await.unwrap().is_err());
+
+        let second_run = tokio::spawn(start());
+        let (context, connect_packet) = listener2broker_rx.recv().await.unwrap();
+        assert_eq!(connect_packet.fixed_header().packet_type(), ControlPacketType::CONNECT);
+        assert_ne!(context.socket, first_socket);
+        assert!(virtual_endpoints.contains(&context.socket));
+        second_run.abort();
+    }
+}
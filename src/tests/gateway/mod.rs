@@ -0,0 +1,2 @@
+pub mod mqtt_sn_tests;
+pub mod coap_tests;
@@ -0,0 +1,102 @@
+#[cfg(all(test, feature = "mqtt-sn"))]
+mod mqtt_sn_tests {
+    use std::net::{IpAddr, SocketAddr};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::net::UdpSocket;
+
+    use crate::config::broker_config::BrokerConfig;
+    use crate::connection::listeners::{Listeners, MQTT_SN_LISTENER};
+    use crate::connection::virtual_endpoint::VirtualEndpoints;
+    use crate::gateway::mqtt_sn::gateway::{MqttSnClient, MqttSnGateway};
+    use crate::gateway::mqtt_sn::message::{MqttSnMessage, ReturnCode, SnTopic};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+
+    fn create_client() -> MqttSnClient {
+        MqttSnClient::new(SocketAddr::new(IpAddr::from([127, 0, 0, 1]), 0))
+    }
+
+    #[test]
+    fn decode_connect() {
+        //Length 13, CONNECT, CleanSession, ProtocolId, Duration 60, "sensor1"
+        let datagram = [13, 0x04, 0x04, 0x01, 0x00, 0x3C, b's', b'e', b'n', b's', b'o', b'r', b'1'];
+        assert!(MqttSnMessage::decode(&datagram[..12]).is_err());
+        assert_eq!(MqttSnMessage::decode(&datagram), Ok(MqttSnMessage::Connect { clean_session: true, duration: 60, client_id: String::from("sensor1") }));
+    }
+
+    #[test]
+    fn encode_decode_roundtrip() {
+        let messages = vec![
+            MqttSnMessage::Register { topic_id: 0, msg_id: 1, topic_name: String::from("sensors/temperature") },
+            MqttSnMessage::Publish { dup: false, qos: QoSLevel::AtLeastOnce, retain: true, topic: SnTopic::Id(7), msg_id: 2, data: vec![21, 5] },
+            MqttSnMessage::Publish { dup: false, qos: QoSLevel::AtMostOnce, retain: false, topic: SnTopic::Short(String::from("ab")), msg_id: 0, data: vec![1; 300] },
+            MqttSnMessage::Subscribe { dup: false, qos: QoSLevel::AtMostOnce, msg_id: 3, topic: SnTopic::Name(String::from("sensors/+")) },
+            MqttSnMessage::Puback { topic_id: 7, msg_id: 2, return_code: ReturnCode::Accepted },
+            MqttSnMessage::Pingreq { client_id: None },
+            MqttSnMessage::Disconnect { duration: Some(30) },
+        ];
+        for message in messages {
+            assert_eq!(MqttSnMessage::decode(&message.encode()), Ok(message));
+        }
+    }
+
+    #[test]
+    fn translate_publish_registers_topic() {
+        let client = create_client();
+        let publish_packet = ControlPacket::publish_with_payload(Some(1), String::from("sensors/temperature"), QoSLevel::AtLeastOnce, false, vec![], vec![21]);
+
+        let messages = MqttSnGateway::translate(&client, &publish_packet);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0], MqttSnMessage::Register { topic_id: 1, msg_id: 0, topic_name: String::from("sensors/temperature") });
+        assert_eq!(messages[1], MqttSnMessage::Publish { dup: false, qos: QoSLevel::AtLeastOnce, retain: false, topic: SnTopic::Id(1), msg_id: 1, data: vec![21] });
+
+        let messages = MqttSnGateway::translate(&client, &publish_packet);
+        assert_eq!(messages.len(), 1);
+    }
+
+    #[test]
+    fn translate_connack() {
+        let client = create_client();
+        assert_eq!(MqttSnGateway::translate(&client, &ControlPacket::connack(false, ReasonCode::Success, vec![])),
+                   vec![MqttSnMessage::Connack { return_code: ReturnCode::Accepted }]);
+        assert_eq!(MqttSnGateway::translate(&client, &ControlPacket::connack(false, ReasonCode::ClientIdentifierNotValid, vec![])),
+                   vec![MqttSnMessage::Connack { return_code: ReturnCode::NotSupported }]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn silent_sensor_is_disconnected_after_its_keep_alive() {
+        let mut config = BrokerConfig::default();
+        config.gateway.mqtt_sn.enabled = true;
+        let (listener2broker_tx, mut listener2broker_rx) = tokio::sync::mpsc::channel(10);
+        let virtual_endpoints = Arc::new(VirtualEndpoints::default());
+        let listeners = Arc::new(Listeners::new(&config));
+        let gateway = MqttSnGateway::new(Arc::new(config), Arc::new(listener2broker_tx), virtual_endpoints.clone(), listeners.clone());
+        let udp_socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+        let sensor = SocketAddr::new(IpAddr::from([127, 0, 0, 1]), 40000);
+
+        //Keep alive of 10s, disconnected after 15.5s of silence with the default grace
+        gateway.handle_message(sensor, MqttSnMessage::Connect { clean_session: true, duration: 10, client_id: String::from("sensor1") }, &udp_socket).await.unwrap();
+        let (context, _) = listener2broker_rx.recv().await.unwrap();
+        let broker_socket = context.socket;
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        gateway.handle_message(sensor, MqttSnMessage::Pingreq { client_id: None }, &udp_socket).await.unwrap();
+        listener2broker_rx.recv().await.unwrap();
+        tokio::time::sleep(Duration::from_secs(10)).await;
+        assert!(listener2broker_rx.try_recv().is_err());
+        assert!(virtual_endpoints.contains(&broker_socket));
+
+        let (context, disconnect_packet) = tokio::time::timeout(Duration::from_secs(10), listener2broker_rx.recv()).await.unwrap().unwrap();
+        assert_eq!(context.socket, broker_socket);
+        assert_eq!(disconnect_packet.fixed_header().packet_type(), ControlPacketType::DISCONNECT);
+        assert_eq!(disconnect_packet.variable_header().reason_code(), Some(&ReasonCode::DisconnectWithWillMessage));
+        tokio::time::sleep(Duration::from_millis(1)).await;
+        assert!(!virtual_endpoints.contains(&broker_socket));
+        let status = listeners.statuses().into_iter().find(|status| status.name == MQTT_SN_LISTENER).unwrap();
+        assert_eq!(status.open_connections, 0);
+        assert!(gateway.handle_message(sensor, MqttSnMessage::Pingreq { client_id: None }, &udp_socket).await.is_err());
+    }
+}
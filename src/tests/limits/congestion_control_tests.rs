@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod congestion_control_tests {
+    use std::collections::BTreeSet;
+
+    use crate::config::broker_config::{BrokerConfig, CongestionConfig};
+    use crate::limits::congestion_control::{CongestionControl, OTHER_TOPICS, RETRY_AFTER_PROPERTY};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::Property;
+    use crate::tests::broker::broker_tests_data::create_connect_packet;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    fn create_congestion_control(queue_threshold: usize, drop_probability: f64) -> CongestionControl {
+        let drop_counter_prefixes = BTreeSet::from([String::from("telemetry/"), String::from("telemetry/engine/")]);
+        CongestionControl::new(CongestionConfig { queue_threshold, drop_probability, drop_counter_prefixes, ..CongestionConfig::default() })
+    }
+
+    fn dropped(congestion_control: &CongestionControl, prefix: &str) -> u64 {
+        congestion_control.metrics.0.get(prefix).unwrap().qos0_dropped.0.get()
+    }
+
+    #[test]
+    fn sheds_only_qos0_while_congested() {
+        let congestion_control = create_congestion_control(10, 1.0);
+        assert!(!congestion_control.shed("telemetry/engine/rpm", QoSLevel::AtMostOnce, 9));
+        assert!(congestion_control.shed("telemetry/engine/rpm", QoSLevel::AtMostOnce, 10));
+        assert!(!congestion_control.shed("telemetry/engine/rpm", QoSLevel::AtLeastOnce, 100));
+        assert!(!congestion_control.shed("telemetry/engine/rpm", QoSLevel::ExactlyOnce, 100));
+        assert!(congestion_control.shed("telemetry/cabin/temperature", QoSLevel::AtMostOnce, 100));
+        assert!(congestion_control.shed("commands/door", QoSLevel::AtMostOnce, 100));
+
+        assert_eq!(dropped(&congestion_control, "telemetry/engine/"), 1);
+        assert_eq!(dropped(&congestion_control, "telemetry/"), 1);
+        assert_eq!(dropped(&congestion_control, OTHER_TOPICS), 1);
+    }
+
+    #[test]
+    fn never_sheds_when_disabled() {
+        let congestion_control = create_congestion_control(0, 1.0);
+        assert!(!congestion_control.shed("telemetry/engine/rpm", QoSLevel::AtMostOnce, usize::MAX));
+        let congestion_control = create_congestion_control(10, 0.0);
+        assert!(!congestion_control.shed("telemetry/engine/rpm", QoSLevel::AtMostOnce, 100));
+        assert_eq!(dropped(&congestion_control, "telemetry/engine/"), 0);
+    }
+
+    #[tokio::test]
+    async fn queued_packets_of_channel() {
+        let (to_listener, mut from_broker) = tokio::sync::mpsc::channel(10);
+        assert_eq!(CongestionControl::queued(&to_listener), 0);
+        to_listener.send((vec![], ControlPacket::disconnect(ReasonCode::Success))).await.unwrap();
+        to_listener.send((vec![], ControlPacket::disconnect(ReasonCode::Success))).await.unwrap();
+        assert_eq!(CongestionControl::queued(&to_listener), 2);
+        from_broker.recv().await.unwrap();
+        assert_eq!(CongestionControl::queued(&to_listener), 1);
+    }
+
+    #[test]
+    fn retry_after_grows_with_the_queue() {
+        let congestion_control = CongestionControl::new(CongestionConfig { connect_queue_threshold: 100, retry_after_min_secs: 10, retry_after_max_secs: 60, ..CongestionConfig::default() });
+        assert_eq!(congestion_control.connect_retry_after(99), None);
+        for _ in 0..20 {
+            let at_threshold = congestion_control.connect_retry_after(100).unwrap();
+            assert!((10..=15).contains(&at_threshold), "{}", at_threshold);
+            let twice_the_threshold = congestion_control.connect_retry_after(200).unwrap();
+            assert!((20..=30).contains(&twice_the_threshold), "{}", twice_the_threshold);
+            assert_eq!(congestion_control.connect_retry_after(10000), Some(60));
+        }
+        assert_eq!(congestion_control.connect_metrics.refused.0.get(), 60);
+
+        let congestion_control = create_congestion_control(10, 1.0);
+        assert_eq!(congestion_control.connect_retry_after(usize::MAX), None);
+    }
+
+    #[tokio::test]
+    async fn busy_broker_refuses_connect_with_retry_after() {
+        let mut config = BrokerConfig::default();
+        config.congestion.connect_queue_threshold = 2;
+        let mut harness = HandlerHarness::new(config);
+        for client_id in ["busy-first", "busy-second", "busy-third"] {
+            harness.send(HandlerHarness::socket(), create_connect_packet(client_id.to_string())).await.unwrap();
+        }
+        for _ in 0..2 {
+            let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+            assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::Success));
+        }
+        let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::ServerBusy));
+        let retry_after = connack_packet.variable_header().properties().iter()
+            .find_map(|property| match property {
+                Property::UserProperty(key, value) if key == RETRY_AFTER_PROPERTY => { value.parse::<u64>().ok() }
+                _ => { None }
+            });
+        assert!(retry_after.is_some_and(|retry_after| retry_after >= 5));
+        harness.expect_nothing();
+        assert!(harness.client_handler.get_socket(&String::from("busy-third")).is_err());
+    }
+}
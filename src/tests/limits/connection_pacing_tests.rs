@@ -0,0 +1,56 @@
+#[cfg(test)]
+mod connection_pacing_tests {
+    use std::time::{Duration, Instant};
+
+    use crate::config::broker_config::BrokerConfig;
+    use crate::limits::connection_pacing::ConnectionPacing;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::tests::broker::broker_tests_data::create_connect_packet;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    #[test]
+    fn pace_past_the_burst() {
+        let pacing = ConnectionPacing::new("accept", 10, 3, 0);
+        let now = Instant::now();
+        let delays: Vec<Duration> = (0..5).map(|_| pacing.reserve(now)).collect();
+        assert_eq!(delays, [Duration::ZERO, Duration::ZERO, Duration::ZERO, Duration::from_millis(100), Duration::from_millis(200)]);
+        assert_eq!((pacing.counters.immediate.0.get(), pacing.counters.paced.0.get()), (3, 2));
+
+        //Half a second later the two waiting went on and three tokens came back
+        let later = now + Duration::from_millis(500);
+        let delays: Vec<Duration> = (0..4).map(|_| pacing.reserve(later)).collect();
+        assert_eq!(delays, [Duration::ZERO, Duration::ZERO, Duration::ZERO, Duration::from_millis(100)]);
+    }
+
+    #[test]
+    fn burst_refills_up_to_its_size() {
+        let pacing = ConnectionPacing::new("accept", 100, 2, 0);
+        let later = Instant::now() + Duration::from_secs(60);
+        let delays: Vec<Duration> = (0..3).map(|_| pacing.reserve(later)).collect();
+        assert_eq!(delays, [Duration::ZERO, Duration::ZERO, Duration::from_millis(10)]);
+    }
+
+    #[test]
+    fn unpaced_without_rate() {
+        let pacing = ConnectionPacing::new("accept", 0, 0, 0);
+        let now = Instant::now();
+        assert!((0..1000).all(|_| pacing.reserve(now).is_zero()));
+        assert_eq!(pacing.counters.paced.0.get(), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn connack_waits_for_its_turn() {
+        let mut config = BrokerConfig::default();
+        config.pacing.connack_per_sec = 2;
+        config.pacing.connack_burst = 1;
+        config.pacing.jitter_ms = 0;
+        let mut harness = HandlerHarness::new(config);
+        harness.connect("pacing-first").await;
+
+        let started_at = tokio::time::Instant::now();
+        harness.send(HandlerHarness::socket(), create_connect_packet(String::from("pacing-second"))).await.unwrap();
+        harness.expect(ControlPacketType::CONNACK).await;
+        assert!(started_at.elapsed() >= Duration::from_millis(500));
+        assert_eq!(harness.packet_dispatcher.connect_handler.connack_pacing.counters.paced.0.get(), 1);
+    }
+}
@@ -0,0 +1,58 @@
+#[cfg(test)]
+mod fair_share_tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use crate::config::broker_config::{BrokerConfig, CongestionConfig, QuotaProfile};
+    use crate::limits::congestion_control::CongestionControl;
+    use crate::limits::fair_share::{FairShare, UNPROFILED_GROUP};
+    use crate::model::qos_level::QoSLevel;
+
+    fn create_fair_share() -> FairShare {
+        let mut config = BrokerConfig::default();
+        config.congestion.fair_share = true;
+        config.quota.profiles.insert(String::from("gold"), QuotaProfile { weight: Some(3), ..QuotaProfile::default() });
+        config.quota.profiles.insert(String::from("bronze"), QuotaProfile::default());
+        FairShare::new(Arc::new(config))
+    }
+
+    fn assert_share_used(share_used: f64, expected: f64) {
+        assert!((share_used - expected).abs() < 1e-9, "{} != {}", share_used, expected);
+    }
+
+    #[test]
+    fn share_used_by_weight() {
+        let fair_share = create_fair_share();
+        assert!(fair_share.is_enabled());
+        assert_eq!(fair_share.weight("gold"), 3);
+        assert_eq!(fair_share.weight("bronze"), 1);
+        assert_eq!(fair_share.weight(UNPROFILED_GROUP), 1);
+
+        let now = Instant::now();
+        //Alone in the window a group uses exactly its share
+        assert_share_used(fair_share.record("bronze", 10, now), 1.0);
+        assert_share_used(fair_share.record("gold", 30, now), 1.0);
+        //A burst of bronze: 40 deliveries out of 70, its share is a quarter of them
+        assert_share_used(fair_share.record("bronze", 30, now), 40.0 / 17.5);
+        assert_share_used(fair_share.record("gold", 0, now), 30.0 / 52.5);
+
+        //Every window starts over
+        assert_share_used(fair_share.record("gold", 5, now + Duration::from_secs(1)), 1.0);
+
+        assert_eq!(fair_share.metrics.0.get("bronze").unwrap().deliveries.0.get(), 40);
+        assert_eq!(fair_share.metrics.0.get("gold").unwrap().deliveries.0.get(), 35);
+        fair_share.record_shed(UNPROFILED_GROUP, 3);
+        assert_eq!(fair_share.metrics.0.get(UNPROFILED_GROUP).unwrap().qos0_shed.0.get(), 3);
+    }
+
+    #[test]
+    fn shed_scaled_by_share_used() {
+        let congestion_control = CongestionControl::new(CongestionConfig { queue_threshold: 10, drop_probability: 0.5, ..CongestionConfig::default() });
+        for _ in 0..100 {
+            assert!(congestion_control.shed_scaled("telemetry/rpm", QoSLevel::AtMostOnce, 10, 2.0));
+            assert!(!congestion_control.shed_scaled("telemetry/rpm", QoSLevel::AtMostOnce, 10, 0.0));
+            assert!(!congestion_control.shed_scaled("telemetry/rpm", QoSLevel::AtLeastOnce, 10, 2.0));
+            assert!(!congestion_control.shed_scaled("telemetry/rpm", QoSLevel::AtMostOnce, 9, 2.0));
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod congestion_control_tests;
+pub mod connection_pacing_tests;
+pub mod fair_share_tests;
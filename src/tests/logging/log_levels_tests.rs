@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod log_levels_tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::config::broker_config::BrokerConfig;
+    use crate::logging::log_levels::{log_levels, LogLevelOverride, LogLevelRequest, LogLevels};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::tests::broker::broker_tests_data::create_connect_packet_with_username;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    fn request(module: &str, level: &str, duration_secs: Option<u64>) -> LogLevelRequest {
+        LogLevelRequest { module: module.to_string(), level: level.to_string(), duration_secs }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn revert_level_after_duration() {
+        let log_levels = Arc::new(LogLevels::default());
+        log_levels.set(&request("patina::serdes", "TRACE", Some(300))).unwrap();
+        log_levels.set(&request("patina::topic", "debug", None)).unwrap();
+        assert_eq!(log_levels.overrides(), vec![
+            LogLevelOverride { module: String::from("patina::serdes"), level: String::from("trace"), reverts_in_secs: Some(300) },
+            LogLevelOverride { module: String::from("patina::topic"), level: String::from("debug"), reverts_in_secs: None },
+        ]);
+
+        tokio::time::sleep(Duration::from_secs(301)).await;
+        assert_eq!(log_levels.overrides().len(), 1);
+        assert!(log_levels.reset("patina::topic"));
+        assert!(!log_levels.reset("patina::topic"));
+        assert!(log_levels.overrides().is_empty());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn newer_level_cancels_revert() {
+        let log_levels = Arc::new(LogLevels::default());
+        log_levels.set(&request("patina::broker", "trace", Some(60))).unwrap();
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        log_levels.set(&request("patina::broker", "debug", Some(60))).unwrap();
+
+        tokio::time::sleep(Duration::from_secs(31)).await;
+        assert_eq!(log_levels.overrides()[0].level, "debug");
+        tokio::time::sleep(Duration::from_secs(30)).await;
+        assert!(log_levels.overrides().is_empty());
+    }
+
+    #[test]
+    fn reject_invalid_requests() {
+        let log_levels = Arc::new(LogLevels::default());
+        assert!(log_levels.set(&request("", "trace", None)).is_err());
+        assert!(log_levels.set(&request("patina::", "trace", None)).is_err());
+        assert!(log_levels.set(&request("patina serdes", "trace", None)).is_err());
+        assert!(log_levels.set(&request("patina", "verbose", None)).is_err());
+        assert!(log_levels.overrides().is_empty());
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn keep_appenders_of_configured_loggers() {
+        use std::collections::BTreeMap;
+
+        use log::LevelFilter;
+        use log4rs::config::Logger;
+
+        use crate::logging::log_levels::backend::with_levels;
+
+        let loggers = vec![Logger::builder().appender("stdout").additive(false).build("patina::broker", LevelFilter::Error)];
+        let levels = BTreeMap::from([(String::from("patina::broker"), LevelFilter::Trace), (String::from("patina::serdes"), LevelFilter::Debug)]);
+        let loggers = with_levels(loggers, &levels);
+        assert_eq!(loggers.len(), 2);
+        assert_eq!((loggers[0].name(), loggers[0].level(), loggers[0].appenders(), loggers[0].additive()), ("patina::broker", LevelFilter::Trace, &[String::from("stdout")][..], false));
+        assert_eq!((loggers[1].name(), loggers[1].level(), loggers[1].additive()), ("patina::serdes", LevelFilter::Debug, true));
+    }
+
+    #[tokio::test]
+    async fn set_level_with_control_command() {
+        let mut config = BrokerConfig::default();
+        config.control.usernames = vec![String::from("ops")];
+        let mut harness = HandlerHarness::new(config);
+        let payload = br#"{"module": "patina::tests::control", "level": "trace", "duration_secs": 300}"#.to_vec();
+        let command = ControlPacket::publish_with_payload(Some(1), String::from("$CONTROL/log-level"), QoSLevel::AtLeastOnce, false, vec![], payload);
+
+        let stranger = harness.connect("control-stranger").await;
+        harness.send(stranger, command.clone()).await.unwrap();
+        let (_, puback_packet) = harness.expect(ControlPacketType::PUBACK).await;
+        assert_eq!(puback_packet.variable_header().reason_code(), Some(&ReasonCode::NotAuthorized));
+        assert!(!log_levels().overrides().iter().any(|level_override| level_override.module == "patina::tests::control"));
+
+        let operator = HandlerHarness::socket();
+        harness.send(operator, create_connect_packet_with_username(String::from("control-operator"), String::from("ops"))).await.unwrap();
+        harness.expect(ControlPacketType::CONNACK).await;
+        harness.send(operator, command).await.unwrap();
+        let (_, puback_packet) = harness.expect(ControlPacketType::PUBACK).await;
+        assert_eq!(puback_packet.variable_header().reason_code(), Some(&ReasonCode::Success));
+        assert!(log_levels().reset("patina::tests::control"));
+
+        let invalid = ControlPacket::publish_with_payload(Some(2), String::from("$CONTROL/log-level"), QoSLevel::AtLeastOnce, false, vec![], b"trace".to_vec());
+        harness.send(operator, invalid).await.unwrap();
+        let (_, puback_packet) = harness.expect(ControlPacketType::PUBACK).await;
+        assert_eq!(puback_packet.variable_header().reason_code(), Some(&ReasonCode::PayloadFormatInvalid));
+        harness.expect_nothing();
+    }
+}
@@ -0,0 +1,47 @@
+#[cfg(all(test, feature = "admin-api"))]
+mod admin_api_tests {
+    use crate::config::broker_config::{AdminConfig, AdminRole, ApiToken};
+    use crate::metrics::admin_api::authorize;
+
+    fn bearer(token: &str) -> Option<String> {
+        Some(format!("Bearer {}", token))
+    }
+
+    fn create_admin_config() -> AdminConfig {
+        let mut config = AdminConfig::default();
+        config.api_token = Some(String::from("admin-secret"));
+        config.tokens = vec![ApiToken { name: String::from("grafana"), token: String::from("read-secret"), role: AdminRole::Read }];
+        config
+    }
+
+    #[test]
+    fn roles_per_endpoint() {
+        let config = create_admin_config();
+        assert_eq!(authorize(&config, "GET /metrics", None), Ok(()));
+        assert_eq!(authorize(&config, "GET /config", None).unwrap_err().status, 401);
+        assert_eq!(authorize(&config, "GET /config", bearer("read-secret").as_ref()), Ok(()));
+        assert_eq!(authorize(&config, "GET /config", bearer("admin-secret").as_ref()), Ok(()));
+        assert_eq!(authorize(&config, "DELETE /clients", bearer("read-secret").as_ref()).unwrap_err().status, 403);
+        assert_eq!(authorize(&config, "DELETE /clients", bearer("admin-secret").as_ref()), Ok(()));
+        assert_eq!(authorize(&config, "DELETE /clients", bearer("wrong").as_ref()).unwrap_err().status, 401);
+    }
+
+    #[test]
+    fn configured_endpoint_roles() {
+        let mut config = create_admin_config();
+        config.endpoint_roles.insert(String::from("GET /metrics"), AdminRole::Read);
+        config.endpoint_roles.insert(String::from("POST /publish"), AdminRole::Read);
+        assert_eq!(authorize(&config, "GET /metrics", None).unwrap_err().status, 401);
+        assert_eq!(authorize(&config, "GET /metrics", bearer("read-secret").as_ref()), Ok(()));
+        assert_eq!(authorize(&config, "POST /publish", bearer("read-secret").as_ref()), Ok(()));
+        //Unknown endpoints are admin only
+        assert_eq!(config.required_role("PUT /reload"), AdminRole::Admin);
+    }
+
+    #[test]
+    fn refused_without_tokens() {
+        let config = AdminConfig::default();
+        assert_eq!(authorize(&config, "GET /metrics", None), Ok(()));
+        assert_eq!(authorize(&config, "GET /config", bearer("anything").as_ref()).unwrap_err().status, 403);
+    }
+}
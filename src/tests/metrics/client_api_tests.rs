@@ -0,0 +1,124 @@
+#[cfg(all(test, feature = "admin-api"))]
+mod client_api_tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use tokio::sync::mpsc::Receiver;
+
+    use crate::audit::audit_log::AuditLog;
+    use crate::broker::utils::{persist_packet, register_clean_session, session_summary, set_connection_metadata};
+    use crate::config::broker_config::{AuditConfig, BrokerConfig};
+    use crate::limits::quota_handler::QuotaHandler;
+    use crate::metrics::client_api::{ClientApi, ClientPurge, QueuePurge};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::session::client_handler::ClientHandler;
+    use crate::session::session_handler::{ConnectionMetadata, QueueSelector};
+    use crate::tests::broker::broker_tests_data::create_connect_packet_with_username;
+    use crate::topic::topic_handler::TopicHandler;
+
+    const TOKEN: &str = "secret";
+
+    fn create_client_api() -> (ClientApi, Arc<ClientHandler>, Arc<TopicHandler>, Receiver<(Vec<SocketAddr>, ControlPacket)>) {
+        let mut config = BrokerConfig::default();
+        config.admin.api_token = Some(String::from(TOKEN));
+        let config = Arc::new(config);
+        let (to_listener, from_broker) = tokio::sync::mpsc::channel(10);
+        let client_handler = Arc::new(ClientHandler::default());
+        let topic_handler = Arc::new(TopicHandler::default());
+        let client_api = ClientApi::new(config.clone(), client_handler.clone(), topic_handler.clone(), Arc::new(QuotaHandler::new(config)), Arc::new(to_listener), Arc::new(AuditLog::new(AuditConfig::default())));
+        (client_api, client_handler, topic_handler, from_broker)
+    }
+
+    //A connected client with a session, a subscription and a retained message
+    fn connect_client(client_id: &String, socket: SocketAddr, client_handler: &ClientHandler, topic_handler: &TopicHandler) {
+        client_handler.register(&socket, client_id);
+        register_clean_session(&*client_handler.sessions, client_id);
+        set_connection_metadata(&*client_handler.sessions, client_id, ConnectionMetadata::from_connect(socket, &create_connect_packet_with_username(client_id.clone(), String::from("alice"))));
+        topic_handler.subscribe(client_id, &String::from("devices/+/status"));
+        let publish_packet = ControlPacket::publish_with_payload(None, format!("devices/{}/status", client_id), QoSLevel::AtMostOnce, true, vec![], b"online".to_vec());
+        topic_handler.retain_message(client_id, &publish_packet, Instant::now());
+    }
+
+    fn bearer(token: &str) -> Option<String> {
+        Some(format!("Bearer {}", token))
+    }
+
+    #[tokio::test]
+    async fn client_api_requires_token() {
+        let (client_api, _, _, _from_broker) = create_client_api();
+        let client_id = String::from("client-api-unauthorized");
+        assert_eq!(client_api.export(None, client_id.clone()).unwrap_err().status, 401);
+        assert_eq!(client_api.purge(bearer("wrong!"), client_id).await.unwrap_err().status, 401);
+    }
+
+    #[tokio::test]
+    async fn export_client() {
+        let (client_api, client_handler, topic_handler, _from_broker) = create_client_api();
+        let client_id = String::from("client-api-export");
+        assert_eq!(client_api.export(bearer(TOKEN), client_id.clone()).unwrap_err().status, 404);
+
+        let socket: SocketAddr = "127.0.0.1:40001".parse().unwrap();
+        connect_client(&client_id, socket, &client_handler, &topic_handler);
+        topic_handler.subscribe(&String::from("other"), &String::from("devices/#"));
+        let export = client_api.export(bearer(TOKEN), client_id.clone()).unwrap();
+        assert!(export.connected);
+        let connection = export.session.unwrap().connection.unwrap();
+        assert_eq!(connection.address, socket);
+        assert_eq!(connection.username, Some(String::from("alice")));
+        assert_eq!(connection.disconnected_at, None);
+        assert_eq!(export.subscriptions.len(), 1);
+        assert_eq!(export.subscriptions[0].topic_filter, "devices/+/status");
+        assert_eq!(export.retained_messages.len(), 1);
+        assert_eq!(export.retained_messages[0].topic, "devices/client-api-export/status");
+    }
+
+    #[tokio::test]
+    async fn purge_client() {
+        let (client_api, client_handler, topic_handler, mut from_broker) = create_client_api();
+        let client_id = String::from("client-api-purge");
+        let socket: SocketAddr = "127.0.0.1:40002".parse().unwrap();
+        connect_client(&client_id, socket, &client_handler, &topic_handler);
+        topic_handler.retain_message(&String::from("other"), &ControlPacket::publish_with_payload(None, String::from("devices/other/status"), QoSLevel::AtMostOnce, true, vec![], b"online".to_vec()), Instant::now());
+
+        let purge = client_api.purge(bearer(TOKEN), client_id.clone()).await.unwrap();
+        assert_eq!(purge, ClientPurge { client_id: client_id.clone(), disconnected: true, session_removed: true, subscriptions_removed: 1, retained_messages_removed: 1 });
+        let (sockets, disconnect_packet) = from_broker.recv().await.unwrap();
+        assert_eq!(sockets, vec![socket]);
+        assert_eq!(disconnect_packet.fixed_header().packet_type(), ControlPacketType::DISCONNECT);
+        assert_eq!(disconnect_packet.variable_header().reason_code(), Some(&ReasonCode::AdministrativeAction));
+
+        assert!(session_summary(&*client_handler.sessions, &client_id).is_none());
+        assert!(topic_handler.subscriptions_of(&client_id).is_empty());
+        assert!(topic_handler.retained_message(&String::from("devices/client-api-purge/status")).is_none());
+        assert!(topic_handler.retained_message(&String::from("devices/other/status")).is_some());
+    }
+
+    #[tokio::test]
+    async fn inspect_and_purge_queue() {
+        let (client_api, client_handler, _, _from_broker) = create_client_api();
+        let client_id = String::from("client-api-queue");
+        assert_eq!(client_api.queue(bearer(TOKEN), client_id.clone()).unwrap_err().status, 404);
+        register_clean_session(&*client_handler.sessions, &client_id);
+        persist_packet(&*client_handler.sessions, &client_id, &[], &ControlPacket::publish_with_payload(None, String::from("logs/debug"), QoSLevel::AtMostOnce, false, vec![], vec![0; 512]), Instant::now() - Duration::from_secs(120));
+        persist_packet(&*client_handler.sessions, &client_id, &[], &ControlPacket::publish_with_payload(Some(7), String::from("logs/debug"), QoSLevel::AtLeastOnce, false, vec![], vec![0; 512]), Instant::now() - Duration::from_secs(60));
+        persist_packet(&*client_handler.sessions, &client_id, &[], &ControlPacket::publish_with_payload(Some(8), String::from("alarms/fire"), QoSLevel::ExactlyOnce, false, vec![], b"now".to_vec()), Instant::now());
+
+        let messages = client_api.queue(bearer(TOKEN), client_id.clone()).unwrap();
+        assert_eq!(messages.iter().map(|message| (message.topic.as_str(), message.qos, message.packet_identifier, message.size)).collect::<Vec<_>>(),
+                   vec![("logs/debug", 0, None, 512), ("logs/debug", 1, Some(7), 512), ("alarms/fire", 2, Some(8), 3)]);
+        assert!(messages[0].age_secs >= 120);
+
+        let purge = client_api.purge_queue(bearer(TOKEN), client_id.clone(), QueueSelector { topic_filter: Some(String::from("logs/#")), older_than_secs: Some(90), ..QueueSelector::default() }).unwrap();
+        assert_eq!(purge, QueuePurge { client_id: client_id.clone(), purged: 1, remaining: 2 });
+        let purge = client_api.purge_queue(bearer(TOKEN), client_id.clone(), QueueSelector { qos: Some(1), packet_identifier: Some(7), ..QueueSelector::default() }).unwrap();
+        assert_eq!(purge.purged, 1);
+        assert_eq!(client_api.queue(bearer(TOKEN), client_id.clone()).unwrap()[0].topic, "alarms/fire");
+        let purge = client_api.purge_queue(bearer(TOKEN), client_id.clone(), QueueSelector::default()).unwrap();
+        assert_eq!(purge, QueuePurge { client_id: client_id.clone(), purged: 1, remaining: 0 });
+        assert_eq!(client_api.purge_queue(None, client_id, QueueSelector::default()).unwrap_err().status, 401);
+    }
+}
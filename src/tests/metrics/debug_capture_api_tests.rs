@@ -0,0 +1,57 @@
+#[cfg(all(test, feature = "admin-api"))]
+mod debug_capture_api_tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use crate::audit::audit_log::AuditLog;
+    use crate::config::broker_config::{AuditConfig, BrokerConfig};
+    use crate::metrics::debug_capture_api::{DebugCapture, DebugCaptureApi, DebugCaptureQuery};
+    use crate::session::client_handler::ClientHandler;
+
+    const TOKEN: &str = "secret";
+
+    fn create_debug_capture_api() -> (DebugCaptureApi, Arc<ClientHandler>) {
+        let mut config = BrokerConfig::default();
+        config.admin.api_token = Some(String::from(TOKEN));
+        config.debug_capture.max_duration_secs = 600;
+        let client_handler = Arc::new(ClientHandler::default());
+        let debug_capture_api = DebugCaptureApi::new(Arc::new(config), client_handler.clone(), Arc::new(AuditLog::new(AuditConfig::default())));
+        (debug_capture_api, client_handler)
+    }
+
+    fn bearer(token: &str) -> Option<String> {
+        Some(format!("Bearer {}", token))
+    }
+
+    #[test]
+    fn debug_capture_api_requires_admin_token() {
+        let (debug_capture_api, client_handler) = create_debug_capture_api();
+        let client_id = String::from("debug-unauthorized");
+        assert_eq!(debug_capture_api.list(None).unwrap_err().status, 401);
+        assert_eq!(debug_capture_api.start(bearer("wrong!"), client_id.clone(), DebugCaptureQuery { duration_secs: None }).unwrap_err().status, 401);
+        assert_eq!(debug_capture_api.stop(None, client_id.clone()).unwrap_err().status, 401);
+        assert!(!client_handler.is_debug_captured(&client_id));
+    }
+
+    #[test]
+    fn start_list_and_stop_debug_capture() {
+        let (debug_capture_api, client_handler) = create_debug_capture_api();
+        let client_id = String::from("debug-sensor");
+        let socket: SocketAddr = "10.0.0.1:1000".parse().unwrap();
+        client_handler.register(&socket, &client_id);
+
+        assert_eq!(debug_capture_api.start(bearer(TOKEN), client_id.clone(), DebugCaptureQuery { duration_secs: Some(601) }).unwrap_err().status, 400);
+        assert_eq!(debug_capture_api.start(bearer(TOKEN), client_id.clone(), DebugCaptureQuery { duration_secs: Some(0) }).unwrap_err().status, 400);
+        let capture = debug_capture_api.start(bearer(TOKEN), client_id.clone(), DebugCaptureQuery { duration_secs: None }).unwrap();
+        assert_eq!(capture, DebugCapture { client_id: client_id.clone(), connected: true, stops_in_secs: 300 });
+        assert_eq!(client_handler.debug_captured_client(&socket), Some(client_id.clone()));
+
+        let captures = debug_capture_api.list(bearer(TOKEN)).unwrap();
+        assert_eq!(captures.len(), 1);
+        assert!(captures[0].stops_in_secs <= 300 && captures[0].stops_in_secs >= 299);
+
+        assert_eq!(debug_capture_api.stop(bearer(TOKEN), client_id.clone()).unwrap().status, 200);
+        assert_eq!(debug_capture_api.stop(bearer(TOKEN), client_id.clone()).unwrap_err().status, 404);
+        assert_eq!(client_handler.debug_captured_client(&socket), None);
+    }
+}
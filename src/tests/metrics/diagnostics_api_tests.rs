@@ -0,0 +1,28 @@
+#[cfg(all(test, feature = "admin-api"))]
+mod diagnostics_api_tests {
+    use std::sync::Arc;
+
+    use crate::audit::audit_log::AuditLog;
+    use crate::broker::diagnostics::Diagnostics;
+    use crate::config::broker_config::{AuditConfig, BrokerConfig, DiagnosticsConfig};
+    use crate::metrics::diagnostics_api::DiagnosticsApi;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    const TOKEN: &str = "secret";
+
+    #[tokio::test]
+    async fn diagnostics_api_requires_admin_token() {
+        let harness = HandlerHarness::default();
+        let directory = std::env::temp_dir().join(format!("patina-diagnostics-api-{}", std::process::id()));
+        let mut config = BrokerConfig::default();
+        config.admin.api_token = Some(String::from(TOKEN));
+        let diagnostics = Diagnostics::new(DiagnosticsConfig { directory: directory.display().to_string(), ..DiagnosticsConfig::default() }, harness.packet_dispatcher.clone());
+        let diagnostics_api = DiagnosticsApi::new(Arc::new(config), Arc::new(diagnostics), Arc::new(AuditLog::new(AuditConfig::default())));
+
+        assert_eq!(diagnostics_api.dump(None).unwrap_err().status, 401);
+        assert!(!directory.exists());
+        let dump = diagnostics_api.dump(Some(format!("Bearer {}", TOKEN))).unwrap();
+        assert!(std::path::Path::new(&dump.path).exists());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
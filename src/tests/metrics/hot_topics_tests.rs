@@ -0,0 +1,76 @@
+#[cfg(test)]
+mod hot_topics_tests {
+    use std::time::{Duration, Instant};
+
+    use crate::config::broker_config::HotTopicsConfig;
+    use crate::metrics::hot_topics::HotTopics;
+
+    fn hot_topics(capacity: usize) -> HotTopics {
+        HotTopics::new(HotTopicsConfig { enabled: true, capacity, half_life_secs: 10 })
+    }
+
+    fn topics(hot_topics: &HotTopics, now: Instant) -> Vec<String> {
+        hot_topics.report(None, now).topics.into_iter().map(|hot_topic| hot_topic.topic).collect()
+    }
+
+    #[test]
+    fn rank_topics_by_rate() {
+        let hot_topics = hot_topics(10);
+        let now = Instant::now();
+        for (topic_name, messages) in [("sensors/a", 5), ("sensors/b", 20), ("sensors/c", 1)] {
+            for _ in 0..messages {
+                hot_topics.record(topic_name, now);
+            }
+        }
+        let report = hot_topics.report(Some(2), now);
+        assert_eq!(report.tracked, 3);
+        assert_eq!(report.topics.len(), 2);
+        assert_eq!(report.topics[0].topic, "sensors/b");
+        assert_eq!(report.topics[0].messages, 20);
+        assert_eq!(report.topics[1].topic, "sensors/a");
+        assert!(report.topics[0].rate > report.topics[1].rate);
+    }
+
+    #[test]
+    fn decay_with_half_life() {
+        let hot_topics = hot_topics(10);
+        let now = Instant::now();
+        for _ in 0..8 {
+            hot_topics.record("sensors/a", now);
+        }
+        let rate = hot_topics.report(None, now).topics[0].rate;
+        let later = hot_topics.report(None, now + Duration::from_secs(10)).topics[0].rate;
+        assert!((later - rate / 2.0).abs() < 1e-9, "{} {}", rate, later);
+
+        //A topic that cooled down falls behind a newly busy one
+        for _ in 0..3 {
+            hot_topics.record("sensors/b", now + Duration::from_secs(30));
+        }
+        assert_eq!(topics(&hot_topics, now + Duration::from_secs(30)), vec!["sensors/b", "sensors/a"]);
+    }
+
+    #[test]
+    fn bound_tracked_topics_by_capacity() {
+        let hot_topics = hot_topics(3);
+        let now = Instant::now();
+        for topic in 0..1000 {
+            hot_topics.record("sensors/hot", now);
+            hot_topics.record(&format!("sensors/cold/{}", topic), now);
+        }
+        let report = hot_topics.report(Some(100), now);
+        assert_eq!(report.tracked, 3);
+        assert_eq!(report.topics.len(), 3);
+        assert_eq!(report.topics[0].topic, "sensors/hot");
+        assert_eq!(hot_topics.metrics.evicted.0.get(), 998);
+        //Inherited the score of the topic it replaced
+        assert!(report.topics[1].error > 0.0);
+    }
+
+    #[test]
+    fn record_nothing_when_disabled() {
+        let hot_topics = HotTopics::new(HotTopicsConfig::default());
+        hot_topics.record("sensors/a", Instant::now());
+        assert_eq!(hot_topics.report(None, Instant::now()).tracked, 0);
+        assert_eq!(hot_topics.metrics.recorded.0.get(), 0);
+    }
+}
@@ -0,0 +1,46 @@
+#[cfg(all(test, feature = "admin-api"))]
+mod listener_api_tests {
+    use std::sync::Arc;
+
+    use crate::audit::audit_log::AuditLog;
+    use crate::config::broker_config::{AuditConfig, BrokerConfig};
+    use crate::connection::listeners::{ListenerMode, Listeners, MQTT_LISTENER};
+    use crate::metrics::listener_api::ListenerApi;
+
+    const TOKEN: &str = "secret";
+
+    fn create_listener_api() -> (ListenerApi, Arc<Listeners>) {
+        let mut config = BrokerConfig::default();
+        config.admin.api_token = Some(String::from(TOKEN));
+        let listeners = Arc::new(Listeners::new(&config));
+        let listener_api = ListenerApi::new(Arc::new(config), listeners.clone(), Arc::new(AuditLog::new(AuditConfig::default())));
+        (listener_api, listeners)
+    }
+
+    fn bearer(token: &str) -> Option<String> {
+        Some(format!("Bearer {}", token))
+    }
+
+    #[test]
+    fn listener_api_requires_admin_token() {
+        let (listener_api, listeners) = create_listener_api();
+        assert_eq!(listener_api.list(None).unwrap_err().status, 401);
+        assert_eq!(listener_api.drain(bearer("wrong!"), String::from(MQTT_LISTENER)).unwrap_err().status, 401);
+        assert_eq!(listener_api.resume(None, String::from(MQTT_LISTENER)).unwrap_err().status, 401);
+        assert_eq!(listeners.statuses()[0].mode, ListenerMode::Accepting);
+    }
+
+    #[test]
+    fn drain_and_resume_listener() {
+        let (listener_api, listeners) = create_listener_api();
+        assert_eq!(listener_api.drain(bearer(TOKEN), String::from("mqtt-tls")).unwrap_err().status, 404);
+
+        let listener = listener_api.drain(bearer(TOKEN), String::from(MQTT_LISTENER)).unwrap();
+        assert_eq!(listener.mode, ListenerMode::Draining);
+        assert_eq!(listener_api.list(bearer(TOKEN)).unwrap(), listeners.statuses());
+        assert_eq!(listeners.statuses()[0].mode, ListenerMode::Draining);
+
+        assert_eq!(listener_api.resume(bearer(TOKEN), String::from(MQTT_LISTENER)).unwrap().mode, ListenerMode::Accepting);
+        assert_eq!(listeners.statuses()[0].mode, ListenerMode::Accepting);
+    }
+}
@@ -0,0 +1,14 @@
+pub mod admin_api_tests;
+pub mod client_api_tests;
+pub mod debug_capture_api_tests;
+pub mod diagnostics_api_tests;
+pub mod hot_topics_tests;
+pub mod listener_api_tests;
+pub mod profile_api_tests;
+pub mod publish_api_tests;
+pub mod subscribe_api_tests;
+pub mod subscription_api_tests;
+pub mod payload_sizes_tests;
+pub mod state_api_tests;
+pub mod retained_api_tests;
+pub mod user_api_tests;
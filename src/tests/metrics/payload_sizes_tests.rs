@@ -0,0 +1,50 @@
+#[cfg(test)]
+mod payload_sizes_tests {
+    use crate::config::broker_config::PayloadSizesConfig;
+    use crate::metrics::payload_sizes::PayloadSizes;
+    use crate::session::qos2_tracker::Direction;
+
+    fn payload_sizes(prefix_levels: usize, capacity: usize) -> PayloadSizes {
+        PayloadSizes::new(PayloadSizesConfig { enabled: true, prefix_levels, capacity })
+    }
+
+    #[test]
+    fn disabled() {
+        let payload_sizes = PayloadSizes::new(PayloadSizesConfig { enabled: false, prefix_levels: 1, capacity: 0 });
+        payload_sizes.record(Direction::Inbound, "sensors/a", 10);
+        assert_eq!(payload_sizes.metrics.untracked.0.get(), 0);
+    }
+
+    #[test]
+    fn prefixes() {
+        let payload_sizes = payload_sizes(2, 10);
+        assert_eq!(payload_sizes.prefix("sensors/a/temperature"), "sensors/a");
+        assert_eq!(payload_sizes.prefix("sensors/a"), "sensors/a");
+        assert_eq!(payload_sizes.prefix("alerts"), "alerts");
+        assert_eq!(payload_sizes.prefix("/a/b"), "/a");
+        assert_eq!(self::payload_sizes(0, 10).prefix("sensors/a"), "sensors");
+    }
+
+    #[cfg(feature = "admin-api")]
+    #[test]
+    fn export_percentiles() {
+        use std::collections::HashMap;
+
+        let payload_sizes = payload_sizes(1, 2);
+        for size in 1..=100 {
+            payload_sizes.record(Direction::Inbound, "sensors/a", size);
+        }
+        payload_sizes.record(Direction::Inbound, "logs/a", 10000);
+        payload_sizes.record(Direction::Inbound, "$SYS/broker", 50);
+        payload_sizes.record(Direction::Outbound, "sensors/b", 7);
+        let metrics = serde_prometheus::to_string(&payload_sizes.metrics(), Some("patina"), HashMap::<&str, &str>::new()).unwrap();
+        assert!(metrics.contains("patina_all_samples{path = \"inbound\"} 102"));
+        assert!(metrics.contains("patina_sensors{quantile = \"0.9\", path = \"inbound/by_prefix\"} 90"));
+        assert!(metrics.contains("patina_sensors_max{path = \"inbound/by_prefix\"} 100"));
+        //Past the capacity of 2 prefixes
+        assert!(metrics.contains("patina_other_samples{path = \"inbound\"} 1"));
+        assert!(!metrics.contains("_SYS"));
+        assert!(metrics.contains("patina_sensors_samples{path = \"outbound/by_prefix\"} 1"));
+        assert!(metrics.contains("patina_untracked 1"));
+    }
+}
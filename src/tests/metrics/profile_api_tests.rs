@@ -0,0 +1,43 @@
+#[cfg(all(test, feature = "profiling"))]
+mod profile_api_tests {
+    use std::sync::Arc;
+
+    use crate::audit::audit_log::AuditLog;
+    use crate::config::broker_config::{AuditConfig, BrokerConfig};
+    use crate::metrics::profile_api::{ProfileApi, ProfileFormat, ProfileQuery};
+
+    const TOKEN: &str = "secret";
+
+    fn create_profile_api() -> ProfileApi {
+        let mut config = BrokerConfig::default();
+        config.admin.api_token = Some(String::from(TOKEN));
+        config.profiling.max_seconds = 5;
+        ProfileApi::new(Arc::new(config), Arc::new(AuditLog::new(AuditConfig::default())))
+    }
+
+    #[test]
+    fn profile_length_is_bounded() {
+        let profile_api = create_profile_api();
+        assert_eq!(profile_api.seconds(&ProfileQuery::default()), 5);
+        assert_eq!(profile_api.seconds(&ProfileQuery { seconds: Some(0), ..ProfileQuery::default() }), 1);
+        assert_eq!(profile_api.seconds(&ProfileQuery { seconds: Some(3), ..ProfileQuery::default() }), 3);
+    }
+
+    #[tokio::test]
+    async fn profile_requires_admin_token() {
+        let profile_api = create_profile_api();
+        assert_eq!(profile_api.profile(None, ProfileQuery::default()).await.unwrap_err().status, 401);
+    }
+
+    #[tokio::test]
+    async fn one_profile_at_a_time() {
+        let profile_api = create_profile_api();
+        let authorization = Some(format!("Bearer {}", TOKEN));
+        let query = || ProfileQuery { seconds: Some(1), format: ProfileFormat::Pprof };
+        let (first, second) = tokio::join!(profile_api.profile(authorization.clone(), query()), profile_api.profile(authorization.clone(), query()));
+        assert!(!first.unwrap().is_empty());
+        assert_eq!(second.unwrap_err().status, 409);
+        //Released once the first one is done
+        assert!(profile_api.profile(authorization, query()).await.is_ok());
+    }
+}
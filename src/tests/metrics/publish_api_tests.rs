@@ -0,0 +1,87 @@
+#[cfg(all(test, feature = "admin-api"))]
+mod publish_api_tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    use tokio::sync::mpsc::Receiver;
+
+    use crate::audit::audit_log::AuditLog;
+    use crate::config::broker_config::{AuditConfig, BrokerConfig};
+    use crate::connection::client_context::ClientContext;
+    use crate::connection::virtual_endpoint::VirtualEndpoints;
+    use crate::metrics::admin_api::ApiResponse;
+    use crate::metrics::publish_api::{PublishApi, PublishRequest};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::variable_header::Property;
+
+    const TOKEN: &str = "secret";
+
+    async fn create_publish_api() -> (Arc<PublishApi>, Receiver<(ClientContext, ControlPacket)>, Arc<VirtualEndpoints>) {
+        let mut config = BrokerConfig::default();
+        config.admin.api_token = Some(String::from(TOKEN));
+        let (listener2broker_tx, mut listener2broker_rx) = tokio::sync::mpsc::channel(10);
+        let virtual_endpoints = Arc::new(VirtualEndpoints::default());
+        let (publish_api, from_broker) = PublishApi::new(Arc::new(config), Arc::new(listener2broker_tx), virtual_endpoints.clone(), Arc::new(AuditLog::new(AuditConfig::default())));
+        publish_api.start(from_broker).await.unwrap();
+        let (_, connect_packet) = listener2broker_rx.recv().await.unwrap();
+        assert_eq!(connect_packet.fixed_header().packet_type(), ControlPacketType::CONNECT);
+        (Arc::new(publish_api), listener2broker_rx, virtual_endpoints)
+    }
+
+    fn create_request(qos: u8) -> PublishRequest {
+        let mut user_properties = BTreeMap::new();
+        user_properties.insert(String::from("source"), String::from("webhook"));
+        PublishRequest { topic: String::from("devices/door"), payload: String::from("open"), qos, retain: true, user_properties }
+    }
+
+    fn bearer(token: &str) -> Option<String> {
+        Some(format!("Bearer {}", token))
+    }
+
+    #[tokio::test]
+    async fn publish_requires_token() {
+        let (publish_api, _listener2broker_rx, _) = create_publish_api().await;
+        assert_eq!(publish_api.handle(None, create_request(0)).await.status, 401);
+        assert_eq!(publish_api.handle(bearer("secreT"), create_request(0)).await.status, 401);
+
+        let (listener2broker_tx, _) = tokio::sync::mpsc::channel(10);
+        let (publish_api, _) = PublishApi::new(Arc::new(BrokerConfig::default()), Arc::new(listener2broker_tx), Arc::new(VirtualEndpoints::default()), Arc::new(AuditLog::new(AuditConfig::default())));
+        assert_eq!(publish_api.handle(bearer(TOKEN), create_request(0)).await.status, 403);
+    }
+
+    #[tokio::test]
+    async fn publish_rejects_invalid_request() {
+        let (publish_api, _listener2broker_rx, _) = create_publish_api().await;
+        assert_eq!(publish_api.handle(bearer(TOKEN), create_request(3)).await.status, 400);
+        let mut request = create_request(0);
+        request.topic = String::from("devices/+");
+        assert_eq!(publish_api.handle(bearer(TOKEN), request).await.status, 400);
+    }
+
+    #[tokio::test]
+    async fn publish_qos0() {
+        let (publish_api, mut listener2broker_rx, _) = create_publish_api().await;
+        assert_eq!(publish_api.handle(bearer(TOKEN), create_request(0)).await, ApiResponse { status: 202, message: String::from("Accepted") });
+
+        let (_, publish_packet) = listener2broker_rx.recv().await.unwrap();
+        assert_eq!(publish_packet.fixed_header().packet_type(), ControlPacketType::PUBLISH);
+        assert!(*publish_packet.fixed_header().retain());
+        assert_eq!(publish_packet.variable_header().topic_name(), &String::from("devices/door"));
+        assert_eq!(publish_packet.payload().data(), &b"open".to_vec());
+        assert!(publish_packet.variable_header().properties().contains(&Property::UserProperty(String::from("source"), String::from("webhook"))));
+    }
+
+    #[tokio::test]
+    async fn publish_qos1_waits_for_puback() {
+        let (publish_api, mut listener2broker_rx, virtual_endpoints) = create_publish_api().await;
+        let publish_api_ = publish_api.clone();
+        let response = tokio::spawn(async move { publish_api_.handle(bearer(TOKEN), create_request(1)).await });
+
+        let (context, publish_packet) = listener2broker_rx.recv().await.unwrap();
+        assert_eq!(*publish_packet.fixed_header().qos_level(), QoSLevel::AtLeastOnce);
+        virtual_endpoints.deliver(&context.socket, ControlPacket::puback(publish_packet.variable_header().packet_identifier_opt())).unwrap();
+        assert_eq!(response.await.unwrap().status, 200);
+    }
+}
@@ -0,0 +1,68 @@
+#[cfg(all(test, feature = "admin-api"))]
+mod retained_api_tests {
+    use std::sync::Arc;
+    use std::time::Instant;
+
+    use crate::audit::audit_log::AuditLog;
+    use crate::config::broker_config::{AuditConfig, BrokerConfig};
+    use crate::metrics::retained_api::{RetainedApi, RetainedPurge, RetainedQuery, RetainedSelector};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::qos_level::QoSLevel;
+    use crate::topic::topic_handler::TopicHandler;
+
+    const TOKEN: &str = "secret";
+
+    fn create_retained_api() -> (RetainedApi, Arc<TopicHandler>) {
+        let mut config = BrokerConfig::default();
+        config.admin.api_token = Some(String::from(TOKEN));
+        let topic_handler = Arc::new(TopicHandler::default());
+        let retained_api = RetainedApi::new(Arc::new(config), topic_handler.clone(), Arc::new(AuditLog::new(AuditConfig::default())));
+        for device in ["a", "b", "c", "d", "e"] {
+            let publish_packet = ControlPacket::publish_with_payload(Some(1), format!("shadows/{}/state", device), QoSLevel::AtLeastOnce, true, vec![], b"{\"on\":true}".to_vec());
+            topic_handler.retain_message(&String::from("device"), &publish_packet, Instant::now());
+        }
+        let publish_packet = ControlPacket::publish_with_payload(None, String::from("alerts/fire"), QoSLevel::AtMostOnce, true, vec![], b"1".to_vec());
+        topic_handler.retain_message(&String::from("alarm"), &publish_packet, Instant::now());
+        (retained_api, topic_handler)
+    }
+
+    fn bearer(token: &str) -> Option<String> {
+        Some(format!("Bearer {}", token))
+    }
+
+    fn query(filter: &str, limit: Option<usize>, after: Option<&str>) -> RetainedQuery {
+        RetainedQuery { filter: String::from(filter), limit, after: after.map(String::from) }
+    }
+
+    #[test]
+    fn retained_api_requires_token() {
+        let (retained_api, _) = create_retained_api();
+        assert_eq!(retained_api.list(None, query("#", None, None)).unwrap_err().status, 401);
+        assert_eq!(retained_api.delete(bearer("wrong!"), RetainedSelector { filter: String::from("#") }).unwrap_err().status, 401);
+        assert_eq!(retained_api.list(bearer(TOKEN), query("shadows/#/state", None, None)).unwrap_err().status, 400);
+    }
+
+    #[test]
+    fn list_retained_pages() {
+        let (retained_api, _) = create_retained_api();
+        let page = retained_api.list(bearer(TOKEN), query("shadows/+/state", Some(2), None)).unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!(page.messages.iter().map(|message| message.topic.as_str()).collect::<Vec<&str>>(), vec!["shadows/a/state", "shadows/b/state"]);
+        assert_eq!((page.messages[0].qos, page.messages[0].size, page.messages[0].publisher.as_str()), (1, 11, "device"));
+        assert_eq!(page.next, Some(String::from("shadows/b/state")));
+
+        let page = retained_api.list(bearer(TOKEN), query("shadows/+/state", Some(2), page.next.as_deref())).unwrap();
+        assert_eq!(page.messages.iter().map(|message| message.topic.as_str()).collect::<Vec<&str>>(), vec!["shadows/c/state", "shadows/d/state"]);
+        let page = retained_api.list(bearer(TOKEN), query("shadows/+/state", Some(2), page.next.as_deref())).unwrap();
+        assert_eq!(page.messages.iter().map(|message| message.topic.as_str()).collect::<Vec<&str>>(), vec!["shadows/e/state"]);
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn delete_retained_by_filter() {
+        let (retained_api, topic_handler) = create_retained_api();
+        let purge = retained_api.delete(bearer(TOKEN), RetainedSelector { filter: String::from("shadows/#") }).unwrap();
+        assert_eq!(purge, RetainedPurge { filter: String::from("shadows/#"), deleted: 5 });
+        assert_eq!(topic_handler.retained_topics(&String::from("#")), vec![String::from("alerts/fire")]);
+    }
+}
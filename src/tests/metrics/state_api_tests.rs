@@ -0,0 +1,60 @@
+#[cfg(all(test, feature = "admin-api"))]
+mod state_api_tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+
+    use crate::audit::audit_log::AuditLog;
+    use crate::broker::utils::get_session_expiry_interval;
+    use crate::config::broker_config::{AuditConfig, BrokerConfig};
+    use crate::metrics::state_api::StateApi;
+    use crate::session::client_handler::ClientHandler;
+    use crate::session::session_store::StoredSession;
+    use crate::session::state_snapshot::StateSnapshot;
+    use crate::topic::topic_handler::TopicHandler;
+
+    const TOKEN: &str = "secret";
+
+    fn create_state_api() -> (StateApi, Arc<ClientHandler>) {
+        let mut config = BrokerConfig::default();
+        config.admin.api_token = Some(String::from(TOKEN));
+        let client_handler = Arc::new(ClientHandler::default());
+        (StateApi::new(Arc::new(config), client_handler.clone(), Arc::new(TopicHandler::default()), Arc::new(AuditLog::new(AuditConfig::default()))), client_handler)
+    }
+
+    fn bearer(token: &str) -> Option<String> {
+        Some(format!("Bearer {}", token))
+    }
+
+    fn create_session(client_id: &str) -> StoredSession {
+        StoredSession { client_id: String::from(client_id), session_expiry_interval: 600, request_problem_information: false, disconnected_at: None, subscriptions: vec![], messages: vec![] }
+    }
+
+    #[test]
+    fn state_api_requires_admin_token() {
+        let (state_api, _) = create_state_api();
+        assert_eq!(state_api.export(None).unwrap_err().status, 401);
+        assert_eq!(state_api.import(bearer("wrong!"), b"{}").unwrap_err().status, 401);
+    }
+
+    #[tokio::test]
+    async fn import_and_export_state() {
+        let (state_api, client_handler) = create_state_api();
+        let client_id = String::from("state-api-imported");
+        let snapshot = StateSnapshot::new(vec![create_session(&client_id)], Utc::now().timestamp_millis());
+
+        let state_import = state_api.import(bearer(TOKEN), &snapshot.to_json().unwrap()).unwrap();
+        assert_eq!(state_import.imported, vec![client_id.clone()]);
+        assert_eq!(get_session_expiry_interval(&*client_handler.sessions, &client_id), Some(600));
+
+        let exported = state_api.export(bearer(TOKEN)).unwrap();
+        let session = exported.sessions.iter().find(|session| session.client_id == client_id).unwrap();
+        assert_eq!(session.disconnected_at, Some(snapshot.exported_at));
+    }
+
+    #[test]
+    fn invalid_state_is_refused() {
+        let (state_api, _) = create_state_api();
+        assert_eq!(state_api.import(bearer(TOKEN), b"not json").unwrap_err().status, 400);
+    }
+}
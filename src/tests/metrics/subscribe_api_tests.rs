@@ -0,0 +1,89 @@
+#[cfg(all(test, feature = "admin-api"))]
+mod subscribe_api_tests {
+    use std::sync::Arc;
+
+    use tokio::sync::mpsc::Receiver;
+
+    use crate::audit::audit_log::AuditLog;
+    use crate::config::broker_config::{AuditConfig, BrokerConfig};
+    use crate::connection::client_context::ClientContext;
+    use crate::connection::virtual_endpoint::VirtualEndpoints;
+    use crate::metrics::subscribe_api::{MessageEnvelope, SubscribeApi};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::Property;
+    use crate::topic::topic_handler::TopicHandler;
+
+    const TOKEN: &str = "secret";
+
+    fn create_subscribe_api(max_subscribe_streams: usize) -> (SubscribeApi, Receiver<(ClientContext, ControlPacket)>, Arc<VirtualEndpoints>, Arc<TopicHandler>) {
+        let mut config = BrokerConfig::default();
+        config.admin.api_token = Some(String::from(TOKEN));
+        config.admin.max_subscribe_streams = max_subscribe_streams;
+        let (listener2broker_tx, listener2broker_rx) = tokio::sync::mpsc::channel(10);
+        let virtual_endpoints = Arc::new(VirtualEndpoints::default());
+        let topic_handler = Arc::new(TopicHandler::default());
+        let subscribe_api = SubscribeApi::new(Arc::new(config), Arc::new(listener2broker_tx), virtual_endpoints.clone(), topic_handler.clone(), Arc::new(AuditLog::new(AuditConfig::default())));
+        (subscribe_api, listener2broker_rx, virtual_endpoints, topic_handler)
+    }
+
+    fn bearer(token: &str) -> Option<String> {
+        Some(format!("Bearer {}", token))
+    }
+
+    #[tokio::test]
+    async fn subscribe_requires_token() {
+        let (subscribe_api, _listener2broker_rx, _, _) = create_subscribe_api(1);
+        assert_eq!(subscribe_api.open(None, String::from("devices/door")).await.unwrap_err().status, 401);
+        assert_eq!(subscribe_api.open(bearer("wrong!"), String::from("devices/door")).await.unwrap_err().status, 401);
+        assert_eq!(subscribe_api.active_streams(), 0);
+    }
+
+    #[tokio::test]
+    async fn subscribe_streams_publishes() {
+        let (subscribe_api, mut listener2broker_rx, virtual_endpoints, _) = create_subscribe_api(1);
+        let mut subscription_stream = subscribe_api.open(bearer(TOKEN), String::from("devices/door")).await.unwrap();
+
+        let (context, connect_packet) = listener2broker_rx.recv().await.unwrap();
+        let socket = context.socket;
+        assert_eq!(connect_packet.fixed_header().packet_type(), ControlPacketType::CONNECT);
+        assert_eq!(connect_packet.payload().client_id(), &String::from("admin-api-sse-1"));
+        let (_, subscribe_packet) = listener2broker_rx.recv().await.unwrap();
+        assert_eq!(subscribe_packet.fixed_header().packet_type(), ControlPacketType::SUBSCRIBE);
+
+        virtual_endpoints.deliver(&socket, ControlPacket::suback(Some(1), vec![ReasonCode::GrantedQoS0], vec![])).unwrap();
+        let properties = vec![Property::UserProperty(String::from("source"), String::from("webhook"))];
+        virtual_endpoints.deliver(&socket, ControlPacket::publish_with_payload(None, String::from("devices/door"), QoSLevel::AtMostOnce, false, properties.clone(), b"open".to_vec())).unwrap();
+        assert_eq!(subscription_stream.next_envelope().await, Some(MessageEnvelope {
+            topic: String::from("devices/door"),
+            payload: String::from("b3Blbg=="),
+            qos: 0,
+            retain: false,
+            properties,
+        }));
+
+        virtual_endpoints.deliver(&socket, ControlPacket::disconnect(ReasonCode::NormalDisconnection)).unwrap();
+        assert_eq!(subscription_stream.next_envelope().await, None);
+    }
+
+    #[tokio::test]
+    async fn subscribe_limits_streams() {
+        let (subscribe_api, mut listener2broker_rx, _, topic_handler) = create_subscribe_api(1);
+        let subscription_stream = subscribe_api.open(bearer(TOKEN), String::from("devices/door")).await.unwrap();
+        assert_eq!(subscribe_api.open(bearer(TOKEN), String::from("devices/window")).await.unwrap_err().status, 429);
+        assert_eq!(subscribe_api.active_streams(), 1);
+
+        topic_handler.subscribe(&String::from("admin-api-sse-1"), &String::from("devices/door"));
+        drop(subscription_stream);
+        assert_eq!(subscribe_api.active_streams(), 0);
+        assert!(topic_handler.find_subscribers(&String::from("devices/door")).is_empty());
+        let mut packet_types = Vec::new();
+        while let Ok((_, control_packet)) = listener2broker_rx.try_recv() {
+            packet_types.push(control_packet.fixed_header().packet_type());
+        }
+        assert_eq!(packet_types, vec![ControlPacketType::CONNECT, ControlPacketType::SUBSCRIBE, ControlPacketType::DISCONNECT]);
+        assert!(subscribe_api.open(bearer(TOKEN), String::from("devices/window")).await.is_ok());
+    }
+}
@@ -0,0 +1,26 @@
+#[cfg(all(test, feature = "admin-api"))]
+mod subscription_api_tests {
+    use std::time::Duration;
+
+    use crate::metrics::subscription_api::{StaleSubscriptionQuery, StaleSubscriptionReport};
+    use crate::TopicHandler;
+
+    #[test]
+    fn stale_subscriptions_longest_idle_first() {
+        let topic_handler = TopicHandler::default();
+        let client_id = String::from("stale_report");
+        topic_handler.subscribe(&client_id, &String::from("test/first"));
+        std::thread::sleep(Duration::from_millis(5));
+        topic_handler.subscribe(&client_id, &String::from("test/second"));
+
+        let report = StaleSubscriptionReport::new(&topic_handler, &StaleSubscriptionQuery { idle_secs: None, limit: None });
+        assert_eq!(report.idle_secs, 3600);
+        assert_eq!(report.total, 0);
+
+        std::thread::sleep(Duration::from_millis(5));
+        let report = StaleSubscriptionReport::new(&topic_handler, &StaleSubscriptionQuery { idle_secs: Some(0), limit: Some(1) });
+        assert_eq!(report.total, 2);
+        assert_eq!(report.subscriptions.len(), 1);
+        assert_eq!(report.subscriptions[0].topic_filter, "test/first");
+    }
+}
@@ -0,0 +1,72 @@
+#[cfg(all(test, feature = "admin-api"))]
+mod user_api_tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use tokio::sync::mpsc::Receiver;
+
+    use crate::audit::audit_log::AuditLog;
+    use crate::config::broker_config::{AuditConfig, BrokerConfig};
+    use crate::metrics::user_api::{UserApi, UserClient, UserConnections, UserKick};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::reason_code::ReasonCode;
+    use crate::session::client_handler::ClientHandler;
+
+    const TOKEN: &str = "secret";
+
+    fn create_user_api() -> (UserApi, Arc<ClientHandler>, Receiver<(Vec<SocketAddr>, ControlPacket)>) {
+        let mut config = BrokerConfig::default();
+        config.admin.api_token = Some(String::from(TOKEN));
+        let (to_listener, from_broker) = tokio::sync::mpsc::channel(10);
+        let client_handler = Arc::new(ClientHandler::default());
+        let user_api = UserApi::new(Arc::new(config), client_handler.clone(), Arc::new(to_listener), Arc::new(AuditLog::new(AuditConfig::default())));
+        (user_api, client_handler, from_broker)
+    }
+
+    fn connect_client(client_handler: &ClientHandler, client_id: &str, username: &str, socket: SocketAddr) {
+        assert!(client_handler.add_user_connection(&String::from(client_id), &String::from(username), 0));
+        client_handler.register(&socket, &String::from(client_id));
+    }
+
+    fn bearer(token: &str) -> Option<String> {
+        Some(format!("Bearer {}", token))
+    }
+
+    #[tokio::test]
+    async fn user_api_requires_token() {
+        let (user_api, _, _from_broker) = create_user_api();
+        assert_eq!(user_api.connections(None, String::from("alice")).unwrap_err().status, 401);
+        assert_eq!(user_api.kick(bearer("wrong!"), String::from("alice")).await.unwrap_err().status, 401);
+    }
+
+    #[tokio::test]
+    async fn list_and_kick_connections_of_user() {
+        let (user_api, client_handler, mut from_broker) = create_user_api();
+        let (sensor, gateway, other): (SocketAddr, SocketAddr, SocketAddr) = ("127.0.0.1:41001".parse().unwrap(), "127.0.0.1:41002".parse().unwrap(), "127.0.0.1:41003".parse().unwrap());
+        connect_client(&client_handler, "user-api-sensor", "alice", sensor);
+        connect_client(&client_handler, "user-api-gateway", "alice", gateway);
+        connect_client(&client_handler, "user-api-other", "bob", other);
+
+        let connections = user_api.connections(bearer(TOKEN), String::from("alice")).unwrap();
+        assert_eq!(connections, UserConnections {
+            username: String::from("alice"),
+            connection_count: 2,
+            clients: vec![
+                UserClient { client_id: String::from("user-api-gateway"), address: Some(gateway) },
+                UserClient { client_id: String::from("user-api-sensor"), address: Some(sensor) },
+            ],
+        });
+
+        let kick = user_api.kick(bearer(TOKEN), String::from("alice")).await.unwrap();
+        assert_eq!(kick, UserKick { username: String::from("alice"), disconnected: vec![gateway, sensor] });
+        let (sockets, disconnect_packet) = from_broker.recv().await.unwrap();
+        assert_eq!(sockets, vec![gateway, sensor]);
+        assert_eq!(disconnect_packet.fixed_header().packet_type(), ControlPacketType::DISCONNECT);
+        assert_eq!(disconnect_packet.variable_header().reason_code(), Some(&ReasonCode::AdministrativeAction));
+        assert!(from_broker.try_recv().is_err());
+
+        assert_eq!(user_api.kick(bearer(TOKEN), String::from("nobody")).await.unwrap().disconnected, vec![]);
+        assert!(from_broker.try_recv().is_err());
+    }
+}
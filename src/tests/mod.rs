@@ -1 +1,13 @@
-pub mod broker;
\ No newline at end of file
+pub mod audit;
+pub mod auth;
+pub mod broker;
+pub mod config;
+pub mod connection;
+pub mod gateway;
+pub mod limits;
+pub mod logging;
+pub mod metrics;
+pub mod model;
+pub mod serdes;
+pub mod session;
+pub mod topic;
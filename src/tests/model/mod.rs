@@ -0,0 +1 @@
+pub mod protocol_table_tests;
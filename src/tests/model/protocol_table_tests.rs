@@ -0,0 +1,23 @@
+#[cfg(test)]
+mod protocol_table_tests {
+    use crate::model::protocol_table::{ProtocolEntry, ProtocolTable};
+
+    #[test]
+    fn list_every_enum_variant() {
+        let table = ProtocolTable::new();
+        assert_eq!(table.protocol_version, 5);
+        assert_eq!(table.packet_types.len(), 16);
+        assert_eq!(table.packet_types[3], ProtocolEntry { name: "PUBLISH", value: 3 });
+        assert_eq!(table.packet_types[15], ProtocolEntry { name: "AUTH", value: 15 });
+        assert_eq!(table.properties.len(), 27);
+        assert!(table.properties.contains(&ProtocolEntry { name: "UserProperty", value: 38 }));
+        assert!(table.reason_codes.contains(&ProtocolEntry { name: "BadUsernameOrPassword", value: 0x86 }));
+        assert!(table.reason_codes.contains(&ProtocolEntry { name: "WildcardSubscriptionsNotSupported", value: 0xA2 }));
+    }
+
+    #[test]
+    fn serialize_as_json() {
+        let table = serde_json::to_value(ProtocolTable::new()).unwrap();
+        assert_eq!(table["properties"][0], serde_json::json!({"name": "PayloadFormatIndicator", "value": 1}));
+    }
+}
@@ -0,0 +1,250 @@
+use crate::model::control_packet::ControlPacket;
+use crate::model::fixed_header::ControlPacketType;
+use crate::model::qos_level::QoSLevel;
+use crate::model::reason_code::ReasonCode;
+use crate::model::topic::RetainHandling;
+use crate::model::variable_header::Property;
+
+//A hand-encoded packet as a client puts it on the wire, with the model the decoder has to produce for it
+pub struct PacketFixture {
+    pub name: &'static str,
+    pub hex: &'static str,
+    //Whether encoding the decoded packet gives back the same bytes, only for packets the broker also sends
+    pub round_trip: bool,
+    pub expect: fn(&ControlPacket),
+}
+
+//A packet of a protocol version the broker doesn't speak, with the reason code the client is refused with
+pub struct RefusedFixture {
+    pub name: &'static str,
+    pub hex: &'static str,
+    pub reason_code: ReasonCode,
+}
+
+pub fn parse_hex(hex: &str) -> Vec<u8> {
+    hex.split_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16).expect("hex byte"))
+        .collect()
+}
+
+//Synthetic MQTT 5 packets, encoded by hand from the spec rather than captured from a client, covering every packet type
+//a client sends with the options clients commonly set
+pub const FIXTURES: &[PacketFixture] = &[
+    PacketFixture {
+        name: "CONNECT clean start",
+        hex: "10 16 00 04 4d 51 54 54 05 02 00 3c 00 00 09 73 65 6e 73 6f 72 2d 30 31",
+        round_trip: false,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::CONNECT);
+            let variable_header = packet.variable_header();
+            assert_eq!(variable_header.protocol_name(), "MQTT");
+            assert_eq!(variable_header.protocol_version(), 5);
+            assert!(variable_header.connect_flags().clean_start_flag());
+            assert!(!variable_header.connect_flags().username_flag());
+            assert_eq!(variable_header.keep_alive(), 60);
+            assert!(variable_header.properties().is_empty());
+            assert_eq!(packet.payload().client_id(), "sensor-01");
+        },
+    },
+    PacketFixture {
+        name: "PUBLISH QoS 1",
+        hex: "32 1c 00 13 73 65 6e 73 6f 72 73 2f 74 65 6d 70 65 72 61 74 75 72 65 00 01 00 32 31 2e 35",
+        round_trip: true,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::PUBLISH);
+            assert_eq!(packet.fixed_header().qos_level(), &QoSLevel::AtLeastOnce);
+            assert!(!packet.fixed_header().dup_flag());
+            assert!(!packet.fixed_header().retain());
+            assert_eq!(packet.variable_header().topic_name(), "sensors/temperature");
+            assert_eq!(packet.variable_header().packet_identifier(), 1);
+            assert_eq!(packet.payload().data(), b"21.5");
+        },
+    },
+    PacketFixture {
+        name: "DISCONNECT without Reason Code",
+        hex: "e0 00",
+        round_trip: false,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::DISCONNECT);
+            assert!(packet.variable_header_opt().is_none());
+        },
+    },
+    PacketFixture {
+        name: "CONNECT with username and password",
+        hex: "10 27 00 04 4d 51 54 54 05 c2 00 3c 00 00 0b 67 61 74 65 77 61 79 2d 30 30 31 00 05 61 6c 69 63 65 00 06 73 65 63 72 65 74",
+        round_trip: false,
+        expect: |packet| {
+            let connect_flags = packet.variable_header().connect_flags();
+            assert!(connect_flags.username_flag());
+            assert!(connect_flags.password_flag());
+            assert!(connect_flags.clean_start_flag());
+            assert_eq!(packet.payload().client_id(), "gateway-001");
+            assert_eq!(packet.payload().username(), Some(&String::from("alice")));
+            assert_eq!(packet.payload().password(), Some(&String::from("secret")));
+        },
+    },
+    PacketFixture {
+        name: "SUBSCRIBE with Subscription Identifier",
+        hex: "82 16 00 01 02 0b 05 00 0e 73 65 6e 73 6f 72 73 2f 2b 2f 74 65 6d 70 01",
+        round_trip: false,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::SUBSCRIBE);
+            assert_eq!(packet.variable_header().packet_identifier(), 1);
+            assert_eq!(packet.variable_header().properties(), &vec![Property::SubscriptionIdentifier(5)]);
+            let topic_filters = packet.payload().topic_filters();
+            assert_eq!(topic_filters.len(), 1);
+            assert_eq!(topic_filters[0].topic_filter(), "sensors/+/temp");
+            let options = topic_filters[0].options().unwrap();
+            assert_eq!(options.maximum_qos(), QoSLevel::AtLeastOnce);
+            assert!(!options.no_local());
+            assert!(!options.retain_as_published());
+            assert_eq!(options.retain_handling(), &RetainHandling::SendRetainedMessagesOnSubscribe);
+        },
+    },
+    PacketFixture {
+        name: "UNSUBSCRIBE",
+        hex: "a2 13 00 02 00 00 0e 73 65 6e 73 6f 72 73 2f 2b 2f 74 65 6d 70",
+        round_trip: false,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::UNSUBSCRIBE);
+            assert_eq!(packet.variable_header().packet_identifier(), 2);
+            let topic_filters = packet.payload().topic_filters();
+            assert_eq!(topic_filters.len(), 1);
+            assert_eq!(topic_filters[0].topic_filter(), "sensors/+/temp");
+            assert!(topic_filters[0].options().is_none());
+        },
+    },
+    PacketFixture {
+        name: "PINGREQ",
+        hex: "c0 00",
+        round_trip: true,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::PINGREQ);
+            assert!(packet.variable_header_opt().is_none());
+            assert!(packet.payload_opt().is_none());
+        },
+    },
+    PacketFixture {
+        name: "CONNECT with retained QoS 1 Will",
+        hex: "10 31 00 04 4d 51 54 54 05 2e 00 1e 00 00 0b 76 65 68 69 63 6c 65 2d 30 30 31 00 00 0d 63 61 72 73 2f 31 2f 73 74 61 74 75 73 00 07 6f 66 66 6c 69 6e 65",
+        round_trip: false,
+        expect: |packet| {
+            let connect_flags = packet.variable_header().connect_flags();
+            assert!(connect_flags.will_flag());
+            assert!(connect_flags.will_retain_flag());
+            assert_eq!(connect_flags.will_qos(), QoSLevel::AtLeastOnce);
+            assert_eq!(packet.variable_header().keep_alive(), 30);
+            assert_eq!(packet.payload().client_id(), "vehicle-001");
+            assert_eq!(packet.payload().will_topic(), Some(&String::from("cars/1/status")));
+            assert_eq!(packet.payload().will_payload(), Some(&b"offline".to_vec()));
+        },
+    },
+    PacketFixture {
+        name: "PUBLISH QoS 2 retained with Payload Format Indicator and Content Type",
+        hex: "35 30 00 0a 63 61 72 73 2f 31 2f 67 70 73 00 0a 15 01 01 03 00 10 61 70 70 6c 69 63 61 74 69 6f 6e 2f 6a 73 6f 6e 7b 22 6c 61 74 22 3a 35 32 2e 35 7d",
+        round_trip: true,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().qos_level(), &QoSLevel::ExactlyOnce);
+            assert!(packet.fixed_header().retain());
+            assert_eq!(packet.variable_header().topic_name(), "cars/1/gps");
+            assert_eq!(packet.variable_header().packet_identifier(), 10);
+            assert_eq!(packet.variable_header().properties(), &vec![Property::PayloadFormatIndicator(1), Property::ContentType(String::from("application/json"))]);
+            assert_eq!(packet.payload().data(), br#"{"lat":52.5}"#);
+        },
+    },
+    PacketFixture {
+        name: "PUBACK",
+        hex: "40 02 00 05",
+        round_trip: false,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::PUBACK);
+            assert_eq!(packet.variable_header().packet_identifier(), 5);
+            assert_eq!(packet.variable_header().reason_code(), None);
+        },
+    },
+    PacketFixture {
+        name: "PUBREC",
+        hex: "50 02 00 06",
+        round_trip: false,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::PUBREC);
+            assert_eq!(packet.variable_header().packet_identifier(), 6);
+        },
+    },
+    PacketFixture {
+        name: "PUBREL",
+        hex: "62 02 00 0a",
+        round_trip: false,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::PUBREL);
+            assert_eq!(packet.variable_header().packet_identifier(), 10);
+        },
+    },
+    PacketFixture {
+        name: "PUBCOMP",
+        hex: "70 02 00 07",
+        round_trip: false,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::PUBCOMP);
+            assert_eq!(packet.variable_header().packet_identifier(), 7);
+        },
+    },
+    PacketFixture {
+        name: "CONNECT with Session Expiry Interval",
+        hex: "10 20 00 04 4d 51 54 54 05 02 00 3c 05 11 00 00 0e 10 00 0e 64 61 73 68 62 6f 61 72 64 2d 30 30 30 31",
+        round_trip: false,
+        expect: |packet| {
+            assert_eq!(packet.variable_header().session_expiry_interval(), Some(3600));
+            assert_eq!(packet.payload().client_id(), "dashboard-0001");
+        },
+    },
+    PacketFixture {
+        name: "PUBLISH QoS 0 with User Property",
+        hex: "30 22 00 0a 63 68 61 74 2f 72 6f 6f 6d 31 10 26 00 06 73 65 6e 64 65 72 00 05 61 6c 69 63 65 68 65 6c 6c 6f",
+        round_trip: true,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().qos_level(), &QoSLevel::AtMostOnce);
+            assert_eq!(packet.variable_header().topic_name(), "chat/room1");
+            assert_eq!(packet.variable_header().packet_identifier_opt(), None);
+            assert_eq!(packet.variable_header().properties(), &vec![Property::UserProperty(String::from("sender"), String::from("alice"))]);
+            assert_eq!(packet.payload().data(), b"hello");
+        },
+    },
+    PacketFixture {
+        name: "SUBSCRIBE with No Local, Retain As Published and Retain Handling",
+        hex: "82 0c 00 03 00 00 06 63 68 61 74 2f 23 1e",
+        round_trip: false,
+        expect: |packet| {
+            let topic_filters = packet.payload().topic_filters();
+            assert_eq!(topic_filters[0].topic_filter(), "chat/#");
+            let options = topic_filters[0].options().unwrap();
+            assert_eq!(options.maximum_qos(), QoSLevel::ExactlyOnce);
+            assert!(options.no_local());
+            assert!(options.retain_as_published());
+            assert_eq!(options.retain_handling(), &RetainHandling::SendRetainedMessagesOnNewSubscribe);
+        },
+    },
+    PacketFixture {
+        name: "DISCONNECT with Reason Code",
+        hex: "e0 02 00 00",
+        round_trip: true,
+        expect: |packet| {
+            assert_eq!(packet.fixed_header().packet_type(), ControlPacketType::DISCONNECT);
+            assert_eq!(packet.variable_header().reason_code().map(ReasonCode::as_u8), Some(0x00));
+            assert!(packet.variable_header().properties().is_empty());
+        },
+    },
+];
+
+pub const REFUSED_FIXTURES: &[RefusedFixture] = &[
+    RefusedFixture {
+        name: "CONNECT MQTT 3.1.1",
+        hex: "10 15 00 04 4d 51 54 54 04 02 00 3c 00 09 73 65 6e 73 6f 72 2d 30 32",
+        reason_code: ReasonCode::UnsupportedProtocolVersion,
+    },
+    RefusedFixture {
+        name: "CONNECT MQTT 3.1",
+        hex: "10 19 00 06 4d 51 49 73 64 70 03 02 00 3c 00 0b 6c 65 67 61 63 79 2d 30 30 30 31",
+        reason_code: ReasonCode::UnsupportedProtocolVersion,
+    },
+];
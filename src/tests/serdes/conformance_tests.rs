@@ -0,0 +1,73 @@
+#[cfg(test)]
+mod conformance_tests {
+    use std::sync::Arc;
+
+    use crate::config::broker_config::BrokerConfig;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::Property;
+    use crate::serdes::mqtt_decoder::MqttDecoder;
+    use crate::serdes::mqtt_encoder::MqttEncoder;
+    use crate::tests::serdes::conformance_fixtures::{FIXTURES, parse_hex, REFUSED_FIXTURES};
+
+    fn create_decoder() -> MqttDecoder {
+        MqttDecoder::new(Arc::new(BrokerConfig::default()))
+    }
+
+    #[test]
+    fn decode_fixtures() {
+        let decoder = create_decoder();
+        for fixture in FIXTURES {
+            let packet = decoder.decode_bytes(&parse_hex(fixture.hex))
+                .unwrap_or_else(|err| panic!("{}: {:?}", fixture.name, err));
+            (fixture.expect)(&packet);
+        }
+    }
+
+    #[test]
+    fn re_encode_fixtures() {
+        let decoder = create_decoder();
+        let encoder = MqttEncoder::default();
+        for fixture in FIXTURES.iter().filter(|fixture| fixture.round_trip) {
+            let bytes = parse_hex(fixture.hex);
+            let packet = decoder.decode_bytes(&bytes).unwrap();
+            let encoded = encoder.encode_packet(&Arc::new(packet)).unwrap();
+            assert_eq!(encoded.to_vec(), bytes, "{}", fixture.name);
+        }
+    }
+
+    #[test]
+    fn refuse_unsupported_versions() {
+        let decoder = create_decoder();
+        for fixture in REFUSED_FIXTURES {
+            let err = decoder.decode_bytes(&parse_hex(fixture.hex)).unwrap_err();
+            assert_eq!(err.reason_code(), fixture.reason_code, "{}", fixture.name);
+        }
+    }
+
+    #[test]
+    fn refuse_truncated_fixtures() {
+        let decoder = create_decoder();
+        for fixture in FIXTURES {
+            let bytes = parse_hex(fixture.hex);
+            assert!(decoder.decode_bytes(&bytes[..bytes.len() - 1]).is_err(), "{}", fixture.name);
+        }
+    }
+
+    #[test]
+    fn encode_acks_with_properties() {
+        let encoder = MqttEncoder::default();
+        let properties = vec![Property::ReasonString(String::from("denied")), Property::UserProperty(String::from("a/#"), String::from("QuotaExceeded"))];
+        let suback_packet = ControlPacket::suback(Some(1), vec![ReasonCode::QuotaExceeded, ReasonCode::GrantedQoS0], properties);
+        assert_eq!(encoder.encode_packet(&Arc::new(suback_packet)).unwrap().to_vec(), parse_hex("90 23 00 01 1e 1f 00 06 64 65 6e 69 65 64 26 00 03 61 2f 23 00 0d 51 75 6f 74 61 45 78 63 65 65 64 65 64 97 00"));
+
+        let unsuback_packet = ControlPacket::unsuback(Some(2), vec![ReasonCode::NoSubscriptionExisted], vec![Property::ReasonString(String::from("missing"))]);
+        assert_eq!(encoder.encode_packet(&Arc::new(unsuback_packet)).unwrap().to_vec(), parse_hex("b0 0e 00 02 0a 1f 00 07 6d 69 73 73 69 6e 67 11"));
+
+        let suback_packet = ControlPacket::suback(Some(3), vec![ReasonCode::GrantedQoS1], vec![]);
+        assert_eq!(encoder.encode_packet(&Arc::new(suback_packet)).unwrap().to_vec(), parse_hex("90 04 00 03 00 01"));
+
+        let disconnect_packet = ControlPacket::disconnect(ReasonCode::PacketTooLarge);
+        assert_eq!(encoder.encode_packet(&Arc::new(disconnect_packet)).unwrap().to_vec(), parse_hex("e0 02 95 00"));
+    }
+}
@@ -0,0 +1,68 @@
+#[cfg(test)]
+mod decode_pool_tests {
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use crate::config::broker_config::{BrokerConfig, DecodeConfig};
+    use crate::model::fixed_header::{ControlPacketType, FixedHeader};
+    use crate::serdes::decode_pool::{DecodePool, PacketRate};
+    use crate::serdes::mqtt_decoder::MqttDecoder;
+    use crate::tests::serdes::conformance_fixtures::{FIXTURES, parse_hex};
+
+    fn create_decode_pool(workers: usize) -> DecodePool {
+        let config = DecodeConfig { workers, min_packet_size: 64, min_packet_rate: 100 };
+        DecodePool::new(config, Arc::new(MqttDecoder::new(Arc::new(BrokerConfig::default()))))
+    }
+
+    fn fixed_header(packet_type: ControlPacketType, remaining_length: u64) -> FixedHeader {
+        FixedHeader::new(packet_type, vec![false; 4], remaining_length)
+    }
+
+    #[test]
+    fn offload_large_packets_and_busy_connections() {
+        let decode_pool = create_decode_pool(2);
+        assert!(decode_pool.should_offload(&fixed_header(ControlPacketType::PUBLISH, 64), 1));
+        assert!(decode_pool.should_offload(&fixed_header(ControlPacketType::PUBLISH, 10), 100));
+        assert!(!decode_pool.should_offload(&fixed_header(ControlPacketType::PUBLISH, 10), 99));
+        assert!(!decode_pool.should_offload(&fixed_header(ControlPacketType::CONNECT, 1024), 1000));
+        assert_eq!(decode_pool.metrics.offloaded.0.get(), 2);
+        assert_eq!(decode_pool.metrics.decoded_inline.0.get(), 2);
+    }
+
+    #[test]
+    fn disabled_without_workers() {
+        let decode_pool = create_decode_pool(0);
+        assert!(!decode_pool.is_enabled());
+        assert!(!decode_pool.should_offload(&fixed_header(ControlPacketType::PUBLISH, 1024), 1000));
+    }
+
+    #[tokio::test]
+    async fn decode_submitted_packets() {
+        let decode_pool = create_decode_pool(3);
+        let decoder = MqttDecoder::new(Arc::new(BrokerConfig::default()));
+        let mut decoded = vec![];
+        for fixture in FIXTURES {
+            let bytes = parse_hex(fixture.hex);
+            let fixed_header = decoder.decode_bytes(&bytes).unwrap().fixed_header().clone();
+            let body = bytes[bytes.len() - fixed_header.remaining_length() as usize..].to_vec();
+            decoded.push(decode_pool.submit(fixed_header, body));
+        }
+        for (fixture, decoded) in FIXTURES.iter().zip(decoded) {
+            let packet = decoded.await.unwrap().unwrap_or_else(|err| panic!("{}: {:?}", fixture.name, err));
+            (fixture.expect)(&packet);
+        }
+    }
+
+    #[test]
+    fn packet_rate_of_last_window() {
+        let start = Instant::now();
+        let mut packet_rate = PacketRate::default();
+        for _ in 0..4 {
+            packet_rate.record(start);
+        }
+        assert_eq!(packet_rate.record(start), 5);
+        assert_eq!(packet_rate.record(start + Duration::from_millis(1500)), 5);
+        //A silent connection starts over
+        assert_eq!(packet_rate.record(start + Duration::from_secs(10)), 1);
+    }
+}
@@ -0,0 +1,34 @@
+#[cfg(test)]
+mod fixed_header_encoder_tests {
+    use bitreader::BitReader;
+    use bytes::BytesMut;
+
+    use crate::model::fixed_header::FixedHeader;
+    use crate::model::qos_level::QoSLevel;
+    use crate::serdes::deserializer::fixed_header_decoder::FixedHeaderDecoder;
+    use crate::serdes::r#trait::decoder::Decoder;
+    use crate::serdes::r#trait::encoder::Encoder;
+    use crate::serdes::serializer::fixed_header_encoder::FixedHeaderEncoder;
+
+    fn encode(fixed_header: &FixedHeader) -> BytesMut {
+        let mut buffer = BytesMut::new();
+        FixedHeaderEncoder::new().encode(&(fixed_header, 0), &mut buffer).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn encode_publish_flags() {
+        assert_eq!(encode(&FixedHeader::from_publish(true, QoSLevel::AtLeastOnce, false, 0))[0], 0b0011_1010);
+        assert_eq!(encode(&FixedHeader::from_publish(false, QoSLevel::ExactlyOnce, true, 0))[0], 0b0011_0101);
+        assert_eq!(encode(&FixedHeader::from_publish(false, QoSLevel::AtMostOnce, false, 0))[0], 0b0011_0000);
+    }
+
+    #[test]
+    fn encode_decode_publish_flags() {
+        let buffer = encode(&FixedHeader::from_publish(true, QoSLevel::AtLeastOnce, true, 0));
+        let fixed_header = FixedHeaderDecoder::default().decode(&mut BitReader::new(&buffer)).unwrap();
+        assert!(*fixed_header.dup_flag());
+        assert_eq!(fixed_header.qos_level(), &QoSLevel::AtLeastOnce);
+        assert!(*fixed_header.retain());
+    }
+}
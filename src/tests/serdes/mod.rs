@@ -0,0 +1,9 @@
+pub mod conformance_fixtures;
+pub mod conformance_tests;
+pub mod decode_pool_tests;
+pub mod fixed_header_encoder_tests;
+pub mod packet_validator_tests;
+pub mod property_decoder_tests;
+pub mod property_encoder_tests;
+pub mod read_buffer_tests;
+pub mod symmetry_check_tests;
@@ -0,0 +1,63 @@
+#[cfg(test)]
+mod packet_validator_tests {
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::{ControlPacketType, FixedHeader};
+    use crate::model::payload::Payload;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::topic::TopicFilter;
+    use crate::model::variable_header::{ConnectFlags, VariableHeader};
+    use crate::serdes::deserializer::error::{DecodeError, ReadError};
+    use crate::serdes::deserializer::packet_validator::PacketValidator;
+    use crate::tests::broker::broker_tests_data::{create_connect_packet, create_publish_packet_qos1};
+
+    fn create_unsubscribe_packet(control_flags: Vec<bool>, topic_filters: Vec<TopicFilter>) -> ControlPacket {
+        let fixed_header = FixedHeader::new(ControlPacketType::UNSUBSCRIBE, control_flags, 0);
+        let variable_header = VariableHeader::from_sub_unsub(Some(1), vec![]);
+        ControlPacket::new(fixed_header, Some(variable_header), Some(Payload::from_sub_unsub(topic_filters)))
+    }
+
+    #[test]
+    fn valid_packets() {
+        let validator = PacketValidator::default();
+        assert_eq!(validator.validate(&create_connect_packet(String::from("valid_packets"))), Ok(()));
+        assert_eq!(validator.validate(&create_publish_packet_qos1(1, String::from("test/qos1"))), Ok(()));
+        let unsubscribe_packet = create_unsubscribe_packet(vec![false, false, true, false], vec![TopicFilter::from_unsubscribe(String::from("test/qos1"))]);
+        assert_eq!(validator.validate(&unsubscribe_packet), Ok(()));
+    }
+
+    #[test]
+    fn connect_reserved_flag() {
+        let validator = PacketValidator::default();
+        let connect_packet = ControlPacket::connect(
+            ConnectFlags::new(false, false, false, QoSLevel::AtMostOnce, false, true, true),
+            None, vec![], Some(String::from("connect_reserved_flag")), None, None, None, None, None);
+        let err = validator.validate(&connect_packet).unwrap_err();
+        assert_eq!(err, DecodeError::ReservedFlag { cause: ReadError::InvalidData });
+        assert_eq!(err.reason_code(), ReasonCode::MalformedPacket);
+    }
+
+    #[test]
+    fn publish_qos1_without_packet_identifier() {
+        let validator = PacketValidator::default();
+        let publish_packet = ControlPacket::publish(None, Some(String::from("test/qos1")), false, QoSLevel::AtLeastOnce, false);
+        let err = validator.validate(&publish_packet).unwrap_err();
+        assert_eq!(err, DecodeError::PacketIdentifier { cause: ReadError::ProtocolViolation });
+        assert_eq!(err.reason_code(), ReasonCode::ProtocolError);
+    }
+
+    #[test]
+    fn unsubscribe_without_topic_filters() {
+        let validator = PacketValidator::default();
+        let err = validator.validate(&create_unsubscribe_packet(vec![false, false, true, false], vec![])).unwrap_err();
+        assert_eq!(err, DecodeError::TopicFilter { cause: ReadError::ProtocolViolation });
+        assert_eq!(err.reason_code(), ReasonCode::ProtocolError);
+    }
+
+    #[test]
+    fn unsubscribe_invalid_control_flags() {
+        let validator = PacketValidator::default();
+        let unsubscribe_packet = create_unsubscribe_packet(vec![false, false, false, false], vec![TopicFilter::from_unsubscribe(String::from("test/qos1"))]);
+        assert_eq!(validator.validate(&unsubscribe_packet), Err(DecodeError::ControlFlags { cause: ReadError::InvalidData }));
+    }
+}
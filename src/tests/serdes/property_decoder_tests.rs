@@ -0,0 +1,45 @@
+#[cfg(test)]
+mod property_decoder_tests {
+    use bitreader::BitReader;
+
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::Property;
+    use crate::serdes::deserializer::error::{DecodeError, ReadError};
+    use crate::serdes::deserializer::property_decoder::PropertyDecoder;
+    use crate::serdes::r#trait::decoder::Decoder;
+
+    //Property Length 5, Session Expiry Interval (17) = 30
+    const SESSION_EXPIRY_PROPERTIES: [u8; 6] = [5, 17, 0, 0, 0, 30];
+
+    #[test]
+    fn decode_properties() {
+        let decoder = PropertyDecoder::default();
+        let mut reader = BitReader::new(&SESSION_EXPIRY_PROPERTIES);
+        assert_eq!(decoder.decode(&mut reader), Ok(vec![Property::SessionExpiryInterval(30)]));
+    }
+
+    #[test]
+    fn property_length_exceeds_remaining_bytes() {
+        let decoder = PropertyDecoder::default();
+        let buffer = [0xFF, 0xFF, 0xFF, 0x7F, 17, 0, 0, 0, 30];
+        let mut reader = BitReader::new(&buffer);
+        let err = decoder.decode(&mut reader).unwrap_err();
+        assert_eq!(err, DecodeError::PropertyLength { cause: ReadError::ProtocolViolation });
+        assert_eq!(err.reason_code(), ReasonCode::ProtocolError);
+    }
+
+    #[test]
+    fn property_length_exceeds_maximum_packet_size() {
+        let decoder = PropertyDecoder::new(4);
+        let mut reader = BitReader::new(&SESSION_EXPIRY_PROPERTIES);
+        assert_eq!(decoder.decode(&mut reader), Err(DecodeError::PropertyLength { cause: ReadError::ProtocolViolation }));
+    }
+
+    #[test]
+    fn property_overruns_property_length() {
+        let decoder = PropertyDecoder::default();
+        let buffer = [2, 17, 0, 0, 0, 30];
+        let mut reader = BitReader::new(&buffer);
+        assert_eq!(decoder.decode(&mut reader), Err(DecodeError::PropertyLength { cause: ReadError::ProtocolViolation }));
+    }
+}
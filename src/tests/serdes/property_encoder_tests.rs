@@ -0,0 +1,88 @@
+#[cfg(test)]
+mod property_encoder_tests {
+    use bitreader::BitReader;
+    use bytes::BytesMut;
+    use strum::IntoEnumIterator;
+
+    use crate::model::variable_header::Property;
+    use crate::serdes::deserializer::property_decoder::PropertyDecoder;
+    use crate::serdes::r#trait::decoder::Decoder;
+    use crate::serdes::r#trait::encoder::Encoder;
+    use crate::serdes::serializer::property_encoder::PropertyEncoder;
+
+    #[test]
+    fn encode_empty_properties() {
+        let mut buffer = BytesMut::new();
+        PropertyEncoder::new().encode(&vec![], &mut buffer).unwrap();
+        assert_eq!(buffer.to_vec(), vec![0]);
+    }
+
+    #[test]
+    fn encode_decode_properties() {
+        let properties = vec![
+            Property::PayloadFormatIndicator(1),
+            Property::MessageExpiryInterval(3600),
+            Property::ContentType(String::from("application/json")),
+            Property::ResponseTopic(String::from("test/response")),
+            Property::CorrelationData(vec![1, 2, 3]),
+            Property::SubscriptionIdentifier(300),
+            Property::SessionExpiryInterval(30),
+            Property::ReasonString(String::from("reason")),
+            Property::ReceiveMaximum(10),
+            Property::TopicAlias(2),
+            Property::UserProperty(String::from("patina-recv-ts"), String::from("1700000000000")),
+            Property::MaximumPacketSize(1024),
+        ];
+        let mut buffer = BytesMut::new();
+        PropertyEncoder::new().encode(&properties, &mut buffer).unwrap();
+        let mut reader = BitReader::new(&buffer);
+        assert_eq!(PropertyDecoder::default().decode(&mut reader), Ok(properties));
+    }
+
+    //The values of the server side properties don't fit in one byte, e.g. a Server Keep Alive of 10 minutes
+    #[test]
+    fn encode_decode_server_properties() {
+        let properties = vec![
+            Property::AssignedClientIdentifier(String::from("auto-4f2a")),
+            Property::ServerKeepAlive(600),
+            Property::AuthenticationMethod(String::from("SCRAM-SHA-256")),
+            Property::AuthenticationData(vec![0, 255, 7]),
+            Property::RequestProblemInformation(0),
+            Property::WillDelayInterval(120),
+            Property::RequestResponseInformation(1),
+            Property::ResponseInformation(String::from("responses/client-1")),
+            Property::ServerReference(String::from("broker-2:1883")),
+            Property::TopicAliasMaximum(65535),
+            Property::MaximumQoS(1),
+            Property::RetainAvailable(0),
+            Property::WildcardSubscriptionAvailable(0),
+            Property::SubscriptionIdentifierAvailable(0),
+            Property::SharedSubscriptionAvailable(1),
+            Property::SubscriptionIdentifier(268_435_455),
+        ];
+        let mut buffer = BytesMut::new();
+        PropertyEncoder::new().encode(&properties, &mut buffer).unwrap();
+        let mut reader = BitReader::new(&buffer);
+        assert_eq!(PropertyDecoder::default().decode(&mut reader), Ok(properties));
+    }
+
+    #[test]
+    fn encode_decode_every_property() {
+        for property in Property::iter() {
+            let mut buffer = BytesMut::new();
+            PropertyEncoder::new().encode(&vec![property.clone()], &mut buffer).unwrap();
+            let mut reader = BitReader::new(&buffer);
+            assert_eq!(PropertyDecoder::default().decode(&mut reader), Ok(vec![property.clone()]), "{:?}", property);
+        }
+    }
+
+    #[test]
+    fn identifiers_match_encoding() {
+        for property in Property::iter() {
+            let mut buffer = BytesMut::new();
+            PropertyEncoder::new().encode(&vec![property.clone()], &mut buffer).unwrap();
+            //After the one byte Property Length
+            assert_eq!(buffer[1], property.identifier(), "{:?}", property);
+        }
+    }
+}
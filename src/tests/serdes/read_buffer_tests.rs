@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod read_buffer_tests {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::time::Instant;
+
+    use bytes::BytesMut;
+
+    use crate::serdes::read_buffer::{ReadBuffer, SHRINK_INTERVAL};
+
+    //Counts the allocations of the current thread, so tests running in parallel don't interfere
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    fn count_allocations<F: FnOnce()>(f: F) -> usize {
+        let before = ALLOCATIONS.with(|allocations| allocations.get());
+        f();
+        ALLOCATIONS.with(|allocations| allocations.get()) - before
+    }
+
+    //Sizes of a connection publishing small telemetry with a few larger packets
+    fn packet_sizes(count: usize) -> Vec<usize> {
+        (0..count).map(|i| if i % 100 == 0 { 4096 } else { 64 + i % 200 }).collect()
+    }
+
+    #[test]
+    fn reuse_without_allocations() {
+        let mut buffer = ReadBuffer::default();
+        buffer.prepare(4096);
+        let sizes = packet_sizes(SHRINK_INTERVAL as usize - 1);
+        let allocations = count_allocations(|| {
+            for len in sizes {
+                buffer.prepare(len)[0] = 1;
+            }
+        });
+        assert_eq!(allocations, 0);
+    }
+
+    #[test]
+    fn shrink_after_spike() {
+        let mut buffer = ReadBuffer::default();
+        buffer.prepare(1024 * 1024);
+        assert!(buffer.capacity() >= 1024 * 1024);
+        for _ in 0..SHRINK_INTERVAL {
+            assert_eq!(buffer.prepare(100).len(), 100);
+        }
+        assert!(buffer.capacity() < 64 * 1024);
+    }
+
+    //cargo test read_buffer_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn read_buffer_benchmark() {
+        let sizes = packet_sizes(1_000_000);
+
+        let now = Instant::now();
+        let fresh_allocations = count_allocations(|| {
+            for len in &sizes {
+                let mut buffer = BytesMut::with_capacity(*len);
+                buffer.resize(*len, 0);
+                std::hint::black_box(&buffer);
+            }
+        });
+        let fresh_elapsed = now.elapsed();
+
+        let now = Instant::now();
+        let reused_allocations = count_allocations(|| {
+            let mut buffer = ReadBuffer::default();
+            for len in &sizes {
+                std::hint::black_box(buffer.prepare(*len));
+            }
+        });
+        let reused_elapsed = now.elapsed();
+
+        println!("{} packets", sizes.len());
+        println!("BytesMut per packet: {} allocations in {:?}", fresh_allocations, fresh_elapsed);
+        println!("ReadBuffer per connection: {} allocations in {:?}", reused_allocations, reused_elapsed);
+        assert!(reused_allocations < fresh_allocations / 100);
+    }
+}
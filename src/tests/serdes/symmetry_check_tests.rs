@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod symmetry_check_tests {
+    use std::sync::Arc;
+
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::Property;
+    use crate::serdes::mqtt_encoder::MqttEncoder;
+    use crate::serdes::symmetry_check::SymmetryCheck;
+
+    fn encode(control_packet: &ControlPacket) -> Vec<u8> {
+        MqttEncoder::default().encode_packet(&Arc::new(control_packet.clone())).unwrap().to_vec()
+    }
+
+    #[test]
+    fn symmetric_packets_pass() {
+        let symmetry_check = SymmetryCheck::default();
+        let control_packets = vec![
+            ControlPacket::publish_with_payload(Some(7), String::from("symmetry/publish"), QoSLevel::AtLeastOnce, true, vec![Property::MessageExpiryInterval(60), Property::ContentType(String::from("text/plain"))], b"payload".to_vec()),
+            ControlPacket::publish(Some(8), Some(String::from("symmetry/empty")), false, QoSLevel::ExactlyOnce, false),
+            ControlPacket::puback_with_reason_code(Some(7), ReasonCode::NoMatchingSubscribers),
+            ControlPacket::pubrel(Some(8)),
+            ControlPacket::disconnect(ReasonCode::ServerShuttingDown),
+        ];
+        for control_packet in control_packets {
+            assert_eq!(symmetry_check.check(&control_packet, &encode(&control_packet)), Vec::<String>::new(), "{:?}", control_packet);
+        }
+        assert_eq!(symmetry_check.metrics.checked.0.get(), 5);
+        assert_eq!(symmetry_check.metrics.mismatches.0.get(), 0);
+    }
+
+    #[test]
+    fn changed_field_is_reported() {
+        let symmetry_check = SymmetryCheck::default();
+        let control_packet = ControlPacket::publish_with_payload(Some(7), String::from("symmetry/a"), QoSLevel::AtLeastOnce, false, vec![], b"payload".to_vec());
+        let mut encoded = encode(&control_packet);
+        //Last byte of the topic name
+        encoded[13] = b'b';
+
+        let mismatches = symmetry_check.check(&control_packet, &encoded);
+        assert_eq!(mismatches.len(), 1);
+        assert!(mismatches[0].starts_with("variable header"), "{:?}", mismatches);
+        assert_eq!(symmetry_check.metrics.mismatches.0.get(), 1);
+    }
+
+    #[test]
+    fn undecodable_packet_is_reported() {
+        let symmetry_check = SymmetryCheck::default();
+        let control_packet = ControlPacket::pubrel(Some(1));
+        let mut encoded = encode(&control_packet);
+        //PUBREL with the control flags of PUBACK
+        encoded[0] = 0x60;
+
+        let mismatches = symmetry_check.check(&control_packet, &encoded);
+        assert_eq!(mismatches, vec![String::from("can't decode ControlFlags { cause: InvalidData }")]);
+    }
+
+    #[test]
+    fn broker_only_packets_compare_fixed_header() {
+        let symmetry_check = SymmetryCheck::default();
+        let control_packet = ControlPacket::suback(Some(3), vec![ReasonCode::GrantedQoS1], vec![]);
+        assert!(symmetry_check.check(&control_packet, &encode(&control_packet)).is_empty());
+        assert_eq!(symmetry_check.metrics.unchecked_bodies.0.get(), 1);
+    }
+}
@@ -0,0 +1,93 @@
+#[cfg(test)]
+mod client_directory_tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use dashmap::DashMap;
+
+    use crate::ClientHandler;
+    use crate::config::broker_config::{BrokerConfig, TakeoverPolicy};
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::reason_code::ReasonCode;
+    use crate::session::client_directory::{ClientDirectory, LocalDirectory};
+    use crate::session::client_handler::Connection;
+    use crate::session::session_directory::LocalSessions;
+    use crate::tests::broker::broker_tests_data::create_connect_packet;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    //The directory of a node that also knows the clients connected to the other nodes of its cluster
+    #[derive(Debug, Default)]
+    struct ClusterDirectory {
+        local: LocalDirectory,
+        other_nodes: DashMap<String, Connection>,
+    }
+
+    #[async_trait]
+    impl ClientDirectory for ClusterDirectory {
+        fn client_of(&self, socket: &SocketAddr) -> Option<(String, u64)> {
+            self.local.client_of(socket)
+        }
+
+        fn set_client(&self, socket: &SocketAddr, client_id: &String, generation: u64) -> Option<(String, u64)> {
+            self.local.set_client(socket, client_id, generation)
+        }
+
+        fn remove_client(&self, socket: &SocketAddr) -> Option<(String, u64)> {
+            self.local.remove_client(socket)
+        }
+
+        fn connection_of(&self, client_id: &String) -> Option<Connection> {
+            self.local.connection_of(client_id)
+        }
+
+        fn set_connection(&self, client_id: &String, connection: Connection) -> Option<Connection> {
+            self.local.set_connection(client_id, connection)
+        }
+
+        fn remove_connection(&self, client_id: &String, generation: u64) -> bool {
+            self.local.remove_connection(client_id, generation)
+        }
+
+        fn connections(&self) -> Vec<(String, Connection)> {
+            self.local.connections()
+        }
+
+        async fn find_connection(&self, client_id: &String) -> Option<Connection> {
+            self.local.connection_of(client_id).or_else(|| self.other_nodes.get(client_id).map(|connection| *connection))
+        }
+    }
+
+    #[test]
+    fn local_directory_keeps_newer_connection() {
+        let directory = LocalDirectory::default();
+        let client_id = String::from("directory-local");
+        let (socket, newer_socket) = (HandlerHarness::socket(), HandlerHarness::socket());
+        directory.set_client(&socket, &client_id, 1);
+        directory.set_connection(&client_id, Connection { socket, generation: 1 });
+        directory.set_client(&newer_socket, &client_id, 2);
+        assert_eq!(directory.set_connection(&client_id, Connection { socket: newer_socket, generation: 2 }), Some(Connection { socket, generation: 1 }));
+
+        assert_eq!(directory.remove_client(&socket), Some((client_id.clone(), 1)));
+        assert!(!directory.remove_connection(&client_id, 1));
+        assert_eq!(directory.connections(), vec![(client_id.clone(), Connection { socket: newer_socket, generation: 2 })]);
+    }
+
+    #[tokio::test]
+    async fn connect_finds_client_of_another_node() {
+        let directory = Arc::new(ClusterDirectory::default());
+        let client_id = String::from("directory-elsewhere");
+        directory.other_nodes.insert(client_id.clone(), Connection { socket: HandlerHarness::socket(), generation: 1 });
+        let mut config = BrokerConfig::default();
+        config.session.takeover_policy = TakeoverPolicy::RejectNew;
+        let client_handler = Arc::new(ClientHandler::with_directories(directory, Arc::new(LocalSessions::default())));
+        let mut harness = HandlerHarness::with_client_handler(config, client_handler.clone());
+
+        let socket = HandlerHarness::socket();
+        harness.send(socket, create_connect_packet(client_id.clone())).await.unwrap();
+        let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::ClientIdentifierNotValid));
+        assert!(client_handler.get_client_id(&socket).is_err());
+        harness.drain();
+    }
+}
@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod client_handler_tests {
+    use std::net::{IpAddr, SocketAddr};
+    use std::time::{Duration, Instant};
+
+    use crate::config::broker_config::BrokerConfig;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::reason_code::ReasonCode;
+    use crate::session::client_handler::ClientHandler;
+    use crate::tests::broker::broker_tests_data::create_connect_packet_with_username;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    fn create_socket(port: u16) -> SocketAddr {
+        SocketAddr::new(IpAddr::from([10, 0, 0, 1]), port)
+    }
+
+    #[test]
+    fn index_connections_by_username() {
+        let client_handler = ClientHandler::default();
+        let alice = String::from("alice");
+        let (sensor, gateway) = (String::from("sensor"), String::from("gateway"));
+        assert!(client_handler.add_user_connection(&sensor, &alice, 2));
+        client_handler.register(&create_socket(1000), &sensor);
+        assert!(client_handler.add_user_connection(&gateway, &alice, 2));
+        client_handler.register(&create_socket(1001), &gateway);
+
+        assert_eq!(client_handler.client_ids_of(&alice), vec![gateway.clone(), sensor.clone()]);
+        assert_eq!(client_handler.sockets_of(&alice), vec![create_socket(1001), create_socket(1000)]);
+        assert!(!client_handler.add_user_connection(&String::from("dashboard"), &alice, 2));
+        //Connecting again is not a new connection
+        assert!(client_handler.add_user_connection(&sensor, &alice, 2));
+
+        client_handler.unregister(&create_socket(1000), &sensor);
+        assert_eq!(client_handler.client_ids_of(&alice), vec![gateway.clone()]);
+        client_handler.unregister_by_socket(&create_socket(1001));
+        assert_eq!(client_handler.connection_count(&alice), 0);
+    }
+
+    #[test]
+    fn client_connecting_with_another_username_leaves_the_previous_one() {
+        let client_handler = ClientHandler::default();
+        let (alice, bob) = (String::from("alice"), String::from("bob"));
+        let sensor = String::from("sensor");
+        assert!(client_handler.add_user_connection(&sensor, &alice, 0));
+        assert!(client_handler.add_user_connection(&sensor, &bob, 0));
+        assert_eq!(client_handler.connection_count(&alice), 0);
+        assert_eq!(client_handler.client_ids_of(&bob), vec![sensor]);
+    }
+
+    #[tokio::test]
+    async fn connect_over_max_connections_per_user_is_refused() {
+        let mut config = BrokerConfig::default();
+        config.session.max_connections_per_user = 1;
+        let mut harness = HandlerHarness::new(config);
+        let (first, second) = (HandlerHarness::socket(), HandlerHarness::socket());
+        harness.send(first, create_connect_packet_with_username(String::from("quota-first"), String::from("alice"))).await.unwrap();
+        let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::Success));
+
+        harness.send(second, create_connect_packet_with_username(String::from("quota-second"), String::from("alice"))).await.unwrap();
+        let (sockets, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert_eq!(sockets, vec![second]);
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::QuotaExceeded));
+        harness.expect_nothing();
+        assert_eq!(harness.client_handler.client_ids_of(&String::from("alice")), vec![String::from("quota-first")]);
+
+        //The first connection of the username is taken over, not counted twice
+        let third = HandlerHarness::socket();
+        harness.send(third, create_connect_packet_with_username(String::from("quota-first"), String::from("alice"))).await.unwrap();
+        harness.expect(ControlPacketType::DISCONNECT).await;
+        let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::Success));
+    }
+
+    #[test]
+    fn debug_capture_expires() {
+        let client_handler = ClientHandler::default();
+        let (captured, other) = (String::from("captured"), String::from("other"));
+        client_handler.register(&create_socket(1000), &captured);
+        client_handler.register(&create_socket(1001), &other);
+        assert!(!client_handler.is_debug_captured(&captured));
+
+        client_handler.start_debug_capture(&captured, Instant::now() + Duration::from_secs(60));
+        assert!(client_handler.is_debug_captured(&captured));
+        assert!(!client_handler.is_debug_captured(&other));
+        assert_eq!(client_handler.debug_captured_client(&create_socket(1000)), Some(captured.clone()));
+        assert_eq!(client_handler.debug_captured_client(&create_socket(1001)), None);
+        assert_eq!(client_handler.debug_captures().len(), 1);
+
+        client_handler.start_debug_capture(&other, Instant::now());
+        assert!(!client_handler.is_debug_captured(&other));
+        assert_eq!(client_handler.debug_captures().iter().map(|(client_id, _)| client_id).collect::<Vec<_>>(), vec![&captured]);
+
+        assert!(client_handler.stop_debug_capture(&captured));
+        assert!(!client_handler.stop_debug_capture(&captured));
+        assert!(!client_handler.is_debug_captured(&captured));
+        assert!(client_handler.debug_captures().is_empty());
+    }
+}
@@ -0,0 +1,91 @@
+#[cfg(test)]
+mod client_limits_tests {
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::model::variable_header::Property;
+    use crate::session::client_limits::ClientLimits;
+    use crate::tests::broker::broker_tests_data::create_connect_packet_with_properties;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    #[test]
+    fn limits_from_connect() {
+        let connect_packet = create_connect_packet_with_properties(String::from("limited"), vec![Property::ReceiveMaximum(10), Property::MaximumPacketSize(1024), Property::TopicAliasMaximum(5)]);
+        let limits = ClientLimits::from_connect(connect_packet.variable_header()).unwrap();
+        assert_eq!(limits, ClientLimits { receive_maximum: 10, maximum_packet_size: Some(1024), topic_alias_maximum: 5 });
+        assert!(limits.fits(1024));
+        assert!(!limits.fits(1025));
+
+        let connect_packet = create_connect_packet_with_properties(String::from("unlimited"), vec![]);
+        assert_eq!(ClientLimits::from_connect(connect_packet.variable_header()).unwrap(), ClientLimits::default());
+
+        for property in [Property::ReceiveMaximum(0), Property::MaximumPacketSize(0)] {
+            let connect_packet = create_connect_packet_with_properties(String::from("invalid"), vec![property]);
+            assert_eq!(ClientLimits::from_connect(connect_packet.variable_header()), Err(ReasonCode::ProtocolError));
+        }
+    }
+
+    #[tokio::test]
+    async fn refuse_connect_with_zero_receive_maximum() {
+        let mut harness = HandlerHarness::default();
+        let socket = HandlerHarness::socket();
+        harness.send(socket, create_connect_packet_with_properties(String::from("zero-receive-maximum"), vec![Property::ReceiveMaximum(0)])).await.unwrap();
+        let (_, connack_packet) = harness.expect(ControlPacketType::CONNACK).await;
+        assert_eq!(connack_packet.variable_header().reason_code(), Some(&ReasonCode::ProtocolError));
+        harness.expect_nothing();
+        assert!(harness.client_handler.get_client_id(&socket).is_err());
+    }
+
+    #[tokio::test]
+    async fn defer_publishes_over_receive_maximum() {
+        let mut harness = HandlerHarness::default();
+        let subscriber = HandlerHarness::socket();
+        harness.send(subscriber, create_connect_packet_with_properties(String::from("slow-subscriber"), vec![Property::ReceiveMaximum(1)])).await.unwrap();
+        harness.expect(ControlPacketType::CONNACK).await;
+        harness.subscribe(subscriber, "limits/deferred", QoSLevel::AtLeastOnce).await;
+        let publisher = harness.connect("fast-publisher").await;
+
+        for packet_identifier in [1, 2] {
+            harness.send(publisher, ControlPacket::publish_with_payload(Some(packet_identifier), String::from("limits/deferred"), QoSLevel::AtLeastOnce, false, vec![], vec![1])).await.unwrap();
+        }
+        let deliveries: Vec<u16> = harness.drain().into_iter()
+            .filter(|(sockets, packet)| sockets.contains(&subscriber) && packet.fixed_header().packet_type() == ControlPacketType::PUBLISH)
+            .map(|(_, packet)| packet.variable_header().packet_identifier())
+            .collect();
+        assert_eq!(deliveries, vec![1]);
+        assert_eq!(harness.client_handler.limits.inflight(&subscriber), 1);
+
+        harness.send(subscriber, ControlPacket::puback(Some(1))).await.unwrap();
+        let (sockets, publish_packet) = harness.expect(ControlPacketType::PUBLISH).await;
+        assert_eq!(sockets, vec![subscriber]);
+        assert_eq!(publish_packet.variable_header().packet_identifier(), 2);
+        harness.send(subscriber, ControlPacket::puback(Some(2))).await.unwrap();
+        harness.expect_nothing();
+        assert_eq!(harness.client_handler.limits.inflight(&subscriber), 0);
+    }
+
+    #[test]
+    fn take_over_unacknowledged_publishes() {
+        let harness = HandlerHarness::default();
+        let limits = &harness.client_handler.limits;
+        let (previous_socket, socket) = (HandlerHarness::socket(), HandlerHarness::socket());
+        limits.set(&previous_socket, ClientLimits { receive_maximum: 2, ..ClientLimits::default() });
+        limits.set(&socket, ClientLimits { receive_maximum: 2, ..ClientLimits::default() });
+        let publish = |packet_identifier: u16, qos_level: QoSLevel| ControlPacket::publish_with_payload(Some(packet_identifier), String::from("limits/takeover"), qos_level, false, vec![], vec![1]);
+        assert!(limits.admit(&previous_socket, &publish(1, QoSLevel::AtLeastOnce)));
+        assert!(limits.admit(&previous_socket, &publish(2, QoSLevel::ExactlyOnce)));
+        assert!(!limits.admit(&previous_socket, &publish(3, QoSLevel::AtLeastOnce)));
+        limits.received(&previous_socket, 2);
+
+        //The received QoS 2 publish keeps its slot, the deferred one waits for the Receive Maximum of 2
+        let resent = limits.take_over(&previous_socket, &socket);
+        let resent: Vec<(ControlPacketType, u16, bool)> = resent.iter()
+            .map(|packet| (packet.fixed_header().packet_type(), packet.variable_header().packet_identifier(), packet.fixed_header().dup_flag_opt().unwrap_or(false)))
+            .collect();
+        assert_eq!(resent, vec![(ControlPacketType::PUBLISH, 1, true), (ControlPacketType::PUBREL, 2, false)]);
+        assert!(limits.unacknowledged(&previous_socket).is_empty());
+        assert_eq!(limits.inflight(&socket), 2);
+        assert_eq!(limits.release(&socket, 2).map(|packet| packet.variable_header().packet_identifier()), Some(3));
+    }
+}
@@ -0,0 +1,66 @@
+#[cfg(test)]
+mod keep_alive_stats_tests {
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+
+    use crate::config::broker_config::BrokerConfig;
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::session::keep_alive_stats::{KeepAliveStats, sys_keep_alive_topic};
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    #[test]
+    fn smooth_turnaround_per_connection() {
+        let keep_alive_stats = KeepAliveStats::default();
+        let client_id = String::from("heartbeat");
+        let socket: SocketAddr = "127.0.0.1:43001".parse().unwrap();
+        let start = Instant::now();
+        assert_eq!(keep_alive_stats.record(&client_id, socket, start, start + Duration::from_millis(80)), (None, Duration::from_millis(80)));
+        let received_at = start + Duration::from_secs(30);
+        assert_eq!(keep_alive_stats.record(&client_id, socket, received_at, received_at + Duration::from_millis(160)), (Some(Duration::from_secs(30)), Duration::from_millis(90)));
+
+        //A new connection of the client starts over
+        let reconnected: SocketAddr = "127.0.0.1:43002".parse().unwrap();
+        let received_at = start + Duration::from_secs(45);
+        assert_eq!(keep_alive_stats.record(&client_id, reconnected, received_at, received_at), (None, Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn publish_statistics_on_pingreq() {
+        let mut config = BrokerConfig::default();
+        config.keep_alive.statistics = true;
+        config.keep_alive.statistics_topic = true;
+        let mut harness = HandlerHarness::new(config);
+        let monitor = harness.connect("heartbeat-monitor").await;
+        harness.subscribe(monitor, "$SYS/clients/+/keep-alive", QoSLevel::AtMostOnce).await;
+        let socket = harness.connect("heartbeat-pinger").await;
+        for _ in 0..2 {
+            harness.packet_dispatcher.pingreq_handler.process(&harness.context(socket), &ControlPacket::pingreq()).await.unwrap();
+        }
+        let samples: Vec<serde_json::Value> = harness.drain().into_iter()
+            .filter(|(_, packet)| packet.fixed_header().packet_type() == ControlPacketType::PUBLISH)
+            .map(|(sockets, packet)| {
+                assert_eq!(sockets, vec![monitor]);
+                assert_eq!(packet.variable_header().topic_name(), &sys_keep_alive_topic("heartbeat-pinger"));
+                serde_json::from_slice(packet.payload().data()).unwrap()
+            })
+            .collect();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0]["client_id"], "heartbeat-pinger");
+        assert!(samples[0]["interval_ms"].is_null());
+        assert!(samples[1]["interval_ms"].is_u64());
+        assert_eq!(samples[1]["queued_messages"], 0);
+    }
+
+    #[tokio::test]
+    async fn no_statistics_by_default() {
+        let mut harness = HandlerHarness::default();
+        let monitor = harness.connect("quiet-monitor").await;
+        harness.subscribe(monitor, "$SYS/clients/+/keep-alive", QoSLevel::AtMostOnce).await;
+        let socket = harness.connect("quiet-pinger").await;
+        harness.packet_dispatcher.pingreq_handler.process(&harness.context(socket), &ControlPacket::pingreq()).await.unwrap();
+        harness.expect(ControlPacketType::PINGRESP).await;
+        harness.expect_nothing();
+    }
+}
@@ -0,0 +1,8 @@
+pub mod takeover_tracker_tests;
+pub mod qos2_tracker_tests;
+pub mod client_handler_tests;
+pub mod keep_alive_stats_tests;
+pub mod client_limits_tests;
+pub mod session_store_tests;
+pub mod state_snapshot_tests;
+pub mod client_directory_tests;
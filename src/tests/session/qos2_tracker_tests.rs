@@ -0,0 +1,104 @@
+#[cfg(test)]
+mod qos2_tracker_tests {
+    use std::time::Duration;
+
+    use crate::broker::utils::queued_packets;
+    use crate::config::broker_config::{BrokerConfig, QuotaProfile};
+    use crate::model::control_packet::ControlPacket;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::reason_code::ReasonCode;
+    use crate::tests::broker::handler_harness::HandlerHarness;
+
+    fn qos2_config() -> BrokerConfig {
+        let mut config = BrokerConfig::default();
+        config.qos2.handshake_timeout_secs = 10;
+        config.qos2.sweep_interval_secs = 1;
+        config
+    }
+
+    fn qos2_publish(packet_identifier: u16, topic_name: &str) -> ControlPacket {
+        ControlPacket::publish_with_payload(Some(packet_identifier), topic_name.to_string(), QoSLevel::ExactlyOnce, false, vec![], vec![1])
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn release_inflight_of_abandoned_inbound_handshake() {
+        let mut config = qos2_config();
+        config.quota.default_profile = Some(String::from("limited"));
+        config.quota.profiles.insert(String::from("limited"), QuotaProfile { max_inflight: Some(1), ..QuotaProfile::default() });
+        let mut harness = HandlerHarness::new(config);
+        tokio::spawn(harness.packet_dispatcher.clone().expire_qos2_handshakes());
+        let publisher = harness.connect("qos2-stalling-publisher").await;
+
+        harness.send(publisher, qos2_publish(1, "qos2/inbound")).await.unwrap();
+        harness.expect(ControlPacketType::PUBREC).await;
+        //Never released
+        tokio::time::sleep(Duration::from_secs(12)).await;
+        assert_eq!(harness.packet_dispatcher.qos2_tracker.metrics.inbound_abandoned.0.get(), 1);
+
+        harness.send(publisher, qos2_publish(2, "qos2/inbound")).await.unwrap();
+        let (_, pubrec_packet) = harness.expect(ControlPacketType::PUBREC).await;
+        assert_eq!(pubrec_packet.variable_header().packet_identifier_opt(), Some(2));
+        harness.send(publisher, ControlPacket::pubrel(Some(2))).await.unwrap();
+        harness.expect(ControlPacketType::PUBCOMP).await;
+        assert_eq!(harness.packet_dispatcher.qos2_tracker.metrics.inbound_completed.0.get(), 1);
+
+        tokio::time::sleep(Duration::from_secs(12)).await;
+        assert_eq!(harness.packet_dispatcher.qos2_tracker.metrics.inbound_abandoned.0.get(), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn drop_stored_message_of_abandoned_outbound_handshake() {
+        let mut harness = HandlerHarness::new(qos2_config());
+        tokio::spawn(harness.packet_dispatcher.clone().expire_qos2_handshakes());
+        let subscriber = harness.connect("qos2-stalling-subscriber").await;
+        harness.subscribe(subscriber, "qos2/outbound", QoSLevel::ExactlyOnce).await;
+        let publisher = harness.connect("qos2-outbound-publisher").await;
+        let subscriber_id = String::from("qos2-stalling-subscriber");
+
+        harness.send(publisher, qos2_publish(7, "qos2/outbound")).await.unwrap();
+        let packets = harness.drain();
+        assert!(packets.iter().any(|(sockets, packet)| sockets == &vec![subscriber] && packet.fixed_header().packet_type() == ControlPacketType::PUBLISH));
+        assert_eq!(queued_packets(&*harness.client_handler.sessions, &subscriber_id), 1);
+
+        //PUBREC restarts the timeout, the PUBCOMP never comes
+        tokio::time::sleep(Duration::from_secs(8)).await;
+        harness.send(subscriber, ControlPacket::pubrec(Some(7))).await.unwrap();
+        harness.expect(ControlPacketType::PUBREL).await;
+        tokio::time::sleep(Duration::from_secs(8)).await;
+        assert_eq!(harness.packet_dispatcher.qos2_tracker.metrics.outbound_abandoned.0.get(), 0);
+        tokio::time::sleep(Duration::from_secs(4)).await;
+        assert_eq!(harness.packet_dispatcher.qos2_tracker.metrics.outbound_abandoned.0.get(), 1);
+        assert_eq!(queued_packets(&*harness.client_handler.sessions, &subscriber_id), 0);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn complete_outbound_handshake_with_pubcomp() {
+        let mut harness = HandlerHarness::new(qos2_config());
+        let subscriber = harness.connect("qos2-subscriber").await;
+        harness.subscribe(subscriber, "qos2/completed", QoSLevel::ExactlyOnce).await;
+        let publisher = harness.connect("qos2-completed-publisher").await;
+
+        harness.send(publisher, qos2_publish(3, "qos2/completed")).await.unwrap();
+        harness.send(publisher, ControlPacket::pubrel(Some(3))).await.unwrap();
+        harness.drain();
+        harness.send(subscriber, ControlPacket::pubrec(Some(3))).await.unwrap();
+        harness.expect(ControlPacketType::PUBREL).await;
+        harness.send(subscriber, ControlPacket::pubcomp(Some(3))).await.unwrap();
+        assert_eq!(harness.packet_dispatcher.qos2_tracker.metrics.outbound_completed.0.get(), 1);
+        assert_eq!(harness.packet_dispatcher.qos2_tracker.metrics.inbound_completed.0.get(), 1);
+        assert!(harness.packet_dispatcher.qos2_tracker.expire(tokio::time::Instant::now() + Duration::from_secs(60)).is_empty());
+    }
+
+    #[tokio::test]
+    async fn never_expire_without_timeout() {
+        let mut config = BrokerConfig::default();
+        config.qos2.handshake_timeout_secs = 0;
+        let mut harness = HandlerHarness::new(config);
+        let publisher = harness.connect("qos2-untracked-publisher").await;
+        harness.send(publisher, qos2_publish(1, "qos2/untracked")).await.unwrap();
+        let (_, pubrec_packet) = harness.expect(ControlPacketType::PUBREC).await;
+        assert_eq!(pubrec_packet.variable_header().reason_code(), Some(&ReasonCode::Success));
+        assert!(harness.packet_dispatcher.qos2_tracker.expire(tokio::time::Instant::now() + Duration::from_secs(3600)).is_empty());
+    }
+}
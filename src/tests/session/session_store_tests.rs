@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod session_store_tests {
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use chrono::Utc;
+
+    use crate::{ClientHandler, TopicHandler};
+    use crate::broker::utils::{get_session_expiry_interval, queued_packets};
+    use crate::config::broker_config::BrokerConfig;
+    use crate::serdes::mqtt_encoder::MqttEncoder;
+    use crate::session::session_persistence::SessionPersistence;
+    use crate::session::session_store::{FileSessionStore, SessionStore, StoredPublish, StoredSession};
+    use crate::tests::broker::broker_tests_data::create_publish_packet_qos1;
+    use crate::topic::subscription::{SubscriptionMetadata, SubscriptionRecord};
+
+    fn create_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("patina-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn create_session(client_id: &str, session_expiry_interval: u32, disconnected_at: Option<i64>) -> StoredSession {
+        let publish_packet = MqttEncoder::default().encode_packet(&Arc::new(create_publish_packet_qos1(1, String::from("sensors/temperature")))).unwrap();
+        StoredSession {
+            client_id: String::from(client_id),
+            session_expiry_interval,
+            request_problem_information: true,
+            disconnected_at,
+            subscriptions: vec![SubscriptionRecord { client_id: String::from(client_id), topic_filter: String::from("sensors/#"), metadata: SubscriptionMetadata::new() }],
+            messages: vec![StoredPublish { received_at: Utc::now().timestamp_millis(), packet: publish_packet.to_vec() }],
+        }
+    }
+
+    #[test]
+    fn save_and_load_sessions() {
+        let directory = create_directory("session-store");
+        let store = FileSessionStore::new(directory.to_str().unwrap());
+        assert_eq!(store.load_all().unwrap(), vec![]);
+        assert_eq!(store.checkpoint_time().unwrap(), None);
+
+        let session = create_session("sensor/1 ü", 60, Some(1_000));
+        store.save(&session).unwrap();
+        //Named after the hex encoded client_id
+        assert!(directory.join("73656e736f722f3120c3bc.json").exists());
+        store.save_checkpoint_time(2_000).unwrap();
+        //Only the renamed files are left
+        assert!(fs::read_dir(&directory).unwrap().all(|entry| !entry.unwrap().file_name().to_string_lossy().ends_with(".tmp")));
+        fs::write(directory.join("7a.json"), "not a session").unwrap();
+
+        assert_eq!(store.load_all().unwrap(), vec![session.clone()]);
+        assert_eq!(store.checkpoint_time().unwrap(), Some(2_000));
+
+        store.remove(&session.client_id).unwrap();
+        store.remove(&session.client_id).unwrap();
+        assert_eq!(store.load_all().unwrap(), vec![]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn save_and_load_sessions_of_long_client_ids() {
+        let directory = create_directory("session-store-long");
+        let store = FileSessionStore::new(directory.to_str().unwrap());
+        //Same first 300 bytes, MQTT allows client identifiers of up to 65535
+        let session = create_session(&format!("{}a", "sensor/".repeat(300)), 60, Some(1_000));
+        let other_session = create_session(&format!("{}b", "sensor/".repeat(300)), 60, Some(1_000));
+        store.save(&session).unwrap();
+        store.save(&other_session).unwrap();
+        for entry in fs::read_dir(&directory).unwrap() {
+            assert!(entry.unwrap().file_name().len() <= 255);
+        }
+
+        let mut sessions = store.load_all().unwrap();
+        sessions.sort_by(|session, other_session| session.client_id.cmp(&other_session.client_id));
+        assert_eq!(sessions, vec![session.clone(), other_session.clone()]);
+
+        store.remove(&session.client_id).unwrap();
+        assert_eq!(store.load_all().unwrap(), vec![other_session]);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn restore_and_checkpoint_sessions() {
+        let directory = create_directory("session-persistence");
+        let store = Arc::new(FileSessionStore::new(directory.to_str().unwrap()));
+        let now = Utc::now().timestamp_millis();
+        let restored = create_session("persistence-restored", 3600, Some(now - 1_000));
+        store.save(&restored).unwrap();
+        store.save(&create_session("persistence-expired", 5, Some(now - 10_000))).unwrap();
+
+        let client_handler = Arc::new(ClientHandler::default());
+        let topic_handler = Arc::new(TopicHandler::default());
+        let persistence = Arc::new(SessionPersistence::new(Arc::new(BrokerConfig::default()), store.clone(), client_handler.clone(), topic_handler.clone()));
+        assert_eq!(persistence.restore(), 1);
+        assert_eq!(get_session_expiry_interval(&*client_handler.sessions, &restored.client_id), Some(3600));
+        assert_eq!(queued_packets(&*client_handler.sessions, &restored.client_id), 1);
+        assert_eq!(topic_handler.subscriptions_of(&restored.client_id).len(), 1);
+        assert_eq!(store.load_all().unwrap().iter().map(|session| session.client_id.clone()).collect::<Vec<String>>(), vec![restored.client_id.clone()]);
+
+        persistence.clone().checkpoint_off_runtime().await;
+        assert!(store.checkpoint_time().unwrap().unwrap() >= now);
+        let saved = store.load_all().unwrap().into_iter().find(|session| session.client_id == restored.client_id).unwrap();
+        assert_eq!(saved.disconnected_at, Some(now - 1_000));
+        assert_eq!(saved.messages.len(), 1);
+
+        //A session that ended is removed at the next checkpoint
+        client_handler.sessions.remove(&restored.client_id);
+        persistence.checkpoint();
+        assert!(store.load_all().unwrap().iter().all(|session| session.client_id != restored.client_id));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,104 @@
+#[cfg(test)]
+mod state_snapshot_tests {
+    use std::collections::HashMap;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    use chrono::Utc;
+
+    use crate::{ClientHandler, TopicHandler};
+    use crate::broker::utils::{get_session_expiry_interval, queued_packets};
+    use crate::config::broker_config::BrokerConfig;
+    use crate::serdes::mqtt_encoder::MqttEncoder;
+    use crate::session::session_state::{SessionState, StateImport};
+    use crate::session::session_store::{FileSessionStore, SessionStore, StoredPublish, StoredSession};
+    use crate::session::state_snapshot::{STATE_SNAPSHOT_VERSION, StateSnapshot};
+    use crate::tests::broker::broker_tests_data::create_publish_packet_qos1;
+    use crate::topic::subscription::{SubscriptionMetadata, SubscriptionRecord};
+
+    fn create_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("patina-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn create_session(client_id: &str, session_expiry_interval: u32, disconnected_at: Option<i64>) -> StoredSession {
+        let publish_packet = MqttEncoder::default().encode_packet(&Arc::new(create_publish_packet_qos1(1, String::from("sensors/temperature")))).unwrap();
+        StoredSession {
+            client_id: String::from(client_id),
+            session_expiry_interval,
+            request_problem_information: true,
+            disconnected_at,
+            subscriptions: vec![SubscriptionRecord { client_id: String::from(client_id), topic_filter: String::from("sensors/#"), metadata: SubscriptionMetadata::new() }],
+            messages: vec![StoredPublish { received_at: Utc::now().timestamp_millis(), packet: publish_packet.to_vec() }],
+        }
+    }
+
+    #[test]
+    fn export_and_import_through_stores() {
+        let directory = create_directory("state-snapshot");
+        let store = FileSessionStore::new(directory.join("from").to_str().unwrap());
+        let now = Utc::now().timestamp_millis();
+        store.save(&create_session("snapshot-connected", 3600, None)).unwrap();
+        store.save(&create_session("snapshot-disconnected", 3600, Some(now - 1_000))).unwrap();
+        store.save(&create_session("snapshot-expiring", 5, Some(now - 4_000))).unwrap();
+        store.save_checkpoint_time(now - 2_000).unwrap();
+
+        let snapshot = StateSnapshot::load(&store, now).unwrap();
+        assert_eq!(snapshot.version, STATE_SNAPSHOT_VERSION);
+        assert_eq!(snapshot.sessions.iter().map(|session| session.client_id.as_str()).collect::<Vec<&str>>(), vec!["snapshot-connected", "snapshot-disconnected", "snapshot-expiring"]);
+        //Connected at the last checkpoint, it counts from then
+        assert_eq!(snapshot.sessions[0].disconnected_at, Some(now - 2_000));
+
+        let path = directory.join("state.json");
+        snapshot.write(&path).unwrap();
+        assert_eq!(StateSnapshot::read(&path).unwrap(), snapshot);
+
+        let other_store = FileSessionStore::new(directory.join("to").to_str().unwrap());
+        other_store.save(&create_session("snapshot-disconnected", 60, Some(now))).unwrap();
+        let state_import = snapshot.save(&other_store, now + 2_000).unwrap();
+        assert_eq!(state_import, StateImport {
+            imported: vec![String::from("snapshot-connected")],
+            existing: vec![String::from("snapshot-disconnected")],
+            expired: vec![String::from("snapshot-expiring")],
+        });
+        let mut imported = other_store.load_all().unwrap();
+        imported.sort_by(|session, other_session| session.client_id.cmp(&other_session.client_id));
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[0], snapshot.sessions[0]);
+        assert_eq!(imported[1].session_expiry_interval, 60);
+        fs::remove_dir_all(&directory).unwrap();
+    }
+
+    #[test]
+    fn invalid_snapshots_are_refused() {
+        let snapshot = StateSnapshot::new(vec![create_session("snapshot-duplicate", 60, None)], 0);
+        assert!(StateSnapshot::from_json(&snapshot.to_json().unwrap()).is_ok());
+        let other_version = StateSnapshot { version: STATE_SNAPSHOT_VERSION + 1, ..snapshot.clone() };
+        assert!(StateSnapshot::from_json(&other_version.to_json().unwrap()).unwrap_err().contains("version"));
+        let duplicates = StateSnapshot { sessions: vec![snapshot.sessions[0].clone(), snapshot.sessions[0].clone()], ..snapshot };
+        assert!(StateSnapshot::from_json(&duplicates.to_json().unwrap()).unwrap_err().contains("snapshot-duplicate"));
+        assert!(StateSnapshot::from_json(b"{\"sessions\": []}").is_err());
+    }
+
+    #[tokio::test]
+    async fn import_and_export_broker_sessions() {
+        let topic_handler = Arc::new(TopicHandler::default());
+        let client_handler = Arc::new(ClientHandler::default());
+        let session_state = SessionState::new(Arc::new(BrokerConfig::default()), client_handler.clone(), topic_handler.clone());
+        let now = Utc::now().timestamp_millis();
+        let sessions = vec![create_session("state-imported", 3600, None), create_session("state-expired", 5, Some(now - 10_000))];
+
+        let state_import = session_state.import(sessions.clone(), now - 1_000, now);
+        assert_eq!(state_import, StateImport { imported: vec![String::from("state-imported")], existing: vec![], expired: vec![String::from("state-expired")] });
+        assert_eq!(get_session_expiry_interval(&*client_handler.sessions, &String::from("state-imported")), Some(3600));
+        assert_eq!(queued_packets(&*client_handler.sessions, &String::from("state-imported")), 1);
+        assert_eq!(topic_handler.subscriptions_of(&String::from("state-imported")).len(), 1);
+        assert_eq!(session_state.import(sessions, now - 1_000, now).existing, vec![String::from("state-imported")]);
+
+        let exported = session_state.export(&HashMap::new(), now).into_iter().find(|session| session.client_id == "state-imported").unwrap();
+        assert_eq!(exported.disconnected_at, Some(now - 1_000));
+        assert_eq!(exported.messages.len(), 1);
+    }
+}
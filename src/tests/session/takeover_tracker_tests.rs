@@ -0,0 +1,27 @@
+#[cfg(test)]
+mod takeover_tracker_tests {
+    use std::net::{IpAddr, SocketAddr};
+
+    use crate::session::takeover_tracker::TakeoverTracker;
+
+    fn create_socket(ip: [u8; 4], port: u16) -> SocketAddr {
+        SocketAddr::new(IpAddr::from(ip), port)
+    }
+
+    #[test]
+    fn count_per_client_and_address() {
+        let takeover_tracker = TakeoverTracker::default();
+        let sensor = String::from("sensor");
+        let gateway = String::from("gateway");
+        takeover_tracker.record(&sensor, &create_socket([10, 0, 0, 1], 1000), &create_socket([10, 0, 0, 2], 1000));
+        takeover_tracker.record(&gateway, &create_socket([10, 0, 0, 1], 1001), &create_socket([10, 0, 0, 3], 1000));
+        let takeover_event = takeover_tracker.record(&sensor, &create_socket([10, 0, 0, 2], 1001), &create_socket([10, 0, 0, 1], 1000));
+        assert_eq!(takeover_event.takeovers, 2);
+        assert_eq!(takeover_event.previous_address, create_socket([10, 0, 0, 1], 1000));
+
+        assert_eq!(takeover_tracker.total(), 3);
+        assert_eq!(takeover_tracker.top_clients(10), vec![(sensor.clone(), 2), (gateway, 1)]);
+        assert_eq!(takeover_tracker.top_clients(1), vec![(sensor, 2)]);
+        assert_eq!(takeover_tracker.top_addresses(10), vec![(IpAddr::from([10, 0, 0, 1]), 2), (IpAddr::from([10, 0, 0, 2]), 1)]);
+    }
+}
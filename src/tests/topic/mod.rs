@@ -0,0 +1,5 @@
+pub mod subscription_tree_tests;
+pub mod topic_handler_tests;
+pub mod topic_matcher_tests;
+pub mod tree_telemetry_tests;
+pub mod virtual_topics_tests;
@@ -0,0 +1,142 @@
+#[cfg(test)]
+mod subscription_tree_tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::time::Instant;
+
+    use crate::config::broker_config::OverlapPolicy;
+    use crate::model::qos_level::QoSLevel;
+    use crate::session::client_handler::ClientHandler;
+    use crate::topic::subscription::{SubscriptionRecord, SubscriptionSettings};
+    use crate::TopicHandler;
+
+    #[test]
+    fn held_version_ignores_later_changes() {
+        let topic_handler = TopicHandler::default();
+        let client_id = String::from("tree_held_version");
+        let topic = String::from("tree/held");
+        topic_handler.subscribe(&client_id, &topic);
+        let before = topic_handler.subscription_tree();
+
+        topic_handler.unsubscribe(&client_id, &topic);
+        topic_handler.subscribe(&client_id, &String::from("tree/+"));
+        let after = topic_handler.subscription_tree();
+
+        assert!(before.routes(&topic).unwrap().contains_key(&client_id));
+        assert_eq!(before.matching_filters(&topic).len(), 1);
+        assert!(after.routes(&topic).is_none());
+        assert_eq!(after.matching_filters(&topic).len(), 2);
+        assert_eq!(after.version(), before.version() + 2);
+    }
+
+    #[test]
+    fn unchanged_filters_are_shared_between_versions() {
+        let topic_handler = TopicHandler::default();
+        let client_id = String::from("tree_shared");
+        let kept = String::from("tree/kept");
+        topic_handler.subscribe(&client_id, &kept);
+        let before = topic_handler.subscription_tree();
+
+        topic_handler.subscribe_with_settings(&client_id, &String::from("tree/other"), &SubscriptionSettings { maximum_qos: QoSLevel::AtMostOnce, subscription_identifier: Some(3), accept_encoding: None, no_local: false, retain_as_published: false });
+        let after = topic_handler.subscription_tree();
+
+        assert!(Arc::ptr_eq(before.routes(&kept).unwrap(), after.routes(&kept).unwrap()));
+        let route = &after.routes(&String::from("tree/other")).unwrap()[&client_id];
+        assert_eq!(route.maximum_qos, QoSLevel::AtMostOnce);
+        assert_eq!(route.subscription_identifier, Some(3));
+    }
+
+    #[test]
+    fn unchanged_subscription_keeps_version() {
+        let topic_handler = TopicHandler::default();
+        let client_id = String::from("tree_unchanged");
+        let topic = String::from("tree/unchanged");
+        topic_handler.subscribe(&client_id, &topic);
+        let version = topic_handler.subscription_tree().version();
+
+        topic_handler.subscribe(&client_id, &topic);
+        topic_handler.unsubscribe(&client_id, &String::from("tree/never"));
+        assert_eq!(topic_handler.subscription_tree().version(), version);
+    }
+
+    //While another thread adds and drops every subscription of a client at once, each publish finds all of them or none
+    #[test]
+    fn unsubscribe_all_is_atomic_for_publishes() {
+        let topic_handler = Arc::new(TopicHandler::default());
+        let client_handler = Arc::new(ClientHandler::default());
+        let topic = String::from("tree/atomic/x");
+        let topic_filters = ["tree/atomic/x", "tree/atomic/+", "tree/#", "#"];
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let publisher = {
+            let (topic_handler, client_handler, stop) = (topic_handler.clone(), client_handler.clone(), stop.clone());
+            let topic = topic.clone();
+            thread::spawn(move || {
+                let mut observed = vec![];
+                while !stop.load(Ordering::Relaxed) {
+                    let deliveries = topic_handler.find_deliveries(&topic, OverlapPolicy::PerSubscription, &client_handler);
+                    observed.push(deliveries.iter().filter(|delivery| delivery.client_id.as_str() == "tree_atomic").count());
+                }
+                observed
+            })
+        };
+        for _ in 0..200 {
+            topic_handler.import_subscriptions(topic_filters.iter()
+                .map(|topic_filter| SubscriptionRecord { client_id: String::from("tree_atomic"), topic_filter: topic_filter.to_string(), metadata: Default::default() })
+                .collect());
+            topic_handler.unsubscribe_all(&String::from("tree_atomic"));
+        }
+        stop.store(true, Ordering::Relaxed);
+
+        let observed = publisher.join().unwrap();
+        assert!(observed.iter().all(|count| *count == 0 || *count == topic_filters.len()), "partial views: {:?}", observed.iter().filter(|count| **count != 0 && **count != topic_filters.len()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn has_subscribers_checks_exact_and_wildcard_filters() {
+        let topic_handler = TopicHandler::default();
+        let client_id = String::from("tree_has_subscribers");
+        topic_handler.subscribe(&client_id, &String::from("sensors/+/temperature"));
+        topic_handler.subscribe(&client_id, &String::from("alerts/fire"));
+        let subscription_tree = topic_handler.subscription_tree();
+        assert!(subscription_tree.has_subscribers(&String::from("alerts/fire")));
+        assert!(subscription_tree.has_subscribers(&String::from("sensors/1/temperature")));
+        assert!(!subscription_tree.has_subscribers(&String::from("sensors/1/humidity")));
+        assert!(!subscription_tree.has_subscribers(&String::from("telemetry/1/temperature")));
+
+        topic_handler.subscribe(&client_id, &String::from("+/+/humidity"));
+        let subscription_tree = topic_handler.subscription_tree();
+        assert!(subscription_tree.has_subscribers(&String::from("telemetry/1/humidity")));
+        assert!(!subscription_tree.has_subscribers(&String::from("telemetry/1/pressure")));
+
+        topic_handler.unsubscribe_all(&client_id);
+        assert!(!topic_handler.subscription_tree().has_subscribers(&String::from("sensors/1/temperature")));
+    }
+
+    //cargo test unrouted_publish_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn unrouted_publish_benchmark() {
+        let topic_handler = TopicHandler::default();
+        let client_handler = ClientHandler::default();
+        for device in 0..1000 {
+            topic_handler.subscribe(&format!("consumer-{}", device), &format!("devices/{}/+/state", device));
+        }
+        let topics: Vec<String> = (0..100_000).map(|message| format!("telemetry/{}/temperature", message % 1000)).collect();
+
+        let now = Instant::now();
+        let resolved = topics.iter().filter(|topic| !topic_handler.find_deliveries(topic, OverlapPolicy::Once, &client_handler).is_empty()).count();
+        let resolve_elapsed = now.elapsed();
+
+        let now = Instant::now();
+        let subscription_tree = topic_handler.subscription_tree();
+        let checked = topics.iter().filter(|topic| subscription_tree.has_subscribers(topic)).count();
+        let check_elapsed = now.elapsed();
+
+        assert_eq!((resolved, checked), (0, 0));
+        println!("{} publishes without subscribers, 1000 wildcard filters", topics.len());
+        println!("find_deliveries: {:?}", resolve_elapsed);
+        println!("has_subscribers: {:?}", check_elapsed);
+    }
+}
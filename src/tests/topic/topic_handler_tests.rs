@@ -0,0 +1,125 @@
+#[cfg(test)]
+mod topic_handler_tests {
+    use std::time::Duration;
+
+    use crate::config::broker_config::OverlapPolicy;
+    use crate::broker::compression::ContentEncoding;
+    use crate::model::qos_level::QoSLevel;
+    use crate::session::client_handler::ClientHandler;
+    use crate::topic::subscription::{DeliveryTarget, SubscriptionSettings};
+    use crate::TopicHandler;
+
+    #[test]
+    fn subscription_metadata() {
+        let topic_handler = TopicHandler::default();
+        let client_id = String::from("subscription_metadata");
+        let topic = String::from("test/metadata");
+
+        topic_handler.subscribe(&client_id, &topic);
+        let metadata = topic_handler.subscription_metadata(&client_id, &topic).expect("missing subscription metadata");
+        assert_eq!(metadata.last_delivery_at(), None);
+        assert_eq!(metadata.delivery_count(), 0);
+
+        topic_handler.register_deliveries(&topic_handler.find_deliveries(&topic, OverlapPolicy::Once, &ClientHandler::default()));
+        let metadata = topic_handler.subscription_metadata(&client_id, &topic).expect("missing subscription metadata");
+        assert!(metadata.last_delivery_at().unwrap() >= metadata.created_at());
+        assert_eq!(metadata.delivery_count(), 1);
+
+        topic_handler.unsubscribe(&client_id, &topic);
+        assert_eq!(topic_handler.subscription_metadata(&client_id, &topic), None);
+        assert_eq!(topic_handler.subscription_churn(), (1, 1));
+    }
+
+    #[test]
+    fn stale_subscriptions() {
+        let topic_handler = TopicHandler::default();
+        let client_id = String::from("stale_subscriptions");
+        topic_handler.subscribe(&client_id, &String::from("test/stale"));
+
+        assert!(topic_handler.stale_subscriptions(Duration::from_secs(60)).is_empty());
+        std::thread::sleep(Duration::from_millis(5));
+        let stale_subscriptions = topic_handler.stale_subscriptions(Duration::from_millis(1));
+        assert_eq!(stale_subscriptions.len(), 1);
+        assert_eq!(stale_subscriptions[0].topic_filter, "test/stale");
+    }
+
+    #[test]
+    fn export_import_subscriptions() {
+        let source = TopicHandler::default();
+        let client_id = String::from("export_import_subscriptions");
+        let topic = String::from("test/export");
+        source.subscribe(&client_id, &topic);
+        source.register_deliveries(&source.find_deliveries(&topic, OverlapPolicy::Once, &ClientHandler::default()));
+
+        let target = TopicHandler::default();
+        target.import_subscriptions(source.subscriptions_of(&client_id));
+        assert_eq!(target.find_subscribers(&topic), vec![client_id.clone()]);
+        assert_eq!(target.subscription_metadata(&client_id, &topic), source.subscription_metadata(&client_id, &topic));
+    }
+
+    #[test]
+    fn unsubscribe_literal_filter() {
+        let topic_handler = TopicHandler::default();
+        let client_id = String::from("unsubscribe_literal_filter");
+        let wildcard = String::from("test/#");
+        let topic = String::from("test/literal");
+        topic_handler.subscribe(&client_id, &wildcard);
+        topic_handler.subscribe(&client_id, &topic);
+
+        assert!(topic_handler.unsubscribe(&client_id, &wildcard));
+        assert!(!topic_handler.unsubscribe(&client_id, &wildcard));
+        assert_eq!(topic_handler.find_subscribers(&topic), vec![client_id.clone()]);
+        assert!(topic_handler.subscription_metadata(&client_id, &topic).is_some());
+    }
+
+    #[test]
+    fn find_deliveries_overlapping() {
+        let topic_handler = TopicHandler::default();
+        let client_id = String::from("find_deliveries_overlapping");
+        let wildcard = String::from("test/#");
+        let topic = String::from("test/overlap");
+        topic_handler.subscribe_with_settings(&client_id, &wildcard, &SubscriptionSettings { maximum_qos: QoSLevel::AtLeastOnce, subscription_identifier: Some(1), accept_encoding: None, no_local: false, retain_as_published: false });
+        topic_handler.subscribe_with_settings(&client_id, &topic, &SubscriptionSettings { maximum_qos: QoSLevel::AtMostOnce, subscription_identifier: Some(2), accept_encoding: None, no_local: false, retain_as_published: false });
+        topic_handler.subscribe(&String::from("other"), &String::from("test/+/other"));
+
+        let deliveries = topic_handler.find_deliveries(&topic, OverlapPolicy::Once, &ClientHandler::default());
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].maximum_qos, QoSLevel::AtLeastOnce);
+        let mut subscription_identifiers = deliveries[0].subscription_identifiers.clone();
+        subscription_identifiers.sort();
+        assert_eq!(subscription_identifiers, vec![1, 2]);
+
+        let deliveries = topic_handler.find_deliveries(&topic, OverlapPolicy::PerSubscription, &ClientHandler::default());
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(topic_handler.find_subscribers(&topic), vec![client_id]);
+    }
+
+    #[test]
+    fn find_deliveries_resolves_connections() {
+        let topic_handler = TopicHandler::default();
+        let client_handler = ClientHandler::default();
+        let online = String::from("find_deliveries_online");
+        let offline = String::from("find_deliveries_offline");
+        let topic = String::from("test/resolved");
+        let socket = "127.0.0.1:40003".parse().unwrap();
+        client_handler.register(&socket, &online);
+        topic_handler.subscribe(&online, &String::from("test/+"));
+        topic_handler.subscribe_with_settings(&online, &topic, &SubscriptionSettings { maximum_qos: QoSLevel::ExactlyOnce, subscription_identifier: None, accept_encoding: Some(ContentEncoding::Deflate), no_local: false, retain_as_published: false });
+        topic_handler.subscribe(&offline, &topic);
+
+        let mut deliveries = topic_handler.find_deliveries(&topic, OverlapPolicy::PerSubscription, &client_handler);
+        deliveries.sort_by_key(|delivery| (delivery.client_id.clone(), delivery.topic_filters.clone()));
+        assert_eq!(deliveries.len(), 3);
+        assert_eq!(deliveries[0].client_id.as_str(), "find_deliveries_offline");
+        assert_eq!(deliveries[0].target, DeliveryTarget::Dropped);
+        assert_eq!(deliveries[1].connection(), client_handler.get_connection(&online).ok());
+        assert!(!deliveries[1].accepts_encoding);
+        assert_eq!(deliveries[2].target, deliveries[1].target);
+        assert!(deliveries[2].accepts_encoding);
+
+        let deliveries = topic_handler.find_deliveries(&topic, OverlapPolicy::Once, &client_handler);
+        let delivery = deliveries.iter().find(|delivery| delivery.client_id.as_str() == "find_deliveries_online").unwrap();
+        assert_eq!(delivery.topic_filters.len(), 2);
+        assert!(delivery.accepts_encoding);
+    }
+}
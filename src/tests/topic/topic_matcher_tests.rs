@@ -0,0 +1,45 @@
+#[cfg(test)]
+mod topic_matcher_tests {
+    use crate::topic::topic_matcher::{is_valid_filter, shared_filter, topic_matches};
+
+    #[test]
+    fn single_level_wildcard() {
+        assert!(topic_matches("sport/+/player1", "sport/tennis/player1"));
+        assert!(topic_matches("sport/+", "sport/"));
+        assert!(topic_matches("+/+", "/finance"));
+        assert!(!topic_matches("sport/+", "sport/tennis/player1"));
+        assert!(!topic_matches("sport/+", "sport"));
+    }
+
+    #[test]
+    fn multi_level_wildcard() {
+        assert!(topic_matches("sport/#", "sport"));
+        assert!(topic_matches("sport/#", "sport/tennis/player1"));
+        assert!(topic_matches("#", "sport/tennis"));
+        assert!(!topic_matches("sport/tennis/#", "sport/football"));
+    }
+
+    #[test]
+    fn dollar_topics() {
+        assert!(!topic_matches("#", "$SYS/uptime"));
+        assert!(!topic_matches("+/uptime", "$SYS/uptime"));
+        assert!(topic_matches("$SYS/#", "$SYS/uptime"));
+    }
+
+    #[test]
+    fn shared_filters() {
+        assert_eq!(shared_filter("$share/group/sport/#"), Some(("group", "sport/#")));
+        assert_eq!(shared_filter("sport/#"), None);
+        assert_eq!(shared_filter("$share/group"), None);
+    }
+
+    #[test]
+    fn valid_filters() {
+        assert!(is_valid_filter("shadows/+/state/#"));
+        assert!(is_valid_filter("#"));
+        assert!(!is_valid_filter(""));
+        assert!(!is_valid_filter("shadows/#/state"));
+        assert!(!is_valid_filter("shadows/device+"));
+        assert!(!is_valid_filter("shadows#"));
+    }
+}
@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tree_telemetry_tests {
+    use std::collections::{BTreeMap, HashMap};
+    use std::time::Instant;
+
+    use crate::config::broker_config::{OverlapPolicy, TreeTelemetryConfig};
+    use crate::session::client_handler::ClientHandler;
+    use crate::topic::tree_telemetry::{TreeShape, TreeTelemetry};
+    use crate::TopicHandler;
+
+    fn create_topic_handler(topic_filters: &[&str]) -> TopicHandler {
+        let topic_handler = TopicHandler::default();
+        for topic_filter in topic_filters {
+            topic_handler.subscribe(&String::from("tree_telemetry"), &topic_filter.to_string());
+        }
+        topic_handler
+    }
+
+    #[test]
+    fn shape_counts_depth_nodes_and_fanout() {
+        let topic_handler = create_topic_handler(&["devices/1/state", "devices/2/state", "devices/3/+", "devices/#", "alerts", "/leading"]);
+        let shape = TreeShape::of(&topic_handler.subscription_tree());
+
+        assert_eq!(shape.filters, 6);
+        assert_eq!(shape.wildcard_filters, 2);
+        assert_eq!(shape.max_depth, 3);
+        assert_eq!(shape.filters_by_depth, BTreeMap::from([(1, 1), (2, 2), (3, 3)]));
+        //devices alerts "" | 1 2 3 # | state state + | leading
+        assert_eq!(shape.nodes, 11);
+        assert_eq!(shape.max_fanout, 4);
+        assert_eq!(shape.widest_node, "devices");
+        assert_eq!(shape.mean_fanout, 11.0 / 6.0);
+    }
+
+    #[test]
+    fn report_keeps_last_shape_and_warns_on_wide_nodes() {
+        let tree_telemetry = TreeTelemetry::new(TreeTelemetryConfig { report_interval_secs: 60, fanout_warning: 3 });
+        assert_eq!(tree_telemetry.last_shape(), None);
+
+        let topic_handler = create_topic_handler(&["devices/1/state", "devices/2/state"]);
+        tree_telemetry.report(&topic_handler.subscription_tree());
+        assert_eq!(tree_telemetry.metrics.wide_nodes.0.get(), 0);
+
+        topic_handler.subscribe(&String::from("tree_telemetry"), &String::from("devices/3/state"));
+        let shape = tree_telemetry.report(&topic_handler.subscription_tree());
+        assert_eq!(tree_telemetry.last_shape(), Some(shape));
+        assert_eq!(tree_telemetry.metrics.reports.0.get(), 2);
+        assert_eq!(tree_telemetry.metrics.wide_nodes.0.get(), 1);
+    }
+
+    #[cfg(feature = "admin-api")]
+    #[test]
+    fn metrics_export_last_shape() {
+        let tree_telemetry = TreeTelemetry::new(TreeTelemetryConfig::default());
+        let topic_handler = create_topic_handler(&["devices/1/state", "devices/2/state", "alerts", "a/b/c/d/e/f/g/h", "a/b/c/d/e/f/g/h/i"]);
+        tree_telemetry.report(&topic_handler.subscription_tree());
+
+        let metrics = serde_prometheus::to_string(&tree_telemetry.metrics(), Some("patina"), HashMap::<&str, &str>::new()).unwrap();
+        assert!(metrics.contains("patina_max_fanout 3"));
+        assert!(metrics.contains("patina_filters_at_depth_3{path = \"filters_by_depth\"} 2"));
+        assert!(metrics.contains("patina_filters_at_depth_8_or_more{path = \"filters_by_depth\"} 2"));
+        assert!(metrics.contains("patina_reports 1"));
+    }
+
+    #[test]
+    fn matching_is_measured() {
+        let topic_handler = create_topic_handler(&["devices/+/state"]);
+        topic_handler.find_subscribers(&String::from("devices/1/state"));
+        topic_handler.find_deliveries(&String::from("devices/2/state"), OverlapPolicy::Once, &ClientHandler::default());
+        assert_eq!(topic_handler.metrics.matching_filters.hit_count.0.get(), 2);
+    }
+
+    //cargo test wide_topic_level_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn wide_topic_level_benchmark() {
+        let topic_handler = TopicHandler::default();
+        let client_handler = ClientHandler::default();
+        //Ids at level 2, one wildcard filter per device
+        for device in 0..10_000 {
+            topic_handler.subscribe(&format!("consumer-{}", device), &format!("devices/{}/+", device));
+        }
+        let topics: Vec<String> = (0..10_000).map(|message| format!("devices/{}/state", message % 10_000)).collect();
+
+        let now = Instant::now();
+        let shape = TreeShape::of(&topic_handler.subscription_tree());
+        let shape_elapsed = now.elapsed();
+
+        let now = Instant::now();
+        let delivered: usize = topics.iter().map(|topic| topic_handler.find_deliveries(topic, OverlapPolicy::Once, &client_handler).len()).sum();
+        let match_elapsed = now.elapsed();
+
+        assert_eq!(delivered, topics.len());
+        println!("{:?}", shape);
+        println!("shape: {:?}", shape_elapsed);
+        println!("{} publishes, {} wildcard filters: {:?}, {:?} per publish", topics.len(), shape.wildcard_filters, match_elapsed, match_elapsed / topics.len() as u32);
+    }
+}
@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod virtual_topics_tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::time::{Duration, Instant};
+
+    use crate::config::broker_config::BrokerConfig;
+    use crate::model::fixed_header::ControlPacketType;
+    use crate::model::qos_level::QoSLevel;
+    use crate::model::topic::RetainHandling;
+    use crate::tests::broker::broker_tests_data::{create_publish_packet_qos0, create_subscribe_packet_with_retain_handling};
+    use crate::tests::broker::handler_harness::HandlerHarness;
+    use crate::topic::virtual_topics::{MessageRate, SYS_MESSAGE_RATE_TOPIC, SYS_TIME_TOPIC, VirtualTopicProvider, VirtualTopics};
+
+    //Its value is how many times it was computed
+    #[derive(Debug, Default)]
+    struct Counter {
+        computations: AtomicU64,
+    }
+
+    impl VirtualTopicProvider for Counter {
+        fn topic_name(&self) -> &str {
+            "virtual/counter"
+        }
+
+        fn default_refresh_interval(&self) -> Duration {
+            Duration::from_secs(5)
+        }
+
+        fn compute(&self, _now: Instant) -> Vec<u8> {
+            (self.computations.fetch_add(1, Ordering::Relaxed) + 1).to_string().into_bytes()
+        }
+    }
+
+    #[test]
+    fn serve_values_until_refresh_interval() {
+        let virtual_topics = VirtualTopics::default();
+        let now = Instant::now();
+        virtual_topics.register(Arc::new(Counter::default()), Duration::from_secs(5));
+        assert_eq!(virtual_topics.value("virtual/counter", now), Some(b"1".to_vec()));
+        assert_eq!(virtual_topics.value("virtual/counter", now + Duration::from_secs(4)), Some(b"1".to_vec()));
+        assert!(virtual_topics.stale(now + Duration::from_secs(4)).is_empty());
+        assert_eq!(virtual_topics.stale(now + Duration::from_secs(5)), vec![String::from("virtual/counter")]);
+        assert_eq!(virtual_topics.value("virtual/counter", now + Duration::from_secs(5)), Some(b"2".to_vec()));
+        assert_eq!(virtual_topics.value("virtual/unknown", now), None);
+        assert_eq!(virtual_topics.metrics.computed.0.get(), 2);
+
+        //Computed for every subscription and never published
+        virtual_topics.register(Arc::new(Counter::default()), Duration::ZERO);
+        assert_eq!(virtual_topics.value("virtual/counter", now), Some(b"1".to_vec()));
+        assert_eq!(virtual_topics.value("virtual/counter", now), Some(b"2".to_vec()));
+        assert!(virtual_topics.stale(now + Duration::from_secs(60)).is_empty());
+    }
+
+    #[test]
+    fn match_subscriptions() {
+        let virtual_topics = VirtualTopics::default();
+        let now = Instant::now();
+        virtual_topics.register(Arc::new(Counter::default()), Duration::from_secs(5));
+        virtual_topics.register(Arc::new(MessageRate::new(now)), Duration::from_secs(10));
+        let topics = |topic_filter: &str| virtual_topics.matching(topic_filter, now).into_iter().map(|(topic_name, _)| topic_name).collect::<Vec<String>>();
+        assert_eq!(topics("virtual/+"), vec![String::from("virtual/counter")]);
+        assert_eq!(topics("$share/group/virtual/#"), vec![String::from("virtual/counter")]);
+        assert_eq!(topics("$SYS/#"), vec![String::from(SYS_MESSAGE_RATE_TOPIC)]);
+        //$SYS topics aren't matched by wildcards
+        assert_eq!(topics("#"), vec![String::from("virtual/counter")]);
+        assert!(topics("sensors/#").is_empty());
+    }
+
+    #[test]
+    fn count_messages_over_last_minute() {
+        let started_at = Instant::now();
+        let message_rate = MessageRate::new(started_at);
+        for second in [0, 0, 10, 59] {
+            message_rate.record(started_at + Duration::from_secs(second));
+        }
+        assert_eq!(message_rate.count(started_at + Duration::from_secs(59)), 4);
+        assert_eq!(message_rate.count(started_at + Duration::from_secs(60)), 2);
+        assert_eq!(message_rate.compute(started_at + Duration::from_secs(70)), b"1".to_vec());
+        assert_eq!(message_rate.count(started_at + Duration::from_secs(200)), 0);
+    }
+
+    #[tokio::test]
+    async fn send_values_on_subscription() {
+        let mut config = BrokerConfig::default();
+        config.virtual_topics.enabled = true;
+        let mut harness = HandlerHarness::new(config);
+        let socket = harness.connect("virtual-subscriber").await;
+        harness.send(socket, create_publish_packet_qos0(1, String::from("virtual/counted"))).await.unwrap();
+        //Retain Handling decides like for retained messages
+        harness.subscribe(socket, "$SYS/#", QoSLevel::AtLeastOnce).await;
+        harness.send(socket, create_subscribe_packet_with_retain_handling(2, String::from("$SYS/#"), QoSLevel::AtLeastOnce, RetainHandling::SendRetainedMessagesOnSubscribe)).await.unwrap();
+        harness.expect(ControlPacketType::SUBACK).await;
+
+        let (sockets, publish_packet) = harness.expect(ControlPacketType::PUBLISH).await;
+        assert_eq!(sockets, vec![socket]);
+        assert_eq!(publish_packet.variable_header().topic_name(), SYS_MESSAGE_RATE_TOPIC);
+        assert_eq!(publish_packet.payload().data(), &b"1".to_vec());
+        assert!(*publish_packet.fixed_header().retain());
+        let (_, publish_packet) = harness.expect(ControlPacketType::PUBLISH).await;
+        assert_eq!(publish_packet.variable_header().topic_name(), SYS_TIME_TOPIC);
+        assert!(chrono::DateTime::parse_from_rfc3339(std::str::from_utf8(publish_packet.payload().data()).unwrap()).is_ok());
+        assert_eq!(harness.topic_handler.virtual_topics.metrics.delivered.0.get(), 2);
+    }
+}
@@ -1 +1,6 @@
 pub mod topic_handler;
+pub mod subscription;
+pub mod subscription_tree;
+pub mod topic_matcher;
+pub mod tree_telemetry;
+pub mod virtual_topics;
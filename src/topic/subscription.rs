@@ -0,0 +1,156 @@
+use std::sync::Arc;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::broker::compression::ContentEncoding;
+use crate::model::qos_level::QoSLevel;
+use crate::session::client_handler::Connection;
+
+//Bookkeeping kept next to every (client_id, topic_filter) subscription.
+//Timestamps are Unix epoch milliseconds so they survive export/import unchanged.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubscriptionMetadata {
+    created_at: i64,
+    last_delivery_at: Option<i64>,
+    delivery_count: u64,
+    //Encoding the subscriber accepts for payloads, asked for at SUBSCRIBE
+    #[serde(default)]
+    accept_encoding: Option<ContentEncoding>,
+    //Highest QoS messages are forwarded with, the publisher's QoS when unset
+    #[serde(default)]
+    maximum_qos: Option<QoSLevel>,
+    #[serde(default)]
+    subscription_identifier: Option<u64>,
+    //No Local as asked for at SUBSCRIBE, the client's own messages skip the subscription
+    #[serde(default)]
+    no_local: bool,
+    //Retain As Published as asked for at SUBSCRIBE, forwarded messages keep their RETAIN flag
+    #[serde(default)]
+    retain_as_published: bool,
+}
+
+impl Default for SubscriptionMetadata {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SubscriptionMetadata {
+    pub fn new() -> Self {
+        Self { created_at: Utc::now().timestamp_millis(), last_delivery_at: None, delivery_count: 0, accept_encoding: None, maximum_qos: None, subscription_identifier: None, no_local: false, retain_as_published: false }
+    }
+
+    pub fn created_at(&self) -> i64 {
+        self.created_at
+    }
+    pub fn last_delivery_at(&self) -> Option<i64> {
+        self.last_delivery_at
+    }
+    pub fn delivery_count(&self) -> u64 {
+        self.delivery_count
+    }
+
+    pub fn accept_encoding(&self) -> Option<ContentEncoding> {
+        self.accept_encoding
+    }
+
+    pub fn maximum_qos(&self) -> Option<QoSLevel> {
+        self.maximum_qos
+    }
+    pub fn subscription_identifier(&self) -> Option<u64> {
+        self.subscription_identifier
+    }
+    pub fn no_local(&self) -> bool {
+        self.no_local
+    }
+    pub fn retain_as_published(&self) -> bool {
+        self.retain_as_published
+    }
+    //Every option is replaced, one the request leaves out goes back to its default
+    pub fn set_settings(&mut self, settings: &SubscriptionSettings) {
+        self.maximum_qos = Some(settings.maximum_qos);
+        self.subscription_identifier = settings.subscription_identifier;
+        self.accept_encoding = settings.accept_encoding;
+        self.no_local = settings.no_local;
+        self.retain_as_published = settings.retain_as_published;
+    }
+
+    pub fn register_delivery(&mut self) {
+        self.last_delivery_at = Some(Utc::now().timestamp_millis());
+        self.delivery_count += 1;
+    }
+
+    //Last time the subscription was used, creation time if it never received anything
+    pub fn last_activity_at(&self) -> i64 {
+        self.last_delivery_at.unwrap_or(self.created_at)
+    }
+}
+
+//Options a SUBSCRIBE sets on one subscription
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+pub struct SubscriptionSettings {
+    pub maximum_qos: QoSLevel,
+    pub subscription_identifier: Option<u64>,
+    pub accept_encoding: Option<ContentEncoding>,
+    pub no_local: bool,
+    pub retain_as_published: bool,
+}
+
+//Flat form of a subscription used to move subscriptions between brokers
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+#[derive(Serialize, Deserialize)]
+pub struct SubscriptionRecord {
+    pub client_id: String,
+    pub topic_filter: String,
+    //A subscription from another broker may come without, it counts as created on import
+    #[serde(default)]
+    pub metadata: SubscriptionMetadata,
+}
+
+//A message to forward to one client. With OverlapPolicy::Once it stands for every subscription
+//of the client matching the topic, otherwise for a single one.
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+pub struct Delivery {
+    pub client_id: Arc<String>,
+    pub target: DeliveryTarget,
+    pub topic_filters: Vec<Arc<String>>,
+    pub maximum_qos: QoSLevel,
+    pub subscription_identifiers: Vec<u64>,
+    //At least one of the subscriptions asked for compressed payloads
+    pub accepts_encoding: bool,
+    //At least one of the subscriptions asked for Retain As Published
+    pub retain_as_published: bool,
+}
+
+impl Delivery {
+    //Connection of the client when the delivery was resolved, None while it is offline
+    pub fn connection(&self) -> Option<Connection> {
+        match self.target {
+            DeliveryTarget::Connected(connection) => { Some(connection) }
+            DeliveryTarget::Queued | DeliveryTarget::Dropped => { None }
+        }
+    }
+}
+
+//Where the message of a delivery goes, decided when the delivery is resolved
+#[derive(Debug)]
+#[derive(Copy, Clone)]
+#[derive(Eq, PartialEq)]
+pub enum DeliveryTarget {
+    Connected(Connection),
+    //Offline with a session outliving the connection, the message waits in the session
+    Queued,
+    //Offline without such a session, nobody would ever read the message
+    Dropped,
+}
@@ -0,0 +1,124 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::model::qos_level::QoSLevel;
+use crate::topic::subscription::SubscriptionMetadata;
+use crate::topic::topic_matcher::{is_wildcard, shared_filter, topic_matches};
+
+//The options of a subscription that decide how a message is forwarded
+#[derive(Debug)]
+#[derive(Clone)]
+#[derive(Eq, PartialEq)]
+pub struct Route {
+    pub maximum_qos: QoSLevel,
+    pub subscription_identifier: Option<u64>,
+    pub accepts_encoding: bool,
+    pub retain_as_published: bool,
+    pub no_local: bool,
+}
+
+impl From<&SubscriptionMetadata> for Route {
+    fn from(metadata: &SubscriptionMetadata) -> Self {
+        Self {
+            maximum_qos: metadata.maximum_qos().unwrap_or(QoSLevel::ExactlyOnce),
+            subscription_identifier: metadata.subscription_identifier(),
+            accepts_encoding: metadata.accept_encoding().is_some(),
+            retain_as_published: metadata.retain_as_published(),
+            no_local: metadata.no_local(),
+        }
+    }
+}
+
+//Routes of one topic filter by client_id
+pub type Routes = HashMap<Arc<String>, Route>;
+
+//One version of the subscriptions as publishes see them. A version is never modified: a change
+//copies the filter map and replaces the routes of the filters it touches, the other filters are shared.
+//What a publish can see, resolving its deliveries against a single version:
+// - all of a change or none of it, dropping every subscription of a client or an import never shows half done
+// - every change that returned before the publish started resolving its deliveries
+// - a change made while it resolves is not seen, the publish is ordered before that change.
+//   A client that just unsubscribed can still get a message published before its UNSUBACK was sent.
+#[derive(Debug)]
+#[derive(Clone, Default)]
+pub struct SubscriptionTree {
+    version: u64,
+    filter2routes: HashMap<Arc<String>, Arc<Routes>>,
+    //Filters with + or #, they are matched against every published topic
+    wildcard_filters: Vec<Arc<String>>,
+    //First levels of the wildcard filters, a topic outside of them only matches an exact filter
+    wildcard_first_levels: HashSet<String>,
+    //Wildcard filters starting with + or #, they match any first level
+    leading_wildcards: usize,
+    //$share/<group>/<filter> filters with the filter their group matches topics against
+    shared_filters: Vec<(Arc<String>, String)>,
+}
+
+impl SubscriptionTree {
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    pub fn topic_filters(&self) -> impl Iterator<Item=&Arc<String>> {
+        self.filter2routes.keys()
+    }
+
+    pub fn routes(&self, topic_filter: &String) -> Option<&Arc<Routes>> {
+        self.filter2routes.get(topic_filter)
+    }
+
+    //Cheap check before any per-message work: an exact filter is a lookup, wildcards are only
+    //matched when one of them can cover the first level of the topic
+    pub fn has_subscribers(&self, topic_name: &String) -> bool {
+        if self.filter2routes.contains_key(topic_name) {
+            return true;
+        }
+        if self.shared_filters.iter().any(|(_, topic_filter)| topic_matches(topic_filter, topic_name)) {
+            return true;
+        }
+        let first_level = topic_name.split('/').next().unwrap_or_default();
+        if self.leading_wildcards == 0 && !self.wildcard_first_levels.contains(first_level) {
+            return false;
+        }
+        self.wildcard_filters.iter().any(|topic_filter| topic_matches(topic_filter, topic_name))
+    }
+
+    //The topic name itself, the wildcard filters matching it and the shared filters whose group matches it
+    pub fn matching_filters(&self, topic_name: &String) -> Vec<Arc<String>> {
+        let mut topic_filters = vec![Arc::new(topic_name.to_owned())];
+        topic_filters.extend(self.wildcard_filters.iter()
+            .filter(|topic_filter| topic_filter.as_str().ne(topic_name) && topic_matches(topic_filter, topic_name))
+            .cloned());
+        topic_filters.extend(self.shared_filters.iter()
+            .filter(|(_, topic_filter)| topic_matches(topic_filter, topic_name))
+            .map(|(shared_filter, _)| shared_filter.clone()));
+        topic_filters
+    }
+
+    //The next version, with the routes of the given filters replaced. Filters left without routes are dropped.
+    pub fn with_routes(&self, changes: Vec<(Arc<String>, Routes)>) -> Self {
+        let mut filter2routes = self.filter2routes.clone();
+        let mut wildcards_changed = false;
+        for (topic_filter, routes) in changes {
+            wildcards_changed |= is_wildcard(&topic_filter) || shared_filter(&topic_filter).is_some();
+            match routes.is_empty() {
+                true => { filter2routes.remove(&topic_filter); }
+                false => { filter2routes.insert(topic_filter, Arc::new(routes)); }
+            }
+        }
+        if !wildcards_changed {
+            return Self { version: self.version + 1, filter2routes, wildcard_filters: self.wildcard_filters.clone(), wildcard_first_levels: self.wildcard_first_levels.clone(), leading_wildcards: self.leading_wildcards, shared_filters: self.shared_filters.clone() };
+        }
+        let shared_filters: Vec<(Arc<String>, String)> = filter2routes.keys()
+            .filter_map(|topic_filter| shared_filter(topic_filter).map(|(_, filter)| (topic_filter.clone(), filter.to_string())))
+            .collect();
+        let wildcard_filters: Vec<Arc<String>> = filter2routes.keys()
+            .filter(|topic_filter| is_wildcard(topic_filter) && shared_filter(topic_filter).is_none())
+            .cloned()
+            .collect();
+        let (leading, wildcard_first_levels): (Vec<String>, Vec<String>) = wildcard_filters.iter()
+            .map(|topic_filter| topic_filter.split('/').next().unwrap_or_default().to_string())
+            .partition(|first_level| is_wildcard(first_level));
+        Self { version: self.version + 1, filter2routes, wildcard_filters, wildcard_first_levels: wildcard_first_levels.into_iter().collect(), leading_wildcards: leading.len(), shared_filters }
+    }
+}
@@ -1,77 +1,425 @@
-use std::collections::HashSet;
-use std::ops::Deref;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
+use chrono::Utc;
 use dashmap::DashMap;
 use log::trace;
 use metered::{*};
 
+use crate::ClientHandler;
+use crate::broker::message_expiry::StoredMessage;
+use crate::broker::utils::has_persistent_session;
+use crate::config::broker_config::OverlapPolicy;
+use crate::model::control_packet::ControlPacket;
+use crate::topic::subscription::{Delivery, DeliveryTarget, SubscriptionMetadata, SubscriptionRecord, SubscriptionSettings};
+use crate::topic::subscription_tree::{Route, Routes, SubscriptionTree};
+use crate::session::client_handler::Connection;
+use crate::topic::topic_matcher::{shared_filter, topic_matches};
+use crate::topic::virtual_topics::VirtualTopics;
+
+//Subscribers of a topic filter with the options and bookkeeping of each subscription
+type Subscribers = HashMap<Arc<String>, SubscriptionMetadata>;
+
 #[derive(Debug)]
 pub struct TopicHandler {
-    topic2subscribers: Arc<DashMap<String, HashSet<String>>>,
+    //Subscriptions with their bookkeeping, the source every version of the tree is built from
+    topic2subscribers: Arc<DashMap<Arc<String>, Subscribers>>,
+    //What publishes resolve deliveries against. Everything a delivery needs is read from here,
+    //with no lookup per subscriber in another map.
+    subscription_tree: RwLock<Arc<SubscriptionTree>>,
+    //Changes are applied one at a time, each version builds on the previous one
+    tree_writer: Mutex<()>,
+    //Retained message per topic name together with the client_id that published it
+    topic2retained: Arc<DashMap<String, (String, StoredMessage)>>,
+    subscribed_count: AtomicU64,
+    unsubscribed_count: AtomicU64,
+    //Messages each shared filter handed out, its members take turns by this count
+    shared_turns: DashMap<Arc<String>, usize>,
+    //Topics with computed values, sent to a subscription like retained messages
+    pub(crate) virtual_topics: VirtualTopics,
     pub(crate) metrics: TopicHandlerMetrics,
 }
 
 impl Default for TopicHandler {
     fn default() -> Self {
-        Self { topic2subscribers: Arc::new(DashMap::new()), metrics: TopicHandlerMetrics::default() }
+        Self {
+            topic2subscribers: Arc::new(DashMap::new()),
+            subscription_tree: RwLock::new(Arc::new(SubscriptionTree::default())),
+            tree_writer: Mutex::new(()),
+            topic2retained: Arc::new(DashMap::new()),
+            subscribed_count: AtomicU64::new(0),
+            unsubscribed_count: AtomicU64::new(0),
+            shared_turns: DashMap::new(),
+            virtual_topics: VirtualTopics::default(),
+            metrics: TopicHandlerMetrics::default(),
+        }
     }
 }
 
 #[metered(registry = TopicHandlerMetrics)]
 impl TopicHandler {
+    //A repeated SUBSCRIBE replaces the subscription but keeps its history
     #[measure([HitCount, Throughput, InFlight, ResponseTime])]
     pub fn subscribe(&self, client_id: &String, topic_filter: &String) {
         trace!("Adding subscriber {:?} to {:?}", client_id, topic_filter);
+        let _writer = self.tree_writer.lock().unwrap();
+        let topic_filter = {
+            let mut subscribers = self.topic2subscribers.entry(Arc::new(topic_filter.to_owned())).or_default();
+            if subscribers.contains_key(client_id) {
+                return;
+            }
+            subscribers.insert(Arc::new(client_id.to_owned()), SubscriptionMetadata::new());
+            subscribers.key().clone()
+        };
+        self.subscribed_count.fetch_add(1, Ordering::Relaxed);
+        self.update_tree(vec![topic_filter]);
+    }
+
+    //Creates the subscription or replaces every option of the one the client has, keeping its history.
+    //Publishes see it with all of its options at once. Returns whether the subscription is new.
+    #[measure([HitCount, Throughput, InFlight, ResponseTime])]
+    pub fn subscribe_with_settings(&self, client_id: &String, topic_filter: &String, settings: &SubscriptionSettings) -> bool {
+        trace!("Subscribing {:?} to {:?} with {:?}", client_id, topic_filter, settings);
+        let _writer = self.tree_writer.lock().unwrap();
+        let (topic_filter, is_new) = {
+            let mut subscribers = self.topic2subscribers.entry(Arc::new(topic_filter.to_owned())).or_default();
+            let is_new = !subscribers.contains_key(client_id);
+            subscribers.entry(Arc::new(client_id.to_owned())).or_default().set_settings(settings);
+            (subscribers.key().clone(), is_new)
+        };
+        if is_new {
+            self.subscribed_count.fetch_add(1, Ordering::Relaxed);
+        }
+        self.update_tree(vec![topic_filter]);
+        is_new
+    }
+
+    //The filter is compared literally, wildcards are not expanded: unsubscribing from a/# keeps
+    //a subscription to a/b. Returns whether the client had a subscription with this exact filter.
+    #[measure([HitCount, Throughput, InFlight, ResponseTime])]
+    pub fn unsubscribe(&self, client_id: &String, topic_filter: &String) -> bool {
+        trace!("Unsubscribing client {:?} from topic {:?}", client_id, topic_filter);
+        let _writer = self.tree_writer.lock().unwrap();
+        let existed = match self.topic2subscribers.get_mut(topic_filter) {
+            Some(mut subscribers) => { subscribers.remove(client_id).is_some() }
+            None => { false }
+        };
+        if existed {
+            self.unsubscribed_count.fetch_add(1, Ordering::Relaxed);
+            self.update_tree(vec![Arc::new(topic_filter.to_owned())]);
+        }
+        existed
+    }
 
-        if self.topic2subscribers.contains_key(topic_filter) {
-            let mut subscribers = self.topic2subscribers.get_mut(topic_filter).unwrap();
-            if subscribers.iter().filter(|s: &&String| { s.to_owned().eq(client_id) }).count() == 0 {
-                subscribers.insert(client_id.to_owned());
+    #[measure([HitCount, Throughput, InFlight, ResponseTime])]
+    pub fn unsubscribe_all(&self, client_id: &String) {
+        let _writer = self.tree_writer.lock().unwrap();
+        let mut topic_filters = vec![];
+        for mut subscribers in self.topic2subscribers.iter_mut() {
+            if subscribers.remove(client_id).is_some() {
+                trace!("Unsubscribed client {:?} from topic {:?}", client_id, subscribers.key());
+                topic_filters.push(subscribers.key().clone());
+            }
+        }
+        self.unsubscribed_count.fetch_add(topic_filters.len() as u64, Ordering::Relaxed);
+        //A publish sees the client either in all of its subscriptions or in none
+        if !topic_filters.is_empty() {
+            self.update_tree(topic_filters);
+        }
+    }
+
+    #[measure([HitCount, Throughput, InFlight, ResponseTime])]
+    pub fn find_subscribers(&self, topic_name: &String) -> Vec<String> {
+        let subscription_tree = self.subscription_tree();
+        let mut subscribers: Vec<String> = Vec::new();
+        for topic_filter in self.matching_filters(&subscription_tree, topic_name) {
+            if let Some(routes) = subscription_tree.routes(&topic_filter) {
+                subscribers.extend(routes.keys().map(|subscriber| subscriber.to_string()));
             }
-        } else {
-            let mut subscribers = HashSet::with_capacity(100);
-            subscribers.insert(client_id.to_owned());
-            self.topic2subscribers.insert(topic_filter.to_owned(), subscribers);
         }
+        subscribers.sort();
+        subscribers.dedup();
+        subscribers
     }
 
+    //Matching alone, the tree telemetry reports match times from these metrics
+    #[measure([HitCount, ResponseTime])]
+    fn matching_filters(&self, subscription_tree: &SubscriptionTree, topic_name: &String) -> Vec<Arc<String>> {
+        subscription_tree.matching_filters(topic_name)
+    }
+
+    //Deliveries are built in a single walk over the matching filters of one version of the tree, with
+    //the connection of each client resolved once. Client ids and filters are shared, not copied.
+    pub fn find_deliveries(&self, topic_name: &String, overlap_policy: OverlapPolicy, client_handler: &ClientHandler) -> Vec<Delivery> {
+        self.find_deliveries_from(None, topic_name, overlap_policy, client_handler)
+    }
+
+    //A subscription of the publisher with No Local set gets none of its messages, its other subscriptions do
     #[measure([HitCount, Throughput, InFlight, ResponseTime])]
-    pub fn unsubscribe(&self, client_id: &String, topic_filter: &String) {
-        self.topic2subscribers.alter_all(|topic, subscribers| {
-            if topic.eq(topic_filter) {
-                trace!("Unsubscribing client {:?} from topic {:?}", client_id, topic_filter);
-                subscribers.to_owned()
-                    .into_iter()
-                    .filter(|s| { s.deref().ne(client_id) })
-                    .collect()
-            } else {
-                subscribers
+    pub fn find_deliveries_from(&self, publisher: Option<&String>, topic_name: &String, overlap_policy: OverlapPolicy, client_handler: &ClientHandler) -> Vec<Delivery> {
+        let mut deliveries: Vec<Delivery> = Vec::new();
+        let mut client2delivery: HashMap<Arc<String>, usize> = HashMap::new();
+        let subscription_tree = self.subscription_tree();
+        for topic_filter in self.matching_filters(&subscription_tree, topic_name) {
+            let routes = match subscription_tree.routes(&topic_filter) {
+                Some(routes) => { routes }
+                None => { continue; }
+            };
+            //A shared filter delivers to one member of its group
+            let routes: Vec<(&Arc<String>, &Route)> = match shared_filter(&topic_filter) {
+                Some(_) => { self.shared_member(&topic_filter, routes, client_handler).into_iter().collect() }
+                None => { routes.iter().collect() }
+            };
+            for (client_id, route) in routes {
+                let Route { maximum_qos, subscription_identifier, accepts_encoding, retain_as_published, no_local } = *route;
+                if no_local && publisher.is_some_and(|publisher| publisher == client_id.as_ref()) {
+                    continue;
+                }
+                let previous = client2delivery.get(client_id).copied();
+                match (previous, overlap_policy) {
+                    (Some(index), OverlapPolicy::Once) => {
+                        let delivery: &mut Delivery = &mut deliveries[index];
+                        delivery.topic_filters.push(topic_filter.clone());
+                        delivery.maximum_qos = delivery.maximum_qos.max(maximum_qos);
+                        delivery.subscription_identifiers.extend(subscription_identifier);
+                        delivery.accepts_encoding |= accepts_encoding;
+                        delivery.retain_as_published |= retain_as_published;
+                    }
+                    _ => {
+                        let target = match previous {
+                            Some(index) => { deliveries[index].target }
+                            None => {
+                                client2delivery.insert(client_id.clone(), deliveries.len());
+                                Self::delivery_target(client_id, client_handler)
+                            }
+                        };
+                        deliveries.push(Delivery { client_id: client_id.clone(), target, topic_filters: vec![topic_filter.clone()], maximum_qos, subscription_identifiers: subscription_identifier.into_iter().collect(), accepts_encoding, retain_as_published });
+                    }
+                }
             }
-        });
+        }
+        deliveries
+    }
+
+    //Members take turns, connected ones first. Without any, a member whose session keeps the message for later.
+    fn shared_member<'a>(&self, topic_filter: &Arc<String>, routes: &'a Routes, client_handler: &ClientHandler) -> Option<(&'a Arc<String>, &'a Route)> {
+        let mut members: Vec<(&Arc<String>, &Route)> = routes.iter().collect();
+        members.sort_by(|(client_id, _), (other_client_id, _)| client_id.cmp(other_client_id));
+        let connected: Vec<(&Arc<String>, &Route)> = members.iter().copied()
+            .filter(|(client_id, _)| client_handler.get_connection(client_id).is_ok())
+            .collect();
+        let candidates = match connected.is_empty() {
+            false => { connected }
+            true => { members.into_iter().filter(|(client_id, _)| has_persistent_session(&*client_handler.sessions, client_id)).collect() }
+        };
+        if candidates.is_empty() {
+            return None;
+        }
+        let mut turn = self.shared_turns.entry(topic_filter.clone()).or_insert(0);
+        let member = candidates[*turn % candidates.len()];
+        *turn = turn.wrapping_add(1);
+        Some(member)
+    }
+
+    //Another connected member of the shared filter to hand a message of the leaving client to
+    //Members of a shared subscription, connected or not
+    pub fn shared_member_count(&self, topic_filter: &String) -> usize {
+        self.subscription_tree().routes(topic_filter).map_or(0, |routes| routes.len())
+    }
+
+    pub fn next_shared_member(&self, topic_filter: &String, leaving_client_id: &String, client_handler: &ClientHandler) -> Option<(Arc<String>, Connection)> {
+        let subscription_tree = self.subscription_tree();
+        let mut members: Vec<(Arc<String>, Connection)> = subscription_tree.routes(topic_filter)?.keys()
+            .filter(|client_id| client_id.as_str().ne(leaving_client_id))
+            .filter_map(|client_id| client_handler.get_connection(client_id).ok().map(|connection| (client_id.clone(), connection)))
+            .collect();
+        if members.is_empty() {
+            return None;
+        }
+        members.sort_by(|(client_id, _), (other_client_id, _)| client_id.cmp(other_client_id));
+        let mut turn = self.shared_turns.entry(Arc::new(topic_filter.to_owned())).or_insert(0);
+        let member = members.swap_remove(*turn % members.len());
+        *turn = turn.wrapping_add(1);
+        Some(member)
+    }
+
+    fn delivery_target(client_id: &String, client_handler: &ClientHandler) -> DeliveryTarget {
+        if let Ok(connection) = client_handler.get_connection(client_id) {
+            return DeliveryTarget::Connected(connection);
+        }
+        return match has_persistent_session(&*client_handler.sessions, client_id) {
+            true => { DeliveryTarget::Queued }
+            false => { DeliveryTarget::Dropped }
+        };
     }
 
     #[measure([HitCount, Throughput, InFlight, ResponseTime])]
-    pub fn unsubscribe_all(&self, client_id: &String) {
-        self.topic2subscribers.alter_all(|topic, subscribers| {
-            trace!("Unsubscribing client {:?} from topic {:?}", client_id, topic);
-            subscribers
-                .into_iter()
-                .filter(|s: &String| s.to_owned().ne(client_id))
-                .collect()
-        });
+    pub fn register_deliveries(&self, deliveries: &[Delivery]) {
+        for delivery in deliveries {
+            trace!("Registering delivery on topics {:?} to {:?}", delivery.topic_filters, delivery.client_id);
+            for topic_filter in &delivery.topic_filters {
+                if let Some(metadata) = self.topic2subscribers.get_mut(topic_filter).as_mut().and_then(|subscribers| subscribers.get_mut(&delivery.client_id)) {
+                    metadata.register_delivery();
+                }
+            }
+        }
     }
 
+    //Keeps the last PUBLISH with the retain flag per topic name, an empty payload clears it
     #[measure([HitCount, Throughput, InFlight, ResponseTime])]
-    pub fn find_subscribers(&self, topic_filter: &String) -> Vec<String> {
-        trace!("Finding subscribers for topic {:?} ", topic_filter);
-        if let Some(subscribers) = self.topic2subscribers.get(topic_filter) {
-            trace!("Found {:?} subscribers for topic {:?}", subscribers, topic_filter);
-            subscribers.iter()
-                .map(|s| { s.clone() })
-                .collect()
+    pub fn retain_message(&self, client_id: &String, control_packet: &ControlPacket, received_at: Instant) {
+        let topic_name = control_packet.variable_header().topic_name();
+        let is_empty = control_packet.payload_opt().map(|payload| payload.data().is_empty()).unwrap_or(true);
+        if is_empty {
+            trace!("Clearing retained message on topic {:?}", topic_name);
+            self.topic2retained.remove(topic_name);
         } else {
-            Vec::with_capacity(0)
+            trace!("Retaining message on topic {:?}", topic_name);
+            self.topic2retained.insert(topic_name.to_owned(), (client_id.to_owned(), StoredMessage::new(control_packet.clone(), received_at)));
         }
     }
-}
\ No newline at end of file
+
+    //An expired retained message is cleared instead of returned
+    #[measure([HitCount, Throughput, InFlight, ResponseTime])]
+    pub fn retained_message(&self, topic_name: &String) -> Option<ControlPacket> {
+        let control_packet = self.topic2retained.get(topic_name)?.1.to_packet();
+        if control_packet.is_none() {
+            trace!("Retained message on topic {:?} expired", topic_name);
+            self.topic2retained.remove_if(topic_name, |_, (_, message)| message.is_expired());
+        }
+        control_packet
+    }
+
+    //Topic names with a retained message matching the topic filter, in topic name order
+    #[measure([HitCount, Throughput, InFlight, ResponseTime])]
+    pub fn retained_topics(&self, topic_filter: &String) -> Vec<String> {
+        let topic_filter = shared_filter(topic_filter).map_or(topic_filter.as_str(), |(_, filter)| filter);
+        let mut topic_names: Vec<String> = self.topic2retained.iter()
+            .filter(|entry| topic_matches(topic_filter, entry.key()))
+            .map(|entry| entry.key().clone())
+            .collect();
+        topic_names.sort();
+        topic_names
+    }
+
+    //Expired ones count until something reads them
+    pub fn retained_count(&self) -> usize {
+        self.topic2retained.len()
+    }
+
+    //Retained messages the client published that haven't expired or been replaced since
+    #[measure([HitCount, Throughput, InFlight, ResponseTime])]
+    pub fn retained_messages_of(&self, client_id: &String) -> Vec<ControlPacket> {
+        self.topic2retained.iter()
+            .filter(|entry| entry.value().0.eq(client_id))
+            .filter_map(|entry| entry.value().1.to_packet())
+            .collect()
+    }
+
+    //Unexpired retained messages on the topics matching the filter with the client that published them, in topic name order
+    #[measure([HitCount, Throughput, InFlight, ResponseTime])]
+    pub fn retained_matching(&self, topic_filter: &String) -> Vec<(String, StoredMessage)> {
+        let mut retained: Vec<(String, (String, StoredMessage))> = self.topic2retained.iter()
+            .filter(|entry| topic_matches(topic_filter, entry.key()) && !entry.value().1.is_expired())
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect();
+        retained.sort_by(|(topic_name, _), (other_topic_name, _)| topic_name.cmp(other_topic_name));
+        retained.into_iter().map(|(_, retained_message)| retained_message).collect()
+    }
+
+    //Returns how many retained messages were cleared
+    #[measure([HitCount, Throughput, InFlight, ResponseTime])]
+    pub fn clear_retained(&self, topic_filter: &String) -> usize {
+        let retained_count = self.topic2retained.len();
+        self.topic2retained.retain(|topic_name, _| !topic_matches(topic_filter, topic_name));
+        retained_count - self.topic2retained.len()
+    }
+
+    //Returns how many retained messages were cleared
+    #[measure([HitCount, Throughput, InFlight, ResponseTime])]
+    pub fn clear_retained_of(&self, client_id: &String) -> usize {
+        let retained_count = self.topic2retained.len();
+        self.topic2retained.retain(|_, (publisher, _)| publisher != client_id);
+        retained_count - self.topic2retained.len()
+    }
+}
+
+impl TopicHandler {
+    //The current version of the tree. Changes made after this call don't show in it, a caller
+    //holding it keeps routing as if they hadn't happened yet.
+    pub fn subscription_tree(&self) -> Arc<SubscriptionTree> {
+        self.subscription_tree.read().unwrap().clone()
+    }
+
+    //Installs the next version of the tree with the routes of the changed filters rebuilt.
+    //Called with tree_writer held and no guard into topic2subscribers.
+    fn update_tree(&self, topic_filters: Vec<Arc<String>>) {
+        let changes = topic_filters.into_iter()
+            .map(|topic_filter| {
+                let routes: Routes = self.topic2subscribers.get(&topic_filter)
+                    .map(|subscribers| subscribers.iter().map(|(client_id, metadata)| (client_id.clone(), Route::from(metadata))).collect())
+                    .unwrap_or_default();
+                if routes.is_empty() {
+                    self.shared_turns.remove(&topic_filter);
+                }
+                (topic_filter, routes)
+            })
+            .collect();
+        let subscription_tree = self.subscription_tree().with_routes(changes);
+        trace!("Installing subscription tree version {}", subscription_tree.version());
+        *self.subscription_tree.write().unwrap() = Arc::new(subscription_tree);
+    }
+
+    fn subscription_records(&self, include: impl Fn(&String, &SubscriptionMetadata) -> bool) -> Vec<SubscriptionRecord> {
+        let mut records = Vec::new();
+        for subscribers in self.topic2subscribers.iter() {
+            records.extend(subscribers.value().iter()
+                .filter(|(client_id, metadata)| include(client_id, metadata))
+                .map(|(client_id, metadata)| SubscriptionRecord { client_id: client_id.to_string(), topic_filter: subscribers.key().to_string(), metadata: metadata.clone() }));
+        }
+        records
+    }
+
+    pub fn subscription_metadata(&self, client_id: &String, topic_filter: &String) -> Option<SubscriptionMetadata> {
+        self.topic2subscribers.get(topic_filter)?.get(client_id).cloned()
+    }
+
+    pub fn subscription_count(&self, client_id: &String) -> usize {
+        self.topic2subscribers.iter()
+            .filter(|subscribers| subscribers.contains_key(client_id))
+            .count()
+    }
+
+    //Subscriptions that had no delivery (or were created without one since) for longer than max_idle
+    pub fn stale_subscriptions(&self, max_idle: Duration) -> Vec<SubscriptionRecord> {
+        let threshold = Utc::now().timestamp_millis() - max_idle.as_millis() as i64;
+        self.subscription_records(|_, metadata| metadata.last_activity_at() < threshold)
+    }
+
+    //Total (subscribed, unsubscribed) counts since start, sampled over time they give the churn rate
+    pub fn subscription_churn(&self) -> (u64, u64) {
+        (self.subscribed_count.load(Ordering::Relaxed), self.unsubscribed_count.load(Ordering::Relaxed))
+    }
+
+    pub fn subscriptions_of(&self, client_id: &String) -> Vec<SubscriptionRecord> {
+        self.subscription_records(|subscriber, _| subscriber.eq(client_id))
+    }
+
+    //The imported subscriptions show up in a single version of the tree
+    pub fn import_subscriptions(&self, records: Vec<SubscriptionRecord>) {
+        let _writer = self.tree_writer.lock().unwrap();
+        let mut topic_filters = vec![];
+        for record in records {
+            let mut subscribers = self.topic2subscribers.entry(Arc::new(record.topic_filter)).or_default();
+            if subscribers.insert(Arc::new(record.client_id), record.metadata).is_none() {
+                self.subscribed_count.fetch_add(1, Ordering::Relaxed);
+            }
+            topic_filters.push(subscribers.key().clone());
+        }
+        if !topic_filters.is_empty() {
+            self.update_tree(topic_filters);
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//MQTT topic filter matching: + matches a single level, # the parent level and everything below.
+//Topic names starting with $ are only matched by filters that start with the same literal level.
+pub fn topic_matches(topic_filter: &str, topic_name: &str) -> bool {
+    if topic_name.starts_with('$') && (topic_filter.starts_with('+') || topic_filter.starts_with('#')) {
+        return false;
+    }
+    let mut filter_levels = topic_filter.split('/');
+    let mut name_levels = topic_name.split('/');
+    loop {
+        match (filter_levels.next(), name_levels.next()) {
+            (Some("#"), _) => { return true; }
+            (Some("+"), Some(_)) => {}
+            (Some(filter_level), Some(name_level)) => {
+                if filter_level != name_level {
+                    return false;
+                }
+            }
+            (None, None) => { return true; }
+            _ => { return false; }
+        }
+    }
+}
+
+//Not empty, # only as the last level and + only as a whole level
+pub fn is_valid_filter(topic_filter: &str) -> bool {
+    let levels: Vec<&str> = topic_filter.split('/').collect();
+    !topic_filter.is_empty() && levels.iter().enumerate().all(|(index, level)| match *level {
+        "#" => { index == levels.len() - 1 }
+        "+" => { true }
+        level => { !level.contains(['+', '#']) }
+    })
+}
+
+pub fn is_wildcard(topic_filter: &str) -> bool {
+    topic_filter.contains(['+', '#'])
+}
+
+pub const SHARED_SUBSCRIPTION_PREFIX: &str = "$share/";
+
+//Group name and topic filter of $share/<group>/<filter>, None for a filter that isn't shared
+pub fn shared_filter(topic_filter: &str) -> Option<(&str, &str)> {
+    let (group, filter) = topic_filter.strip_prefix(SHARED_SUBSCRIPTION_PREFIX)?.split_once('/')?;
+    if group.is_empty() || filter.is_empty() {
+        return None;
+    }
+    Some((group, filter))
+}
@@ -0,0 +1,153 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Mutex;
+
+use log::{info, warn};
+use metered::HitCount;
+use serde::Serialize;
+
+use crate::config::broker_config::TreeTelemetryConfig;
+use crate::topic::subscription_tree::SubscriptionTree;
+use crate::topic::topic_matcher::is_wildcard;
+
+//The shape of one version of the subscriptions, seen as a tree with a node per topic level
+#[derive(Debug, Default)]
+#[derive(Clone, PartialEq)]
+#[derive(Serialize)]
+pub struct TreeShape {
+    pub version: u64,
+    pub filters: usize,
+    pub wildcard_filters: usize,
+    pub nodes: usize,
+    pub max_depth: usize,
+    //Topic filters by their number of levels
+    pub filters_by_depth: BTreeMap<usize, usize>,
+    //Children of the widest node. Ids at a level show up here as a huge sibling set
+    pub max_fanout: usize,
+    pub widest_node: String,
+    //Children per node that has any
+    pub mean_fanout: f64,
+}
+
+impl TreeShape {
+    pub fn of(subscription_tree: &SubscriptionTree) -> Self {
+        let mut shape = TreeShape { version: subscription_tree.version(), ..Default::default() };
+        //Path of a node with its trailing / -> distinct child levels, "" is the root
+        let mut children: HashMap<&str, HashSet<&str>> = HashMap::new();
+        for topic_filter in subscription_tree.topic_filters() {
+            let topic_filter = topic_filter.as_str();
+            shape.filters += 1;
+            if is_wildcard(topic_filter) {
+                shape.wildcard_filters += 1;
+            }
+            let mut depth = 0;
+            let mut parent_end = 0;
+            for level in topic_filter.split('/') {
+                children.entry(&topic_filter[..parent_end]).or_default().insert(level);
+                parent_end += level.len() + 1;
+                depth += 1;
+            }
+            *shape.filters_by_depth.entry(depth).or_insert(0) += 1;
+            shape.max_depth = shape.max_depth.max(depth);
+        }
+        let edges: usize = children.values().map(|levels| levels.len()).sum();
+        shape.nodes = edges;
+        if let Some((widest_node, levels)) = children.iter().max_by(|(a_node, a_levels), (b_node, b_levels)| a_levels.len().cmp(&b_levels.len()).then(b_node.cmp(a_node))) {
+            shape.max_fanout = levels.len();
+            shape.widest_node = widest_node.strip_suffix('/').unwrap_or(widest_node).to_string();
+        }
+        if !children.is_empty() {
+            shape.mean_fanout = edges as f64 / children.len() as f64;
+        }
+        shape
+    }
+}
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct TreeTelemetryMetrics {
+    pub(crate) reports: HitCount,
+    //Reports whose widest node went past fanout_warning
+    pub(crate) wide_nodes: HitCount,
+}
+
+//Deeper filters are exported together, so the number of metrics stays bounded
+const EXPORTED_DEPTHS: usize = 8;
+
+//The last reported shape, exported with the metrics
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct TreeShapeMetrics {
+    filters: usize,
+    wildcard_filters: usize,
+    nodes: usize,
+    max_depth: usize,
+    //filters_at_depth_1 to filters_at_depth_8_or_more
+    filters_by_depth: BTreeMap<String, usize>,
+    max_fanout: usize,
+    mean_fanout: f64,
+    reports: u64,
+    wide_nodes: u64,
+}
+
+//Reports the shape of the subscriptions every report_interval_secs, so a topic design that makes matching
+//slow is noticed. Match times are measured by TopicHandler::matching_filters.
+#[derive(Debug)]
+pub struct TreeTelemetry {
+    config: TreeTelemetryConfig,
+    last_shape: Mutex<Option<TreeShape>>,
+    pub(crate) metrics: TreeTelemetryMetrics,
+}
+
+impl TreeTelemetry {
+    pub fn report(&self, subscription_tree: &SubscriptionTree) -> TreeShape {
+        let shape = TreeShape::of(subscription_tree);
+        self.metrics.reports.incr();
+        info!("Subscription tree version {}: {} filters, {} wildcards, {} nodes, depth {}, widest node {:?} with {} children",
+            shape.version, shape.filters, shape.wildcard_filters, shape.nodes, shape.max_depth, shape.widest_node, shape.max_fanout);
+        if self.config.fanout_warning > 0 && shape.max_fanout >= self.config.fanout_warning {
+            self.metrics.wide_nodes.incr();
+            warn!("Topic level {:?} has {} children. Ids in topic levels should come last", shape.widest_node, shape.max_fanout);
+        }
+        *self.last_shape.lock().unwrap() = Some(shape.clone());
+        shape
+    }
+
+    pub fn last_shape(&self) -> Option<TreeShape> {
+        self.last_shape.lock().unwrap().clone()
+    }
+
+    pub fn metrics(&self) -> TreeShapeMetrics {
+        let shape = self.last_shape().unwrap_or_default();
+        TreeShapeMetrics {
+            filters: shape.filters,
+            wildcard_filters: shape.wildcard_filters,
+            nodes: shape.nodes,
+            max_depth: shape.max_depth,
+            filters_by_depth: exported_depths(&shape.filters_by_depth),
+            max_fanout: shape.max_fanout,
+            mean_fanout: shape.mean_fanout,
+            reports: self.metrics.reports.0.get(),
+            wide_nodes: self.metrics.wide_nodes.0.get(),
+        }
+    }
+
+    pub fn interval_secs(&self) -> u64 {
+        self.config.report_interval_secs
+    }
+
+    pub fn new(config: TreeTelemetryConfig) -> Self {
+        Self { config, last_shape: Mutex::new(None), metrics: TreeTelemetryMetrics::default() }
+    }
+}
+
+fn exported_depths(filters_by_depth: &BTreeMap<usize, usize>) -> BTreeMap<String, usize> {
+    let mut exported = BTreeMap::new();
+    for (depth, filters) in filters_by_depth {
+        let key = match *depth < EXPORTED_DEPTHS {
+            true => { format!("filters_at_depth_{}", depth) }
+            false => { format!("filters_at_depth_{}_or_more", EXPORTED_DEPTHS) }
+        };
+        *exported.entry(key).or_insert(0) += filters;
+    }
+    exported
+}
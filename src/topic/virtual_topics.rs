@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{SecondsFormat, Utc};
+use dashmap::DashMap;
+use log::{debug, info};
+use metered::HitCount;
+use serde::Serialize;
+
+use crate::topic::topic_matcher::{shared_filter, topic_matches};
+
+pub const SYS_TIME_TOPIC: &str = "$SYS/time";
+pub const SYS_MESSAGE_RATE_TOPIC: &str = "$SYS/broker/messages/received/1min";
+
+const MESSAGE_RATE_WINDOW: Duration = Duration::from_secs(60);
+
+//Computes the value of a topic nobody publishes to
+pub trait VirtualTopicProvider: Debug + Send + Sync {
+    fn topic_name(&self) -> &str;
+    //Unless virtual_topics.refresh_secs has one for the topic
+    fn default_refresh_interval(&self) -> Duration;
+    fn compute(&self, now: Instant) -> Vec<u8>;
+}
+
+#[derive(Debug, Default)]
+#[derive(Serialize)]
+pub struct VirtualTopicMetrics {
+    //Values computed, the others were served from the previous computation
+    pub(crate) computed: HitCount,
+    //Values sent, to a new subscription or to the subscribers of a refreshed topic
+    pub(crate) delivered: HitCount,
+}
+
+#[derive(Debug)]
+struct Registration {
+    provider: Arc<dyn VirtualTopicProvider>,
+    refresh_interval: Duration,
+    //The last value with when it was computed
+    cached: Mutex<Option<(Instant, Vec<u8>)>>,
+}
+
+impl Registration {
+    fn is_fresh(&self, now: Instant) -> bool {
+        self.cached.lock().unwrap().as_ref()
+            .is_some_and(|(computed_at, _)| now.saturating_duration_since(*computed_at) < self.refresh_interval)
+    }
+}
+
+//Topics whose value a provider computes when a client subscribes, the way a retained message would be sent.
+//A value is served for the refresh interval of its provider, PacketDispatcher::publish_virtual_topics sends the
+//next one to the subscribers when it runs out. An interval of 0 computes a value for every subscription.
+#[derive(Debug, Default)]
+pub struct VirtualTopics {
+    topic2provider: DashMap<String, Registration>,
+    pub(crate) metrics: VirtualTopicMetrics,
+}
+
+impl VirtualTopics {
+    //Replaces the provider registered for the same topic
+    pub fn register(&self, provider: Arc<dyn VirtualTopicProvider>, refresh_interval: Duration) {
+        let topic_name = provider.topic_name().to_string();
+        info!("Serving virtual topic {:?}, refreshed every {}s", topic_name, refresh_interval.as_secs());
+        self.topic2provider.insert(topic_name, Registration { provider, refresh_interval, cached: Mutex::new(None) });
+    }
+
+    pub fn value(&self, topic_name: &str, now: Instant) -> Option<Vec<u8>> {
+        let registration = self.topic2provider.get(topic_name)?;
+        Some(self.refreshed(&registration, now))
+    }
+
+    //Topic names and values of the virtual topics a subscription to topic_filter gets, in topic name order
+    pub fn matching(&self, topic_filter: &str, now: Instant) -> Vec<(String, Vec<u8>)> {
+        let topic_filter = shared_filter(topic_filter).map_or(topic_filter, |(_, filter)| filter);
+        let mut values: Vec<(String, Vec<u8>)> = self.topic2provider.iter()
+            .filter(|registration| topic_matches(topic_filter, registration.key()))
+            .map(|registration| (registration.key().clone(), self.refreshed(&registration, now)))
+            .collect();
+        values.sort();
+        values
+    }
+
+    //Topics whose value ran out, the ones with an interval of 0 are never published
+    pub fn stale(&self, now: Instant) -> Vec<String> {
+        self.topic2provider.iter()
+            .filter(|registration| !registration.refresh_interval.is_zero() && !registration.is_fresh(now))
+            .map(|registration| registration.key().clone())
+            .collect()
+    }
+
+    fn refreshed(&self, registration: &Registration, now: Instant) -> Vec<u8> {
+        let mut cached = registration.cached.lock().unwrap();
+        if let Some((computed_at, value)) = cached.as_ref() {
+            if now.saturating_duration_since(*computed_at) < registration.refresh_interval {
+                return value.clone();
+            }
+        }
+        debug!("Computing virtual topic {:?}", registration.provider.topic_name());
+        self.metrics.computed.incr();
+        let value = registration.provider.compute(now);
+        *cached = Some((now, value.clone()));
+        value
+    }
+}
+
+//The current time in RFC 3339, UTC
+#[derive(Debug, Default)]
+pub struct SysTime;
+
+impl VirtualTopicProvider for SysTime {
+    fn topic_name(&self) -> &str {
+        SYS_TIME_TOPIC
+    }
+
+    fn default_refresh_interval(&self) -> Duration {
+        Duration::from_secs(1)
+    }
+
+    fn compute(&self, _now: Instant) -> Vec<u8> {
+        Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true).into_bytes()
+    }
+}
+
+//PUBLISH packets received over the last minute, counted per second
+#[derive(Debug)]
+pub struct MessageRate {
+    started_at: Instant,
+    //Second since started_at -> packets received in it, oldest first
+    seconds: Mutex<VecDeque<(u64, u64)>>,
+}
+
+impl MessageRate {
+    pub fn record(&self, now: Instant) {
+        let second = now.saturating_duration_since(self.started_at).as_secs();
+        let mut seconds = self.seconds.lock().unwrap();
+        match seconds.back_mut() {
+            Some((last_second, count)) if *last_second >= second => { *count += 1; }
+            _ => { seconds.push_back((second, 1)); }
+        }
+        Self::forget_before(&mut seconds, second);
+    }
+
+    pub fn count(&self, now: Instant) -> u64 {
+        let second = now.saturating_duration_since(self.started_at).as_secs();
+        let mut seconds = self.seconds.lock().unwrap();
+        Self::forget_before(&mut seconds, second);
+        seconds.iter().map(|(_, count)| count).sum()
+    }
+
+    fn forget_before(seconds: &mut VecDeque<(u64, u64)>, second: u64) {
+        let window_start = (second + 1).saturating_sub(MESSAGE_RATE_WINDOW.as_secs());
+        while seconds.front().is_some_and(|(oldest, _)| *oldest < window_start) {
+            seconds.pop_front();
+        }
+    }
+
+    pub fn new(started_at: Instant) -> Self {
+        Self { started_at, seconds: Mutex::new(VecDeque::new()) }
+    }
+}
+
+impl VirtualTopicProvider for MessageRate {
+    fn topic_name(&self) -> &str {
+        SYS_MESSAGE_RATE_TOPIC
+    }
+
+    fn default_refresh_interval(&self) -> Duration {
+        Duration::from_secs(10)
+    }
+
+    fn compute(&self, now: Instant) -> Vec<u8> {
+        self.count(now).to_string().into_bytes()
+    }
+}